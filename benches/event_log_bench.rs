@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::{ScalableEngine, TransactionRow, TransactionType};
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+async fn process_deposits(engine: ScalableEngine) -> usize {
+    for i in 1..=10_000 {
+        let _ = engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: (i % 100) as u16 + 1,
+            tx: i,
+            amount: Some(dec!(1.0)),
+            to: None,
+        }).await;
+    }
+
+    engine.get_accounts().await.len()
+}
+
+fn benchmark_event_log(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    // One-shot CLI runs, with and without the throwaway log
+    let mut group = c.benchmark_group("cli_10000_deposits");
+    group.sample_size(20);
+
+    group.bench_function("event_log", |b| {
+        b.to_async(&rt).iter(|| async {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let engine = ScalableEngine::new(temp_dir.path().join("events.log"), 16, cold_storage).await.unwrap();
+
+            black_box(process_deposits(engine).await)
+        });
+    });
+
+    group.bench_function("no_event_log", |b| {
+        b.to_async(&rt).iter(|| async {
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let engine = ScalableEngine::without_event_log(16, cold_storage);
+
+            black_box(process_deposits(engine).await)
+        });
+    });
+
+    group.finish();
+
+    // Restart after a large log: 50000 events across 1000 clients replayed
+    let temp_dir = tempfile::tempdir().unwrap();
+    let log_path = temp_dir.path().join("replay.log");
+    let log: String = (1..=50_000u32)
+        .map(|tx| format!("deposit,{},{},1.0\n", tx % 1000 + 1, tx))
+        .collect();
+    std::fs::write(&log_path, log).unwrap();
+
+    let mut group = c.benchmark_group("rebuild_50000_events");
+    group.sample_size(10);
+    group.bench_function("1000_clients", |b| {
+        b.to_async(&rt).iter(|| async {
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let engine = ScalableEngine::new(log_path.clone(), 16, cold_storage).await.unwrap();
+            engine.rebuild_from_events().await.unwrap();
+
+            black_box(engine.get_accounts().await.len())
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_event_log);
+criterion_main!(benches);
@@ -1,7 +1,11 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use futures::future::join_all;
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::topology::auto_shards;
 use payments_engine::{ScalableEngine, TransactionRow, TransactionType};
 use rust_decimal_macros::dec;
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::runtime::Runtime;
 
 fn benchmark_parallel_processing(c: &mut Criterion) {
@@ -16,7 +20,8 @@ fn benchmark_parallel_processing(c: &mut Criterion) {
             |b, &num_clients| {
                 b.to_async(&rt).iter(|| async move {
                     let temp_path = PathBuf::from(format!("/tmp/bench_{}.log", num_clients));
-                    let engine = ScalableEngine::new(temp_path, 16).await.unwrap();
+                    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+                    let engine = ScalableEngine::new(temp_path, 16, cold_storage).await.unwrap();
                     
                     for client_id in 1..=num_clients {
                         let _ = engine.process(TransactionRow {
@@ -24,6 +29,7 @@ fn benchmark_parallel_processing(c: &mut Criterion) {
                             client: client_id,
                             tx: client_id as u32,
                             amount: Some(dec!(100.0)),
+                            to: None,
                         }).await;
                     }
                     
@@ -42,7 +48,8 @@ fn benchmark_actor_throughput(c: &mut Criterion) {
     c.bench_function("actor_1000_transactions", |b| {
         b.to_async(&rt).iter(|| async {
             let temp_path = PathBuf::from("/tmp/bench_throughput.log");
-            let engine = ScalableEngine::new(temp_path, 16).await.unwrap();
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let engine = ScalableEngine::new(temp_path, 16, cold_storage).await.unwrap();
             
             for i in 1..=1000 {
                 let _ = engine.process(TransactionRow {
@@ -50,6 +57,7 @@ fn benchmark_actor_throughput(c: &mut Criterion) {
                     client: (i % 100) as u16 + 1,
                     tx: i,
                     amount: Some(dec!(1.0)),
+                    to: None,
                 }).await;
             }
             
@@ -58,6 +66,49 @@ fn benchmark_actor_throughput(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_parallel_processing, benchmark_actor_throughput);
+// 1000 clients depositing concurrently, over a range of shard counts and the automatic one
+fn benchmark_shard_count(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let auto = auto_shards();
+
+    let mut group = c.benchmark_group("shard_count_10000_deposits");
+    group.sample_size(20);
+
+    let mut counts = vec![1, 4, 16, 64, 256];
+    if !counts.contains(&auto) {
+        counts.push(auto);
+    }
+    for shards in counts {
+        let id = if shards == auto { format!("{}_auto", shards) } else { shards.to_string() };
+        group.bench_with_input(BenchmarkId::from_parameter(id), &shards, |b, &shards| {
+            b.to_async(&rt).iter(|| async move {
+                let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+                let engine = ScalableEngine::without_event_log(shards, cold_storage);
+
+                join_all((1..=1000u16).map(|client| {
+                    let engine = &engine;
+                    async move {
+                        for i in 0..10u32 {
+                            let _ = engine.process(TransactionRow {
+                                tx_type: TransactionType::Deposit,
+                                client,
+                                tx: client as u32 * 10 + i,
+                                amount: Some(dec!(1.0)),
+                                to: None,
+                            }).await;
+                        }
+                    }
+                }))
+                .await;
+
+                black_box(engine.get_accounts().await.len())
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_parallel_processing, benchmark_actor_throughput, benchmark_shard_count);
 criterion_main!(benches);
 
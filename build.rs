@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+/// Generate the gRPC service, protox parses the proto so no protoc install is needed
+#[cfg(feature = "grpc")]
+fn grpc() {
+    println!("cargo:rerun-if-changed=proto/payments.proto");
+    let descriptors = protox::compile(["proto/payments.proto"], ["proto"]).expect("proto/payments.proto compiles");
+    tonic_build::configure()
+        .build_client(true)
+        .compile_fds(descriptors)
+        .expect("gRPC code generates");
+}
@@ -0,0 +1,149 @@
+// Plugging a cold store of one's own into the engine
+//
+//     cargo run --example custom_store
+//
+// `ClientOrderedStore` keys its records by client, so history pages are range
+// scans instead of the full scan `InMemoryStore` does, and counts its reads.
+// The engine moves a client's transactions into it, then disputes and pages
+// through history against it.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use payments_engine::history::Pagination;
+use payments_engine::storage::{CompactionReport, Retain, TransactionStore};
+use payments_engine::{ScalableEngine, StoredTransaction, TransactionRow, TransactionType};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Records {
+    by_client: BTreeMap<(u16, u32), StoredTransaction>,
+    /// Owning client of every tx id, lookups come by id alone
+    clients: HashMap<u32, u16>,
+}
+
+#[derive(Default)]
+struct ClientOrderedStore {
+    records: Mutex<Records>,
+    reads: AtomicU64,
+}
+
+impl ClientOrderedStore {
+    fn len(&self) -> usize {
+        self.records.lock().unwrap().by_client.len()
+    }
+}
+
+#[async_trait]
+impl TransactionStore for ClientOrderedStore {
+    async fn get(&self, tx_id: u32) -> Option<StoredTransaction> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let records = self.records.lock().unwrap();
+        let client = *records.clients.get(&tx_id)?;
+        records.by_client.get(&(client, tx_id)).cloned()
+    }
+
+    async fn put(&self, tx_id: u32, tx: StoredTransaction) -> Result<()> {
+        let mut records = self.records.lock().unwrap();
+        if let Some(previous) = records.clients.insert(tx_id, tx.client) {
+            records.by_client.remove(&(previous, tx_id));
+        }
+        records.by_client.insert((tx.client, tx_id), tx);
+        Ok(())
+    }
+
+    async fn remove(&self, tx_id: u32) -> Result<()> {
+        let mut records = self.records.lock().unwrap();
+        if let Some(client) = records.clients.remove(&tx_id) {
+            records.by_client.remove(&(client, tx_id));
+        }
+        Ok(())
+    }
+
+    async fn list_client(&self, client: u16, after: Option<u32>, limit: usize) -> Result<Vec<(u32, StoredTransaction)>> {
+        let records = self.records.lock().unwrap();
+        let Some(first) = after.map_or(Some(0), |after| after.checked_add(1)) else {
+            return Ok(Vec::new());
+        };
+        Ok(records
+            .by_client
+            .range((client, first)..=(client, u32::MAX))
+            .take(limit)
+            .map(|((_, tx_id), tx)| (*tx_id, tx.clone()))
+            .collect())
+    }
+
+    /// Nothing survives a restart, so snapshots carry the records
+    async fn snapshot_entries(&self) -> Option<Vec<(u32, StoredTransaction)>> {
+        let records = self.records.lock().unwrap();
+        Some(records.by_client.iter().map(|((_, tx_id), tx)| (*tx_id, tx.clone())).collect())
+    }
+
+    async fn compact(&self, retain: Retain) -> Result<CompactionReport> {
+        let mut records = self.records.lock().unwrap();
+        let Records { by_client, clients } = &mut *records;
+        let mut report = CompactionReport {
+            scanned: by_client.len() as u64,
+            ..Default::default()
+        };
+        by_client.retain(|(_, tx_id), tx| {
+            let kept = retain(tx);
+            if !kept {
+                clients.remove(tx_id);
+                report.removed += 1;
+            }
+            kept
+        });
+        Ok(report)
+    }
+}
+
+fn row(tx_type: TransactionType, client: u16, tx: u32, amount: Option<Decimal>) -> TransactionRow {
+    TransactionRow {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to: None,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let store = Arc::new(ClientOrderedStore::default());
+    let engine = ScalableEngine::without_event_log(2, store.clone());
+
+    for tx in 1..=5 {
+        engine.process(row(TransactionType::Deposit, 7, tx, Some(dec!(10)))).await?;
+    }
+    // Normally deposits go cold as they age, this starts moving them at once
+    engine.force_migrate_cold(7).await;
+    while store.len() < 5 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    // The dispute finds its deposit in the custom store
+    engine.process(row(TransactionType::Dispute, 7, 2, None)).await?;
+    let account = engine.get_account(7).await.expect("client 7 exists");
+    println!("client 7: available {} held {}", account.available, account.held);
+    println!("store reads so far: {}", store.reads.load(Ordering::Relaxed));
+
+    let mut after = None;
+    loop {
+        let page = engine
+            .get_transactions(7, Pagination { after, limit: 2 })
+            .await
+            .map_err(anyhow::Error::msg)?;
+        let ids: Vec<u32> = page.transactions.iter().map(|entry| entry.tx).collect();
+        println!("history page {:?}", ids);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+
+    engine.shutdown().await
+}
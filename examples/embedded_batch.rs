@@ -0,0 +1,115 @@
+// Batch processing with the engine embedded in another program
+//
+//     cargo run --example embedded_batch
+//
+// Builds an engine from an `EngineConfig`, hooks in a custom transaction type,
+// a withdrawal authorizer and a domain event subscriber, then applies a CSV
+// held in memory and prints the accounts.
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use payments_engine::amount::AmountUnits;
+use payments_engine::authorizer::{Authorizer, AuthorizerConfig};
+use payments_engine::compat::CompatConfig;
+use payments_engine::config::EngineConfig;
+use payments_engine::csv_io::{stream_transactions, write_account_stream};
+use payments_engine::events::DomainEvent;
+use payments_engine::handlers::{HandlerContext, TransactionHandler};
+use payments_engine::scalable_engine::PREFETCH_WINDOW;
+use payments_engine::storage::InMemoryStore;
+use payments_engine::{AccountOutput, ProcessingError, ScalableEngine, TransactionRow};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+
+const INPUT: &str = "type,client,tx,amount
+deposit,1,1,100.0
+deposit,2,2,40.0
+fee,1,3,1.5
+withdrawal,1,4,20.0
+withdrawal,2,5,500.0
+withdrawal,1,6,75.0
+dispute,2,2,
+";
+
+/// `fee` rows debit the account, overdrawing it if need be
+struct FeeHandler;
+
+impl TransactionHandler for FeeHandler {
+    fn type_name(&self) -> &str {
+        "fee"
+    }
+
+    fn creates_tx(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        match tx.amount {
+            Some(amount) if amount > Decimal::ZERO => Ok(()),
+            _ => Err(ProcessingError::InvalidAmount),
+        }
+    }
+
+    fn apply(&self, ctx: &mut HandlerContext<'_>, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        ctx.account.available -= tx.amount.unwrap_or_default();
+        Ok(())
+    }
+}
+
+/// Stands in for a fraud check: approves withdrawals up to a fixed limit
+struct LimitAuthorizer {
+    limit: Decimal,
+}
+
+#[async_trait]
+impl Authorizer for LimitAuthorizer {
+    async fn authorize(&self, tx: &TransactionRow) -> anyhow::Result<bool> {
+        Ok(tx.amount.is_some_and(|amount| amount <= self.limit))
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = EngineConfig {
+        shards: 4,
+        ..Default::default()
+    };
+    let engine = ScalableEngine::from_config(&config, Arc::new(InMemoryStore::new()))
+        .await?
+        .with_compat(CompatConfig::strict())?
+        .with_authorizer(
+            Arc::new(LimitAuthorizer { limit: dec!(50) }),
+            AuthorizerConfig {
+                threshold: dec!(50),
+                ..Default::default()
+            },
+        );
+    engine.register_handler(Arc::new(FeeHandler));
+
+    // Rejections are reported as they happen, whoever submitted the row
+    let mut events = engine.subscribe();
+    let reporter = tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            if let DomainEvent::TransactionRejected { tx, client, code, .. } = event {
+                eprintln!("tx {} of client {} rejected: {}", tx, client, code);
+            }
+        }
+    });
+
+    let mut rows = stream_transactions(std::io::Cursor::new(INPUT)).ready_chunks(PREFETCH_WINDOW);
+    while let Some(chunk) = rows.next().await {
+        let chunk: Vec<TransactionRow> = chunk.into_iter().filter_map(Result::ok).collect();
+        engine.prefetch(&chunk).await;
+        engine.process_batch(chunk).await;
+    }
+
+    let mut accounts: Vec<AccountOutput> = engine.get_accounts().await.iter().map(AccountOutput::from).collect();
+    accounts.sort_by_key(|account| account.client);
+    write_account_stream(tokio::io::stdout(), futures::stream::iter(accounts), AmountUnits::Decimal).await?;
+
+    // Dropping the engine closes the bus, which ends the reporter
+    drop(engine);
+    reporter.await?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+// Serving the engine from another program, next to its own endpoints
+//
+//     cargo run --example embedded_server
+//
+// Mounts the HTTP API under a router of the host's, serves the TCP wire
+// protocol beside it, submits a few rows with acks and reads the account back
+// over HTTP, then shuts both down.
+
+use axum::routing::get;
+use axum::Router;
+use payments_engine::http;
+use payments_engine::server::serve_until;
+use payments_engine::storage::InMemoryStore;
+use payments_engine::ScalableEngine;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let temp = std::env::temp_dir().join(format!("embedded-server-{}.log", std::process::id()));
+    let engine = Arc::new(ScalableEngine::new(temp.clone(), 4, Arc::new(InMemoryStore::new())).await?);
+
+    // What this build can do, worth checking before relying on an optional feature
+    let capabilities = engine.capabilities();
+    println!("engine {} speaks {:?}, features {:?}", capabilities.engine_version, capabilities.protocols, capabilities.features);
+
+    let shutdown = CancellationToken::new();
+    let wire = TcpListener::bind("127.0.0.1:0").await?;
+    let wire_addr = wire.local_addr()?;
+    let wire_server = tokio::spawn(serve_until(wire, engine.clone(), 16, shutdown.clone(), Duration::from_secs(5)));
+
+    let app = Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .nest("/payments", http::router(engine.clone()));
+    let api = TcpListener::bind("127.0.0.1:0").await?;
+    let api_addr = api.local_addr()?;
+    let stopped = shutdown.clone();
+    let api_server = tokio::spawn(async move {
+        axum::serve(api, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+            .with_graceful_shutdown(async move { stopped.cancelled().await })
+            .await
+    });
+
+    let (reader, mut writer) = TcpStream::connect(wire_addr).await?.into_split();
+    writer
+        .write_all(b"#protocol ack\ntype,client,tx,amount\ndeposit,1,1,25.0\nwithdrawal,1,2,10.0\nwithdrawal,1,3,99.0\n")
+        .await?;
+    writer.shutdown().await?;
+    let mut acks = BufReader::new(reader).lines();
+    while let Some(ack) = acks.next_line().await? {
+        println!("ack {}", ack);
+    }
+
+    let account: serde_json::Value = reqwest::get(format!("http://{}/payments/accounts/1", api_addr))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    println!("account {}", account);
+
+    shutdown.cancel();
+    wire_server.await??;
+    api_server.await??;
+    engine.shutdown().await?;
+    let _ = std::fs::remove_file(temp);
+    Ok(())
+}
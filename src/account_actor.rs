@@ -1,8 +1,23 @@
+use crate::alerts::AlertRules;
+use crate::clock::Clock;
+use crate::compat::{CompatConfig, LockPolicy, LockScope};
+use crate::contention::{measure, Site};
 use crate::errors::ProcessingError;
+use crate::events::AccountUpdates;
+use crate::handlers::{HandlerContext, HandlerRegistry};
+use crate::history::{self, Pagination, TransactionPage};
+use crate::hot_store::{HotStore, HotTransaction};
+use crate::metrics::MigrationMetrics;
+use crate::reporting::{CounterKind, ReportingCounters};
+use crate::snapshots::AccountSnapshotStore;
+use crate::migration::{self, MigrationConfig, MigrationOutcome};
 use crate::models::{Account, TransactionRow, TransactionType};
-use crate::storage::{StoredTransaction, TransactionStore};
+use crate::storage::{RetryPolicy, StorageTier, StoredTransaction, TransactionStore};
+use crate::treasury::ShardTotals;
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tokio::sync::{mpsc, oneshot};
@@ -11,73 +26,307 @@ use tracing::error;
 pub enum AccountMessage {
     Process {
         tx: TransactionRow,
+        /// When the event happened, drives reporting buckets
+        at: SystemTime,
+        reply: oneshot::Sender<Result<Undo, ProcessingError>>,
+    },
+    /// Re-apply an event from the log on startup
+    Replay {
+        tx: TransactionRow,
+        reply: oneshot::Sender<Result<(), ProcessingError>>,
+    },
+    /// Apply one side of a transfer coordinated by the shard manager
+    Transfer {
+        tx: TransactionRow,
+        leg: TransferLeg,
+        /// When the transfer happened, `None` when replaying from the log
+        at: Option<SystemTime>,
+        reply: oneshot::Sender<Result<Undo, ProcessingError>>,
+    },
+    /// Take back a message applied here whose event could not be logged
+    Revert {
+        undo: Undo,
         reply: oneshot::Sender<Result<(), ProcessingError>>,
     },
+    /// Checks a row would go through now and their outcomes, nothing is applied
+    Evaluate {
+        tx: TransactionRow,
+        reply: oneshot::Sender<Vec<ValidationCheck>>,
+    },
+    /// Administrative unlock of an account locked by a chargeback
+    Unlock {
+        reply: oneshot::Sender<Result<Undo, ProcessingError>>,
+    },
     GetState {
         reply: oneshot::Sender<Account>,
     },
+    InspectTransaction {
+        tx_id: u32,
+        reply: oneshot::Sender<Option<(StoredTransaction, StorageTier)>>,
+    },
     MigrateCold,
+    /// Migrate every hot transaction regardless of age, in as many batches as it takes
+    ForceMigrateCold,
+    /// Forget every hot transaction without moving it to cold storage, for a
+    /// client whose transactions nothing may reference (the latency prober's)
+    DiscardHot,
+    HotTransactions {
+        reply: oneshot::Sender<Vec<HotTransaction>>,
+    },
+    HotStorageLen {
+        reply: oneshot::Sender<usize>,
+    },
+    /// A page of the client's hot and cold transactions
+    GetTransactions {
+        page: Pagination,
+        reply: oneshot::Sender<Result<TransactionPage, ProcessingError>>,
+    },
+    /// Balances and hot transactions, for an engine snapshot
+    ExportState {
+        reply: oneshot::Sender<(Account, Vec<(u32, StoredTransaction)>)>,
+    },
     Shutdown,
 }
 
+/// Side of a transfer applied by a single actor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferLeg {
+    /// Take the amount out of the sender's available funds
+    Debit,
+    /// Add the amount to the receiver's available funds
+    Credit,
+    /// Give a debited amount back to the sender after the credit failed
+    Refund,
+}
+
+/// What taking back one applied message needs, see `AccountMessage::Revert`
+///
+/// Balances are reverted by difference, so what other messages changed since
+/// stays. The transaction stored under the row's id goes back to how it was;
+/// a custom handler storing under any other id is not covered.
+#[derive(Debug, Clone)]
+pub struct Undo {
+    client: u16,
+    available: Decimal,
+    held: Decimal,
+    held_on_lock: Decimal,
+    /// Lock state before, when the message changed it
+    locked: Option<bool>,
+    /// The row's id and its stored transaction before, None where the message stores nothing
+    stored: Option<(u32, Option<(StoredTransaction, StorageTier)>)>,
+    /// Reporting counter the message bumped
+    counted: Option<(CounterKind, SystemTime)>,
+}
+
+impl Undo {
+    /// Client whose actor applied the message
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+}
+
+/// One check a row goes through on its account, and how it came out
+#[derive(Debug)]
+pub struct ValidationCheck {
+    pub check: &'static str,
+    pub outcome: Result<(), ProcessingError>,
+}
+
+/// Outcome of a row given its checks, the first failure if any
+pub fn first_failure(checks: Vec<ValidationCheck>) -> Result<(), ProcessingError> {
+    checks.into_iter().map(|check| check.outcome).find(Result::is_err).unwrap_or(Ok(()))
+}
+
+/// Shared dependencies handed to every account actor
+#[derive(Clone)]
+pub struct ActorServices {
+    pub cold_storage: Arc<dyn TransactionStore>,
+    pub migration_metrics: Arc<MigrationMetrics>,
+    pub migration_config: MigrationConfig,
+    pub actor_config: ActorConfig,
+    pub storage_retry: RetryPolicy,
+    pub projection: mpsc::UnboundedSender<Account>,
+    pub handlers: Arc<HandlerRegistry>,
+    pub counters: Arc<ReportingCounters>,
+    pub clock: Arc<dyn Clock>,
+    pub compat: CompatConfig,
+    pub snapshots: Arc<dyn AccountSnapshotStore>,
+    pub totals: Arc<ShardTotals>,
+    pub alerts: Arc<AlertRules>,
+    /// Every applied change's new state goes here, for live subscribers
+    pub updates: AccountUpdates,
+}
+
+/// Lifetime and storage limits of each account actor
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActorConfig {
+    /// Days transactions stay in hot storage before migrating to cold storage
+    pub hot_cutoff_days: u64,
+    /// An actor idle this long persists its account and stops
+    pub idle_timeout_secs: u64,
+    /// Messages queued for an actor before senders wait
+    pub mailbox_size: usize,
+    /// Live actors across all shards, past which the least recently used are
+    /// stopped as if idle; unbounded by default
+    pub max_resident: Option<usize>,
+}
+
+impl Default for ActorConfig {
+    fn default() -> Self {
+        Self {
+            hot_cutoff_days: 90,
+            idle_timeout_secs: 3600,
+            mailbox_size: 1000,
+            max_resident: None,
+        }
+    }
+}
+
 pub struct AccountActor {
     client_id: u16,
     account: Account,
-    hot_transactions: HashMap<u32, StoredTransaction>,
-    cold_storage: Arc<dyn TransactionStore>,
+    hot_transactions: HotStore,
+    services: ActorServices,
+    migration_in_flight: bool,
+    migration_done_tx: mpsc::Sender<MigrationOutcome>,
+    migration_done_rx: mpsc::Receiver<MigrationOutcome>,
+    // Hot entries changed while their older copy was being written to cold storage
+    shadowed_cold: HashSet<u32>,
+    // What the row being processed found under its tx id before changing it, see `stored_before`
+    loaded: Option<(StoredTransaction, StorageTier)>,
     hot_cutoff_days: u64,
+    // Set by a forced migration, drained up to this instead of the hot window until done
+    forced_cutoff: Option<SystemTime>,
     idle_timeout: Duration,
     last_activity: SystemTime,
     receiver: mpsc::Receiver<AccountMessage>,
 }
 
-//TODO: Move to cuutoff and idle timeout to config
 impl AccountActor {
     pub fn new(
         client_id: u16,
         receiver: mpsc::Receiver<AccountMessage>,
-        cold_storage: Arc<dyn TransactionStore>,
+        services: ActorServices,
     ) -> Self {
+        let (migration_done_tx, migration_done_rx) = mpsc::channel(1);
+        let now = services.clock.now();
+        let hot_cutoff_days = services.actor_config.hot_cutoff_days;
+        let idle_timeout = Duration::from_secs(services.actor_config.idle_timeout_secs);
+        
         Self {
             client_id,
             account: Account::new(client_id),
-            hot_transactions: HashMap::new(),
-            cold_storage,
-            hot_cutoff_days: 90, // 90-day hot storage window
-            idle_timeout: Duration::from_secs(3600), // 1 hour idle timeout
-            last_activity: SystemTime::now(),
+            hot_transactions: HotStore::default(),
+            services,
+            migration_in_flight: false,
+            migration_done_tx,
+            migration_done_rx,
+            shadowed_cold: HashSet::new(),
+            loaded: None,
+            hot_cutoff_days,
+            forced_cutoff: None,
+            idle_timeout,
+            last_activity: now,
             receiver,
         }
     }
     
     /// Run the actor event loop with automatic background migration and idle timeout
     pub async fn run(mut self) {
-        use tokio::time::{interval, Duration};
+        use tokio::time::{interval_at, Duration, Instant};
         
-        // Trigger migration every hour to keep hot storage bounded
-        let mut migration_timer = interval(Duration::from_secs(3600));
-        migration_timer.tick().await; // Skip first immediate tick
+        // Pick up where a previous actor for this client left off
+        if let Some(account) = self.services.snapshots.load(self.client_id).await {
+            self.account = account;
+        }
         
-        // Check for idle timeout every 5 minutes
-        let mut idle_check_timer = interval(Duration::from_secs(300));
+        // Trigger migration every hour to keep hot storage bounded, first after one period
+        let migration_every = Duration::from_secs(3600);
+        let mut migration_timer = interval_at(Instant::now() + migration_every, migration_every);
         
-        // Skip first immediate tick
-        idle_check_timer.tick().await;
+        // Check for idle timeout every 5 minutes, or as often as a shorter timeout needs
+        let idle_check_every = Duration::from_secs(300).min(self.idle_timeout).max(Duration::from_secs(1));
+        let mut idle_check_timer = interval_at(Instant::now() + idle_check_every, idle_check_every);
         
         loop {
             tokio::select! {
-                Some(msg) = self.receiver.recv() => {
+                msg = self.receiver.recv() => {
+                    // All handles dropped, nobody can reach this actor anymore
+                    let Some(msg) = msg else { break };
                     
-                    self.last_activity = SystemTime::now();
+                    self.last_activity = self.services.clock.now();
                     
                     match msg {
-                        AccountMessage::Process { tx, reply } => {
-                            let result = self.process_transaction(tx).await;
+                        AccountMessage::Process { tx, at, reply } => {
+                            // Only live traffic counts, replayed events were counted when first applied
+                            let counted = counter_kind(&tx.tx_type).map(|kind| (kind, at));
+                            let previous = self.account.clone();
+                            let stored = self.stored_before(&tx);
+                            self.loaded = None;
+                            let result = self.process_transaction(tx, false).await;
+                            let loaded = self.loaded.take();
+                            let stored = stored.map(|(tx_id, before)| (tx_id, before.or(loaded)));
+                            let result = result.map(|()| {
+                                self.publish(&previous);
+                                if let Some((kind, at)) = counted {
+                                    self.services.counters.record(self.client_id, kind, at);
+                                }
+                                self.undo(&previous, stored, counted)
+                            });
+                            let _ = reply.send(result);
+                        }
+                        AccountMessage::Replay { tx, reply } => {
+                            let previous = self.account.clone();
+                            let result = self.process_transaction(tx, true).await;
+                            if result.is_ok() {
+                                self.publish(&previous);
+                            }
+                            let _ = reply.send(result);
+                        }
+                        AccountMessage::Transfer { tx, leg, at, reply } => {
+                            // Counted once, against the sender
+                            let counted = at.filter(|_| leg == TransferLeg::Debit).map(|at| (CounterKind::Transaction, at));
+                            let previous = self.account.clone();
+                            let stored = match leg {
+                                TransferLeg::Debit => Some((tx.tx, None)),
+                                TransferLeg::Credit | TransferLeg::Refund => None,
+                            };
+                            let result = self.process_transfer_leg(tx, leg, at.is_none());
+                            let result = result.map(|()| {
+                                self.publish(&previous);
+                                if let Some((kind, at)) = counted {
+                                    self.services.counters.record(self.client_id, kind, at);
+                                }
+                                self.undo(&previous, stored, counted)
+                            });
+                            let _ = reply.send(result);
+                        }
+                        AccountMessage::Revert { undo, reply } => {
+                            let previous = self.account.clone();
+                            let result = self.revert(undo).await;
+                            if result.is_ok() {
+                                self.publish(&previous);
+                            }
+                            let _ = reply.send(result);
+                        }
+                        AccountMessage::Evaluate { tx, reply } => {
+                            let _ = reply.send(self.checks(&tx).await);
+                        }
+                        AccountMessage::Unlock { reply } => {
+                            let previous = self.account.clone();
+                            let result = self.unlock().map(|()| {
+                                self.publish(&previous);
+                                self.undo(&previous, None, None)
+                            });
                             let _ = reply.send(result);
                         }
                         AccountMessage::GetState { reply } => {
                             let _ = reply.send(self.account.clone());
                         }
+                        AccountMessage::InspectTransaction { tx_id, reply } => {
+                            let _ = reply.send(self.inspect_transaction(tx_id).await);
+                        }
                         AccountMessage::MigrateCold => {
                             if let Err(e) = self.migrate_old_transactions().await {
                                 error!(
@@ -87,10 +336,43 @@ impl AccountActor {
                                 );
                             }
                         }
-                        AccountMessage::Shutdown => break,
+                        AccountMessage::ForceMigrateCold => {
+                            self.forced_cutoff = Some(self.services.clock.now() + Duration::from_secs(1));
+                            if let Err(e) = self.migrate_old_transactions().await {
+                                error!(
+                                    client_id = self.client_id,
+                                    error = ?e,
+                                    "Failed to start forced migration"
+                                );
+                            }
+                        }
+                        AccountMessage::DiscardHot => {
+                            self.discard_hot().await;
+                        }
+                        AccountMessage::HotTransactions { reply } => {
+                            let _ = reply.send(self.hot_transactions.entries(self.services.clock.now()));
+                        }
+                        AccountMessage::HotStorageLen { reply } => {
+                            let _ = reply.send(self.hot_transactions.len());
+                        }
+                        AccountMessage::GetTransactions { page, reply } => {
+                            let _ = reply.send(self.transaction_page(page).await);
+                        }
+                        AccountMessage::ExportState { reply } => {
+                            let _ = reply.send((self.account.clone(), self.hot_transactions.all()));
+                        }
+                        AccountMessage::Shutdown => {
+                            self.persist().await;
+                            break;
+                        }
                     }
                 }
                 
+                // Background migration batch finished
+                Some(outcome) = self.migration_done_rx.recv() => {
+                    self.finish_migration(outcome).await;
+                }
+                
                 // Automatic periodic migration
                 _ = migration_timer.tick() => {
                     if let Err(e) = self.migrate_old_transactions().await {
@@ -104,7 +386,7 @@ impl AccountActor {
                 
                 // Check for idle timeout
                 _ = idle_check_timer.tick() => {
-                    let idle_duration = SystemTime::now()
+                    let idle_duration = self.services.clock.now()
                         .duration_since(self.last_activity)
                         .unwrap_or(Duration::ZERO);
                     
@@ -114,6 +396,7 @@ impl AccountActor {
                             self.client_id,
                             idle_duration
                         );
+                        self.persist().await;
                         break; // Self-terminate
                     }
                 }
@@ -123,42 +406,450 @@ impl AccountActor {
         tracing::debug!("Actor for client {} terminated", self.client_id);
     }
     
-    /// Migrate old transactions from hot to cold storage
+    /// The transaction stored under the row's id, as it is before the row applies
+    ///
+    /// Outer None when the row stores nothing; a row creating its id finds
+    /// nothing there. Rows changing a stored transaction leave it to their
+    /// handler's own lookup, kept in `loaded`, so cold storage is read once.
+    fn stored_before(&self, tx: &TransactionRow) -> Option<(u32, Option<(StoredTransaction, StorageTier)>)> {
+        let before = match &tx.tx_type {
+            TransactionType::OpeningBalance | TransactionType::Unlock | TransactionType::Transfer => return None,
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Authorize
+            | TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Capture
+            | TransactionType::Void => None,
+            // Handlers only reach hot storage
+            TransactionType::Custom(_) => self.hot_transactions.get(&tx.tx).map(|stored| (stored.clone(), StorageTier::Hot)),
+        };
+        Some((tx.tx, before))
+    }
+    
+    /// Keep what a handler found under `tx_id` before changing it, for the row's undo record
+    fn keep_loaded(&mut self, tx_id: u32, stored: &StoredTransaction) {
+        let tier = if self.hot_transactions.contains_key(&tx_id) { StorageTier::Hot } else { StorageTier::Cold };
+        self.loaded = Some((stored.clone(), tier));
+    }
+    
+    /// How to take back the message that just turned `previous` into the current account
+    fn undo(
+        &self,
+        previous: &Account,
+        stored: Option<(u32, Option<(StoredTransaction, StorageTier)>)>,
+        counted: Option<(CounterKind, SystemTime)>,
+    ) -> Undo {
+        Undo {
+            client: self.client_id,
+            available: self.account.available - previous.available,
+            held: self.account.held - previous.held,
+            held_on_lock: self.account.held_on_lock - previous.held_on_lock,
+            locked: (self.account.locked != previous.locked).then_some(previous.locked),
+            stored,
+            counted,
+        }
+    }
+    
+    /// Take back an applied message, its stored transaction first so a failed write changes nothing
+    async fn revert(&mut self, undo: Undo) -> Result<(), ProcessingError> {
+        match undo.stored {
+            Some((tx_id, Some((stored, StorageTier::Hot)))) => self.hot_transactions.insert(tx_id, stored),
+            Some((tx_id, Some((stored, StorageTier::Cold)))) => self.update_stored_transaction(tx_id, stored).await?,
+            Some((tx_id, None)) => self.remove_stored_transaction(tx_id).await?,
+            None => {}
+        }
+        
+        self.account.available -= undo.available;
+        self.account.held -= undo.held;
+        self.account.held_on_lock -= undo.held_on_lock;
+        if let Some(locked) = undo.locked {
+            self.account.locked = locked;
+        }
+        if let Some((kind, at)) = undo.counted {
+            self.services.counters.forget(self.client_id, kind, at);
+        }
+        Ok(())
+    }
+    
+    /// Make an applied change visible to the shard totals, the projection and the alert rules
+    fn publish(&self, previous: &Account) {
+        self.services.totals.apply(previous, &self.account);
+        self.services.alerts.evaluate(previous, &self.account);
+        
+        // Read model lags by design, a closed projection is not an error
+        let _ = self.services.projection.send(self.account.clone());
+        self.services.updates.publish(&self.account);
+    }
+    
+    /// Save everything a later actor for this client needs, before stopping
+    ///
+    /// Hot transactions go to cold storage so they stay disputable, balances
+    /// go to the snapshot store. Messages queued meanwhile are dropped unanswered
+    /// and retried by the shard manager on the next actor.
+    async fn persist(&mut self) {
+        // In-flight batches must settle first, a late put would overwrite what is written here
+        while self.migration_in_flight {
+            let Some(outcome) = self.migration_done_rx.recv().await else { break };
+            self.finish_migration(outcome).await;
+        }
+        
+        for (tx_id, tx) in self.hot_transactions.drain_all() {
+            let written = measure(Site::ColdStorage, self.services.cold_storage.put(tx_id, tx)).await;
+            if let Err(e) = written {
+                error!(
+                    client_id = self.client_id,
+                    tx_id = tx_id,
+                    error = ?e,
+                    "Failed to move transaction to cold storage on shutdown"
+                );
+            }
+        }
+        self.shadowed_cold.clear();
+        
+        self.save_snapshot().await;
+    }
+    
+    /// Drop hot storage outright, a migration in flight removes its cold copies as it lands
+    async fn discard_hot(&mut self) {
+        for (tx_id, _) in self.hot_transactions.drain_all() {
+            // The cold copy a racing migration left behind is stale, it goes too
+            if !self.shadowed_cold.remove(&tx_id) {
+                continue;
+            }
+            let removed = measure(Site::ColdStorage, self.services.cold_storage.remove(tx_id)).await;
+            if let Err(e) = removed {
+                error!(
+                    client_id = self.client_id,
+                    tx_id = tx_id,
+                    error = ?e,
+                    "Failed to remove discarded transaction from cold storage"
+                );
+            }
+        }
+    }
+    
+    async fn save_snapshot(&self) {
+        if let Err(e) = self.services.snapshots.save(&self.account).await {
+            error!(
+                client_id = self.client_id,
+                error = ?e,
+                "Failed to save account snapshot"
+            );
+        }
+    }
+    
+    /// Start moving old transactions from hot to cold storage
+    ///
+    /// The cold-storage writes run in a spawned task so the actor keeps serving
+    /// messages; transactions stay in hot storage until the batch reports back.
     async fn migrate_old_transactions(&mut self) -> Result<(), ProcessingError> {
-        let cutoff = SystemTime::now() - Duration::from_secs(self.hot_cutoff_days * 24 * 3600);
+        if self.migration_in_flight {
+            return Ok(());
+        }
+        
+        let cutoff = self.forced_cutoff.unwrap_or_else(|| {
+            self.services.clock.now() - Duration::from_secs(self.hot_days() * 24 * 3600)
+        });
+        
+        // Only the expired time range is visited, not every hot transaction
+        let batch = self.hot_transactions.expired(cutoff, self.services.migration_config.batch_size);
+        if batch.is_empty() {
+            self.forced_cutoff = None;
+            return Ok(());
+        }
+        
+        self.migration_in_flight = true;
         
-        let to_migrate: Vec<_> = self.hot_transactions.iter()
-            .filter(|(_, tx)| tx.created_at < cutoff)
-            .map(|(id, tx)| (*id, tx.clone()))
-            .collect();
+        let cold_storage = self.services.cold_storage.clone();
+        let config = self.services.migration_config.clone();
+        let done = self.migration_done_tx.clone();
+        tokio::spawn(async move {
+            let outcome = migration::migrate_batch(cold_storage, batch, &config).await;
+            let _ = done.send(outcome).await;
+        });
+        
+        Ok(())
+    }
+    
+    /// Days this client's transactions stay hot, shorter for a low-risk client
+    fn hot_days(&self) -> u64 {
+        let (transactions, disputes) = self.services.counters.client_totals(self.client_id);
+        self.services
+            .migration_config
+            .hot_days(self.hot_cutoff_days, transactions, disputes)
+    }
+    
+    /// Drop migrated transactions from hot storage once cold storage has them
+    async fn finish_migration(&mut self, outcome: MigrationOutcome) {
+        self.migration_in_flight = false;
         
-        for (tx_id, tx) in to_migrate {
-            match self.cold_storage.put(tx_id, tx).await {
-                Ok(_) => {
+        let migrated = outcome.migrated.len() as u64;
+        let batch_len = outcome.migrated.len() + outcome.failed;
+        
+        // What a forced drain moves early was asked for, not decided by the policy
+        if self.forced_cutoff.is_none() {
+            let window_start = self.services.clock.now() - Duration::from_secs(self.hot_cutoff_days * 24 * 3600);
+            let early = outcome
+                .migrated
+                .iter()
+                .filter(|(_, tx)| tx.created_at > window_start)
+                .count() as u64;
+            self.services.migration_metrics.record_early(early);
+        }
+        
+        for (tx_id, snapshot) in outcome.migrated {
+            match self.hot_transactions.get(&tx_id) {
+                Some(current) if *current == snapshot => {
                     self.hot_transactions.remove(&tx_id);
+                    self.shadowed_cold.remove(&tx_id);
                 }
-                Err(e) => {
-                    error!(
-                        client_id = self.client_id,
-                        tx_id = tx_id,
-                        error = ?e,
-                        "Failed to migrate transaction to cold storage - keeping in hot storage"
-                    );
+                // Changed mid-flight: hot copy is fresher, cold copy must not outlive it
+                Some(_) => {
+                    self.shadowed_cold.insert(tx_id);
+                }
+                // Charged back mid-flight: the migrated copy is stale
+                None => {
+                    let removed = measure(Site::ColdStorage, self.services.cold_storage.remove(tx_id)).await;
+                    if let Err(e) = removed {
+                        error!(
+                            client_id = self.client_id,
+                            tx_id = tx_id,
+                            error = ?e,
+                            "Failed to remove stale migrated transaction"
+                        );
+                    }
                 }
             }
         }
         
-        Ok(())
+        self.services.migration_metrics.record_run(migrated, outcome.failed as u64, outcome.elapsed);
+        self.save_snapshot().await;
+        if batch_len > 0 {
+            tracing::debug!(
+                client_id = self.client_id,
+                migrated,
+                failed = outcome.failed,
+                elapsed = ?outcome.elapsed,
+                "Migrated hot transactions to cold storage"
+            );
+        }
+        
+        // A full clean batch means more may be waiting, keep draining the backlog;
+        // a forced drain requested meanwhile has not started yet either
+        let full_batch = batch_len == self.services.migration_config.batch_size;
+        if outcome.failed == 0 && (full_batch || self.forced_cutoff.is_some()) {
+            if let Err(e) = self.migrate_old_transactions().await {
+                error!(
+                    client_id = self.client_id,
+                    error = ?e,
+                    "Failed to continue migration"
+                );
+            }
+        } else {
+            self.forced_cutoff = None;
+        }
     }
     
-    async fn process_transaction(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
+    async fn process_transaction(
+        &mut self,
+        tx: TransactionRow,
+        replay: bool,
+    ) -> Result<(), ProcessingError> {
         match tx.tx_type {
             TransactionType::Deposit => self.process_deposit(tx),
             TransactionType::Withdrawal => self.process_withdrawal(tx),
             TransactionType::Dispute => self.process_dispute(tx).await,
             TransactionType::Resolve => self.process_resolve(tx).await,
             TransactionType::Chargeback => self.process_chargeback(tx).await,
+            TransactionType::OpeningBalance => self.process_opening_balance(tx, replay).await,
+            // Live unlocks arrive as `AccountMessage::Unlock`, the log replays them as rows
+            TransactionType::Unlock => self.unlock(),
+            TransactionType::Authorize => self.process_authorize(tx),
+            TransactionType::Capture => self.process_capture(tx).await,
+            TransactionType::Void => self.process_void(tx).await,
+            // Transfers span two actors and arrive as legs, see `process_transfer_leg`
+            TransactionType::Transfer => Err(ProcessingError::UnsupportedTransactionType),
+            TransactionType::Custom(_) => self.process_custom(tx, replay),
+        }
+    }
+    
+    /// Apply an extension type through its registered handler
+    fn process_custom(&mut self, tx: TransactionRow, replay: bool) -> Result<(), ProcessingError> {
+        let handler = self
+            .services
+            .handlers
+            .get(tx.tx_type.as_str())
+            .ok_or(ProcessingError::UnsupportedTransactionType)?;
+        
+        if self.account.locked {
+            return Err(ProcessingError::AccountLocked);
+        }
+        
+        // Handlers mutate a copy so a failed apply leaves the account untouched
+        let mut account = self.account.clone();
+        let now = self.services.clock.now();
+        let mut ctx = HandlerContext::new(&mut account, &mut self.hot_transactions, now);
+        if replay {
+            handler.replay(&mut ctx, &tx)?;
+        } else {
+            handler.apply(&mut ctx, &tx)?;
+        }
+        
+        self.account = account;
+        Ok(())
+    }
+    
+    /// Each check `tx` goes through on this account, in order, with its outcome now
+    ///
+    /// A transfer is checked as the debit on the sender's actor and as the
+    /// credit on the receiver's. Custom types run their handler on copies of
+    /// the account and hot storage. A check that needs an earlier one to pass,
+    /// like funds after the amount, is left out when it didn't.
+    pub(crate) async fn checks(&self, tx: &TransactionRow) -> Vec<ValidationCheck> {
+        if let Some(checks) = self.balance_checks(tx) {
+            return checks;
+        }
+        
+        let mut checks = Vec::new();
+        let mut check = |check: &'static str, outcome: Result<(), ProcessingError>| {
+            checks.push(ValidationCheck { check, outcome });
+        };
+        match &tx.tx_type {
+            TransactionType::OpeningBalance => {
+                check("amount", self.validate_amount(tx.amount).map(drop));
+                check("fresh_account", self.check_fresh(false).await);
+            }
+            TransactionType::Dispute => check("dispute_target", self.dispute_target(tx.tx).await.map(drop)),
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                check("disputed_target", self.disputed_target(tx.tx).await.map(drop));
+            }
+            TransactionType::Capture => check("authorization", self.authorization_target(tx.tx, true).await.map(drop)),
+            TransactionType::Void => check("authorization", self.authorization_target(tx.tx, false).await.map(drop)),
+            TransactionType::Custom(name) => {
+                let Some(handler) = self.services.handlers.get(name) else {
+                    check("handler", Err(ProcessingError::UnsupportedTransactionType));
+                    return checks;
+                };
+                if self.account.locked {
+                    check("unlocked", Err(ProcessingError::AccountLocked));
+                    return checks;
+                }
+                check("unlocked", Ok(()));
+                
+                let mut account = self.account.clone();
+                let mut hot_transactions = self.hot_transactions.clone();
+                let now = self.services.clock.now();
+                check("handler", handler.apply(&mut HandlerContext::new(&mut account, &mut hot_transactions, now), tx));
+            }
+            _ => unreachable!("{:?} is checked by balance_checks", tx.tx_type),
+        }
+        checks
+    }
+    
+    /// Checks of the rows the account decides alone, `None` for rows that need a stored transaction or a handler
+    ///
+    /// Processing runs these same checks, see `admit`.
+    fn balance_checks(&self, tx: &TransactionRow) -> Option<Vec<ValidationCheck>> {
+        let mut checks = Vec::new();
+        let mut check = |check: &'static str, outcome: Result<(), ProcessingError>| {
+            checks.push(ValidationCheck { check, outcome });
+        };
+        match &tx.tx_type {
+            TransactionType::Deposit => {
+                check("amount", self.validate_amount(tx.amount).map(drop));
+                if !self.holds_deposits() {
+                    check("unlocked", self.check_unlocked(false));
+                }
+            }
+            TransactionType::Transfer if tx.to == Some(self.client_id) => {
+                check("recipient_unlocked", self.check_unlocked(false));
+            }
+            TransactionType::Withdrawal | TransactionType::Transfer | TransactionType::Authorize => {
+                let amount = self.validate_amount(tx.amount);
+                let funds = amount.as_ref().ok().map(|&amount| self.check_funds(amount));
+                check("amount", amount.map(drop));
+                check("unlocked", self.check_unlocked(true));
+                if let Some(funds) = funds {
+                    check("funds", funds);
+                }
+            }
+            TransactionType::Unlock => check("locked", self.check_locked()),
+            _ => return None,
+        }
+        Some(checks)
+    }
+    
+    /// The row's amount if it passes its `balance_checks`, the first failure otherwise
+    fn admit(&self, tx: &TransactionRow) -> Result<Decimal, ProcessingError> {
+        first_failure(self.balance_checks(tx).unwrap_or_default())?;
+        Ok(tx.amount.unwrap_or_default())
+    }
+    
+    /// Reject if locked; with `LockScope::Outflows` a lock only stops money leaving
+    fn check_unlocked(&self, outflow: bool) -> Result<(), ProcessingError> {
+        let blocked = match self.services.compat.lock_scope {
+            LockScope::All => true,
+            LockScope::Outflows => outflow,
+        };
+        if self.account.locked && blocked {
+            return Err(ProcessingError::AccountLocked);
         }
+        Ok(())
+    }
+    
+    /// Whether a deposit the lock would refuse is accepted into `held` instead
+    fn holds_deposits(&self) -> bool {
+        self.account.locked
+            && self.services.compat.lock_scope == LockScope::All
+            && self.services.compat.lock_policy == LockPolicy::Hold
+    }
+    
+    fn check_funds(&self, amount: Decimal) -> Result<(), ProcessingError> {
+        if self.account.available < amount {
+            return Err(ProcessingError::InsufficientFunds);
+        }
+        Ok(())
+    }
+    
+    /// Opening balances only seed brand-new accounts
+    ///
+    /// Transactions migrated to cold storage count as history too, an account
+    /// netting to zero isn't new. Replay skips that lookup: the row was fresh
+    /// when logged, and cold storage may already hold what followed it.
+    async fn check_fresh(&self, replay: bool) -> Result<(), ProcessingError> {
+        let is_fresh = self.account.available.is_zero()
+            && self.account.held.is_zero()
+            && !self.account.locked
+            && self.hot_transactions.is_empty();
+        if !is_fresh {
+            return Err(ProcessingError::AccountNotEmpty);
+        }
+        if replay {
+            return Ok(());
+        }
+        
+        let listed = self.services.cold_storage.list_client(self.client_id, None, 1);
+        let cold = measure(Site::ColdStorage, listed).await.map_err(|e| {
+            tracing::error!(
+                client_id = self.client_id,
+                error = ?e,
+                "Failed to list transactions in cold storage"
+            );
+            ProcessingError::StorageUnavailable
+        })?;
+        if !cold.is_empty() {
+            return Err(ProcessingError::AccountNotEmpty);
+        }
+        Ok(())
+    }
+    
+    fn check_locked(&self) -> Result<(), ProcessingError> {
+        if !self.account.locked {
+            return Err(ProcessingError::AccountNotLocked);
+        }
+        Ok(())
     }
     
     fn validate_amount(&self, amount_opt: Option<Decimal>) -> Result<Decimal, ProcessingError> {
@@ -178,17 +869,22 @@ impl AccountActor {
                 amount,
                 disputed: false,
                 held_amount: None,
-                created_at: SystemTime::now(),
+                created_at: self.services.clock.now(),
             },
         );
     }
     
     fn process_deposit(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        let amount = self.validate_amount(tx.amount)?;
+        let amount = self.admit(&tx)?;
         
-        if self.account.locked {
-            return Err(ProcessingError::AccountLocked);
+        if self.holds_deposits() {
+            // Stored like any deposit, so its tx id stays taken and it can be disputed once released
+            self.account.held += amount;
+            self.account.held_on_lock += amount;
+            self.store_transaction(tx.tx, TransactionType::Deposit, amount);
+            return Ok(());
         }
+        self.check_unlocked(false)?;
         
         self.account.available += amount;
         self.store_transaction(tx.tx, TransactionType::Deposit, amount);
@@ -197,15 +893,7 @@ impl AccountActor {
     }
     
     fn process_withdrawal(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        let amount = self.validate_amount(tx.amount)?;
-        
-        if self.account.locked {
-            return Err(ProcessingError::AccountLocked);
-        }
-        
-        if self.account.available < amount {
-            return Err(ProcessingError::InsufficientFunds);
-        }
+        let amount = self.admit(&tx)?;
         
         self.account.available -= amount;
 
@@ -215,70 +903,176 @@ impl AccountActor {
         Ok(())
     }
     
+    fn process_authorize(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        let amount = self.admit(&tx)?;
+        
+        // Funds stay in the account, held until captured or voided
+        self.account.available -= amount;
+        self.account.held += amount;
+        self.store_transaction(tx.tx, TransactionType::Authorize, amount);
+        
+        Ok(())
+    }
+    
+    async fn process_opening_balance(&mut self, tx: TransactionRow, replay: bool) -> Result<(), ProcessingError> {
+        let amount = self.validate_amount(tx.amount)?;
+        self.check_fresh(replay).await?;
+        
+        // Not stored: an opening balance is not a disputable transaction
+        self.account.available = amount;
+        
+        Ok(())
+    }
+    
+    fn unlock(&mut self) -> Result<(), ProcessingError> {
+        self.check_locked()?;
+        self.account.locked = false;
+        // Deposits held while locked become spendable
+        self.account.available += self.account.held_on_lock;
+        self.account.held -= self.account.held_on_lock;
+        self.account.held_on_lock = Decimal::ZERO;
+        Ok(())
+    }
+    
+    fn process_transfer_leg(&mut self, tx: TransactionRow, leg: TransferLeg, replay: bool) -> Result<(), ProcessingError> {
+        let amount = self.validate_amount(tx.amount)?;
+        
+        match leg {
+            TransferLeg::Debit => {
+                self.admit(&tx)?;
+                
+                self.account.available -= amount;
+                // Stored for audit like a withdrawal, transfers cannot be disputed
+                self.store_transaction(tx.tx, TransactionType::Transfer, amount);
+            }
+            TransferLeg::Credit => {
+                // A logged transfer was credited when it happened, a lock logged
+                // before it may have been applied after it
+                if !replay {
+                    self.admit(&tx)?;
+                }
+                
+                self.account.available += amount;
+            }
+            TransferLeg::Refund => {
+                // Undoes this actor's own debit, so a lock taken in between does not block it
+                self.account.available += amount;
+                self.hot_transactions.remove(&tx.tx);
+            }
+        }
+        
+        Ok(())
+    }
+    
     async fn get_stored_transaction(&self, tx_id: u32) -> Option<StoredTransaction> {
         if let Some(stored) = self.hot_transactions.get(&tx_id) {
             return Some(stored.clone());
         }
         
-        self.cold_storage.get(tx_id).await
+        measure(Site::ColdStorage, self.services.cold_storage.get(tx_id)).await
+    }
+    
+    /// Look up a transaction and report which storage tier currently holds it
+    async fn inspect_transaction(&self, tx_id: u32) -> Option<(StoredTransaction, StorageTier)> {
+        if let Some(stored) = self.hot_transactions.get(&tx_id) {
+            return Some((stored.clone(), StorageTier::Hot));
+        }
+        
+        self.services.cold_storage
+            .get(tx_id)
+            .await
+            .filter(|stored| stored.client == self.client_id)
+            .map(|stored| (stored, StorageTier::Cold))
+    }
+    
+    /// Hot transactions merged with a range scan over this client's cold ones
+    async fn transaction_page(&self, page: Pagination) -> Result<TransactionPage, ProcessingError> {
+        let listed = self.services.cold_storage.list_client(self.client_id, page.after, page.limit());
+        let cold = measure(Site::ColdStorage, listed).await.map_err(|e| {
+            tracing::error!(
+                client_id = self.client_id,
+                error = ?e,
+                "Failed to list transactions in cold storage"
+            );
+            ProcessingError::StorageUnavailable
+        })?;
+        
+        Ok(history::merge_page(self.hot_transactions.all(), cold, page))
     }
     
+    /// Write back a changed transaction, before any balance change that depends on it
     async fn update_stored_transaction(
         &mut self,
         tx_id: u32,
         stored: StoredTransaction,
     ) -> Result<(), ProcessingError> {
-        if self.hot_transactions.contains_key(&tx_id) {
-            self.hot_transactions.insert(tx_id, stored);
+        if let Some(hot) = self.hot_transactions.get_mut(&tx_id) {
+            *hot = stored;
             return Ok(());
         }
         
-        if let Err(e) = self.cold_storage.put(tx_id, stored).await {
+        let cold_storage = &self.services.cold_storage;
+        let written = self
+            .services
+            .storage_retry
+            .run(|| measure(Site::ColdStorage, cold_storage.put(tx_id, stored.clone())))
+            .await;
+        if let Err(e) = written {
             tracing::error!(
                 client_id = self.client_id,
                 tx_id = tx_id,
                 error = ?e,
                 "Failed to update transaction in cold storage"
             );
-            return Err(ProcessingError::TransactionNotFound);
+            return Err(ProcessingError::StorageUnavailable);
         }
         
         Ok(())
     }
     
     async fn remove_stored_transaction(&mut self, tx_id: u32) -> Result<(), ProcessingError> {
-        if self.hot_transactions.contains_key(&tx_id) {
+        let shadowed = self.shadowed_cold.contains(&tx_id);
+        if self.hot_transactions.contains_key(&tx_id) && !shadowed {
             self.hot_transactions.remove(&tx_id);
             return Ok(());
         }
         
-        if let Err(e) = self.cold_storage.remove(tx_id).await {
+        let cold_storage = &self.services.cold_storage;
+        let removed = self
+            .services
+            .storage_retry
+            .run(|| measure(Site::ColdStorage, cold_storage.remove(tx_id)))
+            .await;
+        if let Err(e) = removed {
             tracing::error!(
                 client_id = self.client_id,
                 tx_id = tx_id,
                 error = ?e,
                 "Failed to remove transaction from cold storage"
             );
+            return Err(ProcessingError::StorageUnavailable);
         }
         
+        // Dropped only once cold storage agrees, a failed removal leaves everything as it was
+        self.hot_transactions.remove(&tx_id);
+        self.shadowed_cold.remove(&tx_id);
+        
         Ok(())
     }
     
-    async fn process_dispute(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        if self.account.locked {
-            return Err(ProcessingError::AccountLocked);
-        }
+    /// This client's stored transaction under `tx_id`, if it may be disputed now
+    async fn dispute_target(&self, tx_id: u32) -> Result<StoredTransaction, ProcessingError> {
+        self.check_unlocked(false)?;
         
-        let mut stored = self.get_stored_transaction(tx.tx).await
+        let stored = self.get_stored_transaction(tx_id).await
             .ok_or(ProcessingError::TransactionNotFound)?;
         
         if stored.client != self.client_id {
             return Err(ProcessingError::ClientMismatch);
         }
         
-        // Only deposits can be disputed
-        // Withdrawals are final and cannot be reversed
-        if stored.tx_type != TransactionType::Deposit {
+        // Only deposits can be disputed, unless the compat config opens up withdrawals
+        if !self.services.compat.disputable(&stored.tx_type) {
             return Err(ProcessingError::TransactionNotFound);
         }
         
@@ -286,28 +1080,34 @@ impl AccountActor {
             return Err(ProcessingError::AlreadyDisputed);
         }
         
-        // Dispute full amount, available can go negative
-        // This maintains total = available + held
-        let dispute_amount = stored.amount;
+        Ok(stored)
+    }
+    
+    /// This client's open authorization under `tx_id`, `capture` when its funds would leave
+    async fn authorization_target(&self, tx_id: u32, capture: bool) -> Result<StoredTransaction, ProcessingError> {
+        self.check_unlocked(capture)?;
         
-        // Can go negative
-        self.account.available -= dispute_amount; 
-        self.account.held += dispute_amount;
-        stored.disputed = true;
-        stored.held_amount = Some(dispute_amount);
+        let stored = self.get_stored_transaction(tx_id).await
+            .ok_or(ProcessingError::TransactionNotFound)?;
         
-        self.update_stored_transaction(tx.tx, stored).await?;
+        if stored.client != self.client_id {
+            return Err(ProcessingError::ClientMismatch);
+        }
         
-        Ok(())
+        // Captured authorizations are stored as withdrawals, voided ones are gone
+        if stored.tx_type != TransactionType::Authorize {
+            return Err(ProcessingError::TransactionNotFound);
+        }
+        
+        Ok(stored)
     }
     
-    async fn process_resolve(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        // Block all operations on locked accounts
-        if self.account.locked {
-            return Err(ProcessingError::AccountLocked);
-        }
+    /// This client's stored transaction under `tx_id`, if it is under dispute
+    async fn disputed_target(&self, tx_id: u32) -> Result<StoredTransaction, ProcessingError> {
+        // Block all operations on locked accounts, the first chargeback locks it
+        self.check_unlocked(false)?;
         
-        let mut stored = self.get_stored_transaction(tx.tx).await
+        let stored = self.get_stored_transaction(tx_id).await
             .ok_or(ProcessingError::TransactionNotFound)?;
         
         if stored.client != self.client_id {
@@ -318,66 +1118,208 @@ impl AccountActor {
             return Err(ProcessingError::NotDisputed);
         }
         
+        Ok(stored)
+    }
+    
+    async fn process_dispute(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        let mut stored = self.dispute_target(tx.tx).await?;
+        self.keep_loaded(tx.tx, &stored);
+        
+        // Dispute full amount, available can go negative
+        // This maintains total = available + held
+        let dispute_amount = stored.amount;
+        let mut staged = StagedBalances {
+            held: dispute_amount,
+            ..Default::default()
+        };
+        if stored.tx_type == TransactionType::Deposit {
+            // Can go negative
+            staged.available = -dispute_amount;
+        }
+        // A disputed withdrawal only holds the amount the client claims back
+        
+        stored.disputed = true;
+        stored.held_amount = Some(dispute_amount);
+        self.update_stored_transaction(tx.tx, stored).await?;
+        
+        staged.commit(&mut self.account);
+        Ok(())
+    }
+    
+    async fn process_resolve(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        let mut stored = self.disputed_target(tx.tx).await?;
+        self.keep_loaded(tx.tx, &stored);
+        
         // Use the actual held amount, not the original deposit amount
         let amount_to_restore = stored.held_amount.unwrap_or(stored.amount);
+        let mut staged = StagedBalances {
+            held: -amount_to_restore,
+            ..Default::default()
+        };
+        // A resolved withdrawal dispute stands, the held claim is simply dropped
+        if stored.tx_type != TransactionType::Withdrawal {
+            staged.available = amount_to_restore;
+        }
         
-        self.account.held -= amount_to_restore;
-        self.account.available += amount_to_restore;
         stored.disputed = false;
         stored.held_amount = None;
-        
-        
         self.update_stored_transaction(tx.tx, stored).await?;
         
+        staged.commit(&mut self.account);
         Ok(())
     }
     
     async fn process_chargeback(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        //Block if already locked, first chargeback locks account
-        if self.account.locked {
-            return Err(ProcessingError::AccountLocked);
+        let stored = self.disputed_target(tx.tx).await?;
+        self.keep_loaded(tx.tx, &stored);
+        
+        // Chargeback removes the held amount, total decreases with it
+        let held_amount = stored.held_amount.unwrap_or(Decimal::ZERO);
+        let mut staged = StagedBalances {
+            held: -held_amount,
+            lock: true,
+            ..Default::default()
+        };
+        // A charged back withdrawal returns the funds to the client
+        if stored.tx_type == TransactionType::Withdrawal {
+            staged.available = held_amount;
         }
         
-        let stored = self.get_stored_transaction(tx.tx).await
-            .ok_or(ProcessingError::TransactionNotFound)?;
+        self.remove_stored_transaction(tx.tx).await?;
         
-        if stored.client != self.client_id {
-            return Err(ProcessingError::ClientMismatch);
-        }
+        staged.commit(&mut self.account);
+        Ok(())
+    }
+    
+    async fn process_capture(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        let mut stored = self.authorization_target(tx.tx, true).await?;
+        self.keep_loaded(tx.tx, &stored);
         
-        if !stored.disputed {
-            return Err(ProcessingError::NotDisputed);
-        }
+        // The whole authorized amount is captured, the row's own amount is ignored
+        let staged = StagedBalances {
+            held: -stored.amount,
+            ..Default::default()
+        };
         
-        // Chargeback removes the held amount
-        let held_amount = stored.held_amount.unwrap_or(Decimal::ZERO);
+        // From here on it is an ordinary withdrawal, disputable like one
+        stored.tx_type = TransactionType::Withdrawal;
+        self.update_stored_transaction(tx.tx, stored).await?;
+        
+        staged.commit(&mut self.account);
+        Ok(())
+    }
+    
+    async fn process_void(&mut self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        let stored = self.authorization_target(tx.tx, false).await?;
+        self.keep_loaded(tx.tx, &stored);
+        
+        let staged = StagedBalances {
+            available: stored.amount,
+            held: -stored.amount,
+            ..Default::default()
+        };
         
-        self.account.held -= held_amount;
-
-        // Total decreases automatically when held decreases
-        self.account.locked = true;
-
         self.remove_stored_transaction(tx.tx).await?;
         
+        staged.commit(&mut self.account);
         Ok(())
     }
 }
 
+/// Balance change of a dispute or authorization step, committed only after its storage write succeeds
+///
+/// Staging keeps a failed write from leaving balances that disagree with the stored transaction.
+#[derive(Debug, Default)]
+struct StagedBalances {
+    available: Decimal,
+    held: Decimal,
+    lock: bool,
+}
+
+impl StagedBalances {
+    fn commit(self, account: &mut Account) {
+        account.available += self.available;
+        account.held += self.held;
+        account.locked |= self.lock;
+    }
+}
+
+/// Reporting counter bumped by an applied transaction of this type
+fn counter_kind(tx_type: &TransactionType) -> Option<CounterKind> {
+    match tx_type {
+        TransactionType::Deposit | TransactionType::Withdrawal => Some(CounterKind::Transaction),
+        TransactionType::Dispute => Some(CounterKind::Dispute),
+        TransactionType::Chargeback => Some(CounterKind::Chargeback),
+        _ => None,
+    }
+}
+
 #[derive(Clone)]
 pub struct AccountHandle {
     sender: mpsc::Sender<AccountMessage>,
+    // Shared by every clone, stamped by the shard manager on each use
+    last_used: Arc<AtomicU64>,
 }
 
 impl AccountHandle {
     pub fn new(sender: mpsc::Sender<AccountMessage>) -> Self {
-        Self { sender }
+        Self {
+            sender,
+            last_used: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    
+    /// Mark the actor used at `stamp`, a counter that only grows
+    pub(crate) fn touch(&self, stamp: u64) {
+        self.last_used.fetch_max(stamp, Ordering::Relaxed);
+    }
+    
+    pub(crate) fn last_used(&self) -> u64 {
+        self.last_used.load(Ordering::Relaxed)
+    }
+    
+    /// Whether the actor has stopped, e.g. after its idle timeout
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+    
+    /// Messages waiting in the actor's mailbox
+    pub fn mailbox_len(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+    
+    /// Ask the actor to persist its state and stop
+    pub async fn shutdown(&self) -> Result<(), ProcessingError> {
+        self.sender
+            .send(AccountMessage::Shutdown)
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    /// Wait until the actor has stopped, its state persisted
+    pub async fn stopped(&self) {
+        self.sender.closed().await
+    }
+    
+    /// Whether both handles reach the same actor
+    pub fn same_actor(&self, other: &AccountHandle) -> bool {
+        self.sender.same_channel(&other.sender)
     }
     
     pub async fn process(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        self.process_at(tx, SystemTime::now()).await
+    }
+    
+    pub async fn process_at(&self, tx: TransactionRow, at: SystemTime) -> Result<(), ProcessingError> {
+        self.process_undoable(tx, at).await.map(drop)
+    }
+    
+    /// Process `tx`, returning how to take it back should its event not get logged
+    pub async fn process_undoable(&self, tx: TransactionRow, at: SystemTime) -> Result<Undo, ProcessingError> {
         let (reply_tx, reply_rx) = oneshot::channel();
         
         self.sender
-            .send(AccountMessage::Process { tx, reply: reply_tx })
+            .send(AccountMessage::Process { tx, at, reply: reply_tx })
             .await
             .map_err(|_| ProcessingError::ActorCommunicationError)?;
         
@@ -386,6 +1328,165 @@ impl AccountHandle {
             .map_err(|_| ProcessingError::ActorCommunicationError)?
     }
     
+    pub async fn replay(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::Replay { tx, reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?
+    }
+    
+    /// Apply one side of a transfer, `at` is `None` when replaying from the log
+    pub async fn transfer_leg(
+        &self,
+        tx: TransactionRow,
+        leg: TransferLeg,
+        at: Option<SystemTime>,
+    ) -> Result<Undo, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::Transfer { tx, leg, at, reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?
+    }
+    
+    pub async fn evaluate(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        first_failure(self.checks(tx).await?)
+    }
+    
+    /// Checks the row would go through now, with their outcomes
+    pub async fn checks(&self, tx: TransactionRow) -> Result<Vec<ValidationCheck>, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::Evaluate { tx, reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    /// Take back a message this actor applied, see `Undo`
+    pub async fn revert(&self, undo: Undo) -> Result<(), ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::Revert { undo, reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?
+    }
+    
+    /// Clear the lock, returning how to put it back should the unlock not get logged
+    pub async fn unlock(&self) -> Result<Undo, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::Unlock { reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?
+    }
+    
+    /// Hand the actor a message, with no reply awaited beyond what it carries itself
+    pub async fn send(&self, message: AccountMessage) -> Result<(), ProcessingError> {
+        self.sender
+            .send(message)
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    /// Ask the actor to move all of its hot transactions to cold storage
+    pub async fn force_migrate_cold(&self) -> Result<(), ProcessingError> {
+        self.sender
+            .send(AccountMessage::ForceMigrateCold)
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    /// Ask the actor to forget its hot transactions, see `AccountMessage::DiscardHot`
+    pub async fn discard_hot(&self) -> Result<(), ProcessingError> {
+        self.send(AccountMessage::DiscardHot).await
+    }
+    
+    pub async fn hot_transactions(&self) -> Result<Vec<HotTransaction>, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::HotTransactions { reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    pub async fn hot_storage_len(&self) -> Result<usize, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::HotStorageLen { reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    pub async fn get_transactions(&self, page: Pagination) -> Result<TransactionPage, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::GetTransactions { page, reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?
+    }
+    
+    pub async fn export_state(&self) -> Result<(Account, Vec<(u32, StoredTransaction)>), ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::ExportState { reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
+    /// Ask the actor to start a hot-to-cold migration pass now
+    pub async fn migrate_cold(&self) -> Result<(), ProcessingError> {
+        self.sender
+            .send(AccountMessage::MigrateCold)
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
+    
     pub async fn get_state(&self) -> Result<Account, ProcessingError> {
         let (reply_tx, reply_rx) = oneshot::channel();
         
@@ -398,4 +1499,20 @@ impl AccountHandle {
             .await
             .map_err(|_| ProcessingError::ActorCommunicationError)
     }
+    
+    pub async fn inspect_transaction(
+        &self,
+        tx_id: u32,
+    ) -> Result<Option<(StoredTransaction, StorageTier)>, ProcessingError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(AccountMessage::InspectTransaction { tx_id, reply: reply_tx })
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)?;
+        
+        reply_rx
+            .await
+            .map_err(|_| ProcessingError::ActorCommunicationError)
+    }
 }
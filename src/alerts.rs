@@ -0,0 +1,170 @@
+use crate::escalation::WebhookSink;
+use crate::events::{DomainEvent, EventBus};
+use crate::models::Account;
+use crate::reporting::tmp_path;
+use crate::scalable_engine::ScalableEngine;
+use crate::storage::RetryPolicy;
+use anyhow::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+/// Condition on a client's balances worth telling someone about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum AlertRule {
+    /// Available funds under `threshold`
+    AvailableBelow { threshold: Decimal },
+    /// Held funds over `threshold`
+    HeldAbove { threshold: Decimal },
+    /// Available funds under zero, e.g. after a dispute of spent funds
+    NegativeAvailable,
+}
+
+impl AlertRule {
+    pub fn breached_by(&self, account: &Account) -> bool {
+        match self {
+            AlertRule::AvailableBelow { threshold } => account.available < *threshold,
+            AlertRule::HeldAbove { threshold } => account.held > *threshold,
+            AlertRule::NegativeAvailable => account.available < Decimal::ZERO,
+        }
+    }
+}
+
+/// A change that put an account in breach of one of its rules, sent as the webhook body
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct BalanceAlert {
+    pub client: u16,
+    pub rule: AlertRule,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+}
+
+/// Alert rules per client, checked by the actors on every change they apply
+///
+/// A rule fires when a change takes the account from within it to breaching
+/// it, not again until the account has recovered, so a client sitting below a
+/// threshold isn't alerted on every transaction. Alerts go out as
+/// `DomainEvent::BalanceAlert`, published while the change is applied.
+pub struct AlertRules {
+    rules: RwLock<BTreeMap<u16, Vec<AlertRule>>>,
+    events: EventBus,
+    path: Mutex<Option<PathBuf>>,
+}
+
+impl AlertRules {
+    /// Rules whose alerts are published on `events`
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            rules: RwLock::new(BTreeMap::new()),
+            events,
+            path: Mutex::new(None),
+        }
+    }
+
+    pub fn rules(&self, client: u16) -> Vec<AlertRule> {
+        self.rules.read().unwrap().get(&client).cloned().unwrap_or_default()
+    }
+
+    /// Replace the client's rules, none removes them all
+    pub async fn set_rules(&self, client: u16, rules: Vec<AlertRule>) -> Result<()> {
+        {
+            let mut all = self.rules.write().unwrap();
+            if rules.is_empty() {
+                all.remove(&client);
+            } else {
+                all.insert(client, rules);
+            }
+        }
+
+        // Rule changes are rare and must survive restarts, persist right away
+        self.flush().await
+    }
+
+    /// Publish an alert for every rule `current` breaches and `previous` didn't
+    pub fn evaluate(&self, previous: &Account, current: &Account) {
+        let rules = self.rules.read().unwrap();
+        let Some(rules) = rules.get(&current.client) else {
+            return;
+        };
+        for rule in rules {
+            if rule.breached_by(current) && !rule.breached_by(previous) {
+                self.events.publish(DomainEvent::BalanceAlert(BalanceAlert {
+                    client: current.client,
+                    rule: rule.clone(),
+                    available: current.available,
+                    held: current.held,
+                    locked: current.locked,
+                }));
+            }
+        }
+    }
+
+    /// Load rules kept at `path` (if any) and persist later changes there
+    pub async fn attach_file(&self, path: PathBuf) -> Result<()> {
+        if path.exists() {
+            let content = tokio::fs::read_to_string(&path).await?;
+            *self.rules.write().unwrap() = serde_json::from_str(&content)?;
+        }
+
+        *self.path.lock().unwrap() = Some(path);
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let Some(path) = self.path.lock().unwrap().clone() else {
+            return Ok(());
+        };
+
+        let content = serde_json::to_vec_pretty(&*self.rules.read().unwrap())?;
+        let tmp = tmp_path(&path);
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+}
+
+/// Where balance alerts are delivered
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn send(&self, alert: &BalanceAlert) -> Result<()>;
+}
+
+#[async_trait]
+impl AlertSink for WebhookSink {
+    async fn send(&self, alert: &BalanceAlert) -> Result<()> {
+        self.post(alert).await
+    }
+}
+
+/// Deliver the engine's balance alerts to `sink` until its events stop
+///
+/// Subscribes before returning, so alerts raised afterwards are delivered.
+/// Deliveries are retried; an alert whose delivery still fails is logged and dropped.
+pub fn spawn_notifier(engine: &ScalableEngine, sink: Arc<dyn AlertSink>) -> JoinHandle<()> {
+    let mut events = engine.subscribe();
+    let retry = RetryPolicy::default();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(DomainEvent::BalanceAlert(alert)) => {
+                    if let Err(e) = retry.run(|| sink.send(&alert)).await {
+                        tracing::error!(client = alert.client, error = ?e, "Failed to deliver balance alert");
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "Alert notifier fell behind, balance alerts may be lost");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
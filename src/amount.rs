@@ -0,0 +1,230 @@
+use crate::models::{AccountOutput, TransactionRow};
+use rust_decimal::Decimal;
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Amount spellings accepted beyond plain decimals like `1000.25`
+///
+/// Both are off by default: `1e3` and `1,000.00` are rejected with an error
+/// naming the rule rather than being read as something else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AmountFormat {
+    /// Accept `1e3`, `2.5E-2`
+    pub allow_scientific: bool,
+    /// Accept `1,000.00`; groups must be exactly three digits and `.` is the only decimal mark
+    pub allow_thousands_separators: bool,
+}
+
+impl AmountFormat {
+    /// `raw` spelled as the plain decimal rows are decoded from, so a feed's format applies before its rows are read
+    pub fn canonical(self, raw: &str) -> Result<String, AmountError> {
+        parse_amount(raw, self).map(|amount| amount.to_string())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("amount '{0}' uses scientific notation, which is not allowed")]
+    ScientificNotation(String),
+    #[error("amount '{0}' uses thousands separators, which are not allowed")]
+    ThousandsSeparator(String),
+    #[error("amount '{0}' has misplaced thousands separators")]
+    MisplacedSeparator(String),
+    #[error("amount '{0}' is not a decimal number")]
+    Malformed(String),
+    #[error("amount '{0}' is not a whole number of minor units")]
+    FractionalMinorUnits(String),
+    #[error("amount '{0}' is out of range for minor units")]
+    MinorUnitsOutOfRange(String),
+}
+
+/// Largest minor-unit exponent, the engine keeps four decimal places
+pub const MAX_MINOR_EXPONENT: u32 = 4;
+
+/// How a feed writes amounts, chosen per feed rather than for the process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AmountUnits {
+    /// `10.50`, as the spec has it
+    #[default]
+    Decimal,
+    /// Integer count of the currency's minor units, `1050` with exponent 2
+    Minor(u32),
+}
+
+impl FromStr for AmountUnits {
+    type Err = anyhow::Error;
+
+    /// `decimal` or `minor:<exponent>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().split_once(':') {
+            None if s.trim().eq_ignore_ascii_case("decimal") => Ok(AmountUnits::Decimal),
+            Some(("minor", exponent)) => {
+                let exponent: u32 = exponent.trim().parse()?;
+                if exponent > MAX_MINOR_EXPONENT {
+                    anyhow::bail!("Minor-unit exponent {} is above the maximum of {}", exponent, MAX_MINOR_EXPONENT);
+                }
+                Ok(AmountUnits::Minor(exponent))
+            }
+            _ => anyhow::bail!("Unknown amount units '{}', expected decimal or minor:<exponent>", s),
+        }
+    }
+}
+
+impl AmountUnits {
+    /// The decimal amount a feed's amount stands for
+    ///
+    /// Minor units must be a whole number within `i64`, so nothing is rounded.
+    pub fn to_decimal(self, amount: Decimal) -> Result<Decimal, AmountError> {
+        let AmountUnits::Minor(exponent) = self else {
+            return Ok(amount);
+        };
+        if !amount.fract().is_zero() {
+            return Err(AmountError::FractionalMinorUnits(amount.to_string()));
+        }
+        let units = i64::try_from(amount).map_err(|_| AmountError::MinorUnitsOutOfRange(amount.to_string()))?;
+        Ok(Decimal::new(units, exponent))
+    }
+
+    /// `amount` as the feed writes it, exact: a fraction of a minor unit stays visible
+    pub fn from_decimal(self, amount: Decimal) -> Decimal {
+        match self {
+            AmountUnits::Decimal => amount,
+            AmountUnits::Minor(exponent) => (amount * Decimal::from(10i64.pow(exponent))).normalize(),
+        }
+    }
+
+    /// `row` with its amount converted to decimal
+    pub fn decode_row(self, mut row: TransactionRow) -> Result<TransactionRow, AmountError> {
+        row.amount = row.amount.map(|amount| self.to_decimal(amount)).transpose()?;
+        Ok(row)
+    }
+
+    /// `account` with its amounts in this feed's units
+    pub fn encode_account(self, account: AccountOutput) -> AccountOutput {
+        AccountOutput {
+            available: self.from_decimal(account.available),
+            held: self.from_decimal(account.held),
+            total: self.from_decimal(account.total),
+            ..account
+        }
+    }
+}
+
+/// Parse a producer amount under `format`
+pub fn parse_amount(raw: &str, format: AmountFormat) -> Result<Decimal, AmountError> {
+    let trimmed = raw.trim();
+    let mut text = trimmed.to_string();
+
+    if text.contains(',') {
+        if !format.allow_thousands_separators {
+            return Err(AmountError::ThousandsSeparator(trimmed.to_string()));
+        }
+        text = strip_separators(&text).ok_or_else(|| AmountError::MisplacedSeparator(trimmed.to_string()))?;
+    }
+
+    if text.contains(['e', 'E']) {
+        if !format.allow_scientific {
+            return Err(AmountError::ScientificNotation(trimmed.to_string()));
+        }
+        return Decimal::from_scientific(&text).map_err(|_| AmountError::Malformed(trimmed.to_string()));
+    }
+
+    Decimal::from_str(&text).map_err(|_| AmountError::Malformed(trimmed.to_string()))
+}
+
+/// Remove `,` from the integer part if it groups digits by three, e.g. `-1,234,567.5`
+fn strip_separators(text: &str) -> Option<String> {
+    let (sign, unsigned) = match text.strip_prefix(['-', '+']) {
+        Some(rest) => (&text[..1], rest),
+        None => ("", text),
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+
+    // A comma after the decimal point would be a locale mixup, not a separator
+    if fraction.is_some_and(|fraction| fraction.contains(',')) {
+        return None;
+    }
+
+    let mut groups = integer.split(',');
+    let first = groups.next()?;
+    let digits = |group: &str| group.bytes().all(|b| b.is_ascii_digit());
+    if first.is_empty() || first.len() > 3 || !digits(first) {
+        return None;
+    }
+
+    let mut stripped = format!("{}{}", sign, first);
+    for group in groups {
+        if group.len() != 3 || !digits(group) {
+            return None;
+        }
+        stripped.push_str(group);
+    }
+    if let Some(fraction) = fraction {
+        stripped.push('.');
+        stripped.push_str(fraction);
+    }
+    Some(stripped)
+}
+
+/// Deserialize an optional amount written as a plain decimal
+///
+/// Text is parsed with [`parse_amount`] under the default format, numbers
+/// from typed formats are taken as they are. Readers of a feed with another
+/// format rewrite its amounts with [`AmountFormat::canonical`] first.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal>, D::Error> {
+    deserializer.deserialize_option(OptionalAmountVisitor)
+}
+
+struct OptionalAmountVisitor;
+
+impl<'de> Visitor<'de> for OptionalAmountVisitor {
+    type Value = Option<Decimal>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an optional amount")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        // Ask for text so CSV hands over the field as written instead of inferring a float
+        deserializer.deserialize_str(AmountVisitor).map(Some)
+    }
+}
+
+struct AmountVisitor;
+
+impl<'de> Visitor<'de> for AmountVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal amount")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        parse_amount(value, AmountFormat::default()).map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(Decimal::from(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(Decimal::from(value))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        Decimal::try_from(value).map_err(E::custom)
+    }
+}
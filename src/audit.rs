@@ -0,0 +1,98 @@
+use crate::errors::ProcessingError;
+use crate::models::TransactionRow;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Default number of outcomes retained by the in-memory audit log
+pub const DEFAULT_AUDIT_CAPACITY: usize = 100_000;
+
+/// Outcome of a single submission to the engine
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub recorded_at: SystemTime,
+    pub tx: TransactionRow,
+    /// None if applied, otherwise the rejection reason
+    pub rejection: Option<String>,
+    /// Tx id of the corrected row that replaced this rejected one
+    pub superseded_by: Option<u32>,
+}
+
+/// Bounded in-memory record of every processed submission, applied or rejected
+pub struct AuditLog {
+    capacity: usize,
+    records: Mutex<VecDeque<AuditRecord>>,
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, tx: &TransactionRow, outcome: &Result<(), ProcessingError>) {
+        let record = AuditRecord {
+            recorded_at: SystemTime::now(),
+            tx: tx.clone(),
+            rejection: outcome.as_ref().err().map(|e| e.to_string()),
+            superseded_by: None,
+        };
+        self.push(record);
+    }
+
+    /// Mark the rejections of `original` as replaced by the applied row `replacement_tx`
+    ///
+    /// A row rejected before this log's records began, e.g. by an earlier run,
+    /// is recorded as a superseded rejection.
+    pub fn supersede(&self, original: &TransactionRow, replacement_tx: u32) {
+        let mut records = self.records.lock().unwrap();
+        let mut found = false;
+        for record in records.iter_mut() {
+            if record.rejection.is_some()
+                && record.tx.tx == original.tx
+                && record.tx.client == original.client
+                && record.tx.tx_type == original.tx_type
+            {
+                record.superseded_by = Some(replacement_tx);
+                found = true;
+            }
+        }
+        drop(records);
+
+        if !found {
+            self.push(AuditRecord {
+                recorded_at: SystemTime::now(),
+                tx: original.clone(),
+                rejection: Some("rejected in an earlier run".to_string()),
+                superseded_by: Some(replacement_tx),
+            });
+        }
+    }
+
+    fn push(&self, record: AuditRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            // Drop oldest to stay bounded
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Records for one client, oldest first
+    pub fn for_client(&self, client: u16) -> Vec<AuditRecord> {
+        let records = self.records.lock().unwrap();
+        records
+            .iter()
+            .filter(|record| record.tx.client == client || record.tx.to == Some(client))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_AUDIT_CAPACITY)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::errors::ProcessingError;
+use crate::models::TransactionRow;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// HTTP header and gRPC metadata key an API key is presented in
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Clients an API key may submit transactions for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientScope {
+    /// Every client, for keys listed as `"*"` and for servers without keys
+    All,
+    /// Only these, none for a missing or unknown key
+    Only(BTreeSet<u16>),
+}
+
+impl ClientScope {
+    /// Scope of a caller that presented no valid key
+    pub fn none() -> Self {
+        ClientScope::Only(BTreeSet::new())
+    }
+
+    pub fn permits(&self, client: u16) -> bool {
+        match self {
+            ClientScope::All => true,
+            ClientScope::Only(clients) => clients.contains(&client),
+        }
+    }
+
+    /// Refuse `row` unless it is submitted for a client in scope
+    ///
+    /// A transfer is submitted for its sender, the recipient needn't be in scope.
+    pub fn check(&self, row: &TransactionRow) -> Result<(), ProcessingError> {
+        if self.permits(row.client) {
+            Ok(())
+        } else {
+            Err(ProcessingError::Unauthorized)
+        }
+    }
+}
+
+/// How a key's clients are written in the keys file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScopeEntry {
+    Clients(Vec<u16>),
+    Wildcard(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysFile {
+    keys: HashMap<String, ScopeEntry>,
+}
+
+/// API keys accepted in server mode, each with the clients it may submit for
+///
+/// Read from a TOML file naming each key's clients, or `"*"` for all of them:
+///
+/// ```toml
+/// [keys]
+/// acme-7f3c = [1, 2, 3]
+/// ops-91d0 = "*"
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, ClientScope>,
+}
+
+impl ApiKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `key` for the clients of `scope`
+    pub fn with_key(mut self, key: impl Into<String>, scope: ClientScope) -> Self {
+        self.keys.insert(key.into(), scope);
+        self
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading API keys {}", path.display()))?;
+        Self::from_toml(&text).with_context(|| format!("parsing API keys {}", path.display()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let file: KeysFile = toml::from_str(text)?;
+        let mut keys = HashMap::with_capacity(file.keys.len());
+        for (key, entry) in file.keys {
+            let scope = match entry {
+                ScopeEntry::Clients(clients) => ClientScope::Only(clients.into_iter().collect()),
+                ScopeEntry::Wildcard(all) if all == "*" => ClientScope::All,
+                ScopeEntry::Wildcard(other) => anyhow::bail!("key '{}': expected a list of clients or \"*\", got '{}'", key, other),
+            };
+            keys.insert(key, scope);
+        }
+        Ok(Self { keys })
+    }
+
+    /// What a caller presenting `key` may submit for, nothing without a known key
+    pub fn scope(&self, key: Option<&str>) -> ClientScope {
+        key.and_then(|key| self.keys.get(key))
+            .cloned()
+            .unwrap_or_else(ClientScope::none)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
@@ -0,0 +1,85 @@
+use crate::errors::ProcessingError;
+use crate::models::{TransactionRow, TransactionType};
+use anyhow::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// External approval step (e.g. fraud scoring) for large withdrawals
+#[async_trait]
+pub trait Authorizer: Send + Sync {
+    /// Ok(true) approves, Ok(false) denies, Err means no decision could be made
+    async fn authorize(&self, tx: &TransactionRow) -> Result<bool>;
+}
+
+/// What to do when the authorizer errors or times out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Apply the withdrawal anyway
+    FailOpen,
+    /// Reject the withdrawal
+    FailClosed,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorizerConfig {
+    /// Withdrawals strictly above this amount need approval
+    pub threshold: Decimal,
+    pub timeout: Duration,
+    pub failure_policy: FailurePolicy,
+}
+
+impl Default for AuthorizerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Decimal::ZERO,
+            timeout: Duration::from_millis(500),
+            failure_policy: FailurePolicy::FailClosed,
+        }
+    }
+}
+
+/// Authorizer plus the policy it runs under
+#[derive(Clone)]
+pub(crate) struct AuthorizationGate {
+    authorizer: Arc<dyn Authorizer>,
+    config: AuthorizerConfig,
+}
+
+impl AuthorizationGate {
+    pub(crate) fn new(authorizer: Arc<dyn Authorizer>, config: AuthorizerConfig) -> Self {
+        Self { authorizer, config }
+    }
+
+    /// Ask the authorizer about a row, if the row needs asking
+    pub(crate) async fn check(&self, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        // An authorize commits the funds a capture later withdraws, it is vetted up front
+        let needs_approval = matches!(tx.tx_type, TransactionType::Withdrawal | TransactionType::Authorize)
+            && tx.amount.is_some_and(|amount| amount > self.config.threshold);
+        if !needs_approval {
+            return Ok(());
+        }
+
+        let decision = tokio::time::timeout(self.config.timeout, self.authorizer.authorize(tx)).await;
+        match decision {
+            Ok(Ok(true)) => Ok(()),
+            Ok(Ok(false)) => Err(ProcessingError::AuthorizationDenied),
+            Ok(Err(e)) => {
+                tracing::warn!("Authorizer failed for tx {}: {}", tx.tx, e);
+                self.on_failure()
+            }
+            Err(_) => {
+                tracing::warn!("Authorizer timed out for tx {}", tx.tx);
+                self.on_failure()
+            }
+        }
+    }
+
+    fn on_failure(&self) -> Result<(), ProcessingError> {
+        match self.config.failure_policy {
+            FailurePolicy::FailOpen => Ok(()),
+            FailurePolicy::FailClosed => Err(ProcessingError::AuthorizerUnavailable),
+        }
+    }
+}
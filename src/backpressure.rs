@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long a connection waits for a saturated engine before refusing rows, by default
+pub const DEFAULT_BUSY_AFTER: Duration = Duration::from_millis(500);
+
+/// When connections stop reading because the actors can't keep up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackpressureConfig {
+    /// Messages in flight to one shard's actors at which the engine counts as saturated
+    pub max_queue_depth: usize,
+    /// Wait for a saturated engine before refusing a chunk with `engine_busy`,
+    /// also the retry hint sent with it
+    pub busy_after: Duration,
+}
+
+/// Messages in flight to each shard's actors, from send until the reply arrives
+pub(crate) struct QueueDepths {
+    shards: RwLock<Vec<Arc<AtomicUsize>>>,
+    drained: Notify,
+}
+
+impl QueueDepths {
+    pub(crate) fn new(num_shards: usize) -> Self {
+        Self {
+            shards: RwLock::new((0..num_shards).map(|_| Arc::default()).collect()),
+            drained: Notify::new(),
+        }
+    }
+
+    /// Count a message to `shard` until the guard is dropped
+    ///
+    /// A shard routed under the table a reshard is replacing may be past the
+    /// end of the new one, its message is counted on a shard that exists.
+    pub(crate) fn enter(&self, shard: usize) -> Queued<'_> {
+        let depth = {
+            let shards = self.shards.read().unwrap();
+            shards[shard % shards.len()].clone()
+        };
+        depth.fetch_add(1, Ordering::Relaxed);
+        Queued { depths: self, depth }
+    }
+
+    /// Track `num_shards` shards, the depths of those dropped are forgotten
+    ///
+    /// Messages already in flight stay counted where they were sent until answered.
+    pub(crate) fn reshard(&self, num_shards: usize) {
+        self.shards.write().unwrap().resize_with(num_shards, Arc::default);
+        self.drained.notify_waiters();
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<usize> {
+        self.shards.read().unwrap().iter().map(|depth| depth.load(Ordering::Relaxed)).collect()
+    }
+
+    fn deepest(&self) -> usize {
+        self.snapshot().into_iter().max().unwrap_or(0)
+    }
+
+    /// Wait until every shard is below `max_depth`, false if that takes longer than `within`
+    pub(crate) async fn wait_below(&self, max_depth: usize, within: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + within;
+        loop {
+            // Registered before the check, a message finishing in between still wakes us
+            let drained = self.drained.notified();
+            tokio::pin!(drained);
+            drained.as_mut().enable();
+            if self.deepest() < max_depth {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, drained).await.is_err() {
+                return self.deepest() < max_depth;
+            }
+        }
+    }
+}
+
+/// One message in flight, counted in its shard's depth while alive
+pub(crate) struct Queued<'a> {
+    depths: &'a QueueDepths,
+    depth: Arc<AtomicUsize>,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        self.depths.drained.notify_waiters();
+    }
+}
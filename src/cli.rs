@@ -1,69 +1,549 @@
-use crate::csv_io::{stream_transactions, write_accounts};
-use crate::models::AccountOutput;
-use crate::scalable_engine::ScalableEngine;
+use crate::amount::{AmountFormat, AmountUnits};
+use crate::compat::CompatConfig;
+use crate::compression::{open_input, InputReader};
+use crate::config::EngineConfig;
+use crate::corrections::Corrections;
+use crate::engine_snapshot::EngineSnapshot;
+use crate::event_store::{DurabilityPolicy, EventStore};
+use crate::csv_io::{
+    render_table, stream_json_transactions, stream_numbered_json_transactions, stream_numbered_transactions,
+    stream_opening_balances, stream_transactions_with, write_account_stream, write_accounts_json,
+    write_accounts_table, NumberedRow,
+};
+use crate::export::{self, ExportConfig};
+use crate::merge::{validate_sorted, MergedRows};
+use crate::models::{AccountOutput, TransactionRow};
+use crate::rejects::{RejectedRow, RejectsReport};
+use crate::scalable_engine::{ScalableEngine, PREFETCH_WINDOW};
+use crate::shard_manager::ShardStats;
+use crate::schema::{sniff, CsvSchema};
 use crate::storage::{InMemoryStore, TransactionStore};
+use crate::topology::auto_shards;
 use anyhow::Result;
+use futures::stream::BoxStream;
 use futures::StreamExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::BufReader;
-
-pub async fn run(input_path: PathBuf) -> Result<()> {
-    // Clean up all old temp files from previous runs as they persist across runs
-    let temp_dir = PathBuf::from("/tmp");
-    if let Ok(mut entries) = tokio::fs::read_dir(&temp_dir).await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            if let Some(name) = entry.file_name().to_str() {
-                if name.starts_with("payments-engine-cli-") && name.ends_with(".log") {
-                    let _ = tokio::fs::remove_file(entry.path()).await;
-                }
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// What a batch run prints once every row is applied
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CliOutput {
+    /// One line per client
+    #[default]
+    Accounts,
+    /// Totals across all clients
+    Treasury,
+    /// One CSV file per client id range plus a manifest, see `export::write_partitions`
+    Partitioned(ExportConfig),
+    /// Actors, hot transactions and queued messages of each shard once the input is applied
+    ShardStats,
+}
+
+/// Encoding of what a batch run prints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// JSON Lines, one object per account
+    Json,
+    /// Aligned columns for a terminal
+    Table,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" | "jsonl" => Ok(OutputFormat::Json),
+            "table" => Ok(OutputFormat::Table),
+            other => anyhow::bail!("Unknown output format '{}', expected csv, json or table", other),
+        }
+    }
+}
+
+/// Encoding of a batch run's input file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// JSON Lines if the file opens with `{`, CSV otherwise
+    #[default]
+    Auto,
+    /// Header line then `type,client,tx,amount` lines
+    Csv,
+    /// One JSON object per line with the fields of a CSV row
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(InputFormat::Auto),
+            "csv" => Ok(InputFormat::Csv),
+            "json" | "jsonl" => Ok(InputFormat::Json),
+            other => anyhow::bail!("Unknown input format '{}', expected auto, csv or json", other),
+        }
+    }
+}
+
+impl InputFormat {
+    /// Rows of `reader`, `Auto` peeking at its first byte and CSV laid out as `schema` or sniffed
+    pub async fn stream_rows(
+        self,
+        mut reader: InputReader,
+        schema: Option<CsvSchema>,
+        amounts: AmountFormat,
+    ) -> Result<BoxStream<'static, Result<TransactionRow>>> {
+        Ok(match self.resolve(&mut reader).await? {
+            InputFormat::Json => stream_json_transactions(reader, amounts).boxed(),
+            _ => {
+                let schema = csv_schema(&mut reader, schema).await?;
+                stream_transactions_with(reader, schema, amounts).boxed()
+            }
+        })
+    }
+
+    /// Rows of `reader` with their line numbers and raw text, for reporting rejections
+    pub async fn stream_numbered_rows(
+        self,
+        mut reader: InputReader,
+        schema: Option<CsvSchema>,
+        amounts: AmountFormat,
+    ) -> Result<BoxStream<'static, NumberedRow>> {
+        Ok(match self.resolve(&mut reader).await? {
+            InputFormat::Json => stream_numbered_json_transactions(reader, amounts).boxed(),
+            _ => {
+                let schema = csv_schema(&mut reader, schema).await?;
+                stream_numbered_transactions(reader, schema, amounts).boxed()
+            }
+        })
+    }
+
+    async fn resolve(self, reader: &mut InputReader) -> Result<Self> {
+        Ok(match self {
+            InputFormat::Auto if reader.fill_buf().await?.first() == Some(&b'{') => InputFormat::Json,
+            InputFormat::Auto => InputFormat::Csv,
+            format => format,
+        })
+    }
+}
+
+/// `schema` if given, otherwise sniffed from the start of `reader` with what looked wrong on stderr
+async fn csv_schema(reader: &mut InputReader, schema: Option<CsvSchema>) -> Result<CsvSchema> {
+    if let Some(schema) = schema {
+        return Ok(schema);
+    }
+    
+    let sniffed = sniff(reader.fill_buf().await?);
+    for diagnostic in &sniffed.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    Ok(sniffed.schema)
+}
+
+/// How a batch run reads its input file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputOptions {
+    pub format: InputFormat,
+    /// Units of amounts in and out
+    pub units: AmountUnits,
+    /// CSV layout, sniffed from the file when `None`
+    pub schema: Option<CsvSchema>,
+    /// Amount spellings accepted in the input
+    pub amounts: AmountFormat,
+}
+
+pub async fn run(
+    input_path: PathBuf,
+    input: InputOptions,
+    compat: CompatConfig,
+    output: CliOutput,
+    output_format: OutputFormat,
+    config: EngineConfig,
+    rejects: Option<PathBuf>,
+) -> Result<()> {
+    let engine = batch_engine(compat, &config).await?;
+    
+    // Open and process input file, gzip and zstd are decompressed as it is read
+    let reader = open_input(&input_path).await?;
+    let source = input_path.display().to_string();
+    
+    match rejects {
+        Some(rejects_path) => {
+            let report = RejectsReport::create(&rejects_path).await?;
+            let rejected = process_reporting_rejects(&engine, reader, input, &source, report).await?;
+            eprintln!("{} rejected rows written to {}", rejected, rejects_path.display());
+        }
+        None => process_rows(&engine, reader, input, &source).await?,
+    }
+    
+    write_final_accounts(&engine, output, output_format, input.units).await?;
+    print_duplicates(&engine);
+    Ok(())
+}
+
+/// Apply the rows of `reader`, skipping those that fail to parse or are rejected
+async fn process_rows(
+    engine: &ScalableEngine,
+    reader: InputReader,
+    input: InputOptions,
+    source: &str,
+) -> Result<()> {
+    let units = input.units;
+    let mut stream = input
+        .format
+        .stream_rows(reader, input.schema, input.amounts)
+        .await?
+        .map(|row| -> Result<TransactionRow> { Ok(units.decode_row(row?)?) })
+        .ready_chunks(PREFETCH_WINDOW);
+    
+    while let Some(chunk) = stream.next().await {
+        // Ignore parse errors
+        let rows: Vec<TransactionRow> = chunk.into_iter().filter_map(Result::ok).collect();
+        engine.prefetch(&rows).await;
+        
+        // Clients run in parallel on their actors, rejected rows are skipped
+        let outcomes = engine.process_batch(rows.clone()).await;
+        for (row, outcome) in rows.iter().zip(&outcomes) {
+            engine.duplicates().record_outcome(source, row.tx, outcome);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Apply the rows of `reader`, writing each one not applied to `report`
+async fn process_reporting_rejects(
+    engine: &ScalableEngine,
+    reader: InputReader,
+    input: InputOptions,
+    source: &str,
+    mut report: RejectsReport,
+) -> Result<u64> {
+    let units = input.units;
+    let mut stream = input
+        .format
+        .stream_numbered_rows(reader, input.schema, input.amounts)
+        .await?
+        .ready_chunks(PREFETCH_WINDOW);
+    
+    while let Some(chunk) = stream.next().await {
+        let mut parsed = Vec::with_capacity(chunk.len());
+        let mut rejected = Vec::new();
+        for NumberedRow { line, raw, row } in chunk {
+            match row.and_then(|row| Ok(units.decode_row(row)?)) {
+                Ok(row) => parsed.push((line, raw, row)),
+                Err(e) => rejected.push(RejectedRow::malformed(line, raw, &e)),
             }
         }
+        
+        let rows: Vec<TransactionRow> = parsed.iter().map(|(_, _, row)| row.clone()).collect();
+        engine.prefetch(&rows).await;
+        let outcomes = engine.process_batch(rows).await;
+        
+        for ((line, raw, row), outcome) in parsed.into_iter().zip(outcomes) {
+            engine.duplicates().record_outcome(source, row.tx, &outcome);
+            if let Err(e) = outcome {
+                rejected.push(RejectedRow::rejected(line, raw, row, &e));
+            }
+        }
+        
+        // The report follows the input's line order
+        rejected.sort_by_key(|row| row.line);
+        for row in &rejected {
+            report.write(row).await?;
+        }
     }
     
-    // Create unique temporary event store to avoid race conditions
-    let temp_log = PathBuf::from(format!(
-        "/tmp/payments-engine-cli-{}.log",
-        std::process::id()
-    ));
+    report.finish().await
+}
+
+/// Process several timestamp-sorted files as one stream, ordered by timestamp
+///
+/// Inputs are checked up front, nothing is applied if any of them is out of order.
+pub async fn run_merged(
+    input_paths: Vec<PathBuf>,
+    compat: CompatConfig,
+    amounts: AmountFormat,
+    output: CliOutput,
+    output_format: OutputFormat,
+    config: EngineConfig,
+) -> Result<()> {
+    validate_sorted(&input_paths, amounts).await?;
+    
+    let engine = batch_engine(compat, &config).await?;
+    let mut merged = MergedRows::open(&input_paths, amounts).await?;
+    let mut window = Vec::with_capacity(PREFETCH_WINDOW);
     
+    loop {
+        while window.len() < PREFETCH_WINDOW {
+            match merged.next_with_input().await {
+                Some(row) => window.push(row?),
+                None => break,
+            }
+        }
+        if window.is_empty() {
+            break;
+        }
+        
+        let times: Vec<_> = window.iter().map(|(row, _)| row.time()).collect();
+        let inputs: Vec<usize> = window.iter().map(|(_, input)| *input).collect();
+        let rows: Vec<TransactionRow> = window.drain(..).map(|(row, _)| row.into_row()).collect();
+        engine.prefetch(&rows).await;
+        
+        for ((row, at), input) in rows.into_iter().zip(times).zip(inputs) {
+            // Rows are applied as of when they happened, not when they were read
+            let tx = row.tx;
+            let outcome = engine.process_at(row, at).await;
+            engine.duplicates().record_outcome(merged.input_name(input), tx, &outcome);
+        }
+    }
+    
+    write_final_accounts(&engine, output, output_format, AmountUnits::Decimal).await?;
+    print_duplicates(&engine);
+    Ok(())
+}
+
+/// Summary of duplicate rejections on stderr, nothing when there were none
+fn print_duplicates(engine: &ScalableEngine) {
+    let report = engine.duplicates().report();
+    if report.total > 0 {
+        eprint!("{}", report.summary());
+    }
+}
+
+/// Engine for a batch run
+/// A one-shot run keeps no event log unless `config` names one
+async fn batch_engine(compat: CompatConfig, config: &EngineConfig) -> Result<ScalableEngine> {
     // Use in-memory cold storage for CLI (no persistence needed)
     let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
     
-    // Initialize scalable engine with 16 shards for parallel processing
-    let engine = ScalableEngine::new(temp_log.clone(), 16, cold_storage).await?;
+    let engine = ScalableEngine::from_config(config, cold_storage).await?.with_compat(compat)?;
+    
+    // A kept log may hold earlier runs, they are applied first
+    engine.rebuild_from_events().await?;
+    
+    Ok(engine)
+}
+
+/// Accounts (in `units`) or treasury totals on stdout
+fn shard_stats_csv(stats: &[ShardStats]) -> String {
+    let mut csv = String::from("shard,actors,hot_transactions,mailbox_depth,in_flight\n");
+    for shard in stats {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            shard.shard, shard.actors, shard.hot_transactions, shard.mailbox_depth, shard.in_flight
+        ));
+    }
+    csv
+}
+
+async fn write_final_accounts(
+    engine: &ScalableEngine,
+    output: CliOutput,
+    format: OutputFormat,
+    units: AmountUnits,
+) -> Result<()> {
+    let mut stdout = tokio::io::stdout();
+    match output {
+        CliOutput::Accounts => {
+            let mut accounts: Vec<AccountOutput> = engine
+                .get_accounts()
+                .await
+                .iter()
+                .map(AccountOutput::from)
+                .collect();
+            
+            // Sort accounts by client ID for simplicity
+            accounts.sort_by_key(|a| a.client);
+            
+            match format {
+                OutputFormat::Csv => write_account_stream(&mut stdout, futures::stream::iter(accounts), units).await?,
+                OutputFormat::Json => write_accounts_json(&mut stdout, accounts, units).await?,
+                OutputFormat::Table => write_accounts_table(&mut stdout, accounts, units).await?,
+            }
+        }
+        CliOutput::Partitioned(config) => {
+            let manifest = export::export_accounts(engine, &config, units).await?;
+            eprintln!(
+                "{} accounts exported to {} partitions in {}",
+                manifest.rows,
+                manifest.partitions.len(),
+                config.dir.display()
+            );
+        }
+        CliOutput::Treasury => {
+            let report = engine.account_totals();
+            let out = match format {
+                OutputFormat::Csv => report.to_csv(),
+                OutputFormat::Json => format!("{}\n", serde_json::to_string(&report)?),
+                OutputFormat::Table => render_table(&report.to_csv()),
+            };
+            stdout.write_all(out.as_bytes()).await?;
+            stdout.flush().await?;
+        }
+        CliOutput::ShardStats => {
+            let stats = engine.stats().await;
+            let csv = shard_stats_csv(&stats);
+            let out = match format {
+                OutputFormat::Csv => csv,
+                OutputFormat::Json => stats
+                    .iter()
+                    .map(|shard| Ok(format!("{}\n", serde_json::to_string(shard)?)))
+                    .collect::<Result<String>>()?,
+                OutputFormat::Table => render_table(&csv),
+            };
+            stdout.write_all(out.as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+    
+    // Keep stdout clean for the accounts, the breakdown goes to stderr
+    #[cfg(feature = "contention-profiling")]
+    crate::contention::write_report(std::io::stderr())?;
+    
+    Ok(())
+}
+
+/// Seed accounts in an event log with opening balances from a `client,amount` CSV
+pub async fn import_balances(input_path: PathBuf, event_log: PathBuf) -> Result<()> {
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(event_log, auto_shards(), cold_storage).await?;
+    
+    // Existing accounts must be known so they are not re-seeded
+    engine.rebuild_from_events().await?;
     
-    // Open and process input file
     let file = File::open(&input_path).await?;
-    let reader = BufReader::new(file);
-    let mut stream = stream_transactions(reader);
+    let mut stream = stream_opening_balances(BufReader::new(file));
+    
+    let mut imported = 0usize;
+    let mut rejected = 0usize;
     
     while let Some(result) = stream.next().await {
         match result {
-            Ok(row) => {
-                // Process with scalable engine (parallel via actors)
-                let _ = engine.process(row).await;
-            }
-            Err(_) => {
-                // Ignore parse errors
+            Ok(row) => match engine.import_opening_balance(row.client, row.amount).await {
+                Ok(()) => imported += 1,
+                Err(e) => {
+                    eprintln!("client {}: {}", row.client, e);
+                    rejected += 1;
+                }
+            },
+            Err(e) => {
+                eprintln!("invalid row: {}", e);
+                rejected += 1;
             }
         }
     }
     
-    let mut accounts: Vec<AccountOutput> = engine
-        .get_accounts()
-        .await
-        .iter()
-        .map(AccountOutput::from)
-        .collect();
+    println!("imported {} opening balances, rejected {}", imported, rejected);
+    
+    Ok(())
+}
+
+/// `snapshot verify`: check a snapshot's checksum and invariants, then describe it
+pub async fn verify_snapshot(path: &Path) -> Result<()> {
+    let snapshot = EngineSnapshot::load_verified(path).await?;
+    println!(
+        "snapshot {} ok: {} events up to byte {} of the log, {} accounts, {} tx ids, {} stored transactions",
+        path.display(),
+        snapshot.events,
+        snapshot.log_offset,
+        snapshot.accounts.len(),
+        snapshot.tx_ids.len(),
+        snapshot.transactions.len()
+    );
+    Ok(())
+}
+
+/// `snapshot restore`: bootstrap an engine from a verified snapshot and the log written after it
+///
+/// Unlike server startup, which falls back to a full replay, a snapshot that
+/// fails verification or doesn't fit the log is an error. The restored
+/// accounts are printed; the log is only read, not even a generation marker
+/// is appended, so the next writer finds it as it was.
+pub async fn restore_snapshot(path: &Path, event_log: PathBuf) -> Result<()> {
+    let snapshot = EngineSnapshot::load_verified(path).await?;
+    if !event_log.exists() {
+        anyhow::bail!("event log not found: {}", event_log.display());
+    }
+    let log = EventStore::new(event_log.clone(), DurabilityPolicy::Buffered).await?;
+    if let Some(reason) = snapshot.mismatch(&log).await? {
+        anyhow::bail!("Snapshot {} doesn't fit {}: {}", path.display(), event_log.display(), reason);
+    }
+    drop(log);
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(event_log, auto_shards(), cold_storage)
+        .await?
+        .with_snapshot_path(path.to_path_buf());
+    engine.rebuild_read_only().await?;
     
-    // Sort accounts by client ID for simplicity
-    accounts.sort_by_key(|a| a.client);
+    let Some(logged_after) = engine.replayed_events().checked_sub(snapshot.events) else {
+        anyhow::bail!("Snapshot {} was not restored, the event log changed while reading it", path.display());
+    };
+    eprintln!(
+        "restored {} events from the snapshot, replayed {} logged after it",
+        snapshot.events, logged_after
+    );
+    write_final_accounts(&engine, CliOutput::Accounts, OutputFormat::Csv, AmountUnits::Decimal).await
+}
 
-    write_accounts(tokio::io::stdout(), accounts).await?;
+/// Re-submit rows from a rejects file against an event log, with field corrections applied
+///
+/// The rejects file holds transaction rows; extra columns such as a reason are ignored.
+pub async fn replay_rejects(
+    rejects_path: PathBuf,
+    corrections_path: Option<PathBuf>,
+    event_log: PathBuf,
+    compat: CompatConfig,
+    amounts: AmountFormat,
+) -> Result<()> {
+    let corrections = match corrections_path {
+        Some(path) => Corrections::load(&path, amounts).await?,
+        None => Corrections::new(),
+    };
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(event_log, auto_shards(), cold_storage)
+        .await?
+        .with_compat(compat)?;
+    engine.rebuild_from_events().await?;
+    
+    let file = File::open(&rejects_path).await?;
+    let mut stream = stream_transactions_with(BufReader::new(file), CsvSchema::default(), amounts);
+    
+    let mut applied = 0usize;
+    let mut rejected = 0usize;
+    
+    while let Some(result) = stream.next().await {
+        let original = match result {
+            Ok(row) => row,
+            Err(e) => {
+                eprintln!("invalid row: {}", e);
+                rejected += 1;
+                continue;
+            }
+        };
+        
+        let outcome = match corrections.apply(&original) {
+            Ok(corrected) => engine.resubmit(&original, corrected).await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(()) => applied += 1,
+            Err(e) => {
+                eprintln!("tx {}: {}", original.tx, e);
+                rejected += 1;
+            }
+        }
+    }
     
-    let _ = tokio::fs::remove_file(&temp_log).await;
+    println!("re-submitted {} rejected rows, still rejected {}", applied, rejected);
     
     Ok(())
 }
@@ -0,0 +1,16 @@
+use std::time::SystemTime;
+
+/// Source of "now" for account actors, swappable so time-dependent behaviour can be tested
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// Wall clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
@@ -0,0 +1,121 @@
+use crate::models::TransactionType;
+use std::str::FromStr;
+
+/// Named preset for the ambiguous parts of the transaction spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompatMode {
+    /// Literal reading: only deposits are disputable, a lock freezes everything
+    #[default]
+    Strict,
+    /// Withdrawal disputes, locks only stop outflows, exact retries are accepted
+    Extended,
+}
+
+impl FromStr for CompatMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "strict" => Ok(CompatMode::Strict),
+            "extended" => Ok(CompatMode::Extended),
+            other => anyhow::bail!("Unknown compat mode '{}', expected strict or extended", other),
+        }
+    }
+}
+
+/// What a locked (charged back) account still accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockScope {
+    /// Every transaction is rejected
+    All,
+    /// Only withdrawals and outgoing transfers are rejected
+    Outflows,
+}
+
+/// What happens to a deposit a lock would refuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockPolicy {
+    /// Rejected as `AccountLocked`
+    #[default]
+    Reject,
+    /// Accepted into `held`, released to `available` when the account is unlocked
+    Hold,
+}
+
+impl FromStr for LockPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "reject" => Ok(LockPolicy::Reject),
+            "hold" => Ok(LockPolicy::Hold),
+            other => anyhow::bail!("Unknown lock policy '{}', expected reject or hold", other),
+        }
+    }
+}
+
+/// What happens to a row whose tx id was already applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Always rejected as a duplicate
+    Reject,
+    /// An exact repeat (same client, type and amount) succeeds without being applied again
+    IgnoreRetries,
+}
+
+/// Spec interpretation applied by the engine and every account actor
+///
+/// Must stay the same across restarts: the event log is replayed under it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompatConfig {
+    /// Disputing a withdrawal holds its amount; a chargeback returns it to the client
+    pub dispute_withdrawals: bool,
+    pub lock_scope: LockScope,
+    /// Deposits a lock refuses are either rejected or held until the unlock
+    pub lock_policy: LockPolicy,
+    pub duplicates: DuplicatePolicy,
+}
+
+impl CompatConfig {
+    pub fn strict() -> Self {
+        Self {
+            dispute_withdrawals: false,
+            lock_scope: LockScope::All,
+            lock_policy: LockPolicy::Reject,
+            duplicates: DuplicatePolicy::Reject,
+        }
+    }
+
+    pub fn extended() -> Self {
+        Self {
+            dispute_withdrawals: true,
+            lock_scope: LockScope::Outflows,
+            lock_policy: LockPolicy::Reject,
+            duplicates: DuplicatePolicy::IgnoreRetries,
+        }
+    }
+
+    /// Whether stored transactions of `tx_type` can be disputed
+    pub fn disputable(&self, tx_type: &TransactionType) -> bool {
+        match tx_type {
+            TransactionType::Deposit => true,
+            TransactionType::Withdrawal => self.dispute_withdrawals,
+            _ => false,
+        }
+    }
+}
+
+impl Default for CompatConfig {
+    fn default() -> Self {
+        Self::strict()
+    }
+}
+
+impl From<CompatMode> for CompatConfig {
+    fn from(mode: CompatMode) -> Self {
+        match mode {
+            CompatMode::Strict => Self::strict(),
+            CompatMode::Extended => Self::extended(),
+        }
+    }
+}
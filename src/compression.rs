@@ -0,0 +1,58 @@
+use anyhow::Result;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Input of any compression, read as the plain bytes
+pub type InputReader = BufReader<Box<dyn AsyncRead + Unpin + Send>>;
+
+/// Compression of an input file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Told apart by the magic bytes at the start of `reader`, nothing is consumed
+    ///
+    /// No CSV or JSON input starts with either magic, so the extension is not needed.
+    pub async fn detect<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Self> {
+        let head = reader.fill_buf().await?;
+        Ok(if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        })
+    }
+}
+
+/// Open `path`, decompressing it if it is gzip or zstd
+pub async fn open_input(path: &Path) -> Result<InputReader> {
+    let mut reader = BufReader::new(File::open(path).await?);
+    let compression = Compression::detect(&mut reader).await?;
+
+    // Dumps are often several members concatenated, e.g. by pigz or appended to
+    let reader: Box<dyn AsyncRead + Unpin + Send> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Compression::Zstd => {
+            let mut decoder = ZstdDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+    };
+
+    Ok(BufReader::new(reader))
+}
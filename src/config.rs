@@ -0,0 +1,151 @@
+use crate::account_actor::ActorConfig;
+use crate::migration::MigrationConfig;
+use crate::topology::auto_shards;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Prefix of the environment variables overriding config file values
+pub const ENV_PREFIX: &str = "PAYMENTS_ENGINE_";
+
+/// Tunables an engine is assembled from
+///
+/// Read from a TOML file, any value missing from it keeps its default:
+///
+/// ```toml
+/// shards = 32
+/// event_log = "/var/lib/payments/events.log"
+///
+/// [actor]
+/// hot_cutoff_days = 30
+/// idle_timeout_secs = 600
+/// mailbox_size = 1000
+/// max_resident = 20000
+///
+/// [migration]
+/// batch_size = 500
+/// concurrency = 4
+/// max_puts_per_sec = 200
+/// low_risk_hot_days = 7
+/// low_risk_max_dispute_rate = 0.001
+/// low_risk_min_transactions = 100
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Account shards, each guarding the actors of its clients; by default
+    /// two per CPU, see `topology::auto_shards`
+    pub shards: usize,
+    /// Event log replayed on startup and appended to, none is kept without one
+    pub event_log: Option<PathBuf>,
+    pub actor: ActorConfig,
+    pub migration: MigrationConfig,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            shards: auto_shards(),
+            event_log: None,
+            actor: ActorConfig::default(),
+            migration: MigrationConfig::default(),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Defaults, overridden by the file at `path` if given, then by `PAYMENTS_ENGINE_*` variables
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.with_env(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading engine config {}", path.display()))?;
+        Self::from_toml(&text).with_context(|| format!("parsing engine config {}", path.display()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()
+    }
+
+    /// Override values from variables named after them, e.g. `PAYMENTS_ENGINE_SHARDS`
+    /// or `PAYMENTS_ENGINE_MIGRATION_BATCH_SIZE`, as looked up by `lookup`
+    pub fn with_env(mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(&format!("{}{}", ENV_PREFIX, name)).map(|value| (name.to_string(), value));
+
+        if let Some((name, value)) = var("SHARDS") {
+            self.shards = parse_var(&name, &value)?;
+        }
+        if let Some((_, value)) = var("EVENT_LOG") {
+            self.event_log = (!value.is_empty()).then(|| PathBuf::from(value));
+        }
+        if let Some((name, value)) = var("HOT_CUTOFF_DAYS") {
+            self.actor.hot_cutoff_days = parse_var(&name, &value)?;
+        }
+        if let Some((name, value)) = var("IDLE_TIMEOUT_SECS") {
+            self.actor.idle_timeout_secs = parse_var(&name, &value)?;
+        }
+        if let Some((name, value)) = var("MAILBOX_SIZE") {
+            self.actor.mailbox_size = parse_var(&name, &value)?;
+        }
+        if let Some((name, value)) = var("MAX_RESIDENT_ACTORS") {
+            self.actor.max_resident = if value.is_empty() { None } else { Some(parse_var(&name, &value)?) };
+        }
+        if let Some((name, value)) = var("MIGRATION_BATCH_SIZE") {
+            self.migration.batch_size = parse_var(&name, &value)?;
+        }
+        if let Some((name, value)) = var("MIGRATION_CONCURRENCY") {
+            self.migration.concurrency = parse_var(&name, &value)?;
+        }
+        if let Some((name, value)) = var("MIGRATION_MAX_PUTS_PER_SEC") {
+            self.migration.max_puts_per_sec = if value.is_empty() { None } else { Some(parse_var(&name, &value)?) };
+        }
+        if let Some((name, value)) = var("MIGRATION_LOW_RISK_HOT_DAYS") {
+            self.migration.low_risk_hot_days = if value.is_empty() { None } else { Some(parse_var(&name, &value)?) };
+        }
+        if let Some((name, value)) = var("MIGRATION_LOW_RISK_MAX_DISPUTE_RATE") {
+            self.migration.low_risk_max_dispute_rate = parse_var(&name, &value)?;
+        }
+        if let Some((name, value)) = var("MIGRATION_LOW_RISK_MIN_TRANSACTIONS") {
+            self.migration.low_risk_min_transactions = parse_var(&name, &value)?;
+        }
+        self.validate()
+    }
+
+    /// Values an engine could not run with, e.g. zero shards
+    fn validate(self) -> Result<Self> {
+        let positive = [
+            ("shards", self.shards),
+            ("actor.mailbox_size", self.actor.mailbox_size),
+            ("migration.batch_size", self.migration.batch_size),
+            ("migration.concurrency", self.migration.concurrency),
+            ("actor.max_resident", self.actor.max_resident.unwrap_or(1)),
+        ];
+        for (name, value) in positive {
+            if value == 0 {
+                anyhow::bail!("{} must be at least 1", name);
+            }
+        }
+        if !(0.0..=1.0).contains(&self.migration.low_risk_max_dispute_rate) {
+            anyhow::bail!("migration.low_risk_max_dispute_rate must be between 0 and 1");
+        }
+        Ok(self)
+    }
+}
+
+fn parse_var<T: FromStr>(name: &str, value: &str) -> Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("{}{}={}: {}", ENV_PREFIX, name, value, e))
+}
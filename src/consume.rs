@@ -0,0 +1,149 @@
+use crate::amount::AmountFormat;
+use crate::compat::CompatConfig;
+use crate::config::EngineConfig;
+use crate::csv_io::parse_json_row;
+use crate::errors::ProcessingError;
+use crate::event_store::DurabilityPolicy;
+use crate::models::TransactionRow;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Everything `consume` is started with
+pub struct ConsumeConfig {
+    pub brokers: String,
+    pub topic: String,
+    /// Consumer group whose committed offsets the consumer resumes from
+    pub group: String,
+    pub event_log_path: PathBuf,
+    /// Shards, actor and migration limits; its event log is `event_log_path`
+    pub engine: EngineConfig,
+    pub compat: CompatConfig,
+    pub durability: DurabilityPolicy,
+    /// Directory keeping registered tx ids, so redelivered records are refused as duplicates
+    pub tx_registry_dir: Option<PathBuf>,
+    /// Amount spellings accepted in records
+    pub amounts: AmountFormat,
+}
+
+/// One record's value: a JSON object, as a line of JSON Lines input, its amount read under `format`
+pub fn decode_record(payload: &[u8], format: AmountFormat) -> Result<TransactionRow> {
+    let text = std::str::from_utf8(payload).context("record is not UTF-8")?;
+    Ok(parse_json_row(text.trim(), format)?)
+}
+
+/// Whether a record is done with after `outcome`, so its offset may be committed
+///
+/// Refused rows are done with; a transient failure, e.g. of the event log
+/// append, leaves the record to be delivered again.
+pub fn settled(outcome: &Result<(), ProcessingError>) -> bool {
+    !matches!(outcome, Err(e) if e.is_transient())
+}
+
+#[cfg(feature = "kafka")]
+pub use kafka::run;
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use super::{decode_record, settled, ConsumeConfig};
+    use crate::config::EngineConfig;
+    use crate::scalable_engine::ScalableEngine;
+    use crate::server::shutdown_signal;
+    use crate::storage::InMemoryStore;
+    use anyhow::Result;
+    use rdkafka::config::ClientConfig;
+    use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+    use rdkafka::message::{BorrowedMessage, Message};
+    use std::sync::Arc;
+
+    /// Apply records from `topic` until SIGTERM or Ctrl-C, at least once
+    ///
+    /// Records are applied one at a time in partition order. An offset is
+    /// stored for commit only once its row is in the event log, or was refused;
+    /// auto-commit sends the stored offsets every few seconds. A transient
+    /// failure stops the consumer without storing the failed offset, so after
+    /// a restart the log is replayed and the record delivered again.
+    pub async fn run(config: ConsumeConfig) -> Result<()> {
+        let ConsumeConfig {
+            brokers,
+            topic,
+            group,
+            event_log_path,
+            engine,
+            compat,
+            durability,
+            tx_registry_dir,
+            amounts,
+        } = config;
+
+        let config = EngineConfig {
+            event_log: Some(event_log_path),
+            ..engine
+        };
+        let mut engine = ScalableEngine::from_config(&config, Arc::new(InMemoryStore::new()))
+            .await?
+            .with_compat(compat)?
+            .with_durability(durability)?
+            .with_amount_format(amounts);
+        if let Some(dir) = &tx_registry_dir {
+            engine = engine.with_tx_registry_dir(dir).await?;
+        }
+        engine.rebuild_from_events().await?;
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("group.id", &group)
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+        consumer.subscribe(&[&topic])?;
+        tracing::info!("Consuming {} from {} as group {}", topic, brokers, group);
+
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        let result = loop {
+            let message = tokio::select! {
+                _ = &mut shutdown => break Ok(()),
+                message = consumer.recv() => message?,
+            };
+            if let Err(e) = apply(&engine, &message).await {
+                break Err(e);
+            }
+            consumer.store_offset_from_message(&message)?;
+        };
+
+        // Offsets stored so far are all covered by the log
+        if let Err(e) = consumer.commit_consumer_state(CommitMode::Sync) {
+            tracing::warn!("Failed to commit offsets on exit: {}", e);
+        }
+        engine.shutdown().await?;
+        result
+    }
+
+    /// Apply one record, an error if it must be delivered again
+    async fn apply(engine: &ScalableEngine, message: &BorrowedMessage<'_>) -> Result<()> {
+        let source = format!("{}/{}", message.topic(), message.partition());
+        let row = match message.payload().map(|payload| decode_record(payload, engine.amount_format())) {
+            Some(Ok(row)) => row,
+            // Malformed records would never decode, skipping them is the only way forward
+            Some(Err(e)) => {
+                tracing::warn!(source, offset = message.offset(), "Skipping malformed record: {}", e);
+                return Ok(());
+            }
+            None => {
+                tracing::warn!(source, offset = message.offset(), "Skipping record without a value");
+                return Ok(());
+            }
+        };
+
+        let tx = row.tx;
+        let outcome = engine.process(row).await;
+        engine.duplicates().record_outcome(&source, tx, &outcome);
+        if !settled(&outcome) {
+            if let Err(e) = outcome {
+                anyhow::bail!("record at {} offset {} failed: {}", source, message.offset(), e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use serde::Serialize;
+use std::future::Future;
+
+/// Shared resource a task may wait on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Site {
+    /// Event store append mutex
+    EventStore,
+    /// Shard actor-map RwLocks
+    ShardLock,
+    /// Cold storage calls, including its internal locks
+    ColdStorage,
+}
+
+impl Site {
+    pub const ALL: [Site; 3] = [Site::EventStore, Site::ShardLock, Site::ColdStorage];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Site::EventStore => "event_store",
+            Site::ShardLock => "shard_lock",
+            Site::ColdStorage => "cold_storage",
+        }
+    }
+}
+
+/// Accumulated waits at one site
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteReport {
+    pub site: &'static str,
+    pub waits: u64,
+    pub total_wait_us: u64,
+    pub max_wait_us: u64,
+}
+
+/// Run `fut`, charging the time it takes to `site`
+///
+/// Only records with the `contention-profiling` feature, otherwise a plain await.
+/// With the `shuttle` feature every site is also a scheduling point.
+#[cfg(feature = "contention-profiling")]
+pub async fn measure<F: Future>(site: Site, fut: F) -> F::Output {
+    schedule_point().await;
+    let start = std::time::Instant::now();
+    let output = fut.await;
+    profiler::record(site, start.elapsed());
+    output
+}
+
+/// Run `fut`, charging the time it takes to `site`
+#[cfg(not(feature = "contention-profiling"))]
+#[inline(always)]
+pub async fn measure<F: Future>(_site: Site, fut: F) -> F::Output {
+    schedule_point().await;
+    fut.await
+}
+
+/// Let a model checker switch tasks here
+///
+/// Outside a tokio runtime, `yield_now` wakes itself and returns pending once,
+/// which is a preemption point for shuttle's executor.
+#[cfg(feature = "shuttle")]
+async fn schedule_point() {
+    tokio::task::yield_now().await;
+}
+
+#[cfg(not(feature = "shuttle"))]
+#[inline(always)]
+async fn schedule_point() {}
+
+/// Start a long-lived engine task (an actor or the projection) in the background
+///
+/// With the `shuttle` feature it runs on shuttle's executor, so model-checked
+/// tests can drive a whole engine, otherwise on tokio.
+#[cfg(feature = "shuttle")]
+pub fn spawn<F>(fut: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    shuttle::future::spawn(fut);
+}
+
+#[cfg(not(feature = "shuttle"))]
+pub fn spawn<F>(fut: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(fut);
+}
+
+#[cfg(feature = "contention-profiling")]
+pub use profiler::{report, reset, write_report};
+
+#[cfg(feature = "contention-profiling")]
+mod profiler {
+    use super::{Site, SiteReport};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+
+    struct SiteStats {
+        waits: AtomicU64,
+        total_ns: AtomicU64,
+        max_ns: AtomicU64,
+    }
+
+    impl SiteStats {
+        const fn new() -> Self {
+            Self {
+                waits: AtomicU64::new(0),
+                total_ns: AtomicU64::new(0),
+                max_ns: AtomicU64::new(0),
+            }
+        }
+    }
+
+    static STATS: [SiteStats; 3] = [SiteStats::new(), SiteStats::new(), SiteStats::new()];
+
+    fn stats(site: Site) -> &'static SiteStats {
+        &STATS[site as usize]
+    }
+
+    pub(super) fn record(site: Site, waited: Duration) {
+        let ns = waited.as_nanos() as u64;
+        let stats = stats(site);
+        stats.waits.fetch_add(1, Ordering::Relaxed);
+        stats.total_ns.fetch_add(ns, Ordering::Relaxed);
+        stats.max_ns.fetch_max(ns, Ordering::Relaxed);
+    }
+
+    /// Breakdown per site since start (or the last reset)
+    pub fn report() -> Vec<SiteReport> {
+        Site::ALL
+            .iter()
+            .map(|&site| {
+                let stats = stats(site);
+                SiteReport {
+                    site: site.as_str(),
+                    waits: stats.waits.load(Ordering::Relaxed),
+                    total_wait_us: stats.total_ns.load(Ordering::Relaxed) / 1_000,
+                    max_wait_us: stats.max_ns.load(Ordering::Relaxed) / 1_000,
+                }
+            })
+            .collect()
+    }
+
+    pub fn reset() {
+        for stats in &STATS {
+            stats.waits.store(0, Ordering::Relaxed);
+            stats.total_ns.store(0, Ordering::Relaxed);
+            stats.max_ns.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Print the breakdown as a table
+    pub fn write_report<W: std::io::Write>(mut out: W) -> std::io::Result<()> {
+        writeln!(out, "site,waits,total_wait_us,max_wait_us")?;
+        for site in report() {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                site.site, site.waits, site.total_wait_us, site.max_wait_us
+            )?;
+        }
+        Ok(())
+    }
+}
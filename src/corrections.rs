@@ -0,0 +1,68 @@
+use crate::amount::{parse_amount, AmountFormat};
+use crate::csv_io::stream_corrections;
+use crate::models::{parse_transaction_type, CorrectionRow, TransactionRow};
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::BufReader;
+
+/// Field fixes for rejected rows, keyed by the tx id the row was rejected under
+#[derive(Debug, Default)]
+pub struct Corrections {
+    fixes: HashMap<u32, Vec<CorrectionRow>>,
+    /// Spellings a corrected amount may use
+    amounts: AmountFormat,
+}
+
+impl Corrections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read corrected amounts as `format` allows instead of as plain decimals
+    pub fn with_amount_format(mut self, format: AmountFormat) -> Self {
+        self.amounts = format;
+        self
+    }
+
+    /// Read a `tx,field,value` CSV, corrected amounts spelled as `format` allows
+    pub async fn load(path: &Path, format: AmountFormat) -> Result<Self> {
+        let file = File::open(path).await?;
+        let mut stream = stream_corrections(BufReader::new(file));
+
+        let mut corrections = Self::new().with_amount_format(format);
+        while let Some(row) = stream.next().await {
+            corrections.add(row?);
+        }
+        Ok(corrections)
+    }
+
+    /// Later corrections of the same field win
+    pub fn add(&mut self, row: CorrectionRow) {
+        self.fixes.entry(row.tx).or_default().push(row);
+    }
+
+    /// `row` with its corrections applied, unchanged when it has none
+    ///
+    /// Fields are `type`, `client`, `tx`, `amount` and `to`; an empty value
+    /// clears `amount` or `to`.
+    pub fn apply(&self, row: &TransactionRow) -> Result<TransactionRow> {
+        let mut corrected = row.clone();
+        for fix in self.fixes.get(&row.tx).into_iter().flatten() {
+            let value = fix.value.trim();
+            match fix.field.trim() {
+                "type" => corrected.tx_type = parse_transaction_type(value)?,
+                "client" => corrected.client = value.parse().with_context(|| format!("tx {}: bad client '{}'", row.tx, value))?,
+                "tx" => corrected.tx = value.parse().with_context(|| format!("tx {}: bad tx '{}'", row.tx, value))?,
+                "amount" if value.is_empty() => corrected.amount = None,
+                "amount" => corrected.amount = Some(parse_amount(value, self.amounts)?),
+                "to" if value.is_empty() => corrected.to = None,
+                "to" => corrected.to = Some(value.parse().with_context(|| format!("tx {}: bad client '{}'", row.tx, value))?),
+                other => anyhow::bail!("tx {}: unknown field '{}'", row.tx, other),
+            }
+        }
+        Ok(corrected)
+    }
+}
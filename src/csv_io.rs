@@ -1,40 +1,416 @@
-use crate::models::{AccountOutput, TransactionRow};
-use csv_async::AsyncReaderBuilder;
-use futures::stream::Stream;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use tokio_util::compat::TokioAsyncReadCompatExt;
+use crate::amount::{AmountFormat, AmountUnits};
+use crate::models::{
+    AccountOutput, CorrectionRow, OpeningBalanceRow, SequencedTransactionRow, TimedTransactionRow, TransactionRow,
+};
+use crate::schema::{CsvSchema, DEFAULT_COLUMNS};
+use csv_async::{AsyncDeserializer, AsyncReaderBuilder, StringRecord};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
 
-/// Stream transactions from async reader
+/// Stream transactions from async reader, amounts written as plain decimals
 pub fn stream_transactions<R: AsyncRead + Unpin + Send + 'static>(
     reader: R,
-) -> impl Stream<Item = Result<TransactionRow, csv_async::Error>> {
+) -> impl Stream<Item = anyhow::Result<TransactionRow>> {
+    stream_transactions_with(reader, CsvSchema::default(), AmountFormat::default())
+}
+
+/// Stream transactions laid out as `schema` says, positionally when it has no header
+pub fn stream_transactions_with<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    schema: CsvSchema,
+    format: AmountFormat,
+) -> impl Stream<Item = anyhow::Result<TransactionRow>> {
+    let compat_reader = reader.compat();
+    let csv_reader = AsyncReaderBuilder::new()
+        .delimiter(schema.delimiter)
+        .has_headers(schema.has_headers)
+        .trim(csv_async::Trim::All)
+        .flexible(true)
+        .create_deserializer(compat_reader);
+    
+    deserialize_rows(csv_reader, format)
+}
+
+/// Rows of `csv_reader` with their amounts read under `format`
+///
+/// A row that fails to decode is an error of its own, the rows after it are
+/// still read. Without a header, columns are in the order of `DEFAULT_COLUMNS`.
+fn deserialize_rows<R, T>(csv_reader: AsyncDeserializer<Compat<R>>, format: AmountFormat) -> BoxStream<'static, anyhow::Result<T>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+{
+    let positional = (!csv_reader.has_headers()).then(|| StringRecord::from(DEFAULT_COLUMNS.to_vec()));
+    futures::stream::unfold(Some((csv_reader, positional)), move |state| async move {
+        let (mut csv_reader, headers) = state?;
+        let headers = match headers {
+            Some(headers) => headers,
+            None => match csv_reader.headers().await {
+                Ok(headers) => headers.clone(),
+                // Without its header no row can be read
+                Err(e) => return Some((Err(e.into()), None)),
+            },
+        };
+        
+        let mut record = StringRecord::new();
+        match csv_reader.read_record(&mut record).await {
+            Ok(true) => {
+                let row = decode_record(&record, &headers, format);
+                Some((row, Some((csv_reader, Some(headers)))))
+            }
+            Ok(false) => None,
+            // The input itself failed, nothing more can be read from it
+            Err(e) if e.is_io_error() => Some((Err(e.into()), None)),
+            Err(e) => Some((Err(e.into()), Some((csv_reader, Some(headers))))),
+        }
+    })
+    .boxed()
+}
+
+/// One CSV record as `T`, its amount spelled as a plain decimal first, see `AmountFormat::canonical`
+fn decode_record<T: DeserializeOwned>(record: &StringRecord, headers: &StringRecord, format: AmountFormat) -> anyhow::Result<T> {
+    let column = headers.iter().position(|name| name == "amount");
+    let Some(raw) = column.and_then(|column| record.get(column)).filter(|raw| !raw.is_empty()) else {
+        return Ok(record.deserialize(Some(headers))?);
+    };
+    
+    let canonical = format.canonical(raw)?;
+    let record: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(i, field)| if Some(i) == column { canonical.as_str() } else { field })
+        .collect();
+    Ok(record.deserialize(Some(headers))?)
+}
+
+/// Stream transactions from newline-delimited JSON, one object per line, amounts read under `format`
+///
+/// Blank lines are skipped. A line that fails to decode is an error of its
+/// own, the lines after it are still read.
+pub fn stream_json_transactions<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    format: AmountFormat,
+) -> impl Stream<Item = anyhow::Result<TransactionRow>> {
+    let lines = BufReader::new(reader).lines();
+    futures::stream::unfold(lines, move |mut lines| async move {
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => {
+                    let row = parse_json_row(&line, format).map_err(anyhow::Error::from);
+                    return Some((row, lines));
+                }
+                Ok(None) => return None,
+                Err(e) => return Some((Err(e.into()), lines)),
+            }
+        }
+    })
+}
+
+/// Input row with the line it was read from, so it can be reported back
+#[derive(Debug)]
+pub struct NumberedRow {
+    /// 1-based line number in the input
+    pub line: u64,
+    /// The line as read, without its line ending
+    pub raw: String,
+    pub row: anyhow::Result<TransactionRow>,
+}
+
+/// Stream transactions from CSV along with their line numbers and raw text
+///
+/// Slower than `stream_transactions`, each line is parsed on its own. Blank
+/// lines are skipped, a row may not span several lines.
+pub fn stream_numbered_transactions<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    schema: CsvSchema,
+    format: AmountFormat,
+) -> impl Stream<Item = NumberedRow> {
+    let framing = if schema.has_headers {
+        Framing::CsvHeader(schema.delimiter)
+    } else {
+        Framing::Csv(schema.delimiter, StringRecord::from(DEFAULT_COLUMNS.to_vec()))
+    };
+    number_lines(reader, framing, format)
+}
+
+/// Stream transactions from JSON Lines along with their line numbers and raw text
+pub fn stream_numbered_json_transactions<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    format: AmountFormat,
+) -> impl Stream<Item = NumberedRow> {
+    number_lines(reader, Framing::Json, format)
+}
+
+/// How the lines of a numbered stream are decoded
+enum Framing {
+    Json,
+    /// CSV whose header line is still to be read
+    CsvHeader(u8),
+    /// CSV rows decoded against this header
+    Csv(u8, StringRecord),
+}
+
+fn number_lines<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    framing: Framing,
+    format: AmountFormat,
+) -> impl Stream<Item = NumberedRow> {
+    let lines = BufReader::new(reader).lines();
+    // Lines are dropped after a read error, the stream ends with it
+    futures::stream::unfold((Some(lines), 0u64, framing), move |(mut lines, mut line, mut framing)| async move {
+        loop {
+            line += 1;
+            let raw = match lines.as_mut()?.next_line().await {
+                Ok(Some(raw)) => raw,
+                Ok(None) => return None,
+                Err(e) => {
+                    let row = NumberedRow { line, raw: String::new(), row: Err(e.into()) };
+                    return Some((row, (None, line, framing)));
+                }
+            };
+            if raw.trim().is_empty() {
+                continue;
+            }
+
+            let row = match &framing {
+                Framing::Json => parse_json_row(&raw, format).map_err(anyhow::Error::from),
+                Framing::CsvHeader(delimiter) => match parse_csv_record(&raw, *delimiter).await {
+                    Ok(header) => {
+                        framing = Framing::Csv(*delimiter, header);
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                },
+                Framing::Csv(delimiter, header) => match parse_csv_record(&raw, *delimiter).await {
+                    Ok(record) => decode_record(&record, header, format),
+                    Err(e) => Err(e),
+                },
+            };
+            return Some((NumberedRow { line, raw, row }, (lines, line, framing)));
+        }
+    })
+}
+
+/// Fields of one CSV line, trimmed as `stream_transactions` trims them
+async fn parse_csv_record(line: &str, delimiter: u8) -> anyhow::Result<StringRecord> {
+    let mut reader = AsyncReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .trim(csv_async::Trim::All)
+        .create_reader(line.as_bytes());
+    let mut record = StringRecord::new();
+    reader.read_record(&mut record).await?;
+    Ok(record)
+}
+
+/// Decode one JSON object into the row a CSV line would give, its amount read under `format`
+pub fn parse_json_row(line: &str, format: AmountFormat) -> serde_json::Result<TransactionRow> {
+    let fields: HashMap<String, &RawValue> = serde_json::from_str(line)?;
+    let mut value = serde_json::Map::with_capacity(fields.len());
+
+    for (name, raw) in fields {
+        // A numeric amount keeps its exact digits for the text rules, f64 would round them
+        let field = match raw.get().as_bytes().first() {
+            Some(b'-' | b'0'..=b'9') if name == "amount" => Value::String(canonical_json_amount(raw.get(), format)?),
+            Some(b'"') if name == "amount" => {
+                let text: String = serde_json::from_str(raw.get())?;
+                Value::String(canonical_json_amount(&text, format)?)
+            }
+            _ => serde_json::from_str(raw.get())?,
+        };
+        value.insert(name, field);
+    }
+
+    serde_json::from_value(Value::Object(value))
+}
+
+/// `text` as `AmountFormat::canonical` spells it, failing like a JSON decoding error
+fn canonical_json_amount(text: &str, format: AmountFormat) -> serde_json::Result<String> {
+    format.canonical(text).map_err(serde::de::Error::custom)
+}
+
+/// Stream timestamped transactions (type,client,tx,amount,timestamp) from async reader, amounts read under `format`
+pub fn stream_timed_transactions<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    format: AmountFormat,
+) -> impl Stream<Item = anyhow::Result<TimedTransactionRow>> {
     let compat_reader = reader.compat();
     let csv_reader = AsyncReaderBuilder::new()
         .trim(csv_async::Trim::All)
         .flexible(true)
         .create_deserializer(compat_reader);
     
-    csv_reader.into_deserialize::<TransactionRow>()
+    deserialize_rows(csv_reader, format)
 }
 
+/// Stream sequenced transactions (type,client,tx,amount,seq) from async reader, amounts read under `format`
+pub fn stream_sequenced_transactions<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    format: AmountFormat,
+) -> impl Stream<Item = anyhow::Result<SequencedTransactionRow>> {
+    let compat_reader = reader.compat();
+    let csv_reader = AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .flexible(true)
+        .create_deserializer(compat_reader);
+    
+    deserialize_rows(csv_reader, format)
+}
+
+/// Stream opening balances (client,amount) from async reader
+pub fn stream_opening_balances<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+) -> impl Stream<Item = Result<OpeningBalanceRow, csv_async::Error>> {
+    let compat_reader = reader.compat();
+    let csv_reader = AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .create_deserializer(compat_reader);
+    
+    csv_reader.into_deserialize::<OpeningBalanceRow>()
+}
+
+/// Stream corrections (tx,field,value) from async reader
+pub fn stream_corrections<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+) -> impl Stream<Item = Result<CorrectionRow, csv_async::Error>> {
+    let compat_reader = reader.compat();
+    let csv_reader = AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .flexible(true)
+        .create_deserializer(compat_reader);
+    
+    csv_reader.into_deserialize::<CorrectionRow>()
+}
+
+/// Bytes of formatted output gathered before each write
+pub const WRITE_CHUNK_SIZE: usize = 64 * 1024;
+
 pub async fn write_accounts<W: AsyncWrite + Unpin>(
-    mut writer: W,
+    writer: W,
     accounts: Vec<AccountOutput>,
 ) -> Result<(), anyhow::Error> {
-    writer.write_all(b"client,available,held,total,locked\n").await?;
+    write_account_stream(writer, futures::stream::iter(accounts), AmountUnits::Decimal).await
+}
+
+/// Write accounts as the stream yields them, holding at most one chunk of output
+///
+/// Each chunk is written before the next account is pulled, so a slow reader
+/// slows the stream down instead of letting output pile up. Decimal amounts
+/// get four places, minor units are written as the exact count.
+pub async fn write_account_stream<W, S>(mut writer: W, mut accounts: S, units: AmountUnits) -> Result<(), anyhow::Error>
+where
+    W: AsyncWrite + Unpin,
+    S: Stream<Item = AccountOutput> + Unpin,
+{
+    let mut chunk = String::with_capacity(WRITE_CHUNK_SIZE + 128);
+    chunk.push_str("client,available,held,total,locked\n");
+    
+    while let Some(account) = accounts.next().await {
+        match units {
+            AmountUnits::Decimal => writeln!(
+                chunk,
+                "{},{:.4},{:.4},{:.4},{}",
+                account.client,
+                account.available,
+                account.held,
+                account.total,
+                account.locked
+            )?,
+            AmountUnits::Minor(_) => {
+                let account = units.encode_account(account);
+                writeln!(
+                    chunk,
+                    "{},{},{},{},{}",
+                    account.client,
+                    account.available,
+                    account.held,
+                    account.total,
+                    account.locked
+                )?
+            }
+        }
+        
+        // One large write per chunk instead of one small write per account
+        if chunk.len() >= WRITE_CHUNK_SIZE {
+            writer.write_all(chunk.as_bytes()).await?;
+            chunk.clear();
+        }
+    }
     
+    writer.write_all(chunk.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Write accounts as JSON Lines, one object per account as the server's JSON summary has them
+pub async fn write_accounts_json<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    accounts: Vec<AccountOutput>,
+    units: AmountUnits,
+) -> Result<(), anyhow::Error> {
+    let mut out = String::new();
     for account in accounts {
-        let line = format!(
-            "{},{:.4},{:.4},{:.4},{}\n",
-            account.client,
-            account.available,
-            account.held,
-            account.total,
-            account.locked
-        );
-        writer.write_all(line.as_bytes()).await?;
+        out.push_str(&serde_json::to_string(&units.encode_account(account))?);
+        out.push('\n');
     }
     
+    writer.write_all(out.as_bytes()).await?;
     writer.flush().await?;
     Ok(())
 }
+
+/// Write accounts as an aligned table for reading in a terminal, amounts as in the CSV
+pub async fn write_accounts_table<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    accounts: Vec<AccountOutput>,
+    units: AmountUnits,
+) -> Result<(), anyhow::Error> {
+    let mut csv = Vec::new();
+    write_account_stream(&mut csv, futures::stream::iter(accounts), units).await?;
+    
+    writer.write_all(render_table(&String::from_utf8(csv)?).as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Align CSV without quoted fields into columns, the first line as header
+///
+/// Numbers are right-aligned, anything else left-aligned.
+pub fn render_table(csv: &str) -> String {
+    let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| rows.iter().filter_map(|row| row.get(column)).map(|field| field.len()).max().unwrap_or(0))
+        .collect();
+    
+    let mut out = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(field, &width)| {
+                if index > 0 && field.parse::<rust_decimal::Decimal>().is_ok() {
+                    format!("{:>width$}", field)
+                } else {
+                    format!("{:<width$}", field)
+                }
+            })
+            .collect();
+        out.push_str(cells.join(" | ").trim_end());
+        out.push('\n');
+        
+        if index == 0 {
+            let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+            out.push_str(&rule.join("-+-"));
+            out.push('\n');
+        }
+    }
+    out
+}
@@ -0,0 +1,123 @@
+use crate::errors::ProcessingError;
+use anyhow::Result;
+use csv_async::AsyncWriterBuilder;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+/// Tx ids kept per source, its count goes on past them
+pub const MAX_DUPLICATE_IDS_PER_SOURCE: usize = 1_000;
+
+/// Sources counted on their own, each connection is one so they come and go
+pub const MAX_DUPLICATE_SOURCES: usize = 1_000;
+
+/// Source the duplicates of sources past `MAX_DUPLICATE_SOURCES` are counted under
+pub const OTHER_SOURCES: &str = "(other sources)";
+
+/// Duplicates rejected from one source
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct SourceDuplicates {
+    /// Input file, or the name or address of a connection
+    pub source: String,
+    pub count: u64,
+    /// The first `MAX_DUPLICATE_IDS_PER_SOURCE` of them, in the order rejected
+    pub tx_ids: Vec<u32>,
+}
+
+impl SourceDuplicates {
+    /// More were rejected than `tx_ids` lists
+    pub fn truncated(&self) -> bool {
+        self.count > self.tx_ids.len() as u64
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DuplicateReport {
+    pub total: u64,
+    /// Ordered by source
+    pub sources: Vec<SourceDuplicates>,
+}
+
+impl DuplicateReport {
+    /// Summary section printed after a CLI run
+    pub fn summary(&self) -> String {
+        let mut out = format!("duplicates: {} rejected\n", self.total);
+        for source in &self.sources {
+            let ids: Vec<String> = source.tx_ids.iter().map(u32::to_string).collect();
+            let more = if source.truncated() { ", ..." } else { "" };
+            out.push_str(&format!(
+                "  {}: {} (tx {}{})\n",
+                source.source,
+                source.count,
+                ids.join(", "),
+                more
+            ));
+        }
+        out
+    }
+}
+
+const CSV_HEADER: [&str; 3] = ["source", "count", "tx_ids"];
+
+/// Rows rejected as duplicates, counted per source as they are rejected
+///
+/// Counts are kept in memory only, a run's report covers what it was sent.
+#[derive(Default)]
+pub struct DuplicateTracker {
+    sources: Mutex<BTreeMap<String, SourceDuplicates>>,
+}
+
+impl DuplicateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, source: &str, tx: u32) {
+        let mut sources = self.sources.lock().unwrap();
+        let source = if sources.len() >= MAX_DUPLICATE_SOURCES && !sources.contains_key(source) {
+            OTHER_SOURCES
+        } else {
+            source
+        };
+        let entry = sources
+            .entry(source.to_string())
+            .or_insert_with(|| SourceDuplicates {
+                source: source.to_string(),
+                ..Default::default()
+            });
+
+        entry.count += 1;
+        if entry.tx_ids.len() < MAX_DUPLICATE_IDS_PER_SOURCE {
+            entry.tx_ids.push(tx);
+        }
+    }
+
+    /// Record `tx` if `outcome` rejected it as a duplicate
+    pub fn record_outcome(&self, source: &str, tx: u32, outcome: &Result<(), ProcessingError>) {
+        if matches!(outcome, Err(ProcessingError::DuplicateTransaction)) {
+            self.record(source, tx);
+        }
+    }
+
+    pub fn report(&self) -> DuplicateReport {
+        let sources: Vec<SourceDuplicates> = self.sources.lock().unwrap().values().cloned().collect();
+        DuplicateReport {
+            total: sources.iter().map(|s| s.count).sum(),
+            sources,
+        }
+    }
+
+    /// One line per source, its tx ids separated by spaces
+    ///
+    /// Sources are file names and connection names, quoted as the csv writer sees fit.
+    pub async fn to_csv(&self) -> Result<String> {
+        let mut writer = AsyncWriterBuilder::new().create_writer(Vec::new());
+        writer.write_record(CSV_HEADER).await?;
+        for s in self.report().sources {
+            let ids: Vec<String> = s.tx_ids.iter().map(u32::to_string).collect();
+            writer.write_record([s.source, s.count.to_string(), ids.join(" ")]).await?;
+        }
+        Ok(String::from_utf8(writer.into_inner().await?)?)
+    }
+}
@@ -0,0 +1,187 @@
+use crate::event_store::EventLog;
+use crate::models::Account;
+use crate::reporting::tmp_path;
+use crate::storage::StoredTransaction;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Bumped whenever the snapshot layout changes, older snapshots are ignored
+const SNAPSHOT_VERSION: u32 = 4;
+
+/// Engine state as of a position in the event log
+///
+/// Startup loads it and replays only the events logged after `log_offset`.
+/// On disk it is a MessagePack map followed by the CRC32 of its bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    version: u32,
+    /// Event log length in bytes when the snapshot was taken
+    pub log_offset: u64,
+    /// Writer generation that took it, the log's last generation marker before `log_offset`
+    pub generation: u64,
+    /// CRC32 of the event log before `log_offset`, for logs that keep one
+    pub log_checksum: Option<u32>,
+    /// Events in the log before `log_offset`
+    pub events: usize,
+    pub accounts: Vec<Account>,
+    /// Every registered tx id
+    pub tx_ids: Vec<u32>,
+    /// Stored transactions that disputes after the snapshot may reference
+    pub transactions: Vec<(u32, StoredTransaction)>,
+}
+
+impl EngineSnapshot {
+    pub fn new(
+        log_offset: u64,
+        generation: u64,
+        events: usize,
+        accounts: Vec<Account>,
+        tx_ids: Vec<u32>,
+        transactions: Vec<(u32, StoredTransaction)>,
+    ) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            log_offset,
+            generation,
+            log_checksum: None,
+            events,
+            accounts,
+            tx_ids,
+            transactions,
+        }
+    }
+
+    pub fn with_log_checksum(mut self, log_checksum: Option<u32>) -> Self {
+        self.log_checksum = log_checksum;
+        self
+    }
+
+    /// Why the snapshot can't have been taken from `log`, None when it fits
+    ///
+    /// The log must reach the snapshot's offset, have been written up to it by
+    /// the snapshot's generation and hold the same bytes there.
+    pub async fn mismatch(&self, log: &dyn EventLog) -> Result<Option<String>> {
+        let log_len = log.end_offset().await?;
+        if self.log_offset > log_len {
+            return Ok(Some(format!(
+                "it covers {} bytes of the event log, which holds only {}",
+                self.log_offset, log_len
+            )));
+        }
+        if let Some(generation) = log.generation_at(self.log_offset).await? {
+            if generation != self.generation {
+                return Ok(Some(format!(
+                    "it was taken by writer generation {}, the event log was written by generation {} up to byte {}",
+                    self.generation, generation, self.log_offset
+                )));
+            }
+        }
+        if let (Some(expected), Some(actual)) = (self.log_checksum, log.checksum_to(self.log_offset).await?) {
+            if expected != actual {
+                return Ok(Some(format!(
+                    "the event log's first {} bytes have checksum {:08x}, not {:08x}",
+                    self.log_offset, actual, expected
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Write then rename so a crash never leaves a truncated snapshot
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let mut bytes = rmp_serde::to_vec_named(self)?;
+        let crc = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        let tmp = tmp_path(path);
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    /// `None` when there is no snapshot or it was written by another version
+    pub async fn load(path: &Path) -> Result<Option<Self>> {
+        let bytes = match tokio::fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let snapshot = Self::decode(&bytes)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            tracing::warn!(
+                "Ignoring snapshot {} with version {}",
+                path.display(),
+                snapshot.version
+            );
+            return Ok(None);
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Load a snapshot that must exist, be of this version and pass `violations`
+    pub async fn load_verified(path: &Path) -> Result<Self> {
+        let bytes = tokio::fs::read(path).await?;
+        let snapshot = Self::decode(&bytes)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            anyhow::bail!("Snapshot version {}, this build reads version {}", snapshot.version, SNAPSHOT_VERSION);
+        }
+
+        let violations = snapshot.violations();
+        if !violations.is_empty() {
+            anyhow::bail!("Snapshot fails {} invariant(s):\n{}", violations.len(), violations.join("\n"));
+        }
+        Ok(snapshot)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let Some(split) = bytes.len().checked_sub(4) else {
+            anyhow::bail!("Snapshot is truncated");
+        };
+        let (payload, crc) = bytes.split_at(split);
+        let crc = u32::from_le_bytes(crc.try_into()?);
+        if crc32fast::hash(payload) != crc {
+            anyhow::bail!("Snapshot checksum mismatch");
+        }
+        Ok(rmp_serde::from_slice(payload)?)
+    }
+
+    /// Invariants the snapshot breaks, empty for a consistent one
+    ///
+    /// Each client has one account with no negative held funds, each tx id is
+    /// registered once, and every stored transaction is registered and belongs
+    /// to an account.
+    pub fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        let mut clients = HashSet::with_capacity(self.accounts.len());
+        for account in &self.accounts {
+            if !clients.insert(account.client) {
+                violations.push(format!("client {} has more than one account", account.client));
+            }
+            if account.held.is_sign_negative() && !account.held.is_zero() {
+                violations.push(format!("client {} holds {}", account.client, account.held));
+            }
+        }
+
+        let mut tx_ids = HashSet::with_capacity(self.tx_ids.len());
+        for &tx_id in &self.tx_ids {
+            if !tx_ids.insert(tx_id) {
+                violations.push(format!("tx {} is registered twice", tx_id));
+            }
+        }
+
+        for (tx_id, tx) in &self.transactions {
+            if !tx_ids.contains(tx_id) {
+                violations.push(format!("stored tx {} is not registered", tx_id));
+            }
+            if !clients.contains(&tx.client) {
+                violations.push(format!("stored tx {} belongs to client {} without an account", tx_id, tx.client));
+            }
+        }
+
+        violations
+    }
+}
@@ -18,8 +18,90 @@ pub enum ProcessingError {
     AlreadyDisputed,
     #[error("not disputed")]
     NotDisputed,
+    #[error("invalid transfer destination")]
+    InvalidTransfer,
     #[error("duplicate transaction ID")]
     DuplicateTransaction,
+    #[error("account already has activity")]
+    AccountNotEmpty,
+    #[error("account not found")]
+    AccountNotFound,
+    #[error("account not locked")]
+    AccountNotLocked,
+    #[error("transaction type not accepted from producers")]
+    UnsupportedTransactionType,
+    #[error("internal tx id space exhausted")]
+    IdSpaceExhausted,
+    #[error("rejected by authorizer")]
+    AuthorizationDenied,
+    #[error("API key not permitted to submit for this client")]
+    Unauthorized,
+    #[error("client reserved for the latency prober")]
+    ReservedClient,
+    #[error("authorizer unavailable")]
+    AuthorizerUnavailable,
+    #[error("accounting period closed")]
+    PeriodClosed,
+    #[error("event log not replayed yet")]
+    RebuildPending,
+    #[error("sequence number already used or not reached in time")]
+    OutOfSequence,
+    #[error("cold storage unavailable")]
+    StorageUnavailable,
+    #[error("event log append failed")]
+    EventLogUnavailable,
     #[error("actor communication failed")]
     ActorCommunicationError,
+    #[error("transfer debited but neither credited nor refunded")]
+    TransferIncomplete,
+    #[error("engine busy, retry in {retry_after_ms}ms")]
+    EngineBusy { retry_after_ms: u64 },
+}
+
+impl ProcessingError {
+    /// Stable identifier reported to producers, unlike the message it never changes
+    pub fn code(&self) -> &'static str {
+        match self {
+            ProcessingError::MissingAmount => "missing_amount",
+            ProcessingError::InvalidAmount => "invalid_amount",
+            ProcessingError::AccountLocked => "account_locked",
+            ProcessingError::InsufficientFunds => "insufficient_funds",
+            ProcessingError::TransactionNotFound => "transaction_not_found",
+            ProcessingError::ClientMismatch => "client_mismatch",
+            ProcessingError::AlreadyDisputed => "already_disputed",
+            ProcessingError::NotDisputed => "not_disputed",
+            ProcessingError::InvalidTransfer => "invalid_transfer",
+            ProcessingError::DuplicateTransaction => "duplicate_transaction",
+            ProcessingError::AccountNotEmpty => "account_not_empty",
+            ProcessingError::AccountNotFound => "account_not_found",
+            ProcessingError::AccountNotLocked => "account_not_locked",
+            ProcessingError::UnsupportedTransactionType => "unsupported_transaction_type",
+            ProcessingError::IdSpaceExhausted => "id_space_exhausted",
+            ProcessingError::AuthorizationDenied => "authorization_denied",
+            ProcessingError::Unauthorized => "unauthorized",
+            ProcessingError::ReservedClient => "reserved_client",
+            ProcessingError::AuthorizerUnavailable => "authorizer_unavailable",
+            ProcessingError::PeriodClosed => "period_closed",
+            ProcessingError::RebuildPending => "rebuild_pending",
+            ProcessingError::OutOfSequence => "out_of_sequence",
+            ProcessingError::StorageUnavailable => "storage_unavailable",
+            ProcessingError::EventLogUnavailable => "event_log_unavailable",
+            ProcessingError::ActorCommunicationError => "actor_communication_error",
+            ProcessingError::TransferIncomplete => "transfer_incomplete",
+            ProcessingError::EngineBusy { .. } => "engine_busy",
+        }
+    }
+
+    /// A dependency failed rather than the row being refused, the same row may succeed later
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ProcessingError::AuthorizerUnavailable
+                | ProcessingError::RebuildPending
+                | ProcessingError::StorageUnavailable
+                | ProcessingError::EventLogUnavailable
+                | ProcessingError::ActorCommunicationError
+                | ProcessingError::EngineBusy { .. }
+        )
+    }
 }
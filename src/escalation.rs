@@ -0,0 +1,249 @@
+use crate::clock::{Clock, SystemClock};
+use crate::events::DomainEvent;
+use crate::models::TransactionType;
+use crate::scalable_engine::ScalableEngine;
+use crate::storage::RetryPolicy;
+use anyhow::Result;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// When disputes are escalated to case management
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscalationPolicy {
+    /// A dispute still open this long after it was opened is escalated once
+    pub max_open_age: Duration,
+    /// A chargeback of at least this amount is escalated
+    pub high_value_chargeback: Decimal,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        Self {
+            max_open_age: Duration::from_secs(30 * 24 * 60 * 60),
+            high_value_chargeback: Decimal::from(10_000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscalationReason {
+    DisputeAged,
+    HighValueChargeback,
+}
+
+/// Dispute context sent to case management, serialized as the webhook body
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Escalation {
+    pub reason: EscalationReason,
+    pub client: u16,
+    pub tx: u32,
+    /// Amount held by the dispute
+    pub amount: Decimal,
+    /// Unix seconds the dispute was seen opening
+    pub opened_at: u64,
+    /// Unix seconds it was escalated
+    pub escalated_at: u64,
+}
+
+/// Where escalations are delivered
+#[async_trait]
+pub trait EscalationSink: Send + Sync {
+    async fn send(&self, escalation: &Escalation) -> Result<()>;
+}
+
+/// POSTs each escalation as JSON, any non-2xx answer is a failed delivery
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+        }
+    }
+}
+
+impl WebhookSink {
+    /// POST `body` as JSON, failing on a non-2xx answer
+    pub(crate) async fn post<T: Serialize + ?Sized>(&self, body: &T) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EscalationSink for WebhookSink {
+    async fn send(&self, escalation: &Escalation) -> Result<()> {
+        self.post(escalation).await
+    }
+}
+
+struct OpenDispute {
+    amount: Decimal,
+    opened_at: SystemTime,
+    escalated: bool,
+}
+
+/// Watches the engine's domain events for disputes that need a human
+///
+/// Tracks the disputes opened while it is subscribed; ones opened before it
+/// started, e.g. replayed from the log, are not known to it. The disputed
+/// amount is looked up when the dispute is seen, a dispute already closed by
+/// then is not tracked. Deliveries are retried, an aged dispute whose
+/// delivery still fails is tried again on the next sweep.
+pub struct EscalationMonitor {
+    policy: EscalationPolicy,
+    sink: Arc<dyn EscalationSink>,
+    clock: Arc<dyn Clock>,
+    retry: RetryPolicy,
+    open: Mutex<HashMap<(u16, u32), OpenDispute>>,
+}
+
+impl EscalationMonitor {
+    pub fn new(policy: EscalationPolicy, sink: Arc<dyn EscalationSink>) -> Self {
+        Self {
+            policy,
+            sink,
+            clock: Arc::new(SystemClock),
+            retry: RetryPolicy::default(),
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Disputes being tracked
+    pub fn open_disputes(&self) -> usize {
+        self.open.lock().unwrap().len()
+    }
+
+    /// Track or close a dispute, escalating a high-value chargeback right away
+    pub async fn observe(&self, engine: &ScalableEngine, event: &DomainEvent) {
+        match event {
+            DomainEvent::DisputeOpened { client, tx } => {
+                let Some((stored, _)) = engine.inspect_transaction(*client, *tx).await else {
+                    return;
+                };
+                let dispute = OpenDispute {
+                    amount: stored.held_amount.unwrap_or(stored.amount),
+                    opened_at: self.clock.now(),
+                    escalated: false,
+                };
+                self.open.lock().unwrap().insert((*client, *tx), dispute);
+            }
+            DomainEvent::TransactionApplied { tx, client, tx_type, .. } => {
+                let closed = match tx_type {
+                    TransactionType::Resolve | TransactionType::Chargeback => {
+                        self.open.lock().unwrap().remove(&(*client, *tx))
+                    }
+                    _ => None,
+                };
+                if let (TransactionType::Chargeback, Some(dispute)) = (tx_type, closed) {
+                    if dispute.amount >= self.policy.high_value_chargeback {
+                        let escalation = self.escalation(EscalationReason::HighValueChargeback, *client, *tx, &dispute);
+                        if let Err(e) = self.deliver(&escalation).await {
+                            tracing::error!(client, tx, error = ?e, "Failed to escalate chargeback");
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Escalate every dispute open longer than the policy allows, each once
+    pub async fn sweep(&self) {
+        let now = self.clock.now();
+        let due: Vec<Escalation> = self
+            .open
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, dispute)| !dispute.escalated)
+            .filter(|(_, dispute)| {
+                now.duration_since(dispute.opened_at).unwrap_or_default() >= self.policy.max_open_age
+            })
+            .map(|(&(client, tx), dispute)| self.escalation(EscalationReason::DisputeAged, client, tx, dispute))
+            .collect();
+
+        for escalation in due {
+            match self.deliver(&escalation).await {
+                Ok(()) => {
+                    if let Some(dispute) = self.open.lock().unwrap().get_mut(&(escalation.client, escalation.tx)) {
+                        dispute.escalated = true;
+                    }
+                }
+                Err(e) => tracing::error!(
+                    client = escalation.client,
+                    tx = escalation.tx,
+                    error = ?e,
+                    "Failed to escalate aged dispute"
+                ),
+            }
+        }
+    }
+
+    /// Subscribe to `engine` and sweep every `interval` until the engine's events stop
+    ///
+    /// Subscribes before returning, so disputes processed afterwards are seen.
+    pub fn spawn(self: Arc<Self>, engine: Arc<ScalableEngine>, interval: Duration) -> JoinHandle<()> {
+        let mut events = engine.subscribe();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) => self.observe(&engine, &event).await,
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::warn!(missed, "Escalation monitor fell behind, disputes may go untracked");
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = ticker.tick() => self.sweep().await,
+                }
+            }
+        })
+    }
+
+    fn escalation(&self, reason: EscalationReason, client: u16, tx: u32, dispute: &OpenDispute) -> Escalation {
+        Escalation {
+            reason,
+            client,
+            tx,
+            amount: dispute.amount,
+            opened_at: unix_secs(dispute.opened_at),
+            escalated_at: unix_secs(self.clock.now()),
+        }
+    }
+
+    async fn deliver(&self, escalation: &Escalation) -> Result<()> {
+        self.retry.run(|| self.sink.send(escalation)).await
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
@@ -1,84 +1,705 @@
-use crate::models::TransactionRow;
-use anyhow::Result;
-use std::path::PathBuf;
+use crate::contention::{measure, Site};
+use crate::models::{parse_transaction_type, TransactionRow};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
-/// Simple append-only event store using CSV format
+/// On-disk encoding of an event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `type,client,tx,amount[,to]` lines, written by earlier versions
+    Csv,
+    /// Versioned header, then length-prefixed bincode records each with a CRC32
+    Binary,
+}
+
+/// When appended events are forced to disk, and so what `append` waits for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurabilityPolicy {
+    /// Handed to the OS only, a machine crash can lose acknowledged events
+    #[default]
+    Buffered,
+    /// fsync before every append returns, once per written batch
+    PerWrite,
+    /// fsync in the background at this interval, appends don't wait for it
+    Interval(Duration),
+    /// Like `PerWrite`, with batches collected for this long so more appends share each fsync
+    GroupCommit(Duration),
+}
+
+impl FromStr for DurabilityPolicy {
+    type Err = anyhow::Error;
+
+    /// `buffered`, `per-write`, `interval:<ms>` or `group:<ms>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (name, millis) = match s.split_once(':') {
+            Some((name, millis)) => (name, Some(Duration::from_millis(millis.trim().parse()?))),
+            None => (s.as_str(), None),
+        };
+        match (name, millis) {
+            ("buffered", None) => Ok(DurabilityPolicy::Buffered),
+            ("per-write", None) => Ok(DurabilityPolicy::PerWrite),
+            ("interval", Some(every)) => Ok(DurabilityPolicy::Interval(every)),
+            ("group", Some(window)) => Ok(DurabilityPolicy::GroupCommit(window)),
+            _ => anyhow::bail!(
+                "Unknown durability policy '{}', expected buffered, per-write, interval:<ms> or group:<ms>",
+                s
+            ),
+        }
+    }
+}
+
+/// What the engine needs from its event log, so backends other than a local file can be plugged in
+///
+/// Offsets are opaque to the engine: it only stores ones taken from
+/// `end_offset` (in snapshots) and hands them back to `replay_from`.
+#[async_trait]
+pub trait EventLog: Send + Sync {
+    /// Append transactions in order, returning once they are as durable as the log promises
+    async fn append_batch(&self, txs: &[TransactionRow]) -> Result<()>;
+    
+    async fn append(&self, tx: &TransactionRow) -> Result<()> {
+        self.append_batch(std::slice::from_ref(tx)).await
+    }
+    
+    /// The events stored from `offset` on, an offset taken from `end_offset`
+    async fn replay_from(&self, offset: u64) -> Result<Vec<TransactionRow>>;
+    
+    async fn replay(&self) -> Result<Vec<TransactionRow>> {
+        self.replay_from(0).await
+    }
+    
+    /// Offset the next append will start at
+    async fn end_offset(&self) -> Result<u64>;
+    
+    /// Whether the log holds anything from an earlier run
+    async fn has_history(&self) -> Result<bool>;
+    
+    /// Start a new writer generation, called once the log has been fully replayed
+    async fn open_generation(&self) -> Result<u64>;
+    
+    /// Generation that wrote the log up to `offset`, None for logs that keep no generation markers
+    async fn generation_at(&self, _offset: u64) -> Result<Option<u64>> {
+        Ok(None)
+    }
+    
+    /// CRC32 of everything logged before `offset`, None for logs that are not a byte stream
+    async fn checksum_to(&self, _offset: u64) -> Result<Option<u32>> {
+        Ok(None)
+    }
+    
+    /// Record that the rejected `original` was replaced by the applied `replacement_tx`
+    ///
+    /// Replay skips the mark, it only feeds the audit trail of later runs.
+    async fn append_supersede(&self, _original: &TransactionRow, _replacement_tx: u32) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Every supersede mark in the log, oldest first
+    async fn superseded(&self) -> Result<Vec<(TransactionRow, u32)>> {
+        Ok(Vec::new())
+    }
+    
+    /// Force everything appended so far to durable storage
+    async fn sync(&self) -> Result<()>;
+    
+    /// Where the log lives, named in traces
+    fn path(&self) -> &Path;
+    
+    /// Change the policy for appends from now on, called before the log is shared
+    ///
+    /// Logs with nothing to fsync ignore it.
+    fn set_durability(&mut self, _durability: DurabilityPolicy) {}
+}
+
+/// Append-only event store, binary for new logs, CSV for logs started as CSV
 pub struct EventStore {
     path: PathBuf,
-    writer: Mutex<File>,
+    format: LogFormat,
+    writer: Arc<Mutex<File>>,
+    durability: DurabilityPolicy,
+    // Batching writer task all appends go through
+    appends: mpsc::Sender<PendingAppend>,
+    // Background fsync of `DurabilityPolicy::Interval`
+    interval_sync: Option<JoinHandle<()>>,
 }
 
+/// Encoded events waiting for the writer task, answered once its batch is written
+struct PendingAppend {
+    bytes: Vec<u8>,
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+/// Most appends written with one syscall
+const MAX_BATCH_RECORDS: usize = 1024;
+
 impl EventStore {
-    pub async fn new(path: PathBuf) -> Result<Self> {
+    pub async fn new(path: PathBuf, durability: DurabilityPolicy) -> Result<Self> {
         // Create file if doesn't exist, append if exists
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&path)
             .await?;
         
-        Ok(Self {
+        // An existing log keeps its format, records of the other kind would not parse
+        let format = if file.metadata().await?.len() == 0 {
+            file.write_all(&binary_header()).await?;
+            file.flush().await?;
+            LogFormat::Binary
+        } else {
+            detect_format(&path).await?
+        };
+        
+        let writer = Arc::new(Mutex::new(file));
+        let mut store = Self {
             path,
-            writer: Mutex::new(file),
-        })
+            format,
+            appends: spawn_writer(writer.clone(), DurabilityPolicy::Buffered),
+            writer,
+            durability: DurabilityPolicy::Buffered,
+            interval_sync: None,
+        };
+        store.set_durability(durability);
+        Ok(store)
+    }
+    
+    /// Change the policy for appends from now on, called before the store is shared
+    ///
+    /// The writer task is replaced; the old one stops once its queue is empty.
+    pub fn set_durability(&mut self, durability: DurabilityPolicy) {
+        if let Some(task) = self.interval_sync.take() {
+            task.abort();
+        }
+        if let DurabilityPolicy::Interval(every) = durability {
+            self.interval_sync = Some(spawn_interval_sync(Arc::downgrade(&self.writer), every));
+        }
+        self.appends = spawn_writer(self.writer.clone(), durability);
+        self.durability = durability;
+    }
+    
+    pub fn durability(&self) -> DurabilityPolicy {
+        self.durability
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    pub fn format(&self) -> LogFormat {
+        self.format
     }
     
-    /// Append transaction to event log
+    /// Force everything appended so far to disk, whatever the durability policy
+    ///
+    /// Appends already returned have been written, this only adds the fsync.
+    pub async fn sync(&self) -> Result<()> {
+        let mut writer = measure(Site::EventStore, self.writer.lock()).await;
+        writer.flush().await?;
+        writer.sync_all().await?;
+        Ok(())
+    }
+    
+    /// Append transaction to event log, returning once the durability policy is satisfied
+    ///
+    /// Appends queue for a writer task that writes everything queued at once,
+    /// so concurrent appends share a syscall (and an fsync, if the policy asks for one).
     pub async fn append(&self, tx: &TransactionRow) -> Result<()> {
-        let mut writer = self.writer.lock().await;
-        
-        let line = format!(
-            "{},{},{},{}\n",
-            tx.tx_type_str(),
-            tx.client,
-            tx.tx,
-            tx.amount.map(|a| a.to_string()).unwrap_or_default()
+        self.append_batch(std::slice::from_ref(tx)).await
+    }
+    
+    /// Append several transactions in order as one queued write
+    pub async fn append_batch(&self, txs: &[TransactionRow]) -> Result<()> {
+        let mut bytes = Vec::new();
+        for tx in txs {
+            match self.format {
+                LogFormat::Binary => bytes.extend(encode_record(&LogRecord::from(tx))?),
+                LogFormat::Csv => bytes.extend(csv_line(tx).into_bytes()),
+            }
+        }
+        self.write_queued(bytes).await
+    }
+    
+    /// Append a supersede mark, see `EventLog::append_supersede`
+    pub async fn append_supersede(&self, original: &TransactionRow, replacement_tx: u32) -> Result<()> {
+        let bytes = match self.format {
+            LogFormat::Binary => encode_record(&LogRecord::Supersede {
+                original: Box::new(LogRecord::from(original)),
+                replacement_tx,
+            })?,
+            LogFormat::Csv => format!("{}{},{}", SUPERSEDE_MARKER, replacement_tx, csv_line(original)).into_bytes(),
+        };
+        self.write_queued(bytes).await
+    }
+    
+    /// Every supersede mark in the log, oldest first
+    pub async fn superseded(&self) -> Result<Vec<(TransactionRow, u32)>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        
+        let content = tokio::fs::read(&self.path).await?;
+        if self.format == LogFormat::Binary {
+            return Ok(read_records(&content)?
+                .records
+                .into_iter()
+                .filter_map(|record| match record {
+                    LogRecord::Supersede { original, replacement_tx } => Some((original.into_row()?, replacement_tx)),
+                    _ => None,
+                })
+                .collect());
+        }
+        Ok(String::from_utf8_lossy(&content)
+            .lines()
+            .filter_map(parse_supersede_marker)
+            .collect())
+    }
+    
+    /// Hand encoded records to the writer task and wait for them to be written
+    async fn write_queued(&self, bytes: Vec<u8>) -> Result<()> {
+        let (reply, written) = oneshot::channel();
+        self.appends
+            .send(PendingAppend { bytes, reply })
+            .await
+            .map_err(|_| anyhow::anyhow!("Event log writer stopped"))?;
+        written
+            .await
+            .map_err(|_| anyhow::anyhow!("Event log writer stopped"))?
+            .map_err(anyhow::Error::msg)
+    }
+    
+    /// Whether the log holds anything from an earlier run
+    pub async fn has_history(&self) -> Result<bool> {
+        let len = tokio::fs::metadata(&self.path).await?.len();
+        Ok(match self.format {
+            LogFormat::Csv => len > 0,
+            LogFormat::Binary => len > HEADER_LEN as u64,
+        })
+    }
+    
+    /// Start a new writer generation, called once the log has been fully replayed
+    ///
+    /// Appends a `#generation,N` marker so each run's writes are delimited. A
+    /// torn last line left by a crash is terminated first, so it stays a lone
+    /// unparseable line instead of merging with this run's first event.
+    pub async fn open_generation(&self) -> Result<u64> {
+        if self.format == LogFormat::Binary {
+            return self.open_binary_generation().await;
+        }
+        
+        let mut last_generation = 0;
+        let mut torn_tail = false;
+        
+        if self.path.exists() {
+            let content = tokio::fs::read(&self.path).await?;
+            torn_tail = content.last().is_some_and(|&b| b != b'\n');
+            
+            for line in content.split(|&b| b == b'\n') {
+                if let Some(generation) = parse_generation_marker(line) {
+                    last_generation = last_generation.max(generation);
+                }
+            }
+        }
+        
+        let generation = last_generation + 1;
+        let marker = format!(
+            "{}{}{}\n",
+            if torn_tail { "\n" } else { "" },
+            GENERATION_MARKER,
+            generation
         );
         
-        // TODO: add batched flushes for performance
-        writer.write_all(line.as_bytes()).await?;
+        let mut writer = measure(Site::EventStore, self.writer.lock()).await;
+        writer.write_all(marker.as_bytes()).await?;
+        writer.flush().await?;
         
-        Ok(())
+        Ok(generation)
+    }
+    
+    /// Binary counterpart of `open_generation`
+    ///
+    /// Records can't be resynchronised after a torn one, so a torn tail is
+    /// cut off before the marker is appended.
+    async fn open_binary_generation(&self) -> Result<u64> {
+        let mut writer = measure(Site::EventStore, self.writer.lock()).await;
+        
+        let content = tokio::fs::read(&self.path).await?;
+        let records = read_records(&content)?;
+        let last_generation = last_generation(&records.records);
+        
+        if records.valid_len < content.len() {
+            tracing::warn!(
+                "Dropping {} bytes of a torn record at the end of {}",
+                content.len() - records.valid_len,
+                self.path.display()
+            );
+            writer.set_len(records.valid_len as u64).await?;
+        }
+        
+        let generation = last_generation + 1;
+        writer.write_all(&encode_record(&LogRecord::Generation(generation))?).await?;
+        writer.flush().await?;
+        
+        Ok(generation)
+    }
+    
+    /// Generation whose marker last precedes `offset`, 0 before the first marker
+    ///
+    /// A snapshot taken at `offset` must have been taken by that generation,
+    /// otherwise the log it was taken from has since been replaced.
+    pub async fn generation_at(&self, offset: u64) -> Result<u64> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+        
+        let content = tokio::fs::read(&self.path).await?;
+        let Some(prefix) = content.get(..usize::try_from(offset)?) else {
+            anyhow::bail!("Offset {} is past the end of {}", offset, self.path.display());
+        };
+        
+        if self.format == LogFormat::Binary {
+            if prefix.len() < HEADER_LEN {
+                return Ok(0);
+            }
+            return Ok(last_generation(&read_records(prefix)?.records));
+        }
+        Ok(prefix
+            .split(|&b| b == b'\n')
+            .filter_map(parse_generation_marker)
+            .max()
+            .unwrap_or(0))
+    }
+    
+    /// CRC32 of the log's first `offset` bytes
+    ///
+    /// A snapshot keeps it for its offset, so a log rewritten in place with
+    /// the same length and generations is still told apart.
+    pub async fn checksum_to(&self, offset: u64) -> Result<u32> {
+        let mut prefix = File::open(&self.path).await?.take(offset);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut read = 0;
+        loop {
+            let n = prefix.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            read += n as u64;
+        }
+        
+        if read < offset {
+            anyhow::bail!("Offset {} is past the end of {}", offset, self.path.display());
+        }
+        Ok(hasher.finalize())
     }
     
     /// Replay all events from the log
     pub async fn replay(&self) -> Result<Vec<TransactionRow>> {
+        self.replay_from(0).await
+    }
+    
+    /// Byte length of the log, the offset the next append will start at
+    pub async fn end_offset(&self) -> Result<u64> {
+        let _writer = measure(Site::EventStore, self.writer.lock()).await;
+        Ok(tokio::fs::metadata(&self.path).await?.len())
+    }
+    
+    /// Replay the events stored from `offset` on, an offset taken from `end_offset`
+    pub async fn replay_from(&self, offset: u64) -> Result<Vec<TransactionRow>> {
         if !self.path.exists() {
             return Ok(Vec::new());
         }
         
-        let file = File::open(&self.path).await?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
-        
-        let mut transactions = Vec::new();
+        let content = tokio::fs::read(&self.path).await?;
+        let offset = usize::try_from(offset)?;
+        if offset > content.len() {
+            anyhow::bail!(
+                "Offset {} is past the end of {} ({} bytes)",
+                offset,
+                self.path.display(),
+                content.len()
+            );
+        }
         
-        // Skip header if exists
-        if let Some(first_line) = lines.next_line().await? {
-            if !first_line.starts_with("type") {
+        decode_events(&content, offset, self.format)
+    }
+}
+
+/// Events of the log at `path`, read without opening it for writing
+///
+/// For tools that only explain a log, such as `trace` and `statement`: unlike
+/// `EventStore::new`, an empty file isn't given a header and no writer runs.
+pub async fn read_events(path: &Path) -> Result<Vec<TransactionRow>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let content = tokio::fs::read(path).await?;
+    let format = if content.starts_with(MAGIC) { LogFormat::Binary } else { LogFormat::Csv };
+    decode_events(&content, 0, format)
+}
+
+/// The events in `content` from `offset` on, a record boundary
+fn decode_events(content: &[u8], offset: usize, format: LogFormat) -> Result<Vec<TransactionRow>> {
+    if format == LogFormat::Binary {
+        return Ok(read_records_from(content, offset.max(HEADER_LEN))?
+            .records
+            .into_iter()
+            .filter_map(LogRecord::into_row)
+            .collect());
+    }
+    
+    // A header line and generation markers fail to parse like any bad line
+    Ok(String::from_utf8_lossy(&content[offset..])
+        .lines()
+        .filter_map(|line| parse_csv_line(line).ok())
+        .collect())
+}
 
-                if let Ok(tx) = parse_csv_line(&first_line) {
-                    transactions.push(tx);
+#[async_trait]
+impl EventLog for EventStore {
+    async fn append_batch(&self, txs: &[TransactionRow]) -> Result<()> {
+        EventStore::append_batch(self, txs).await
+    }
+    
+    async fn replay_from(&self, offset: u64) -> Result<Vec<TransactionRow>> {
+        EventStore::replay_from(self, offset).await
+    }
+    
+    async fn end_offset(&self) -> Result<u64> {
+        EventStore::end_offset(self).await
+    }
+    
+    async fn has_history(&self) -> Result<bool> {
+        EventStore::has_history(self).await
+    }
+    
+    async fn open_generation(&self) -> Result<u64> {
+        EventStore::open_generation(self).await
+    }
+    
+    async fn generation_at(&self, offset: u64) -> Result<Option<u64>> {
+        EventStore::generation_at(self, offset).await.map(Some)
+    }
+    
+    async fn checksum_to(&self, offset: u64) -> Result<Option<u32>> {
+        EventStore::checksum_to(self, offset).await.map(Some)
+    }
+    
+    async fn append_supersede(&self, original: &TransactionRow, replacement_tx: u32) -> Result<()> {
+        EventStore::append_supersede(self, original, replacement_tx).await
+    }
+    
+    async fn superseded(&self) -> Result<Vec<(TransactionRow, u32)>> {
+        EventStore::superseded(self).await
+    }
+    
+    async fn sync(&self) -> Result<()> {
+        EventStore::sync(self).await
+    }
+    
+    fn path(&self) -> &Path {
+        EventStore::path(self)
+    }
+    
+    fn set_durability(&mut self, durability: DurabilityPolicy) {
+        EventStore::set_durability(self, durability)
+    }
+}
+
+/// Event log kept in memory, for runs whose state is thrown away afterwards and for tests
+///
+/// Replay, traces and timelines work as with a file, without any disk writes;
+/// everything is lost with the process. Offsets count events.
+#[derive(Default)]
+pub struct InMemoryEventLog {
+    state: Mutex<InMemoryState>,
+}
+
+#[derive(Default)]
+struct InMemoryState {
+    events: Vec<TransactionRow>,
+    superseded: Vec<(TransactionRow, u32)>,
+    generation: u64,
+}
+
+impl InMemoryEventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Log starting out with `events`, as if appended by an earlier run
+    pub fn with_events(events: Vec<TransactionRow>) -> Self {
+        Self {
+            state: Mutex::new(InMemoryState {
+                events,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl EventLog for InMemoryEventLog {
+    async fn append_batch(&self, txs: &[TransactionRow]) -> Result<()> {
+        measure(Site::EventStore, self.state.lock()).await.events.extend_from_slice(txs);
+        Ok(())
+    }
+    
+    async fn replay_from(&self, offset: u64) -> Result<Vec<TransactionRow>> {
+        let state = self.state.lock().await;
+        let offset = usize::try_from(offset)?;
+        match state.events.get(offset..) {
+            Some(events) => Ok(events.to_vec()),
+            None => anyhow::bail!("Offset {} is past the end of the in-memory log ({} events)", offset, state.events.len()),
+        }
+    }
+    
+    async fn end_offset(&self) -> Result<u64> {
+        Ok(self.state.lock().await.events.len() as u64)
+    }
+    
+    async fn has_history(&self) -> Result<bool> {
+        let state = self.state.lock().await;
+        Ok(state.generation > 0 || !state.events.is_empty())
+    }
+    
+    async fn open_generation(&self) -> Result<u64> {
+        let mut state = self.state.lock().await;
+        state.generation += 1;
+        Ok(state.generation)
+    }
+    
+    async fn append_supersede(&self, original: &TransactionRow, replacement_tx: u32) -> Result<()> {
+        self.state.lock().await.superseded.push((original.clone(), replacement_tx));
+        Ok(())
+    }
+    
+    async fn superseded(&self) -> Result<Vec<(TransactionRow, u32)>> {
+        Ok(self.state.lock().await.superseded.clone())
+    }
+    
+    async fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+    
+    fn path(&self) -> &Path {
+        Path::new(":memory:")
+    }
+}
+
+/// Prefix of the line delimiting writer generations, never a valid event
+const GENERATION_MARKER: &str = "#generation,";
+
+fn parse_generation_marker(line: &[u8]) -> Option<u64> {
+    let line = std::str::from_utf8(line).ok()?;
+    line.trim().strip_prefix(GENERATION_MARKER)?.parse().ok()
+}
+
+/// Prefix of a supersede mark, followed by the replacement's tx id and the original row
+const SUPERSEDE_MARKER: &str = "#superseded,";
+
+fn parse_supersede_marker(line: &str) -> Option<(TransactionRow, u32)> {
+    let (replacement_tx, original) = line.trim().strip_prefix(SUPERSEDE_MARKER)?.split_once(',')?;
+    Some((parse_csv_line(original).ok()?, replacement_tx.parse().ok()?))
+}
+
+fn csv_line(tx: &TransactionRow) -> String {
+    let mut line = format!(
+        "{},{},{},{}",
+        tx.tx_type_str(),
+        tx.client,
+        tx.tx,
+        tx.amount.map(|a| a.to_string()).unwrap_or_default()
+    );
+    // Transfers carry their receiving client in a fifth column
+    if let Some(to) = tx.to {
+        line.push_str(&format!(",{}", to));
+    }
+    line.push('\n');
+    line
+}
+
+/// Start the task writing queued appends in batches, see `EventStore::append`
+fn spawn_writer(writer: Arc<Mutex<File>>, durability: DurabilityPolicy) -> mpsc::Sender<PendingAppend> {
+    let (appends, mut queue) = mpsc::channel::<PendingAppend>(MAX_BATCH_RECORDS);
+    let window = match durability {
+        DurabilityPolicy::GroupCommit(window) => window,
+        _ => Duration::ZERO,
+    };
+    
+    tokio::spawn(async move {
+        let mut batch = Vec::with_capacity(MAX_BATCH_RECORDS);
+        while let Some(first) = queue.recv().await {
+            batch.push(first);
+            
+            // Without a window only appends already queued join, a lone append isn't held back
+            let deadline = Instant::now() + window;
+            while batch.len() < MAX_BATCH_RECORDS {
+                let next = if window.is_zero() {
+                    queue.try_recv().ok()
+                } else {
+                    tokio::time::timeout_at(deadline, queue.recv()).await.ok().flatten()
+                };
+                match next {
+                    Some(pending) => batch.push(pending),
+                    None => break,
                 }
             }
-        }
-        
-        while let Some(line) = lines.next_line().await? {
-            if let Ok(tx) = parse_csv_line(&line) {
-                transactions.push(tx);
+            
+            let result = write_batch(&writer, &batch, durability).await.map_err(|e| e.to_string());
+            for pending in batch.drain(..) {
+                let _ = pending.reply.send(result.clone());
             }
         }
-        
-        Ok(transactions)
+    });
+    
+    appends
+}
+
+async fn write_batch(writer: &Mutex<File>, batch: &[PendingAppend], durability: DurabilityPolicy) -> Result<()> {
+    let bytes: Vec<u8> = batch.iter().flat_map(|pending| pending.bytes.iter().copied()).collect();
+    
+    let mut writer = measure(Site::EventStore, writer.lock()).await;
+    writer.write_all(&bytes).await?;
+    
+    // tokio's File completes writes in the background, wait so readers of the log see these events
+    writer.flush().await?;
+    
+    if matches!(durability, DurabilityPolicy::PerWrite | DurabilityPolicy::GroupCommit(_)) {
+        writer.sync_data().await?;
     }
+    Ok(())
+}
+
+/// fsync the log every `every` until the store is dropped
+fn spawn_interval_sync(writer: Weak<Mutex<File>>, every: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        loop {
+            ticker.tick().await;
+            let Some(writer) = writer.upgrade() else { break };
+            let result = writer.lock().await.sync_data().await;
+            if let Err(e) = result {
+                tracing::error!("Failed to sync event log: {}", e);
+            }
+        }
+    })
 }
 
 fn parse_csv_line(line: &str) -> Result<TransactionRow> {
-    use crate::models::parse_transaction_type;
-    
     let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
     
     if parts.len() < 3 {
@@ -93,11 +714,186 @@ fn parse_csv_line(line: &str) -> Result<TransactionRow> {
     } else {
         None
     };
+    let to = if parts.len() > 4 && !parts[4].is_empty() {
+        Some(parts[4].parse()?)
+    } else {
+        None
+    };
     
     Ok(TransactionRow {
         tx_type,
         client,
         tx,
         amount,
+        to,
+    })
+}
+
+/// Start of every binary log, followed by the format version
+const MAGIC: &[u8; 4] = b"PELG";
+const VERSION: u16 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+/// Payload length and CRC32 in front of every record
+const RECORD_PREFIX_LEN: usize = 8;
+/// Longest payload a record may claim, a longer length prefix is corrupt
+const MAX_RECORD_LEN: usize = 1 << 20;
+
+fn binary_header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header
+}
+
+async fn detect_format(path: &Path) -> Result<LogFormat> {
+    let mut head = Vec::with_capacity(MAGIC.len());
+    File::open(path).await?.take(MAGIC.len() as u64).read_to_end(&mut head).await?;
+    
+    Ok(if head == MAGIC {
+        LogFormat::Binary
+    } else {
+        LogFormat::Csv
+    })
+}
+
+/// One binary log entry
+///
+/// Amounts are kept as text, producers' amount format rules don't apply to the log.
+#[derive(Debug, Serialize, Deserialize)]
+enum LogRecord {
+    Event {
+        tx_type: String,
+        client: u16,
+        tx: u32,
+        amount: Option<String>,
+        to: Option<u16>,
+    },
+    /// Start of a writer generation, see `EventStore::open_generation`
+    Generation(u64),
+    /// A rejected row replaced by a corrected one, see `EventLog::append_supersede`
+    Supersede {
+        original: Box<LogRecord>,
+        replacement_tx: u32,
+    },
+}
+
+impl From<&TransactionRow> for LogRecord {
+    fn from(tx: &TransactionRow) -> Self {
+        LogRecord::Event {
+            tx_type: tx.tx_type_str().to_string(),
+            client: tx.client,
+            tx: tx.tx,
+            amount: tx.amount.map(|a| a.to_string()),
+            to: tx.to,
+        }
+    }
+}
+
+impl LogRecord {
+    fn into_row(self) -> Option<TransactionRow> {
+        let LogRecord::Event { tx_type, client, tx, amount, to } = self else {
+            return None;
+        };
+        
+        let amount = match amount {
+            Some(amount) => Some(Decimal::from_str(&amount).ok()?),
+            None => None,
+        };
+        Some(TransactionRow {
+            tx_type: parse_transaction_type(&tx_type).ok()?,
+            client,
+            tx,
+            amount,
+            to,
+        })
+    }
+}
+
+/// Highest generation marked among the records, 0 without a marker
+fn last_generation(records: &[LogRecord]) -> u64 {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            LogRecord::Generation(generation) => Some(*generation),
+            LogRecord::Event { .. } | LogRecord::Supersede { .. } => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn encode_record(record: &LogRecord) -> Result<Vec<u8>> {
+    let payload = bincode::serialize(record)?;
+    let len = (payload.len() as u32).to_le_bytes();
+    
+    let mut bytes = Vec::with_capacity(RECORD_PREFIX_LEN + payload.len());
+    bytes.extend_from_slice(&len);
+    bytes.extend_from_slice(&record_crc(&len, &payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Checksum over the length prefix and the payload, so a bad length is caught too
+fn record_crc(len: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(len);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Payload and end of the record at `offset`, if one is complete and checks out
+fn record_at(content: &[u8], offset: usize) -> Option<(&[u8], usize)> {
+    let prefix = content.get(offset..offset + RECORD_PREFIX_LEN)?;
+    let len = u32::from_le_bytes(prefix[..4].try_into().ok()?) as usize;
+    if len > MAX_RECORD_LEN {
+        return None;
+    }
+    
+    let start = offset + RECORD_PREFIX_LEN;
+    let payload = content.get(start..start + len)?;
+    let crc = u32::from_le_bytes(prefix[4..].try_into().ok()?);
+    (record_crc(&prefix[..4], payload) == crc).then_some((payload, start + len))
+}
+
+struct DecodedLog {
+    records: Vec<LogRecord>,
+    /// Bytes up to the end of the last complete record
+    valid_len: usize,
+}
+
+/// Decode a binary log, stopping at a torn last record
+///
+/// A bad record is only a torn tail when no good record follows it; one in the
+/// middle of the log is corruption and fails the read rather than being skipped.
+fn read_records(content: &[u8]) -> Result<DecodedLog> {
+    read_records_from(content, HEADER_LEN)
+}
+
+/// Decode the records of a binary log starting at `offset`, a record boundary
+fn read_records_from(content: &[u8], offset: usize) -> Result<DecodedLog> {
+    if content.len() < HEADER_LEN || &content[..MAGIC.len()] != MAGIC {
+        anyhow::bail!("Not a binary event log");
+    }
+    let version = u16::from_le_bytes([content[4], content[5]]);
+    if version != VERSION {
+        anyhow::bail!("Unsupported event log version {}", version);
+    }
+    
+    let mut records = Vec::new();
+    let mut offset = offset;
+    while offset < content.len() {
+        let Some((payload, end)) = record_at(content, offset) else {
+            if (offset + 1..content.len()).any(|start| record_at(content, start).is_some()) {
+                anyhow::bail!("Corrupt event log record at byte {}, records after it are intact", offset);
+            }
+            break;
+        };
+        let record = bincode::deserialize(payload)
+            .with_context(|| format!("Undecodable event log record at byte {}", offset))?;
+        records.push(record);
+        offset = end;
+    }
+    
+    Ok(DecodedLog {
+        records,
+        valid_len: offset,
     })
 }
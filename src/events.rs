@@ -0,0 +1,140 @@
+use crate::alerts::BalanceAlert;
+use crate::errors::ProcessingError;
+use crate::models::{Account, TransactionRow, TransactionType};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events a subscriber may fall behind by before it misses some
+pub const DEFAULT_EVENT_BUS_CAPACITY: usize = 4_096;
+
+/// What happened to a submission, as seen by subsystems outside the hot path
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DomainEvent {
+    TransactionApplied {
+        tx: u32,
+        client: u16,
+        tx_type: TransactionType,
+        amount: Option<Decimal>,
+        to: Option<u16>,
+    },
+    TransactionRejected {
+        tx: u32,
+        client: u16,
+        tx_type: TransactionType,
+        /// Stable error code, as in acks and problem details
+        code: &'static str,
+    },
+    /// Follows the `TransactionApplied` of the dispute
+    DisputeOpened { client: u16, tx: u32 },
+    /// A chargeback of `tx` locked the account, follows its `TransactionApplied`
+    AccountLocked { client: u16, tx: u32 },
+    /// A change put the account in breach of one of its alert rules, published as the
+    /// actor applies it, before the change's `TransactionApplied`
+    BalanceAlert(BalanceAlert),
+}
+
+/// Broadcast of domain events to in-process subscribers
+///
+/// Publishing never waits: without subscribers events are dropped, and a
+/// subscriber more than the capacity behind gets `RecvError::Lagged` and
+/// continues from the oldest event still held.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_BUS_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::Sender::new(capacity),
+        }
+    }
+
+    /// Events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    pub fn publish(&self, event: DomainEvent) {
+        // No subscribers is not an error, nobody asked
+        let _ = self.sender.send(event);
+    }
+
+    /// Publish the events of `tx` having `outcome`
+    pub fn publish_outcome(&self, tx: &TransactionRow, outcome: &Result<(), ProcessingError>) {
+        if self.subscribers() == 0 {
+            return;
+        }
+
+        match outcome {
+            Ok(()) => {
+                self.publish(DomainEvent::TransactionApplied {
+                    tx: tx.tx,
+                    client: tx.client,
+                    tx_type: tx.tx_type.clone(),
+                    amount: tx.amount,
+                    to: tx.to,
+                });
+                match tx.tx_type {
+                    TransactionType::Dispute => self.publish(DomainEvent::DisputeOpened { client: tx.client, tx: tx.tx }),
+                    TransactionType::Chargeback => self.publish(DomainEvent::AccountLocked { client: tx.client, tx: tx.tx }),
+                    _ => {}
+                }
+            }
+            Err(e) => self.publish(DomainEvent::TransactionRejected {
+                tx: tx.tx,
+                client: tx.client,
+                tx_type: tx.tx_type.clone(),
+                code: e.code(),
+            }),
+        }
+    }
+}
+
+/// Account states the actors publish after every change they apply
+///
+/// The change notifications behind live balance pushes. Like the bus,
+/// publishing never waits and a subscriber that falls too far behind gets
+/// `RecvError::Lagged`; the state it then receives next is still current.
+#[derive(Clone)]
+pub struct AccountUpdates {
+    sender: broadcast::Sender<Account>,
+}
+
+impl Default for AccountUpdates {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_BUS_CAPACITY)
+    }
+}
+
+impl AccountUpdates {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::Sender::new(capacity),
+        }
+    }
+
+    /// States published from now on, of every client
+    pub fn subscribe(&self) -> broadcast::Receiver<Account> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, account: &Account) {
+        // Skip the clone on the hot path while nobody listens
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(account.clone());
+        }
+    }
+}
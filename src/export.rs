@@ -0,0 +1,116 @@
+use crate::amount::AmountUnits;
+use crate::csv_io::write_account_stream;
+use crate::models::AccountOutput;
+use crate::scalable_engine::ScalableEngine;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Name of the manifest written next to the partition files
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Where a partitioned export goes and how many files it is split into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportConfig {
+    pub dir: PathBuf,
+    /// Client id ranges, one file each, up to one per client id
+    pub partitions: usize,
+}
+
+/// One partition file as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionEntry {
+    /// File name, relative to the manifest
+    pub file: String,
+    pub first_client: u16,
+    pub last_client: u16,
+    /// Accounts in the file, not counting the header line
+    pub rows: u64,
+    pub bytes: u64,
+    /// CRC-32 of the file's bytes, as 8 hex digits
+    pub crc32: String,
+}
+
+/// What a loader reads first: every partition file, its client range, size and checksum
+///
+/// Written after all the partitions, so a manifest is only there once they are complete.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionManifest {
+    pub partitions: Vec<PartitionEntry>,
+    /// Accounts across all partitions
+    pub rows: u64,
+}
+
+/// Client id ranges of `partitions` equal slices of the whole id space, in order
+///
+/// The ranges only depend on the count, so an account lands in the same
+/// partition in every export with that count.
+pub fn client_ranges(partitions: usize) -> Vec<RangeInclusive<u16>> {
+    let space = u16::MAX as usize + 1;
+    let partitions = partitions.clamp(1, space);
+    // Sizes differ by one at most, every range holds at least one id
+    let start = |i: usize| i * space / partitions;
+    (0..partitions)
+        .map(|i| start(i) as u16..=(start(i + 1) - 1) as u16)
+        .collect()
+}
+
+/// Write `accounts`, sorted by client, as one CSV file per client range in `dir`, then the manifest
+pub async fn write_partitions(
+    accounts: Vec<AccountOutput>,
+    dir: &Path,
+    partitions: usize,
+    units: AmountUnits,
+) -> Result<PartitionManifest> {
+    if partitions == 0 {
+        anyhow::bail!("An export needs at least one partition");
+    }
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("creating export directory {}", dir.display()))?;
+
+    let mut entries = Vec::new();
+    let mut accounts = accounts.into_iter().peekable();
+    for (index, range) in client_ranges(partitions).into_iter().enumerate() {
+        let mut rows = Vec::new();
+        while let Some(account) = accounts.next_if(|account| account.client <= *range.end()) {
+            rows.push(account);
+        }
+        let count = rows.len() as u64;
+
+        // Partitions are small enough to checksum in memory before they hit the disk
+        let mut bytes = Vec::new();
+        write_account_stream(&mut bytes, futures::stream::iter(rows), units).await?;
+        let file = format!("part-{:05}.csv", index);
+        tokio::fs::write(dir.join(&file), &bytes)
+            .await
+            .with_context(|| format!("writing {}", dir.join(&file).display()))?;
+
+        entries.push(PartitionEntry {
+            file,
+            first_client: *range.start(),
+            last_client: *range.end(),
+            rows: count,
+            bytes: bytes.len() as u64,
+            crc32: format!("{:08x}", crc32fast::hash(&bytes)),
+        });
+    }
+
+    let manifest = PartitionManifest {
+        rows: entries.iter().map(|entry| entry.rows).sum(),
+        partitions: entries,
+    };
+    // Renamed into place, a loader never reads half a manifest
+    let temp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    tokio::fs::write(&temp, serde_json::to_vec_pretty(&manifest)?).await?;
+    tokio::fs::rename(&temp, dir.join(MANIFEST_FILE)).await?;
+    Ok(manifest)
+}
+
+/// Export the engine's current accounts as `config` says
+pub async fn export_accounts(engine: &ScalableEngine, config: &ExportConfig, units: AmountUnits) -> Result<PartitionManifest> {
+    let mut accounts: Vec<AccountOutput> = engine.get_accounts().await.iter().map(AccountOutput::from).collect();
+    accounts.sort_by_key(|account| account.client);
+    write_partitions(accounts, &config.dir, config.partitions, units).await
+}
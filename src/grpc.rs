@@ -0,0 +1,280 @@
+use crate::amount::{self, AmountFormat, AmountUnits};
+use crate::auth::{ClientScope, API_KEY_HEADER};
+use crate::errors::ProcessingError;
+use crate::models::{parse_transaction_type, Account, AccountOutput, TransactionRow};
+use crate::scalable_engine::{ScalableEngine, PREFETCH_WINDOW};
+use crate::server::settle_live_row;
+use anyhow::Result;
+use futures::StreamExt;
+use prost::Message;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tonic::codegen::Bytes;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+/// Messages, client and server generated from `proto/payments.proto`
+pub mod proto {
+    tonic::include_proto!("payments.v1");
+}
+
+use proto::payments_engine_server::{PaymentsEngine, PaymentsEngineServer};
+
+/// The engine behind the `PaymentsEngine` gRPC service
+///
+/// Submitted transactions go through the same live path as the wire
+/// protocol's: they wait for a backfill, repeats of it are acknowledged,
+/// refused duplicates are counted under the peer's address and the fixture
+/// recorder sees them. Rows for clients outside the scope of the call's
+/// `x-api-key` metadata are refused as unauthorized.
+pub struct GrpcService {
+    engine: Arc<ScalableEngine>,
+}
+
+impl GrpcService {
+    pub fn new(engine: Arc<ScalableEngine>) -> Self {
+        Self { engine }
+    }
+
+    pub fn into_server(self) -> PaymentsEngineServer<Self> {
+        PaymentsEngineServer::new(self)
+    }
+}
+
+/// Serve the gRPC service on `bind` until `shutdown` completes
+pub async fn serve(
+    bind: String,
+    engine: Arc<ScalableEngine>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let listener = TcpListener::bind(&bind).await?;
+    tracing::info!("gRPC service listening on {}", bind);
+    serve_on(listener, engine, shutdown).await
+}
+
+/// Serve the gRPC service on an already bound listener
+pub async fn serve_on(
+    listener: TcpListener,
+    engine: Arc<ScalableEngine>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let incoming = TcpIncoming::from_listener(listener, true, None).map_err(|e| anyhow::anyhow!(e))?;
+    tonic::transport::Server::builder()
+        .add_service(GrpcService::new(engine).into_server())
+        .serve_with_incoming_shutdown(incoming, shutdown)
+        .await?;
+    Ok(())
+}
+
+/// gRPC status code of a processing error, fixed per variant like the HTTP status
+pub fn code_of(error: &ProcessingError) -> Code {
+    if error.is_transient() {
+        return Code::Unavailable;
+    }
+    match error {
+        ProcessingError::MissingAmount
+        | ProcessingError::InvalidAmount
+        | ProcessingError::InvalidTransfer
+        | ProcessingError::UnsupportedTransactionType => Code::InvalidArgument,
+        ProcessingError::AuthorizationDenied
+        | ProcessingError::Unauthorized
+        | ProcessingError::ReservedClient => Code::PermissionDenied,
+        ProcessingError::TransactionNotFound | ProcessingError::AccountNotFound => Code::NotFound,
+        ProcessingError::DuplicateTransaction => Code::AlreadyExists,
+        ProcessingError::IdSpaceExhausted => Code::ResourceExhausted,
+        ProcessingError::TransferIncomplete => Code::Internal,
+        _ => Code::FailedPrecondition,
+    }
+}
+
+/// Failed call carrying an `ErrorDetail` with the error's stable code
+fn error_status(code: Code, detail: proto::ErrorDetail, message: String) -> Status {
+    Status::with_details(code, message, Bytes::from(detail.encode_to_vec()))
+}
+
+fn processing_status(error: &ProcessingError, tx: Option<u32>, client: Option<u16>) -> Status {
+    let detail = proto::ErrorDetail {
+        code: error.code().to_string(),
+        transient: error.is_transient(),
+        tx,
+        client: client.map(u32::from),
+    };
+    error_status(code_of(error), detail, error.to_string())
+}
+
+fn malformed_status(tx: u32, reason: String) -> Status {
+    let detail = proto::ErrorDetail {
+        code: "malformed".to_string(),
+        transient: false,
+        tx: Some(tx),
+        client: None,
+    };
+    error_status(Code::InvalidArgument, detail, reason)
+}
+
+/// Row of a submitted transaction, its amount read under `format`
+fn decode(transaction: proto::Transaction, format: AmountFormat) -> Result<TransactionRow, String> {
+    let client = u16::try_from(transaction.client).map_err(|_| format!("client {} out of range", transaction.client))?;
+    let to = transaction
+        .to
+        .map(|to| u16::try_from(to).map_err(|_| format!("client {} out of range", to)))
+        .transpose()?;
+    let amount = transaction
+        .amount
+        .map(|raw| amount::parse_amount(&raw, format))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let row = TransactionRow {
+        tx_type: parse_transaction_type(&transaction.r#type).map_err(|e| e.to_string())?,
+        client,
+        tx: transaction.tx,
+        amount,
+        to,
+    };
+    // Amounts in minor units are a connection setting of the wire protocol, gRPC always sends decimals
+    AmountUnits::Decimal.decode_row(row).map_err(|e| e.to_string())
+}
+
+fn encode(account: &Account) -> proto::Account {
+    let account = AccountOutput::from(account);
+    proto::Account {
+        client: u32::from(account.client),
+        available: format!("{:.4}", account.available),
+        held: format!("{:.4}", account.held),
+        total: format!("{:.4}", account.total),
+        locked: account.locked,
+    }
+}
+
+/// Clients the call's `x-api-key` metadata may submit for
+fn scope_of<T>(engine: &ScalableEngine, request: &Request<T>) -> ClientScope {
+    let key = request.metadata().get(API_KEY_HEADER).and_then(|key| key.to_str().ok());
+    engine.access(key)
+}
+
+/// Peer address, what duplicates are counted under
+fn source_of<T>(request: &Request<T>) -> String {
+    request
+        .remote_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "grpc".to_string())
+}
+
+#[tonic::async_trait]
+impl PaymentsEngine for GrpcService {
+    async fn submit_transaction(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<proto::SubmitReply>, Status> {
+        let source = source_of(&request);
+        let scope = scope_of(&self.engine, &request);
+        let transaction = request.into_inner();
+        let tx = transaction.tx;
+        let row = decode(transaction, self.engine.amount_format()).map_err(|reason| malformed_status(tx, reason))?;
+        scope
+            .check(&row)
+            .map_err(|e| processing_status(&e, Some(row.tx), Some(row.client)))?;
+
+        // Nothing live may land before the backfill's rows
+        self.engine
+            .ingestion()
+            .wait_live()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+
+        self.engine.prefetch(std::slice::from_ref(&row)).await;
+        let outcome = self.engine.process(row.clone()).await;
+        settle_live_row(&self.engine, &source, &row, outcome)
+            .await
+            .map_err(|e| processing_status(&e, Some(row.tx), Some(row.client)))?;
+
+        Ok(Response::new(proto::SubmitReply { tx: row.tx }))
+    }
+
+    async fn submit_stream(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<proto::StreamSummary>, Status> {
+        let source = source_of(&request);
+        let scope = scope_of(&self.engine, &request);
+        self.engine
+            .ingestion()
+            .wait_live()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+
+        let mut summary = proto::StreamSummary::default();
+        let mut chunks = request.into_inner().ready_chunks(PREFETCH_WINDOW);
+        while let Some(chunk) = chunks.next().await {
+            // A broken stream ends the call, the rows before it stay applied
+            let chunk = chunk.into_iter().collect::<Result<Vec<_>, Status>>()?;
+            let decoded: Vec<(u32, Result<TransactionRow, String>)> = chunk
+                .into_iter()
+                .map(|transaction| (transaction.tx, decode(transaction, self.engine.amount_format())))
+                .collect();
+            let rows: Vec<TransactionRow> = decoded
+                .iter()
+                .filter_map(|(_, row)| row.as_ref().ok())
+                .filter(|row| scope.permits(row.client))
+                .cloned()
+                .collect();
+
+            // Same path as a wire connection's chunk: prefetch, then each client's rows in order
+            self.engine.prefetch(&rows).await;
+            let mut outcomes = self.engine.process_batch(rows).await.into_iter();
+            for (tx, row) in decoded {
+                let rejection = match row {
+                    Ok(row) if !scope.permits(row.client) => {
+                        let e = ProcessingError::Unauthorized;
+                        Some((e.code().to_string(), e.to_string()))
+                    }
+                    Ok(row) => {
+                        let outcome = outcomes.next().expect("one outcome per decoded row");
+                        match settle_live_row(&self.engine, &source, &row, outcome).await {
+                            Ok(()) => None,
+                            Err(e) => Some((e.code().to_string(), e.to_string())),
+                        }
+                    }
+                    Err(reason) => Some(("malformed".to_string(), reason)),
+                };
+                match rejection {
+                    None => summary.applied += 1,
+                    Some((code, message)) => summary.rejections.push(proto::Rejection { tx, code, message }),
+                }
+            }
+        }
+
+        Ok(Response::new(summary))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::GetAccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let request = request.into_inner();
+        let unknown = || processing_status(&ProcessingError::AccountNotFound, None, u16::try_from(request.client).ok());
+        let client = u16::try_from(request.client).map_err(|_| unknown())?;
+
+        let account = match request.consistency() {
+            proto::Consistency::Strong => self.engine.get_account(client).await,
+            proto::Consistency::Eventual => self.engine.get_account_eventual(client),
+        };
+        account.map(|account| Response::new(encode(&account))).ok_or_else(unknown)
+    }
+
+    async fn list_accounts(
+        &self,
+        request: Request<proto::ListAccountsRequest>,
+    ) -> Result<Response<proto::ListAccountsReply>, Status> {
+        let mut accounts = match request.into_inner().consistency() {
+            proto::Consistency::Strong => self.engine.get_accounts().await,
+            proto::Consistency::Eventual => self.engine.get_accounts_eventual(),
+        };
+        accounts.sort_by_key(|account| account.client);
+
+        Ok(Response::new(proto::ListAccountsReply {
+            accounts: accounts.iter().map(encode).collect(),
+        }))
+    }
+}
@@ -0,0 +1,120 @@
+use crate::errors::ProcessingError;
+use crate::hot_store::HotStore;
+use crate::models::{Account, TransactionRow, TransactionType};
+use crate::storage::StoredTransaction;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Account state a handler may touch, borrowed from the owning actor
+pub struct HandlerContext<'a> {
+    pub account: &'a mut Account,
+    hot_transactions: &'a mut HotStore,
+    now: SystemTime,
+}
+
+impl<'a> HandlerContext<'a> {
+    pub(crate) fn new(
+        account: &'a mut Account,
+        hot_transactions: &'a mut HotStore,
+        now: SystemTime,
+    ) -> Self {
+        Self {
+            account,
+            hot_transactions,
+            now,
+        }
+    }
+
+    /// Look up a transaction still held in hot storage
+    pub fn get_transaction(&self, tx_id: u32) -> Option<&StoredTransaction> {
+        self.hot_transactions.get(&tx_id)
+    }
+
+    /// Remember a transaction so later events can reference it
+    pub fn store_transaction(&mut self, tx_id: u32, tx_type: TransactionType, amount: Decimal) {
+        self.hot_transactions.insert(
+            tx_id,
+            StoredTransaction {
+                client: self.account.client,
+                tx_type,
+                amount,
+                disputed: false,
+                held_amount: None,
+                created_at: self.now,
+            },
+        );
+    }
+}
+
+/// Plug-in implementation of a custom transaction type
+///
+/// `validate` runs in the engine before the row is routed, `apply` and
+/// `replay` run inside the owning account actor.
+pub trait TransactionHandler: Send + Sync {
+    /// Type string this handler is registered under, as it appears in input and the event log
+    fn type_name(&self) -> &str;
+
+    /// Whether rows of this type introduce a new, globally unique tx id
+    fn creates_tx(&self) -> bool {
+        false
+    }
+
+    /// Stateless checks on the row itself
+    fn validate(&self, _tx: &TransactionRow) -> Result<(), ProcessingError> {
+        Ok(())
+    }
+
+    /// Apply a live row to the account
+    fn apply(&self, ctx: &mut HandlerContext<'_>, tx: &TransactionRow) -> Result<(), ProcessingError>;
+
+    /// Re-apply a row from the event log on startup
+    fn replay(&self, ctx: &mut HandlerContext<'_>, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        self.apply(ctx, tx)
+    }
+}
+
+/// Handlers for custom transaction types, shared by the engine and all actors
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: RwLock<HashMap<String, Arc<dyn TransactionHandler>>>,
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler, replacing any previous one with the same type name
+    pub fn register(&self, handler: Arc<dyn TransactionHandler>) {
+        let name = handler.type_name().to_lowercase();
+        self.handlers.write().unwrap().insert(name, handler);
+    }
+
+    pub fn get(&self, type_name: &str) -> Option<Arc<dyn TransactionHandler>> {
+        self.handlers.read().unwrap().get(type_name).cloned()
+    }
+
+    /// Whether a row of this type introduces a new tx id
+    pub fn creates_tx(&self, tx_type: &TransactionType) -> bool {
+        match tx_type {
+            TransactionType::Custom(name) => self
+                .get(name)
+                .map(|handler| handler.creates_tx())
+                .unwrap_or(false),
+            builtin => builtin.creates_tx(),
+        }
+    }
+
+    /// Run the handler's stateless validation, built-in types always pass
+    pub fn validate(&self, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        match &tx.tx_type {
+            TransactionType::Custom(name) => self
+                .get(name)
+                .ok_or(ProcessingError::UnsupportedTransactionType)?
+                .validate(tx),
+            _ => Ok(()),
+        }
+    }
+}
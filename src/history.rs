@@ -0,0 +1,109 @@
+use crate::storage::{StorageTier, StoredTransaction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+/// Page size when a query names none
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Largest page a query can ask for
+pub const MAX_PAGE_SIZE: usize = 1_000;
+
+/// Window into a client's transactions ordered by tx id
+///
+/// Pages are keyed by tx id rather than offset, so transactions stored
+/// between two requests never shift a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    /// Only tx ids above this, the `next` of the previous page
+    #[serde(default)]
+    pub after: Option<u32>,
+    #[serde(default = "default_page_size")]
+    pub limit: usize,
+}
+
+fn default_page_size() -> usize {
+    DEFAULT_PAGE_SIZE
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            after: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl Pagination {
+    pub fn first(limit: usize) -> Self {
+        Self { after: None, limit }
+    }
+
+    /// The page following `after`
+    pub fn after(after: u32, limit: usize) -> Self {
+        Self { after: Some(after), limit }
+    }
+
+    /// Requested size clamped to `1..=MAX_PAGE_SIZE`
+    pub fn limit(&self) -> usize {
+        self.limit.clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// One stored transaction and the tier it was read from
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct HistoryEntry {
+    pub tx: u32,
+    pub tier: StorageTier,
+    #[serde(flatten)]
+    pub transaction: StoredTransaction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TransactionPage {
+    pub transactions: Vec<HistoryEntry>,
+    /// Cursor of the next page, `None` on the last one
+    pub next: Option<u32>,
+}
+
+/// Merge a client's hot transactions with a page listed from cold storage
+///
+/// `cold` holds at most `page.limit()` ids in order. A hot copy wins over a
+/// cold one of the same id, it is the newer. When cold storage filled the
+/// page, hot ids past its last one wait for the next page, cold ids between
+/// them were not read yet.
+pub fn merge_page(
+    hot: Vec<(u32, StoredTransaction)>,
+    cold: Vec<(u32, StoredTransaction)>,
+    page: Pagination,
+) -> TransactionPage {
+    let limit = page.limit();
+    let after = |tx_id: &u32| page.after.is_none_or(|after| *tx_id > after);
+    let cold_bound = if cold.len() >= limit {
+        cold.last().map(|(tx_id, _)| *tx_id)
+    } else {
+        None
+    };
+
+    let mut merged = BTreeMap::new();
+    for (tx, transaction) in cold.into_iter().filter(|(tx_id, _)| after(tx_id)) {
+        merged.insert(tx, HistoryEntry { tx, tier: StorageTier::Cold, transaction });
+    }
+    let hot = hot
+        .into_iter()
+        .filter(|(tx_id, _)| after(tx_id) && cold_bound.is_none_or(|bound| *tx_id <= bound));
+    for (tx, transaction) in hot {
+        merged.insert(tx, HistoryEntry { tx, tier: StorageTier::Hot, transaction });
+    }
+
+    let more = merged.len() > limit || cold_bound.is_some();
+    let transactions: Vec<HistoryEntry> = merged.into_values().take(limit).collect();
+    let next = if more {
+        transactions.last().map(|entry| entry.tx)
+    } else {
+        None
+    };
+
+    TransactionPage { transactions, next }
+}
@@ -0,0 +1,189 @@
+use crate::models::TransactionType;
+use crate::storage::StoredTransaction;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+/// One hot-storage entry as shown to operators
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct HotTransaction {
+    pub tx: u32,
+    pub tx_type: TransactionType,
+    pub amount: Decimal,
+    pub age_secs: u64,
+    pub disputed: bool,
+}
+
+/// Number of hot transactions a client's actor holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct HotStorageSize {
+    pub client: u16,
+    pub transactions: usize,
+}
+
+/// Width of a hot-storage time bucket
+pub const DEFAULT_BUCKET_WIDTH: Duration = Duration::from_secs(24 * 3600);
+
+/// Actor-local hot storage partitioned into time buckets by `created_at`
+///
+/// Lookups go through a tx-id index straight to the owning bucket, and
+/// migration only visits the expired range instead of every transaction.
+#[derive(Clone)]
+pub struct HotStore {
+    bucket_secs: u64,
+    buckets: BTreeMap<u64, HashMap<u32, StoredTransaction>>,
+    index: HashMap<u32, u64>,
+}
+
+impl HotStore {
+    pub fn new(bucket_width: Duration) -> Self {
+        Self {
+            bucket_secs: bucket_width.as_secs().max(1),
+            buckets: BTreeMap::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn bucket_of(&self, time: SystemTime) -> u64 {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        secs / self.bucket_secs
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains_key(&self, tx_id: &u32) -> bool {
+        self.index.contains_key(tx_id)
+    }
+
+    pub fn get(&self, tx_id: &u32) -> Option<&StoredTransaction> {
+        let bucket = self.index.get(tx_id)?;
+        self.buckets.get(bucket)?.get(tx_id)
+    }
+
+    pub fn get_mut(&mut self, tx_id: &u32) -> Option<&mut StoredTransaction> {
+        let bucket = self.index.get(tx_id)?;
+        self.buckets.get_mut(bucket)?.get_mut(tx_id)
+    }
+
+    pub fn insert(&mut self, tx_id: u32, tx: StoredTransaction) {
+        // Replacing an entry may move it to another bucket
+        self.remove(&tx_id);
+
+        let bucket = self.bucket_of(tx.created_at);
+        self.buckets.entry(bucket).or_default().insert(tx_id, tx);
+        self.index.insert(tx_id, bucket);
+    }
+
+    pub fn remove(&mut self, tx_id: &u32) -> Option<StoredTransaction> {
+        let bucket = self.index.remove(tx_id)?;
+        let entries = self.buckets.get_mut(&bucket)?;
+        let removed = entries.remove(tx_id);
+        if entries.is_empty() {
+            self.buckets.remove(&bucket);
+        }
+        removed
+    }
+
+    /// Remove and return every transaction, e.g. to hand them to cold storage on shutdown
+    pub fn drain_all(&mut self) -> Vec<(u32, StoredTransaction)> {
+        self.index.clear();
+        std::mem::take(&mut self.buckets)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// Copy of every entry, e.g. for an engine snapshot
+    pub fn all(&self) -> Vec<(u32, StoredTransaction)> {
+        self.buckets
+            .values()
+            .flatten()
+            .map(|(tx_id, tx)| (*tx_id, tx.clone()))
+            .collect()
+    }
+
+    /// Every entry ordered by tx id, ages measured at `now`
+    pub fn entries(&self, now: SystemTime) -> Vec<HotTransaction> {
+        let mut entries: Vec<HotTransaction> = self
+            .buckets
+            .values()
+            .flatten()
+            .map(|(tx_id, tx)| HotTransaction {
+                tx: *tx_id,
+                tx_type: tx.tx_type.clone(),
+                amount: tx.amount,
+                age_secs: now.duration_since(tx.created_at).map(|age| age.as_secs()).unwrap_or(0),
+                disputed: tx.disputed,
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.tx);
+        entries
+    }
+
+    /// Copy up to `limit` transactions created before `cutoff`, oldest buckets first
+    pub fn expired(&self, cutoff: SystemTime, limit: usize) -> Vec<(u32, StoredTransaction)> {
+        let boundary = self.bucket_of(cutoff);
+
+        self.buckets
+            .range(..=boundary)
+            .flat_map(|(_, entries)| entries.iter())
+            .filter(|(_, tx)| tx.created_at < cutoff)
+            .take(limit)
+            .map(|(tx_id, tx)| (*tx_id, tx.clone()))
+            .collect()
+    }
+
+    /// Remove and return every transaction created before `cutoff`
+    ///
+    /// Buckets entirely before the cutoff are split off wholesale; only the
+    /// bucket straddling the cutoff is scanned entry by entry.
+    pub fn drain_expired(&mut self, cutoff: SystemTime) -> Vec<(u32, StoredTransaction)> {
+        let boundary = self.bucket_of(cutoff);
+        let live = self.buckets.split_off(&boundary);
+        let expired = std::mem::replace(&mut self.buckets, live);
+
+        let mut drained = Vec::new();
+        for (_, entries) in expired {
+            for (tx_id, tx) in entries {
+                self.index.remove(&tx_id);
+                drained.push((tx_id, tx));
+            }
+        }
+
+        if let Some(entries) = self.buckets.get_mut(&boundary) {
+            let stale: Vec<u32> = entries
+                .iter()
+                .filter(|(_, tx)| tx.created_at < cutoff)
+                .map(|(tx_id, _)| *tx_id)
+                .collect();
+            for tx_id in stale {
+                if let Some(tx) = entries.remove(&tx_id) {
+                    self.index.remove(&tx_id);
+                    drained.push((tx_id, tx));
+                }
+            }
+            if entries.is_empty() {
+                self.buckets.remove(&boundary);
+            }
+        }
+
+        drained
+    }
+}
+
+impl Default for HotStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKET_WIDTH)
+    }
+}
@@ -0,0 +1,731 @@
+use crate::alerts::AlertRule;
+use crate::errors::ProcessingError;
+use crate::history::{HistoryEntry, Pagination, TransactionPage};
+use crate::hot_store::{HotStorageSize, HotTransaction};
+use crate::ingestion::IngestionStatus;
+use crate::models::{Account, AccountOutput, TransactionRow};
+use crate::scalable_engine::ScalableEngine;
+use crate::shard_manager::ShardStats;
+use crate::storage::CompactionReport;
+use crate::treasury::TreasuryReport;
+use crate::tx_registry_actor::TxRegistryStats;
+use crate::wire::{Ack, Capabilities};
+use anyhow::Result;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Build the HTTP query/admin API over a shared engine
+pub fn router(engine: Arc<ScalableEngine>) -> Router {
+    Router::new()
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/:client", get(get_account))
+        .route("/accounts:query", post(query_accounts))
+        .route("/transactions/:tx/trace", get(trace_transaction))
+        .route("/transactions:validate", post(validate_transaction))
+        .route("/accounts/:client/timeline", get(account_timeline))
+        .route("/accounts/:client/transactions", get(account_transactions))
+        .route("/metrics/migration", get(migration_metrics))
+        .route("/metrics/probe", get(probe_metrics))
+        .route("/reports/disputes", get(dispute_report))
+        .route("/reports/disputes.csv", get(dispute_report_csv))
+        .route("/reports/duplicates", get(duplicate_report))
+        .route("/reports/duplicates.csv", get(duplicate_report_csv))
+        .route("/reports/treasury", get(treasury_report))
+        .route("/reports/treasury.csv", get(treasury_report_csv))
+        .route("/periods", get(closed_periods))
+        .route("/periods/:month/close", post(close_period))
+        .route("/admin/hot-storage", get(hot_storage_sizes))
+        .route("/admin/shards", get(shard_stats).put(reshard))
+        .route("/admin/accounts/:client/hot", get(hot_transactions))
+        .route("/admin/accounts/:client/migrate", post(force_migrate))
+        .route("/admin/migrate", post(migrate_all))
+        .route("/admin/accounts/:client/unlock", post(unlock_account))
+        .route("/admin/accounts/:client/alerts", get(alert_rules).put(set_alert_rules))
+        .route("/admin/cold-storage/compact", post(compact_cold_storage))
+        .route("/admin/ingestion", get(ingestion_status))
+        .route("/admin/tx-registry", get(tx_registry_stats))
+        .route("/capabilities", get(capabilities))
+        .route("/ws", get(crate::ws::stream))
+        .route("/openapi.json", get(openapi_json))
+        .with_state(engine)
+}
+
+/// OpenAPI description of `router`, generated from the handlers
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "payments-engine", description = "Query and admin API of the payments engine server"),
+    paths(
+        list_accounts,
+        get_account,
+        query_accounts,
+        trace_transaction,
+        validate_transaction,
+        account_timeline,
+        account_transactions,
+        migration_metrics,
+        probe_metrics,
+        dispute_report,
+        dispute_report_csv,
+        duplicate_report,
+        duplicate_report_csv,
+        treasury_report,
+        treasury_report_csv,
+        closed_periods,
+        close_period,
+        hot_storage_sizes,
+        shard_stats,
+        reshard,
+        hot_transactions,
+        force_migrate,
+        migrate_all,
+        unlock_account,
+        alert_rules,
+        set_alert_rules,
+        compact_cold_storage,
+        ingestion_status,
+        tx_registry_stats,
+        capabilities,
+        crate::ws::stream,
+    ),
+    components(schemas(Problem, HistoryEntry))
+)]
+pub struct ApiDoc;
+
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI over `/openapi.json`, its assets are loaded from a CDN by the browser
+const SWAGGER_UI_PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>payments-engine API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+async fn swagger_ui() -> axum::response::Html<&'static str> {
+    axum::response::Html(SWAGGER_UI_PAGE)
+}
+
+/// Serve the HTTP API until `shutdown` completes, letting requests in flight finish
+///
+/// With `swagger_ui` set, `/docs` serves a Swagger UI over `/openapi.json`.
+pub async fn serve(
+    bind: String,
+    engine: Arc<ScalableEngine>,
+    swagger_ui: bool,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let listener = TcpListener::bind(&bind).await?;
+    tracing::info!("HTTP API listening on {}", bind);
+
+    let mut app = router(engine);
+    if swagger_ui {
+        app = app.route("/docs", get(self::swagger_ui));
+    }
+    // Peer addresses name the source WebSocket duplicates are counted under
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(shutdown)
+        .await?;
+
+    Ok(())
+}
+
+/// Error body of the API: RFC 7807 problem details sent as `application/problem+json`
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Problem {
+    /// `urn:payments-engine:problem:<code>`
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    /// Stable code to branch on, the same acks carry for processing errors
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &'static str, title: impl Into<String>) -> Self {
+        Self {
+            problem_type: format!("urn:payments-engine:problem:{}", code),
+            title: title.into(),
+            status: status.as_u16(),
+            code,
+            tx: None,
+            client: None,
+            detail: None,
+        }
+    }
+
+    /// Failure on our side, the detail goes to the log rather than the client
+    fn internal(status: StatusCode, detail: impl std::fmt::Display) -> Self {
+        tracing::error!("HTTP request failed: {}", detail);
+        Self::new(status, "internal_error", "internal error")
+    }
+
+    fn account_not_found(client: u16) -> Self {
+        Problem::from(ProcessingError::AccountNotFound).with_client(client)
+    }
+
+    pub fn with_tx(mut self, tx: u32) -> Self {
+        self.tx = Some(tx);
+        self
+    }
+
+    pub fn with_client(mut self, client: u16) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// HTTP status of a processing error, fixed per variant
+pub fn status_of(error: &ProcessingError) -> StatusCode {
+    match error {
+        ProcessingError::MissingAmount
+        | ProcessingError::InvalidAmount
+        | ProcessingError::InvalidTransfer
+        | ProcessingError::UnsupportedTransactionType => StatusCode::BAD_REQUEST,
+        ProcessingError::AuthorizationDenied
+        | ProcessingError::Unauthorized
+        | ProcessingError::ReservedClient => StatusCode::FORBIDDEN,
+        ProcessingError::TransactionNotFound | ProcessingError::AccountNotFound => StatusCode::NOT_FOUND,
+        ProcessingError::DuplicateTransaction
+        | ProcessingError::AlreadyDisputed
+        | ProcessingError::NotDisputed
+        | ProcessingError::AccountNotEmpty
+        | ProcessingError::AccountNotLocked
+        | ProcessingError::PeriodClosed
+        | ProcessingError::OutOfSequence => StatusCode::CONFLICT,
+        ProcessingError::InsufficientFunds | ProcessingError::ClientMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+        ProcessingError::AccountLocked => StatusCode::LOCKED,
+        ProcessingError::IdSpaceExhausted | ProcessingError::TransferIncomplete => StatusCode::INTERNAL_SERVER_ERROR,
+        // Worth retrying once the dependency or the replay is back
+        ProcessingError::AuthorizerUnavailable
+        | ProcessingError::RebuildPending
+        | ProcessingError::StorageUnavailable
+        | ProcessingError::EventLogUnavailable
+        | ProcessingError::ActorCommunicationError
+        | ProcessingError::EngineBusy { .. } => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+impl From<ProcessingError> for Problem {
+    fn from(error: ProcessingError) -> Self {
+        Problem::new(status_of(&error), error.code(), error.to_string())
+    }
+}
+
+impl From<PathRejection> for Problem {
+    fn from(rejection: PathRejection) -> Self {
+        Problem::new(rejection.status(), "malformed", "malformed path").with_detail(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for Problem {
+    fn from(rejection: QueryRejection) -> Self {
+        Problem::new(rejection.status(), "malformed", "malformed query string").with_detail(rejection.body_text())
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, [(header::CONTENT_TYPE, "application/problem+json")], Json(self)).into_response()
+    }
+}
+
+/// Freshness demanded by a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Consistency {
+    /// Ask the owning actors, reflects every acknowledged write
+    Strong,
+    /// Read the projection, cheap but may lag recent writes
+    #[default]
+    Eventual,
+}
+
+/// End-to-end latency of the latency prober's synthetic transactions, all zero without a prober
+#[utoipa::path(get, path = "/metrics/probe", tag = "admin", responses((status = 200, body = crate::metrics::ProbeMetricsSnapshot)))]
+async fn probe_metrics(
+    State(engine): State<Arc<ScalableEngine>>,
+) -> Json<crate::metrics::ProbeMetricsSnapshot> {
+    Json(engine.probe_metrics().map(|metrics| metrics.snapshot()).unwrap_or_default())
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReadOptions {
+    #[serde(default)]
+    consistency: Consistency,
+}
+
+#[utoipa::path(get, path = "/accounts", tag = "accounts", params(ReadOptions), responses((status = 200, body = Vec<AccountOutput>)))]
+async fn list_accounts(
+    State(engine): State<Arc<ScalableEngine>>,
+    options: Result<Query<ReadOptions>, QueryRejection>,
+) -> Result<Json<Vec<AccountOutput>>, Problem> {
+    let Query(options) = options?;
+    let accounts = match options.consistency {
+        Consistency::Strong => engine.get_accounts().await,
+        Consistency::Eventual => engine.get_accounts_eventual(),
+    };
+
+    let mut output: Vec<AccountOutput> = accounts.iter().map(AccountOutput::from).collect();
+    output.sort_by_key(|a| a.client);
+
+    Ok(Json(output))
+}
+
+#[utoipa::path(get, path = "/accounts/{client}", tag = "accounts", params(("client" = u16, Path, description = "Client id"), ReadOptions), responses((status = 200, body = AccountOutput), (status = 404, description = "Unknown client", body = Problem, content_type = "application/problem+json")))]
+async fn get_account(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+    options: Result<Query<ReadOptions>, QueryRejection>,
+) -> Result<Json<AccountOutput>, Problem> {
+    let Path(client) = client?;
+    let Query(options) = options?;
+    let account: Option<Account> = match options.consistency {
+        Consistency::Strong => engine.get_account(client).await,
+        Consistency::Eventual => engine.get_account_eventual(client),
+    };
+
+    account
+        .map(|account| Json(AccountOutput::from(&account)))
+        .ok_or_else(|| Problem::account_not_found(client))
+}
+
+/// Account field a bulk query can ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountField {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+}
+
+/// Clients to fetch, listed or as a range (both may be given), and the fields wanted
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AccountQuery {
+    #[serde(default)]
+    pub clients: Vec<u16>,
+    /// Every client from `from` to `to`, both included
+    pub range: Option<ClientRange>,
+    /// Fields to return, all of them when empty
+    #[serde(default)]
+    pub fields: Vec<AccountField>,
+    #[serde(default)]
+    pub consistency: Consistency,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct ClientRange {
+    pub from: u16,
+    pub to: u16,
+}
+
+/// An account reduced to the fields a query asked for
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct MaskedAccount {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<bool>,
+}
+
+impl MaskedAccount {
+    fn new(account: &Account, fields: &[AccountField]) -> Self {
+        let wants = |field| fields.is_empty() || fields.contains(&field);
+        let output = AccountOutput::from(account);
+        Self {
+            client: wants(AccountField::Client).then_some(output.client),
+            available: wants(AccountField::Available).then_some(output.available),
+            held: wants(AccountField::Held).then_some(output.held),
+            total: wants(AccountField::Total).then_some(output.total),
+            locked: wants(AccountField::Locked).then_some(output.locked),
+        }
+    }
+}
+
+/// Many accounts in one request, in client order; unknown clients are left out
+///
+/// Strong reads take each shard's lock once and ask its actors concurrently;
+/// eventual reads come from the projection without any actor round trip.
+#[utoipa::path(post, path = "/accounts:query", tag = "accounts", request_body = AccountQuery, responses((status = 200, body = Vec<MaskedAccount>), (status = 400, description = "Malformed query or empty range", body = Problem, content_type = "application/problem+json")))]
+async fn query_accounts(
+    State(engine): State<Arc<ScalableEngine>>,
+    query: Result<Json<AccountQuery>, JsonRejection>,
+) -> Result<Json<Vec<MaskedAccount>>, Problem> {
+    let Json(query) = query.map_err(|rejection| {
+        Problem::new(rejection.status(), "malformed", "malformed query").with_detail(rejection.body_text())
+    })?;
+
+    let mut clients: std::collections::BTreeSet<u16> = query.clients.into_iter().collect();
+    if let Some(range) = query.range {
+        if range.from > range.to {
+            return Err(Problem::new(StatusCode::BAD_REQUEST, "malformed", "malformed query")
+                .with_detail(format!("range from {} is above to {}", range.from, range.to)));
+        }
+        clients.extend(range.from..=range.to);
+    }
+    let clients: Vec<u16> = clients.into_iter().collect();
+
+    let accounts = match query.consistency {
+        Consistency::Strong => engine.get_accounts_of(&clients).await,
+        Consistency::Eventual => engine.get_accounts_of_eventual(&clients),
+    };
+    Ok(Json(accounts.iter().map(|account| MaskedAccount::new(account, &query.fields)).collect()))
+}
+
+#[utoipa::path(get, path = "/transactions/{tx}/trace", tag = "transactions", params(("tx" = u32, Path, description = "Transaction id")), responses((status = 200, body = crate::trace::TransactionTrace), (status = 404, description = "Tx id never logged", body = Problem, content_type = "application/problem+json")))]
+async fn trace_transaction(
+    State(engine): State<Arc<ScalableEngine>>,
+    tx: Result<Path<u32>, PathRejection>,
+) -> Result<Json<crate::trace::TransactionTrace>, Problem> {
+    let Path(tx) = tx?;
+    let trace = engine.trace_transaction(tx).await.map_err(|e| {
+        Problem::internal(StatusCode::INTERNAL_SERVER_ERROR, format!("tracing tx {}: {}", tx, e))
+    })?;
+
+    if trace.events.is_empty() {
+        return Err(Problem::from(ProcessingError::TransactionNotFound).with_tx(tx));
+    }
+
+    Ok(Json(trace))
+}
+
+/// Dry run: the ack the row would get, nothing is applied
+///
+/// A rejection is the answer rather than a failed request, only a body that
+/// isn't a row is a problem.
+#[utoipa::path(post, path = "/transactions:validate", tag = "transactions", request_body = TransactionRow, responses((status = 200, description = "The ack the row would get", body = Ack), (status = 400, description = "Body is not a row", body = Problem, content_type = "application/problem+json")))]
+async fn validate_transaction(
+    State(engine): State<Arc<ScalableEngine>>,
+    body: String,
+) -> Result<Json<Ack>, Problem> {
+    // Read like a JSON Lines row so a numeric amount keeps its exact digits
+    let tx = crate::csv_io::parse_json_row(&body, engine.amount_format()).map_err(|e| {
+        Problem::new(StatusCode::BAD_REQUEST, "malformed", "malformed row").with_detail(e.to_string())
+    })?;
+
+    let outcome = engine.validate(&tx).await;
+    Ok(Json(Ack::new(tx.tx, &outcome)))
+}
+
+#[utoipa::path(get, path = "/accounts/{client}/timeline", tag = "accounts", params(("client" = u16, Path, description = "Client id")), responses((status = 200, body = crate::timeline::AccountTimeline), (status = 404, description = "Unknown client", body = Problem, content_type = "application/problem+json")))]
+async fn account_timeline(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+) -> Result<Json<crate::timeline::AccountTimeline>, Problem> {
+    let Path(client) = client?;
+    let timeline = engine.account_timeline(client).await.map_err(|e| {
+        Problem::internal(StatusCode::INTERNAL_SERVER_ERROR, format!("timeline of client {}: {}", client, e))
+    })?;
+
+    if timeline.entries.is_empty() {
+        return Err(Problem::account_not_found(client));
+    }
+
+    Ok(Json(timeline))
+}
+
+/// `?after=<tx>&limit=<n>`, follow `next` for the page after
+#[utoipa::path(get, path = "/accounts/{client}/transactions", tag = "accounts", params(("client" = u16, Path, description = "Client id"), Pagination), responses((status = 200, body = TransactionPage), (status = 404, description = "Unknown client", body = Problem, content_type = "application/problem+json")))]
+async fn account_transactions(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+    pagination: Result<Query<Pagination>, QueryRejection>,
+) -> Result<Json<TransactionPage>, Problem> {
+    let Path(client) = client?;
+    let Query(pagination) = pagination?;
+    engine
+        .get_transactions(client, pagination)
+        .await
+        .map(Json)
+        .map_err(|e| Problem::from(e).with_client(client))
+}
+
+#[utoipa::path(get, path = "/metrics/migration", tag = "admin", responses((status = 200, body = crate::metrics::MigrationMetricsSnapshot)))]
+async fn migration_metrics(
+    State(engine): State<Arc<ScalableEngine>>,
+) -> Json<crate::metrics::MigrationMetricsSnapshot> {
+    Json(engine.migration_metrics())
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReportOptions {
+    /// Restrict to one month, "YYYY-MM"
+    month: Option<String>,
+}
+
+#[utoipa::path(get, path = "/reports/disputes", tag = "reports", params(ReportOptions), responses((status = 200, body = Vec<crate::reporting::MonthlyCounts>)))]
+async fn dispute_report(
+    State(engine): State<Arc<ScalableEngine>>,
+    options: Result<Query<ReportOptions>, QueryRejection>,
+) -> Result<Json<Vec<crate::reporting::MonthlyCounts>>, Problem> {
+    let Query(options) = options?;
+    Ok(Json(engine.dispute_counters().report(options.month.as_deref())))
+}
+
+#[utoipa::path(get, path = "/reports/disputes.csv", tag = "reports", params(ReportOptions), responses((status = 200, body = String, content_type = "text/csv")))]
+async fn dispute_report_csv(
+    State(engine): State<Arc<ScalableEngine>>,
+    options: Result<Query<ReportOptions>, QueryRejection>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), Problem> {
+    let Query(options) = options?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/csv")],
+        engine.dispute_counters().to_csv(options.month.as_deref()),
+    ))
+}
+
+#[utoipa::path(get, path = "/reports/duplicates", tag = "reports", responses((status = 200, body = crate::duplicates::DuplicateReport)))]
+async fn duplicate_report(State(engine): State<Arc<ScalableEngine>>) -> Json<crate::duplicates::DuplicateReport> {
+    Json(engine.duplicates().report())
+}
+
+#[utoipa::path(get, path = "/reports/duplicates.csv", tag = "reports", responses((status = 200, body = String, content_type = "text/csv")))]
+async fn duplicate_report_csv(
+    State(engine): State<Arc<ScalableEngine>>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), Problem> {
+    let csv = engine
+        .duplicates()
+        .to_csv()
+        .await
+        .map_err(|e| Problem::internal(StatusCode::INTERNAL_SERVER_ERROR, format!("duplicate report: {}", e)))?;
+    Ok(([(header::CONTENT_TYPE, "text/csv")], csv))
+}
+
+fn treasury(engine: &ScalableEngine, consistency: Consistency) -> TreasuryReport {
+    match consistency {
+        Consistency::Strong => engine.account_totals(),
+        Consistency::Eventual => engine.treasury_report(),
+    }
+}
+
+#[utoipa::path(get, path = "/reports/treasury", tag = "reports", params(ReadOptions), responses((status = 200, body = TreasuryReport)))]
+async fn treasury_report(
+    State(engine): State<Arc<ScalableEngine>>,
+    options: Result<Query<ReadOptions>, QueryRejection>,
+) -> Result<Json<TreasuryReport>, Problem> {
+    let Query(options) = options?;
+    Ok(Json(treasury(&engine, options.consistency)))
+}
+
+#[utoipa::path(get, path = "/reports/treasury.csv", tag = "reports", params(ReadOptions), responses((status = 200, body = String, content_type = "text/csv")))]
+async fn treasury_report_csv(
+    State(engine): State<Arc<ScalableEngine>>,
+    options: Result<Query<ReadOptions>, QueryRejection>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), Problem> {
+    let Query(options) = options?;
+    Ok(([(header::CONTENT_TYPE, "text/csv")], treasury(&engine, options.consistency).to_csv()))
+}
+
+#[utoipa::path(get, path = "/periods", tag = "periods", responses((status = 200, description = "Closed months, \"YYYY-MM\"", body = Vec<String>)))]
+async fn closed_periods(State(engine): State<Arc<ScalableEngine>>) -> Json<Vec<String>> {
+    Json(engine.periods().closed())
+}
+
+#[utoipa::path(post, path = "/periods/{month}/close", tag = "periods", params(("month" = String, Path, description = "Month as \"YYYY-MM\"")), responses((status = 201, description = "Closed now"), (status = 200, description = "Already closed"), (status = 400, description = "Not a month, or one that has not ended", body = Problem, content_type = "application/problem+json")))]
+async fn close_period(
+    State(engine): State<Arc<ScalableEngine>>,
+    month: Result<Path<String>, PathRejection>,
+) -> Result<StatusCode, Problem> {
+    let Path(month) = month?;
+    match engine.close_period(&month).await {
+        Ok(true) => Ok(StatusCode::CREATED),
+        Ok(false) => Ok(StatusCode::OK),
+        Err(e) => Err(
+            Problem::new(StatusCode::BAD_REQUEST, "invalid_period", "invalid period").with_detail(e.to_string()),
+        ),
+    }
+}
+
+#[utoipa::path(get, path = "/admin/hot-storage", tag = "admin", responses((status = 200, body = Vec<HotStorageSize>)))]
+async fn hot_storage_sizes(State(engine): State<Arc<ScalableEngine>>) -> Json<Vec<HotStorageSize>> {
+    Json(engine.hot_storage_sizes().await)
+}
+
+/// Per-shard load, an uneven spread points at a poor routing for the client ids in use
+#[utoipa::path(get, path = "/admin/shards", tag = "admin", responses((status = 200, body = Vec<ShardStats>)))]
+async fn shard_stats(State(engine): State<Arc<ScalableEngine>>) -> Json<Vec<ShardStats>> {
+    Json(engine.stats().await)
+}
+
+/// Shard count asked of `PUT /admin/shards`
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct ReshardRequest {
+    pub shards: usize,
+}
+
+/// Outcome of `PUT /admin/shards`
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct ReshardReport {
+    pub shards: usize,
+    /// Running actors now on another shard
+    pub moved: usize,
+}
+
+/// Add or remove shards while running, best with consistent-hash routing which moves the fewest clients
+#[utoipa::path(put, path = "/admin/shards", tag = "admin", request_body = ReshardRequest, responses((status = 200, body = ReshardReport), (status = 400, description = "Not a shard count", body = Problem, content_type = "application/problem+json")))]
+async fn reshard(
+    State(engine): State<Arc<ScalableEngine>>,
+    request: Result<Json<ReshardRequest>, JsonRejection>,
+) -> Result<Json<ReshardReport>, Problem> {
+    let Json(ReshardRequest { shards }) = request.map_err(|rejection| {
+        Problem::new(rejection.status(), "malformed", "malformed reshard request").with_detail(rejection.body_text())
+    })?;
+    match engine.reshard(shards).await {
+        Ok(moved) => Ok(Json(ReshardReport { shards, moved })),
+        Err(e) => Err(Problem::new(StatusCode::BAD_REQUEST, "invalid_shards", "invalid shard count").with_detail(e.to_string())),
+    }
+}
+
+#[utoipa::path(get, path = "/admin/accounts/{client}/hot", tag = "admin", params(("client" = u16, Path, description = "Client id")), responses((status = 200, body = Vec<HotTransaction>), (status = 404, description = "No running actor", body = Problem, content_type = "application/problem+json")))]
+async fn hot_transactions(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+) -> Result<Json<Vec<HotTransaction>>, Problem> {
+    let Path(client) = client?;
+    engine
+        .hot_transactions(client)
+        .await
+        .map(Json)
+        .ok_or_else(|| Problem::account_not_found(client))
+}
+
+/// Migration runs in the background, poll the client's hot storage to see it finish
+#[utoipa::path(post, path = "/admin/accounts/{client}/migrate", tag = "admin", params(("client" = u16, Path, description = "Client id")), responses((status = 202, description = "Migration started"), (status = 404, description = "No running actor", body = Problem, content_type = "application/problem+json")))]
+async fn force_migrate(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+) -> Result<StatusCode, Problem> {
+    let Path(client) = client?;
+    if engine.force_migrate_cold(client).await {
+        Ok(StatusCode::ACCEPTED)
+    } else {
+        Err(Problem::account_not_found(client))
+    }
+}
+
+/// Actors told to migrate by `POST /admin/migrate`
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct MigrationStarted {
+    pub actors: usize,
+}
+
+/// Every running actor migrates in the background, `/admin/hot-storage` empties as they finish
+#[utoipa::path(post, path = "/admin/migrate", tag = "admin", responses((status = 202, description = "Migration started", body = MigrationStarted)))]
+async fn migrate_all(State(engine): State<Arc<ScalableEngine>>) -> (StatusCode, Json<MigrationStarted>) {
+    let actors = engine.migrate_all_cold().await;
+    (StatusCode::ACCEPTED, Json(MigrationStarted { actors }))
+}
+
+#[utoipa::path(post, path = "/admin/accounts/{client}/unlock", tag = "admin", params(("client" = u16, Path, description = "Client id")), responses((status = 200, description = "Unlocked"), (status = 404, description = "Unknown client", body = Problem, content_type = "application/problem+json"), (status = 409, description = "Not locked", body = Problem, content_type = "application/problem+json")))]
+async fn unlock_account(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+) -> Result<StatusCode, Problem> {
+    let Path(client) = client?;
+    engine
+        .unlock_account(client)
+        .await
+        .map(|()| StatusCode::OK)
+        .map_err(|e| Problem::from(e).with_client(client))
+}
+
+#[utoipa::path(get, path = "/admin/accounts/{client}/alerts", tag = "admin", params(("client" = u16, Path, description = "Client id")), responses((status = 200, body = Vec<AlertRule>)))]
+async fn alert_rules(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+) -> Result<Json<Vec<AlertRule>>, Problem> {
+    let Path(client) = client?;
+    Ok(Json(engine.alerts().rules(client)))
+}
+
+/// Replace the client's alert rules, an empty list removes them; clients without an account yet may have rules
+#[utoipa::path(put, path = "/admin/accounts/{client}/alerts", tag = "admin", params(("client" = u16, Path, description = "Client id")), request_body = Vec<AlertRule>, responses((status = 200, body = Vec<AlertRule>), (status = 400, description = "Body is not a list of rules", body = Problem, content_type = "application/problem+json"), (status = 503, description = "Rules could not be persisted", body = Problem, content_type = "application/problem+json")))]
+async fn set_alert_rules(
+    State(engine): State<Arc<ScalableEngine>>,
+    client: Result<Path<u16>, PathRejection>,
+    rules: Result<Json<Vec<AlertRule>>, JsonRejection>,
+) -> Result<Json<Vec<AlertRule>>, Problem> {
+    let Path(client) = client?;
+    let Json(rules) = rules.map_err(|rejection| {
+        Problem::new(rejection.status(), "malformed", "malformed alert rules").with_detail(rejection.body_text())
+    })?;
+
+    engine
+        .alerts()
+        .set_rules(client, rules)
+        .await
+        .map_err(|e| Problem::internal(StatusCode::SERVICE_UNAVAILABLE, format!("alert rules of client {}: {}", client, e)))?;
+    Ok(Json(engine.alerts().rules(client)))
+}
+
+/// Runs to completion before answering, which takes a full scan of the cold store
+#[utoipa::path(post, path = "/admin/cold-storage/compact", tag = "admin", responses((status = 200, body = CompactionReport), (status = 503, description = "Cold storage unavailable", body = Problem, content_type = "application/problem+json")))]
+async fn compact_cold_storage(State(engine): State<Arc<ScalableEngine>>) -> Result<Json<CompactionReport>, Problem> {
+    engine
+        .compact_cold_storage()
+        .await
+        .map(Json)
+        .map_err(|e| Problem::internal(StatusCode::SERVICE_UNAVAILABLE, format!("cold storage compaction: {}", e)))
+}
+
+#[utoipa::path(get, path = "/admin/ingestion", tag = "admin", responses((status = 200, body = IngestionStatus)))]
+async fn ingestion_status(State(engine): State<Arc<ScalableEngine>>) -> Json<IngestionStatus> {
+    Json(engine.ingestion().status())
+}
+
+/// Protocol version, formats and features, as the wire protocol's `#hello` answer has them
+#[utoipa::path(get, path = "/capabilities", tag = "meta", responses((status = 200, body = Capabilities)))]
+async fn capabilities(State(engine): State<Arc<ScalableEngine>>) -> Json<Capabilities> {
+    Json(engine.capabilities())
+}
+
+#[utoipa::path(get, path = "/admin/tx-registry", tag = "admin", responses((status = 200, body = TxRegistryStats), (status = 503, description = "Registry unavailable", body = Problem, content_type = "application/problem+json")))]
+async fn tx_registry_stats(State(engine): State<Arc<ScalableEngine>>) -> Result<Json<TxRegistryStats>, Problem> {
+    engine
+        .tx_registry_stats()
+        .await
+        .map(Json)
+        .map_err(|e| Problem::internal(StatusCode::SERVICE_UNAVAILABLE, format!("tx registry stats: {}", e)))
+}
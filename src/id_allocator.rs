@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// First tx id of the range reserved for engine-generated events
+pub const INTERNAL_ID_RANGE_START: u32 = 0xF000_0000;
+
+/// Source of tx ids for events the engine creates itself (opening balances, fees, ...)
+///
+/// Allocated ids are still registered with the tx registry, so a collision with
+/// a producer-supplied id is detected and the allocator is simply asked again.
+pub trait IdAllocator: Send + Sync {
+    /// Next candidate id, or None once the allocator is exhausted
+    fn next_id(&self) -> Option<u32>;
+
+    /// Note an id seen during replay so it is not handed out again
+    fn observe(&self, tx_id: u32);
+}
+
+/// Hands out ids sequentially from a reserved high range
+pub struct ReservedRangeAllocator {
+    next: AtomicU64,
+    end: u64,
+}
+
+impl ReservedRangeAllocator {
+    /// Allocate from `start..=end`
+    pub fn new(start: u32, end: u32) -> Self {
+        Self {
+            next: AtomicU64::new(start as u64),
+            end: end as u64,
+        }
+    }
+}
+
+impl Default for ReservedRangeAllocator {
+    fn default() -> Self {
+        Self::new(INTERNAL_ID_RANGE_START, u32::MAX)
+    }
+}
+
+impl IdAllocator for ReservedRangeAllocator {
+    fn next_id(&self) -> Option<u32> {
+        let id = self.next.fetch_add(1, Ordering::SeqCst);
+        if id > self.end {
+            return None;
+        }
+        Some(id as u32)
+    }
+
+    fn observe(&self, tx_id: u32) {
+        let tx_id = tx_id as u64;
+        if tx_id <= self.end {
+            self.next.fetch_max(tx_id + 1, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::compression::open_input;
+use crate::csv_io::{stream_timed_transactions, stream_transactions_with};
+use crate::errors::ProcessingError;
+use crate::merge::validate_sorted;
+use crate::models::TransactionRow;
+use crate::scalable_engine::{ScalableEngine, PREFETCH_WINDOW};
+use crate::schema::CsvSchema;
+use anyhow::Result;
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+/// Backfilled tx ids kept for boundary dedup, and live rows after which they are dropped
+///
+/// A live stream only overlaps the tail of the file it takes over from.
+const BOUNDARY_WINDOW: usize = 65_536;
+
+/// Last row of a backfill file, the live stream carries everything after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cutover {
+    /// The whole file
+    EndOfFile,
+    /// Rows up to and including this 1-based row number
+    Sequence(u64),
+    /// Rows stamped at or before this unix second, the file has a timestamp column
+    Timestamp(u64),
+}
+
+impl FromStr for Cutover {
+    type Err = anyhow::Error;
+
+    /// `end`, `seq:<row>` or `at:<unix secs>`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "end" => Ok(Cutover::EndOfFile),
+            Some(("seq", row)) => Ok(Cutover::Sequence(row.parse()?)),
+            Some(("at", secs)) => Ok(Cutover::Timestamp(secs.parse()?)),
+            _ => anyhow::bail!("Invalid cutover '{}', expected end, seq:<row> or at:<unix secs>", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestionPhase {
+    /// Applying the file, live rows wait
+    Backfilling,
+    Live,
+    /// The file could not be applied, live rows are refused rather than leave a gap
+    Failed,
+}
+
+/// Progress of a backfill and of the live stream after it
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct IngestionStatus {
+    pub phase: IngestionPhase,
+    /// File rows read up to the cutover
+    pub backfill_rows: u64,
+    pub backfill_rejected: u64,
+    /// File rows past the cutover, left to the live stream
+    pub backfill_skipped: u64,
+    pub live_rows: u64,
+    /// Live rows repeating a row of the backfill, acknowledged without applying them again
+    pub boundary_duplicates: u64,
+}
+
+/// Hand-over from a historical file to the live stream
+///
+/// Live connections wait until the file is applied up to its cutover, so no
+/// live row lands before an older one. A live row repeating a file row
+/// applied by the backfill (same tx id, type, client and amount) is
+/// acknowledged as applied instead of rejected as a duplicate, once and only
+/// within the first `BOUNDARY_WINDOW` rows on either side of the cutover.
+pub struct Ingestion {
+    phase: watch::Sender<IngestionPhase>,
+    // Tx ids created by the backfill, only those count as boundary duplicates
+    backfilled: Mutex<BoundaryIds>,
+    backfill_rows: AtomicU64,
+    backfill_rejected: AtomicU64,
+    backfill_skipped: AtomicU64,
+    live_rows: AtomicU64,
+    boundary_duplicates: AtomicU64,
+}
+
+impl Default for Ingestion {
+    fn default() -> Self {
+        Self {
+            phase: watch::Sender::new(IngestionPhase::Live),
+            backfilled: Mutex::new(BoundaryIds::default()),
+            backfill_rows: AtomicU64::new(0),
+            backfill_rejected: AtomicU64::new(0),
+            backfill_skipped: AtomicU64::new(0),
+            live_rows: AtomicU64::new(0),
+            boundary_duplicates: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Ingestion {
+    /// Live from the start, nothing to backfill
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold live rows until `finish_backfill`, call before accepting connections
+    pub fn start_backfill(&self) {
+        self.phase.send_replace(IngestionPhase::Backfilling);
+    }
+
+    fn finish_backfill(&self, phase: IngestionPhase) {
+        self.phase.send_replace(phase);
+    }
+
+    pub fn phase(&self) -> IngestionPhase {
+        *self.phase.borrow()
+    }
+
+    pub fn status(&self) -> IngestionStatus {
+        IngestionStatus {
+            phase: self.phase(),
+            backfill_rows: self.backfill_rows.load(Ordering::Relaxed),
+            backfill_rejected: self.backfill_rejected.load(Ordering::Relaxed),
+            backfill_skipped: self.backfill_skipped.load(Ordering::Relaxed),
+            live_rows: self.live_rows.load(Ordering::Relaxed),
+            boundary_duplicates: self.boundary_duplicates.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Wait until live rows may be applied, an error if the backfill failed
+    pub async fn wait_live(&self) -> Result<()> {
+        let mut phase = self.phase.subscribe();
+        let phase = phase
+            .wait_for(|phase| *phase != IngestionPhase::Backfilling)
+            .await?;
+        if *phase == IngestionPhase::Failed {
+            anyhow::bail!("Backfill failed, live rows are not accepted");
+        }
+        Ok(())
+    }
+
+    /// Outcome to acknowledge for a live row, with repeats of the backfill turned into success
+    pub async fn settle_live(
+        &self,
+        engine: &ScalableEngine,
+        row: &TransactionRow,
+        outcome: Result<(), ProcessingError>,
+    ) -> Result<(), ProcessingError> {
+        if self.live_rows.fetch_add(1, Ordering::Relaxed) == BOUNDARY_WINDOW as u64 {
+            // Past the overlap, nothing left to repeat
+            *self.backfilled.lock().unwrap() = BoundaryIds::default();
+        }
+
+        if !matches!(outcome, Err(ProcessingError::DuplicateTransaction)) {
+            return outcome;
+        }
+        if !self.backfilled.lock().unwrap().contains(row.tx) || !engine.repeats_applied(row).await {
+            return outcome;
+        }
+        // A file row is repeated once, a second repeat is a duplicate
+        if !self.backfilled.lock().unwrap().remove(row.tx) {
+            return outcome;
+        }
+
+        self.boundary_duplicates.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn record_backfill(&self, row: &TransactionRow, outcome: &Result<(), ProcessingError>) {
+        self.backfill_rows.fetch_add(1, Ordering::Relaxed);
+        match outcome {
+            Ok(()) if row.tx_type.creates_tx() => {
+                self.backfilled.lock().unwrap().insert(row.tx);
+            }
+            Ok(()) => {}
+            Err(_) => {
+                self.backfill_rejected.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// The last `BOUNDARY_WINDOW` tx ids the backfill created, oldest first
+#[derive(Default)]
+struct BoundaryIds {
+    order: VecDeque<u32>,
+    ids: HashSet<u32>,
+}
+
+impl BoundaryIds {
+    fn insert(&mut self, tx: u32) {
+        if !self.ids.insert(tx) {
+            return;
+        }
+        self.order.push_back(tx);
+        if self.order.len() > BOUNDARY_WINDOW {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+    }
+
+    fn contains(&self, tx: u32) -> bool {
+        self.ids.contains(&tx)
+    }
+
+    fn remove(&mut self, tx: u32) -> bool {
+        self.ids.remove(&tx)
+    }
+}
+
+/// Apply `path` up to `cutover`, then switch the engine's ingestion to live
+///
+/// On failure live rows are refused, a partly applied file would leave a gap.
+pub async fn backfill(engine: &ScalableEngine, path: &Path, cutover: Cutover) -> Result<IngestionStatus> {
+    let ingestion = engine.ingestion();
+    let result = match cutover {
+        Cutover::Timestamp(secs) => backfill_timed(engine, path, secs).await,
+        Cutover::EndOfFile => backfill_rows(engine, path, u64::MAX).await,
+        Cutover::Sequence(last) => backfill_rows(engine, path, last).await,
+    };
+
+    match result {
+        Ok(()) => {
+            ingestion.finish_backfill(IngestionPhase::Live);
+            let status = ingestion.status();
+            tracing::info!(
+                rows = status.backfill_rows,
+                rejected = status.backfill_rejected,
+                skipped = status.backfill_skipped,
+                "Backfill of {} complete, switching to live",
+                path.display()
+            );
+            Ok(status)
+        }
+        Err(e) => {
+            ingestion.finish_backfill(IngestionPhase::Failed);
+            Err(e)
+        }
+    }
+}
+
+async fn backfill_rows(engine: &ScalableEngine, path: &Path, last: u64) -> Result<()> {
+    let ingestion = engine.ingestion();
+    let source = path.display().to_string();
+    let mut stream =
+        stream_transactions_with(open_input(path).await?, CsvSchema::default(), engine.amount_format()).ready_chunks(PREFETCH_WINDOW);
+
+    let mut sequence = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let mut rows = Vec::with_capacity(chunk.len());
+        for result in chunk {
+            sequence += 1;
+            if sequence > last {
+                ingestion.backfill_skipped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            match result {
+                Ok(row) => rows.push(row),
+                Err(e) => {
+                    tracing::warn!("Backfill row {}: {}", sequence, e);
+                    ingestion.backfill_rows.fetch_add(1, Ordering::Relaxed);
+                    ingestion.backfill_rejected.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        engine.prefetch(&rows).await;
+        let outcomes = engine.process_batch(rows.clone()).await;
+        for (row, outcome) in rows.iter().zip(&outcomes) {
+            ingestion.record_backfill(row, outcome);
+            engine.duplicates().record_outcome(&source, row.tx, outcome);
+        }
+    }
+    Ok(())
+}
+
+async fn backfill_timed(engine: &ScalableEngine, path: &Path, cutover_secs: u64) -> Result<()> {
+    // Rows past the cutover are only told apart from earlier ones in a sorted file
+    validate_sorted(&[path.to_path_buf()], engine.amount_format()).await?;
+
+    let ingestion = engine.ingestion();
+    let source = path.display().to_string();
+    let mut stream = stream_timed_transactions(open_input(path).await?, engine.amount_format());
+
+    while let Some(result) = stream.next().await {
+        let row = match result {
+            Ok(row) => row,
+            Err(e) => {
+                tracing::warn!("Backfill row: {}", e);
+                ingestion.backfill_rows.fetch_add(1, Ordering::Relaxed);
+                ingestion.backfill_rejected.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+        if row.timestamp > cutover_secs {
+            ingestion.backfill_skipped.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+
+        // Applied as of when it happened, like `merge`
+        let at = row.time();
+        let row = row.into_row();
+        let outcome = engine.process_at(row.clone(), at).await;
+        ingestion.record_backfill(&row, &outcome);
+        engine.duplicates().record_outcome(&source, row.tx, &outcome);
+    }
+    Ok(())
+}
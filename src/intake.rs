@@ -0,0 +1,156 @@
+use crate::models::TransactionRow;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Where a row read from a connection stands in the intake log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Receipt {
+    /// The engine keeps no intake log
+    Untracked,
+    /// Staged under this id, to be settled once acknowledged
+    Staged(u64),
+    /// Staging failed, the row must be refused rather than applied unrecorded
+    Unstaged,
+}
+
+/// One line of the intake log
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum IntakeRecord {
+    Received { id: u64, row: TransactionRow },
+    Settled { id: u64 },
+}
+
+/// Staged intake: rows recorded on receipt, settled once applied or refused
+///
+/// A row read from a connection is written here before anything else happens
+/// to it, so rows accepted but not yet applied when the process dies (queued
+/// in a spill buffer, waiting in an actor's mailbox) are recovered on restart.
+/// Records are JSON lines flushed to the OS, surviving a process crash but not
+/// a power loss. The file is emptied whenever nothing is pending.
+pub struct IntakeLog {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+struct State {
+    writer: BufWriter<File>,
+    next_id: u64,
+    pending: usize,
+    written: u64,
+}
+
+impl IntakeLog {
+    /// Open the log at `path`, returning it with the rows received but never settled, in receipt order
+    ///
+    /// The returned rows still count as pending: settle them with `settle`
+    /// once applied, or leave them to the next restart.
+    pub fn open(path: PathBuf) -> Result<(Self, Vec<(u64, TransactionRow)>)> {
+        let mut pending = BTreeMap::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                // A torn last line is a receipt that never completed, its row was never acknowledged
+                match serde_json::from_str(&line?) {
+                    Ok(IntakeRecord::Received { id, row }) => {
+                        pending.insert(id, row);
+                    }
+                    Ok(IntakeRecord::Settled { id }) => {
+                        pending.remove(&id);
+                    }
+                    Err(e) => tracing::warn!("Skipping unreadable intake record in {}: {}", path.display(), e),
+                }
+            }
+        }
+        let next_id = pending.keys().next_back().map_or(0, |id| id + 1);
+        let pending: Vec<(u64, TransactionRow)> = pending.into_iter().collect();
+
+        // Start the file over with only what is still pending
+        let mut writer = BufWriter::new(
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&path)
+                .with_context(|| format!("opening intake log {}", path.display()))?,
+        );
+        let mut written = 0;
+        for (id, row) in &pending {
+            written += write_record(&mut writer, &IntakeRecord::Received { id: *id, row: row.clone() })?;
+        }
+        writer.flush()?;
+
+        let log = Self {
+            path,
+            state: Mutex::new(State {
+                writer,
+                next_id,
+                pending: pending.len(),
+                written,
+            }),
+        };
+        Ok((log, pending))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rows received and not settled yet
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending
+    }
+
+    /// Record a row on receipt, before it is applied
+    pub fn receive(&self, row: &TransactionRow) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        let written = write_record(&mut state.writer, &IntakeRecord::Received { id, row: row.clone() })?;
+        state.writer.flush()?;
+        state.next_id += 1;
+        state.pending += 1;
+        state.written += written;
+        Ok(id)
+    }
+
+    /// Stage a row, turning a failed write into a receipt the row is refused with
+    pub fn stage(&self, row: &TransactionRow) -> Receipt {
+        match self.receive(row) {
+            Ok(id) => Receipt::Staged(id),
+            Err(e) => {
+                tracing::error!("Failed to stage tx {} in intake log {}: {}", row.tx, self.path.display(), e);
+                Receipt::Unstaged
+            }
+        }
+    }
+
+    /// Record that a received row was applied or refused, it won't be recovered any more
+    pub fn settle(&self, id: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let written = write_record(&mut state.writer, &IntakeRecord::Settled { id })?;
+        state.writer.flush()?;
+        state.pending = state.pending.saturating_sub(1);
+        state.written += written;
+
+        // Nothing left to recover, start the file over instead of letting it grow
+        if state.pending == 0 && state.written > 0 {
+            let file = state.writer.get_mut();
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            state.written = 0;
+        }
+        Ok(())
+    }
+}
+
+fn write_record(writer: &mut BufWriter<File>, record: &IntakeRecord) -> Result<u64> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    Ok(line.len() as u64)
+}
@@ -1,14 +1,65 @@
 pub mod account_actor;
+pub mod alerts;
+pub mod amount;
+pub mod audit;
+pub mod auth;
+pub mod backpressure;
+pub mod authorizer;
 pub mod cli;
+pub mod clock;
+pub mod compat;
+pub mod compression;
+pub mod config;
+pub mod consume;
+pub mod contention;
+pub mod corrections;
 pub mod csv_io;
+pub mod duplicates;
+pub mod engine_snapshot;
 pub mod errors;
+pub mod escalation;
 pub mod event_store;
+pub mod events;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handlers;
+pub mod history;
+pub mod hot_store;
+pub mod http;
+pub mod id_allocator;
+pub mod ingestion;
+pub mod intake;
+pub mod metrics;
+pub mod merge;
+pub mod migration;
 pub mod models;
+pub mod periods;
+pub mod prober;
+pub mod projection;
+pub mod recorder;
+pub mod rejects;
+pub mod reporting;
+pub mod routing;
 pub mod scalable_engine;
+pub mod schema;
+pub mod sequencer;
 pub mod server;
 pub mod shard_manager;
+pub mod snapshots;
+pub mod soak;
+pub mod spill;
+pub mod statement;
 pub mod storage;
+#[cfg(feature = "test-util")]
+pub mod test_support;
+pub mod timeline;
+pub mod topology;
+pub mod trace;
+pub mod treasury;
 pub mod tx_registry_actor;
+pub mod wire;
+pub mod ws;
 
 pub use errors::ProcessingError;
 pub use models::{Account, AccountOutput, TransactionRow, TransactionType};
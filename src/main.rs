@@ -1,15 +1,69 @@
 use anyhow::Result;
-use clap::Parser;
-use payments_engine::{cli, server};
+use clap::{Args, Parser, Subcommand};
+use payments_engine::amount::{AmountFormat, AmountUnits};
+use payments_engine::backpressure::{BackpressureConfig, DEFAULT_BUSY_AFTER};
+use payments_engine::compat::{CompatConfig, CompatMode, LockPolicy};
+use payments_engine::config::EngineConfig;
+use payments_engine::consume::ConsumeConfig;
+use payments_engine::cli::{CliOutput, InputFormat, InputOptions, OutputFormat};
+use payments_engine::escalation::EscalationPolicy;
+use payments_engine::event_store::DurabilityPolicy;
+use payments_engine::export::ExportConfig;
+use payments_engine::ingestion::Cutover;
+use payments_engine::routing::RoutingMode;
+use payments_engine::recorder::{RecorderConfig, DEFAULT_RECORD_ROWS};
+use payments_engine::schema::CsvSchema;
+use payments_engine::server::ServerConfig;
+use payments_engine::soak::{self, SoakConfig};
+use payments_engine::spill::{SpillConfig, DEFAULT_SPILL_AFTER};
+use payments_engine::storage::open_cold_storage;
+use payments_engine::topology::CpuTopology;
+use payments_engine::{cli, server, statement, trace};
+use rust_decimal::Decimal;
 use std::path::PathBuf;
+use std::time::Duration;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
 #[command(name = "payments-engine")]
 #[command(about = "Process payment transactions")]
 enum Cli {
     #[command(name = "cli")]
-    CliMode { input: PathBuf },
+    CliMode {
+        input: PathBuf,
+        /// Input encoding: auto, csv or json (JSON Lines)
+        #[arg(long, default_value = "auto")]
+        format: InputFormat,
+        /// Amounts in and out: decimal, or minor:<exponent> for integer minor units (minor:2 for cents)
+        #[arg(long, default_value = "decimal")]
+        amount_units: AmountUnits,
+        /// CSV layout instead of sniffing it: sep=<comma|semicolon|tab|pipe>,header=<yes|no>
+        #[arg(long)]
+        schema: Option<CsvSchema>,
+        #[command(flatten)]
+        compat: CompatArgs,
+        #[command(flatten)]
+        amounts: AmountArgs,
+        /// Print totals across all accounts instead of each account
+        #[arg(long)]
+        treasury: bool,
+        /// Print each shard's actors, hot transactions and queued messages instead of the accounts
+        #[arg(long, conflicts_with = "treasury")]
+        shard_stats: bool,
+        #[command(flatten)]
+        export: ExportArgs,
+        /// Output encoding: csv, json (JSON Lines) or table
+        #[arg(long, default_value = "csv")]
+        output_format: OutputFormat,
+        #[command(flatten)]
+        event_log: EventLogArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        /// Write rows that were not applied, with line number and reason (JSON Lines for .json/.jsonl, CSV otherwise)
+        #[arg(long)]
+        rejects: Option<PathBuf>,
+    },
     /// Run TCP server
     #[command(name = "server")]
     Server {
@@ -17,25 +71,536 @@ enum Cli {
         bind: String,
         #[arg(long, default_value = "1000")]
         max_connections: usize,
+        /// Also serve the HTTP API on this address
+        #[arg(long)]
+        http_bind: Option<String>,
+        /// Serve a Swagger UI at /docs of the HTTP API, over its /openapi.json
+        #[arg(long, requires = "http_bind")]
+        swagger_ui: bool,
+        /// Also serve the gRPC service on this address (build with the grpc feature)
+        #[arg(long)]
+        grpc_bind: Option<String>,
+        /// Event log replayed on startup and appended to while running [default: the config's, else server_transactions.log]
+        #[arg(long)]
+        log: Option<PathBuf>,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(flatten)]
+        compat: CompatArgs,
+        /// RocksDB directory for cold transactions, in memory if omitted
+        #[arg(long)]
+        storage_path: Option<PathBuf>,
+        /// S3 location for cold transactions, s3://<bucket>/<prefix>; credentials from AWS_* variables
+        #[arg(long, conflicts_with = "storage_path")]
+        object_store_url: Option<String>,
+        /// Engine snapshot loaded on startup, so only later log events are replayed
+        #[arg(long)]
+        snapshot: Option<PathBuf>,
+        /// Seconds between snapshot writes
+        #[arg(long, default_value = "300")]
+        snapshot_interval_secs: u64,
+        /// When logged events reach the disk: buffered, per-write, interval:<ms> or group:<ms>
+        #[arg(long, default_value = "group:2")]
+        durability: DurabilityPolicy,
+        /// Historical CSV applied before live rows, which wait until it is done
+        #[arg(long)]
+        backfill: Option<PathBuf>,
+        /// Last backfill row: end, seq:<row> or at:<unix secs> (needs a timestamp column)
+        #[arg(long, default_value = "end", requires = "backfill")]
+        cutover: Cutover,
+        /// Directory keeping registered tx ids, so duplicates are refused across restarts
+        #[arg(long)]
+        tx_registry_dir: Option<PathBuf>,
+        /// Seconds open connections get to finish after SIGTERM before they are aborted
+        #[arg(long, default_value = "30")]
+        shutdown_grace_secs: u64,
+        /// Milliseconds `#ordering sequenced` rows wait for a missing sequence number before they are rejected
+        #[arg(long, default_value = "5000")]
+        sequence_timeout_ms: u64,
+        /// File keeping the sequence number each `#ordering sequenced` client expects next across restarts
+        #[arg(long)]
+        sequence_state: Option<PathBuf>,
+        /// How clients are assigned to account shards: modulo or consistent-hash
+        #[arg(long, default_value = "modulo")]
+        routing: RoutingMode,
+        /// POST disputes needing a human here as JSON: open too long, or charged back for a high amount
+        #[arg(long)]
+        escalation_webhook: Option<String>,
+        /// Days a dispute may stay open before it is escalated
+        #[arg(long, default_value = "30", requires = "escalation_webhook")]
+        escalate_after_days: u64,
+        /// Chargebacks of at least this amount are escalated
+        #[arg(long, default_value = "10000", requires = "escalation_webhook")]
+        escalate_chargebacks_over: Decimal,
+        /// POST balance alerts (rules set with PUT /admin/accounts/<client>/alerts) here as JSON
+        #[arg(long)]
+        alert_webhook: Option<String>,
+        /// Hours between compactions dropping cold records no dispute can target any more
+        #[arg(long)]
+        compact_every_hours: Option<u64>,
+        /// Spill rows a connection reads faster than it applies into files here instead of slowing the producer
+        #[arg(long)]
+        spill_dir: Option<PathBuf>,
+        /// Rows a connection holds in memory before spilling
+        #[arg(long, default_value_t = DEFAULT_SPILL_AFTER, requires = "spill_dir")]
+        spill_after: usize,
+        /// Stop reading from connections while this many messages are queued for one shard's actors
+        #[arg(long)]
+        max_queue_depth: Option<usize>,
+        /// Milliseconds to wait for the queues to drain before refusing rows with `engine_busy`
+        #[arg(long, default_value_t = DEFAULT_BUSY_AFTER.as_millis() as u64, requires = "max_queue_depth")]
+        busy_after_ms: u64,
+        /// Stage each received row in this file until it is applied, recovering unapplied rows on restart
+        #[arg(long)]
+        intake_log: Option<PathBuf>,
+        /// Record a sample of live rows, anonymized, as a golden fixture case in this directory
+        #[arg(long)]
+        record_fixture: Option<PathBuf>,
+        /// Share of clients recorded, 0 to 1
+        #[arg(long, default_value_t = 0.01, value_parser = parse_sample_rate, requires = "record_fixture")]
+        record_sample: f64,
+        /// Rows recorded before the bundle is written, it is written at shutdown otherwise
+        #[arg(long, default_value_t = DEFAULT_RECORD_ROWS, requires = "record_fixture")]
+        record_rows: usize,
+        /// Inject a synthetic transaction pair for the reserved client 65535 this often, measuring end-to-end latency
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        probe_interval_secs: Option<u64>,
+        /// TOML file of API keys and the clients each may submit for; connections then need a key
+        #[arg(long)]
+        api_keys: Option<PathBuf>,
+        /// Export the accounts as client id range partitions with a manifest into a new subdirectory here, periodically
+        #[arg(long)]
+        export_dir: Option<PathBuf>,
+        /// Client id ranges each export is split into, one file each
+        #[arg(long, default_value = "8", requires = "export_dir", value_parser = clap::value_parser!(u64).range(1..=65536))]
+        export_partitions: u64,
+        /// Minutes between exports
+        #[arg(long, default_value = "60", requires = "export_dir", value_parser = clap::value_parser!(u64).range(1..))]
+        export_every_mins: u64,
+        #[command(flatten)]
+        amounts: AmountArgs,
+    },
+    /// Process several timestamp-sorted CSVs (daily files, corrections) in one global order
+    #[command(name = "merge")]
+    Merge {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        #[command(flatten)]
+        compat: CompatArgs,
+        #[command(flatten)]
+        amounts: AmountArgs,
+        /// Print totals across all accounts instead of each account
+        #[arg(long)]
+        treasury: bool,
+        /// Print each shard's actors, hot transactions and queued messages instead of the accounts
+        #[arg(long, conflicts_with = "treasury")]
+        shard_stats: bool,
+        #[command(flatten)]
+        export: ExportArgs,
+        /// Output encoding: csv, json (JSON Lines) or table
+        #[arg(long, default_value = "csv")]
+        output_format: OutputFormat,
+        #[command(flatten)]
+        event_log: EventLogArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Seed accounts with opening balances from a client,amount CSV
+    #[command(name = "import-balances")]
+    ImportBalances {
+        input: PathBuf,
+        #[arg(long, default_value = "server_transactions.log")]
+        log: PathBuf,
+    },
+    /// Re-submit rejected rows to an event log, with field corrections from a tx,field,value CSV
+    #[command(name = "replay-rejects")]
+    ReplayRejects {
+        rejects: PathBuf,
+        #[arg(long)]
+        map: Option<PathBuf>,
+        #[arg(long, default_value = "server_transactions.log")]
+        log: PathBuf,
+        #[command(flatten)]
+        compat: CompatArgs,
+        #[command(flatten)]
+        amounts: AmountArgs,
+    },
+    /// Stream generated traffic over many TCP connections to an in-process server, checking invariants
+    #[command(name = "soak")]
+    Soak {
+        #[arg(long, default_value = "200")]
+        connections: usize,
+        #[arg(long, default_value = "3600")]
+        duration_secs: u64,
+        /// Seconds between invariant and memory checks
+        #[arg(long, default_value = "30")]
+        check_interval_secs: u64,
+        /// Rows each connection sends before waiting for their acks
+        #[arg(long, default_value = "64")]
+        batch_size: usize,
+        /// Fail once resident memory exceeds this many MiB
+        #[arg(long)]
+        max_rss_mb: Option<u64>,
+    },
+    /// Check or restore engine snapshots
+    #[command(name = "snapshot", subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Reconstruct the history of one transaction from an event log
+    #[command(name = "trace")]
+    Trace {
+        #[arg(long)]
+        tx: u32,
+        #[arg(long)]
+        log: PathBuf,
+        #[command(flatten)]
+        compat: CompatArgs,
+    },
+    /// Print one client's statement with running balances, replayed from an event log
+    #[command(name = "statement")]
+    Statement {
+        #[arg(long)]
+        client: u16,
+        /// Event log to replay [default: the config's]
+        #[arg(long)]
+        log: Option<PathBuf>,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(flatten)]
+        compat: CompatArgs,
+        /// First event log position to list, 1-based; earlier events only make up the opening balance
+        #[arg(long)]
+        from: Option<usize>,
+        /// Last event log position to list, inclusive
+        #[arg(long)]
+        to: Option<usize>,
+        /// Output encoding: csv, json or table
+        #[arg(long, default_value = "csv")]
+        output_format: OutputFormat,
+        /// The server's RocksDB cold storage, read for when transactions were recorded
+        #[arg(long)]
+        storage_path: Option<PathBuf>,
+        /// The server's object store cold storage, read for when transactions were recorded
+        #[arg(long, conflicts_with = "storage_path")]
+        object_store_url: Option<String>,
+    },
+    /// Apply JSON transaction records from a Kafka topic, committing offsets once logged
+    #[command(name = "consume")]
+    Consume {
+        /// Comma-separated bootstrap brokers, host:port
+        #[arg(long)]
+        brokers: String,
+        #[arg(long)]
+        topic: String,
+        /// Consumer group, its committed offsets are where consuming resumes
+        #[arg(long, default_value = "payments-engine")]
+        group: String,
+        /// Event log replayed on startup and appended to while running [default: the config's, else consumer_transactions.log]
+        #[arg(long)]
+        log: Option<PathBuf>,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(flatten)]
+        compat: CompatArgs,
+        /// When logged events reach the disk, and so when offsets may be committed
+        #[arg(long, default_value = "per-write")]
+        durability: DurabilityPolicy,
+        /// Directory keeping registered tx ids, so redelivered records are refused as duplicates
+        #[arg(long)]
+        tx_registry_dir: Option<PathBuf>,
+        #[command(flatten)]
+        amounts: AmountArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Check a snapshot's checksum and invariants
+    Verify { file: PathBuf },
+    /// Load a verified snapshot, replay the log written after it and print the accounts
+    Restore {
+        file: PathBuf,
+        #[arg(long)]
+        log: PathBuf,
     },
 }
 
+/// Amount spellings accepted from producers, both rejected by default
+#[derive(Args)]
+struct AmountArgs {
+    /// Accept amounts in scientific notation, e.g. 1e3
+    #[arg(long)]
+    allow_scientific: bool,
+    /// Accept thousands separators, e.g. 1,000.00
+    #[arg(long)]
+    allow_thousands_separators: bool,
+}
+
+/// Spec interpretation, keep it fixed for a given event log: replay applies it too
+#[derive(Args)]
+struct CompatArgs {
+    /// Preset for the spec's ambiguous cases: strict or extended
+    #[arg(long, default_value = "strict")]
+    compat: CompatMode,
+    /// Allow disputing withdrawals whatever the preset: the amount is held, a chargeback returns it
+    #[arg(long)]
+    dispute_withdrawals: bool,
+    /// Deposits to a locked account: reject, or hold them until the account is unlocked
+    #[arg(long, default_value = "reject")]
+    lock_policy: LockPolicy,
+}
+
+impl CompatArgs {
+    fn config(&self) -> CompatConfig {
+        let mut config = CompatConfig::from(self.compat);
+        config.dispute_withdrawals |= self.dispute_withdrawals;
+        config.lock_policy = self.lock_policy;
+        config
+    }
+}
+
+/// Accounts written as client id range partitions instead of printed
+#[derive(Args)]
+struct ExportArgs {
+    /// Write the accounts as CSV partitions by client id range plus a manifest.json into this directory
+    #[arg(long, conflicts_with = "treasury")]
+    export_dir: Option<PathBuf>,
+    /// Client id ranges the export is split into, one file each
+    #[arg(long, default_value = "8", requires = "export_dir", value_parser = clap::value_parser!(u64).range(1..=65536))]
+    partitions: u64,
+}
+
+/// Where a batch run logs its events, nowhere by default
+#[derive(Args)]
+struct EventLogArgs {
+    /// Keep the event log at this path, continuing any events already in it
+    #[arg(long, conflicts_with = "no_event_log")]
+    event_log: Option<PathBuf>,
+    /// Keep no event log, the default unless the engine config names one
+    #[arg(long)]
+    no_event_log: bool,
+}
+
+impl EventLogArgs {
+    /// Engine config with the event log these flags ask for, the config's own if neither is given
+    fn apply(self, mut config: EngineConfig) -> EngineConfig {
+        if self.no_event_log {
+            config.event_log = None;
+        } else if let Some(path) = self.event_log {
+            config.event_log = Some(path);
+        }
+        config
+    }
+}
+
+/// Engine tunables: flags over `PAYMENTS_ENGINE_*` variables over the file's
+#[derive(Args)]
+struct ConfigArgs {
+    /// TOML file with engine tunables: shards, event_log, [actor] and [migration] limits
+    #[arg(long = "config")]
+    config_file: Option<PathBuf>,
+    /// Account shards processing clients in parallel, overriding the config [default: 2 per CPU, at least 4]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    shards: Option<u16>,
+}
+
+impl ConfigArgs {
+    fn load(&self) -> Result<EngineConfig> {
+        let mut config = EngineConfig::load(self.config_file.as_deref())?;
+        if let Some(shards) = self.shards {
+            config.shards = shards.into();
+        }
+        tracing::debug!(shards = config.shards, topology = ?CpuTopology::detect(), "Account shards");
+        Ok(config)
+    }
+}
+
+impl AmountArgs {
+    fn format(&self) -> AmountFormat {
+        AmountFormat {
+            allow_scientific: self.allow_scientific,
+            allow_thousands_separators: self.allow_thousands_separators,
+        }
+    }
+}
+
+fn output(treasury: bool, shard_stats: bool, export: ExportArgs) -> CliOutput {
+    match (treasury, shard_stats, export.export_dir) {
+        (true, _, _) => CliOutput::Treasury,
+        (false, true, _) => CliOutput::ShardStats,
+        (false, false, Some(dir)) => CliOutput::Partitioned(ExportConfig { dir, partitions: export.partitions as usize }),
+        (false, false, None) => CliOutput::Accounts,
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().collect();
     
     if args.len() == 2 && !args[1].starts_with('-') {
         // Direct file argument as per spec, no logging for clean stdout
-        cli::run(PathBuf::from(&args[1])).await?;
+        cli::run(
+            PathBuf::from(&args[1]),
+            InputOptions::default(),
+            CompatMode::Strict.into(),
+            CliOutput::Accounts,
+            OutputFormat::Csv,
+            EngineConfig::default(),
+            None,
+        )
+        .await?;
     } else {
         match Cli::parse() {
-            Cli::CliMode { input } => {
+            Cli::CliMode {
+                input,
+                format,
+                amount_units,
+                schema,
+                compat,
+                amounts,
+                treasury,
+                shard_stats,
+                export,
+                output_format,
+                event_log,
+                config,
+                rejects,
+            } => {
                 // CLI mode, no logging for clean stdout
-                cli::run(input).await?;
+                let options = InputOptions { format, units: amount_units, schema, amounts: amounts.format() };
+                let engine = event_log.apply(config.load()?);
+                cli::run(input, options, compat.config(), output(treasury, shard_stats, export), output_format, engine, rejects).await?;
+            }
+            Cli::Merge { inputs, compat, amounts, treasury, shard_stats, export, output_format, event_log, config } => {
+                let engine = event_log.apply(config.load()?);
+                cli::run_merged(inputs, compat.config(), amounts.format(), output(treasury, shard_stats, export), output_format, engine).await?;
+            }
+            Cli::ImportBalances { input, log } => {
+                cli::import_balances(input, log).await?;
+            }
+            Cli::ReplayRejects { rejects, map, log, compat, amounts } => {
+                cli::replay_rejects(rejects, map, log, compat.config(), amounts.format()).await?;
+            }
+            Cli::Soak {
+                connections,
+                duration_secs,
+                check_interval_secs,
+                batch_size,
+                max_rss_mb,
+            } => {
+                tracing_subscriber::fmt()
+                    .with_writer(std::io::stderr)
+                    .with_env_filter(
+                        EnvFilter::from_default_env()
+                            .add_directive(tracing::Level::INFO.into()),
+                    )
+                    .init();
+
+                let report = soak::run(SoakConfig {
+                    connections,
+                    duration: Duration::from_secs(duration_secs),
+                    check_interval: Duration::from_secs(check_interval_secs),
+                    batch_size,
+                    max_rss_bytes: max_rss_mb.map(|mb| mb * 1024 * 1024),
+                })
+                .await?;
+                println!(
+                    "soak passed: {} rows over {} connections, {} checks, peak RSS {}",
+                    report.rows,
+                    connections,
+                    report.checks,
+                    report
+                        .peak_rss_bytes
+                        .map(|bytes| format!("{} MiB", bytes / (1024 * 1024)))
+                        .unwrap_or_else(|| "unknown".to_string())
+                );
+            }
+            Cli::Snapshot(SnapshotCommand::Verify { file }) => {
+                cli::verify_snapshot(&file).await?;
+            }
+            Cli::Snapshot(SnapshotCommand::Restore { file, log }) => {
+                cli::restore_snapshot(&file, log).await?;
+            }
+            Cli::Trace { tx, log, compat } => {
+                trace::run(tx, log, compat.config()).await?;
+            }
+            Cli::Statement { client, log, config, compat, from, to, output_format, storage_path, object_store_url } => {
+                let Some(log) = log.or(config.load()?.event_log) else {
+                    anyhow::bail!("statement needs --log or an event_log in the config");
+                };
+                let cold_storage = open_cold_storage(storage_path, object_store_url)?;
+                statement::run(client, log, from, to, output_format, cold_storage, compat.config()).await?;
+            }
+            Cli::Consume { brokers, topic, group, log, config, compat, durability, tx_registry_dir, amounts } => {
+                tracing_subscriber::fmt()
+                    .with_writer(std::io::stderr)
+                    .with_env_filter(
+                        EnvFilter::from_default_env()
+                            .add_directive(tracing::Level::INFO.into()),
+                    )
+                    .init();
+                
+                let engine = config.load()?;
+                let config = ConsumeConfig {
+                    brokers,
+                    topic,
+                    group,
+                    event_log_path: log
+                        .or_else(|| engine.event_log.clone())
+                        .unwrap_or_else(|| PathBuf::from("consumer_transactions.log")),
+                    engine,
+                    compat: compat.config(),
+                    durability,
+                    tx_registry_dir,
+                    amounts: amounts.format(),
+                };
+                #[cfg(feature = "kafka")]
+                payments_engine::consume::run(config).await?;
+                #[cfg(not(feature = "kafka"))]
+                {
+                    let _ = config;
+                    anyhow::bail!("consume needs a build with the kafka feature");
+                }
             }
             Cli::Server {
                 bind,
                 max_connections,
+                http_bind,
+                swagger_ui,
+                grpc_bind,
+                log,
+                config,
+                compat,
+                storage_path,
+                object_store_url,
+                snapshot,
+                snapshot_interval_secs,
+                durability,
+                backfill,
+                cutover,
+                tx_registry_dir,
+                shutdown_grace_secs,
+                sequence_timeout_ms,
+                sequence_state,
+                routing,
+                escalation_webhook,
+                escalate_after_days,
+                escalate_chargebacks_over,
+                alert_webhook,
+                compact_every_hours,
+                spill_dir,
+                spill_after,
+                max_queue_depth,
+                busy_after_ms,
+                intake_log,
+                record_fixture,
+                record_sample,
+                record_rows,
+                probe_interval_secs,
+                api_keys,
+                export_dir,
+                export_partitions,
+                export_every_mins,
+                amounts,
             } => {
                 // Initialize logging only for server mode
                 tracing_subscriber::fmt()
@@ -46,10 +611,68 @@ async fn main() -> Result<()> {
                     )
                     .init();
                 
-                server::run(bind, max_connections).await?;
+                let engine = config.load()?;
+                server::run(ServerConfig {
+                    bind,
+                    max_connections,
+                    http_bind,
+                    swagger_ui,
+                    grpc_bind,
+                    event_log_path: log
+                        .or_else(|| engine.event_log.clone())
+                        .unwrap_or_else(|| PathBuf::from("server_transactions.log")),
+                    engine,
+                    compat: compat.config(),
+                    storage_path,
+                    object_store_url,
+                    snapshot: snapshot.map(|path| (path, Duration::from_secs(snapshot_interval_secs))),
+                    durability,
+                    backfill: backfill.map(|path| (path, cutover)),
+                    tx_registry_dir,
+                    shutdown_grace: Duration::from_secs(shutdown_grace_secs),
+                    sequence_timeout: Duration::from_millis(sequence_timeout_ms),
+                    sequence_state,
+                    routing,
+                    escalation: escalation_webhook.map(|url| {
+                        let policy = EscalationPolicy {
+                            max_open_age: Duration::from_secs(escalate_after_days * 24 * 60 * 60),
+                            high_value_chargeback: escalate_chargebacks_over,
+                        };
+                        (url, policy)
+                    }),
+                    alert_webhook,
+                    compaction_interval: compact_every_hours.map(|hours| Duration::from_secs(hours * 60 * 60)),
+                    spill: spill_dir.map(|dir| SpillConfig { dir, memory_rows: spill_after }),
+                    backpressure: max_queue_depth.map(|max_queue_depth| BackpressureConfig {
+                        max_queue_depth,
+                        busy_after: Duration::from_millis(busy_after_ms),
+                    }),
+                    intake_log,
+                    recording: record_fixture.map(|dir| RecorderConfig {
+                        dir,
+                        sample_rate: record_sample,
+                        max_rows: record_rows,
+                    }),
+                    probe_interval: probe_interval_secs.map(Duration::from_secs),
+                    api_keys,
+                    export: export_dir.map(|dir| {
+                        let config = ExportConfig { dir, partitions: export_partitions as usize };
+                        (config, Duration::from_secs(export_every_mins * 60))
+                    }),
+                    amounts: amounts.format(),
+                })
+                .await?;
             }
         }
     }
     
     Ok(())
 }
+
+fn parse_sample_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("{} is not between 0 and 1", rate));
+    }
+    Ok(rate)
+}
@@ -0,0 +1,145 @@
+use crate::amount::AmountFormat;
+use crate::compression::open_input;
+use crate::csv_io::stream_timed_transactions;
+use crate::models::TimedTransactionRow;
+use anyhow::Result;
+use futures::stream::{BoxStream, StreamExt};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+
+/// One sorted input of a merge
+struct MergeInput {
+    name: String,
+    rows: BoxStream<'static, Result<TimedTransactionRow>>,
+    last_timestamp: u64,
+    position: usize,
+}
+
+/// Head row of an input waiting in the merge heap
+struct Pending {
+    row: TimedTransactionRow,
+    input: usize,
+}
+
+impl Pending {
+    // Ties go to the input listed first, so corrections passed last apply last
+    fn key(&self) -> (u64, usize) {
+        (self.row.timestamp, self.input)
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// K-way merge of timestamp-sorted inputs into one deterministic stream
+///
+/// Order is by timestamp, then by position of the input in the list, then by
+/// position within the input. Rows that fail to parse are skipped, rows going
+/// back in time within their input end the merge with an error.
+pub struct MergedRows {
+    inputs: Vec<MergeInput>,
+    heap: BinaryHeap<Reverse<Pending>>,
+    primed: bool,
+}
+
+impl MergedRows {
+    /// Open each input, amounts spelled as `amounts` allows
+    pub async fn open(paths: &[PathBuf], amounts: AmountFormat) -> Result<Self> {
+        let mut inputs = Vec::with_capacity(paths.len());
+        for path in paths {
+            inputs.push(MergeInput {
+                name: path.display().to_string(),
+                rows: stream_timed_transactions(open_input(path).await?, amounts).boxed(),
+                last_timestamp: 0,
+                position: 0,
+            });
+        }
+
+        Ok(Self {
+            inputs,
+            heap: BinaryHeap::new(),
+            primed: false,
+        })
+    }
+
+    pub async fn next(&mut self) -> Option<Result<TimedTransactionRow>> {
+        Some(self.next_with_input().await?.map(|(row, _)| row))
+    }
+
+    /// Next row and the index of the input it came from, see `input_name`
+    pub async fn next_with_input(&mut self) -> Option<Result<(TimedTransactionRow, usize)>> {
+        if !self.primed {
+            self.primed = true;
+            for input in 0..self.inputs.len() {
+                if let Err(e) = self.advance(input).await {
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        let Reverse(Pending { row, input }) = self.heap.pop()?;
+        if let Err(e) = self.advance(input).await {
+            return Some(Err(e));
+        }
+
+        Some(Ok((row, input)))
+    }
+
+    /// Path of an input as it was opened
+    pub fn input_name(&self, input: usize) -> &str {
+        &self.inputs[input].name
+    }
+
+    /// Pull the next parseable row of an input into the heap
+    async fn advance(&mut self, index: usize) -> Result<()> {
+        let input = &mut self.inputs[index];
+        while let Some(result) = input.rows.next().await {
+            input.position += 1;
+            let Ok(row) = result else { continue };
+
+            if row.timestamp < input.last_timestamp {
+                anyhow::bail!(
+                    "{}: row {} (timestamp {}) is earlier than the row before it (timestamp {})",
+                    input.name,
+                    input.position,
+                    row.timestamp,
+                    input.last_timestamp
+                );
+            }
+            input.last_timestamp = row.timestamp;
+
+            self.heap.push(Reverse(Pending { row, input: index }));
+            break;
+        }
+
+        Ok(())
+    }
+}
+
+/// Check every input is sorted by timestamp without applying anything
+pub async fn validate_sorted(paths: &[PathBuf], amounts: AmountFormat) -> Result<()> {
+    let mut merged = MergedRows::open(paths, amounts).await?;
+    while let Some(result) = merged.next().await {
+        result?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// Hot-to-cold migration counters shared by all account actors
+#[derive(Default)]
+pub struct MigrationMetrics {
+    runs: AtomicU64,
+    migrated: AtomicU64,
+    failed: AtomicU64,
+    early: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+/// Point-in-time copy of the migration counters
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct MigrationMetricsSnapshot {
+    pub runs: u64,
+    pub migrated: u64,
+    pub failed: u64,
+    /// Migrated before the actor's hot window ran out, for low-risk clients
+    pub early: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+}
+
+impl MigrationMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one migration pass of a single actor
+    pub fn record_run(&self, migrated: u64, failed: u64, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.migrated.fetch_add(migrated, Ordering::Relaxed);
+        self.failed.fetch_add(failed, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Count transactions a low-risk client had migrated ahead of the hot window
+    pub fn record_early(&self, migrated: u64) {
+        self.early.fetch_add(migrated, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MigrationMetricsSnapshot {
+        MigrationMetricsSnapshot {
+            runs: self.runs.load(Ordering::Relaxed),
+            migrated: self.migrated.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            early: self.early.load(Ordering::Relaxed),
+            total_micros: self.total_micros.load(Ordering::Relaxed),
+            max_micros: self.max_micros.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// End-to-end latency of the prober's synthetic transactions
+#[derive(Default)]
+pub struct ProbeMetrics {
+    probes: AtomicU64,
+    failures: AtomicU64,
+    last_micros: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+/// Point-in-time copy of the probe counters
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct ProbeMetricsSnapshot {
+    /// Probe transactions applied and logged
+    pub probes: u64,
+    /// Probe transactions the engine refused, e.g. while the event log was unavailable
+    pub failures: u64,
+    pub last_micros: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+}
+
+impl ProbeMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one probe transaction that made it through the pipeline in `elapsed`
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+
+        self.probes.fetch_add(1, Ordering::Relaxed);
+        self.last_micros.store(micros, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ProbeMetricsSnapshot {
+        ProbeMetricsSnapshot {
+            probes: self.probes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            last_micros: self.last_micros.load(Ordering::Relaxed),
+            total_micros: self.total_micros.load(Ordering::Relaxed),
+            max_micros: self.max_micros.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::contention::{measure, Site};
+use crate::storage::{StoredTransaction, TransactionStore};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Limits applied to each hot-to-cold migration pass
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MigrationConfig {
+    /// Maximum transactions moved per pass, the rest wait for the next one
+    pub batch_size: usize,
+    /// Maximum cold-storage puts in flight at once
+    pub concurrency: usize,
+    /// Optional cap on cold-storage puts per second
+    pub max_puts_per_sec: Option<u32>,
+    /// Hot window of low-risk clients in days, 0 migrates on the next pass;
+    /// without it every client keeps the actor's `hot_cutoff_days`
+    pub low_risk_hot_days: Option<u64>,
+    /// Highest share of a client's transactions disputed for it to be low risk
+    pub low_risk_max_dispute_rate: f64,
+    /// Transactions a client needs on record before its dispute rate is trusted
+    pub low_risk_min_transactions: u64,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            concurrency: 4,
+            max_puts_per_sec: None,
+            low_risk_hot_days: None,
+            low_risk_max_dispute_rate: 0.001,
+            low_risk_min_transactions: 100,
+        }
+    }
+}
+
+impl MigrationConfig {
+    /// Days a client's transactions stay hot given its recorded activity
+    ///
+    /// A client with enough transactions and a dispute rate at or below the
+    /// threshold gets the low-risk window, never a longer one than `hot_cutoff_days`.
+    /// Its rare disputes then read their transaction from cold storage.
+    pub fn hot_days(&self, hot_cutoff_days: u64, transactions: u64, disputes: u64) -> u64 {
+        let Some(low_risk_days) = self.low_risk_hot_days else {
+            return hot_cutoff_days;
+        };
+        if transactions == 0 || transactions < self.low_risk_min_transactions {
+            return hot_cutoff_days;
+        }
+
+        let dispute_rate = disputes as f64 / transactions as f64;
+        if dispute_rate <= self.low_risk_max_dispute_rate {
+            low_risk_days.min(hot_cutoff_days)
+        } else {
+            hot_cutoff_days
+        }
+    }
+}
+
+/// Result of one migration pass, reported back to the owning actor
+#[derive(Debug)]
+pub struct MigrationOutcome {
+    /// Transactions written to cold storage, as they were when the batch was taken
+    pub migrated: Vec<(u32, StoredTransaction)>,
+    pub failed: usize,
+    pub elapsed: Duration,
+}
+
+/// Write a batch to cold storage within the configured concurrency and rate limits
+pub async fn migrate_batch(
+    cold_storage: Arc<dyn TransactionStore>,
+    batch: Vec<(u32, StoredTransaction)>,
+    config: &MigrationConfig,
+) -> MigrationOutcome {
+    let started = Instant::now();
+    let spacing = config
+        .max_puts_per_sec
+        .filter(|rate| *rate > 0)
+        .map(|rate| Duration::from_secs(1) / rate);
+
+    let results: Vec<_> = stream::iter(batch.into_iter().enumerate())
+        .map(|(idx, (tx_id, tx))| {
+            let cold_storage = cold_storage.clone();
+            async move {
+                // Each put gets a fixed slot so the rate holds regardless of concurrency
+                if let Some(spacing) = spacing {
+                    tokio::time::sleep_until(started + spacing * idx as u32).await;
+                }
+
+                let result = measure(Site::ColdStorage, cold_storage.put(tx_id, tx.clone())).await;
+                if let Err(e) = &result {
+                    tracing::error!(
+                        client_id = tx.client,
+                        tx_id = tx_id,
+                        error = ?e,
+                        "Failed to migrate transaction to cold storage - keeping in hot storage"
+                    );
+                }
+                (tx_id, tx, result.is_ok())
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut migrated = Vec::new();
+    let mut failed = 0;
+    for (tx_id, tx, ok) in results {
+        if ok {
+            migrated.push((tx_id, tx));
+        } else {
+            failed += 1;
+        }
+    }
+
+    MigrationOutcome {
+        migrated,
+        failed,
+        elapsed: started.elapsed(),
+    }
+}
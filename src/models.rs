@@ -1,12 +1,19 @@
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub client: u16,
     pub available: Decimal,
     pub held: Decimal,
     pub locked: bool,
+    /// Part of `held`: deposits accepted while locked under `LockPolicy::Hold`, released on unlock
+    #[serde(default)]
+    pub held_on_lock: Decimal,
 }
 
 impl Account {
@@ -16,6 +23,7 @@ impl Account {
             available: Decimal::ZERO,
             held: Decimal::ZERO,
             locked: false,
+            held_on_lock: Decimal::ZERO,
         }
     }
     
@@ -24,27 +32,142 @@ impl Account {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    /// Initial balance seeded when migrating an account from a legacy system
+    OpeningBalance,
+    /// Move available funds from `client` to the row's `to` client
+    Transfer,
+    /// Administrative unlock after a chargeback, logged by the engine, never sent by producers
+    Unlock,
+    /// Hold available funds for a later capture, without moving them
+    Authorize,
+    /// Turn the hold of the authorize under this tx id into a withdrawal
+    Capture,
+    /// Release the hold of the authorize under this tx id back to available
+    Void,
+    /// Extension type, applied by the handler registered under this name
+    Custom(String),
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl Serialize for TransactionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TransactionType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_transaction_type(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A string: a built-in type name or the name of a registered custom type
+impl utoipa::PartialSchema for TransactionType {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .description(Some("deposit, withdrawal, dispute, resolve, chargeback, transfer, authorize, capture, void, or a registered custom type"))
+            .into()
+    }
+}
+
+impl ToSchema for TransactionType {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct TransactionRow {
     #[serde(rename = "type")]
     pub tx_type: TransactionType,
     pub client: u16,
     pub tx: u32,
+    #[serde(default, deserialize_with = "crate::amount::deserialize")]
+    pub amount: Option<Decimal>,
+    /// Receiving client of a transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<u16>,
+}
+
+/// Transaction row stamped with when it happened, as found in merge inputs
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimedTransactionRow {
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub client: u16,
+    pub tx: u32,
+    #[serde(default, deserialize_with = "crate::amount::deserialize")]
+    pub amount: Option<Decimal>,
     #[serde(default)]
+    pub to: Option<u16>,
+    /// Unix seconds
+    pub timestamp: u64,
+}
+
+impl TimedTransactionRow {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    pub fn into_row(self) -> TransactionRow {
+        TransactionRow {
+            tx_type: self.tx_type,
+            client: self.client,
+            tx: self.tx,
+            amount: self.amount,
+            to: self.to,
+        }
+    }
+}
+
+/// Transaction row numbered by its producer, applied in that order per client
+#[derive(Debug, Clone, Deserialize)]
+pub struct SequencedTransactionRow {
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub client: u16,
+    pub tx: u32,
+    #[serde(default, deserialize_with = "crate::amount::deserialize")]
     pub amount: Option<Decimal>,
+    #[serde(default)]
+    pub to: Option<u16>,
+    /// Position among the client's rows across every connection, from 1
+    pub seq: u64,
 }
 
-#[derive(Debug)]
+impl SequencedTransactionRow {
+    pub fn into_row(self) -> TransactionRow {
+        TransactionRow {
+            tx_type: self.tx_type,
+            client: self.client,
+            tx: self.tx,
+            amount: self.amount,
+            to: self.to,
+        }
+    }
+}
+
+/// Row of an opening balances import file
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpeningBalanceRow {
+    pub client: u16,
+    pub amount: Decimal,
+}
+
+/// Row of a corrections file: replace `field` of the row rejected under `tx` with `value`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CorrectionRow {
+    pub tx: u32,
+    pub field: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AccountOutput {
     pub client: u16,
     pub available: Decimal,
@@ -65,16 +188,48 @@ impl From<&Account> for AccountOutput {
     }
 }
 
-impl TransactionRow {
-    pub fn tx_type_str(&self) -> &str {
-        match self.tx_type {
+impl TransactionType {
+    pub fn as_str(&self) -> &str {
+        match self {
             TransactionType::Deposit => "deposit",
             TransactionType::Withdrawal => "withdrawal",
             TransactionType::Dispute => "dispute",
             TransactionType::Resolve => "resolve",
             TransactionType::Chargeback => "chargeback",
+            TransactionType::OpeningBalance => "opening_balance",
+            TransactionType::Transfer => "transfer",
+            TransactionType::Unlock => "unlock",
+            TransactionType::Authorize => "authorize",
+            TransactionType::Capture => "capture",
+            TransactionType::Void => "void",
+            TransactionType::Custom(name) => name,
         }
     }
+    
+    /// Whether producers may send this type, the others come only from the engine's own APIs
+    pub fn accepted_from_producers(&self) -> bool {
+        !matches!(self, TransactionType::OpeningBalance | TransactionType::Unlock)
+    }
+    
+    /// Whether this built-in type introduces a new tx id (as opposed to referencing one)
+    ///
+    /// Custom types answer through their handler, see `HandlerRegistry::creates_tx`.
+    pub fn creates_tx(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Deposit
+                | TransactionType::Withdrawal
+                | TransactionType::OpeningBalance
+                | TransactionType::Transfer
+                | TransactionType::Authorize
+        )
+    }
+}
+
+impl TransactionRow {
+    pub fn tx_type_str(&self) -> &str {
+        self.tx_type.as_str()
+    }
 }
 
 pub fn parse_transaction_type(s: &str) -> Result<TransactionType, anyhow::Error> {
@@ -84,6 +239,13 @@ pub fn parse_transaction_type(s: &str) -> Result<TransactionType, anyhow::Error>
         "dispute" => Ok(TransactionType::Dispute),
         "resolve" => Ok(TransactionType::Resolve),
         "chargeback" => Ok(TransactionType::Chargeback),
-        _ => anyhow::bail!("Unknown transaction type: {}", s),
+        "opening_balance" => Ok(TransactionType::OpeningBalance),
+        "transfer" => Ok(TransactionType::Transfer),
+        "unlock" => Ok(TransactionType::Unlock),
+        "authorize" => Ok(TransactionType::Authorize),
+        "capture" => Ok(TransactionType::Capture),
+        "void" => Ok(TransactionType::Void),
+        "" => anyhow::bail!("Missing transaction type"),
+        other => Ok(TransactionType::Custom(other.to_string())),
     }
 }
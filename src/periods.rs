@@ -0,0 +1,97 @@
+use crate::reporting::{month_key, tmp_path};
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+/// Closed accounting periods (calendar months, "YYYY-MM")
+///
+/// Once a month is closed the engine rejects events timestamped inside it and
+/// its reporting counters stop changing.
+#[derive(Default)]
+pub struct AccountingPeriods {
+    closed: RwLock<BTreeSet<String>>,
+    path: Mutex<Option<PathBuf>>,
+}
+
+impl AccountingPeriods {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_closed(&self, month: &str) -> bool {
+        self.closed.read().unwrap().contains(month)
+    }
+
+    /// Whether an event at `at` falls inside a closed period
+    pub fn is_fenced(&self, at: SystemTime) -> bool {
+        self.is_closed(&month_key(at))
+    }
+
+    /// Closed months, oldest first
+    pub fn closed(&self) -> Vec<String> {
+        self.closed.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Close a month, returns false if it was already closed
+    ///
+    /// Only months that have ended can be closed, events may still arrive for the current one.
+    pub async fn close(&self, month: &str) -> Result<bool> {
+        if !is_valid_month(month) {
+            anyhow::bail!("Invalid period: {} (expected YYYY-MM)", month);
+        }
+        if month >= month_key(SystemTime::now()).as_str() {
+            anyhow::bail!("Period {} has not ended", month);
+        }
+
+        if !self.closed.write().unwrap().insert(month.to_string()) {
+            return Ok(false);
+        }
+
+        // Closing is rare and must survive restarts, persist right away
+        self.flush().await?;
+        Ok(true)
+    }
+
+    /// Load periods closed at `path` (if any) and persist later closes there
+    pub async fn attach_file(&self, path: PathBuf) -> Result<()> {
+        if path.exists() {
+            let content = tokio::fs::read_to_string(&path).await?;
+            let mut closed = self.closed.write().unwrap();
+            for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                if !is_valid_month(line) {
+                    anyhow::bail!("Invalid period line: {}", line);
+                }
+                closed.insert(line.to_string());
+            }
+        }
+
+        *self.path.lock().unwrap() = Some(path);
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let Some(path) = self.path.lock().unwrap().clone() else {
+            return Ok(());
+        };
+
+        let mut content = self.closed().join("\n");
+        content.push('\n');
+        let tmp = tmp_path(&path);
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+}
+
+fn is_valid_month(month: &str) -> bool {
+    let Some((year, mm)) = month.split_once('-') else {
+        return false;
+    };
+
+    year.len() == 4
+        && year.bytes().all(|b| b.is_ascii_digit())
+        && mm.len() == 2
+        && matches!(mm.parse::<u8>(), Ok(1..=12))
+}
@@ -0,0 +1,76 @@
+use crate::errors::ProcessingError;
+use crate::id_allocator::INTERNAL_ID_RANGE_START;
+use crate::metrics::ProbeMetrics;
+use crate::models::{TransactionRow, TransactionType};
+use crate::scalable_engine::ScalableEngine;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Client the probe transactions are booked to, refused to producers while a prober runs
+pub const PROBE_CLIENT: u16 = u16::MAX;
+
+/// Deposited, then withdrawn again, by every probe
+pub const PROBE_AMOUNT: Decimal = dec!(0.0001);
+
+/// Whether `row` is one of the prober's: the probe client, under an engine-generated tx id
+pub fn is_probe(row: &TransactionRow) -> bool {
+    row.client == PROBE_CLIENT && row.tx >= INTERNAL_ID_RANGE_START
+}
+
+/// Periodically pushes a synthetic deposit and withdrawal through the engine, timing each
+///
+/// Probes take the path of any live row: they wait for a backfill to hand
+/// over, then go through the tx id registry, the probe client's actor and the
+/// event log, and are acknowledged only once logged. Each probe leaves the
+/// probe account at zero and discards its transactions, so neither storage
+/// tier grows with them.
+pub struct LatencyProber {
+    metrics: Arc<ProbeMetrics>,
+}
+
+impl LatencyProber {
+    pub fn new(metrics: Arc<ProbeMetrics>) -> Self {
+        Self { metrics }
+    }
+
+    /// Run one probe: a deposit and its withdrawal, each recorded with its latency
+    pub async fn probe(&self, engine: &ScalableEngine) -> Result<(), ProcessingError> {
+        if engine.ingestion().wait_live().await.is_err() {
+            // A failed backfill refuses live rows, probes included
+            self.metrics.record_failure();
+            return Err(ProcessingError::RebuildPending);
+        }
+
+        for tx_type in [TransactionType::Deposit, TransactionType::Withdrawal] {
+            let started = Instant::now();
+            match engine.apply_probe(tx_type).await {
+                Ok(()) => self.metrics.record(started.elapsed()),
+                Err(e) => {
+                    self.metrics.record_failure();
+                    return Err(e);
+                }
+            }
+        }
+
+        engine.discard_probes().await;
+        Ok(())
+    }
+
+    /// Probe `engine` every `interval`, the first probe after one interval
+    pub fn spawn(self: Arc<Self>, engine: Arc<ScalableEngine>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.probe(&engine).await {
+                    tracing::warn!(error = ?e, "Latency probe failed");
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use crate::contention;
+use crate::models::Account;
+use crate::treasury::TreasuryReport;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+
+/// Eventually consistent read model of account balances
+///
+/// Actors publish their state after every applied transaction; a background
+/// task folds those updates in, so reads never wait on actor mailboxes but may
+/// trail the latest writes. Treasury totals are folded in alongside, so
+/// reading them never scans the accounts.
+pub struct AccountProjection {
+    state: RwLock<ProjectionState>,
+}
+
+#[derive(Default)]
+struct ProjectionState {
+    accounts: HashMap<u16, Account>,
+    treasury: TreasuryReport,
+}
+
+impl AccountProjection {
+    /// Start the projection task, returning the read model and the sender actors publish to
+    pub fn spawn() -> (Arc<Self>, mpsc::UnboundedSender<Account>) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Account>();
+        let projection = Arc::new(Self {
+            state: RwLock::new(ProjectionState::default()),
+        });
+
+        let target = projection.clone();
+        contention::spawn(async move {
+            while let Some(account) = rx.recv().await {
+                let mut state = target.state.write().unwrap();
+                let ProjectionState { accounts, treasury } = &mut *state;
+                treasury.update(accounts.get(&account.client), &account);
+                accounts.insert(account.client, account);
+            }
+        });
+
+        (projection, tx)
+    }
+
+    pub fn get(&self, client_id: u16) -> Option<Account> {
+        self.state.read().unwrap().accounts.get(&client_id).cloned()
+    }
+
+    /// The listed clients' accounts in the order given, unknown clients left out
+    pub fn get_many(&self, client_ids: &[u16]) -> Vec<Account> {
+        let state = self.state.read().unwrap();
+        client_ids.iter().filter_map(|client_id| state.accounts.get(client_id).cloned()).collect()
+    }
+
+    pub fn all(&self) -> Vec<Account> {
+        self.state.read().unwrap().accounts.values().cloned().collect()
+    }
+
+    pub fn treasury(&self) -> TreasuryReport {
+        self.state.read().unwrap().treasury.clone()
+    }
+}
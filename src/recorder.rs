@@ -0,0 +1,168 @@
+use crate::config::EngineConfig;
+use crate::csv_io::write_accounts;
+use crate::event_store::InMemoryEventLog;
+use crate::models::{AccountOutput, TransactionRow, TransactionType};
+use crate::scalable_engine::ScalableEngine;
+use crate::storage::InMemoryStore;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Rows a recording keeps before it writes its bundle
+pub const DEFAULT_RECORD_ROWS: usize = 10_000;
+
+/// Where a recording goes and how much of the traffic it takes
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Bundle directory, laid out as a golden fixture case
+    pub dir: PathBuf,
+    /// Share of clients recorded, 0 to 1
+    pub sample_rate: f64,
+    pub max_rows: usize,
+}
+
+/// Records a sampled, anonymized slice of live rows as a golden fixture case
+///
+/// Clients are sampled as a whole, so a recorded client's rows are all there
+/// from the moment recording starts. Client and tx ids are renumbered from 1
+/// in order of first appearance; amounts are kept. The bundle holds the rows
+/// as `input.csv` and, as `expected_accounts.csv`, the accounts replaying them
+/// into a fresh engine gives, so it can be copied under `tests/fixtures/golden/`
+/// as is. References to transactions from before recording started don't
+/// resolve in the replay and come out as invalid references.
+pub struct FixtureRecorder {
+    config: RecorderConfig,
+    state: Mutex<Recording>,
+}
+
+#[derive(Default)]
+struct Recording {
+    rows: Vec<TransactionRow>,
+    clients: HashMap<u16, u16>,
+    txs: HashMap<u32, u32>,
+    finished: bool,
+}
+
+impl Recording {
+    fn client(&mut self, client: u16) -> u16 {
+        let next = self.clients.len() as u16 + 1;
+        *self.clients.entry(client).or_insert(next)
+    }
+
+    fn tx(&mut self, tx: u32) -> u32 {
+        let next = self.txs.len() as u32 + 1;
+        *self.txs.entry(tx).or_insert(next)
+    }
+}
+
+impl FixtureRecorder {
+    pub fn new(config: RecorderConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(Recording::default()),
+        }
+    }
+
+    pub fn config(&self) -> &RecorderConfig {
+        &self.config
+    }
+
+    /// Whether `client` is in the sample, the same answer every time
+    pub fn samples(&self, client: u16) -> bool {
+        let mut hasher = DefaultHasher::new();
+        client.hash(&mut hasher);
+        let position = (hasher.finish() % 1_000_000) as f64 / 1_000_000.0;
+        position < self.config.sample_rate
+    }
+
+    /// Rows recorded so far
+    pub fn recorded(&self) -> usize {
+        self.state.lock().unwrap().rows.len()
+    }
+
+    /// Record a row in the order it was applied or refused, returns true once the recording is full
+    pub fn record(&self, row: &TransactionRow) -> bool {
+        if !self.samples(row.client) {
+            return false;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.finished || state.rows.len() >= self.config.max_rows {
+            return false;
+        }
+        // Renumbering starts at 1, a 65536th distinct client has no id left
+        if state.clients.len() >= u16::MAX as usize && !state.clients.contains_key(&row.client) {
+            return false;
+        }
+
+        let anonymized = TransactionRow {
+            tx_type: row.tx_type.clone(),
+            client: state.client(row.client),
+            tx: state.tx(row.tx),
+            amount: row.amount,
+            to: row.to.map(|to| state.client(to)),
+        };
+        state.rows.push(anonymized);
+        state.rows.len() >= self.config.max_rows
+    }
+
+    /// Write the bundle, once; later calls and recordings with no rows write nothing
+    ///
+    /// Returns the bundle directory if this call wrote it.
+    pub async fn finish(&self) -> Result<Option<PathBuf>> {
+        let rows = {
+            let mut state = self.state.lock().unwrap();
+            if state.finished || state.rows.is_empty() {
+                return Ok(None);
+            }
+            state.finished = true;
+            std::mem::take(&mut state.rows)
+        };
+
+        let expected = replay(&rows).await?;
+        tokio::fs::create_dir_all(&self.config.dir).await?;
+        tokio::fs::write(self.config.dir.join("input.csv"), to_csv(&rows)).await?;
+        tokio::fs::write(self.config.dir.join("expected_accounts.csv"), expected).await?;
+        Ok(Some(self.config.dir.clone()))
+    }
+}
+
+/// Rows as the golden runner reads them, with a `to` column only when a transfer needs one
+fn to_csv(rows: &[TransactionRow]) -> String {
+    let transfers = rows.iter().any(|row| row.tx_type == TransactionType::Transfer);
+    let mut out = String::from(if transfers { "type,client,tx,amount,to\n" } else { "type,client,tx,amount\n" });
+    for row in rows {
+        let amount = row.amount.map(|amount| amount.to_string()).unwrap_or_default();
+        let _ = write!(out, "{},{},{},{}", row.tx_type.as_str(), row.client, row.tx, amount);
+        if transfers {
+            let to = row.to.map(|to| to.to_string()).unwrap_or_default();
+            let _ = write!(out, ",{}", to);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Accounts from applying `rows` one by one to a fresh engine, as the golden runner does
+async fn replay(rows: &[TransactionRow]) -> Result<String> {
+    let engine = ScalableEngine::from_event_log(
+        Arc::new(InMemoryEventLog::new()),
+        &EngineConfig::default(),
+        Arc::new(InMemoryStore::new()),
+    )
+    .await?;
+    for row in rows {
+        let _ = engine.process(row.clone()).await;
+    }
+
+    let mut accounts: Vec<AccountOutput> = engine.get_accounts().await.iter().map(AccountOutput::from).collect();
+    accounts.sort_by_key(|a| a.client);
+    engine.shutdown().await?;
+
+    let mut out = Vec::new();
+    write_accounts(&mut out, accounts).await?;
+    Ok(String::from_utf8(out)?)
+}
@@ -0,0 +1,106 @@
+use crate::errors::ProcessingError;
+use crate::models::TransactionRow;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+/// Input row a run did not apply, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedRow {
+    /// The row as decoded, `None` when it could not be
+    #[serde(flatten)]
+    pub row: Option<TransactionRow>,
+    /// 1-based line number in the input
+    pub line: u64,
+    /// `malformed` for rows that could not be decoded, the error code otherwise
+    pub code: &'static str,
+    pub reason: String,
+    pub raw: String,
+}
+
+impl RejectedRow {
+    pub fn malformed(line: u64, raw: String, error: &anyhow::Error) -> Self {
+        Self {
+            row: None,
+            line,
+            code: "malformed",
+            reason: error.to_string(),
+            raw,
+        }
+    }
+
+    pub fn rejected(line: u64, raw: String, row: TransactionRow, error: &ProcessingError) -> Self {
+        Self {
+            row: Some(row),
+            line,
+            code: error.code(),
+            reason: error.to_string(),
+            raw,
+        }
+    }
+}
+
+/// Transaction columns first, so `replay-rejects` reads the report back as input
+const CSV_HEADER: &str = "type,client,tx,amount,to,code,reason,line,raw";
+
+/// File the rejected rows of a run are written to, for reconciling the input
+///
+/// JSON Lines when the path ends in `.json` or `.jsonl`, CSV otherwise. Rows
+/// that could not be decoded leave the transaction columns empty.
+pub struct RejectsReport {
+    writer: BufWriter<File>,
+    json: bool,
+    rows: u64,
+}
+
+impl RejectsReport {
+    pub async fn create(path: &Path) -> Result<Self> {
+        let json = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("json" | "jsonl")
+        );
+        let mut writer = BufWriter::new(File::create(path).await?);
+        if !json {
+            writer.write_all(format!("{}\n", CSV_HEADER).as_bytes()).await?;
+        }
+
+        Ok(Self { writer, json, rows: 0 })
+    }
+
+    pub async fn write(&mut self, row: &RejectedRow) -> Result<()> {
+        let line = if self.json {
+            let mut line = serde_json::to_string(row)?;
+            line.push('\n');
+            line
+        } else {
+            let transaction = match &row.row {
+                Some(tx) => format!(
+                    "{},{},{},{},{}",
+                    tx.tx_type.as_str(),
+                    tx.client,
+                    tx.tx,
+                    tx.amount.map(|amount| amount.to_string()).unwrap_or_default(),
+                    tx.to.map(|to| to.to_string()).unwrap_or_default()
+                ),
+                None => ",,,,".to_string(),
+            };
+            format!("{},{},{},{},{}\n", transaction, row.code, quote(&row.reason), row.line, quote(&row.raw))
+        };
+        self.writer.write_all(line.as_bytes()).await?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Flush the file, returning the number of rows written
+    pub async fn finish(mut self) -> Result<u64> {
+        self.writer.flush().await?;
+        Ok(self.rows)
+    }
+}
+
+/// Quote a CSV field, raw rows and error messages hold commas
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
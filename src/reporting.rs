@@ -0,0 +1,265 @@
+use crate::models::{TransactionRow, TransactionType};
+use crate::periods::AccountingPeriods;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+/// Per-client activity within one calendar month (UTC)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct MonthlyCounts {
+    pub client: u16,
+    /// Month as "YYYY-MM"
+    pub month: String,
+    pub transactions: u64,
+    pub disputes: u64,
+    pub chargebacks: u64,
+}
+
+/// Kind of activity counted for chargeback-ratio reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterKind {
+    Transaction,
+    Dispute,
+    Chargeback,
+}
+
+/// Monthly transaction, dispute and chargeback counters per client
+///
+/// Persisted to their own CSV file together with the event log offset they
+/// cover. Events logged past that offset (counted by a run that crashed before
+/// flushing) are counted again on replay, in the month they are recovered in:
+/// the log carries no timestamps.
+#[derive(Default)]
+pub struct ReportingCounters {
+    counts: Mutex<BTreeMap<(u16, String), MonthlyCounts>>,
+    path: Mutex<Option<PathBuf>>,
+    periods: Arc<AccountingPeriods>,
+    excluded: Mutex<BTreeSet<u16>>,
+    // Log offset the counts include every event before, UNKNOWN_OFFSET for files that don't say
+    counted_offset: AtomicU64,
+}
+
+const CSV_HEADER: &str = "client,month,transactions,disputes,chargebacks";
+
+/// First line of a persisted counters file, before the CSV header
+const OFFSET_PREFIX: &str = "# log_offset=";
+
+const UNKNOWN_OFFSET: u64 = u64::MAX;
+
+impl ReportingCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_periods(periods: Arc<AccountingPeriods>) -> Self {
+        Self {
+            periods,
+            ..Self::default()
+        }
+    }
+
+    /// Periods whose counters are frozen
+    pub fn periods(&self) -> &Arc<AccountingPeriods> {
+        &self.periods
+    }
+
+    /// Stop counting `client`, whose traffic is not a customer's (the latency prober's)
+    pub fn exclude(&self, client: u16) {
+        self.excluded.lock().unwrap().insert(client);
+    }
+
+    pub fn record(&self, client: u16, kind: CounterKind, at: SystemTime) {
+        if self.excluded.lock().unwrap().contains(&client) {
+            return;
+        }
+        let month = month_key(at);
+        // Reports of a closed period are final
+        if self.periods.is_closed(&month) {
+            return;
+        }
+
+        let mut counts = self.counts.lock().unwrap();
+        let entry = counts
+            .entry((client, month.clone()))
+            .or_insert_with(|| MonthlyCounts {
+                client,
+                month,
+                ..Default::default()
+            });
+
+        match kind {
+            CounterKind::Transaction => entry.transactions += 1,
+            CounterKind::Dispute => entry.disputes += 1,
+            CounterKind::Chargeback => entry.chargebacks += 1,
+        }
+    }
+
+    /// Take back a `record` whose transaction was reverted
+    pub fn forget(&self, client: u16, kind: CounterKind, at: SystemTime) {
+        let month = month_key(at);
+        if self.periods.is_closed(&month) {
+            return;
+        }
+
+        let mut counts = self.counts.lock().unwrap();
+        let Some(entry) = counts.get_mut(&(client, month)) else {
+            return;
+        };
+        let count = match kind {
+            CounterKind::Transaction => &mut entry.transactions,
+            CounterKind::Dispute => &mut entry.disputes,
+            CounterKind::Chargeback => &mut entry.chargebacks,
+        };
+        *count = count.saturating_sub(1);
+    }
+
+    /// Count a replayed log event the persisted counters did not include
+    pub fn record_logged(&self, tx: &TransactionRow, at: SystemTime) {
+        let kind = match tx.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+                CounterKind::Transaction
+            }
+            TransactionType::Dispute => CounterKind::Dispute,
+            TransactionType::Chargeback => CounterKind::Chargeback,
+            _ => return,
+        };
+        self.record(tx.client, kind, at);
+    }
+
+    /// Event log offset from which replayed events still need counting, None when unknown
+    ///
+    /// Files written before offsets were recorded leave it unknown, and nothing is recounted.
+    pub fn counted_offset(&self) -> Option<u64> {
+        match self.counted_offset.load(Ordering::SeqCst) {
+            UNKNOWN_OFFSET => None,
+            offset => Some(offset),
+        }
+    }
+
+    /// A client's transactions and disputes across every month on record
+    pub fn client_totals(&self, client: u16) -> (u64, u64) {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .range((client, String::new())..)
+            .take_while(|((c, _), _)| *c == client)
+            .fold((0, 0), |(transactions, disputes), (_, c)| {
+                (transactions + c.transactions, disputes + c.disputes)
+            })
+    }
+
+    /// All counters, optionally limited to one month, ordered by client then month
+    pub fn report(&self, month: Option<&str>) -> Vec<MonthlyCounts> {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .values()
+            .filter(|c| month.is_none_or(|m| c.month == m))
+            .cloned()
+            .collect()
+    }
+
+    pub fn to_csv(&self, month: Option<&str>) -> String {
+        let mut out = String::from(CSV_HEADER);
+        out.push('\n');
+        for c in self.report(month) {
+            out.push_str(&format!(
+                "{},{},{},{},{}\n",
+                c.client, c.month, c.transactions, c.disputes, c.chargebacks
+            ));
+        }
+        out
+    }
+
+    /// Load counters persisted at `path` (if any) and keep persisting there
+    ///
+    /// Attached before the event log is replayed, so replay knows what they already count.
+    pub async fn attach_file(&self, path: PathBuf) -> Result<()> {
+        if path.exists() {
+            let content = tokio::fs::read_to_string(&path).await?;
+            let mut lines = content.lines().peekable();
+            let offset = match lines.peek().and_then(|line| line.strip_prefix(OFFSET_PREFIX)) {
+                Some(offset) => offset.trim().parse()?,
+                None => UNKNOWN_OFFSET,
+            };
+            if offset != UNKNOWN_OFFSET {
+                lines.next();
+            }
+
+            let mut counts = self.counts.lock().unwrap();
+            for line in lines.skip(1) {
+                let c = parse_line(line)?;
+                counts.insert((c.client, c.month.clone()), c);
+            }
+            self.counted_offset.store(offset, Ordering::SeqCst);
+        }
+
+        *self.path.lock().unwrap() = Some(path);
+        Ok(())
+    }
+
+    /// Write counters to the attached file, no-op when none is attached
+    ///
+    /// `log_offset` is where the event log ended when the counts were taken,
+    /// see `ScalableEngine::flush_counters` for taking both together.
+    pub async fn flush(&self, log_offset: Option<u64>) -> Result<()> {
+        let Some(path) = self.path.lock().unwrap().clone() else {
+            return Ok(());
+        };
+
+        let content = match log_offset {
+            Some(offset) => format!("{}{}\n{}", OFFSET_PREFIX, offset, self.to_csv(None)),
+            None => self.to_csv(None),
+        };
+
+        // Write then rename so a crash never leaves a truncated file
+        let tmp = tmp_path(&path);
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+}
+
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn parse_line(line: &str) -> Result<MonthlyCounts> {
+    let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+    if parts.len() != 5 {
+        anyhow::bail!("Invalid counters line: {}", line);
+    }
+
+    Ok(MonthlyCounts {
+        client: parts[0].parse()?,
+        month: parts[1].to_string(),
+        transactions: parts[2].parse()?,
+        disputes: parts[3].parse()?,
+        chargebacks: parts[4].parse()?,
+    })
+}
+
+/// UTC calendar month of a timestamp as "YYYY-MM"
+pub fn month_key(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+
+    // Civil-from-days (Howard Hinnant), valid for the whole Unix era
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}", year, month)
+}
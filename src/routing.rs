@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Picks the shard that owns a client's actor
+///
+/// Must stay the same for the life of an engine, a client moved to another
+/// shard would get a second actor; `ShardManager::reshard` moves the actors
+/// along when the shard count changes. Shards past the last one wrap around.
+pub trait RoutingStrategy: Send + Sync {
+    fn shard(&self, client: u16, num_shards: usize) -> usize;
+}
+
+/// `client % num_shards`, the default
+///
+/// Even spread for sequential ids, but ids sharing a factor with the shard
+/// count (e.g. only even ids over 16 shards) leave shards idle.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModuloRouting;
+
+impl RoutingStrategy for ModuloRouting {
+    fn shard(&self, client: u16, num_shards: usize) -> usize {
+        client as usize % num_shards
+    }
+}
+
+/// Jump consistent hash of the mixed client id
+///
+/// Spreads any id pattern evenly, and growing the shard count from n to n + 1
+/// moves only about 1 / (n + 1) of the clients.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsistentHashRouting;
+
+impl RoutingStrategy for ConsistentHashRouting {
+    fn shard(&self, client: u16, num_shards: usize) -> usize {
+        jump_hash(mix(client as u64), num_shards)
+    }
+}
+
+/// SplitMix64 finalizer, neighbouring ids end up far apart
+fn mix(mut key: u64) -> u64 {
+    key = key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    key = (key ^ (key >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    key = (key ^ (key >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    key ^ (key >> 31)
+}
+
+/// Lamping and Veach, "A Fast, Minimal Memory, Consistent Hash Algorithm"
+fn jump_hash(mut key: u64, buckets: usize) -> usize {
+    let mut bucket = 0;
+    let mut next = 0u64;
+    while next < buckets as u64 {
+        bucket = next as usize;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        next = ((bucket as f64 + 1.0) * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as u64;
+    }
+    bucket
+}
+
+/// Explicit client to shard assignments, other clients routed by a fallback
+///
+/// For pinning known heavy clients to shards of their own.
+pub struct TableRouting {
+    table: HashMap<u16, usize>,
+    fallback: Arc<dyn RoutingStrategy>,
+}
+
+impl TableRouting {
+    pub fn new(table: HashMap<u16, usize>) -> Self {
+        Self {
+            table,
+            fallback: Arc::new(ModuloRouting),
+        }
+    }
+
+    /// Route clients missing from the table with `fallback` instead of modulo
+    pub fn with_fallback(mut self, fallback: Arc<dyn RoutingStrategy>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+}
+
+impl RoutingStrategy for TableRouting {
+    fn shard(&self, client: u16, num_shards: usize) -> usize {
+        match self.table.get(&client) {
+            Some(&shard) => shard,
+            None => self.fallback.shard(client, num_shards),
+        }
+    }
+}
+
+/// Named routing strategy, for picking one from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoutingMode {
+    #[default]
+    Modulo,
+    ConsistentHash,
+}
+
+impl RoutingMode {
+    pub fn strategy(self) -> Arc<dyn RoutingStrategy> {
+        match self {
+            RoutingMode::Modulo => Arc::new(ModuloRouting),
+            RoutingMode::ConsistentHash => Arc::new(ConsistentHashRouting),
+        }
+    }
+}
+
+impl FromStr for RoutingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "modulo" => Ok(RoutingMode::Modulo),
+            "consistent-hash" => Ok(RoutingMode::ConsistentHash),
+            other => anyhow::bail!("Unknown routing '{}', expected modulo or consistent-hash", other),
+        }
+    }
+}
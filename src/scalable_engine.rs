@@ -1,18 +1,94 @@
+use crate::account_actor::{AccountMessage, Undo};
+use crate::alerts::AlertRules;
+use crate::amount::AmountFormat;
+use crate::audit::AuditLog;
+use crate::auth::{ApiKeys, ClientScope};
+use crate::authorizer::{AuthorizationGate, Authorizer, AuthorizerConfig};
+use crate::backpressure::BackpressureConfig;
+use crate::compat::{CompatConfig, DuplicatePolicy};
+use crate::config::EngineConfig;
+use crate::contention::{measure, Site};
+use crate::duplicates::DuplicateTracker;
+use crate::engine_snapshot::EngineSnapshot;
 use crate::errors::ProcessingError;
-use crate::event_store::EventStore;
-use crate::models::{Account, TransactionRow};
-use crate::shard_manager::ShardManager;
-use crate::storage::TransactionStore;
-use crate::tx_registry_actor::ShardedTxRegistry;
-use anyhow::Result;
-use std::path::PathBuf;
+use crate::event_store::{DurabilityPolicy, EventLog, EventStore};
+use crate::events::{DomainEvent, EventBus};
+use crate::handlers::TransactionHandler;
+use crate::history::{Pagination, TransactionPage};
+use crate::hot_store::{HotStorageSize, HotTransaction};
+use crate::id_allocator::{IdAllocator, ReservedRangeAllocator};
+use crate::ingestion::Ingestion;
+use crate::intake::IntakeLog;
+use crate::metrics::{MigrationMetricsSnapshot, ProbeMetrics};
+use crate::migration::MigrationConfig;
+use crate::models::{Account, TransactionRow, TransactionType};
+use crate::prober::{PROBE_AMOUNT, PROBE_CLIENT};
+use crate::periods::AccountingPeriods;
+use crate::recorder::FixtureRecorder;
+use crate::reporting::ReportingCounters;
+use crate::routing::RoutingStrategy;
+use crate::sequencer::Sequencer;
+use crate::shard_manager::{ShardManager, ShardStats};
+use crate::spill::SpillConfig;
+use crate::storage::{
+    CompactionReport, PrefetchingStore, StorageTier, StoredTransaction, TransactionStore, DEFAULT_PREFETCH_CAPACITY,
+};
+use crate::timeline::{self, AccountTimeline};
+use crate::trace::{self, TransactionTrace};
+use crate::treasury::TreasuryReport;
+use crate::tx_registry_actor::{ShardedTxRegistry, TxRegistryStats};
+use crate::wire::Capabilities;
+use anyhow::{Context, Result};
+use rust_decimal::Decimal;
+use futures::future::join_all;
+use futures::stream::{BoxStream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+
+/// Rows read ahead of processing so dispute lookups can be prefetched
+pub const PREFETCH_WINDOW: usize = 256;
+
+/// Logged events a rebuild registers, then applies, at a time
+const REPLAY_CHUNK: usize = 4096;
 
 #[derive(Clone)]
 pub struct ScalableEngine {
-    event_store: Arc<EventStore>,
+    // None for one-shot runs that keep no log
+    event_store: Option<Arc<dyn EventLog>>,
     shard_manager: Arc<ShardManager>,
     tx_registry: ShardedTxRegistry,
+    cold_storage: Arc<PrefetchingStore>,
+    id_allocator: Arc<dyn IdAllocator>,
+    audit: Arc<AuditLog>,
+    events: EventBus,
+    authorization: Option<AuthorizationGate>,
+    // Number of log events applied by rebuild_from_events, the rest arrive via the audit log
+    replayed_events: Arc<AtomicUsize>,
+    // Writer generation of this run in the event log, 0 until the log has been replayed
+    generation: Arc<AtomicU64>,
+    // Events appended by this run, together with replayed_events the length of the log
+    appended_events: Arc<AtomicUsize>,
+    snapshot_path: Option<PathBuf>,
+    // Held shared by every write, exclusively while a snapshot is taken
+    write_gate: Arc<RwLock<()>>,
+    ingestion: Arc<Ingestion>,
+    duplicates: Arc<DuplicateTracker>,
+    sequencer: Arc<Sequencer>,
+    spill: Option<SpillConfig>,
+    backpressure: Option<BackpressureConfig>,
+    intake: Option<Arc<IntakeLog>>,
+    recorder: Option<Arc<FixtureRecorder>>,
+    // Set once a latency prober runs against this engine, its client is then hidden from reports
+    probe_metrics: Option<Arc<ProbeMetrics>>,
+    // None leaves submissions open to anyone, as for one-shot runs
+    api_keys: Option<Arc<ApiKeys>>,
+    // Amount spellings the front ends accept from producers
+    amount_format: AmountFormat,
 }
 
 impl ScalableEngine {
@@ -21,82 +97,1284 @@ impl ScalableEngine {
         num_shards: usize,
         cold_storage: Arc<dyn TransactionStore>,
     ) -> Result<Self> {
-        let event_store = Arc::new(EventStore::new(storage_path).await?);
-        let shard_manager = Arc::new(ShardManager::new(num_shards, cold_storage));
-        let tx_registry = ShardedTxRegistry::new(num_shards);
+        Self::with_migration_config(storage_path, num_shards, cold_storage, MigrationConfig::default())
+            .await
+    }
+    
+    pub async fn with_migration_config(
+        storage_path: PathBuf,
+        num_shards: usize,
+        cold_storage: Arc<dyn TransactionStore>,
+        migration_config: MigrationConfig,
+    ) -> Result<Self> {
+        let config = EngineConfig {
+            shards: num_shards,
+            event_log: Some(storage_path),
+            migration: migration_config,
+            ..Default::default()
+        };
+        Self::from_config(&config, cold_storage).await
+    }
+    
+    /// Engine laid out as `config` says, logging nothing when it names no event log
+    pub async fn from_config(config: &EngineConfig, cold_storage: Arc<dyn TransactionStore>) -> Result<Self> {
+        let Some(storage_path) = &config.event_log else {
+            // No log to replay, writes can start right away
+            return Ok(Self::assemble(None, 1, config, cold_storage));
+        };
+        let event_store = EventStore::new(storage_path.clone(), DurabilityPolicy::default()).await?;
+        Self::from_event_log(Arc::new(event_store), config, cold_storage).await
+    }
+    
+    /// Engine appending to `event_log` instead of the file `config` names
+    ///
+    /// Call `rebuild_from_events` before processing if the log holds earlier runs.
+    pub async fn from_event_log(
+        event_log: Arc<dyn EventLog>,
+        config: &EngineConfig,
+        cold_storage: Arc<dyn TransactionStore>,
+    ) -> Result<Self> {
+        // A fresh log has nothing to replay, writes can start right away
+        let generation = if event_log.has_history().await? {
+            0
+        } else {
+            event_log.open_generation().await?
+        };
+        
+        Ok(Self::assemble(Some(event_log), generation, config, cold_storage))
+    }
+    
+    /// Engine that logs nothing, for one-shot runs whose state is thrown away afterwards
+    ///
+    /// Nothing can be replayed, traced or snapshotted.
+    pub fn without_event_log(num_shards: usize, cold_storage: Arc<dyn TransactionStore>) -> Self {
+        let config = EngineConfig {
+            shards: num_shards,
+            ..Default::default()
+        };
+        Self::assemble(None, 1, &config, cold_storage)
+    }
+    
+    fn assemble(
+        event_store: Option<Arc<dyn EventLog>>,
+        generation: u64,
+        config: &EngineConfig,
+        cold_storage: Arc<dyn TransactionStore>,
+    ) -> Self {
+        let cold_storage = Arc::new(PrefetchingStore::new(cold_storage, DEFAULT_PREFETCH_CAPACITY));
+        // Actors publish balance alerts on the engine's bus
+        let events = EventBus::default();
+        let mut shard_manager = ShardManager::with_config(config, cold_storage.clone());
+        shard_manager.set_alerts(Arc::new(AlertRules::new(events.clone())));
+        let shard_manager = Arc::new(shard_manager);
+        let tx_registry = ShardedTxRegistry::new(config.shards);
         
-        Ok(Self {
+        Self {
             event_store,
             shard_manager,
             tx_registry,
-        })
+            cold_storage,
+            id_allocator: Arc::new(ReservedRangeAllocator::default()),
+            audit: Arc::new(AuditLog::default()),
+            events,
+            authorization: None,
+            replayed_events: Arc::new(AtomicUsize::new(0)),
+            generation: Arc::new(AtomicU64::new(generation)),
+            appended_events: Arc::new(AtomicUsize::new(0)),
+            snapshot_path: None,
+            write_gate: Arc::new(RwLock::new(())),
+            ingestion: Arc::new(Ingestion::new()),
+            duplicates: Arc::new(DuplicateTracker::new()),
+            sequencer: Arc::new(Sequencer::default()),
+            spill: None,
+            backpressure: None,
+            intake: None,
+            recorder: None,
+            probe_metrics: None,
+            api_keys: None,
+            amount_format: AmountFormat::default(),
+        }
+    }
+    
+    /// Make `process` wait until each logged event is as durable as `durability` asks
+    ///
+    /// Fails once the engine has been cloned.
+    pub fn with_durability(mut self, durability: DurabilityPolicy) -> Result<Self> {
+        if let Some(event_store) = &mut self.event_store {
+            Arc::get_mut(event_store)
+                .context("Durability can only be set before the engine is shared")?
+                .set_durability(durability);
+        }
+        Ok(self)
+    }
+    
+    /// How long sequenced rows wait for a missing sequence number before they are rejected
+    pub fn with_sequence_timeout(mut self, timeout: Duration) -> Self {
+        self.sequencer = Arc::new(Sequencer::new(timeout));
+        self
+    }
+    
+    /// Order `#ordering sequenced` rows with `sequencer`, such as one resuming from a state file
+    pub fn with_sequencer(mut self, sequencer: Sequencer) -> Self {
+        self.sequencer = Arc::new(sequencer);
+        self
+    }
+    
+    /// Let connections spill rows to disk while they arrive faster than they are applied
+    pub fn with_spill(mut self, spill: SpillConfig) -> Self {
+        self.spill = Some(spill);
+        self
+    }
+    
+    pub fn spill(&self) -> Option<&SpillConfig> {
+        self.spill.as_ref()
+    }
+    
+    /// Make connections stop reading while a shard's actors have too many messages in flight
+    pub fn with_backpressure(mut self, backpressure: BackpressureConfig) -> Self {
+        self.backpressure = Some(backpressure);
+        self
+    }
+    
+    pub fn backpressure(&self) -> Option<&BackpressureConfig> {
+        self.backpressure.as_ref()
+    }
+    
+    /// Wait until every shard has room for more messages, `EngineBusy` if none is made in time
+    ///
+    /// Returns at once without backpressure configured.
+    pub async fn wait_for_capacity(&self) -> Result<(), ProcessingError> {
+        let Some(backpressure) = &self.backpressure else {
+            return Ok(());
+        };
+        if self.shard_manager.wait_for_queue_depth(backpressure.max_queue_depth, backpressure.busy_after).await {
+            Ok(())
+        } else {
+            Err(ProcessingError::EngineBusy {
+                retry_after_ms: backpressure.busy_after.as_millis() as u64,
+            })
+        }
+    }
+    
+    /// Messages in flight to each shard's actors, indexed by shard
+    pub fn queue_depths(&self) -> Vec<usize> {
+        self.shard_manager.queue_depths()
+    }
+    
+    /// Stage rows connections receive in `intake` until they are applied, see `recover_intake`
+    pub fn with_intake(mut self, intake: IntakeLog) -> Self {
+        self.intake = Some(Arc::new(intake));
+        self
+    }
+    
+    pub fn intake(&self) -> Option<&Arc<IntakeLog>> {
+        self.intake.as_ref()
+    }
+    
+    /// Record a sample of the rows connections settle as a golden fixture, see `FixtureRecorder`
+    pub fn with_recorder(mut self, recorder: FixtureRecorder) -> Self {
+        self.recorder = Some(Arc::new(recorder));
+        self
+    }
+    
+    pub fn recorder(&self) -> Option<&Arc<FixtureRecorder>> {
+        self.recorder.as_ref()
+    }
+    
+    /// Accept amounts spelled as `format` allows from the front ends' producers
+    pub fn with_amount_format(mut self, format: AmountFormat) -> Self {
+        self.amount_format = format;
+        self
+    }
+    
+    /// Amount spellings front ends read producer rows under, plain decimals only by default
+    pub fn amount_format(&self) -> AmountFormat {
+        self.amount_format
+    }
+    
+    /// Require front ends to present one of `keys`, submitting only for its clients
+    pub fn with_api_keys(mut self, keys: ApiKeys) -> Self {
+        self.api_keys = Some(Arc::new(keys));
+        self
+    }
+    
+    /// Clients a caller presenting `key` may submit for, all of them without API keys
+    pub fn access(&self, key: Option<&str>) -> ClientScope {
+        match &self.api_keys {
+            Some(keys) => keys.scope(key),
+            None => ClientScope::All,
+        }
+    }
+    
+    /// Reserve `PROBE_CLIENT` for a latency prober, see `prober::LatencyProber`
+    ///
+    /// Its account is left out of account listings, totals and dispute counters
+    /// from now on, and producer rows naming it are refused with `ReservedClient`.
+    pub fn with_prober(mut self) -> Self {
+        self.shard_manager.counters().exclude(PROBE_CLIENT);
+        self.probe_metrics = Some(Arc::new(ProbeMetrics::new()));
+        self
+    }
+    
+    /// Latency of the prober's transactions, None without a prober
+    pub fn probe_metrics(&self) -> Option<&Arc<ProbeMetrics>> {
+        self.probe_metrics.as_ref()
+    }
+    
+    /// Apply one synthetic probe transaction of `PROBE_AMOUNT` to `PROBE_CLIENT`, logged like any other
+    pub async fn apply_probe(&self, tx_type: TransactionType) -> Result<(), ProcessingError> {
+        let tx = TransactionRow {
+            tx_type,
+            client: PROBE_CLIENT,
+            tx: 0,
+            amount: Some(PROBE_AMOUNT),
+            to: None,
+        };
+        
+        self.apply_internal(tx).await
+    }
+    
+    /// Forget the probe client's transactions instead of keeping them in either tier, false if its actor isn't running
+    ///
+    /// Producers can't send rows for the probe client, so nothing can dispute them.
+    pub async fn discard_probes(&self) -> bool {
+        self.shard_manager.discard_hot(PROBE_CLIENT).await
+    }
+    
+    /// Refuse what producers may not send: engine-only types, and the prober's client while one runs
+    fn accept_from_producer(&self, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        if !tx.tx_type.accepted_from_producers() {
+            return Err(ProcessingError::UnsupportedTransactionType);
+        }
+        if self.hides(tx.client) || tx.to.is_some_and(|to| self.hides(to)) {
+            return Err(ProcessingError::ReservedClient);
+        }
+        Ok(())
+    }
+    
+    /// Whether `client` is the prober's, kept out of what customers and reports see
+    fn hides(&self, client: u16) -> bool {
+        self.probe_metrics.is_some() && client == PROBE_CLIENT
+    }
+    
+    /// `report` without the probe account, as the projection last saw it
+    fn without_probe(&self, report: TreasuryReport) -> TreasuryReport {
+        if self.probe_metrics.is_none() {
+            return report;
+        }
+        match self.shard_manager.projection().get(PROBE_CLIENT) {
+            Some(probe) => report.without(&probe),
+            None => report,
+        }
+    }
+    
+    /// What connections to this engine can use, including the features it was started with
+    pub fn capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::builtin();
+        if self.spill.is_some() {
+            capabilities = capabilities.with_feature("spill");
+        }
+        if self.intake.is_some() {
+            capabilities = capabilities.with_feature("intake_log");
+        }
+        if self.api_keys.is_some() {
+            capabilities = capabilities.with_feature("api_keys");
+        }
+        if self.backpressure.is_some() {
+            capabilities = capabilities.with_feature("backpressure");
+        }
+        capabilities
+    }
+    
+    /// Apply rows an earlier run staged but never settled, in receipt order, returning how many were applied
+    ///
+    /// Call after `rebuild_from_events`. A row whose event was logged just
+    /// before the crash comes back refused: its tx id is taken, or its dispute
+    /// step already happened. Only a dispute resolved within that window is
+    /// applied again, resolve included, which leaves the balances as they were.
+    pub async fn recover_intake(&self, rows: Vec<(u64, TransactionRow)>) -> Result<usize> {
+        let Some(intake) = &self.intake else {
+            anyhow::bail!("Engine runs without an intake log");
+        };
+        let (ids, rows): (Vec<u64>, Vec<TransactionRow>) = rows.into_iter().unzip();
+        let outcomes = self.process_batch(rows).await;
+        
+        let mut applied = 0;
+        for (id, outcome) in ids.into_iter().zip(outcomes) {
+            // Refused rows are settled too, retrying them on every restart changes nothing
+            if outcome.is_ok() {
+                applied += 1;
+            }
+            intake.settle(id)?;
+        }
+        Ok(applied)
+    }
+    
+    /// The log this engine appends to, `None` when it keeps none
+    fn log(&self) -> Result<&Arc<dyn EventLog>> {
+        self.event_store
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Engine runs without an event log"))
+    }
+    
+    /// Keep engine snapshots at `path`, loaded by `rebuild_from_events` and written by `write_snapshot`
+    pub fn with_snapshot_path(mut self, path: PathBuf) -> Self {
+        self.snapshot_path = Some(path);
+        self
+    }
+    
+    /// Keep registered tx ids in `dir`, so duplicates are refused across restarts
+    ///
+    /// Ids registered by earlier runs stay taken even when their events are
+    /// no longer replayed. A directory keeps the registry shard count it was
+    /// created with, whatever the engine's. Call before the engine is cloned.
+    pub async fn with_tx_registry_dir(mut self, dir: &Path) -> Result<Self> {
+        self.tx_registry = ShardedTxRegistry::persistent(self.tx_registry.num_shards(), dir).await?;
+        Ok(self)
+    }
+    
+    /// Replace the allocator used for engine-generated tx ids
+    pub fn with_id_allocator(mut self, id_allocator: Arc<dyn IdAllocator>) -> Self {
+        self.id_allocator = id_allocator;
+        self
+    }
+    
+    /// Require external approval for withdrawals above `config.threshold`
+    pub fn with_authorizer(mut self, authorizer: Arc<dyn Authorizer>, config: AuthorizerConfig) -> Self {
+        self.authorization = Some(AuthorizationGate::new(authorizer, config));
+        self
+    }
+    
+    /// Interpret ambiguous spec cases as `compat` says
+    ///
+    /// Call before the engine processes anything, fails once it has been cloned;
+    /// replay must use the same config the log was written under.
+    pub fn with_compat(mut self, compat: CompatConfig) -> Result<Self> {
+        Arc::get_mut(&mut self.shard_manager)
+            .context("Compat config can only be set before the engine is shared")?
+            .set_compat(compat);
+        Ok(self)
+    }
+    
+    pub fn compat(&self) -> CompatConfig {
+        self.shard_manager.compat()
+    }
+    
+    /// Assign clients to account shards with `routing` instead of client id modulo
+    ///
+    /// Call before the engine processes anything, fails once it has been cloned.
+    pub fn with_routing(mut self, routing: Arc<dyn RoutingStrategy>) -> Result<Self> {
+        Arc::get_mut(&mut self.shard_manager)
+            .context("Routing can only be set before the engine is shared")?
+            .set_routing(routing);
+        Ok(self)
+    }
+    
+    /// Account shard owning the client's actor
+    pub fn shard_of(&self, client: u16) -> usize {
+        self.shard_manager.shard_of(client)
+    }
+    
+    /// Account shards the clients are spread over
+    pub fn num_shards(&self) -> usize {
+        self.shard_manager.num_shards()
+    }
+    
+    /// Spread the clients over `num_shards` account shards while running, the number of
+    /// actors moved; nothing sent to them is lost, see `ShardManager::reshard`
+    pub async fn reshard(&self, num_shards: usize) -> anyhow::Result<usize> {
+        self.shard_manager.reshard(num_shards).await
+    }
+    
+    /// Plug in a handler for a custom transaction type
+    pub fn register_handler(&self, handler: Arc<dyn TransactionHandler>) {
+        self.shard_manager.handlers().register(handler);
     }
     
     /// Rebuild state from event log (on startup)
+    ///
+    /// Starts from the snapshot when one is configured and still matches the
+    /// log, replaying only the events logged after it.
+    ///
+    /// Live processing is refused until this has run on a log with history.
+    /// Calling it again once the log is open for writing is a no-op, replaying
+    /// twice would apply every event twice.
     pub async fn rebuild_from_events(&self) -> Result<()> {
-        use crate::models::TransactionType;
+        if self.generation.load(Ordering::SeqCst) != 0 {
+            return Ok(());
+        }
+        let event_store = self.log()?;
+        self.replay_log(event_store.as_ref()).await?;
+        
+        let generation = event_store.open_generation().await?;
+        self.generation.store(generation, Ordering::SeqCst);
+        tracing::info!("Event log replayed, writing generation {}", generation);
+        
+        Ok(())
+    }
+    
+    /// Rebuild state as `rebuild_from_events` does without opening the log for writing
+    ///
+    /// No generation marker is appended, the log is left exactly as found and
+    /// live processing stays refused. For inspecting a log, call it once.
+    pub async fn rebuild_read_only(&self) -> Result<()> {
+        if self.generation() != 0 {
+            anyhow::bail!("Event log is already open for writing");
+        }
+        self.replay_log(self.log()?.as_ref()).await
+    }
+    
+    /// Restore the snapshot if any and apply the events logged after it
+    async fn replay_log(&self, event_store: &dyn EventLog) -> Result<()> {
+        let (offset, snapshot_events) = self.restore_snapshot(event_store).await?.unwrap_or((0, 0));
+        let events = event_store.replay_from(offset).await?;
+        self.replayed_events.store(snapshot_events + events.len(), Ordering::SeqCst);
         
-        let events = self.event_store.replay().await?;
+        // Each chunk's tx ids are registered while the chunk before it is applied
+        let mut events = events.into_iter();
+        let mut next_chunk = || events.by_ref().take(REPLAY_CHUNK).collect::<Vec<_>>();
+        let mut pending = self.register_replayed(next_chunk()).await?;
+        while !pending.is_empty() {
+            let (_, registered) = tokio::join!(self.replay_chunk(pending), self.register_replayed(next_chunk()));
+            pending = registered?;
+        }
+        self.tx_registry.finish_replay().await?;
+        self.recount_replayed(event_store).await?;
         
-        for event in events {
-            // Register TX ID only for deposits/withdrawals (consistent with process logic)
-            let is_new_tx = matches!(event.tx_type, TransactionType::Deposit | TransactionType::Withdrawal);
-            if is_new_tx {
-                let _ = self.tx_registry.register(event.tx).await;
+        for (original, replacement_tx) in event_store.superseded().await? {
+            self.audit.supersede(&original, replacement_tx);
+        }
+        Ok(())
+    }
+    
+    /// Count the logged events the persisted reporting counters miss, see `ReportingCounters`
+    async fn recount_replayed(&self, event_store: &dyn EventLog) -> Result<()> {
+        let counters = self.shard_manager.counters();
+        let Some(from) = counters.counted_offset() else {
+            return Ok(());
+        };
+        if from > event_store.end_offset().await? {
+            tracing::warn!("Reporting counters cover more than the event log holds, not recounting");
+            return Ok(());
+        }
+        
+        let at = SystemTime::now();
+        let events = event_store.replay_from(from).await?;
+        for event in &events {
+            counters.record_logged(event, at);
+        }
+        if from > 0 && !events.is_empty() {
+            tracing::info!("Counted {} logged events the reporting counters were missing", events.len());
+        }
+        Ok(())
+    }
+    
+    /// Register the tx ids of logged events in bulk, returning the events left to apply
+    async fn register_replayed(&self, events: Vec<TransactionRow>) -> Result<Vec<TransactionRow>> {
+        // Only types creating a tx id register one (consistent with process logic)
+        let handlers = self.shard_manager.handlers();
+        let tx_ids: Vec<u32> = events
+            .iter()
+            .filter(|event| handlers.creates_tx(&event.tx_type))
+            .map(|event| event.tx)
+            .collect();
+        let mut registered = self.tx_registry.register_replayed_many(&tx_ids).await?.into_iter();
+        
+        Ok(events
+            .into_iter()
+            .filter(|event| {
+                if !handlers.creates_tx(&event.tx_type) {
+                    return true;
+                }
+                // A tx id logged twice (e.g. a file ingested twice) is applied once
+                if registered.next() != Some(true) {
+                    tracing::warn!("Skipping duplicate tx {} in event log", event.tx);
+                    return false;
+                }
+                self.id_allocator.observe(event.tx);
+                true
+            })
+            .collect())
+    }
+    
+    /// Re-apply registered events, clients in parallel and each client's in log order
+    ///
+    /// Split like a batch, so a transfer or a tx id another client used waits
+    /// for every event before it.
+    async fn replay_chunk(&self, events: Vec<TransactionRow>) {
+        for segment in independent_segments(&events) {
+            let mut by_client: HashMap<u16, Vec<&TransactionRow>> = HashMap::new();
+            for event in &events[segment] {
+                by_client.entry(event.client).or_default().push(event);
             }
             
-            // Replay through shard manager (rebuilds actor state)
-            let _ = self.shard_manager.process(event).await;
+            join_all(by_client.into_values().map(|events| async move {
+                for event in events {
+                    let _ = self.shard_manager.replay(event.clone()).await;
+                }
+            }))
+            .await;
+        }
+    }
+    
+    /// Seed actors, registry and cold storage from the snapshot, returning where replay resumes
+    ///
+    /// An unreadable snapshot, or one taken from another log (see
+    /// `EngineSnapshot::mismatch`), is ignored.
+    async fn restore_snapshot(&self, event_store: &dyn EventLog) -> Result<Option<(u64, usize)>> {
+        let Some(path) = &self.snapshot_path else {
+            return Ok(None);
+        };
+        
+        let snapshot = match EngineSnapshot::load(path).await {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable snapshot {}: {}", path.display(), e);
+                return Ok(None);
+            }
+        };
+        if let Some(reason) = snapshot.mismatch(event_store).await? {
+            tracing::warn!("Ignoring snapshot {}, {}", path.display(), reason);
+            return Ok(None);
+        }
+        
+        for &tx_id in &snapshot.tx_ids {
+            self.tx_registry.register_replayed(tx_id).await?;
+            self.id_allocator.observe(tx_id);
         }
         
+        // Restored as cold, an actor looks there for anything not in its hot storage
+        for (tx_id, tx) in snapshot.transactions {
+            self.cold_storage.put(tx_id, tx).await?;
+        }
+        
+        for account in snapshot.accounts {
+            self.shard_manager.restore_account(account).await?;
+        }
+        
+        tracing::info!(
+            "Restored snapshot {} covering {} events, replaying from byte {}",
+            path.display(),
+            snapshot.events,
+            snapshot.log_offset
+        );
+        Ok(Some((snapshot.log_offset, snapshot.events)))
+    }
+    
+    /// Snapshot every account, registered tx id and stored transaction, returning its log offset
+    ///
+    /// Writes wait while the state is collected, so it matches the log up to
+    /// the offset exactly. Background migrations may run meanwhile: hot storage
+    /// is read before cold so a migrating transaction is found in one or both.
+    pub async fn write_snapshot(&self) -> Result<u64> {
+        let Some(path) = &self.snapshot_path else {
+            anyhow::bail!("No snapshot path configured");
+        };
+        if self.generation() == 0 {
+            anyhow::bail!("Event log has not been replayed yet");
+        }
+        let event_store = self.log()?;
+        
+        let snapshot = {
+            let _gate = self.write_gate.write().await;
+            
+            let log_offset = event_store.end_offset().await?;
+            let events = self.replayed_events.load(Ordering::SeqCst)
+                + self.appended_events.load(Ordering::SeqCst);
+            let exported = self.shard_manager.export_accounts().await;
+            
+            // Hot copies are newer than cold ones left behind by an unfinished migration
+            let mut transactions: HashMap<u32, _> = self
+                .cold_storage
+                .snapshot_entries()
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            let mut accounts = Vec::with_capacity(exported.len());
+            for (account, hot) in exported {
+                transactions.extend(hot);
+                accounts.push(account);
+            }
+            
+            let tx_ids = self.tx_registry.export().await?;
+            EngineSnapshot::new(log_offset, self.generation(), events, accounts, tx_ids, transactions.into_iter().collect())
+        };
+        // The log before the offset no longer changes, no need to hold writes up for it
+        let log_checksum = event_store.checksum_to(snapshot.log_offset).await?;
+        let snapshot = snapshot.with_log_checksum(log_checksum);
+        
+        snapshot.save(path).await?;
+        tracing::info!("Wrote snapshot {} at byte {} of the event log", path.display(), snapshot.log_offset);
+        Ok(snapshot.log_offset)
+    }
+    
+    /// Stop every actor and registry shard, then force the event log to disk
+    ///
+    /// Waits for writes in flight, so call it once producers have stopped.
+    /// Actors hand their hot transactions to cold storage as when idle.
+    pub async fn shutdown(&self) -> Result<()> {
+        let _gate = self.write_gate.write().await;
+        self.shard_manager.shutdown().await;
+        self.tx_registry.shutdown().await;
+        if let Some(event_store) = &self.event_store {
+            event_store.sync().await?;
+        }
         Ok(())
     }
     
+    /// Events applied from the snapshot and the event log at startup
+    pub fn replayed_events(&self) -> usize {
+        self.replayed_events.load(Ordering::SeqCst)
+    }
+    
+    /// Writer generation of this run, 0 while the event log still needs replaying
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+    
+    /// Warm cold-storage lookups for upcoming rows that reference existing transactions
+    pub async fn prefetch(&self, rows: &[TransactionRow]) {
+        let tx_ids: Vec<u32> = rows
+            .iter()
+            .filter(|row| matches!(
+                row.tx_type,
+                TransactionType::Dispute
+                    | TransactionType::Resolve
+                    | TransactionType::Chargeback
+                    | TransactionType::Capture
+                    | TransactionType::Void
+            ))
+            .map(|row| row.tx)
+            .collect();
+        
+        if !tx_ids.is_empty() {
+            measure(Site::ColdStorage, self.cold_storage.warm(&tx_ids)).await;
+        }
+    }
+    
     pub async fn process(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        use crate::models::TransactionType;
+        self.process_at(tx, SystemTime::now()).await
+    }
+    
+    /// Process an event that happened at `at`, e.g. a backdated row from a settlement feed
+    pub async fn process_at(&self, tx: TransactionRow, at: SystemTime) -> Result<(), ProcessingError> {
+        // Opening balances and unlocks are only accepted through import_opening_balance and unlock_account
+        if let Err(e) = self.accept_from_producer(&tx) {
+            let result = Err(e);
+            self.audit.record(&tx, &result);
+            self.events.publish_outcome(&tx, &result);
+            return result;
+        }
+        
+        let result = self.apply(tx.clone(), at).await;
+        if matches!(result, Err(ProcessingError::DuplicateTransaction)) && self.is_retry(&tx).await {
+            // Acknowledged again, but nothing happened a second time
+            self.audit.record(&tx, &Ok(()));
+            return Ok(());
+        }
+        self.audit.record(&tx, &result);
+        self.events.publish_outcome(&tx, &result);
+        result
+    }
+    
+    /// Process rows concurrently across clients, in order per client, results in input order
+    ///
+    /// Each client's rows run on its actor while other clients' rows run on
+    /// theirs, and the applied events are logged with one append. A row tying
+    /// clients together (a transfer, or a tx id another client used earlier in
+    /// the batch) waits for every row before it.
+    pub async fn process_batch(&self, rows: Vec<TransactionRow>) -> Vec<Result<(), ProcessingError>> {
+        let at = SystemTime::now();
+        let applied = std::sync::Mutex::new(Vec::new());
+        let mut results: Vec<Option<Result<(), ProcessingError>>> = rows.iter().map(|_| None).collect();
+        
+        let _gate = self.write_gate.read().await;
+        for segment in independent_segments(&rows) {
+            let mut by_client: HashMap<u16, Vec<usize>> = HashMap::new();
+            for i in segment {
+                by_client.entry(rows[i].client).or_default().push(i);
+            }
+            
+            let outcomes = join_all(by_client.into_values().map(|indices| {
+                let rows = &rows;
+                let applied = &applied;
+                async move {
+                    let mut outcomes = Vec::with_capacity(indices.len());
+                    for i in indices {
+                        outcomes.push((i, self.apply_batched(i, &rows[i], at, applied).await));
+                    }
+                    outcomes
+                }
+            }))
+            .await;
+            for (i, result) in outcomes.into_iter().flatten() {
+                results[i] = Some(result);
+            }
+        }
+        
+        // Logged in the order the actors applied them, which replay repeats
+        let applied: Vec<(usize, TransactionRow, Vec<Undo>)> = applied.into_inner().unwrap();
+        let events: Vec<TransactionRow> = applied.iter().map(|(_, tx, _)| tx.clone()).collect();
+        if self.log_events(&events).await.is_err() {
+            // Nothing unlogged may stay applied, nor be acknowledged
+            for (i, _, _) in &applied {
+                results[*i] = Some(Err(ProcessingError::EventLogUnavailable));
+            }
+            self.revert_unlogged(applied.into_iter().map(|(_, tx, undo)| (tx, undo)).collect()).await;
+            return self.finish_batch(&rows, results, &HashSet::new());
+        }
+        
+        // Retries are acknowledged without being applied, they publish nothing
+        let applied: HashSet<usize> = applied.into_iter().map(|(i, _, _)| i).collect();
+        self.finish_batch(&rows, results, &applied)
+    }
+    
+    /// Audit and publish a batch's outcomes, returned in input order
+    fn finish_batch(
+        &self,
+        rows: &[TransactionRow],
+        results: Vec<Option<Result<(), ProcessingError>>>,
+        applied: &HashSet<usize>,
+    ) -> Vec<Result<(), ProcessingError>> {
+        rows.iter()
+            .zip(results)
+            .enumerate()
+            .map(|(i, (tx, result))| {
+                let result = result.expect("every row belongs to a segment");
+                self.audit.record(tx, &result);
+                if result.is_err() || applied.contains(&i) {
+                    self.events.publish_outcome(tx, &result);
+                }
+                result
+            })
+            .collect()
+    }
+    
+    /// One row of `process_batch`, noted in `applied` if it needs logging
+    async fn apply_batched(
+        &self,
+        index: usize,
+        tx: &TransactionRow,
+        at: SystemTime,
+        applied: &std::sync::Mutex<Vec<(usize, TransactionRow, Vec<Undo>)>>,
+    ) -> Result<(), ProcessingError> {
+        self.accept_from_producer(tx)?;
+        
+        match self.apply_unlogged(tx, at).await {
+            Ok(undo) => {
+                applied.lock().unwrap().push((index, tx.clone(), undo));
+                Ok(())
+            }
+            Err(ProcessingError::DuplicateTransaction) if self.is_retry(tx).await => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// Outcome `tx` would have if processed now, without applying it
+    ///
+    /// Runs the checks of `process` in the same order: accepted type and client,
+    /// rebuild, closed periods, handler validation, tx id uniqueness and the
+    /// account's state (lock, balance, dispute state). The external authorizer
+    /// is not asked, a dry run must not reach it.
+    pub async fn validate(&self, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        self.accept_from_producer(tx)?;
+        if self.generation() == 0 {
+            return Err(ProcessingError::RebuildPending);
+        }
+        if self.periods().is_fenced(SystemTime::now()) {
+            return Err(ProcessingError::PeriodClosed);
+        }
         
-        // Check global TX ID uniqueness (only for deposit/withdrawal, they create new TXs)
+        self.shard_manager.handlers().validate(tx)?;
+        
+        if self.shard_manager.handlers().creates_tx(&tx.tx_type) {
+            let taken = self
+                .tx_registry
+                .contains(tx.tx)
+                .await
+                .map_err(|_| ProcessingError::ActorCommunicationError)?;
+            if taken {
+                // A retry of the applied row would be acknowledged again
+                return if self.is_retry(tx).await {
+                    Ok(())
+                } else {
+                    Err(ProcessingError::DuplicateTransaction)
+                };
+            }
+        }
+        
+        self.shard_manager.evaluate(tx).await
+    }
+    
+    /// Submit a corrected version of a rejected row
+    ///
+    /// Once the correction applies, the original's rejections in the audit
+    /// trail are marked as superseded by it. The mark is logged too, so later
+    /// runs replaying the log show it.
+    pub async fn resubmit(
+        &self,
+        original: &TransactionRow,
+        corrected: TransactionRow,
+    ) -> Result<(), ProcessingError> {
+        let corrected_tx = corrected.tx;
+        self.process(corrected).await?;
+        self.audit.supersede(original, corrected_tx);
+        
+        // The correction is applied either way, failing it now would invite a duplicate retry
+        if let Some(event_store) = &self.event_store {
+            if let Err(e) = event_store.append_supersede(original, corrected_tx).await {
+                tracing::warn!("Could not log tx {} as superseded by tx {}: {}", original.tx, corrected_tx, e);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Whether a duplicate row repeats the applied one and may be acknowledged again
+    async fn is_retry(&self, tx: &TransactionRow) -> bool {
+        self.compat().duplicates == DuplicatePolicy::IgnoreRetries && self.repeats_applied(tx).await
+    }
+    
+    /// Whether `tx` has the type, client and amount of the transaction applied under its id
+    pub async fn repeats_applied(&self, tx: &TransactionRow) -> bool {
+        match self.shard_manager.inspect_transaction(tx.client, tx.tx).await {
+            Some((stored, _)) => stored.tx_type == tx.tx_type && Some(stored.amount) == tx.amount,
+            None => false,
+        }
+    }
+    
+    /// Seed a new account with a balance migrated from a legacy system
+    pub async fn import_opening_balance(
+        &self,
+        client_id: u16,
+        amount: Decimal,
+    ) -> Result<(), ProcessingError> {
+        let tx = TransactionRow {
+            tx_type: TransactionType::OpeningBalance,
+            client: client_id,
+            tx: 0,
+            amount: Some(amount),
+            to: None,
+        };
+        
+        self.apply_internal(tx).await
+    }
+    
+    /// Clear the lock a chargeback left on an account, recorded in the event log
+    pub async fn unlock_account(&self, client_id: u16) -> Result<(), ProcessingError> {
+        let tx = TransactionRow {
+            tx_type: TransactionType::Unlock,
+            client: client_id,
+            tx: 0,
+            amount: None,
+            to: None,
+        };
+        
+        let result = async {
+            let _gate = self.write_gate.read().await;
+            if self.generation() == 0 {
+                return Err(ProcessingError::RebuildPending);
+            }
+            let undo = self.shard_manager.unlock(client_id).await?;
+            if let Err(e) = self.log_events(std::slice::from_ref(&tx)).await {
+                self.revert_unlogged(vec![(tx.clone(), vec![undo])]).await;
+                return Err(e);
+            }
+            Ok(())
+        }
+        .await;
+        
+        self.audit.record(&tx, &result);
+        self.events.publish_outcome(&tx, &result);
+        result
+    }
+    
+    /// Apply an engine-generated event under a freshly allocated tx id
+    async fn apply_internal(&self, mut tx: TransactionRow) -> Result<(), ProcessingError> {
+        let result = loop {
+            let Some(tx_id) = self.id_allocator.next_id() else {
+                break Err(ProcessingError::IdSpaceExhausted);
+            };
+            tx.tx = tx_id;
+            
+            match self.apply(tx.clone(), SystemTime::now()).await {
+                // A producer already used this id, take the next one
+                Err(ProcessingError::DuplicateTransaction) => continue,
+                result => break result,
+            }
+        };
+        
+        self.audit.record(&tx, &result);
+        self.events.publish_outcome(&tx, &result);
+        result
+    }
+    
+    async fn apply(&self, tx: TransactionRow, at: SystemTime) -> Result<(), ProcessingError> {
+        let _gate = self.write_gate.read().await;
+        let undo = self.apply_unlogged(&tx, at).await?;
+        if let Err(e) = self.log_events(std::slice::from_ref(&tx)).await {
+            self.revert_unlogged(vec![(tx, undo)]).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+    
+    /// Apply `tx` without logging it, the caller holds the write gate and logs the event
+    ///
+    /// Returns how to take it back, see `revert_unlogged`.
+    async fn apply_unlogged(&self, tx: &TransactionRow, at: SystemTime) -> Result<Vec<Undo>, ProcessingError> {
+        // Applying on top of an unreplayed log would reuse ids and balances from the last run
+        if self.generation() == 0 {
+            return Err(ProcessingError::RebuildPending);
+        }
+        
+        // Closed periods are immutable, nothing may land in them
+        if self.periods().is_fenced(at) {
+            return Err(ProcessingError::PeriodClosed);
+        }
+        
+        self.shard_manager.handlers().validate(tx)?;
+        
+        // Check global TX ID uniqueness (only for types that create new TXs)
         // Disputes/resolves/chargebacks reference existing TXs, so skip uniqueness check
-        let is_new_tx = matches!(tx.tx_type, TransactionType::Deposit | TransactionType::Withdrawal);
+        let is_new_tx = self.shard_manager.handlers().creates_tx(&tx.tx_type);
         
         if is_new_tx {
             let is_new = self
                 .tx_registry
                 .register(tx.tx)
                 .await
-                .map_err(|_| ProcessingError::TransactionNotFound)?;
+                .map_err(|_| ProcessingError::ActorCommunicationError)?;
             
             if !is_new {
                 return Err(ProcessingError::DuplicateTransaction);
             }
         }
         
+        // Only rows that can still apply reach the external authorizer, a
+        // denial then releases the claimed id so it leaves no trace
+        let result = match &self.authorization {
+            Some(gate) => gate.check(tx).await,
+            None => Ok(()),
+        };
+        
         // Apply to account actor
-        let result = self.shard_manager.process(tx.clone()).await;
+        let result = match result {
+            Ok(()) => self.shard_manager.process_undoable(tx.clone(), at).await,
+            Err(e) => Err(e),
+        };
         
-        if let Err(e) = result {
+        if result.is_err() && is_new_tx {
             // Processing failed, unregister TX ID if it was a new transaction
-            if is_new_tx {
+            let _ = self.tx_registry.unregister(tx.tx).await;
+        }
+        result
+    }
+    
+    /// Take back applied rows whose events could not be logged, the last applied first
+    ///
+    /// Their actors revert what the rows changed and their tx ids are released,
+    /// so a retry starts from the state the log describes.
+    async fn revert_unlogged(&self, applied: Vec<(TransactionRow, Vec<Undo>)>) {
+        for (tx, undo) in applied.into_iter().rev() {
+            if let Err(e) = self.shard_manager.revert(undo).await {
+                tracing::error!(client_id = tx.client, tx_id = tx.tx, error = ?e, "Failed to revert an unlogged transaction");
+                continue;
+            }
+            if self.shard_manager.handlers().creates_tx(&tx.tx_type) {
                 let _ = self.tx_registry.unregister(tx.tx).await;
             }
-            return Err(e);
+        }
+    }
+    
+    /// Persist successfully processed transactions with one append
+    async fn log_events(&self, txs: &[TransactionRow]) -> Result<(), ProcessingError> {
+        if let Some(event_store) = &self.event_store {
+            if txs.is_empty() {
+                return Ok(());
+            }
+            event_store
+                .append_batch(txs)
+                .await
+                .map_err(|e| {
+                    tracing::error!(error = ?e, "Failed to append to event log");
+                    ProcessingError::EventLogUnavailable
+                })?;
+            self.appended_events.fetch_add(txs.len(), Ordering::SeqCst);
         }
         
-        // Persist to event store only successfully processed transactions
-        self.event_store
-            .append(&tx)
-            .await
-            .map_err(|_| ProcessingError::TransactionNotFound)?;
+        // Only now, an id on file whose event never made it would be refused forever
+        let handlers = self.shard_manager.handlers();
+        let tx_ids: Vec<u32> = txs.iter().filter(|tx| handlers.creates_tx(&tx.tx_type)).map(|tx| tx.tx).collect();
+        if let Err(e) = self.tx_registry.persist(&tx_ids).await {
+            tracing::error!(error = ?e, "Failed to persist tx ids, replay registers them again");
+        }
         
         Ok(())
     }
     
-    // TODO: won't scale, future improvement
+    /// Every account, one actor round trip each: for exports, see `account_totals` for aggregates
     pub async fn get_accounts(&self) -> Vec<Account> {
-        self.shard_manager.get_all_accounts().await
+        let mut accounts = self.shard_manager.get_all_accounts().await;
+        accounts.retain(|account| !self.hides(account.client));
+        accounts
+    }
+    
+    /// Every account ordered by client id, streamed so large exports don't sit in memory
+    pub fn stream_accounts(&self) -> BoxStream<'_, Account> {
+        self.shard_manager
+            .stream_accounts()
+            .filter(move |account| std::future::ready(!self.hides(account.client)))
+            .boxed()
     }
     
     pub async fn get_account(&self, client_id: u16) -> Option<Account> {
         self.shard_manager.get_account(client_id).await
     }
+    
+    /// Accounts of the listed clients ordered by client id, with one shard lock and concurrent actor reads per shard
+    pub async fn get_accounts_of(&self, client_ids: &[u16]) -> Vec<Account> {
+        let mut accounts = self.shard_manager.get_accounts_of(client_ids).await;
+        accounts.retain(|account| !self.hides(account.client));
+        accounts
+    }
+    
+    /// Monthly transaction/dispute/chargeback counts per client
+    pub fn dispute_counters(&self) -> &ReportingCounters {
+        self.shard_manager.counters()
+    }
+    
+    /// Persist the reporting counters with the event log offset they cover
+    pub async fn flush_counters(&self) -> Result<()> {
+        let counters = self.shard_manager.counters();
+        
+        // Writes wait, so the counts include every event before the offset and none after
+        let _gate = self.write_gate.write().await;
+        let log_offset = match &self.event_store {
+            Some(event_store) if self.generation() != 0 => Some(event_store.end_offset().await?),
+            // Nothing counted since the file was loaded
+            Some(_) => counters.counted_offset(),
+            None => None,
+        };
+        counters.flush(log_offset).await
+    }
+    
+    /// Domain events of every submission from now on, retries of applied rows excluded
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<DomainEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Account states from now on, pushed by the actors after each change they apply
+    pub fn subscribe_accounts(&self) -> tokio::sync::broadcast::Receiver<Account> {
+        self.shard_manager.account_updates().subscribe()
+    }
+    
+    /// Backfill and live stream hand-over, live from the start unless a backfill runs
+    pub fn ingestion(&self) -> &Arc<Ingestion> {
+        &self.ingestion
+    }
+    
+    /// Per-client ordering of rows from connections that number them
+    pub fn sequencer(&self) -> &Sequencer {
+        &self.sequencer
+    }
+    
+    /// Duplicate rejections per source, recorded by whoever knows where rows came from
+    pub fn duplicates(&self) -> &DuplicateTracker {
+        &self.duplicates
+    }
+    
+    /// Per-client balance alert rules, see `AlertRules`
+    pub fn alerts(&self) -> &Arc<AlertRules> {
+        self.shard_manager.alerts()
+    }
+    
+    /// Closed accounting periods
+    pub fn periods(&self) -> &Arc<AccountingPeriods> {
+        self.shard_manager.counters().periods()
+    }
+    
+    /// Close a month ("YYYY-MM"): later events dated inside it are rejected
+    pub async fn close_period(&self, month: &str) -> Result<bool> {
+        self.periods().close(month).await
+    }
+    
+    /// Projection read of one account, may trail recent writes
+    pub fn get_account_eventual(&self, client_id: u16) -> Option<Account> {
+        self.shard_manager.projection().get(client_id)
+    }
+    
+    /// Projection read of the listed clients' accounts in the order given, may trail recent writes
+    pub fn get_accounts_of_eventual(&self, client_ids: &[u16]) -> Vec<Account> {
+        let mut accounts = self.shard_manager.projection().get_many(client_ids);
+        accounts.retain(|account| !self.hides(account.client));
+        accounts
+    }
+    
+    /// Projection read of all accounts, may trail recent writes
+    pub fn get_accounts_eventual(&self) -> Vec<Account> {
+        let mut accounts = self.shard_manager.projection().all();
+        accounts.retain(|account| !self.hides(account.client));
+        accounts
+    }
+    
+    /// Totals across all accounts, reflecting every acknowledged write
+    ///
+    /// Summed from per-shard running totals, no actor is contacted. With a
+    /// prober, a probe in flight may show up as its amount until the projection catches up.
+    pub fn account_totals(&self) -> TreasuryReport {
+        self.without_probe(self.shard_manager.totals())
+    }
+    
+    /// Running totals of one shard, `None` past the last shard
+    pub fn shard_totals(&self, shard: usize) -> Option<TreasuryReport> {
+        self.shard_manager.shard_totals(shard)
+    }
+    
+    /// Totals across all accounts, kept up to date by the projection so may trail recent writes
+    pub fn treasury_report(&self) -> TreasuryReport {
+        self.without_probe(self.shard_manager.projection().treasury())
+    }
+    
+    /// Start moving all of a client's hot transactions to cold storage, false if its actor isn't running
+    pub async fn force_migrate_cold(&self, client_id: u16) -> bool {
+        self.shard_manager.force_migrate_cold(client_id).await
+    }
+    
+    /// Actors, hot transactions and queued messages of every shard, in shard order
+    pub async fn stats(&self) -> Vec<ShardStats> {
+        self.shard_manager.stats().await
+    }
+    
+    /// Start moving every running actor's hot transactions to cold storage, e.g. before a
+    /// planned restart; the number of actors told
+    ///
+    /// Migration runs in the background, `hot_storage_sizes` empties as it finishes.
+    pub async fn migrate_all_cold(&self) -> usize {
+        self.shard_manager.broadcast(|| AccountMessage::ForceMigrateCold).await
+    }
+    
+    /// Drop cold records no dispute can target any more, keeping disputed and disputable ones
+    ///
+    /// Charged back records are already gone; what goes are the ones kept only
+    /// for the audit trail (withdrawals under strict compat, transfer debits),
+    /// which leave the transaction history with them. With a prober, so do any
+    /// probe records that reached cold storage, e.g. on shutdown.
+    pub async fn compact_cold_storage(&self) -> Result<CompactionReport> {
+        let compat = self.compat();
+        let probes = self.probe_metrics.is_some();
+        let report = self
+            .cold_storage
+            .compact(Arc::new(move |tx: &StoredTransaction| {
+                !(probes && tx.client == PROBE_CLIENT) && (tx.disputed || compat.disputable(&tx.tx_type))
+            }))
+            .await?;
+        tracing::info!(
+            scanned = report.scanned,
+            removed = report.removed,
+            bytes_reclaimed = report.bytes_reclaimed,
+            "Compacted cold storage"
+        );
+        Ok(report)
+    }
+    
+    /// A transaction held by the client's running actor and the tier holding it, never spawning an actor
+    pub async fn inspect_transaction(&self, client_id: u16, tx_id: u32) -> Option<(StoredTransaction, StorageTier)> {
+        self.shard_manager.inspect_transaction(client_id, tx_id).await
+    }
+    
+    /// A client's hot transactions, `None` if its actor isn't running
+    pub async fn hot_transactions(&self, client_id: u16) -> Option<Vec<HotTransaction>> {
+        self.shard_manager.hot_transactions(client_id).await
+    }
+    
+    /// A page of a client's stored transactions ordered by tx id, from both storage tiers
+    pub async fn get_transactions(
+        &self,
+        client_id: u16,
+        pagination: Pagination,
+    ) -> Result<TransactionPage, ProcessingError> {
+        self.shard_manager.get_transactions(client_id, pagination).await
+    }
+    
+    /// Registered tx ids and the memory their sets take
+    pub async fn tx_registry_stats(&self) -> Result<TxRegistryStats> {
+        self.tx_registry.stats().await
+    }
+    
+    /// Hot-storage size per running actor, largest first
+    pub async fn hot_storage_sizes(&self) -> Vec<HotStorageSize> {
+        self.shard_manager.hot_storage_sizes().await
+    }
+    
+    pub fn migration_metrics(&self) -> MigrationMetricsSnapshot {
+        self.shard_manager.migration_metrics()
+    }
+    
+    /// Reconstruct the history of a transaction from the event log
+    pub async fn trace_transaction(&self, tx_id: u32) -> Result<TransactionTrace> {
+        let event_store = self.log()?;
+        let events = event_store.replay().await?;
+        let scratch = ShardManager::scratch(self.compat(), self.shard_manager.handlers().clone());
+        let mut trace = trace::trace_transaction(event_store.path(), events, tx_id, scratch).await;
+        
+        // Prefer the live actor's view of where the transaction is stored
+        let owner = trace
+            .events
+            .iter()
+            .find(|event| event.outcome == "applied")
+            .map(|event| event.client);
+        if let Some(client) = owner {
+            if let Some((_, tier)) = self.shard_manager.inspect_transaction(client, tx_id).await {
+                trace.storage_tier = Some(tier);
+            }
+        }
+        
+        Ok(trace)
+    }
+    
+    /// Chronological history of one account: replayed log events, then live outcomes
+    pub async fn account_timeline(&self, client_id: u16) -> Result<AccountTimeline> {
+        let replayed = self.replayed_events.load(Ordering::SeqCst);
+        let history = match &self.event_store {
+            Some(event_store) => event_store.replay().await?.into_iter().take(replayed).collect(),
+            None => Vec::new(),
+        };
+        
+        Ok(timeline::build_timeline(client_id, history, self.audit.for_client(client_id)))
+    }
+}
+
+/// Split a batch into runs of rows whose clients don't affect each other
+fn independent_segments(rows: &[TransactionRow]) -> Vec<Range<usize>> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut owners: HashMap<u32, u16> = HashMap::new();
+    let mut clients: HashSet<u16> = HashSet::new();
+    let mut credited: HashSet<u16> = HashSet::new();
+    
+    for (i, row) in rows.iter().enumerate() {
+        let depends = owners.get(&row.tx).is_some_and(|&owner| owner != row.client)
+            || credited.contains(&row.client)
+            || row.to.is_some_and(|to| clients.contains(&to));
+        if depends {
+            segments.push(start..i);
+            start = i;
+            owners.clear();
+            clients.clear();
+            credited.clear();
+        }
+        
+        owners.entry(row.tx).or_insert(row.client);
+        clients.insert(row.client);
+        if let Some(to) = row.to {
+            credited.insert(to);
+        }
+    }
+    
+    if start < rows.len() {
+        segments.push(start..rows.len());
+    }
+    segments
 }
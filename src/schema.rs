@@ -0,0 +1,138 @@
+use crate::models::{parse_transaction_type, TransactionType};
+use anyhow::Result;
+use std::str::FromStr;
+
+/// Delimiters input files are sniffed for, with the names `--schema` knows them by
+pub const DELIMITERS: [(u8, &str); 4] = [(b',', "comma"), (b';', "semicolon"), (b'\t', "tab"), (b'|', "pipe")];
+
+/// Columns read in order from a file without a header line
+pub const DEFAULT_COLUMNS: [&str; 5] = ["type", "client", "tx", "amount", "to"];
+
+/// Columns every row needs
+const REQUIRED_COLUMNS: [&str; 3] = ["type", "client", "tx"];
+
+/// Layout of a CSV input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvSchema {
+    pub delimiter: u8,
+    /// Without one, columns are read in the order of `DEFAULT_COLUMNS`
+    pub has_headers: bool,
+}
+
+impl Default for CsvSchema {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+        }
+    }
+}
+
+impl FromStr for CsvSchema {
+    type Err = anyhow::Error;
+
+    /// `sep=<comma|semicolon|tab|pipe>` and `header=<yes|no>`, comma separated, either may be left out
+    fn from_str(s: &str) -> Result<Self> {
+        let mut schema = CsvSchema::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part.split_once('=') {
+                Some(("sep", name)) => {
+                    schema.delimiter = DELIMITERS
+                        .iter()
+                        .find(|(_, known)| *known == name)
+                        .map(|(delimiter, _)| *delimiter)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown separator '{}', expected comma, semicolon, tab or pipe", name))?;
+                }
+                Some(("header", "yes")) => schema.has_headers = true,
+                Some(("header", "no")) => schema.has_headers = false,
+                _ => anyhow::bail!("Invalid schema '{}', expected sep=<comma|semicolon|tab|pipe>,header=<yes|no>", part),
+            }
+        }
+        Ok(schema)
+    }
+}
+
+/// Schema sniffed from the start of an input, and what looked wrong with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sniffed {
+    pub schema: CsvSchema,
+    /// One actionable line per problem, empty for a well-formed input
+    pub diagnostics: Vec<String>,
+}
+
+/// Guess delimiter and header presence from the first lines of an input
+///
+/// The delimiter is the candidate found most often on the first line. The
+/// line is a header unless its first field is a transaction type.
+pub fn sniff(head: &[u8]) -> Sniffed {
+    let text = String::from_utf8_lossy(head);
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let mut sniffed = Sniffed {
+        schema: CsvSchema::default(),
+        diagnostics: Vec::new(),
+    };
+    let Some(first) = lines.next() else {
+        return sniffed;
+    };
+
+    let (delimiter, name, count) = DELIMITERS
+        .iter()
+        .map(|&(delimiter, name)| (delimiter, name, first.bytes().filter(|&b| b == delimiter).count()))
+        .max_by_key(|&(_, _, count)| count)
+        .expect("at least one delimiter");
+    if count == 0 {
+        sniffed.diagnostics.push(format!(
+            "The first line has no comma, semicolon, tab or pipe, expected columns {}",
+            DEFAULT_COLUMNS[..4].join(",")
+        ));
+        return sniffed;
+    }
+    sniffed.schema.delimiter = delimiter;
+    if delimiter != b',' {
+        sniffed.diagnostics.push(format!(
+            "Reading the input as {}-separated, pass --schema sep=comma to override",
+            name
+        ));
+    }
+
+    let fields: Vec<String> = first
+        .split(delimiter as char)
+        .map(|field| field.trim().to_lowercase())
+        .collect();
+    let starts_with_row = !matches!(
+        parse_transaction_type(&fields[0]),
+        Ok(TransactionType::Custom(_)) | Err(_)
+    );
+    if starts_with_row {
+        sniffed.schema.has_headers = false;
+        sniffed.diagnostics.push(format!(
+            "No header line, reading columns in the order {}; pass --schema header=yes to override",
+            DEFAULT_COLUMNS.join(",")
+        ));
+    } else {
+        let missing: Vec<&str> = REQUIRED_COLUMNS
+            .into_iter()
+            .filter(|column| !fields.iter().any(|field| field == column))
+            .collect();
+        if !missing.is_empty() {
+            sniffed.diagnostics.push(format!(
+                "The header has no {} column, every row will be rejected; expected {}",
+                missing.join(", "),
+                DEFAULT_COLUMNS[..4].join(",")
+            ));
+        }
+    }
+
+    let data = if starts_with_row { Some(first) } else { lines.next() };
+    if let Some(data) = data {
+        let columns = data.split(delimiter as char).count();
+        if columns < REQUIRED_COLUMNS.len() {
+            sniffed.diagnostics.push(format!(
+                "The first row has {} column(s), a row needs at least type, client and tx",
+                columns
+            ));
+        }
+    }
+
+    sniffed
+}
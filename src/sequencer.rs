@@ -0,0 +1,231 @@
+use crate::errors::ProcessingError;
+use crate::models::TransactionRow;
+use crate::scalable_engine::ScalableEngine;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long rows wait for a missing sequence number when none is configured
+pub const DEFAULT_SEQUENCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of a sequenced row, sent once it was applied or given up on
+pub type SequencedOutcome = oneshot::Receiver<Result<(), ProcessingError>>;
+
+struct Pending {
+    row: TransactionRow,
+    reply: oneshot::Sender<Result<(), ProcessingError>>,
+}
+
+struct ClientSequence {
+    /// Sequence number applied next
+    next: u64,
+    /// Rows that arrived ahead of `next`, by sequence number
+    waiting: BTreeMap<u64, Pending>,
+}
+
+impl Default for ClientSequence {
+    fn default() -> Self {
+        Self::starting_at(1)
+    }
+}
+
+impl ClientSequence {
+    fn starting_at(next: u64) -> Self {
+        Self {
+            next,
+            waiting: BTreeMap::new(),
+        }
+    }
+}
+
+/// One line of the sequence state file, the number a client expects next
+#[derive(Debug, Serialize, Deserialize)]
+struct SequenceRecord {
+    client: u16,
+    next: u64,
+}
+
+/// File keeping each client's next sequence number across restarts
+///
+/// A line is appended once a client's rows are applied, so after a crash a
+/// client may expect a number it already used; that row is then applied
+/// again and refused as a repeat of its tx id. Lines are flushed to the OS
+/// and the file is rewritten with one line per client as it grows.
+struct SequenceState {
+    path: PathBuf,
+    file: Mutex<StateFile>,
+}
+
+struct StateFile {
+    writer: BufWriter<File>,
+    next: HashMap<u16, u64>,
+    lines: usize,
+}
+
+impl SequenceState {
+    /// Open the file at `path`, returning it with the number each client expects next
+    fn open(path: PathBuf) -> Result<(Self, HashMap<u16, u64>)> {
+        let mut next = HashMap::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                // A torn last line is a number that was never recorded, the earlier one stands
+                match serde_json::from_str::<SequenceRecord>(&line?) {
+                    Ok(record) => {
+                        next.insert(record.client, record.next);
+                    }
+                    Err(e) => tracing::warn!("Skipping unreadable sequence record in {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        let writer = rewrite(&path, &next).with_context(|| format!("opening sequence state {}", path.display()))?;
+        let state = Self {
+            path,
+            file: Mutex::new(StateFile {
+                writer,
+                next: next.clone(),
+                lines: next.len(),
+            }),
+        };
+        Ok((state, next))
+    }
+
+    fn record(&self, client: u16, next: u64) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.next.insert(client, next);
+        write_record(&mut file.writer, &SequenceRecord { client, next })?;
+        file.writer.flush()?;
+        file.lines += 1;
+
+        // Only the last line of each client counts, start over once most are stale
+        if file.lines > 4 * file.next.len() + 1024 {
+            file.writer = rewrite(&self.path, &file.next)?;
+            file.lines = file.next.len();
+        }
+        Ok(())
+    }
+}
+
+/// Replace the file at `path` with one line per client, returning a writer appending to it
+fn rewrite(path: &Path, next: &HashMap<u16, u64>) -> Result<BufWriter<File>> {
+    let tmp = crate::reporting::tmp_path(path);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    for (&client, &next) in next {
+        write_record(&mut writer, &SequenceRecord { client, next })?;
+    }
+    writer.flush()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(BufWriter::new(OpenOptions::new().append(true).open(path)?))
+}
+
+fn write_record(writer: &mut BufWriter<File>, record: &SequenceRecord) -> Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    Ok(())
+}
+
+/// Applies each client's rows in the order of producer-assigned sequence numbers
+///
+/// Sequences start at 1 per client and are shared by every connection feeding
+/// that client. A row arriving ahead of its turn waits for the ones before it;
+/// if the gap is not filled within the timeout of it opening, the rows waiting
+/// are rejected as `OutOfSequence` and the client still expects the missing
+/// number. A number already applied or already waiting is rejected the same way.
+///
+/// Without a state file, see `persistent`, every client starts over at 1 on restart.
+pub struct Sequencer {
+    timeout: Duration,
+    clients: Mutex<HashMap<u16, Arc<tokio::sync::Mutex<ClientSequence>>>>,
+    state: Option<SequenceState>,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEQUENCE_TIMEOUT)
+    }
+}
+
+impl Sequencer {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            clients: Mutex::new(HashMap::new()),
+            state: None,
+        }
+    }
+
+    /// Sequencer resuming each client at the number `path` recorded for it
+    pub fn persistent(timeout: Duration, path: PathBuf) -> Result<Self> {
+        let (state, next) = SequenceState::open(path)?;
+        let clients = next
+            .into_iter()
+            .map(|(client, next)| (client, Arc::new(tokio::sync::Mutex::new(ClientSequence::starting_at(next)))))
+            .collect();
+        Ok(Self {
+            timeout,
+            clients: Mutex::new(clients),
+            state: Some(state),
+        })
+    }
+
+    /// Queue `row` as number `seq` of its client, applying every row whose turn has come
+    ///
+    /// Returns once the rows now in order are applied, the outcome of `row`
+    /// itself may still be pending behind a gap.
+    pub async fn submit(&self, engine: &ScalableEngine, seq: u64, row: TransactionRow) -> SequencedOutcome {
+        let (reply, outcome) = oneshot::channel();
+        let client_id = row.client;
+        let client = self.clients.lock().unwrap().entry(client_id).or_default().clone();
+        let mut sequence = client.lock().await;
+
+        if seq < sequence.next || sequence.waiting.contains_key(&seq) {
+            let _ = reply.send(Err(ProcessingError::OutOfSequence));
+            return outcome;
+        }
+
+        let gap_before = sequence.next;
+        let was_waiting = !sequence.waiting.is_empty();
+        sequence.waiting.insert(seq, Pending { row, reply });
+
+        loop {
+            let next = sequence.next;
+            let Some(pending) = sequence.waiting.remove(&next) else { break };
+            let result = engine.process(pending.row).await;
+            let _ = pending.reply.send(result);
+            sequence.next += 1;
+        }
+
+        if let Some(state) = self.state.as_ref().filter(|_| sequence.next != gap_before) {
+            // Stale on failure, which costs a repeat refused by tx id rather than a gap
+            if let Err(e) = state.record(client_id, sequence.next) {
+                tracing::error!("Failed to record sequence {} of client {} in {}: {}", sequence.next, client_id, state.path.display(), e);
+            }
+        }
+
+        // One timer per gap, a timer whose gap was filled finds `next` moved on
+        if !sequence.waiting.is_empty() && (!was_waiting || sequence.next != gap_before) {
+            let gap = sequence.next;
+            let client = client.clone();
+            let timeout = self.timeout;
+            tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                let mut sequence = client.lock().await;
+                if sequence.next == gap {
+                    for (_, pending) in std::mem::take(&mut sequence.waiting) {
+                        let _ = pending.reply.send(Err(ProcessingError::OutOfSequence));
+                    }
+                }
+            });
+        }
+
+        outcome
+    }
+}
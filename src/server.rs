@@ -1,83 +1,693 @@
-use crate::csv_io::{stream_transactions, write_accounts};
-use crate::models::AccountOutput;
-use crate::scalable_engine::ScalableEngine;
-use crate::storage::{InMemoryStore, TransactionStore};
+use crate::alerts;
+use crate::amount::{AmountFormat, AmountUnits};
+use crate::auth::{ApiKeys, ClientScope};
+use crate::backpressure::BackpressureConfig;
+use crate::compat::CompatConfig;
+use crate::config::EngineConfig;
+use crate::csv_io::stream_sequenced_transactions;
+use crate::errors::ProcessingError;
+use crate::escalation::{EscalationMonitor, EscalationPolicy, WebhookSink};
+use crate::event_store::DurabilityPolicy;
+use crate::export::{self, ExportConfig};
+use crate::ingestion::{self, Cutover};
+use crate::intake::{IntakeLog, Receipt};
+use crate::prober::LatencyProber;
+use crate::recorder::{FixtureRecorder, RecorderConfig};
+use crate::models::{AccountOutput, TransactionRow};
+use crate::routing::RoutingMode;
+use crate::scalable_engine::{ScalableEngine, PREFETCH_WINDOW};
+use crate::sequencer::Sequencer;
+use crate::spill::{self, SpillConfig};
+use crate::storage::open_cold_storage;
+use crate::wire::{Ack, ConnectionHeader, Protocol, RowOrdering, WireCodec, WireFormat, WireReader, WireWriter};
 use anyhow::Result;
-use futures::StreamExt;
+use futures::future::{self, BoxFuture};
+use futures::stream::{BoxStream, FuturesOrdered, Stream};
+use futures::{FutureExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::io::{BufReader, BufWriter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
-pub async fn run(bind: String, max_connections: usize) -> Result<()> {
+/// How often open disputes are checked for escalation
+const ESCALATION_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Everything `server` is started with
+pub struct ServerConfig {
+    pub bind: String,
+    pub max_connections: usize,
+    pub http_bind: Option<String>,
+    /// Serve a Swagger UI at `/docs` of the HTTP API
+    pub swagger_ui: bool,
+    /// Also serve the gRPC service here, needs the `grpc` feature
+    pub grpc_bind: Option<String>,
+    pub event_log_path: PathBuf,
+    /// Shards, actor and migration limits; its event log is `event_log_path`
+    pub engine: EngineConfig,
+    pub compat: CompatConfig,
+    pub storage_path: Option<PathBuf>,
+    /// `s3://<bucket>/<prefix>` cold transactions are offloaded to, instead of `storage_path`
+    pub object_store_url: Option<String>,
+    /// Snapshot file and the interval it is rewritten at
+    pub snapshot: Option<(PathBuf, Duration)>,
+    pub durability: DurabilityPolicy,
+    /// Historical file applied up to its cutover before live rows are
+    pub backfill: Option<(PathBuf, Cutover)>,
+    /// Directory keeping registered tx ids across restarts
+    pub tx_registry_dir: Option<PathBuf>,
+    /// How long open connections may take to finish once shutdown is requested
+    pub shutdown_grace: Duration,
+    /// How long sequenced rows wait for a missing sequence number
+    pub sequence_timeout: Duration,
+    /// File keeping each client's next sequence number across restarts
+    pub sequence_state: Option<PathBuf>,
+    /// How clients are assigned to account shards, fixed for the life of the log
+    pub routing: RoutingMode,
+    /// Webhook disputes needing a human are posted to, and when they do
+    pub escalation: Option<(String, EscalationPolicy)>,
+    /// POST balance alerts raised by the clients' alert rules here as JSON
+    pub alert_webhook: Option<String>,
+    /// Interval cold storage is compacted at, besides the admin API
+    pub compaction_interval: Option<Duration>,
+    /// Where connections spill rows arriving faster than they are applied
+    pub spill: Option<SpillConfig>,
+    /// Queue depth at which connections stop reading, and refuse rows once it lasts
+    pub backpressure: Option<BackpressureConfig>,
+    /// Where rows are staged on receipt so a crash before they are applied doesn't lose them
+    pub intake_log: Option<PathBuf>,
+    /// Record a sample of the live rows as a golden fixture bundle
+    pub recording: Option<RecorderConfig>,
+    /// Interval synthetic transactions measure end-to-end latency at
+    pub probe_interval: Option<Duration>,
+    /// TOML file of API keys and their clients, submissions are open to anyone without one
+    pub api_keys: Option<PathBuf>,
+    /// Where accounts are exported as client id range partitions, and how often
+    pub export: Option<(ExportConfig, Duration)>,
+    /// Amount spellings accepted from producers on every front end
+    pub amounts: AmountFormat,
+}
+
+pub async fn run(config: ServerConfig) -> Result<()> {
+    let ServerConfig {
+        bind,
+        max_connections,
+        http_bind,
+        swagger_ui,
+        grpc_bind,
+        event_log_path,
+        engine,
+        compat,
+        storage_path,
+        object_store_url,
+        snapshot,
+        durability,
+        backfill,
+        tx_registry_dir,
+        shutdown_grace,
+        sequence_timeout,
+        sequence_state,
+        routing,
+        escalation,
+        alert_webhook,
+        compaction_interval,
+        spill,
+        backpressure,
+        intake_log,
+        recording,
+        probe_interval,
+        api_keys,
+        export,
+        amounts,
+    } = config;
     tracing::info!("Server mode: binding to {}", bind);
     
-    // Use in-memory cold storage for server
-    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let cold_storage = open_cold_storage(storage_path, object_store_url)?;
+    
+    let config = EngineConfig {
+        event_log: Some(event_log_path),
+        ..engine
+    };
+    let mut engine = ScalableEngine::from_config(&config, cold_storage)
+        .await?
+        .with_compat(compat)?
+        .with_routing(routing.strategy())?
+        .with_durability(durability)?
+        .with_amount_format(amounts);
+    engine = match sequence_state {
+        Some(path) => engine.with_sequencer(Sequencer::persistent(sequence_timeout, path)?),
+        None => engine.with_sequence_timeout(sequence_timeout),
+    };
+    if let Some((path, _)) = &snapshot {
+        engine = engine.with_snapshot_path(path.clone());
+    }
+    if let Some(spill) = spill {
+        std::fs::create_dir_all(&spill.dir)?;
+        engine = engine.with_spill(spill);
+    }
+    if let Some(backpressure) = backpressure {
+        engine = engine.with_backpressure(backpressure);
+    }
+    if let Some(dir) = &tx_registry_dir {
+        engine = engine.with_tx_registry_dir(dir).await?;
+    }
+    if let Some(recording) = recording {
+        engine = engine.with_recorder(FixtureRecorder::new(recording));
+    }
+    if probe_interval.is_some() {
+        engine = engine.with_prober();
+    }
+    if let Some(path) = api_keys {
+        let keys = ApiKeys::from_file(&path)?;
+        tracing::info!("Accepting submissions under {} API key(s)", keys.len());
+        engine = engine.with_api_keys(keys);
+    }
+    let mut unsettled = Vec::new();
+    if let Some(path) = intake_log {
+        let (intake, pending) = IntakeLog::open(path)?;
+        engine = engine.with_intake(intake);
+        unsettled = pending;
+    }
+    let engine = Arc::new(engine);
     
-    let event_log_path = PathBuf::from("server_transactions.log");
-    let engine = Arc::new(ScalableEngine::new(event_log_path, 16, cold_storage).await?);
+    // Counters persisted by the last run, replay counts what they miss
+    engine
+        .dispute_counters()
+        .attach_file(PathBuf::from("server_counters.csv"))
+        .await?;
     
     // Rebuild state from previous runs
     engine.rebuild_from_events().await?;
     
-    let listener = TcpListener::bind(&bind).await?;
-    let semaphore = Arc::new(Semaphore::new(max_connections));
+    // Rows the last run accepted but never applied, before any new ones
+    if !unsettled.is_empty() {
+        let staged = unsettled.len();
+        let applied = engine.recover_intake(unsettled).await?;
+        tracing::info!("Recovered {} staged rows from the intake log, {} applied", staged, applied);
+    }
     
+    engine
+        .periods()
+        .attach_file(PathBuf::from("server_periods.log"))
+        .await?;
+    engine
+        .alerts()
+        .attach_file(PathBuf::from("server_alerts.json"))
+        .await?;
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                ticker.tick().await;
+                if let Err(e) = engine.flush_counters().await {
+                    tracing::error!("Failed to persist reporting counters: {}", e);
+                }
+            }
+        });
+    }
+    if let Some((_, interval)) = snapshot {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await; // Just restored, nothing new to snapshot yet
+            loop {
+                ticker.tick().await;
+                if let Err(e) = engine.write_snapshot().await {
+                    tracing::error!("Failed to write engine snapshot: {}", e);
+                }
+            }
+        });
+    }
+    if let Some(interval) = compaction_interval {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await; // The first pass waits a full interval, not startup
+            loop {
+                ticker.tick().await;
+                if let Err(e) = engine.compact_cold_storage().await {
+                    tracing::error!("Failed to compact cold storage: {}", e);
+                }
+            }
+        });
+    }
+    if let Some((config, interval)) = export {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await; // Nothing applied yet at startup worth a first export
+            loop {
+                ticker.tick().await;
+                if let Err(e) = export_now(&engine, &config).await {
+                    tracing::error!("Failed to export accounts to {}: {}", config.dir.display(), e);
+                }
+            }
+        });
+    }
+    if let Some(url) = alert_webhook {
+        alerts::spawn_notifier(&engine, Arc::new(WebhookSink::new(url)));
+    }
+    if let Some((url, policy)) = escalation {
+        let monitor = EscalationMonitor::new(policy, Arc::new(WebhookSink::new(url)));
+        Arc::new(monitor).spawn(engine.clone(), ESCALATION_SWEEP_INTERVAL);
+    }
+    if let (Some(interval), Some(metrics)) = (probe_interval, engine.probe_metrics()) {
+        Arc::new(LatencyProber::new(metrics.clone())).spawn(engine.clone(), interval);
+    }
+    
+    // Connections are accepted meanwhile, their rows wait for the hand-over
+    if let Some((path, cutover)) = backfill {
+        engine.ingestion().start_backfill();
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(e) = ingestion::backfill(&engine, &path, cutover).await {
+                tracing::error!("Backfill of {} failed: {}", path.display(), e);
+            }
+        });
+    }
+    
+    #[cfg(not(feature = "grpc"))]
+    if grpc_bind.is_some() {
+        anyhow::bail!("--grpc-bind needs a build with the grpc feature");
+    }
+
+    let shutdown = CancellationToken::new();
+    let http = http_bind.map(|http_bind| {
+        let engine = engine.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::http::serve(http_bind, engine, swagger_ui, shutdown.cancelled_owned()).await {
+                tracing::error!("HTTP API error: {}", e);
+            }
+        })
+    });
+    #[cfg(feature = "grpc")]
+    let grpc = grpc_bind.map(|grpc_bind| {
+        let engine = engine.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::grpc::serve(grpc_bind, engine, shutdown.cancelled_owned()).await {
+                tracing::error!("gRPC service error: {}", e);
+            }
+        })
+    });
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            tracing::info!("Shutdown requested, draining connections");
+            shutdown.cancel();
+        });
+    }
+    
+    let listener = TcpListener::bind(&bind).await?;
     tracing::info!("Listening on {}, max {} connections", bind, max_connections);
+    serve_until(listener, engine.clone(), max_connections, shutdown, shutdown_grace).await?;
+    if let Some(http) = http {
+        // Requests in flight get the same grace as connections
+        let _ = tokio::time::timeout(shutdown_grace, http).await;
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc) = grpc {
+        let _ = tokio::time::timeout(shutdown_grace, grpc).await;
+    }
+    
+    // Nothing writes anymore: persist what the actors, registry and counters still buffer
+    engine.shutdown().await?;
+    engine.flush_counters().await?;
+    if let Some(recorder) = engine.recorder() {
+        finish_recording(recorder).await;
+    }
+    tracing::info!("Shutdown complete");
+    Ok(())
+}
+
+/// SIGTERM or Ctrl-C
+pub(crate) async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Final outcome of a live row from `source` that was processed with `outcome`
+///
+/// Rows repeating the backfill count as applied, refused duplicates are
+/// counted against the source and the row goes to the fixture recorder, if any.
+pub(crate) async fn settle_live_row(
+    engine: &ScalableEngine,
+    source: &str,
+    row: &TransactionRow,
+    outcome: Result<(), ProcessingError>,
+) -> Result<(), ProcessingError> {
+    let outcome = engine.ingestion().settle_live(engine, row, outcome).await;
+    engine.duplicates().record_outcome(source, row.tx, &outcome);
+    if let Some(recorder) = engine.recorder() {
+        if recorder.record(row) {
+            finish_recording(recorder).await;
+        }
+    }
+    outcome
+}
+
+/// Export the accounts into a new subdirectory of `config.dir`, named after the export's unix time
+///
+/// Earlier exports are left in place, a loader may still be reading one.
+pub async fn export_now(engine: &ScalableEngine, config: &ExportConfig) -> Result<PathBuf> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let dir = config.dir.join(secs.to_string());
+    let manifest = export::export_accounts(engine, &ExportConfig { dir: dir.clone(), ..config.clone() }, AmountUnits::Decimal).await?;
+    tracing::info!("Exported {} accounts in {} partitions to {}", manifest.rows, manifest.partitions.len(), dir.display());
+    Ok(dir)
+}
+
+/// Write a recording's bundle, a failure only costs the fixture
+async fn finish_recording(recorder: &FixtureRecorder) {
+    match recorder.finish().await {
+        Ok(Some(dir)) => tracing::info!("Recorded fixture written to {}", dir.display()),
+        Ok(None) => {}
+        Err(e) => tracing::error!("Failed to write recorded fixture to {}: {}", recorder.config().dir.display(), e),
+    }
+}
+
+/// Serve connections on `listener` until `shutdown` is cancelled, then drain them
+///
+/// Once cancelled no connection is accepted; open ones finish their rows
+/// and get their answer. Connections still open after `grace` are aborted,
+/// their unacknowledged rows may or may not have been applied.
+pub async fn serve_until(
+    listener: TcpListener,
+    engine: Arc<ScalableEngine>,
+    max_connections: usize,
+    shutdown: CancellationToken,
+    grace: Duration,
+) -> Result<()> {
+    let semaphore = Arc::new(Semaphore::new(max_connections));
+    let mut connections = JoinSet::new();
     
     loop {
-        let permit = semaphore.clone().acquire_owned().await?;
-        let (socket, addr) = listener.accept().await?;
+        let accepted = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            // Reap finished connections so the set only holds open ones
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            accepted = async {
+                let permit = semaphore.clone().acquire_owned().await?;
+                let (socket, addr) = listener.accept().await?;
+                anyhow::Ok((permit, socket, addr))
+            } => accepted?,
+        };
+        let (permit, socket, addr) = accepted;
         tracing::info!("Accepted connection from {}", addr);
         
         let engine = engine.clone();
         
-        tokio::spawn(async move {
+        connections.spawn(async move {
             if let Err(e) = handle_connection(socket, engine).await {
                 tracing::error!("Connection {} error: {}", addr, e);
             }
             drop(permit);
         });
     }
+    drop(listener);
+    
+    let open = connections.len();
+    if open > 0 {
+        tracing::info!("Waiting up to {:?} for {} open connection(s)", grace, open);
+    }
+    let drained = tokio::time::timeout(grace, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        tracing::warn!("Aborting {} connection(s) still open after {:?}", connections.len(), grace);
+        connections.shutdown().await;
+    }
+    Ok(())
 }
 
-async fn handle_connection(
+/// Serve one client: apply its rows, then answer as negotiated by its header lines
+pub async fn handle_connection(
     socket: TcpStream,
     engine: Arc<ScalableEngine>,
 ) -> Result<()> {
-    let (reader, writer) = socket.into_split();
-    let reader = BufReader::new(reader);
+    // A peer gone already has no address, its rows never arrive either
+    let peer = socket.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let (reader, mut writer) = socket.into_split();
+    let mut reader = BufReader::new(reader);
+    
+    // A `#hello` is answered on the spot, the client may wait for it before sending rows
+    let header = ConnectionHeader::read_answering(&mut reader, &mut writer, &engine.capabilities()).await?;
+    let ConnectionHeader { protocol, format, units, source, ordering, api_key, .. } = header;
+    let source = source.unwrap_or(peer);
+    // Rows for clients out of scope are refused one by one, the connection stays open for the rest
+    let scope = engine.access(api_key.as_deref());
+    
+    // Each connection picks its own format, named in its header or told apart by the first byte
+    let format = match format {
+        Some(format) => format,
+        None => WireFormat::sniff(&mut reader).await?,
+    };
+    tracing::debug!("Connection using {:?} framing, {:?} protocol, {:?} amounts", format, protocol, units);
+    let codec = format.codec_in(units, engine.amount_format());
+    if ordering == RowOrdering::Sequenced && format != WireFormat::Csv {
+        anyhow::bail!("Sequenced ordering needs CSV rows with a seq column, got {:?}", format);
+    }
+    
+    // Nothing from the live stream may land before the backfill's rows
+    engine.ingestion().wait_live().await?;
+    
+    let mut writer: WireWriter = Box::new(BufWriter::new(writer));
+    let connection = Connection {
+        engine: &engine,
+        codec: codec.as_ref(),
+        protocol,
+        source: &source,
+        scope,
+    };
+    match ordering {
+        RowOrdering::Arrival => connection.apply_in_arrival_order(Box::new(reader), &mut writer, format).await?,
+        RowOrdering::Sequenced => connection.apply_in_sequence(Box::new(reader), &mut writer, units).await?,
+    }
     
-    // Stream CSV from socket
-    let mut stream = stream_transactions(reader);
+    if protocol == Protocol::Ack {
+        return Ok(());
+    }
+    
+    // Final state in client order, read from the actors only as fast as the client takes it
+    let accounts = engine
+        .stream_accounts()
+        .map(|account| AccountOutput::from(&account))
+        .boxed();
+    codec.write_accounts(writer, accounts).await?;
     
-    while let Some(result) = stream.next().await {
-        match result {
-            Ok(row) => {
-                // Process via parallel actors
-                let _ = engine.process(row).await;
+    Ok(())
+}
+
+/// Rows read as fast as they arrive, spilled to disk past the engine's limit, if it has one
+///
+/// Without a spill buffer rows are only read as fast as they are applied,
+/// so a burst backs up into the producer's socket.
+fn absorb_bursts<'a, T>(engine: &ScalableEngine, rows: impl Stream<Item = Result<T>> + Send + 'a) -> BoxStream<'a, Result<T>>
+where
+    T: Serialize + DeserializeOwned + Send + 'a,
+{
+    match engine.spill() {
+        Some(spill) => spill::absorb(rows, spill),
+        None => rows.boxed(),
+    }
+}
+
+/// What the rows of one connection are applied and acknowledged with
+struct Connection<'a> {
+    engine: &'a Arc<ScalableEngine>,
+    codec: &'a dyn WireCodec,
+    protocol: Protocol,
+    source: &'a str,
+    /// Clients the connection's API key may submit for
+    scope: ClientScope,
+}
+
+impl Connection<'_> {
+    async fn apply_in_arrival_order(&self, reader: WireReader, writer: &mut WireWriter, format: WireFormat) -> Result<()> {
+        let engine = self.engine;
+        // Staged on receipt, before a spill buffer or the actors hold them
+        let intake = engine.intake().cloned();
+        let rows = self.codec.decode_rows(reader).map(move |row| {
+            let row = row?;
+            let receipt = intake.as_ref().map_or(Receipt::Untracked, |intake| intake.stage(&row));
+            Ok((receipt, row))
+        });
+        let mut stream = absorb_bursts(engine, rows).ready_chunks(PREFETCH_WINDOW);
+        
+        loop {
+            // Nothing more is read while the actors are saturated; if they stay so, the next chunk is refused
+            let capacity = engine.wait_for_capacity().await;
+            let Some(chunk) = stream.next().await else { break };
+            let chunk: Vec<Result<(Receipt, TransactionRow)>> = chunk;
+            let rows: Vec<TransactionRow> = chunk
+                .iter()
+                .filter_map(|result| match result {
+                    Ok((receipt, row))
+                        if *receipt != Receipt::Unstaged && self.scope.permits(row.client) && capacity.is_ok() =>
+                    {
+                        Some(row.clone())
+                    }
+                    _ => None,
+                })
+                .collect();
+            
+            // Warm cold storage for disputes in this chunk before the actors need it
+            engine.prefetch(&rows).await;
+            
+            // Rows are acknowledged in the order they were sent, each client's also applied in that order
+            let mut outcomes = engine.process_batch(rows).await.into_iter();
+            for result in chunk {
+                let ack = match result {
+                    Ok((receipt, row)) if !self.scope.permits(row.client) => {
+                        // Never applied, so not worth recovering after a restart either
+                        self.settle_receipt(receipt);
+                        Ack::new(row.tx, &self.scope.check(&row))
+                    }
+                    Ok((Receipt::Unstaged, row)) => Ack::new(row.tx, &Err(ProcessingError::StorageUnavailable)),
+                    Ok((receipt, row)) if capacity.is_err() => {
+                        // The producer sends it again after the hint, recovering it too would apply it twice
+                        self.settle_receipt(receipt);
+                        Ack::new(row.tx, &capacity)
+                    }
+                    Ok((receipt, row)) => {
+                        let outcome = outcomes.next().expect("one outcome per parsed row");
+                        let ack = self.settle(&row, outcome).await;
+                        self.settle_receipt(receipt);
+                        ack
+                    }
+                    Err(e) => {
+                        tracing::warn!("{:?} parse error: {}", format, e);
+                        Ack::malformed()
+                    }
+                };
+                self.write_ack(writer, &ack).await?;
             }
-            Err(e) => {
-                tracing::warn!("CSV parse error: {}", e);
+            
+            // One flush per chunk keeps acks timely without a write per row
+            if self.protocol == Protocol::Ack {
+                writer.flush().await?;
             }
         }
+        
+        Ok(())
     }
     
-    // Read final state and return to client
-    let mut accounts: Vec<AccountOutput> = engine
-        .get_accounts()
-        .await
-        .iter()
-        .map(AccountOutput::from)
-        .collect();
+    /// Rows numbered per client, applied in that order with other connections' rows for the client
+    async fn apply_in_sequence(&self, reader: WireReader, writer: &mut WireWriter, units: AmountUnits) -> Result<()> {
+        let engine = self.engine;
+        let rows = stream_sequenced_transactions(reader, engine.amount_format()).map(move |result| {
+            let row = result?;
+            Ok((row.seq, units.decode_row(row.into_row())?))
+        });
+        let mut stream = absorb_bursts(engine, rows).ready_chunks(PREFETCH_WINDOW);
+        
+        // Acks go out in row order, but reading goes on while one waits on a gap a later chunk may fill
+        let mut acks: FuturesOrdered<BoxFuture<'_, Ack>> = FuturesOrdered::new();
+        let mut reading = true;
+        while reading || !acks.is_empty() {
+            tokio::select! {
+                Some(ack) = acks.next(), if !acks.is_empty() => {
+                    self.write_ack(writer, &ack).await?;
+                    while let Some(Some(ack)) = acks.next().now_or_never() {
+                        self.write_ack(writer, &ack).await?;
+                    }
+                    if self.protocol == Protocol::Ack {
+                        writer.flush().await?;
+                    }
+                }
+                rows = async {
+                    // Only paused for, a refused sequence number would hold up the client's later rows
+                    let _ = engine.wait_for_capacity().await;
+                    stream.next().await
+                }, if reading => {
+                    let Some(rows) = rows else {
+                        reading = false;
+                        continue;
+                    };
+                    self.queue_sequenced(rows, &mut acks).await;
+                }
+            }
+        }
+        
+        Ok(())
+    }
     
-    // Sort accounts by client ID for simplicity in CLI output
-    accounts.sort_by_key(|a| a.client);
+    /// Submit a chunk of sequenced rows, queueing the ack each will get
+    async fn queue_sequenced<'a>(&'a self, rows: Vec<Result<(u64, TransactionRow)>>, acks: &mut FuturesOrdered<BoxFuture<'a, Ack>>) {
+        let engine = self.engine;
+        let parsed: Vec<TransactionRow> = rows
+            .iter()
+            .filter_map(|row| row.as_ref().ok())
+            .filter(|(_, row)| self.scope.permits(row.client))
+            .map(|(_, row)| row.clone())
+            .collect();
+        engine.prefetch(&parsed).await;
+        
+        for row in rows {
+            let ack = match row {
+                // Out of scope rows take no sequence number, the client's producers fill it
+                Ok((_, row)) if !self.scope.permits(row.client) => future::ready(Ack::new(row.tx, &self.scope.check(&row))).boxed(),
+                Ok((seq, row)) => {
+                    let outcome = engine.sequencer().submit(engine, seq, row.clone()).await;
+                    async move {
+                        let outcome = outcome.await.unwrap_or(Err(ProcessingError::ActorCommunicationError));
+                        self.settle(&row, outcome).await
+                    }
+                    .boxed()
+                }
+                Err(e) => {
+                    tracing::warn!("Sequenced row parse error: {}", e);
+                    future::ready(Ack::malformed()).boxed()
+                }
+            };
+            acks.push_back(ack);
+        }
+    }
     
-    let writer = BufWriter::new(writer);
-    write_accounts(writer, accounts).await?;
+    /// Ack of a row applied or rejected with `outcome`
+    async fn settle(&self, row: &TransactionRow, outcome: Result<(), ProcessingError>) -> Ack {
+        let outcome = settle_live_row(self.engine, self.source, row, outcome).await;
+        Ack::new(row.tx, &outcome)
+    }
     
-    Ok(())
+    /// Mark a staged row as done in the intake log, it won't be recovered on restart
+    fn settle_receipt(&self, receipt: Receipt) {
+        if let (Receipt::Staged(id), Some(intake)) = (receipt, self.engine.intake()) {
+            // The row is applied either way, at worst a restart offers it again and it is refused as a repeat
+            if let Err(e) = intake.settle(id) {
+                tracing::error!("Failed to settle intake record {}: {}", id, e);
+            }
+        }
+    }
+    
+    async fn write_ack(&self, writer: &mut WireWriter, ack: &Ack) -> Result<()> {
+        if self.protocol == Protocol::Ack {
+            self.codec.write_ack(writer, ack).await?;
+        }
+        Ok(())
+    }
 }
@@ -1,92 +1,539 @@
-use crate::account_actor::{AccountActor, AccountHandle};
+use crate::account_actor::{
+    first_failure, AccountActor, AccountHandle, AccountMessage, ActorServices, TransferLeg, Undo, ValidationCheck,
+};
+use crate::alerts::AlertRules;
+use crate::backpressure::QueueDepths;
+use crate::clock::SystemClock;
+use crate::compat::CompatConfig;
+use crate::config::EngineConfig;
+use crate::contention::{self, measure, Site};
 use crate::errors::ProcessingError;
-use crate::models::{Account, TransactionRow};
-use crate::storage::TransactionStore;
+use crate::events::{AccountUpdates, EventBus};
+use crate::handlers::HandlerRegistry;
+use crate::history::{Pagination, TransactionPage};
+use crate::hot_store::{HotStorageSize, HotTransaction};
+use crate::metrics::{MigrationMetrics, MigrationMetricsSnapshot};
+use crate::migration::MigrationConfig;
+use crate::models::{Account, TransactionRow, TransactionType};
+use crate::projection::AccountProjection;
+use crate::reporting::ReportingCounters;
+use crate::routing::{ModuloRouting, RoutingStrategy};
+use crate::snapshots::InMemorySnapshotStore;
+use crate::storage::{InMemoryStore, RetryPolicy, StorageTier, StoredTransaction, TransactionStore};
+use crate::treasury::{ShardTotals, TreasuryReport};
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, Mutex, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+use utoipa::ToSchema;
+
+/// Account states requested ahead of the one being written by `stream_accounts`
+const ACCOUNT_STREAM_WINDOW: usize = 64;
+
+/// Load of one shard, to spot clients piling up on a few shards
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ShardStats {
+    pub shard: usize,
+    /// Running actors
+    pub actors: usize,
+    /// Hot transactions those actors hold in memory
+    pub hot_transactions: usize,
+    /// Messages waiting in their mailboxes
+    pub mailbox_depth: usize,
+    /// Messages sent to them still awaiting a reply, what backpressure watches
+    pub in_flight: usize,
+}
+
+/// Shards in routing order, swapped whole by a reshard
+type ShardTable = Arc<Vec<Arc<RwLock<Shard>>>>;
 
 /// Manages multiple shards for parallel processing
 pub struct ShardManager {
-    shards: Vec<Arc<RwLock<Shard>>>,
-    num_shards: usize,
-    cold_storage: Arc<dyn TransactionStore>,
+    shards: std::sync::RwLock<ShardTable>,
+    /// Held by a reshard, and by shutdown so that it drains the current table
+    resharding: Mutex<()>,
+    services: ActorServices,
+    projection: Arc<AccountProjection>,
+    migration_metrics: Arc<MigrationMetrics>,
+    routing: Arc<dyn RoutingStrategy>,
+    residency: Arc<Residency>,
+    queued: QueueDepths,
 }
 
+#[derive(Default)]
 struct Shard {
     actors: HashMap<u16, AccountHandle>,
+    /// Replaced by a reshard, its clients are looked up again in the new table
+    retired: bool,
+}
+
+/// Live actors and the use counter they are stamped with, for `ActorConfig::max_resident`
+#[derive(Default)]
+struct Residency {
+    live: AtomicUsize,
+    uses: AtomicU64,
+    evicting: AtomicBool,
 }
 
 impl ShardManager {
     pub fn new(num_shards: usize, cold_storage: Arc<dyn TransactionStore>) -> Self {
-        let shards = (0..num_shards)
-            .map(|_| {
-                Arc::new(RwLock::new(Shard {
-                    actors: HashMap::new(),
-                }))
-            })
-            .collect();
+        Self::with_migration_config(num_shards, cold_storage, MigrationConfig::default())
+    }
+    
+    pub fn with_migration_config(
+        num_shards: usize,
+        cold_storage: Arc<dyn TransactionStore>,
+        migration_config: MigrationConfig,
+    ) -> Self {
+        let config = EngineConfig {
+            shards: num_shards,
+            migration: migration_config,
+            ..Default::default()
+        };
+        Self::with_config(&config, cold_storage)
+    }
+    
+    /// Shards and actors laid out as `config` says, its event log is the engine's business
+    pub fn with_config(config: &EngineConfig, cold_storage: Arc<dyn TransactionStore>) -> Self {
+        let num_shards = config.shards;
+        let shards = (0..num_shards).map(|_| Arc::new(RwLock::new(Shard::default()))).collect();
         
-        Self {
-            shards,
-            num_shards,
+        let (projection, projection_tx) = AccountProjection::spawn();
+        
+        let migration_metrics = Arc::new(MigrationMetrics::new());
+        let services = ActorServices {
             cold_storage,
+            migration_metrics: migration_metrics.clone(),
+            migration_config: config.migration.clone(),
+            actor_config: config.actor.clone(),
+            storage_retry: RetryPolicy::default(),
+            projection: projection_tx,
+            handlers: Arc::new(HandlerRegistry::new()),
+            counters: Arc::new(ReportingCounters::new()),
+            clock: Arc::new(SystemClock),
+            compat: CompatConfig::default(),
+            snapshots: Arc::new(InMemorySnapshotStore::new()),
+            totals: Arc::new(ShardTotals::new(num_shards)),
+            alerts: Arc::new(AlertRules::new(EventBus::default())),
+            updates: AccountUpdates::default(),
+        };
+        
+        Self {
+            shards: std::sync::RwLock::new(Arc::new(shards)),
+            resharding: Mutex::new(()),
+            services,
+            projection,
+            migration_metrics,
+            routing: Arc::new(ModuloRouting),
+            residency: Arc::new(Residency::default()),
+            queued: QueueDepths::new(num_shards),
+        }
+    }
+    
+    /// One shard with in-memory transactions, applying replayed events as an engine under `compat` with `handlers` did
+    ///
+    /// For re-running a log to explain it, see `trace` and `statement`.
+    pub fn scratch(compat: CompatConfig, handlers: Arc<HandlerRegistry>) -> Self {
+        let mut manager = Self::new(1, Arc::new(InMemoryStore::new()));
+        manager.services.compat = compat;
+        manager.services.handlers = handlers;
+        manager
+    }
+    
+    /// Alert rules every actor checks its changes against, set before any actor is spawned
+    pub fn set_alerts(&mut self, alerts: Arc<AlertRules>) {
+        self.services.alerts = alerts;
+    }
+    
+    pub fn alerts(&self) -> &Arc<AlertRules> {
+        &self.services.alerts
+    }
+    
+    /// New state of every account each time an actor applies a change to it
+    pub fn account_updates(&self) -> &AccountUpdates {
+        &self.services.updates
+    }
+    
+    /// Spec interpretation for actors spawned from now on
+    pub fn set_compat(&mut self, compat: CompatConfig) {
+        self.services.compat = compat;
+    }
+    
+    pub fn compat(&self) -> CompatConfig {
+        self.services.compat
+    }
+    
+    /// Assign clients to shards with `routing`, before any actor is spawned
+    pub fn set_routing(&mut self, routing: Arc<dyn RoutingStrategy>) {
+        self.services.totals = Arc::new(ShardTotals::with_routing(self.num_shards(), routing.clone()));
+        self.routing = routing;
+    }
+    
+    /// Shard owning the client's actor
+    pub fn shard_of(&self, client_id: u16) -> usize {
+        self.route(client_id, self.num_shards())
+    }
+    
+    fn route(&self, client_id: u16, num_shards: usize) -> usize {
+        self.routing.shard(client_id, num_shards) % num_shards
+    }
+    
+    pub fn num_shards(&self) -> usize {
+        self.shards.read().unwrap().len()
+    }
+    
+    /// The current shard table, shards of an older one may be retired by the time they are locked
+    fn shards(&self) -> ShardTable {
+        self.shards.read().unwrap().clone()
+    }
+    
+    /// Read lock on the shard owning the client in the current table
+    async fn read_shard(&self, client_id: u16) -> OwnedRwLockReadGuard<Shard> {
+        loop {
+            let shards = self.shards();
+            let shard = shards[self.route(client_id, shards.len())].clone();
+            let shard_lock = measure(Site::ShardLock, shard.read_owned()).await;
+            // Resharded while waiting, the client may live elsewhere now
+            if !shard_lock.retired {
+                return shard_lock;
+            }
+        }
+    }
+    
+    /// Write lock on the shard owning the client in the current table
+    async fn write_shard(&self, client_id: u16) -> OwnedRwLockWriteGuard<Shard> {
+        loop {
+            let shards = self.shards();
+            let shard = shards[self.route(client_id, shards.len())].clone();
+            let shard_lock = measure(Site::ShardLock, shard.write_owned()).await;
+            if !shard_lock.retired {
+                return shard_lock;
+            }
         }
     }
     
     /// Get or create actor for a client
-    async fn get_or_create_actor(&self, client_id: u16) -> AccountHandle {
-        let shard_id = (client_id as usize) % self.num_shards;
-        let shard = &self.shards[shard_id];
-        
-        // Check if actor exists (read lock)
+    pub async fn get_or_create_actor(&self, client_id: u16) -> AccountHandle {
+        // Check if actor exists (read lock), a stopped one is replaced below
         {
-            let shard_lock = shard.read().await;
-            if let Some(handle) = shard_lock.actors.get(&client_id) {
-                return handle.clone();
+            let shard_lock = self.read_shard(client_id).await;
+            if let Some(handle) = shard_lock.actors.get(&client_id).filter(|h| !h.is_closed()) {
+                return self.used(handle.clone());
             }
         }
         
         // Create new actor (write lock)
-        let mut shard_lock = shard.write().await;
+        let mut shard_lock = self.write_shard(client_id).await;
         
         // Double-check (another task might have created it)
-        if let Some(handle) = shard_lock.actors.get(&client_id) {
-            return handle.clone();
+        if let Some(handle) = shard_lock.actors.get(&client_id).filter(|h| !h.is_closed()) {
+            return self.used(handle.clone());
+        }
+        
+        // A replacement actor resumes from the snapshot, already counted in the totals
+        if !shard_lock.actors.contains_key(&client_id) {
+            self.services.totals.open(client_id);
         }
         
         // Create new actor with cold storage
-        let (tx, rx) = mpsc::channel(1000);
+        let (tx, rx) = mpsc::channel(self.services.actor_config.mailbox_size);
         let handle = AccountHandle::new(tx);
         
-        let actor = AccountActor::new(client_id, rx, self.cold_storage.clone());
+        let actor = AccountActor::new(client_id, rx, self.services.clone());
+        let residency = self.residency.clone();
+        let live = residency.live.fetch_add(1, Ordering::Relaxed) + 1;
 
-        tokio::spawn(async move {
+        contention::spawn(async move {
             actor.run().await;
+            residency.live.fetch_sub(1, Ordering::Relaxed);
         });
         
-        shard_lock.actors.insert(client_id, handle.clone());
+        shard_lock.actors.insert(client_id, self.used(handle.clone()));
+        drop(shard_lock);
+        
+        // Stamped first, the new actor is the last candidate
+        if let Some(max_resident) = self.services.actor_config.max_resident {
+            if live > max_resident {
+                self.evict_least_recently_used(max_resident).await;
+            }
+        }
+        handle
+    }
+    
+    /// Stamp `handle` as the most recently used, when there is a resident cap to enforce
+    fn used(&self, handle: AccountHandle) -> AccountHandle {
+        if self.services.actor_config.max_resident.is_some() {
+            handle.touch(self.residency.uses.fetch_add(1, Ordering::Relaxed) + 1);
+        }
         handle
     }
     
+    /// Stop the least recently used actors until a tenth of `max_resident` is free again
+    ///
+    /// Each persists its state as on an idle timeout, the next message for its
+    /// client starts a new actor from it. One eviction runs at a time, those
+    /// asked meanwhile are left to the next actor created.
+    async fn evict_least_recently_used(&self, max_resident: usize) {
+        if self.residency.evicting.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        
+        let mut live = Vec::new();
+        for shard in self.shards().iter() {
+            let shard_lock = measure(Site::ShardLock, shard.read()).await;
+            live.extend(shard_lock.actors.values().filter(|h| !h.is_closed()).map(|h| (h.last_used(), h.clone())));
+        }
+        let excess = live.len().saturating_sub(max_resident - max_resident / 10);
+        if excess > 0 {
+            live.sort_unstable_by_key(|(last_used, _)| *last_used);
+            // Outside the shard locks, stopping actors must not hold up the others
+            for (_, handle) in live.into_iter().take(excess) {
+                let _ = handle.shutdown().await;
+            }
+            tracing::debug!(evicted = excess, max_resident, "Stopped least recently used actors");
+        }
+        
+        self.residency.evicting.store(false, Ordering::Release);
+    }
+    
+    /// Messages in flight to each shard's actors, indexed by shard
+    pub fn queue_depths(&self) -> Vec<usize> {
+        self.queued.snapshot()
+    }
+    
+    /// Wait until no shard has `max_depth` messages in flight, false if that takes longer than `within`
+    pub async fn wait_for_queue_depth(&self, max_depth: usize, within: Duration) -> bool {
+        self.queued.wait_below(max_depth, within).await
+    }
+    
+    /// Actors currently running, stopping ones included until their state is persisted
+    pub fn resident_actors(&self) -> usize {
+        self.residency.live.load(Ordering::Relaxed)
+    }
+    
     pub async fn process(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
-        let actor = self.get_or_create_actor(tx.client).await;
-        actor.process(tx).await
+        self.process_at(tx, SystemTime::now()).await
+    }
+    
+    /// Process an event that happened at `at`
+    pub async fn process_at(&self, tx: TransactionRow, at: SystemTime) -> Result<(), ProcessingError> {
+        self.process_undoable(tx, at).await.map(drop)
+    }
+    
+    /// Process an event, returning how to take it back should it not get logged, see `revert`
+    pub async fn process_undoable(&self, tx: TransactionRow, at: SystemTime) -> Result<Vec<Undo>, ProcessingError> {
+        if tx.tx_type == TransactionType::Transfer {
+            return self.transfer(tx, Some(at)).await;
+        }
+        
+        self.call_actor(tx.client, |actor| {
+            let tx = tx.clone();
+            async move { actor.process_undoable(tx, at).await }
+        })
+        .await
+        .map(|undo| vec![undo])
+    }
+    
+    /// Take back processed events, the last applied first
+    ///
+    /// Each undo goes to its client's actor, one restarted meanwhile included.
+    pub async fn revert(&self, undos: Vec<Undo>) -> Result<(), ProcessingError> {
+        for undo in undos.into_iter().rev() {
+            self.call_actor(undo.client(), |actor| {
+                let undo = undo.clone();
+                async move { actor.revert(undo).await }
+            })
+            .await?;
+        }
+        Ok(())
+    }
+    
+    /// Re-apply an event from the log, using handlers' replay hooks
+    pub async fn replay(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        if tx.tx_type == TransactionType::Transfer {
+            return self.transfer(tx, None).await.map(drop);
+        }
+        
+        self.call_actor(tx.client, |actor| {
+            let tx = tx.clone();
+            async move { actor.replay(tx).await }
+        })
+        .await
+    }
+    
+    /// Outcome `tx` would have now, nothing is applied and no actor starts for a new client
+    pub async fn evaluate(&self, tx: &TransactionRow) -> Result<(), ProcessingError> {
+        first_failure(self.checks(tx).await)
+    }
+    
+    /// Each account check `tx` would go through now, with its outcome
+    ///
+    /// A transfer gets the sender's checks, then the receiver's.
+    pub async fn checks(&self, tx: &TransactionRow) -> Vec<ValidationCheck> {
+        if tx.tx_type != TransactionType::Transfer {
+            return self.checks_on(tx.client, tx).await;
+        }
+        
+        let to = match tx.to {
+            Some(to) if to != tx.client => to,
+            _ => return vec![ValidationCheck { check: "recipient", outcome: Err(ProcessingError::InvalidTransfer) }],
+        };
+        let mut checks = self.checks_on(tx.client, tx).await;
+        checks.extend(self.checks_on(to, tx).await);
+        checks
+    }
+    
+    async fn checks_on(&self, client_id: u16, tx: &TransactionRow) -> Vec<ValidationCheck> {
+        if self.has_account(client_id).await {
+            let checks = self
+                .call_actor(client_id, |actor| {
+                    let tx = tx.clone();
+                    async move { actor.checks(tx).await }
+                })
+                .await;
+            return checks.unwrap_or_else(|e| vec![ValidationCheck { check: "actor", outcome: Err(e) }]);
+        }
+        
+        // A new client is judged by an empty actor that is never started
+        let (_, receiver) = mpsc::channel(1);
+        AccountActor::new(client_id, receiver, self.services.clone()).checks(tx).await
+    }
+    
+    /// Clear the lock of an existing account, returning how to take it back, see `revert`
+    pub async fn unlock(&self, client_id: u16) -> Result<Undo, ProcessingError> {
+        if !self.has_account(client_id).await {
+            return Err(ProcessingError::AccountNotFound);
+        }
+        
+        self.call_actor(client_id, |actor| async move { actor.unlock().await }).await
+    }
+    
+    /// A page of an existing account's transactions, hot and cold
+    ///
+    /// A stopped actor is started again, its hot transactions went to cold
+    /// storage but the page is read through the actor like any other.
+    pub async fn get_transactions(
+        &self,
+        client_id: u16,
+        page: Pagination,
+    ) -> Result<TransactionPage, ProcessingError> {
+        if !self.has_account(client_id).await {
+            return Err(ProcessingError::AccountNotFound);
+        }
+        
+        self.call_actor(client_id, |actor| async move { actor.get_transactions(page).await }).await
+    }
+    
+    /// Whether the client ever had an actor, running or stopped
+    async fn has_account(&self, client_id: u16) -> bool {
+        let shard_lock = self.read_shard(client_id).await;
+        shard_lock.actors.contains_key(&client_id)
+    }
+    
+    /// Run `op` on the client's actor, again on a fresh one if it stopped before answering
+    ///
+    /// A stopping actor drops queued messages without applying them, so the retry is safe.
+    async fn call_actor<T, F, Fut>(&self, client_id: u16, op: F) -> Result<T, ProcessingError>
+    where
+        F: Fn(AccountHandle) -> Fut,
+        Fut: Future<Output = Result<T, ProcessingError>>,
+    {
+        let _queued = self.queued.enter(self.shard_of(client_id));
+        let actor = self.get_or_create_actor(client_id).await;
+        match op(actor.clone()).await {
+            Err(ProcessingError::ActorCommunicationError) if actor.is_closed() => {
+                tracing::debug!(client_id, "Actor stopped under a message, retrying on a new one");
+                op(self.get_or_create_actor(client_id).await).await
+            }
+            result => result,
+        }
+    }
+    
+    /// Move funds between two actors: debit the sender, then credit the receiver
+    ///
+    /// If the credit fails the sender is refunded, so either both legs apply or
+    /// neither does. Other messages to the sender may interleave between legs.
+    ///
+    /// Only a transfer whose legs both applied is logged, and replay applies
+    /// both legs of a logged one unconditionally. A refund that can't be
+    /// delivered leaves the sender debited in memory only, and fails the
+    /// transfer with `TransferIncomplete` rather than the credit's error.
+    async fn transfer(&self, tx: TransactionRow, at: Option<SystemTime>) -> Result<Vec<Undo>, ProcessingError> {
+        let to = match tx.to {
+            Some(to) if to != tx.client => to,
+            _ => return Err(ProcessingError::InvalidTransfer),
+        };
+        
+        let leg = |leg| {
+            let tx = tx.clone();
+            move |actor: AccountHandle| {
+                let tx = tx.clone();
+                async move { actor.transfer_leg(tx, leg, at).await }
+            }
+        };
+        
+        let debit = self.call_actor(tx.client, leg(TransferLeg::Debit)).await?;
+        
+        let credit = match self.call_actor(to, leg(TransferLeg::Credit)).await {
+            Ok(credit) => credit,
+            Err(e) => {
+                if let Err(refund_error) = self.call_actor(tx.client, leg(TransferLeg::Refund)).await {
+                    tracing::error!(
+                        client_id = tx.client,
+                        tx_id = tx.tx,
+                        credit_error = ?e,
+                        error = ?refund_error,
+                        "Failed to refund transfer after credit failed"
+                    );
+                    return Err(ProcessingError::TransferIncomplete);
+                }
+                return Err(e);
+            }
+        };
+        
+        Ok(vec![debit, credit])
+    }
+    
+    /// Custom transaction handlers visible to every actor
+    pub fn handlers(&self) -> &Arc<HandlerRegistry> {
+        &self.services.handlers
+    }
+    
+    /// Monthly per-client counters maintained by the actors
+    pub fn counters(&self) -> &Arc<ReportingCounters> {
+        &self.services.counters
+    }
+    
+    /// Running totals of every shard combined, without contacting any actor
+    pub fn totals(&self) -> TreasuryReport {
+        self.services.totals.combined()
+    }
+    
+    /// Running totals of one shard, `None` past the last shard
+    pub fn shard_totals(&self, shard: usize) -> Option<TreasuryReport> {
+        self.services.totals.get(shard)
     }
     
     /// Get all account states parallelly
+    ///
+    /// One round trip per actor, reserved for full exports; use `totals` for aggregates.
     pub async fn get_all_accounts(&self) -> Vec<Account> {
         use futures::future::join_all;
         
-        let futures: Vec<_> = self
-            .shards
+        let shards = self.shards();
+        let futures: Vec<_> = shards
             .iter()
             .map(|shard| async move {
-                let shard_lock = shard.read().await;
+                let shard_lock = measure(Site::ShardLock, shard.read()).await;
                 let mut shard_accounts = Vec::new();
                 
-                for handle in shard_lock.actors.values() {
-                    if let Ok(account) = handle.get_state().await {
+                for (client_id, handle) in &shard_lock.actors {
+                    if let Some(account) = self.account_state(*client_id, handle).await {
                         shard_accounts.push(account);
                     }
                 }
@@ -99,15 +546,306 @@ impl ShardManager {
         results.into_iter().flatten().collect()
     }
     
+    /// Every account in client order, read from its actor as the stream is polled
+    ///
+    /// Only the client ids are collected up front, at most `ACCOUNT_STREAM_WINDOW`
+    /// states are in flight at a time.
+    pub fn stream_accounts(&self) -> BoxStream<'_, Account> {
+        stream::once(self.client_ids())
+            .flat_map(stream::iter)
+            .map(move |client_id| self.get_account(client_id))
+            .buffered(ACCOUNT_STREAM_WINDOW)
+            .filter_map(future::ready)
+            .boxed()
+    }
+    
+    async fn client_ids(&self) -> Vec<u16> {
+        let mut client_ids = Vec::new();
+        for shard in self.shards().iter() {
+            client_ids.extend(measure(Site::ShardLock, shard.read()).await.actors.keys());
+        }
+        client_ids.sort_unstable();
+        client_ids
+    }
+    
+    /// Accounts of the listed clients ordered by client id, unknown clients left out
+    ///
+    /// Clients are grouped by shard so each shard lock is taken once; shards
+    /// are asked concurrently, and up to `ACCOUNT_STREAM_WINDOW` of a shard's
+    /// actors at a time.
+    pub async fn get_accounts_of(&self, client_ids: &[u16]) -> Vec<Account> {
+        let table = self.shards();
+        let mut by_shard: Vec<Vec<u16>> = vec![Vec::new(); table.len()];
+        for &client_id in client_ids {
+            by_shard[self.route(client_id, table.len())].push(client_id);
+        }
+        
+        let shards = by_shard
+            .into_iter()
+            .zip(table.iter())
+            .filter(|(clients, _)| !clients.is_empty())
+            .map(|(clients, shard)| async move {
+                let shard_lock = measure(Site::ShardLock, shard.read()).await;
+                if shard_lock.retired {
+                    // Resharded meanwhile, each client is looked up on its own
+                    drop(shard_lock);
+                    return stream::iter(clients)
+                        .map(|client_id| self.get_account(client_id))
+                        .buffer_unordered(ACCOUNT_STREAM_WINDOW)
+                        .filter_map(future::ready)
+                        .collect::<Vec<_>>()
+                        .await;
+                }
+                let handles: Vec<(u16, AccountHandle)> = clients
+                    .into_iter()
+                    .filter_map(|client_id| Some((client_id, shard_lock.actors.get(&client_id)?.clone())))
+                    .collect();
+                drop(shard_lock);
+                stream::iter(handles)
+                    .map(|(client_id, handle)| async move { self.account_state(client_id, &handle).await })
+                    .buffer_unordered(ACCOUNT_STREAM_WINDOW)
+                    .filter_map(future::ready)
+                    .collect::<Vec<_>>()
+                    .await
+            });
+        
+        let mut accounts: Vec<Account> = future::join_all(shards).await.into_iter().flatten().collect();
+        accounts.sort_unstable_by_key(|account| account.client);
+        accounts
+    }
+    
     pub async fn get_account(&self, client_id: u16) -> Option<Account> {
-        let shard_id = (client_id as usize) % self.num_shards;
-        let shard = &self.shards[shard_id];
+        let shard_lock = self.read_shard(client_id).await;
+        let handle = shard_lock.actors.get(&client_id)?;
+        self.account_state(client_id, handle).await
+    }
+    
+    /// Live state, or the snapshot a stopped actor left behind
+    async fn account_state(&self, client_id: u16, handle: &AccountHandle) -> Option<Account> {
+        match handle.get_state().await {
+            Ok(account) => Some(account),
+            Err(_) => self.services.snapshots.load(client_id).await,
+        }
+    }
+    
+    /// Eventually consistent view of all accounts, no actor round trips
+    pub fn projection(&self) -> &AccountProjection {
+        &self.projection
+    }
+    
+    pub fn migration_metrics(&self) -> MigrationMetricsSnapshot {
+        self.migration_metrics.snapshot()
+    }
+    
+    /// Inspect a transaction held by a live actor, without spawning one
+    pub async fn inspect_transaction(
+        &self,
+        client_id: u16,
+        tx_id: u32,
+    ) -> Option<(StoredTransaction, StorageTier)> {
+        let handle = self.live_actor(client_id).await?;
+        handle.inspect_transaction(tx_id).await.ok().flatten()
+    }
+    
+    /// Handle of the client's running actor, never spawning one
+    async fn live_actor(&self, client_id: u16) -> Option<AccountHandle> {
+        let shard_lock = self.read_shard(client_id).await;
+        shard_lock.actors.get(&client_id).filter(|h| !h.is_closed()).cloned()
+    }
+    
+    /// Start moving all of a live actor's hot transactions to cold storage, false if none runs
+    pub async fn force_migrate_cold(&self, client_id: u16) -> bool {
+        match self.live_actor(client_id).await {
+            Some(handle) => handle.force_migrate_cold().await.is_ok(),
+            None => false,
+        }
+    }
+    
+    /// Tell a live actor to forget its hot transactions, false if none runs
+    pub async fn discard_hot(&self, client_id: u16) -> bool {
+        match self.live_actor(client_id).await {
+            Some(handle) => handle.discard_hot().await.is_ok(),
+            None => false,
+        }
+    }
+    
+    /// Send a message to every live actor, never spawning one; the number it reached
+    ///
+    /// Messages are built once per actor, those carrying a reply channel can't
+    /// be shared. Actors stopping meanwhile are skipped.
+    pub async fn broadcast(&self, message: impl Fn() -> AccountMessage) -> usize {
+        let mut handles = Vec::new();
+        for shard in self.shards().iter() {
+            let shard_lock = measure(Site::ShardLock, shard.read()).await;
+            handles.extend(shard_lock.actors.values().filter(|h| !h.is_closed()).cloned());
+        }
+        
+        let sent = future::join_all(handles.iter().map(|handle| handle.send(message()))).await;
+        sent.into_iter().filter(Result::is_ok).count()
+    }
+    
+    /// Hot transactions of a live actor, `None` if none runs
+    pub async fn hot_transactions(&self, client_id: u16) -> Option<Vec<HotTransaction>> {
+        self.live_actor(client_id).await?.hot_transactions().await.ok()
+    }
+    
+    /// Balances and hot transactions of every client, for an engine snapshot
+    ///
+    /// A stopped actor handed its hot transactions to cold storage, only its
+    /// balances are left to export.
+    pub async fn export_accounts(&self) -> Vec<(Account, Vec<(u32, StoredTransaction)>)> {
+        let mut exported = Vec::new();
+        for shard in self.shards().iter() {
+            let handles: Vec<(u16, AccountHandle)> = measure(Site::ShardLock, shard.read())
+                .await
+                .actors
+                .iter()
+                .map(|(client, handle)| (*client, handle.clone()))
+                .collect();
+            
+            for (client, handle) in handles {
+                match handle.export_state().await {
+                    Ok(state) => exported.push(state),
+                    Err(_) => {
+                        if let Some(account) = self.services.snapshots.load(client).await {
+                            exported.push((account, Vec::new()));
+                        }
+                    }
+                }
+            }
+        }
+        exported
+    }
+    
+    /// Stop every live actor, waiting until each has persisted its state
+    ///
+    /// Hot transactions go to cold storage and balances to the snapshot store,
+    /// as when an actor stops idle. A later message starts a fresh actor.
+    pub async fn shutdown(&self) {
+        let _resharding = self.resharding.lock().await;
+        let mut handles = Vec::new();
+        for shard in self.shards().iter() {
+            let mut shard_lock = measure(Site::ShardLock, shard.write()).await;
+            handles.extend(shard_lock.actors.drain().map(|(_, handle)| handle));
+        }
         
-        let shard_lock = shard.read().await;
-        if let Some(handle) = shard_lock.actors.get(&client_id) {
-            handle.get_state().await.ok()
-        } else {
-            None
+        future::join_all(handles.iter().map(|handle| async move {
+            // Already stopped, nothing left to persist
+            if handle.shutdown().await.is_ok() {
+                handle.stopped().await;
+            }
+        }))
+        .await;
+    }
+    
+    /// Spread the clients over `num_shards` shards while running, the number of actors moved
+    ///
+    /// Only handles change shard: actors keep running with their mailboxes, so
+    /// messages queued or in flight to them are applied as usual. Lookups wait
+    /// for the move, then route under the new count. Consistent hashing moves
+    /// only the clients the added shards take or the removed ones gave up,
+    /// modulo routing nearly all of them.
+    pub async fn reshard(&self, num_shards: usize) -> anyhow::Result<usize> {
+        anyhow::ensure!(num_shards > 0, "at least one shard is needed");
+        let _resharding = self.resharding.lock().await;
+        
+        // Every old shard locked, so no actor is created or looked up during the move
+        let old = self.shards();
+        let mut old_locks = Vec::with_capacity(old.len());
+        for shard in old.iter() {
+            old_locks.push(measure(Site::ShardLock, shard.clone().write_owned()).await);
+        }
+        
+        let mut shards: Vec<Shard> = (0..num_shards).map(|_| Shard::default()).collect();
+        let mut moved = 0;
+        for (shard_id, shard_lock) in old_locks.iter().enumerate() {
+            for (&client_id, handle) in &shard_lock.actors {
+                let target = self.route(client_id, num_shards);
+                if target != shard_id && !handle.is_closed() {
+                    moved += 1;
+                }
+                // Stopped ones too, a replacement must know the client is already counted
+                shards[target].actors.insert(client_id, handle.clone());
+            }
+        }
+        self.services.totals.reshard(num_shards);
+        self.queued.reshard(num_shards);
+        *self.shards.write().unwrap() = Arc::new(shards.into_iter().map(|shard| Arc::new(RwLock::new(shard))).collect());
+        
+        // Lookups waiting on the old locks retry on the new table
+        for shard_lock in &mut old_locks {
+            shard_lock.retired = true;
+        }
+        drop(old_locks);
+        
+        tracing::info!(from = old.len(), to = num_shards, moved, "Resharded");
+        Ok(moved)
+    }
+    
+    /// Start a client's actor from balances in an engine snapshot
+    ///
+    /// Only valid before anything else has reached the client's actor.
+    pub async fn restore_account(&self, account: Account) -> anyhow::Result<()> {
+        let client = account.client;
+        self.services.snapshots.save(&account).await?;
+        self.get_or_create_actor(client).await;
+        
+        self.services.totals.apply(&Account::new(client), &account);
+        let _ = self.services.projection.send(account);
+        Ok(())
+    }
+    
+    /// Actors, hot transactions and queued messages of every shard, in shard order
+    pub async fn stats(&self) -> Vec<ShardStats> {
+        let shards = self.shards();
+        let in_flight = self.queued.snapshot();
+        let mut stats = Vec::with_capacity(shards.len());
+        for (shard, lock) in shards.iter().enumerate() {
+            let handles: Vec<AccountHandle> = measure(Site::ShardLock, lock.read())
+                .await
+                .actors
+                .values()
+                .filter(|h| !h.is_closed())
+                .cloned()
+                .collect();
+            
+            // Read before asking for hot storage, the question queues a message of its own
+            let mailbox_depth = handles.iter().map(AccountHandle::mailbox_len).sum();
+            let mut hot_transactions = 0;
+            for handle in &handles {
+                hot_transactions += handle.hot_storage_len().await.unwrap_or(0);
+            }
+            stats.push(ShardStats {
+                shard,
+                actors: handles.len(),
+                hot_transactions,
+                mailbox_depth,
+                in_flight: in_flight.get(shard).copied().unwrap_or(0),
+            });
         }
+        stats
+    }
+    
+    /// Hot-storage size of every live actor, largest first
+    pub async fn hot_storage_sizes(&self) -> Vec<HotStorageSize> {
+        let mut sizes = Vec::new();
+        for shard in self.shards().iter() {
+            let handles: Vec<(u16, AccountHandle)> = measure(Site::ShardLock, shard.read())
+                .await
+                .actors
+                .iter()
+                .map(|(client, handle)| (*client, handle.clone()))
+                .collect();
+            
+            // Asked outside the shard lock, a busy actor must not hold up actor creation
+            for (client, handle) in handles {
+                if let Ok(transactions) = handle.hot_storage_len().await {
+                    sizes.push(HotStorageSize { client, transactions });
+                }
+            }
+        }
+        
+        sizes.sort_by(|a, b| b.transactions.cmp(&a.transactions).then(a.client.cmp(&b.client)));
+        sizes
     }
 }
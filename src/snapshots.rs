@@ -0,0 +1,39 @@
+use crate::models::Account;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// Last known balances of accounts whose actor stopped
+///
+/// Written when an actor shuts down or finishes a migration pass, read when
+/// the actor for that client is created again.
+#[async_trait]
+pub trait AccountSnapshotStore: Send + Sync {
+    async fn load(&self, client: u16) -> Option<Account>;
+    async fn save(&self, account: &Account) -> Result<()>;
+}
+
+/// Snapshots kept for the lifetime of the process
+#[derive(Default)]
+pub struct InMemorySnapshotStore {
+    accounts: RwLock<HashMap<u16, Account>>,
+}
+
+impl InMemorySnapshotStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AccountSnapshotStore for InMemorySnapshotStore {
+    async fn load(&self, client: u16) -> Option<Account> {
+        self.accounts.read().await.get(&client).cloned()
+    }
+
+    async fn save(&self, account: &Account) -> Result<()> {
+        self.accounts.write().await.insert(account.client, account.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use crate::scalable_engine::ScalableEngine;
+use crate::server::handle_connection;
+use crate::storage::{InMemoryStore, TransactionStore};
+use crate::topology::auto_shards;
+use anyhow::{Context, Result};
+use rust_decimal::Decimal;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+/// Settings of a soak run
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    /// Concurrent connections, each streaming rows for its own client
+    pub connections: usize,
+    pub duration: Duration,
+    /// Time between checks of the global invariants and memory
+    pub check_interval: Duration,
+    /// Rows sent before waiting for their acks
+    pub batch_size: usize,
+    /// Fail once resident memory exceeds this many bytes
+    pub max_rss_bytes: Option<u64>,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            connections: 200,
+            duration: Duration::from_secs(3600),
+            check_interval: Duration::from_secs(30),
+            batch_size: 64,
+            max_rss_bytes: None,
+        }
+    }
+}
+
+/// What a soak run that held every invariant got through
+#[derive(Debug, Clone, Default)]
+pub struct SoakReport {
+    pub rows: u64,
+    pub checks: u64,
+    /// `None` where resident memory can't be read
+    pub peak_rss_bytes: Option<u64>,
+}
+
+// Tells apart the logs of soak runs sharing a process
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Serve an in-process engine over TCP and stream generated traffic at it
+///
+/// Every connection drives one client and predicts each ack from its own
+/// model of the balance, so any divergence fails the run. The event log is
+/// a temporary file removed afterwards.
+pub async fn run(config: SoakConfig) -> Result<SoakReport> {
+    if config.connections == 0 || config.connections >= u16::MAX as usize {
+        anyhow::bail!("Connections must be between 1 and {}", u16::MAX - 1);
+    }
+    if config.batch_size == 0 {
+        anyhow::bail!("Batch size must be at least 1");
+    }
+
+    let log = std::env::temp_dir().join(format!(
+        "payments-engine-soak-{}-{}.log",
+        std::process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = drive(&config, log.clone()).await;
+    let _ = tokio::fs::remove_file(&log).await;
+    result
+}
+
+async fn drive(config: &SoakConfig, log: PathBuf) -> Result<SoakReport> {
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::new(log, auto_shards(), cold_storage).await?);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let engine = engine.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(socket, engine).await {
+                        tracing::error!("Soak connection error: {}", e);
+                    }
+                });
+            }
+        })
+    };
+
+    let deadline = Instant::now() + config.duration;
+    let rows = Arc::new(AtomicU64::new(0));
+    let next_tx = Arc::new(AtomicU64::new(1));
+    let mut workers = JoinSet::new();
+    for i in 0..config.connections {
+        workers.spawn(stream_client(
+            addr,
+            (i + 1) as u16,
+            engine.clone(),
+            config.batch_size,
+            deadline,
+            rows.clone(),
+            next_tx.clone(),
+        ));
+    }
+
+    let mut report = SoakReport::default();
+    let mut expected_total = Decimal::ZERO;
+    let mut ticker = tokio::time::interval(config.check_interval);
+    ticker.tick().await; // Skip first immediate tick
+
+    let outcome: Result<()> = loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if let Err(e) = check_invariants(&engine, config, &rows, &mut report) {
+                    break Err(e);
+                }
+            }
+            joined = workers.join_next() => match joined {
+                None => break Ok(()),
+                Some(Ok(Ok(balance))) => expected_total += balance,
+                Some(Ok(Err(e))) => break Err(e),
+                Some(Err(e)) => break Err(e.into()),
+            }
+        }
+    };
+    workers.abort_all();
+    server.abort();
+    outcome?;
+
+    check_invariants(&engine, config, &rows, &mut report)?;
+    let totals = engine.account_totals();
+    if totals.total != expected_total {
+        anyhow::bail!("Engine holds {} in total, the clients expect {}", totals.total, expected_total);
+    }
+
+    report.rows = rows.load(Ordering::Relaxed);
+    Ok(report)
+}
+
+/// Stream deposits and withdrawals for `client` until the deadline, returning its final balance
+async fn stream_client(
+    addr: SocketAddr,
+    client: u16,
+    engine: Arc<ScalableEngine>,
+    batch_size: usize,
+    deadline: Instant,
+    rows: Arc<AtomicU64>,
+    next_tx: Arc<AtomicU64>,
+) -> Result<Decimal> {
+    let socket = TcpStream::connect(addr).await?;
+    let (reader, mut writer) = socket.into_split();
+    let mut acks = BufReader::new(reader).lines();
+    writer.write_all(b"#protocol ack\ntype,client,tx,amount\n").await?;
+
+    let mut balance = Decimal::ZERO;
+    let mut step = 0u64;
+    let mut exhausted = false;
+    while !exhausted && Instant::now() < deadline {
+        let mut batch = String::new();
+        let mut expected = Vec::with_capacity(batch_size);
+        for _ in 0..batch_size {
+            let tx = next_tx.fetch_add(1, Ordering::Relaxed);
+            let Ok(tx) = u32::try_from(tx) else {
+                // Tx id space used up, the run ends as if the deadline had passed
+                exhausted = true;
+                break;
+            };
+            step += 1;
+
+            // Every third row withdraws more than a deposit brings in, so some are refused
+            let code = if step.is_multiple_of(3) {
+                let amount = Decimal::from(step % 11 + 1);
+                writeln!(batch, "withdrawal,{},{},{}", client, tx, amount)?;
+                if balance >= amount {
+                    balance -= amount;
+                    "ok"
+                } else {
+                    "insufficient_funds"
+                }
+            } else {
+                let amount = Decimal::new((step % 5 + 1) as i64 * 125, 2);
+                writeln!(batch, "deposit,{},{},{}", client, tx, amount)?;
+                balance += amount;
+                "ok"
+            };
+            expected.push(format!("{},{}", tx, code));
+        }
+
+        if expected.is_empty() {
+            break;
+        }
+
+        writer.write_all(batch.as_bytes()).await?;
+        for expected in &expected {
+            let ack = acks
+                .next_line()
+                .await?
+                .with_context(|| format!("Client {}: connection closed before every ack arrived", client))?;
+            if &ack != expected {
+                anyhow::bail!("Client {}: expected ack {}, got {}", client, expected, ack);
+            }
+        }
+        rows.fetch_add(expected.len() as u64, Ordering::Relaxed);
+
+        // Exact at a batch boundary, no other connection writes to this client
+        let account = engine
+            .get_account(client)
+            .await
+            .with_context(|| format!("Client {} has no account", client))?;
+        if account.available != balance || account.held != Decimal::ZERO {
+            anyhow::bail!(
+                "Client {}: expected {} available, engine has {} available and {} held",
+                client,
+                balance,
+                account.available,
+                account.held
+            );
+        }
+    }
+
+    writer.shutdown().await?;
+    Ok(balance)
+}
+
+fn check_invariants(
+    engine: &ScalableEngine,
+    config: &SoakConfig,
+    rows: &AtomicU64,
+    report: &mut SoakReport,
+) -> Result<()> {
+    // The generated traffic has no disputes: nothing may be held, locked or overdrawn
+    let totals = engine.account_totals();
+    if totals.held != Decimal::ZERO || totals.locked != 0 || totals.negative_exposure != Decimal::ZERO {
+        anyhow::bail!("Invariant violated: {:?}", totals);
+    }
+
+    let rss = resident_memory_bytes();
+    if let Some(rss) = rss {
+        report.peak_rss_bytes = Some(report.peak_rss_bytes.unwrap_or(0).max(rss));
+        if let Some(max) = config.max_rss_bytes.filter(|&max| rss > max) {
+            anyhow::bail!("Resident memory {} bytes exceeds the {} byte bound", rss, max);
+        }
+    }
+
+    report.checks += 1;
+    tracing::info!(
+        rows = rows.load(Ordering::Relaxed),
+        accounts = totals.accounts,
+        rss_bytes = rss,
+        "Soak check passed"
+    );
+    Ok(())
+}
+
+/// Resident set size of this process, Linux only
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
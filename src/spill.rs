@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Rows a connection holds in memory before spilling, by default
+pub const DEFAULT_SPILL_AFTER: usize = 4096;
+
+/// Where and when connections spill rows they can't apply yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillConfig {
+    /// Directory spill files are created in, one per connection while it spills
+    pub dir: PathBuf,
+    /// Rows waiting in memory before further ones go to the spill file
+    pub memory_rows: usize,
+}
+
+/// Names spill files apart within a process
+static NEXT_SPILL_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// FIFO of rows read ahead of processing, overflowing from memory into a local file
+///
+/// Once a row is spilled every later one is too until the file is drained,
+/// so rows come out in the order they went in. The file is written and read
+/// with blocking IO under the lock, only while a burst is being absorbed;
+/// it is truncated each time it drains and deleted with the buffer.
+pub struct SpillBuffer<T> {
+    memory_rows: usize,
+    path: PathBuf,
+    state: Mutex<State<T>>,
+    ready: Notify,
+}
+
+struct State<T> {
+    memory: VecDeque<T>,
+    file: Option<SpillFile>,
+    /// Rows in the file not read back yet
+    spilled: usize,
+    /// Rows spilled over the buffer's life
+    spilled_total: u64,
+    closed: bool,
+}
+
+struct SpillFile {
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+}
+
+impl<T: Serialize + DeserializeOwned> SpillBuffer<T> {
+    /// Buffer spilling into a new file under `config.dir`, created only once needed
+    pub fn new(config: &SpillConfig) -> Self {
+        let name = format!("spill-{}-{}.jsonl", std::process::id(), NEXT_SPILL_FILE.fetch_add(1, Ordering::Relaxed));
+        Self {
+            memory_rows: config.memory_rows,
+            path: config.dir.join(name),
+            state: Mutex::new(State {
+                memory: VecDeque::new(),
+                file: None,
+                spilled: 0,
+                spilled_total: 0,
+                closed: false,
+            }),
+            ready: Notify::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rows spilled to disk so far
+    pub fn spilled_total(&self) -> u64 {
+        self.state.lock().unwrap().spilled_total
+    }
+
+    /// Queue a row, never waiting for the consumer
+    pub fn push(&self, row: T) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.spilled == 0 && state.memory.len() < self.memory_rows {
+            state.memory.push_back(row);
+        } else {
+            if state.file.is_none() {
+                state.file = Some(SpillFile::create(&self.path)?);
+                tracing::info!(path = %self.path.display(), "Rows arrive faster than they are applied, spilling to disk");
+            }
+            let file = state.file.as_mut().expect("spill file just created");
+            serde_json::to_writer(&mut file.writer, &row)?;
+            file.writer.write_all(b"\n")?;
+            file.writer.flush()?;
+            state.spilled += 1;
+            state.spilled_total += 1;
+        }
+        drop(state);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// No more rows will be pushed, `pop` returns `None` once the rest are taken
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+
+    /// Oldest row, waiting for one if none is queued; `None` once closed and empty
+    ///
+    /// For a single consumer. Cancelling the wait loses nothing.
+    pub async fn pop(&self) -> Result<Option<T>> {
+        loop {
+            match self.next()? {
+                Next::Row(row) => return Ok(Some(row)),
+                Next::Done => return Ok(None),
+                Next::Wait => self.ready.notified().await,
+            }
+        }
+    }
+
+    fn next(&self) -> Result<Next<T>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(row) = state.memory.pop_front() {
+            return Ok(Next::Row(row));
+        }
+        if state.spilled == 0 {
+            return Ok(if state.closed { Next::Done } else { Next::Wait });
+        }
+
+        state.spilled -= 1;
+        let drained = state.spilled == 0;
+        let file = state.file.as_mut().context("spilled rows without a spill file")?;
+        let mut line = String::new();
+        file.reader.read_line(&mut line)?;
+        let row = serde_json::from_str(&line).context("corrupt spill file")?;
+        // Drained, start the file over instead of letting it grow for the whole connection
+        if drained {
+            file.truncate()?;
+        }
+        Ok(Next::Row(row))
+    }
+}
+
+enum Next<T> {
+    Row(T),
+    Wait,
+    Done,
+}
+
+/// `rows` as they come out of a spill buffer fed from them as fast as they arrive
+///
+/// The feeding runs as part of polling the returned stream, which yields
+/// the rows, errors in place, in their original order.
+pub fn absorb<'a, T>(rows: impl Stream<Item = Result<T>> + Send + 'a, config: &SpillConfig) -> BoxStream<'a, Result<T>>
+where
+    T: Serialize + DeserializeOwned + Send + 'a,
+{
+    let buffer = Arc::new(SpillBuffer::<Result<T, String>>::new(config));
+    let feeder = buffer.clone();
+    let fill: BoxFuture<'a, Result<()>> = Box::pin(async move {
+        let mut rows = std::pin::pin!(rows);
+        let mut result = Ok(());
+        while let Some(row) = rows.next().await {
+            // Errors travel as their message, a spilled one has to be written out
+            if let Err(e) = feeder.push(row.map_err(|e| format!("{:#}", e))) {
+                result = Err(e);
+                break;
+            }
+        }
+        feeder.close();
+        result
+    });
+
+    stream::unfold((buffer, Some(fill)), |(buffer, mut fill)| async move {
+        loop {
+            let filling = fill.is_some();
+            tokio::select! {
+                biased;
+                row = buffer.pop() => {
+                    let item = match row {
+                        Ok(Some(Ok(row))) => Ok(row),
+                        Ok(Some(Err(message))) => Err(anyhow::anyhow!(message)),
+                        Ok(None) => return None,
+                        Err(e) => Err(e),
+                    };
+                    return Some((item, (buffer, fill)));
+                }
+                filled = async { fill.as_mut().expect("polled only while filling").await }, if filling => {
+                    fill = None;
+                    if let Err(e) = filled {
+                        return Some((Err(e.context("spilling rows")), (buffer, fill)));
+                    }
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+impl<T> Drop for SpillBuffer<T> {
+    fn drop(&mut self) {
+        if self.state.get_mut().map(|state| state.file.is_some()).unwrap_or(false) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl SpillFile {
+    fn create(path: &Path) -> Result<Self> {
+        let writer = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("creating spill file {}", path.display()))?;
+        let reader = File::open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(writer),
+            reader: BufReader::new(reader),
+        })
+    }
+
+    fn truncate(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
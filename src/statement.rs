@@ -0,0 +1,239 @@
+use crate::cli::OutputFormat;
+use crate::compat::CompatConfig;
+use crate::csv_io::render_table;
+use crate::event_store::read_events;
+use crate::handlers::HandlerRegistry;
+use crate::models::{Account, TransactionRow, TransactionType};
+use crate::prober::is_probe;
+use crate::shard_manager::ShardManager;
+use crate::storage::TransactionStore;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+
+const CSV_HEADER: &str = "seq,type,tx,amount,counterparty,outcome,available,held,total,locked,recorded_at";
+
+/// Balances of the account at one point of the statement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct StatementBalance {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl From<&Account> for StatementBalance {
+    fn from(account: &Account) -> Self {
+        Self {
+            available: account.available,
+            held: account.held,
+            total: account.total(),
+            locked: account.locked,
+        }
+    }
+}
+
+/// One event of the client's, with the balances it left behind
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StatementLine {
+    /// 1-based position of the event in the log
+    pub seq: usize,
+    pub tx_type: TransactionType,
+    pub tx: u32,
+    pub amount: Option<Decimal>,
+    /// Other side of a transfer
+    pub counterparty: Option<u16>,
+    /// "applied", or the reason the replay rejected the event
+    pub outcome: String,
+    pub balance: StatementBalance,
+    /// Unix seconds, known for transactions found in cold storage
+    pub recorded_at: Option<u64>,
+}
+
+/// Chronological statement of one client's account over a range of the event log
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AccountStatement {
+    pub client: u16,
+    /// Event log the statement was read from
+    #[schema(value_type = String)]
+    pub source: PathBuf,
+    /// First and last log position covered, inclusive
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+    /// Balances before the first line
+    pub opening: StatementBalance,
+    /// Balances after the last line
+    pub closing: StatementBalance,
+    pub lines: Vec<StatementLine>,
+}
+
+impl AccountStatement {
+    /// Lines as CSV, balances with four places like the accounts output
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("{}\n", CSV_HEADER);
+        for line in &self.lines {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{},{:.4},{:.4},{:.4},{},{}",
+                line.seq,
+                line.tx_type.as_str(),
+                line.tx,
+                line.amount.map(|amount| amount.to_string()).unwrap_or_default(),
+                line.counterparty.map(|client| client.to_string()).unwrap_or_default(),
+                // Rejection reasons are plain words, but keep the columns intact regardless
+                line.outcome.replace(',', ";"),
+                line.balance.available,
+                line.balance.held,
+                line.balance.total,
+                line.balance.locked,
+                line.recorded_at.map(|at| at.to_string()).unwrap_or_default(),
+            );
+        }
+        out
+    }
+}
+
+/// Statement of `client` from replaying `events` in full into `scratch`, keeping lines within `from..=to`
+///
+/// Every event is replayed, not just the client's: a transfer's outcome depends
+/// on the other side's balance too. `scratch` is an empty manager applying
+/// events as the engine that wrote the log did, see `ShardManager::scratch`.
+/// A line the replay rejects is kept with its reason; `recorded_at` comes from
+/// `cold_storage` where it holds the transaction. Latency probes are replayed
+/// but never listed.
+pub async fn build_statement(
+    source: &Path,
+    events: Vec<TransactionRow>,
+    client: u16,
+    from: Option<usize>,
+    to: Option<usize>,
+    cold_storage: &dyn TransactionStore,
+    scratch: ShardManager,
+) -> AccountStatement {
+    let mut opening = None;
+    let mut lines = Vec::new();
+    for (idx, event) in events.into_iter().enumerate() {
+        let seq = idx + 1;
+        if to.is_some_and(|to| seq > to) {
+            break;
+        }
+
+        let involved = event.client == client || event.to == Some(client);
+        let before_range = from.is_some_and(|from| seq < from);
+        // Latency probes are the engine's own traffic, not the account holder's
+        if !involved || before_range || is_probe(&event) {
+            let _ = scratch.replay(event).await;
+            continue;
+        }
+
+        if opening.is_none() {
+            opening = Some(account_or_default(&scratch, client).await);
+        }
+        let result = scratch.replay(event.clone()).await;
+        let after = account_or_default(&scratch, client).await;
+
+        let counterparty = match event.to {
+            Some(to) if event.client == client => Some(to),
+            Some(_) => Some(event.client),
+            None => None,
+        };
+        lines.push(StatementLine {
+            seq,
+            tx_type: event.tx_type,
+            tx: event.tx,
+            amount: event.amount,
+            counterparty,
+            outcome: match result {
+                Ok(()) => "applied".to_string(),
+                Err(e) => e.to_string(),
+            },
+            balance: StatementBalance::from(&after),
+            recorded_at: None,
+        });
+    }
+
+    let closing = account_or_default(&scratch, client).await;
+    let opening = opening.unwrap_or_else(|| closing.clone());
+    annotate_recorded_at(&mut lines, client, cold_storage).await;
+
+    AccountStatement {
+        client,
+        source: source.to_path_buf(),
+        from,
+        to,
+        opening: StatementBalance::from(&opening),
+        closing: StatementBalance::from(&closing),
+        lines,
+    }
+}
+
+/// Fill in when each line's transaction was recorded, from the client's records in cold storage
+async fn annotate_recorded_at(lines: &mut [StatementLine], client: u16, cold_storage: &dyn TransactionStore) {
+    let tx_ids: Vec<u32> = lines.iter().map(|line| line.tx).collect();
+    let recorded: HashMap<u32, u64> = cold_storage
+        .get_many(&tx_ids)
+        .await
+        .into_iter()
+        .filter(|(_, stored)| stored.client == client)
+        .filter_map(|(tx, stored)| {
+            let at = stored.created_at.duration_since(UNIX_EPOCH).ok()?;
+            Some((tx, at.as_secs()))
+        })
+        .collect();
+
+    // Only the event that introduced the transaction was recorded at that time
+    for line in lines.iter_mut().filter(|line| line.tx_type.creates_tx()) {
+        line.recorded_at = recorded.get(&line.tx).copied();
+    }
+}
+
+async fn account_or_default(manager: &ShardManager, client: u16) -> Account {
+    manager
+        .get_account(client)
+        .await
+        .unwrap_or_else(|| Account::new(client))
+}
+
+/// `statement` subcommand: print one client's statement in `format`
+///
+/// `compat` must be the one the log was written under, as for replay.
+pub async fn run(
+    client: u16,
+    log_path: PathBuf,
+    from: Option<usize>,
+    to: Option<usize>,
+    format: OutputFormat,
+    cold_storage: Arc<dyn TransactionStore>,
+    compat: CompatConfig,
+) -> Result<()> {
+    if !log_path.exists() {
+        anyhow::bail!("event log not found: {}", log_path.display());
+    }
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            anyhow::bail!("--from {} is after --to {}", from, to);
+        }
+    }
+
+    let events = read_events(&log_path).await?;
+    let scratch = ShardManager::scratch(compat, Arc::new(HandlerRegistry::new()));
+    let statement = build_statement(&log_path, events, client, from, to, cold_storage.as_ref(), scratch).await;
+
+    let out = match format {
+        OutputFormat::Csv => statement.to_csv(),
+        OutputFormat::Json => format!("{}\n", serde_json::to_string(&statement)?),
+        OutputFormat::Table => render_table(&statement.to_csv()),
+    };
+    let mut stdout = tokio::io::stdout();
+    stdout.write_all(out.as_bytes()).await?;
+    stdout.flush().await?;
+
+    Ok(())
+}
@@ -4,12 +4,15 @@ use crate::models::TransactionType;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
+use utoipa::ToSchema;
 
 /// Stored transaction with timestamp for hot/cold tiering
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct StoredTransaction {
     pub client: u16,
     pub tx_type: TransactionType,
@@ -17,10 +20,34 @@ pub struct StoredTransaction {
     pub disputed: bool,
     #[serde(default)]
     pub held_amount: Option<Decimal>,
+    /// Unix seconds
     #[serde(with = "systemtime_serde")]
+    #[schema(value_type = u64)]
     pub created_at: SystemTime,
 }
 
+/// Storage tier currently holding a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageTier {
+    Hot,
+    Cold,
+}
+
+/// Decides which records a compaction keeps
+pub type Retain = Arc<dyn Fn(&StoredTransaction) -> bool + Send + Sync>;
+
+/// Outcome of one cold storage compaction pass
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CompactionReport {
+    /// Records looked at
+    pub scanned: u64,
+    /// Records dropped
+    pub removed: u64,
+    /// Size of the dropped records in the backend's encoding, keys included
+    pub bytes_reclaimed: u64,
+}
+
 mod systemtime_serde {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -49,6 +76,85 @@ pub trait TransactionStore: Send + Sync {
     async fn get(&self, tx_id: u32) -> Option<StoredTransaction>;
     async fn put(&self, tx_id: u32, tx: StoredTransaction) -> Result<()>;
     async fn remove(&self, tx_id: u32) -> Result<()>;
+    
+    /// One client's transactions with tx ids above `after`, ascending, at most `limit` of them
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> Result<Vec<(u32, StoredTransaction)>>;
+    
+    /// Fetch several transactions in one round trip, missing ids are omitted
+    async fn get_many(&self, tx_ids: &[u32]) -> Vec<(u32, StoredTransaction)> {
+        let mut found = Vec::with_capacity(tx_ids.len());
+        for &tx_id in tx_ids {
+            if let Some(tx) = self.get(tx_id).await {
+                found.push((tx_id, tx));
+            }
+        }
+        found
+    }
+    
+    /// Contents an engine snapshot must carry, `None` for stores that survive restarts themselves
+    async fn snapshot_entries(&self) -> Option<Vec<(u32, StoredTransaction)>> {
+        None
+    }
+    
+    /// Drop every record `retain` rejects, reclaiming the space it took
+    ///
+    /// Runs alongside reads and writes; records `retain` rejects must not be
+    /// written meanwhile.
+    async fn compact(&self, retain: Retain) -> Result<CompactionReport>;
+}
+
+/// Bytes of a record in the MessagePack encoding the persistent backends store
+fn encoded_len(tx: &StoredTransaction) -> u64 {
+    rmp_serde::to_vec_named(tx).map(|bytes| bytes.len() as u64).unwrap_or(0)
+}
+
+/// Retries of cold-storage writes made while a transaction is being applied
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Tries in total, the first included
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Run `op` until it succeeds or the attempts run out, returning the last error
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= self.attempts => return Err(e),
+                Err(e) => {
+                    tracing::warn!(attempt, error = ?e, "Cold storage write failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+            }
+        }
+    }
 }
 
 /// In-memory storage (simple, fast, no persistence needed for cold tier in CLI mode)
@@ -64,6 +170,12 @@ impl InMemoryStore {
     }
 }
 
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl TransactionStore for InMemoryStore {
     async fn get(&self, tx_id: u32) -> Option<StoredTransaction> {
@@ -82,4 +194,671 @@ impl TransactionStore for InMemoryStore {
         cache.remove(&tx_id);
         Ok(())
     }
+    
+    /// Scans every entry, the map isn't ordered by client
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> Result<Vec<(u32, StoredTransaction)>> {
+        let cache = self.cache.read().await;
+        let mut listed: Vec<(u32, StoredTransaction)> = cache
+            .iter()
+            .filter(|(tx_id, tx)| tx.client == client && after.is_none_or(|after| **tx_id > after))
+            .map(|(tx_id, tx)| (*tx_id, tx.clone()))
+            .collect();
+        listed.sort_unstable_by_key(|(tx_id, _)| *tx_id);
+        listed.truncate(limit);
+        Ok(listed)
+    }
+    
+    async fn get_many(&self, tx_ids: &[u32]) -> Vec<(u32, StoredTransaction)> {
+        let cache = self.cache.read().await;
+        tx_ids
+            .iter()
+            .filter_map(|tx_id| cache.get(tx_id).map(|tx| (*tx_id, tx.clone())))
+            .collect()
+    }
+    
+    async fn snapshot_entries(&self) -> Option<Vec<(u32, StoredTransaction)>> {
+        let cache = self.cache.read().await;
+        Some(cache.iter().map(|(tx_id, tx)| (*tx_id, tx.clone())).collect())
+    }
+    
+    /// Reclaimed bytes are what the records would take encoded, keys included
+    async fn compact(&self, retain: Retain) -> Result<CompactionReport> {
+        let mut cache = self.cache.write().await;
+        let mut report = CompactionReport {
+            scanned: cache.len() as u64,
+            ..Default::default()
+        };
+        cache.retain(|_, tx| {
+            let kept = retain(tx);
+            if !kept {
+                report.removed += 1;
+                report.bytes_reclaimed += 4 + encoded_len(tx);
+            }
+            kept
+        });
+        Ok(report)
+    }
+}
+
+/// Default number of prefetched transactions kept ahead of the actors
+pub const DEFAULT_PREFETCH_CAPACITY: usize = 10_000;
+
+/// Read-ahead cache in front of a cold store, warmed for upcoming dispute lookups
+///
+/// The cache lock is never held across a backend call. Every write bumps the
+/// cache generation before and after reaching the inner store, and a warm
+/// whose fetch overlapped a bump drops what it read rather than cache a
+/// record that may already be stale.
+pub struct PrefetchingStore {
+    inner: Arc<dyn TransactionStore>,
+    capacity: usize,
+    cache: Mutex<PrefetchCache>,
+}
+
+#[derive(Default)]
+struct PrefetchCache {
+    entries: HashMap<u32, StoredTransaction>,
+    generation: u64,
+}
+
+impl PrefetchCache {
+    fn invalidate(&mut self, tx_id: u32) {
+        self.generation += 1;
+        self.entries.remove(&tx_id);
+    }
+}
+
+impl PrefetchingStore {
+    pub fn new(inner: Arc<dyn TransactionStore>, capacity: usize) -> Self {
+        Self {
+            inner,
+            capacity,
+            cache: Mutex::new(PrefetchCache::default()),
+        }
+    }
+    
+    fn cache(&self) -> MutexGuard<'_, PrefetchCache> {
+        self.cache.lock().unwrap()
+    }
+    
+    /// Load the given transactions from the inner store with one `get_many`
+    pub async fn warm(&self, tx_ids: &[u32]) {
+        let (missing, generation) = {
+            let mut cache = self.cache();
+            
+            // Entries never consumed (e.g. rejected disputes) must not block new warming
+            if cache.entries.len() >= self.capacity {
+                cache.entries.clear();
+            }
+            
+            let missing: Vec<u32> = tx_ids
+                .iter()
+                .copied()
+                .filter(|tx_id| !cache.entries.contains_key(tx_id))
+                .take(self.capacity.saturating_sub(cache.entries.len()))
+                .collect();
+            (missing, cache.generation)
+        };
+        if missing.is_empty() {
+            return;
+        }
+        
+        let fetched = self.inner.get_many(&missing).await;
+        let mut cache = self.cache();
+        if cache.generation != generation {
+            return;
+        }
+        cache.entries.extend(fetched);
+    }
+    
+    pub async fn cached_len(&self) -> usize {
+        self.cache().entries.len()
+    }
+}
+
+#[async_trait]
+impl TransactionStore for PrefetchingStore {
+    async fn get(&self, tx_id: u32) -> Option<StoredTransaction> {
+        // Prefetched entries are consumed by the lookup they were warmed for
+        let cached = self.cache().entries.remove(&tx_id);
+        if let Some(tx) = cached {
+            return Some(tx);
+        }
+        
+        self.inner.get(tx_id).await
+    }
+    
+    async fn put(&self, tx_id: u32, tx: StoredTransaction) -> Result<()> {
+        self.cache().invalidate(tx_id);
+        let result = self.inner.put(tx_id, tx).await;
+        self.cache().invalidate(tx_id);
+        result
+    }
+    
+    async fn remove(&self, tx_id: u32) -> Result<()> {
+        self.cache().invalidate(tx_id);
+        let result = self.inner.remove(tx_id).await;
+        self.cache().invalidate(tx_id);
+        result
+    }
+    
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> Result<Vec<(u32, StoredTransaction)>> {
+        self.inner.list_client(client, after, limit).await
+    }
+    
+    async fn get_many(&self, tx_ids: &[u32]) -> Vec<(u32, StoredTransaction)> {
+        self.inner.get_many(tx_ids).await
+    }
+    
+    async fn snapshot_entries(&self) -> Option<Vec<(u32, StoredTransaction)>> {
+        self.inner.snapshot_entries().await
+    }
+    
+    /// Prefetched copies of dropped records are purged afterwards, and a warm
+    /// overlapping the compaction discards what it fetched
+    async fn compact(&self, retain: Retain) -> Result<CompactionReport> {
+        self.cache().generation += 1;
+        let report = self.inner.compact(retain.clone()).await;
+        let mut cache = self.cache();
+        cache.generation += 1;
+        cache.entries.retain(|_, tx| retain(tx));
+        report
+    }
+}
+
+/// Cold storage at `--storage-path` (RocksDB) or `--object-store-url`, in memory without either
+///
+/// Cold transactions only survive restarts when persisted to RocksDB or an object store.
+pub fn open_cold_storage(
+    storage_path: Option<PathBuf>,
+    object_store_url: Option<String>,
+) -> Result<Arc<dyn TransactionStore>> {
+    let store: Arc<dyn TransactionStore> = match (storage_path, object_store_url) {
+        (Some(_), Some(_)) => anyhow::bail!("--storage-path and --object-store-url can't be combined"),
+        #[cfg(feature = "rocksdb")]
+        (Some(path), None) => Arc::new(RocksDbStore::open(&path)?),
+        #[cfg(not(feature = "rocksdb"))]
+        (Some(_), None) => anyhow::bail!("--storage-path needs a build with the rocksdb feature"),
+        #[cfg(feature = "object-store")]
+        (None, Some(url)) => Arc::new(ObjectStoreBackend::from_url(&url)?),
+        #[cfg(not(feature = "object-store"))]
+        (None, Some(_)) => anyhow::bail!("--object-store-url needs a build with the object-store feature"),
+        (None, None) => Arc::new(InMemoryStore::new()),
+    };
+    Ok(store)
+}
+
+#[cfg(feature = "rocksdb")]
+pub use rocks::RocksDbStore;
+
+#[cfg(feature = "rocksdb")]
+mod rocks {
+    use super::{CompactionReport, Retain, StoredTransaction, TransactionStore};
+    use anyhow::{Context, Result};
+    use async_trait::async_trait;
+    use rocksdb::{
+        ColumnFamily, ColumnFamilyDescriptor, DBWithThreadMode, Direction, IteratorMode, Options, SingleThreaded,
+        WriteBatch,
+    };
+    use std::path::Path;
+    use std::sync::Arc;
+
+    type Db = DBWithThreadMode<SingleThreaded>;
+
+    /// Transactions keyed by `(client, tx_id)`
+    const CF_TRANSACTIONS: &str = "transactions";
+    /// `tx_id` -> owning client, so lookups by tx id alone can find the row
+    const CF_TX_CLIENTS: &str = "tx_clients";
+    /// Deletions a compaction writes at once
+    const COMPACTION_BATCH: usize = 1024;
+
+    /// Cold storage persisted in a RocksDB directory, survives restarts
+    ///
+    /// RocksDB calls block, they run on tokio's blocking pool.
+    pub struct RocksDbStore {
+        db: Arc<Db>,
+    }
+
+    impl RocksDbStore {
+        pub fn open(path: &Path) -> Result<Self> {
+            let mut options = Options::default();
+            options.create_if_missing(true);
+            options.create_missing_column_families(true);
+
+            let column_families = [CF_TRANSACTIONS, CF_TX_CLIENTS]
+                .into_iter()
+                .map(|name| ColumnFamilyDescriptor::new(name, Options::default()));
+            let db = Db::open_cf_descriptors(&options, path, column_families)
+                .with_context(|| format!("opening RocksDB store at {}", path.display()))?;
+
+            Ok(Self { db: Arc::new(db) })
+        }
+
+        async fn blocking<T, F>(&self, op: F) -> Result<T>
+        where
+            T: Send + 'static,
+            F: FnOnce(&Db) -> Result<T> + Send + 'static,
+        {
+            let db = self.db.clone();
+            tokio::task::spawn_blocking(move || op(&db)).await?
+        }
+    }
+
+    fn column_family<'a>(db: &'a Db, name: &str) -> Result<&'a ColumnFamily> {
+        db.cf_handle(name)
+            .with_context(|| format!("missing column family {}", name))
+    }
+
+    fn tx_key(tx_id: u32) -> [u8; 4] {
+        tx_id.to_be_bytes()
+    }
+
+    /// Client first so one client's transactions sort together
+    fn row_key(client: u16, tx_id: u32) -> [u8; 6] {
+        let mut key = [0; 6];
+        key[..2].copy_from_slice(&client.to_be_bytes());
+        key[2..].copy_from_slice(&tx_id.to_be_bytes());
+        key
+    }
+
+    fn owner(db: &Db, tx_id: u32) -> Result<Option<u16>> {
+        let clients = column_family(db, CF_TX_CLIENTS)?;
+        let Some(bytes) = db.get_cf(clients, tx_key(tx_id))? else {
+            return Ok(None);
+        };
+        let bytes: [u8; 2] = bytes.as_slice().try_into().context("corrupt tx client index")?;
+        Ok(Some(u16::from_be_bytes(bytes)))
+    }
+
+    fn read(db: &Db, tx_id: u32) -> Result<Option<StoredTransaction>> {
+        let Some(client) = owner(db, tx_id)? else {
+            return Ok(None);
+        };
+        let transactions = column_family(db, CF_TRANSACTIONS)?;
+        match db.get_cf(transactions, row_key(client, tx_id))? {
+            Some(bytes) => Ok(Some(rmp_serde::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[async_trait]
+    impl TransactionStore for RocksDbStore {
+        async fn get(&self, tx_id: u32) -> Option<StoredTransaction> {
+            match self.blocking(move |db| read(db, tx_id)).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    tracing::error!(tx_id, error = ?e, "Failed to read transaction from RocksDB");
+                    None
+                }
+            }
+        }
+
+        async fn put(&self, tx_id: u32, tx: StoredTransaction) -> Result<()> {
+            self.blocking(move |db| {
+                let transactions = column_family(db, CF_TRANSACTIONS)?;
+                let clients = column_family(db, CF_TX_CLIENTS)?;
+
+                // Both column families change in one atomic write
+                let mut batch = WriteBatch::default();
+                if let Some(previous) = owner(db, tx_id)?.filter(|&client| client != tx.client) {
+                    batch.delete_cf(transactions, row_key(previous, tx_id));
+                }
+                batch.put_cf(transactions, row_key(tx.client, tx_id), rmp_serde::to_vec_named(&tx)?);
+                batch.put_cf(clients, tx_key(tx_id), tx.client.to_be_bytes());
+                db.write(batch)?;
+                Ok(())
+            })
+            .await
+        }
+
+        async fn remove(&self, tx_id: u32) -> Result<()> {
+            self.blocking(move |db| {
+                let Some(client) = owner(db, tx_id)? else {
+                    return Ok(());
+                };
+                let transactions = column_family(db, CF_TRANSACTIONS)?;
+                let clients = column_family(db, CF_TX_CLIENTS)?;
+
+                let mut batch = WriteBatch::default();
+                batch.delete_cf(transactions, row_key(client, tx_id));
+                batch.delete_cf(clients, tx_key(tx_id));
+                db.write(batch)?;
+                Ok(())
+            })
+            .await
+        }
+
+        /// Range scan over the client's key prefix
+        async fn list_client(
+            &self,
+            client: u16,
+            after: Option<u32>,
+            limit: usize,
+        ) -> Result<Vec<(u32, StoredTransaction)>> {
+            let first = match after {
+                None => 0,
+                Some(after) => match after.checked_add(1) {
+                    Some(first) => first,
+                    None => return Ok(Vec::new()),
+                },
+            };
+
+            self.blocking(move |db| {
+                let transactions = column_family(db, CF_TRANSACTIONS)?;
+                let start = row_key(client, first);
+                let mut listed = Vec::new();
+                for item in db.iterator_cf(transactions, IteratorMode::From(&start, Direction::Forward)) {
+                    let (key, value) = item?;
+                    if listed.len() >= limit || key[..2] != client.to_be_bytes() {
+                        break;
+                    }
+                    let tx_id: [u8; 4] = key[2..].try_into().context("corrupt transaction key")?;
+                    listed.push((u32::from_be_bytes(tx_id), rmp_serde::from_slice(&value)?));
+                }
+                Ok(listed)
+            })
+            .await
+        }
+
+        async fn get_many(&self, tx_ids: &[u32]) -> Vec<(u32, StoredTransaction)> {
+            let tx_ids = tx_ids.to_vec();
+            let found = self
+                .blocking(move |db| {
+                    let mut found = Vec::with_capacity(tx_ids.len());
+                    for tx_id in tx_ids {
+                        if let Some(tx) = read(db, tx_id)? {
+                            found.push((tx_id, tx));
+                        }
+                    }
+                    Ok(found)
+                })
+                .await;
+
+            found.unwrap_or_else(|e| {
+                tracing::error!(error = ?e, "Failed to read transactions from RocksDB");
+                Vec::new()
+            })
+        }
+
+        /// Deletes in batches while scanning, then compacts both column
+        /// families so the deletions' tombstones are dropped from disk too
+        async fn compact(&self, retain: Retain) -> Result<CompactionReport> {
+            self.blocking(move |db| {
+                let transactions = column_family(db, CF_TRANSACTIONS)?;
+                let clients = column_family(db, CF_TX_CLIENTS)?;
+
+                let mut report = CompactionReport::default();
+                let mut batch = WriteBatch::default();
+                for item in db.iterator_cf(transactions, IteratorMode::Start) {
+                    let (key, value) = item?;
+                    report.scanned += 1;
+                    let tx: StoredTransaction = rmp_serde::from_slice(&value)?;
+                    if retain(&tx) {
+                        continue;
+                    }
+                    let tx_id: [u8; 4] = key[2..].try_into().context("corrupt transaction key")?;
+                    batch.delete_cf(transactions, &key);
+                    batch.delete_cf(clients, tx_id);
+                    report.removed += 1;
+                    // The owner index entry is a 4 byte key and a 2 byte value
+                    report.bytes_reclaimed += (key.len() + value.len() + 6) as u64;
+                    if batch.len() >= COMPACTION_BATCH * 2 {
+                        db.write(std::mem::take(&mut batch))?;
+                    }
+                }
+                db.write(batch)?;
+
+                db.compact_range_cf(transactions, None::<&[u8]>, None::<&[u8]>);
+                db.compact_range_cf(clients, None::<&[u8]>, None::<&[u8]>);
+                Ok(report)
+            })
+            .await
+        }
+    }
+}
+
+#[cfg(feature = "object-store")]
+pub use object::ObjectStoreBackend;
+
+#[cfg(feature = "object-store")]
+mod object {
+    use super::{CompactionReport, Retain, StoredTransaction, TransactionStore};
+    use anyhow::{Context, Result};
+    use async_trait::async_trait;
+    use futures::stream::{self, StreamExt, TryStreamExt};
+    use object_store::aws::AmazonS3Builder;
+    use object_store::path::Path;
+    use object_store::{ObjectStore, PutPayload};
+    use std::sync::Arc;
+
+    /// Objects fetched at once by batched reads
+    const READ_CONCURRENCY: usize = 16;
+
+    /// Cold storage in an object store such as S3, survives restarts
+    ///
+    /// Each transaction is one object under its client's prefix,
+    /// `<prefix>/clients/<client>/<tx_id>`, with a `<prefix>/tx/<tx_id>` object
+    /// naming the owner so lookups by tx id alone can find it. Numbers are
+    /// zero-padded, keys sort in tx id order.
+    pub struct ObjectStoreBackend {
+        store: Arc<dyn ObjectStore>,
+        prefix: Path,
+        sorted_listing: bool,
+    }
+
+    impl ObjectStoreBackend {
+        pub fn new(store: Arc<dyn ObjectStore>, prefix: &str) -> Result<Self> {
+            let prefix = Path::parse(prefix).with_context(|| format!("invalid object store prefix {}", prefix))?;
+            Ok(Self {
+                store,
+                prefix,
+                sorted_listing: false,
+            })
+        }
+
+        /// The store lists keys in order, as S3 does, so a page stops listing once it is full
+        pub fn with_sorted_listing(mut self) -> Self {
+            self.sorted_listing = true;
+            self
+        }
+
+        /// S3 bucket from an `s3://<bucket>/<prefix>` url, credentials and endpoint from the `AWS_*` variables
+        pub fn from_url(url: &str) -> Result<Self> {
+            let location = url
+                .strip_prefix("s3://")
+                .with_context(|| format!("expected an s3://<bucket>/<prefix> url, got {}", url))?;
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            let store = AmazonS3Builder::from_env().with_bucket_name(bucket).build()?;
+            Ok(Self::new(Arc::new(store), prefix)?.with_sorted_listing())
+        }
+
+        fn client_prefix(&self, client: u16) -> Path {
+            self.prefix.child("clients").child(format!("{:05}", client))
+        }
+
+        fn row_path(&self, client: u16, tx_id: u32) -> Path {
+            self.client_prefix(client).child(format!("{:010}", tx_id))
+        }
+
+        fn owner_path(&self, tx_id: u32) -> Path {
+            self.prefix.child("tx").child(format!("{:010}", tx_id))
+        }
+
+        /// Object contents, `None` if there is no such object
+        async fn fetch(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+            match self.store.get(path).await {
+                Ok(object) => Ok(Some(object.bytes().await?.to_vec())),
+                Err(object_store::Error::NotFound { .. }) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+
+        async fn owner(&self, tx_id: u32) -> Result<Option<u16>> {
+            let Some(bytes) = self.fetch(&self.owner_path(tx_id)).await? else {
+                return Ok(None);
+            };
+            let bytes: [u8; 2] = bytes.as_slice().try_into().context("corrupt tx owner object")?;
+            Ok(Some(u16::from_be_bytes(bytes)))
+        }
+
+        async fn read(&self, tx_id: u32) -> Result<Option<StoredTransaction>> {
+            let Some(client) = self.owner(tx_id).await? else {
+                return Ok(None);
+            };
+            match self.fetch(&self.row_path(client, tx_id)).await? {
+                Some(bytes) => Ok(Some(rmp_serde::from_slice(&bytes)?)),
+                None => Ok(None),
+            }
+        }
+
+        async fn delete(&self, path: &Path) -> Result<()> {
+            match self.store.delete(path).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TransactionStore for ObjectStoreBackend {
+        async fn get(&self, tx_id: u32) -> Option<StoredTransaction> {
+            match self.read(tx_id).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    tracing::error!(tx_id, error = ?e, "Failed to read transaction from object store");
+                    None
+                }
+            }
+        }
+
+        /// The row is written before the owner object, a reader never finds an owner without a row
+        async fn put(&self, tx_id: u32, tx: StoredTransaction) -> Result<()> {
+            let previous = self.owner(tx_id).await?.filter(|&client| client != tx.client);
+            let row = PutPayload::from(rmp_serde::to_vec_named(&tx)?);
+            self.store.put(&self.row_path(tx.client, tx_id), row).await?;
+            let owner = PutPayload::from(tx.client.to_be_bytes().to_vec());
+            self.store.put(&self.owner_path(tx_id), owner).await?;
+            if let Some(previous) = previous {
+                self.delete(&self.row_path(previous, tx_id)).await?;
+            }
+            Ok(())
+        }
+
+        async fn remove(&self, tx_id: u32) -> Result<()> {
+            let Some(client) = self.owner(tx_id).await? else {
+                return Ok(());
+            };
+            self.delete(&self.row_path(client, tx_id)).await?;
+            self.delete(&self.owner_path(tx_id)).await
+        }
+
+        /// Listing of the client's prefix past `after`, then a batched read of the page
+        async fn list_client(
+            &self,
+            client: u16,
+            after: Option<u32>,
+            limit: usize,
+        ) -> Result<Vec<(u32, StoredTransaction)>> {
+            let prefix = self.client_prefix(client);
+            let listing = match after {
+                Some(after) => self.store.list_with_offset(Some(&prefix), &self.row_path(client, after)),
+                None => self.store.list(Some(&prefix)),
+            };
+            let listing = listing
+                .map_ok(|meta| meta.location.filename().and_then(|name| name.parse::<u32>().ok()))
+                .try_filter_map(|tx_id| async move { Ok(tx_id) });
+            let tx_ids: Vec<u32> = if self.sorted_listing {
+                listing.take(limit).try_collect().await?
+            } else {
+                // Other stores may list in any order, the whole rest of the prefix is needed
+                let mut tx_ids: Vec<u32> = listing.try_collect().await?;
+                tx_ids.sort_unstable();
+                tx_ids.retain(|&tx_id| after.is_none_or(|after| tx_id > after));
+                tx_ids.truncate(limit);
+                tx_ids
+            };
+
+            stream::iter(tx_ids)
+                .map(|tx_id| async move {
+                    let bytes = self.fetch(&self.row_path(client, tx_id)).await?;
+                    bytes.map(|bytes| Ok((tx_id, rmp_serde::from_slice(&bytes)?))).transpose()
+                })
+                .buffered(READ_CONCURRENCY)
+                .try_filter_map(|row| async move { Ok(row) })
+                .try_collect()
+                .await
+        }
+
+        async fn get_many(&self, tx_ids: &[u32]) -> Vec<(u32, StoredTransaction)> {
+            let found: Result<Vec<_>> = stream::iter(tx_ids.iter().copied())
+                .map(|tx_id| async move { Ok(self.read(tx_id).await?.map(|tx| (tx_id, tx))) })
+                .buffered(READ_CONCURRENCY)
+                .try_filter_map(|row| async move { Ok(row) })
+                .try_collect()
+                .await;
+
+            found.unwrap_or_else(|e| {
+                tracing::error!(error = ?e, "Failed to read transactions from object store");
+                Vec::new()
+            })
+        }
+
+        /// Reads every row under `<prefix>/clients`, deleting rejected ones like `remove`
+        async fn compact(&self, retain: Retain) -> Result<CompactionReport> {
+            let clients = self.prefix.child("clients");
+            let rows: Vec<(u16, u32, u64)> = self
+                .store
+                .list(Some(&clients))
+                .map_ok(|meta| {
+                    let parts: Vec<_> = meta.location.prefix_match(&clients)?.collect();
+                    match parts.as_slice() {
+                        [client, tx_id] => Some((client.as_ref().parse().ok()?, tx_id.as_ref().parse().ok()?, meta.size)),
+                        _ => None,
+                    }
+                })
+                .try_filter_map(|row| async move { Ok(row) })
+                .try_collect()
+                .await?;
+
+            // Per row: `None` if it vanished meanwhile, else the bytes its removal reclaimed, if removed
+            let retain = &retain;
+            let outcomes: Vec<Option<Option<u64>>> = stream::iter(rows)
+                .map(|(client, tx_id, size)| async move {
+                    let Some(bytes) = self.fetch(&self.row_path(client, tx_id)).await? else {
+                        return Ok(None);
+                    };
+                    if retain(&rmp_serde::from_slice(&bytes)?) {
+                        return Ok(Some(None));
+                    }
+                    self.delete(&self.row_path(client, tx_id)).await?;
+                    self.delete(&self.owner_path(tx_id)).await?;
+                    // The owner object holds the 2 byte client id
+                    let keys = self.row_path(client, tx_id).as_ref().len() + self.owner_path(tx_id).as_ref().len();
+                    Ok::<_, anyhow::Error>(Some(Some(size + 2 + keys as u64)))
+                })
+                .buffered(READ_CONCURRENCY)
+                .try_collect()
+                .await?;
+
+            let mut report = CompactionReport::default();
+            for removed in outcomes.into_iter().flatten() {
+                report.scanned += 1;
+                if let Some(bytes) = removed {
+                    report.removed += 1;
+                    report.bytes_reclaimed += bytes;
+                }
+            }
+            Ok(report)
+        }
+    }
 }
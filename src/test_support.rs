@@ -0,0 +1,298 @@
+use crate::account_actor::{AccountActor, AccountHandle, ActorConfig, ActorServices};
+use crate::alerts::AlertRules;
+use crate::clock::Clock;
+use crate::compat::CompatConfig;
+use crate::errors::ProcessingError;
+use crate::events::{AccountUpdates, EventBus};
+use crate::handlers::HandlerRegistry;
+use crate::hot_store::HotTransaction;
+use crate::metrics::{MigrationMetrics, MigrationMetricsSnapshot};
+use crate::migration::MigrationConfig;
+use crate::models::{Account, TransactionRow, TransactionType};
+use crate::reporting::ReportingCounters;
+use crate::snapshots::InMemorySnapshotStore;
+use crate::storage::{
+    CompactionReport, InMemoryStore, Retain, RetryPolicy, StorageTier, StoredTransaction, TransactionStore,
+};
+use crate::treasury::ShardTotals;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Clock that only moves when told to
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for ManualClock {
+    /// Starts at 2024-01-01T00:00:00Z
+    fn default() -> Self {
+        Self::new(UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// A single account actor wired to in-memory services, driven without the engine
+///
+/// Rows go straight to the actor: no tx id registry, event log or period checks.
+pub struct ActorHarness {
+    handle: AccountHandle,
+    clock: Arc<ManualClock>,
+    cold_storage: Arc<InMemoryStore>,
+    migration_metrics: Arc<MigrationMetrics>,
+    handlers: Arc<HandlerRegistry>,
+}
+
+impl ActorHarness {
+    pub fn new(client_id: u16) -> Self {
+        Self::with_migration_config(client_id, MigrationConfig::default())
+    }
+
+    pub fn with_migration_config(client_id: u16, migration_config: MigrationConfig) -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let cold_storage = Arc::new(InMemoryStore::new());
+        let migration_metrics = Arc::new(MigrationMetrics::new());
+        let handlers = Arc::new(HandlerRegistry::new());
+        // Nobody reads the projection here, a closed channel is ignored by the actor
+        let (projection, _) = mpsc::unbounded_channel();
+        let totals = Arc::new(ShardTotals::new(1));
+        totals.open(client_id);
+
+        let services = ActorServices {
+            cold_storage: cold_storage.clone(),
+            migration_metrics: migration_metrics.clone(),
+            migration_config,
+            actor_config: ActorConfig::default(),
+            storage_retry: RetryPolicy::default(),
+            projection,
+            handlers: handlers.clone(),
+            counters: Arc::new(ReportingCounters::new()),
+            clock: clock.clone(),
+            compat: CompatConfig::default(),
+            snapshots: Arc::new(InMemorySnapshotStore::new()),
+            totals,
+            alerts: Arc::new(AlertRules::new(EventBus::default())),
+            updates: AccountUpdates::default(),
+        };
+
+        let (tx, rx) = mpsc::channel(1000);
+        tokio::spawn(AccountActor::new(client_id, rx, services).run());
+
+        Self {
+            handle: AccountHandle::new(tx),
+            clock,
+            cold_storage,
+            migration_metrics,
+            handlers,
+        }
+    }
+
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    pub fn migration_metrics(&self) -> MigrationMetricsSnapshot {
+        self.migration_metrics.snapshot()
+    }
+
+    pub fn cold_storage(&self) -> &InMemoryStore {
+        &self.cold_storage
+    }
+
+    /// Register custom transaction handlers here before sending their rows
+    pub fn handlers(&self) -> &HandlerRegistry {
+        &self.handlers
+    }
+
+    /// Apply a live row, stamped with the manual clock
+    pub async fn process(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        self.handle.process_at(tx, self.clock.now()).await
+    }
+
+    /// Apply a row as if replayed from the event log
+    pub async fn replay(&self, tx: TransactionRow) -> Result<(), ProcessingError> {
+        self.handle.replay(tx).await
+    }
+
+    pub async fn state(&self) -> Account {
+        self.handle
+            .get_state()
+            .await
+            .expect("actor stopped")
+    }
+
+    /// Where a transaction is stored, if the actor still knows it
+    pub async fn inspect(&self, tx_id: u32) -> Option<(StoredTransaction, StorageTier)> {
+        self.handle
+            .inspect_transaction(tx_id)
+            .await
+            .expect("actor stopped")
+    }
+
+    /// Hot transactions as the admin API reports them
+    pub async fn hot_transactions(&self) -> Vec<HotTransaction> {
+        self.handle
+            .hot_transactions()
+            .await
+            .expect("actor stopped")
+    }
+
+    /// Force-migrate everything and wait until hot storage is empty, false after a second
+    pub async fn force_migrate_cold(&self) -> bool {
+        self.handle.force_migrate_cold().await.expect("actor stopped");
+
+        for _ in 0..100 {
+            if self.handle.hot_storage_len().await.expect("actor stopped") == 0 {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    /// Run a migration pass and wait for it to complete
+    ///
+    /// Returns false if no pass finished within a second, e.g. because nothing
+    /// was old enough to migrate at the current clock time.
+    pub async fn migrate_cold(&self) -> bool {
+        let runs_before = self.migration_metrics.snapshot().runs;
+        self.handle.migrate_cold().await.expect("actor stopped");
+
+        for _ in 0..100 {
+            // Runs are recorded after hot storage has been cleaned up
+            if self.migration_metrics.snapshot().runs > runs_before {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+}
+
+/// In-memory cold store whose writes can be made to fail on demand
+///
+/// Reads always succeed, so a transaction that is about to be updated can still be found.
+#[derive(Default)]
+pub struct FaultyStore {
+    inner: InMemoryStore,
+    failures: AtomicU32,
+}
+
+impl FaultyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail the next `writes` puts and removes, replacing any failures still pending
+    pub fn fail_next(&self, writes: u32) {
+        self.failures.store(writes, Ordering::SeqCst);
+    }
+
+    fn write_fails(&self) -> bool {
+        self.failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+#[async_trait]
+impl TransactionStore for FaultyStore {
+    async fn get(&self, tx_id: u32) -> Option<StoredTransaction> {
+        self.inner.get(tx_id).await
+    }
+
+    async fn put(&self, tx_id: u32, tx: StoredTransaction) -> anyhow::Result<()> {
+        if self.write_fails() {
+            anyhow::bail!("injected cold storage failure");
+        }
+        self.inner.put(tx_id, tx).await
+    }
+
+    async fn remove(&self, tx_id: u32) -> anyhow::Result<()> {
+        if self.write_fails() {
+            anyhow::bail!("injected cold storage failure");
+        }
+        self.inner.remove(tx_id).await
+    }
+
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u32, StoredTransaction)>> {
+        self.inner.list_client(client, after, limit).await
+    }
+
+    async fn snapshot_entries(&self) -> Option<Vec<(u32, StoredTransaction)>> {
+        self.inner.snapshot_entries().await
+    }
+
+    async fn compact(&self, retain: Retain) -> anyhow::Result<CompactionReport> {
+        if self.write_fails() {
+            anyhow::bail!("injected cold storage failure");
+        }
+        self.inner.compact(retain).await
+    }
+}
+
+pub fn deposit(client: u16, tx: u32, amount: Decimal) -> TransactionRow {
+    row(TransactionType::Deposit, client, tx, Some(amount))
+}
+
+pub fn withdrawal(client: u16, tx: u32, amount: Decimal) -> TransactionRow {
+    row(TransactionType::Withdrawal, client, tx, Some(amount))
+}
+
+pub fn dispute(client: u16, tx: u32) -> TransactionRow {
+    row(TransactionType::Dispute, client, tx, None)
+}
+
+pub fn resolve(client: u16, tx: u32) -> TransactionRow {
+    row(TransactionType::Resolve, client, tx, None)
+}
+
+pub fn chargeback(client: u16, tx: u32) -> TransactionRow {
+    row(TransactionType::Chargeback, client, tx, None)
+}
+
+pub fn transfer(client: u16, to: u16, tx: u32, amount: Decimal) -> TransactionRow {
+    TransactionRow {
+        to: Some(to),
+        ..row(TransactionType::Transfer, client, tx, Some(amount))
+    }
+}
+
+fn row(tx_type: TransactionType, client: u16, tx: u32, amount: Option<Decimal>) -> TransactionRow {
+    TransactionRow {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to: None,
+    }
+}
@@ -0,0 +1,128 @@
+use crate::audit::AuditRecord;
+use crate::models::{TransactionRow, TransactionType};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::time::UNIX_EPOCH;
+use utoipa::ToSchema;
+
+/// What happened to the account at a timeline entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineKind {
+    Applied,
+    Rejected,
+    AccountLocked,
+}
+
+/// Where a timeline entry was reconstructed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineSource {
+    EventLog,
+    Audit,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TimelineEntry {
+    pub kind: TimelineKind,
+    pub tx_type: TransactionType,
+    pub tx: u32,
+    pub amount: Option<Decimal>,
+    pub reason: Option<String>,
+    /// Tx id of the corrected row that replaced this rejected one
+    pub superseded_by: Option<u32>,
+    /// Unix seconds; unknown for entries replayed from the event log
+    pub recorded_at: Option<u64>,
+    pub source: TimelineSource,
+}
+
+/// Chronological view of everything that affected one account
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AccountTimeline {
+    pub client: u16,
+    pub entries: Vec<TimelineEntry>,
+    pub open_disputes: Vec<u32>,
+}
+
+/// Assemble a timeline from events replayed at startup followed by live audit records
+pub fn build_timeline(
+    client: u16,
+    history: impl IntoIterator<Item = TransactionRow>,
+    live: impl IntoIterator<Item = AuditRecord>,
+) -> AccountTimeline {
+    let historical = history
+        .into_iter()
+        .filter(|event| event.client == client || event.to == Some(client))
+        .map(|event| (event, None, None, None, TimelineSource::EventLog));
+
+    let live = live.into_iter().map(|record| {
+        let recorded_at = record
+            .recorded_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        (record.tx, record.rejection, record.superseded_by, recorded_at, TimelineSource::Audit)
+    });
+
+    let mut entries = Vec::new();
+    let mut open_disputes = BTreeSet::new();
+
+    for (tx, rejection, superseded_by, recorded_at, source) in historical.chain(live) {
+        let applied = rejection.is_none();
+
+        entries.push(TimelineEntry {
+            kind: if applied { TimelineKind::Applied } else { TimelineKind::Rejected },
+            tx_type: tx.tx_type.clone(),
+            tx: tx.tx,
+            amount: tx.amount,
+            reason: rejection,
+            superseded_by,
+            recorded_at,
+            source,
+        });
+
+        if !applied {
+            continue;
+        }
+
+        match tx.tx_type {
+            TransactionType::Dispute => {
+                open_disputes.insert(tx.tx);
+            }
+            TransactionType::Resolve => {
+                open_disputes.remove(&tx.tx);
+            }
+            TransactionType::Chargeback => {
+                open_disputes.remove(&tx.tx);
+                // An applied chargeback always locks the account
+                entries.push(TimelineEntry {
+                    kind: TimelineKind::AccountLocked,
+                    tx_type: TransactionType::Chargeback,
+                    tx: tx.tx,
+                    amount: None,
+                    reason: None,
+                    superseded_by: None,
+                    recorded_at,
+                    source,
+                });
+            }
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::OpeningBalance
+            | TransactionType::Transfer
+            | TransactionType::Unlock
+            | TransactionType::Authorize
+            | TransactionType::Capture
+            | TransactionType::Void
+            | TransactionType::Custom(_) => {}
+        }
+    }
+
+    AccountTimeline {
+        client,
+        entries,
+        open_disputes: open_disputes.into_iter().collect(),
+    }
+}
+
@@ -0,0 +1,56 @@
+use std::sync::OnceLock;
+
+/// Shards per CPU the engine may run on
+pub const SHARDS_PER_CPU: usize = 2;
+/// Fewest shards picked automatically
+pub const MIN_AUTO_SHARDS: usize = 4;
+/// Most shards picked automatically
+pub const MAX_AUTO_SHARDS: usize = 1024;
+
+/// CPUs and NUMA nodes the process can use, what the default shard count is derived from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTopology {
+    /// CPUs this process may run on, affinity masks and cgroup quotas included
+    pub cpus: usize,
+    /// Memory nodes, 1 where the platform doesn't say
+    pub numa_nodes: usize,
+}
+
+impl CpuTopology {
+    /// The machine's topology, read once per process
+    pub fn detect() -> Self {
+        static DETECTED: OnceLock<CpuTopology> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+            let numa_nodes = std::fs::read_to_string("/sys/devices/system/node/online")
+                .ok()
+                .and_then(|list| parse_node_list(&list))
+                .unwrap_or(1);
+            Self { cpus, numa_nodes: numa_nodes.clamp(1, cpus) }
+        })
+    }
+
+    /// `SHARDS_PER_CPU` per CPU within the auto limits, rounded up to a multiple
+    /// of the NUMA nodes so that each node's CPUs get an equal share
+    pub fn shards(&self) -> usize {
+        let shards = (self.cpus * SHARDS_PER_CPU).clamp(MIN_AUTO_SHARDS, MAX_AUTO_SHARDS);
+        shards.div_ceil(self.numa_nodes) * self.numa_nodes
+    }
+}
+
+/// Shard count used when neither the config nor `--shards` sets one
+pub fn auto_shards() -> usize {
+    CpuTopology::detect().shards()
+}
+
+/// Number of nodes in a sysfs node list such as `0-1,3`, `None` if malformed
+pub fn parse_node_list(list: &str) -> Option<usize> {
+    let mut count = 0;
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        count += match range.split_once('-') {
+            Some((first, last)) => last.parse::<usize>().ok()?.checked_sub(first.parse().ok()?)? + 1,
+            None => range.parse::<usize>().map(|_| 1).ok()?,
+        };
+    }
+    (count > 0).then_some(count)
+}
@@ -0,0 +1,209 @@
+use crate::compat::CompatConfig;
+use crate::event_store::read_events;
+use crate::handlers::HandlerRegistry;
+use crate::models::{Account, TransactionRow, TransactionType};
+use crate::shard_manager::ShardManager;
+use crate::storage::StorageTier;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Dispute lifecycle of a traced transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    Undisputed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+/// One account check a traced event went through
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TraceCheck {
+    pub check: String,
+    /// "passed", or why it failed
+    pub outcome: String,
+}
+
+/// One event-log entry that referenced the traced transaction
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TraceEvent {
+    /// 1-based position of the event in the log
+    pub seq: usize,
+    pub tx_type: TransactionType,
+    pub client: u16,
+    pub amount: Option<Decimal>,
+    /// Account checks in the order they ran, against the state before the event
+    pub checks: Vec<TraceCheck>,
+    /// "applied", or the reason the replay rejected the event
+    pub outcome: String,
+    pub available_delta: Decimal,
+    pub held_delta: Decimal,
+    pub locked_after: bool,
+}
+
+/// Everything the event log knows about a single transaction
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TransactionTrace {
+    pub tx: u32,
+    /// Event log the trace was read from
+    #[schema(value_type = String)]
+    pub source: PathBuf,
+    pub events: Vec<TraceEvent>,
+    pub dispute_state: DisputeState,
+    pub storage_tier: Option<StorageTier>,
+}
+
+/// Rebuild the history of `tx_id` by replaying the involved clients, and their transfer counterparties, into `scratch`
+///
+/// `scratch` is an empty manager applying events as the engine that wrote
+/// the log did, see `ShardManager::scratch`.
+pub async fn trace_transaction(
+    source: &Path,
+    events: Vec<TransactionRow>,
+    tx_id: u32,
+    scratch: ShardManager,
+) -> TransactionTrace {
+    // Only clients that referenced the tx can have affected it
+    let referencing = events
+        .iter()
+        .filter(|event| event.tx == tx_id)
+        .flat_map(|event| std::iter::once(event.client).chain(event.to));
+    let clients = with_counterparties(&events, referencing);
+
+
+    let mut trace_events = Vec::new();
+    let mut dispute_state = DisputeState::Undisputed;
+    let mut owner = None;
+
+    for (idx, event) in events.into_iter().enumerate() {
+        let involved = clients.contains(&event.client)
+            || event.to.is_some_and(|to| clients.contains(&to));
+        if !involved {
+            continue;
+        }
+
+        if event.tx != tx_id {
+            let _ = scratch.replay(event).await;
+            continue;
+        }
+
+        let checks = scratch
+            .checks(&event)
+            .await
+            .into_iter()
+            .map(|check| TraceCheck {
+                check: check.check.to_string(),
+                outcome: match check.outcome {
+                    Ok(()) => "passed".to_string(),
+                    Err(e) => e.to_string(),
+                },
+            })
+            .collect();
+        let before = account_or_default(&scratch, event.client).await;
+        let result = scratch.replay(event.clone()).await;
+        let after = account_or_default(&scratch, event.client).await;
+
+        if result.is_ok() {
+            match event.tx_type {
+                TransactionType::Deposit
+                | TransactionType::Withdrawal
+                | TransactionType::Transfer
+                | TransactionType::Authorize => {
+                    owner = Some(event.client);
+                }
+                TransactionType::Dispute => dispute_state = DisputeState::Disputed,
+                TransactionType::Resolve => dispute_state = DisputeState::Resolved,
+                TransactionType::Chargeback => dispute_state = DisputeState::ChargedBack,
+                TransactionType::OpeningBalance
+                | TransactionType::Unlock
+                | TransactionType::Capture
+                | TransactionType::Void
+                | TransactionType::Custom(_) => {}
+            }
+        }
+
+        trace_events.push(TraceEvent {
+            seq: idx + 1,
+            tx_type: event.tx_type,
+            client: event.client,
+            amount: event.amount,
+            checks,
+            outcome: match result {
+                Ok(()) => "applied".to_string(),
+                Err(e) => e.to_string(),
+            },
+            available_delta: after.available - before.available,
+            held_delta: after.held - before.held,
+            locked_after: after.locked,
+        });
+    }
+
+    let storage_tier = match owner {
+        Some(client) => scratch
+            .inspect_transaction(client, tx_id)
+            .await
+            .map(|(_, tier)| tier),
+        None => None,
+    };
+
+    TransactionTrace {
+        tx: tx_id,
+        source: source.to_path_buf(),
+        events: trace_events,
+        dispute_state,
+        storage_tier,
+    }
+}
+
+/// `clients` and every client a transfer links them to, directly or through others
+///
+/// A transfer only applies as it did when its sender's balance is replayed
+/// too, and that balance may in turn depend on transfers to the sender.
+fn with_counterparties(events: &[TransactionRow], clients: impl IntoIterator<Item = u16>) -> HashSet<u16> {
+    let mut links: HashMap<u16, Vec<u16>> = HashMap::new();
+    for event in events.iter().filter(|event| event.tx_type == TransactionType::Transfer) {
+        if let Some(to) = event.to {
+            links.entry(event.client).or_default().push(to);
+            links.entry(to).or_default().push(event.client);
+        }
+    }
+
+    let mut pending: Vec<u16> = clients.into_iter().collect();
+    let mut reached = HashSet::new();
+    while let Some(client) = pending.pop() {
+        if reached.insert(client) {
+            pending.extend(links.get(&client).into_iter().flatten().copied());
+        }
+    }
+    reached
+}
+
+async fn account_or_default(manager: &ShardManager, client: u16) -> Account {
+    manager
+        .get_account(client)
+        .await
+        .unwrap_or_else(|| Account::new(client))
+}
+
+/// `trace` subcommand: print the trace of one transaction as JSON
+///
+/// `compat` must be the one the log was written under, as for replay.
+pub async fn run(tx_id: u32, log_path: PathBuf, compat: CompatConfig) -> Result<()> {
+    if !log_path.exists() {
+        anyhow::bail!("event log not found: {}", log_path.display());
+    }
+
+    let events = read_events(&log_path).await?;
+    let scratch = ShardManager::scratch(compat, Arc::new(HandlerRegistry::new()));
+    let trace = trace_transaction(&log_path, events, tx_id, scratch).await;
+
+    println!("{}", serde_json::to_string_pretty(&trace)?);
+
+    Ok(())
+}
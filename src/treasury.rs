@@ -0,0 +1,178 @@
+use crate::models::Account;
+use crate::routing::{ModuloRouting, RoutingStrategy};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use utoipa::ToSchema;
+
+/// Funds held for clients, summed over every account
+///
+/// The engine has a single currency and no tenants, so there is one report.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TreasuryReport {
+    pub accounts: u64,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: u64,
+    /// Sum of negative available balances, owed by clients after disputes of spent funds
+    pub negative_exposure: Decimal,
+}
+
+const CSV_HEADER: &str = "accounts,available,held,total,locked,negative_exposure";
+
+impl TreasuryReport {
+    /// Full scan, for callers that already hold every account
+    pub fn from_accounts<'a>(accounts: impl IntoIterator<Item = &'a Account>) -> Self {
+        let mut report = Self::default();
+        for account in accounts {
+            report.add(account);
+        }
+        report
+    }
+
+    /// Replace `previous` (if the account was already counted) with `current`
+    pub fn update(&mut self, previous: Option<&Account>, current: &Account) {
+        if let Some(previous) = previous {
+            self.remove(previous);
+        }
+        self.add(current);
+    }
+
+    /// These totals less one account's share
+    pub fn without(mut self, account: &Account) -> Self {
+        self.remove(account);
+        self
+    }
+
+    /// Fold another report's totals into this one
+    pub fn merge(&mut self, other: &TreasuryReport) {
+        self.accounts += other.accounts;
+        self.available += other.available;
+        self.held += other.held;
+        self.total += other.total;
+        self.locked += other.locked;
+        self.negative_exposure += other.negative_exposure;
+    }
+
+    fn add(&mut self, account: &Account) {
+        self.accounts += 1;
+        self.available += account.available;
+        self.held += account.held;
+        self.total += account.total();
+        self.locked += account.locked as u64;
+        self.negative_exposure += shortfall(account);
+    }
+
+    fn remove(&mut self, account: &Account) {
+        self.accounts -= 1;
+        self.available -= account.available;
+        self.held -= account.held;
+        self.total -= account.total();
+        self.locked -= account.locked as u64;
+        self.negative_exposure -= shortfall(account);
+    }
+
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{}\n{},{:.4},{:.4},{:.4},{},{:.4}\n",
+            CSV_HEADER,
+            self.accounts,
+            self.available,
+            self.held,
+            self.total,
+            self.locked,
+            self.negative_exposure
+        )
+    }
+}
+
+/// Running totals per shard, updated by actors before they acknowledge a write
+///
+/// Unlike the projection these never trail acknowledged writes, and reading
+/// them costs one lock per shard instead of a round trip per actor.
+pub struct ShardTotals {
+    shards: RwLock<Vec<Mutex<ShardTally>>>,
+    routing: Arc<dyn RoutingStrategy>,
+}
+
+/// One shard's totals and the accounts summed into them, kept to regroup them on a reshard
+#[derive(Default)]
+struct ShardTally {
+    report: TreasuryReport,
+    accounts: HashMap<u16, Account>,
+}
+
+impl ShardTally {
+    fn update(&mut self, previous: Option<&Account>, current: &Account) {
+        self.report.update(previous, current);
+        self.accounts.insert(current.client, current.clone());
+    }
+}
+
+impl ShardTotals {
+    pub fn new(num_shards: usize) -> Self {
+        Self::with_routing(num_shards, Arc::new(ModuloRouting))
+    }
+
+    /// Totals kept by the shard `routing` assigns each client to
+    pub fn with_routing(num_shards: usize, routing: Arc<dyn RoutingStrategy>) -> Self {
+        Self {
+            shards: RwLock::new((0..num_shards).map(|_| Mutex::default()).collect()),
+            routing,
+        }
+    }
+
+    fn update(&self, previous: Option<&Account>, current: &Account) {
+        let shards = self.shards.read().unwrap();
+        let shard = self.routing.shard(current.client, shards.len()) % shards.len();
+        shards[shard].lock().unwrap().update(previous, current);
+    }
+
+    /// Count a client's first actor, which starts from an empty account
+    pub fn open(&self, client: u16) {
+        self.update(None, &Account::new(client));
+    }
+
+    /// Account changed from `previous` to `current`
+    pub fn apply(&self, previous: &Account, current: &Account) {
+        self.update(Some(previous), current);
+    }
+
+    /// Regroup the totals over `num_shards` shards, as the routing assigns the clients now
+    pub fn reshard(&self, num_shards: usize) {
+        let mut shards = self.shards.write().unwrap();
+        let mut regrouped: Vec<Mutex<ShardTally>> = (0..num_shards).map(|_| Mutex::default()).collect();
+        for tally in shards.drain(..) {
+            for account in tally.into_inner().unwrap().accounts.into_values() {
+                let shard = self.routing.shard(account.client, num_shards) % num_shards;
+                regrouped[shard].get_mut().unwrap().update(None, &account);
+            }
+        }
+        *shards = regrouped;
+    }
+
+    /// Totals of one shard, `None` past the last shard
+    pub fn get(&self, shard: usize) -> Option<TreasuryReport> {
+        let shards = self.shards.read().unwrap();
+        shards.get(shard).map(|tally| tally.lock().unwrap().report.clone())
+    }
+
+    /// Totals of every shard combined
+    pub fn combined(&self) -> TreasuryReport {
+        let mut report = TreasuryReport::default();
+        for tally in self.shards.read().unwrap().iter() {
+            report.merge(&tally.lock().unwrap().report);
+        }
+        report
+    }
+}
+
+fn shortfall(account: &Account) -> Decimal {
+    if account.available < Decimal::ZERO {
+        -account.available
+    } else {
+        Decimal::ZERO
+    }
+}
@@ -1,6 +1,14 @@
-use anyhow::Result;
-use std::collections::HashSet;
+use crate::contention;
+use crate::reporting::tmp_path;
+use anyhow::{Context, Result};
+use futures::future::join_all;
+use roaring::RoaringBitmap;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::sync::{mpsc, oneshot};
+use utoipa::ToSchema;
 
 /// Message types for transaction registry actor
 pub enum TxRegistryMessage {
@@ -9,41 +17,278 @@ pub enum TxRegistryMessage {
         // true if new, false if duplicate (for duplicate, we reject the transaction)
         reply: oneshot::Sender<bool>, 
     },
+    /// Record registered ids in the registry file, their events are logged
+    Persist {
+        tx_ids: Vec<u32>,
+        reply: oneshot::Sender<()>,
+    },
     Unregister {
         tx_id: u32,
         // true if was present (for duplicate, we reject the transaction)
         reply: oneshot::Sender<bool>, 
     },
+    /// Register an id read back from the event log or a snapshot
+    RegisterReplayed {
+        tx_id: u32,
+        reply: oneshot::Sender<bool>,
+    },
+    /// Register the ids of a run of replayed events in one message, a flag for each in order
+    RegisterReplayedMany {
+        tx_ids: Vec<u32>,
+        reply: oneshot::Sender<Vec<bool>>,
+    },
+    /// Replay is over, ids loaded from the registry file are ordinary registrations from now on
+    FinishReplay,
+    Contains {
+        tx_id: u32,
+        reply: oneshot::Sender<bool>,
+    },
+    /// Every registered id, for engine snapshots
+    Export {
+        reply: oneshot::Sender<Vec<u32>>,
+    },
+    Stats {
+        reply: oneshot::Sender<TxRegistryStats>,
+    },
     Shutdown,
 }
 
+/// Memory held by registered ids
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TxRegistryStats {
+    pub tx_ids: u64,
+    /// Bytes of the compressed sets, ids loaded from the registry file included until replay ends
+    pub bytes: u64,
+    /// Ranges of 65536 ids holding at least one registered id
+    pub containers: u64,
+}
+
+impl TxRegistryStats {
+    fn add(&mut self, other: TxRegistryStats) {
+        self.tx_ids += other.tx_ids;
+        self.bytes += other.bytes;
+        self.containers += other.containers;
+    }
+}
+
+// Serialized size, within a few bytes per container of what the set holds in memory
+fn bitmap_bytes(bitmap: &RoaringBitmap) -> u64 {
+    bitmap.serialized_size() as u64
+}
+
+const REGISTERED: u8 = 1;
+const UNREGISTERED: u8 = 0;
+/// Marker byte then the big-endian tx id
+const RECORD_LEN: usize = 5;
+
+/// Append-only file of one shard's registrations and removals
+struct IdLog {
+    writer: BufWriter<File>,
+}
+
+impl IdLog {
+    /// Load the ids registered in `path`, then rewrite it compacted and keep appending to it
+    async fn open(path: &Path) -> Result<(Self, RoaringBitmap)> {
+        let bytes = match tokio::fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        
+        // A record cut short by a crash is dropped, its event never reached the log either
+        let mut tx_ids = RoaringBitmap::new();
+        for record in bytes.chunks_exact(RECORD_LEN) {
+            let tx_id = u32::from_be_bytes([record[1], record[2], record[3], record[4]]);
+            match record[0] {
+                REGISTERED => tx_ids.insert(tx_id),
+                UNREGISTERED => tx_ids.remove(tx_id),
+                marker => anyhow::bail!("Corrupt record marker {} in {}", marker, path.display()),
+            };
+        }
+        
+        // Without the removals, so rolled back ids don't grow the file run after run
+        let mut compacted = Vec::with_capacity(tx_ids.len() as usize * RECORD_LEN);
+        for tx_id in &tx_ids {
+            compacted.push(REGISTERED);
+            compacted.extend_from_slice(&tx_id.to_be_bytes());
+        }
+        let tmp = tmp_path(path);
+        tokio::fs::write(&tmp, compacted).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        
+        let file = OpenOptions::new().append(true).open(path).await?;
+        Ok((Self { writer: BufWriter::new(file) }, tx_ids))
+    }
+    
+    async fn append(&mut self, marker: u8, tx_id: u32) -> Result<()> {
+        let mut record = [marker, 0, 0, 0, 0];
+        record[1..].copy_from_slice(&tx_id.to_be_bytes());
+        self.writer.write_all(&record).await?;
+        Ok(())
+    }
+}
+
 /// Actor managing a shard of transaction IDs
 pub struct TxRegistryActor {
-    seen_tx_ids: HashSet<u32>,
+    // Compressed, dense runs of ids cost a fraction of a byte each
+    seen_tx_ids: RoaringBitmap,
+    // Loaded from the registry file and not yet met again by replay
+    restored: RoaringBitmap,
+    // Registered but not in the registry file yet, their events may never be logged
+    pending: RoaringBitmap,
+    id_log: Option<IdLog>,
     receiver: mpsc::Receiver<TxRegistryMessage>,
 }
 
 impl TxRegistryActor {
     pub fn new(receiver: mpsc::Receiver<TxRegistryMessage>) -> Self {
         Self {
-            seen_tx_ids: HashSet::new(),
+            seen_tx_ids: RoaringBitmap::new(),
+            restored: RoaringBitmap::new(),
+            pending: RoaringBitmap::new(),
+            id_log: None,
             receiver,
         }
     }
     
+    /// Actor that starts with the ids recorded in `path` and records every change there
+    async fn persistent(receiver: mpsc::Receiver<TxRegistryMessage>, path: &Path) -> Result<Self> {
+        let (id_log, tx_ids) = IdLog::open(path)
+            .await
+            .with_context(|| format!("opening tx registry file {}", path.display()))?;
+        
+        Ok(Self {
+            seen_tx_ids: tx_ids.clone(),
+            restored: tx_ids,
+            pending: RoaringBitmap::new(),
+            id_log: Some(id_log),
+            receiver,
+        })
+    }
+    
     pub async fn run(mut self) {
         while let Some(msg) = self.receiver.recv().await {
             match msg {
                 TxRegistryMessage::Register { tx_id, reply } => {
-                    let is_new = self.seen_tx_ids.insert(tx_id);
-                    let _ = reply.send(is_new);
+                    let _ = reply.send(self.register(tx_id));
+                }
+                TxRegistryMessage::RegisterReplayed { tx_id, reply } => {
+                    // Dropping the reply on a failed write fails the caller's registration
+                    if let Some(is_new) = self.register_replayed(tx_id).await {
+                        let _ = reply.send(is_new);
+                    }
+                }
+                TxRegistryMessage::RegisterReplayedMany { tx_ids, reply } => {
+                    let mut flags = Vec::with_capacity(tx_ids.len());
+                    for &tx_id in &tx_ids {
+                        match self.register_replayed(tx_id).await {
+                            Some(is_new) => flags.push(is_new),
+                            None => break,
+                        }
+                    }
+                    // Short of a flag, the reply is dropped and the whole call fails
+                    if flags.len() == tx_ids.len() {
+                        let _ = reply.send(flags);
+                    }
+                }
+                TxRegistryMessage::FinishReplay => {
+                    self.restored = RoaringBitmap::new();
+                }
+                TxRegistryMessage::Persist { tx_ids, reply } => {
+                    for tx_id in tx_ids {
+                        // A failed write leaves it to replay, which registers the logged id again
+                        if self.pending.remove(tx_id) {
+                            self.record(REGISTERED, tx_id).await;
+                        }
+                    }
+                    let _ = reply.send(());
                 }
                 TxRegistryMessage::Unregister { tx_id, reply } => {
-                    let was_present = self.seen_tx_ids.remove(&tx_id);
+                    let was_present = self.seen_tx_ids.remove(tx_id);
+                    if was_present && !self.pending.remove(tx_id) {
+                        // Left registered in the file if this fails, the id stays refused after a restart
+                        self.record(UNREGISTERED, tx_id).await;
+                    }
                     let _ = reply.send(was_present);
                 }
+                TxRegistryMessage::Contains { tx_id, reply } => {
+                    let _ = reply.send(self.seen_tx_ids.contains(tx_id));
+                }
+                TxRegistryMessage::Export { reply } => {
+                    let _ = reply.send(self.seen_tx_ids.iter().collect());
+                }
+                TxRegistryMessage::Stats { reply } => {
+                    let _ = reply.send(TxRegistryStats {
+                        tx_ids: self.seen_tx_ids.len(),
+                        bytes: bitmap_bytes(&self.seen_tx_ids) + bitmap_bytes(&self.restored),
+                        containers: self.seen_tx_ids.statistics().n_containers as u64,
+                    });
+                }
                 TxRegistryMessage::Shutdown => break,
             }
+            
+            // Records held back while the queue was busy are written once it drains
+            if self.receiver.is_empty() {
+                self.flush().await;
+            }
+        }
+        self.flush().await;
+    }
+    
+    /// Whether `tx_id` is new, it reaches the registry file once its event is logged
+    ///
+    /// Written any earlier, a crash before the event is logged would leave the
+    /// id refused with nothing to show for it.
+    fn register(&mut self, tx_id: u32) -> bool {
+        if !self.seen_tx_ids.insert(tx_id) {
+            return false;
+        }
+        if self.id_log.is_some() {
+            self.pending.insert(tx_id);
+        }
+        true
+    }
+    
+    /// Register an id whose event is logged, `None` if it could not be recorded
+    ///
+    /// Replay meeting an id from the file for the first time isn't a duplicate.
+    async fn register_replayed(&mut self, tx_id: u32) -> Option<bool> {
+        if self.restored.remove(tx_id) {
+            return Some(true);
+        }
+        if !self.seen_tx_ids.insert(tx_id) {
+            return Some(false);
+        }
+        if !self.record(REGISTERED, tx_id).await {
+            self.seen_tx_ids.remove(tx_id);
+            return None;
+        }
+        Some(true)
+    }
+    
+    async fn record(&mut self, marker: u8, tx_id: u32) -> bool {
+        let Some(id_log) = &mut self.id_log else {
+            return true;
+        };
+        let mut written = id_log.append(marker, tx_id).await;
+        // A burst of registrations shares one write, a quiet registry writes each before replying
+        if written.is_ok() && self.receiver.is_empty() {
+            written = id_log.writer.flush().await.map_err(Into::into);
+        }
+        match written {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!(tx_id, error = ?e, "Failed to record tx id in the registry file");
+                false
+            }
+        }
+    }
+    
+    async fn flush(&mut self) {
+        if let Some(id_log) = &mut self.id_log {
+            if let Err(e) = id_log.writer.flush().await {
+                tracing::error!(error = ?e, "Failed to flush the tx registry file");
+            }
         }
     }
 }
@@ -68,6 +313,41 @@ impl TxRegistryHandle {
         Ok(reply_rx.await?)
     }
     
+    pub async fn register_replayed(&self, tx_id: u32) -> Result<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(TxRegistryMessage::RegisterReplayed { tx_id, reply: reply_tx })
+            .await?;
+        
+        Ok(reply_rx.await?)
+    }
+    
+    pub async fn register_replayed_many(&self, tx_ids: Vec<u32>) -> Result<Vec<bool>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(TxRegistryMessage::RegisterReplayedMany { tx_ids, reply: reply_tx })
+            .await?;
+        
+        Ok(reply_rx.await?)
+    }
+    
+    pub async fn finish_replay(&self) -> Result<()> {
+        self.sender.send(TxRegistryMessage::FinishReplay).await?;
+        Ok(())
+    }
+    
+    pub async fn persist(&self, tx_ids: Vec<u32>) -> Result<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(TxRegistryMessage::Persist { tx_ids, reply: reply_tx })
+            .await?;
+        
+        Ok(reply_rx.await?)
+    }
+    
     pub async fn unregister(&self, tx_id: u32) -> Result<bool> {
         let (reply_tx, reply_rx) = oneshot::channel();
         
@@ -77,12 +357,50 @@ impl TxRegistryHandle {
         
         Ok(reply_rx.await?)
     }
+    
+    pub async fn contains(&self, tx_id: u32) -> Result<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(TxRegistryMessage::Contains { tx_id, reply: reply_tx })
+            .await?;
+        
+        Ok(reply_rx.await?)
+    }
+    
+    pub async fn export(&self) -> Result<Vec<u32>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(TxRegistryMessage::Export { reply: reply_tx })
+            .await?;
+        
+        Ok(reply_rx.await?)
+    }
+    
+    /// Stop the shard once it has written its queued records, waiting until it has
+    pub async fn shutdown(&self) {
+        if self.sender.send(TxRegistryMessage::Shutdown).await.is_ok() {
+            self.sender.closed().await;
+        }
+    }
+    
+    pub async fn stats(&self) -> Result<TxRegistryStats> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        
+        self.sender
+            .send(TxRegistryMessage::Stats { reply: reply_tx })
+            .await?;
+        
+        Ok(reply_rx.await?)
+    }
 }
 
 /// Sharded transaction registry with multiple actors for parallel processing
 #[derive(Clone)]
 pub struct ShardedTxRegistry {
     shards: Vec<TxRegistryHandle>,
+    persistent: bool,
 }
 
 impl ShardedTxRegistry {
@@ -94,14 +412,56 @@ impl ShardedTxRegistry {
             let handle = TxRegistryHandle::new(tx);
             let actor = TxRegistryActor::new(rx);
             
-            tokio::spawn(async move {
-                actor.run().await;
-            });
+            contention::spawn(actor.run());
             
             shards.push(handle);
         }
         
-        Self { shards }
+        Self { shards, persistent: false }
+    }
+    
+    /// Registry whose shards keep their ids in `dir`, reloaded from there
+    ///
+    /// Each shard appends to its own file; ids are routed by the shard count,
+    /// so a directory written before keeps the count in its file names, used
+    /// over `num_shards` whatever the engine's own shard count now is.
+    pub async fn persistent(num_shards: usize, dir: &Path) -> Result<Self> {
+        tokio::fs::create_dir_all(dir).await?;
+        let mut found = None;
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some((_, count)) = name.strip_suffix(".ids").and_then(|name| name.rsplit_once("-of-")) else {
+                continue;
+            };
+            let count: usize = count
+                .parse()
+                .with_context(|| format!("{} in {} names no shard count", name, dir.display()))?;
+            match found {
+                Some(found) if found != count => anyhow::bail!(
+                    "{} holds tx registry files of both {} and {} shards",
+                    dir.display(),
+                    found,
+                    count
+                ),
+                _ => found = Some(count),
+            }
+        }
+        let num_shards = found.unwrap_or(num_shards);
+        
+        let mut shards = Vec::new();
+        for shard in 0..num_shards {
+            let (tx, rx) = mpsc::channel(10_000);
+            let actor = TxRegistryActor::persistent(rx, &shard_file(dir, shard, num_shards)).await?;
+            tokio::spawn(actor.run());
+            shards.push(TxRegistryHandle::new(tx));
+        }
+        
+        Ok(Self { shards, persistent: true })
+    }
+    
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
     }
     
     pub async fn register(&self, tx_id: u32) -> Result<bool> {
@@ -110,9 +470,115 @@ impl ShardedTxRegistry {
         self.shards[shard_id].register(tx_id).await
     }
     
+    /// Register an id from the event log or a snapshot, false if replay already met it
+    ///
+    /// An id only known from the registry file counts as new the first time,
+    /// its event is being replayed rather than repeated.
+    pub async fn register_replayed(&self, tx_id: u32) -> Result<bool> {
+        let shard_id = (tx_id as usize) % self.shards.len();
+        self.shards[shard_id].register_replayed(tx_id).await
+    }
+    
+    /// `register_replayed` for a run of ids at once, one message per shard, flags in input order
+    ///
+    /// An id repeated within the run is new at most once, at its first position.
+    pub async fn register_replayed_many(&self, tx_ids: &[u32]) -> Result<Vec<bool>> {
+        let mut by_shard: Vec<(Vec<usize>, Vec<u32>)> = vec![(Vec::new(), Vec::new()); self.shards.len()];
+        for (position, &tx_id) in tx_ids.iter().enumerate() {
+            let (positions, ids) = &mut by_shard[(tx_id as usize) % self.shards.len()];
+            positions.push(position);
+            ids.push(tx_id);
+        }
+        
+        let replies = join_all(
+            self.shards
+                .iter()
+                .zip(by_shard)
+                .filter(|(_, (positions, _))| !positions.is_empty())
+                .map(|(shard, (positions, ids))| async move {
+                    shard.register_replayed_many(ids).await.map(|flags| (positions, flags))
+                }),
+        )
+        .await;
+        
+        let mut flags = vec![false; tx_ids.len()];
+        for reply in replies {
+            let (positions, shard_flags) = reply?;
+            for (position, is_new) in positions.into_iter().zip(shard_flags) {
+                flags[position] = is_new;
+            }
+        }
+        Ok(flags)
+    }
+    
+    /// Write registered ids to the registry files, call once their events are logged
+    ///
+    /// Ids registered and never persisted are gone after a restart, as are
+    /// their events.
+    pub async fn persist(&self, tx_ids: &[u32]) -> Result<()> {
+        if !self.persistent || tx_ids.is_empty() {
+            return Ok(());
+        }
+        
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for &tx_id in tx_ids {
+            by_shard[(tx_id as usize) % self.shards.len()].push(tx_id);
+        }
+        let replies = join_all(
+            self.shards
+                .iter()
+                .zip(by_shard)
+                .filter(|(_, ids)| !ids.is_empty())
+                .map(|(shard, ids)| shard.persist(ids)),
+        )
+        .await;
+        replies.into_iter().collect()
+    }
+    
+    /// Treat ids from the registry file like any other once the log is replayed
+    pub async fn finish_replay(&self) -> Result<()> {
+        for shard in &self.shards {
+            shard.finish_replay().await?;
+        }
+        Ok(())
+    }
+    
     /// Unregister a transaction ID
     pub async fn unregister(&self, tx_id: u32) -> Result<bool> {
         let shard_id = (tx_id as usize) % self.shards.len();
         self.shards[shard_id].unregister(tx_id).await
     }
+    
+    /// Whether a transaction ID is registered, without registering it
+    pub async fn contains(&self, tx_id: u32) -> Result<bool> {
+        let shard_id = (tx_id as usize) % self.shards.len();
+        self.shards[shard_id].contains(tx_id).await
+    }
+    
+    /// Every registered id across all shards, in no particular order
+    pub async fn export(&self) -> Result<Vec<u32>> {
+        let mut tx_ids = Vec::new();
+        for shard in &self.shards {
+            tx_ids.extend(shard.export().await?);
+        }
+        Ok(tx_ids)
+    }
+    
+    /// Stop every shard, their id files flushed
+    pub async fn shutdown(&self) {
+        join_all(self.shards.iter().map(TxRegistryHandle::shutdown)).await;
+    }
+    
+    /// Registered ids and their memory, summed over the shards
+    pub async fn stats(&self) -> Result<TxRegistryStats> {
+        let mut stats = TxRegistryStats::default();
+        for shard in &self.shards {
+            stats.add(shard.stats().await?);
+        }
+        Ok(stats)
+    }
+}
+
+fn shard_file(dir: &Path, shard: usize, num_shards: usize) -> PathBuf {
+    dir.join(format!("tx-registry-{}-of-{}.ids", shard, num_shards))
 }
@@ -0,0 +1,506 @@
+use crate::amount::{parse_amount, AmountFormat, AmountUnits};
+use crate::csv_io::{stream_json_transactions, stream_transactions_with, write_account_stream};
+use crate::errors::ProcessingError;
+use crate::models::{AccountOutput, TransactionRow, TransactionType};
+use crate::schema::CsvSchema;
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_util::bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, FramedRead};
+use utoipa::ToSchema;
+
+/// Byte source of one connection
+pub type WireReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Byte sink of one connection
+pub type WireWriter = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Accounts of a summary in client order, produced while they are written
+pub type AccountStream<'a> = BoxStream<'a, AccountOutput>;
+
+/// Framing of rows sent to the server and of the account summary sent back
+#[async_trait]
+pub trait WireCodec: Send + Sync {
+    /// Stream the rows a client sends, malformed frames surface as errors
+    fn decode_rows(&self, reader: WireReader) -> BoxStream<'static, Result<TransactionRow>>;
+
+    /// Write the final account summary, pulling accounts only as fast as the writer takes them
+    async fn write_accounts(&self, writer: WireWriter, accounts: AccountStream<'_>) -> Result<()>;
+
+    /// Write the outcome of one row, flushing is left to the caller
+    async fn write_ack(&self, writer: &mut WireWriter, ack: &Ack) -> Result<()>;
+}
+
+/// Line a client may send before any row to choose the protocol, followed by its name
+pub const PROTOCOL_HEADER: &str = "#protocol ";
+
+/// Line a client may send before any row to name its format instead of having it sniffed
+pub const FORMAT_HEADER: &str = "#format ";
+
+/// Line a client may send before any row to send amounts as `decimal` or `minor:<exponent>`
+pub const AMOUNTS_HEADER: &str = "#amounts ";
+
+/// Line a client may send before any row to name the feed in reports, its address otherwise
+pub const SOURCE_HEADER: &str = "#source ";
+
+/// Line a client may send before any row to choose `arrival` or `sequenced` row ordering
+pub const ORDERING_HEADER: &str = "#ordering ";
+
+/// Line a client sends before any row to present its API key, when the server requires one
+pub const API_KEY_HEADER: &str = "#api-key ";
+
+/// First line a client may send to learn what the server supports, followed by the newest protocol version it speaks
+///
+/// The server answers it right away with one `#hello` line of its own, see
+/// `Capabilities::hello_line`, before reading further. Servers predating the
+/// handshake refuse the line as an unknown header and close the connection, so
+/// a client can reconnect without it and keep to version 1 features.
+pub const HELLO_HEADER: &str = "#hello ";
+
+/// Wire protocol version this server speaks
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What a server supports, sent in answer to `#hello` and served at `GET /capabilities`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Capabilities {
+    pub engine_version: String,
+    pub protocol_version: u32,
+    pub formats: Vec<String>,
+    pub protocols: Vec<String>,
+    pub orderings: Vec<String>,
+    /// `minor` stands for every `minor:<exponent>`
+    pub amounts: Vec<String>,
+    /// Optional parts this build or server run has, e.g. `spill`, `intake_log`, `rocksdb`
+    pub features: Vec<String>,
+}
+
+impl Capabilities {
+    /// What every server of this build supports, without the features a run enables
+    pub fn builtin() -> Self {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let mut features = Vec::new();
+        if cfg!(feature = "rocksdb") {
+            features.push("rocksdb".to_string());
+        }
+        if cfg!(feature = "object-store") {
+            features.push("object_store".to_string());
+        }
+        if cfg!(feature = "kafka") {
+            features.push("kafka".to_string());
+        }
+        Self {
+            engine_version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            formats: names(&["csv", "json", "msgpack"]),
+            protocols: names(&["batch", "ack"]),
+            orderings: names(&["arrival", "sequenced"]),
+            amounts: names(&["decimal", "minor"]),
+            features,
+        }
+    }
+
+    pub fn with_feature(mut self, name: &str) -> Self {
+        self.features.push(name.to_string());
+        self
+    }
+
+    /// Answer to a client's `#hello`, naming the version both sides speak
+    ///
+    /// `#hello 1 engine=0.1.0 formats=csv,json,msgpack protocols=batch,ack ...`,
+    /// a text line whatever format the rows come in.
+    pub fn hello_line(&self, version: u32) -> String {
+        format!(
+            "{}{} engine={} formats={} protocols={} orderings={} amounts={} features={}\n",
+            HELLO_HEADER,
+            version,
+            self.engine_version,
+            self.formats.join(","),
+            self.protocols.join(","),
+            self.orderings.join(","),
+            self.amounts.join(","),
+            self.features.join(",")
+        )
+    }
+}
+
+/// Exchange pattern of one connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Rows in, then the account summary once the client stops sending
+    #[default]
+    Batch,
+    /// One ack per row, in the order rows were sent, and no summary
+    Ack,
+}
+
+impl Protocol {
+    /// Consume the protocol header if the client sent one, `Batch` otherwise
+    pub async fn negotiate<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Self> {
+        Ok(ConnectionHeader::read(reader).await?.protocol)
+    }
+}
+
+/// Order a connection's rows are applied in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RowOrdering {
+    /// As they arrive, interleaved with other connections' rows for the same client
+    #[default]
+    Arrival,
+    /// By the `seq` column the producer numbers each client's rows with, across connections
+    Sequenced,
+}
+
+/// Header lines a client sent before its first row, in any order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionHeader {
+    pub protocol: Protocol,
+    /// `None` leaves the format to be sniffed from the first row
+    pub format: Option<WireFormat>,
+    /// Units of amounts in rows and in the account summary
+    pub units: AmountUnits,
+    /// Name of the feed, `None` leaves it to the peer address
+    pub source: Option<String>,
+    pub ordering: RowOrdering,
+    /// Protocol version agreed in a `#hello` exchange, `None` for clients that skipped it
+    pub version: Option<u32>,
+    /// Key the connection's rows are submitted under, see `auth::ApiKeys`
+    pub api_key: Option<String>,
+}
+
+impl ConnectionHeader {
+    /// Consume every header line, stopping at the first row
+    pub async fn read<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Self> {
+        Self::read_answering(reader, &mut tokio::io::sink(), &Capabilities::builtin()).await
+    }
+
+    /// Consume every header line, answering a `#hello` on `writer` as soon as it is read
+    ///
+    /// The client may wait for the answer before sending anything else, so it
+    /// is flushed before the next line is read.
+    pub async fn read_answering<R, W>(reader: &mut R, writer: &mut W, capabilities: &Capabilities) -> Result<Self>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut header = Self::default();
+        let mut first = true;
+
+        // No format's rows start with `#`, so one byte tells whether a header follows
+        while reader.fill_buf().await?.first() == Some(&b'#') {
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            let line = line.trim_end();
+
+            if let Some(version) = line.strip_prefix(HELLO_HEADER) {
+                // Anything sent before it was sent without knowing what the server supports
+                if !first {
+                    anyhow::bail!("'{}' must be the first header line", line);
+                }
+                let version: u32 = match version.parse() {
+                    Ok(version) if version > 0 => version,
+                    _ => anyhow::bail!("Unknown hello header '{}'", line),
+                };
+                let version = version.min(capabilities.protocol_version);
+                writer.write_all(capabilities.hello_line(version).as_bytes()).await?;
+                writer.flush().await?;
+                header.version = Some(version);
+            } else if let Some(name) = line.strip_prefix(PROTOCOL_HEADER) {
+                header.protocol = match name {
+                    "batch" => Protocol::Batch,
+                    "ack" => Protocol::Ack,
+                    _ => anyhow::bail!("Unknown protocol header '{}'", line),
+                };
+            } else if let Some(name) = line.strip_prefix(FORMAT_HEADER) {
+                header.format = Some(name.parse()?);
+            } else if let Some(units) = line.strip_prefix(AMOUNTS_HEADER) {
+                header.units = units.parse()?;
+            } else if let Some(source) = line.strip_prefix(SOURCE_HEADER) {
+                header.source = Some(source.to_string());
+            } else if let Some(name) = line.strip_prefix(ORDERING_HEADER) {
+                header.ordering = match name {
+                    "arrival" => RowOrdering::Arrival,
+                    "sequenced" => RowOrdering::Sequenced,
+                    _ => anyhow::bail!("Unknown ordering header '{}'", line),
+                };
+            } else if let Some(key) = line.strip_prefix(API_KEY_HEADER) {
+                header.api_key = Some(key.to_string());
+            } else {
+                anyhow::bail!("Unknown header '{}'", line);
+            }
+            first = false;
+        }
+
+        Ok(header)
+    }
+}
+
+/// Outcome of one row sent back in `Protocol::Ack`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Ack {
+    /// `None` when the row could not be decoded
+    pub tx: Option<u32>,
+    /// `ok`, `malformed`, or the rejection's error code
+    pub code: &'static str,
+    /// How long to wait before sending the row again, for `engine_busy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl Ack {
+    pub fn new(tx: u32, result: &Result<(), ProcessingError>) -> Self {
+        Self {
+            tx: Some(tx),
+            code: match result {
+                Ok(()) => "ok",
+                Err(e) => e.code(),
+            },
+            retry_after_ms: match result {
+                Err(ProcessingError::EngineBusy { retry_after_ms }) => Some(*retry_after_ms),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn malformed() -> Self {
+        Self {
+            tx: None,
+            code: "malformed",
+            retry_after_ms: None,
+        }
+    }
+}
+
+/// Wire formats understood by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    /// Header line then `type,client,tx,amount` lines
+    Csv,
+    /// One JSON object per line
+    Json,
+    /// Back-to-back MessagePack maps
+    MessagePack,
+}
+
+impl FromStr for WireFormat {
+    type Err = anyhow::Error;
+
+    /// `csv`, `json` (or `jsonl`) or `msgpack`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(WireFormat::Csv),
+            "json" | "jsonl" => Ok(WireFormat::Json),
+            "msgpack" => Ok(WireFormat::MessagePack),
+            other => anyhow::bail!("Unknown format '{}', expected csv, json or msgpack", other),
+        }
+    }
+}
+
+impl WireFormat {
+    /// Pick the format from the first byte a client sends
+    ///
+    /// JSON rows open with `{`, MessagePack rows with a map marker, anything
+    /// else (including an empty connection) is treated as CSV.
+    pub fn detect(first_byte: Option<u8>) -> Self {
+        match first_byte {
+            Some(b'{') => WireFormat::Json,
+            Some(0x80..=0x8f | 0xde | 0xdf) => WireFormat::MessagePack,
+            _ => WireFormat::Csv,
+        }
+    }
+
+    /// Peek at a buffered reader without consuming anything
+    pub async fn sniff<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Self> {
+        let buf = reader.fill_buf().await?;
+        Ok(Self::detect(buf.first().copied()))
+    }
+
+    /// Codec for this format with plain decimal amounts
+    pub fn codec(self) -> Box<dyn WireCodec> {
+        self.codec_in(AmountUnits::Decimal, AmountFormat::default())
+    }
+
+    /// Codec for this format reading and writing amounts in `units`, spelled as `amounts` allows
+    pub fn codec_in(self, units: AmountUnits, amounts: AmountFormat) -> Box<dyn WireCodec> {
+        match self {
+            WireFormat::Csv => Box::new(CsvCodec { units, amounts }),
+            WireFormat::Json => Box::new(JsonCodec { units, amounts }),
+            WireFormat::MessagePack => Box::new(MessagePackCodec { units, amounts }),
+        }
+    }
+}
+
+/// Rows with their amounts converted from `units` to decimal
+fn decode_amounts<S>(rows: S, units: AmountUnits) -> BoxStream<'static, Result<TransactionRow>>
+where
+    S: futures::Stream<Item = Result<TransactionRow>> + Send + 'static,
+{
+    rows.map(move |row| Ok(units.decode_row(row?)?)).boxed()
+}
+
+pub struct CsvCodec {
+    units: AmountUnits,
+    amounts: AmountFormat,
+}
+
+#[async_trait]
+impl WireCodec for CsvCodec {
+    fn decode_rows(&self, reader: WireReader) -> BoxStream<'static, Result<TransactionRow>> {
+        decode_amounts(stream_transactions_with(reader, CsvSchema::default(), self.amounts), self.units)
+    }
+
+    async fn write_accounts(&self, writer: WireWriter, accounts: AccountStream<'_>) -> Result<()> {
+        write_account_stream(writer, accounts, self.units).await
+    }
+
+    async fn write_ack(&self, writer: &mut WireWriter, ack: &Ack) -> Result<()> {
+        let tx = ack.tx.map(|tx| tx.to_string()).unwrap_or_default();
+        let line = match ack.retry_after_ms {
+            Some(retry_after_ms) => format!("{},{},{}\n", tx, ack.code, retry_after_ms),
+            None => format!("{},{}\n", tx, ack.code),
+        };
+        writer.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+pub struct JsonCodec {
+    units: AmountUnits,
+    amounts: AmountFormat,
+}
+
+#[async_trait]
+impl WireCodec for JsonCodec {
+    fn decode_rows(&self, reader: WireReader) -> BoxStream<'static, Result<TransactionRow>> {
+        decode_amounts(stream_json_transactions(reader, self.amounts), self.units)
+    }
+
+    async fn write_accounts(&self, mut writer: WireWriter, mut accounts: AccountStream<'_>) -> Result<()> {
+        while let Some(account) = accounts.next().await {
+            let mut line = serde_json::to_vec(&self.units.encode_account(account))?;
+            line.push(b'\n');
+            writer.write_all(&line).await?;
+        }
+
+        writer.flush().await?;
+        Ok(())
+    }
+
+    async fn write_ack(&self, writer: &mut WireWriter, ack: &Ack) -> Result<()> {
+        let mut line = serde_json::to_vec(ack)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        Ok(())
+    }
+}
+
+pub struct MessagePackCodec {
+    units: AmountUnits,
+    amounts: AmountFormat,
+}
+
+#[async_trait]
+impl WireCodec for MessagePackCodec {
+    fn decode_rows(&self, reader: WireReader) -> BoxStream<'static, Result<TransactionRow>> {
+        decode_amounts(FramedRead::new(reader, MessagePackDecoder { amounts: self.amounts }), self.units)
+    }
+
+    async fn write_accounts(&self, mut writer: WireWriter, mut accounts: AccountStream<'_>) -> Result<()> {
+        while let Some(account) = accounts.next().await {
+            writer.write_all(&encode_msgpack(&self.units.encode_account(account))?).await?;
+        }
+
+        writer.flush().await?;
+        Ok(())
+    }
+
+    async fn write_ack(&self, writer: &mut WireWriter, ack: &Ack) -> Result<()> {
+        writer.write_all(&encode_msgpack(ack)?).await?;
+        Ok(())
+    }
+}
+
+/// Encode one value as a MessagePack map with named fields
+pub fn encode_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec_named(value)?)
+}
+
+/// Splits a byte stream into MessagePack values, waiting for more input on a partial one
+struct MessagePackDecoder {
+    amounts: AmountFormat,
+}
+
+/// A row as MessagePack carries it, a text amount not yet read under the connection's format
+#[derive(Deserialize)]
+struct MessagePackRow {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    #[serde(default)]
+    amount: Option<SentAmount>,
+    #[serde(default)]
+    to: Option<u16>,
+}
+
+/// Amount as sent: text, or a number taken as it is
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SentAmount {
+    Text(String),
+    Integer(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+impl MessagePackRow {
+    fn into_row(self, format: AmountFormat) -> Result<TransactionRow> {
+        let amount = match self.amount {
+            None => None,
+            Some(SentAmount::Text(text)) => Some(parse_amount(&text, format)?),
+            Some(SentAmount::Integer(value)) => Some(Decimal::from(value)),
+            Some(SentAmount::Unsigned(value)) => Some(Decimal::from(value)),
+            Some(SentAmount::Float(value)) => Some(Decimal::try_from(value)?),
+        };
+        Ok(TransactionRow { tx_type: self.tx_type, client: self.client, tx: self.tx, amount, to: self.to })
+    }
+}
+
+impl Decoder for MessagePackDecoder {
+    type Item = TransactionRow;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<TransactionRow>> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+
+        let mut cursor = Cursor::new(&src[..]);
+        match rmp_serde::from_read::<_, MessagePackRow>(&mut cursor) {
+            Ok(row) => {
+                let consumed = cursor.position() as usize;
+                src.advance(consumed);
+                row.into_row(self.amounts).map(Some)
+            }
+            Err(e) if is_truncated(&e) => Ok(None),
+            // A bad value can't be skipped reliably, the connection is done
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn is_truncated(error: &rmp_serde::decode::Error) -> bool {
+    use rmp_serde::decode::Error;
+
+    match error {
+        Error::InvalidMarkerRead(e) | Error::InvalidDataRead(e) => {
+            e.kind() == std::io::ErrorKind::UnexpectedEof
+        }
+        _ => false,
+    }
+}
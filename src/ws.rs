@@ -0,0 +1,175 @@
+use crate::auth::{ClientScope, API_KEY_HEADER};
+use crate::models::{Account, AccountOutput, TransactionRow};
+use crate::scalable_engine::ScalableEngine;
+use crate::server::settle_live_row;
+use crate::wire::Ack;
+use axum::extract::connect_info::ConnectInfo;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+/// What a client sends, one JSON object per text message
+///
+/// `{"op": "submit", "type": "deposit", "client": 1, "tx": 7, "amount": "2.5"}`
+/// submits a row, the row's fields as in JSON Lines input.
+/// `{"op": "subscribe", "clients": [1, 2]}` and `unsubscribe` change which
+/// clients' account states are pushed.
+#[derive(Debug, Deserialize)]
+struct ClientMessage {
+    op: String,
+    #[serde(default)]
+    clients: Vec<u16>,
+}
+
+/// What the server pushes, one JSON object per text message
+#[derive(Debug, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ServerMessage {
+    /// Outcome of a submitted row, `tx` is left out when it couldn't be decoded
+    Ack(Ack),
+    /// Clients now pushed, after a subscribe or unsubscribe
+    Subscribed { clients: Vec<u16> },
+    /// A subscribed account's state, on subscribing and after every change to it
+    Account(AccountOutput),
+    /// This many updates were dropped for falling behind, re-subscribe for current states
+    Lagged { missed: u64 },
+    /// A message that isn't one of the ops
+    Error { message: String },
+}
+
+/// Live connection: submit transactions and get subscribed accounts pushed as they change
+///
+/// Submissions take the same live path as a wire connection's rows and are
+/// acked in order, those for clients the `x-api-key` header's key doesn't
+/// cover are refused as unauthorized. An account's state is pushed first when it is subscribed
+/// to, then each time an actor applies a change to it.
+#[utoipa::path(get, path = "/ws", tag = "transactions", responses((status = 101, description = "Switched to the WebSocket protocol")))]
+pub(crate) async fn stream(
+    State(engine): State<Arc<ScalableEngine>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let key = headers.get(API_KEY_HEADER).and_then(|key| key.to_str().ok());
+    let scope = engine.access(key);
+    // Duplicates are counted under the peer, as for the other front ends
+    let source = peer
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "ws".to_string());
+    upgrade.on_upgrade(move |socket| session(engine, source, scope, socket))
+}
+
+async fn session(engine: Arc<ScalableEngine>, source: String, scope: ClientScope, mut socket: WebSocket) {
+    let mut subscribed = BTreeSet::new();
+    // Only taken once something is subscribed, so idle sessions don't hold the channel
+    let mut updates: Option<Receiver<Account>> = None;
+
+    loop {
+        let outgoing = tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum, binary frames carry nothing here
+                    Some(Ok(_)) => continue,
+                };
+                handle(&engine, &source, &scope, &text, &mut subscribed, &mut updates).await
+            }
+            update = next_update(&mut updates) => match update {
+                Ok(account) if subscribed.contains(&account.client) => vec![ServerMessage::Account(AccountOutput::from(&account))],
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => vec![ServerMessage::Lagged { missed }],
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        for message in outgoing {
+            let Ok(text) = serde_json::to_string(&message) else { continue };
+            if socket.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Next published state, pending forever while nothing is subscribed
+async fn next_update(updates: &mut Option<Receiver<Account>>) -> Result<Account, RecvError> {
+    match updates {
+        Some(updates) => updates.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn handle(
+    engine: &ScalableEngine,
+    source: &str,
+    scope: &ClientScope,
+    text: &str,
+    subscribed: &mut BTreeSet<u16>,
+    updates: &mut Option<Receiver<Account>>,
+) -> Vec<ServerMessage> {
+    let message: ClientMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => return vec![ServerMessage::Error { message: e.to_string() }],
+    };
+
+    match message.op.as_str() {
+        "submit" => {
+            // The row's own fields sit next to `op`, which it ignores
+            let Ok(row) = crate::csv_io::parse_json_row(text, engine.amount_format()) else {
+                return vec![ServerMessage::Ack(Ack::malformed())];
+            };
+            if let Err(e) = scope.check(&row) {
+                return vec![ServerMessage::Ack(Ack::new(row.tx, &Err(e)))];
+            }
+            // Nothing live may land before the backfill's rows
+            if let Err(e) = engine.ingestion().wait_live().await {
+                return vec![ServerMessage::Error { message: e.to_string() }];
+            }
+            if let Err(busy) = engine.wait_for_capacity().await {
+                return vec![ServerMessage::Ack(Ack::new(row.tx, &Err(busy)))];
+            }
+            vec![ServerMessage::Ack(submit(engine, source, row).await)]
+        }
+        "subscribe" => {
+            // Subscribe before reading, a change in between is pushed rather than missed
+            if updates.is_none() {
+                *updates = Some(engine.subscribe_accounts());
+            }
+            subscribed.extend(message.clients.iter().copied());
+
+            let mut replies = vec![ServerMessage::Subscribed { clients: subscribed.iter().copied().collect() }];
+            for client in message.clients {
+                if let Some(account) = engine.get_account(client).await {
+                    replies.push(ServerMessage::Account(AccountOutput::from(&account)));
+                }
+            }
+            replies
+        }
+        "unsubscribe" => {
+            for client in &message.clients {
+                subscribed.remove(client);
+            }
+            if subscribed.is_empty() {
+                *updates = None;
+            }
+            vec![ServerMessage::Subscribed { clients: subscribed.iter().copied().collect() }]
+        }
+        op => vec![ServerMessage::Error { message: format!("unknown op '{}'", op) }],
+    }
+}
+
+/// Apply a row the way a wire connection would and ack it
+async fn submit(engine: &ScalableEngine, source: &str, row: TransactionRow) -> Ack {
+    engine.prefetch(std::slice::from_ref(&row)).await;
+    let outcome = engine.process(row.clone()).await;
+    let settled = settle_live_row(engine, source, &row, outcome).await;
+    Ack::new(row.tx, &settled)
+}
@@ -0,0 +1,159 @@
+use payments_engine::storage::{StorageTier, TransactionStore};
+use payments_engine::test_support::{chargeback, deposit, dispute, resolve, withdrawal, ActorHarness};
+use payments_engine::ProcessingError;
+use rust_decimal_macros::dec;
+use std::time::Duration;
+
+// ============================================================================
+// DISPUTE STATE TRANSITION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_dispute_resolve_cycle() {
+    let actor = ActorHarness::new(1);
+
+    actor.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    actor.process(dispute(1, 1)).await.unwrap();
+
+    let account = actor.state().await;
+    assert_eq!(account.available, dec!(0.0));
+    assert_eq!(account.held, dec!(10.0));
+
+    assert!(matches!(
+        actor.process(dispute(1, 1)).await,
+        Err(ProcessingError::AlreadyDisputed)
+    ));
+
+    actor.process(resolve(1, 1)).await.unwrap();
+    let account = actor.state().await;
+    assert_eq!(account.available, dec!(10.0));
+    assert_eq!(account.held, dec!(0.0));
+}
+
+#[tokio::test]
+async fn test_chargeback_locks_account() {
+    let actor = ActorHarness::new(1);
+
+    actor.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    actor.process(dispute(1, 1)).await.unwrap();
+    actor.process(chargeback(1, 1)).await.unwrap();
+
+    let account = actor.state().await;
+    assert!(account.locked);
+    assert_eq!(account.total(), dec!(0.0));
+    assert!(matches!(
+        actor.process(withdrawal(1, 2, dec!(1.0))).await,
+        Err(ProcessingError::AccountLocked)
+    ));
+}
+
+// ============================================================================
+// MANUAL CLOCK TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_old_transactions_migrate_and_stay_disputable() {
+    let actor = ActorHarness::new(1);
+
+    actor.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+
+    // Nothing is old enough yet
+    assert!(!actor.migrate_cold().await);
+    assert_eq!(actor.inspect(1).await.unwrap().1, StorageTier::Hot);
+
+    actor.clock().advance(Duration::from_secs(91 * 24 * 3600));
+    actor.process(deposit(1, 2, dec!(5.0))).await.unwrap();
+    assert!(actor.migrate_cold().await);
+
+    assert_eq!(actor.inspect(1).await.unwrap().1, StorageTier::Cold);
+    assert_eq!(actor.inspect(2).await.unwrap().1, StorageTier::Hot);
+    assert!(actor.cold_storage().get(1).await.is_some());
+
+    actor.process(dispute(1, 1)).await.unwrap();
+    assert_eq!(actor.state().await.held, dec!(10.0));
+}
+
+#[tokio::test]
+async fn test_forced_migration_drains_every_batch() {
+    use payments_engine::migration::MigrationConfig;
+
+    let config = MigrationConfig {
+        batch_size: 2,
+        ..MigrationConfig::default()
+    };
+    let actor = ActorHarness::with_migration_config(1, config);
+
+    for tx in 1..=5 {
+        actor.process(deposit(1, tx, dec!(1.0))).await.unwrap();
+    }
+    actor.clock().advance(Duration::from_secs(60));
+    assert_eq!(actor.hot_transactions().await[0].age_secs, 60);
+
+    // Nothing is past the hot window, forcing moves it all anyway
+    assert!(actor.force_migrate_cold().await);
+    for tx in 1..=5 {
+        assert_eq!(actor.inspect(tx).await.unwrap().1, StorageTier::Cold);
+    }
+
+    // Later passes go back to the hot window
+    actor.process(deposit(1, 6, dec!(1.0))).await.unwrap();
+    assert!(!actor.migrate_cold().await);
+    assert_eq!(actor.inspect(6).await.unwrap().1, StorageTier::Hot);
+}
+
+#[tokio::test]
+async fn test_low_risk_client_migrates_ahead_of_hot_window() {
+    use payments_engine::migration::MigrationConfig;
+
+    let config = MigrationConfig {
+        low_risk_hot_days: Some(0),
+        low_risk_max_dispute_rate: 0.25,
+        low_risk_min_transactions: 4,
+        ..MigrationConfig::default()
+    };
+    let quiet = ActorHarness::with_migration_config(1, config.clone());
+    let disputing = ActorHarness::with_migration_config(2, config);
+
+    for tx in 1..=4 {
+        quiet.process(deposit(1, tx, dec!(1.0))).await.unwrap();
+        disputing.process(deposit(2, 10 + tx, dec!(1.0))).await.unwrap();
+    }
+    disputing.process(dispute(2, 11)).await.unwrap();
+    disputing.process(resolve(2, 11)).await.unwrap();
+    disputing.process(dispute(2, 12)).await.unwrap();
+    quiet.clock().advance(Duration::from_secs(60));
+    disputing.clock().advance(Duration::from_secs(60));
+
+    // No dispute in four transactions: nothing waits out the 90 days
+    assert!(quiet.migrate_cold().await);
+    for tx in 1..=4 {
+        assert_eq!(quiet.inspect(tx).await.unwrap().1, StorageTier::Cold);
+    }
+    assert_eq!(quiet.migration_metrics().early, 4);
+
+    // Still disputable, from cold storage
+    quiet.process(dispute(1, 1)).await.unwrap();
+    assert_eq!(quiet.state().await.held, dec!(1.0));
+
+    // Two disputes in four transactions is above the threshold
+    assert!(!disputing.migrate_cold().await);
+    assert_eq!(disputing.inspect(13).await.unwrap().1, StorageTier::Hot);
+}
+
+#[tokio::test]
+async fn test_new_client_keeps_hot_window() {
+    use payments_engine::migration::MigrationConfig;
+
+    let config = MigrationConfig {
+        low_risk_hot_days: Some(0),
+        low_risk_min_transactions: 10,
+        ..MigrationConfig::default()
+    };
+    let actor = ActorHarness::with_migration_config(1, config);
+
+    // Too few transactions to tell its dispute rate
+    actor.process(deposit(1, 1, dec!(1.0))).await.unwrap();
+    actor.clock().advance(Duration::from_secs(60));
+    assert!(!actor.migrate_cold().await);
+    assert_eq!(actor.inspect(1).await.unwrap().1, StorageTier::Hot);
+}
@@ -4,6 +4,12 @@ use rust_decimal_macros::dec;
 use std::sync::Arc;
 use tempfile::TempDir;
 
+/// Events in a log as replay sees them, whatever its format
+async fn logged_events(log_path: &std::path::Path) -> Vec<TransactionRow> {
+    let event_store = payments_engine::event_store::EventStore::new(log_path.to_path_buf(), Default::default()).await.unwrap();
+    event_store.replay().await.unwrap()
+}
+
 // ============================================================================
 // EVENT STORE & PERSISTENCE TESTS
 // ============================================================================
@@ -23,6 +29,7 @@ async fn test_event_store_persistence_and_recovery() {
             client: 1,
             tx: 1,
             amount: Some(dec!(100.0)),
+            to: None,
         }).await.unwrap();
         
         engine.process(TransactionRow {
@@ -30,6 +37,7 @@ async fn test_event_store_persistence_and_recovery() {
             client: 1,
             tx: 2,
             amount: Some(dec!(30.0)),
+            to: None,
         }).await.unwrap();
         
         let accounts = engine.get_accounts().await;
@@ -73,6 +81,7 @@ async fn test_parallel_processing_different_clients() {
                     client: client_id,
                     tx: (client_id as u32) * 1000 + tx_id,
                     amount: Some(dec!(1.0)),
+                    to: None,
                 }).await;
             }
         });
@@ -111,6 +120,7 @@ async fn test_actor_isolation() {
         client: 1,
         tx: 1,
         amount: Some(dec!(100.0)),
+        to: None,
     }).await.unwrap();
     
     // Process for client 2
@@ -119,6 +129,7 @@ async fn test_actor_isolation() {
         client: 2,
         tx: 2,
         amount: Some(dec!(200.0)),
+        to: None,
     }).await.unwrap();
     
     // Dispute for client 1 shouldn't affect client 2
@@ -127,6 +138,7 @@ async fn test_actor_isolation() {
         client: 1,
         tx: 1,
         amount: None,
+        to: None,
     }).await.unwrap();
     
     let accounts = engine.get_accounts().await;
@@ -160,6 +172,7 @@ async fn test_duplicate_transaction_rejection() {
         client: 1,
         tx: 100,
         amount: Some(dec!(50.0)),
+        to: None,
     }).await.unwrap();
     
     // Duplicate deposit with same tx ID - should be rejected
@@ -168,6 +181,7 @@ async fn test_duplicate_transaction_rejection() {
         client: 1,
         tx: 100,
         amount: Some(dec!(75.0)),
+        to: None,
     }).await;
     
     assert!(result.is_err());
@@ -177,6 +191,44 @@ async fn test_duplicate_transaction_rejection() {
     assert_eq!(account.available, dec!(50.0));
 }
 
+#[tokio::test]
+async fn test_unreachable_tx_registry_is_an_engine_failure() {
+    use payments_engine::ProcessingError;
+    use payments_engine::test_support::deposit;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = ScalableEngine::new(temp_dir.path().join("registry.log"), 4, Arc::new(InMemoryStore::new()))
+        .await
+        .unwrap();
+    engine.shutdown().await.unwrap();
+
+    // The row itself is fine, the caller is told to retry rather than fix it
+    let row = deposit(1, 1, dec!(1.0));
+    let result = engine.validate(&row).await;
+    assert!(matches!(result, Err(ProcessingError::ActorCommunicationError)));
+    assert!(result.unwrap_err().is_transient());
+    assert!(matches!(engine.process(row).await, Err(ProcessingError::ActorCommunicationError)));
+}
+
+#[tokio::test]
+async fn test_tx_registry_stays_compact_for_dense_ids() {
+    use payments_engine::tx_registry_actor::ShardedTxRegistry;
+    
+    let registry = ShardedTxRegistry::new(4);
+    for tx_id in 1..=100_000 {
+        assert!(registry.register(tx_id).await.unwrap());
+    }
+    assert!(!registry.register(7).await.unwrap());
+    assert!(registry.unregister(7).await.unwrap());
+    assert!(!registry.contains(7).await.unwrap());
+    
+    let stats = registry.stats().await.unwrap();
+    assert_eq!(stats.tx_ids, 99_999);
+    // A HashSet would need tens of bytes per id
+    assert!(stats.bytes < stats.tx_ids, "{} bytes for {} ids", stats.bytes, stats.tx_ids);
+    assert_eq!(stats.containers, 4 * 2);
+}
+
 // ============================================================================
 // INTEGRATION TEST: NEGATIVE BALANCE HANDLING
 // ============================================================================
@@ -195,6 +247,7 @@ async fn test_negative_balance_from_dispute() {
         client: 1,
         tx: 1,
         amount: Some(dec!(100.0)),
+        to: None,
     }).await.unwrap();
     
     engine.process(TransactionRow {
@@ -202,6 +255,7 @@ async fn test_negative_balance_from_dispute() {
         client: 1,
         tx: 2,
         amount: Some(dec!(60.0)),
+        to: None,
     }).await.unwrap();
     
     // Full dispute allowed - available can go negative
@@ -210,6 +264,7 @@ async fn test_negative_balance_from_dispute() {
         client: 1,
         tx: 1,
         amount: None,
+        to: None,
     }).await;
     
     assert!(result.is_ok());
@@ -220,3 +275,3534 @@ async fn test_negative_balance_from_dispute() {
     assert_eq!(account.held, dec!(100.0));       // Full dispute amount held
     assert_eq!(account.available + account.held, dec!(40.0));  // Invariant maintained
 }
+
+// ============================================================================
+// TRANSACTION TRACING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_trace_transaction_dispute_lifecycle() {
+    use payments_engine::storage::StorageTier;
+    use payments_engine::trace::DisputeState;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("trace.log");
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    
+    for (tx_type, tx, amount) in [
+        (TransactionType::Deposit, 1, Some(dec!(100.0))),
+        (TransactionType::Deposit, 2, Some(dec!(5.0))),
+        (TransactionType::Dispute, 1, None),
+        (TransactionType::Resolve, 1, None),
+    ] {
+        engine.process(TransactionRow { tx_type, client: 1, tx, amount, to: None }).await.unwrap();
+    }
+    
+    let trace = engine.trace_transaction(1).await.unwrap();
+    
+    assert_eq!(trace.events.len(), 3);
+    assert_eq!(trace.events[0].seq, 1);
+    assert_eq!(trace.events[0].available_delta, dec!(100.0));
+    assert_eq!(trace.events[1].seq, 3);
+    assert_eq!(trace.events[1].available_delta, dec!(-100.0));
+    assert_eq!(trace.events[1].held_delta, dec!(100.0));
+    assert_eq!(trace.events[2].held_delta, dec!(-100.0));
+    assert!(trace.events.iter().all(|e| e.outcome == "applied"));
+    assert!(trace.events.iter().all(|e| !e.checks.is_empty()));
+    assert!(trace.events.iter().flat_map(|e| &e.checks).all(|c| c.outcome == "passed"));
+    assert!(trace.events[1].checks.iter().any(|c| c.check == "dispute_target"));
+    assert_eq!(trace.dispute_state, DisputeState::Resolved);
+    assert_eq!(trace.storage_tier, Some(StorageTier::Hot));
+    
+    // Unknown transaction has no history
+    let trace = engine.trace_transaction(999).await.unwrap();
+    assert!(trace.events.is_empty());
+    assert_eq!(trace.storage_tier, None);
+}
+
+#[tokio::test]
+async fn test_trace_replays_transfer_counterparties() {
+    use payments_engine::test_support::{deposit, transfer, withdrawal};
+
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("trace.log"), 4, cold_storage).await.unwrap();
+
+    // Client 1's funds come from client 2, whose funds come from client 3
+    for row in [
+        deposit(3, 1, dec!(10.0)),
+        transfer(3, 2, 2, dec!(6.0)),
+        transfer(2, 1, 3, dec!(5.0)),
+        withdrawal(1, 4, dec!(5.0)),
+    ] {
+        engine.process(row).await.unwrap();
+    }
+
+    let trace = engine.trace_transaction(4).await.unwrap();
+    assert_eq!(trace.events.len(), 1);
+    assert_eq!(trace.events[0].outcome, "applied");
+    assert_eq!(trace.events[0].available_delta, dec!(-5.0));
+
+    let trace = engine.trace_transaction(3).await.unwrap();
+    assert_eq!(trace.events[0].outcome, "applied");
+}
+
+#[tokio::test]
+async fn test_trace_replays_under_the_engine_compat() {
+    use payments_engine::compat::CompatConfig;
+    use payments_engine::test_support::{deposit, dispute, withdrawal};
+    use payments_engine::trace::DisputeState;
+
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("trace.log"), 4, cold_storage)
+        .await
+        .unwrap()
+        .with_compat(CompatConfig::extended())
+        .unwrap();
+    for row in [deposit(1, 1, dec!(10.0)), withdrawal(1, 2, dec!(4.0)), dispute(1, 2)] {
+        engine.process(row).await.unwrap();
+    }
+
+    // Disputing a withdrawal is only accepted under extended compat
+    let trace = engine.trace_transaction(2).await.unwrap();
+    assert_eq!(trace.events.len(), 2);
+    assert!(trace.events.iter().all(|event| event.outcome == "applied"), "{:?}", trace.events);
+    assert_eq!(trace.events[1].held_delta, dec!(4.0));
+    assert_eq!(trace.dispute_state, DisputeState::Disputed);
+}
+
+// ============================================================================
+// ACCOUNT TIMELINE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_account_timeline_includes_rejections_and_locks() {
+    use payments_engine::timeline::{TimelineKind, TimelineSource};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("timeline.log");
+    
+    // Seed history in a previous "run"
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(dec!(10.0)),
+            to: None,
+        }).await.unwrap();
+    }
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    for (tx_type, tx, amount) in [
+        (TransactionType::Withdrawal, 2, Some(dec!(50.0))),
+        (TransactionType::Dispute, 1, None),
+        (TransactionType::Chargeback, 1, None),
+    ] {
+        let _ = engine.process(TransactionRow { tx_type, client: 1, tx, amount, to: None }).await;
+    }
+    
+    let timeline = engine.account_timeline(1).await.unwrap();
+    let kinds: Vec<_> = timeline.entries.iter().map(|e| e.kind).collect();
+    assert_eq!(kinds, vec![
+        TimelineKind::Applied,
+        TimelineKind::Rejected,
+        TimelineKind::Applied,
+        TimelineKind::Applied,
+        TimelineKind::AccountLocked,
+    ]);
+    
+    // History comes from the log, everything after startup from the audit log
+    assert_eq!(timeline.entries[0].source, TimelineSource::EventLog);
+    assert_eq!(timeline.entries[1].source, TimelineSource::Audit);
+    assert_eq!(timeline.entries[1].reason.as_deref(), Some("insufficient funds"));
+    assert!(timeline.open_disputes.is_empty());
+}
+
+// ============================================================================
+// OPENING BALANCE IMPORT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_opening_balance_import_and_replay() {
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("opening.log");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        
+        engine.import_opening_balance(1, dec!(250.0)).await.unwrap();
+        
+        // Seeding twice would double the balance
+        let result = engine.import_opening_balance(1, dec!(250.0)).await;
+        assert!(matches!(result, Err(ProcessingError::AccountNotEmpty)));
+        
+        // Producers cannot submit opening balances directly
+        let result = engine.process(TransactionRow {
+            tx_type: TransactionType::OpeningBalance,
+            client: 2,
+            tx: 0,
+            amount: Some(dec!(10.0)),
+            to: None,
+        }).await;
+        assert!(matches!(result, Err(ProcessingError::UnsupportedTransactionType)));
+        
+        // Opening balance doesn't consume producer tx ids
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 0,
+            amount: Some(dec!(50.0)),
+            to: None,
+        }).await.unwrap();
+    }
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(300.0));
+    assert!(engine.get_account(2).await.is_none());
+}
+
+#[tokio::test]
+async fn test_opening_balance_refuses_accounts_with_cold_history() {
+    use payments_engine::test_support::{deposit, withdrawal};
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("opening.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage.clone()).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    engine.import_opening_balance(2, dec!(7.0)).await.unwrap();
+    for (client, tx) in [(1, 1), (2, 3)] {
+        engine.process(deposit(client, tx, dec!(5.0))).await.unwrap();
+        engine.process(withdrawal(client, tx + 1, dec!(5.0))).await.unwrap();
+        assert!(engine.force_migrate_cold(client).await);
+    }
+    for _ in 0..50 {
+        if engine.hot_transactions(1).await.unwrap().is_empty() && engine.hot_transactions(2).await.unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    
+    // Netting to zero with its history in cold storage doesn't make it new
+    let result = engine.import_opening_balance(1, dec!(250.0)).await;
+    assert!(matches!(result, Err(ProcessingError::AccountNotEmpty)));
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(0.0));
+    engine.shutdown().await.unwrap();
+    
+    // Replay over the same cold storage still applies the logged opening balance
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    assert_eq!(engine.get_account(2).await.unwrap().available, dec!(7.0));
+}
+
+// ============================================================================
+// HOT STORAGE PARTITIONING TESTS
+// ============================================================================
+
+#[test]
+fn test_hot_store_drains_only_expired_buckets() {
+    use payments_engine::hot_store::HotStore;
+    use payments_engine::StoredTransaction;
+    use std::time::{Duration, SystemTime};
+    
+    let day = Duration::from_secs(24 * 3600);
+    let now = SystemTime::now();
+    let stored = |created_at| StoredTransaction {
+        client: 1,
+        tx_type: TransactionType::Deposit,
+        amount: dec!(1.0),
+        disputed: false,
+        held_amount: None,
+        created_at,
+    };
+    
+    let mut store = HotStore::new(day);
+    store.insert(1, stored(now - day * 100));
+    store.insert(2, stored(now - day * 95));
+    store.insert(3, stored(now));
+    
+    // Lookups go through the index regardless of bucket
+    assert_eq!(store.get(&2).unwrap().created_at, now - day * 95);
+    
+    let mut drained: Vec<u32> = store
+        .drain_expired(now - day * 90)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    drained.sort();
+    
+    assert_eq!(drained, vec![1, 2]);
+    assert_eq!(store.len(), 1);
+    assert!(store.contains_key(&3));
+    assert!(store.get(&1).is_none());
+}
+
+#[test]
+fn test_hot_store_drain_is_exact_within_boundary_bucket() {
+    use payments_engine::hot_store::HotStore;
+    use payments_engine::StoredTransaction;
+    use std::time::{Duration, UNIX_EPOCH};
+    
+    let stored = |secs| StoredTransaction {
+        client: 1,
+        tx_type: TransactionType::Deposit,
+        amount: dec!(1.0),
+        disputed: false,
+        held_amount: None,
+        created_at: UNIX_EPOCH + Duration::from_secs(secs),
+    };
+    
+    // One wide bucket holds everything, so the cutoff falls inside it
+    let mut store = HotStore::new(Duration::from_secs(1000));
+    store.insert(1, stored(100));
+    store.insert(2, stored(600));
+    
+    let drained = store.drain_expired(UNIX_EPOCH + Duration::from_secs(500));
+    
+    assert_eq!(drained.len(), 1);
+    assert_eq!(drained[0].0, 1);
+    assert!(store.contains_key(&2));
+}
+
+// ============================================================================
+// MIGRATION BATCHING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_migrate_batch_respects_rate_limit() {
+    use payments_engine::hot_store::HotStore;
+    use payments_engine::migration::{migrate_batch, MigrationConfig};
+    use payments_engine::StoredTransaction;
+    use std::time::{Duration, Instant, SystemTime};
+    
+    let old = SystemTime::now() - Duration::from_secs(100 * 24 * 3600);
+    let mut hot = HotStore::default();
+    for tx_id in 1..=10 {
+        hot.insert(tx_id, StoredTransaction {
+            client: 1,
+            tx_type: TransactionType::Deposit,
+            amount: dec!(1.0),
+            disputed: false,
+            held_amount: None,
+            created_at: old,
+        });
+    }
+    
+    let config = MigrationConfig {
+        batch_size: 5,
+        concurrency: 2,
+        max_puts_per_sec: Some(50),
+        ..MigrationConfig::default()
+    };
+    
+    // Batch is capped, and taking it leaves hot storage untouched
+    let batch = hot.expired(SystemTime::now(), config.batch_size);
+    assert_eq!(batch.len(), 5);
+    assert_eq!(hot.len(), 10);
+    
+    let cold = Arc::new(InMemoryStore::new());
+    let started = Instant::now();
+    let outcome = migrate_batch(cold.clone(), batch, &config).await;
+    
+    // 5 puts at 50/s need at least 4 spacing intervals of 20ms
+    assert!(started.elapsed() >= Duration::from_millis(80));
+    assert_eq!(outcome.migrated.len(), 5);
+    assert_eq!(outcome.failed, 0);
+    for (tx_id, _) in &outcome.migrated {
+        assert!(cold.get(*tx_id).await.is_some());
+    }
+}
+
+#[test]
+fn test_hot_days_shortened_for_low_risk_clients() {
+    use payments_engine::migration::MigrationConfig;
+    
+    // No policy: every client keeps the actor's window
+    assert_eq!(MigrationConfig::default().hot_days(90, 10_000, 0), 90);
+    
+    let config = MigrationConfig {
+        low_risk_hot_days: Some(7),
+        low_risk_max_dispute_rate: 0.01,
+        low_risk_min_transactions: 100,
+        ..MigrationConfig::default()
+    };
+    assert_eq!(config.hot_days(90, 1_000, 10), 7);
+    assert_eq!(config.hot_days(90, 1_000, 11), 90);
+    assert_eq!(config.hot_days(90, 99, 0), 90);
+    assert_eq!(config.hot_days(90, 0, 0), 90);
+    // Never longer than the actor's window
+    assert_eq!(config.hot_days(3, 1_000, 0), 3);
+}
+
+// ============================================================================
+// COLD STORAGE PREFETCH TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_prefetching_store_warms_and_invalidates() {
+    use payments_engine::storage::PrefetchingStore;
+    use payments_engine::StoredTransaction;
+    use std::time::SystemTime;
+    
+    let stored = |amount| StoredTransaction {
+        client: 1,
+        tx_type: TransactionType::Deposit,
+        amount,
+        disputed: false,
+        held_amount: None,
+        created_at: SystemTime::now(),
+    };
+    
+    let inner = Arc::new(InMemoryStore::new());
+    inner.put(1, stored(dec!(10.0))).await.unwrap();
+    inner.put(2, stored(dec!(20.0))).await.unwrap();
+    
+    let store = PrefetchingStore::new(inner.clone(), 100);
+    store.warm(&[1, 2, 3]).await;
+    assert_eq!(store.cached_len().await, 2);
+    
+    // A lookup consumes its prefetched entry
+    assert_eq!(store.get(1).await.unwrap().amount, dec!(10.0));
+    assert_eq!(store.cached_len().await, 1);
+    
+    // Writes invalidate the cache so later reads see the new value
+    store.put(2, stored(dec!(25.0))).await.unwrap();
+    assert_eq!(store.cached_len().await, 0);
+    assert_eq!(store.get(2).await.unwrap().amount, dec!(25.0));
+}
+
+/// Cold store whose batch reads wait until the test opens the gate
+struct GatedBatchStore {
+    inner: InMemoryStore,
+    gate: tokio::sync::Semaphore,
+}
+
+#[async_trait::async_trait]
+impl TransactionStore for GatedBatchStore {
+    async fn get(&self, tx_id: u32) -> Option<payments_engine::StoredTransaction> {
+        self.inner.get(tx_id).await
+    }
+    
+    async fn put(&self, tx_id: u32, tx: payments_engine::StoredTransaction) -> anyhow::Result<()> {
+        self.inner.put(tx_id, tx).await
+    }
+    
+    async fn remove(&self, tx_id: u32) -> anyhow::Result<()> {
+        self.inner.remove(tx_id).await
+    }
+    
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u32, payments_engine::StoredTransaction)>> {
+        self.inner.list_client(client, after, limit).await
+    }
+    
+    async fn get_many(&self, tx_ids: &[u32]) -> Vec<(u32, payments_engine::StoredTransaction)> {
+        let _permit = self.gate.acquire().await.unwrap();
+        self.inner.get_many(tx_ids).await
+    }
+    
+    async fn compact(&self, retain: payments_engine::storage::Retain) -> anyhow::Result<payments_engine::storage::CompactionReport> {
+        self.inner.compact(retain).await
+    }
+}
+
+#[tokio::test]
+async fn test_prefetch_warm_does_not_block_writes_or_cache_stale_reads() {
+    use payments_engine::storage::PrefetchingStore;
+    use payments_engine::StoredTransaction;
+    use std::time::{Duration, SystemTime};
+    
+    let stored = |amount| StoredTransaction {
+        client: 1,
+        tx_type: TransactionType::Deposit,
+        amount,
+        disputed: false,
+        held_amount: None,
+        created_at: SystemTime::now(),
+    };
+    
+    let inner = Arc::new(GatedBatchStore {
+        inner: InMemoryStore::new(),
+        gate: tokio::sync::Semaphore::new(0),
+    });
+    inner.put(1, stored(dec!(10.0))).await.unwrap();
+    
+    let store = Arc::new(PrefetchingStore::new(inner.clone(), 100));
+    let warming = tokio::spawn({
+        let store = store.clone();
+        async move { store.warm(&[1]).await }
+    });
+    tokio::task::yield_now().await;
+    
+    // The write goes through while the warm is still waiting on its read
+    tokio::time::timeout(Duration::from_secs(5), store.put(1, stored(dec!(15.0))))
+        .await
+        .expect("write blocked behind a prefetch")
+        .unwrap();
+    
+    inner.gate.add_permits(1);
+    warming.await.unwrap();
+    
+    // What the warm read may predate the write, so it was not cached
+    assert_eq!(store.cached_len().await, 0);
+    assert_eq!(store.get(1).await.unwrap().amount, dec!(15.0));
+}
+
+// ============================================================================
+// INTERNAL ID ALLOCATION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_internal_ids_skip_producer_ids_and_survive_replay() {
+    use payments_engine::id_allocator::ReservedRangeAllocator;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("ids.log");
+    
+    let new_engine = |log_path| async move {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        ScalableEngine::new(log_path, 4, cold_storage)
+            .await
+            .unwrap()
+            .with_id_allocator(Arc::new(ReservedRangeAllocator::new(500, 510)))
+    };
+    
+    {
+        let engine = new_engine(log_path.clone()).await;
+        
+        // Producer happens to use the first reserved id
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 500,
+            amount: Some(dec!(1.0)),
+            to: None,
+        }).await.unwrap();
+        
+        engine.import_opening_balance(2, dec!(10.0)).await.unwrap();
+        
+        let timeline = engine.account_timeline(2).await.unwrap();
+        assert_eq!(timeline.entries[0].tx, 501);
+    }
+    
+    // After replay the allocator continues past ids already in the log
+    let engine = new_engine(log_path).await;
+    engine.rebuild_from_events().await.unwrap();
+    engine.import_opening_balance(3, dec!(10.0)).await.unwrap();
+    
+    let timeline = engine.account_timeline(3).await.unwrap();
+    assert_eq!(timeline.entries[0].tx, 502);
+}
+
+// ============================================================================
+// CUSTOM TRANSACTION HANDLER TESTS
+// ============================================================================
+
+struct FeeHandler;
+
+impl payments_engine::handlers::TransactionHandler for FeeHandler {
+    fn type_name(&self) -> &str {
+        "fee"
+    }
+    
+    fn creates_tx(&self) -> bool {
+        true
+    }
+    
+    fn validate(&self, tx: &TransactionRow) -> Result<(), payments_engine::ProcessingError> {
+        match tx.amount {
+            Some(amount) if amount > dec!(0) => Ok(()),
+            _ => Err(payments_engine::ProcessingError::InvalidAmount),
+        }
+    }
+    
+    fn apply(
+        &self,
+        ctx: &mut payments_engine::handlers::HandlerContext<'_>,
+        tx: &TransactionRow,
+    ) -> Result<(), payments_engine::ProcessingError> {
+        // Fees may overdraw the account
+        ctx.account.available -= tx.amount.unwrap();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_custom_handler_applies_and_replays() {
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("handlers.log");
+    let fee = |tx, amount| TransactionRow {
+        tx_type: TransactionType::Custom("fee".to_string()),
+        client: 1,
+        tx,
+        amount,
+        to: None,
+    };
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        
+        // Unknown until a handler is registered
+        let result = engine.process(fee(1, Some(dec!(2.5)))).await;
+        assert!(matches!(result, Err(ProcessingError::UnsupportedTransactionType)));
+        
+        engine.register_handler(Arc::new(FeeHandler));
+        engine.process(fee(1, Some(dec!(2.5)))).await.unwrap();
+        
+        let result = engine.process(fee(2, None)).await;
+        assert!(matches!(result, Err(ProcessingError::InvalidAmount)));
+        
+        // Fee created tx 1, so the id is taken
+        let result = engine.process(fee(1, Some(dec!(1.0)))).await;
+        assert!(matches!(result, Err(ProcessingError::DuplicateTransaction)));
+    }
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.register_handler(Arc::new(FeeHandler));
+    engine.rebuild_from_events().await.unwrap();
+    
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(-2.5));
+}
+
+// ============================================================================
+// DISPUTE REPORTING COUNTER TESTS
+// ============================================================================
+
+#[test]
+fn test_month_key_civil_calendar() {
+    use payments_engine::reporting::month_key;
+    use std::time::{Duration, UNIX_EPOCH};
+    
+    assert_eq!(month_key(UNIX_EPOCH), "1970-01");
+    // 2024-02-29 12:00 UTC (leap day)
+    assert_eq!(month_key(UNIX_EPOCH + Duration::from_secs(1_709_208_000)), "2024-02");
+    // 2024-03-01 00:00 UTC
+    assert_eq!(month_key(UNIX_EPOCH + Duration::from_secs(1_709_251_200)), "2024-03");
+}
+
+#[tokio::test]
+async fn test_dispute_counters_persist_and_skip_replay() {
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("counters.log");
+    let counters_path = temp_dir.path().join("counters.csv");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.dispute_counters().attach_file(counters_path.clone()).await.unwrap();
+        
+        for (tx_type, tx, amount) in [
+            (TransactionType::Deposit, 1, Some(dec!(10.0))),
+            (TransactionType::Deposit, 2, Some(dec!(10.0))),
+            (TransactionType::Dispute, 1, None),
+            (TransactionType::Chargeback, 1, None),
+        ] {
+            engine.process(TransactionRow { tx_type, client: 7, tx, amount, to: None }).await.unwrap();
+        }
+        
+        // Rejected rows are not counted
+        let _ = engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 7,
+            tx: 3,
+            amount: Some(dec!(1.0)),
+            to: None,
+        }).await;
+        
+        engine.flush_counters().await.unwrap();
+    }
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.dispute_counters().attach_file(counters_path).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    let report = engine.dispute_counters().report(None);
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].client, 7);
+    assert_eq!(report[0].transactions, 2);
+    assert_eq!(report[0].disputes, 1);
+    assert_eq!(report[0].chargebacks, 1);
+}
+
+#[tokio::test]
+async fn test_dispute_counters_recount_events_logged_after_last_flush() {
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("counters.log");
+    let counters_path = temp_dir.path().join("counters.csv");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.dispute_counters().attach_file(counters_path.clone()).await.unwrap();
+        
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 7,
+            tx: 1,
+            amount: Some(dec!(10.0)),
+            to: None,
+        }).await.unwrap();
+        engine.flush_counters().await.unwrap();
+        
+        // Crashes before these are flushed
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 7,
+            tx: 2,
+            amount: Some(dec!(10.0)),
+            to: None,
+        }).await.unwrap();
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Dispute,
+            client: 7,
+            tx: 2,
+            amount: None,
+            to: None,
+        }).await.unwrap();
+    }
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.dispute_counters().attach_file(counters_path).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    let (transactions, disputes) = engine.dispute_counters().client_totals(7);
+    assert_eq!(transactions, 2);
+    assert_eq!(disputes, 1);
+    
+    // Without a counters file every logged event is counted
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("counters.log"), 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    assert_eq!(engine.dispute_counters().client_totals(7), (2, 1));
+}
+
+// ============================================================================
+// ACCOUNTING PERIOD CLOSE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_closed_period_rejects_backdated_events() {
+    use payments_engine::reporting::month_key;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("periods.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    
+    // 2020-01-15
+    let january = UNIX_EPOCH + Duration::from_secs(1_579_046_400);
+    engine.process_at(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 1,
+        amount: Some(dec!(10.0)),
+        to: None,
+    }, january).await.unwrap();
+    
+    assert!(engine.close_period("2020-01").await.unwrap());
+    assert!(!engine.close_period("2020-01").await.unwrap());
+    
+    let result = engine.process_at(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 2,
+        amount: Some(dec!(5.0)),
+        to: None,
+    }, january).await;
+    assert!(matches!(result, Err(payments_engine::ProcessingError::PeriodClosed)));
+    
+    // The rejected id is free again and the open period still accepts it
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 2,
+        amount: Some(dec!(5.0)),
+        to: None,
+    }).await.unwrap();
+    
+    // Closed report is frozen, even for direct counter writes
+    let counters = engine.dispute_counters();
+    counters.record(1, payments_engine::reporting::CounterKind::Dispute, january);
+    let closed = counters.report(Some("2020-01"));
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].transactions, 1);
+    assert_eq!(closed[0].disputes, 0);
+    
+    let open = counters.report(Some(&month_key(SystemTime::now())));
+    assert_eq!(open[0].transactions, 1);
+    
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(15.0));
+}
+
+#[tokio::test]
+async fn test_period_close_validation_and_persistence() {
+    use payments_engine::periods::AccountingPeriods;
+    use payments_engine::reporting::month_key;
+    use std::time::SystemTime;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("periods.txt");
+    
+    let periods = AccountingPeriods::new();
+    periods.attach_file(path.clone()).await.unwrap();
+    assert!(periods.close("2021-13").await.is_err());
+    assert!(periods.close("21-01").await.is_err());
+    assert!(periods.close("9999-01").await.is_err());
+    // Events may still arrive for the running month
+    assert!(periods.close(&month_key(SystemTime::now())).await.is_err());
+    assert!(periods.close("2021-03").await.unwrap());
+    assert!(periods.close("2021-02").await.unwrap());
+    
+    let reloaded = AccountingPeriods::new();
+    reloaded.attach_file(path).await.unwrap();
+    assert_eq!(reloaded.closed(), vec!["2021-02".to_string(), "2021-03".to_string()]);
+    assert!(reloaded.is_closed("2021-03"));
+    assert!(!reloaded.is_closed("2021-04"));
+}
+
+// ============================================================================
+// EXTERNAL AUTHORIZER TESTS
+// ============================================================================
+
+struct ScriptedAuthorizer {
+    approve: bool,
+    delay: std::time::Duration,
+    calls: std::sync::atomic::AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl payments_engine::authorizer::Authorizer for ScriptedAuthorizer {
+    async fn authorize(&self, _tx: &TransactionRow) -> anyhow::Result<bool> {
+        self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        Ok(self.approve)
+    }
+}
+
+async fn engine_with_authorizer(
+    temp_dir: &TempDir,
+    authorizer: Arc<ScriptedAuthorizer>,
+    failure_policy: payments_engine::authorizer::FailurePolicy,
+) -> ScalableEngine {
+    use payments_engine::authorizer::AuthorizerConfig;
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("auth.log"), 4, cold_storage)
+        .await
+        .unwrap()
+        .with_authorizer(authorizer, AuthorizerConfig {
+            threshold: dec!(100.0),
+            timeout: std::time::Duration::from_millis(50),
+            failure_policy,
+        });
+    
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 1,
+        amount: Some(dec!(1000.0)),
+        to: None,
+    }).await.unwrap();
+    engine
+}
+
+#[tokio::test]
+async fn test_authorizer_gates_large_withdrawals_only() {
+    use payments_engine::authorizer::FailurePolicy;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let authorizer = Arc::new(ScriptedAuthorizer {
+        approve: false,
+        delay: std::time::Duration::ZERO,
+        calls: Default::default(),
+    });
+    let engine = engine_with_authorizer(&temp_dir, authorizer.clone(), FailurePolicy::FailClosed).await;
+    
+    // At the threshold: no approval needed
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Withdrawal,
+        client: 1,
+        tx: 2,
+        amount: Some(dec!(100.0)),
+        to: None,
+    }).await.unwrap();
+    assert_eq!(authorizer.calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    
+    let result = engine.process(TransactionRow {
+        tx_type: TransactionType::Withdrawal,
+        client: 1,
+        tx: 3,
+        amount: Some(dec!(500.0)),
+        to: None,
+    }).await;
+    assert!(matches!(result, Err(payments_engine::ProcessingError::AuthorizationDenied)));
+    assert_eq!(authorizer.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    
+    // A dry run is judged without asking the authorizer
+    let dry_run = TransactionRow {
+        tx_type: TransactionType::Withdrawal,
+        client: 1,
+        tx: 4,
+        amount: Some(dec!(500.0)),
+        to: None,
+    };
+    engine.validate(&dry_run).await.unwrap();
+    assert_eq!(authorizer.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    
+    // A duplicate is rejected before the authorizer is asked
+    let result = engine.process(TransactionRow {
+        tx_type: TransactionType::Withdrawal,
+        client: 1,
+        tx: 2,
+        amount: Some(dec!(500.0)),
+        to: None,
+    }).await;
+    assert!(matches!(result, Err(payments_engine::ProcessingError::DuplicateTransaction)));
+    assert_eq!(authorizer.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    
+    // The denied id was released
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 3,
+        amount: Some(dec!(1.0)),
+        to: None,
+    }).await.unwrap();
+    
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(901.0));
+}
+
+#[tokio::test]
+async fn test_authorizer_timeout_follows_failure_policy() {
+    use payments_engine::authorizer::FailurePolicy;
+    
+    let slow = || Arc::new(ScriptedAuthorizer {
+        approve: true,
+        delay: std::time::Duration::from_secs(5),
+        calls: Default::default(),
+    });
+    let withdrawal = TransactionRow {
+        tx_type: TransactionType::Withdrawal,
+        client: 1,
+        tx: 2,
+        amount: Some(dec!(500.0)),
+        to: None,
+    };
+    
+    let temp_dir = TempDir::new().unwrap();
+    let engine = engine_with_authorizer(&temp_dir, slow(), FailurePolicy::FailClosed).await;
+    let result = engine.process(withdrawal.clone()).await;
+    assert!(matches!(result, Err(payments_engine::ProcessingError::AuthorizerUnavailable)));
+    
+    let temp_dir = TempDir::new().unwrap();
+    let engine = engine_with_authorizer(&temp_dir, slow(), FailurePolicy::FailOpen).await;
+    engine.process(withdrawal).await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(500.0));
+}
+
+// ============================================================================
+// BATCHED OUTPUT TESTS
+// ============================================================================
+
+/// Collects output and counts the writes that produced it
+#[derive(Default)]
+struct CountingWriter {
+    bytes: Vec<u8>,
+    writes: usize,
+}
+
+impl tokio::io::AsyncWrite for CountingWriter {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.writes += 1;
+        self.bytes.extend_from_slice(buf);
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn test_write_accounts_batches_large_outputs() {
+    use payments_engine::csv_io::write_accounts;
+    use payments_engine::AccountOutput;
+    
+    let accounts: Vec<AccountOutput> = (0..=u16::MAX)
+        .map(|client| AccountOutput {
+            client,
+            available: dec!(1.5),
+            held: dec!(0.25),
+            total: dec!(1.75),
+            locked: client % 2 == 0,
+        })
+        .collect();
+    
+    let mut expected = String::from("client,available,held,total,locked\n");
+    for a in &accounts {
+        expected.push_str(&format!(
+            "{},{:.4},{:.4},{:.4},{}\n",
+            a.client, a.available, a.held, a.total, a.locked
+        ));
+    }
+    
+    let mut writer = CountingWriter::default();
+    write_accounts(&mut writer, accounts).await.unwrap();
+    
+    assert_eq!(String::from_utf8(writer.bytes).unwrap(), expected);
+    assert!(writer.writes < 100, "{} writes", writer.writes);
+}
+
+// ============================================================================
+// EVENT LOG GENERATION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_live_writes_wait_for_replay_and_replay_runs_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("generations.log");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        assert_eq!(engine.generation(), 1);
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(dec!(10.0)),
+            to: None,
+        }).await.unwrap();
+    }
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+    assert_eq!(engine.generation(), 0);
+    
+    let early = TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 2,
+        amount: Some(dec!(5.0)),
+        to: None,
+    };
+    let result = engine.process(early.clone()).await;
+    assert!(matches!(result, Err(payments_engine::ProcessingError::RebuildPending)));
+    
+    engine.rebuild_from_events().await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    assert_eq!(engine.generation(), 2);
+    engine.process(early).await.unwrap();
+    
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(15.0));
+    
+    let events = logged_events(&log_path).await;
+    let logged: Vec<_> = events.iter().map(|e| (e.tx, e.amount)).collect();
+    assert_eq!(logged, vec![(1, Some(dec!(10.0))), (2, Some(dec!(5.0)))]);
+}
+
+#[tokio::test]
+async fn test_replay_skips_duplicate_events_and_isolates_torn_tail() {
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("reingested.log");
+    
+    // The same deposit logged twice, then a crash mid-append
+    std::fs::write(
+        &log_path,
+        "deposit,1,1,10.0\ndeposit,1,1,10.0\nwithdrawal,1,2,1.0\ndepos",
+    ).unwrap();
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    assert_eq!(engine.generation(), 1);
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(9.0));
+    
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 3,
+        amount: Some(dec!(1.0)),
+        to: None,
+    }).await.unwrap();
+    
+    // A log started as CSV is continued as CSV
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    assert!(log.ends_with("\ndepos\n#generation,1\ndeposit,1,3,1.0\n"));
+}
+
+#[tokio::test]
+async fn test_binary_log_cuts_torn_tail_and_refuses_corruption_before_it() {
+    use payments_engine::event_store::{EventStore, LogFormat};
+    use payments_engine::test_support::deposit;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("binary.log");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+        engine.process(deposit(1, 2, dec!(5.0))).await.unwrap();
+    }
+    assert_eq!(EventStore::new(log_path.clone(), Default::default()).await.unwrap().format(), LogFormat::Binary);
+    let intact = std::fs::read(&log_path).unwrap();
+    
+    // Half a record at the end is a torn write, cut off on open
+    let mut content = intact.clone();
+    content.extend_from_slice(&[40, 0, 0, 0, 1, 2]);
+    std::fs::write(&log_path, &content).unwrap();
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.rebuild_from_events().await.unwrap();
+        assert_eq!(engine.generation(), 2);
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(15.0));
+        
+        // Writes after the cut tail stay readable
+        engine.process(deposit(1, 3, dec!(1.0))).await.unwrap();
+        let logged: Vec<_> = logged_events(&log_path).await.iter().map(|e| e.tx).collect();
+        assert_eq!(logged, vec![1, 2, 3]);
+    }
+    
+    // Header (6 bytes) and generation record (20 bytes), then the first deposit:
+    // a flipped bit in its length prefix or payload, with records after it, is
+    // corruption and the log is refused rather than replayed without it
+    for byte in [26, 26 + 8 + 4] {
+        let mut content = intact.clone();
+        content[byte] ^= 0x01;
+        std::fs::write(&log_path, &content).unwrap();
+        
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        let err = engine.rebuild_from_events().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Corrupt event log record at byte 26"), "{:#}", err);
+        assert_eq!(std::fs::read(&log_path).unwrap(), content);
+    }
+}
+
+#[tokio::test]
+async fn test_bulk_replay_registration_keeps_first_of_each_id() {
+    use payments_engine::tx_registry_actor::ShardedTxRegistry;
+    
+    let registry = ShardedTxRegistry::new(4);
+    assert!(registry.register(9).await.unwrap());
+    
+    let flags = registry.register_replayed_many(&[5, 6, 5, 9, 7, 6]).await.unwrap();
+    assert_eq!(flags, vec![true, true, false, false, true, false]);
+    assert_eq!(registry.register_replayed_many(&[]).await.unwrap(), Vec::<bool>::new());
+    assert!(!registry.register(7).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_parallel_replay_keeps_each_clients_order_across_chunks() {
+    use payments_engine::test_support::deposit;
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("large.log");
+    
+    // Ten deposits of 2.0 per client, a withdrawal of 15.0 that only fits after the eighth
+    let mut log = String::new();
+    for round in 0..10u32 {
+        for client in 1..=500u32 {
+            log.push_str(&format!("deposit,{},{},2.0\n", client, 1 + round * 500 + (client - 1)));
+        }
+        if round == 7 {
+            for client in 1..=500u32 {
+                log.push_str(&format!("withdrawal,{},{},15.0\n", client, 10_000 + client));
+            }
+        }
+    }
+    // Client 2 only covers its withdrawal once the transfer lands, and tx 1 is logged again
+    log.push_str("transfer,1,20001,5.0,2\nwithdrawal,2,20002,10.0\ndeposit,1,1,2.0\n");
+    std::fs::write(&log_path, log).unwrap();
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 8, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    for client in 3..=500u16 {
+        assert_eq!(engine.get_account(client).await.unwrap().available, dec!(5.0), "client {}", client);
+    }
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(0.0));
+    assert_eq!(engine.get_account(2).await.unwrap().available, dec!(0.0));
+    
+    let result = engine.process(deposit(3, 5_000, dec!(1.0))).await;
+    assert!(matches!(result, Err(ProcessingError::DuplicateTransaction)));
+}
+
+// ============================================================================
+// TRANSFER TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_transfer_moves_funds_and_replays_once() {
+    use payments_engine::test_support::{deposit, transfer};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.csv");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+        engine.process(transfer(1, 2, 2, dec!(40.0))).await.unwrap();
+        
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(60.0));
+        assert_eq!(engine.get_account(2).await.unwrap().available, dec!(40.0));
+    }
+    
+    let events = logged_events(&log_path).await;
+    assert!(events.iter().any(|e| e.tx_type == TransactionType::Transfer
+        && (e.client, e.tx, e.amount, e.to) == (1, 2, Some(dec!(40.0)), Some(2))));
+    
+    // Restart: the transfer is replayed exactly once
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(60.0));
+    assert_eq!(engine.get_account(2).await.unwrap().available, dec!(40.0));
+    
+    // Its tx id is taken
+    let result = engine.process(transfer(1, 2, 2, dec!(1.0))).await;
+    assert!(matches!(result, Err(payments_engine::ProcessingError::DuplicateTransaction)));
+}
+
+#[tokio::test]
+async fn test_transfer_rejections_leave_both_accounts_untouched() {
+    use payments_engine::test_support::{chargeback, deposit, dispute, transfer};
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("events.csv"), 4, cold_storage)
+        .await
+        .unwrap();
+    
+    engine.process(deposit(1, 1, dec!(50.0))).await.unwrap();
+    
+    let result = engine.process(transfer(1, 2, 2, dec!(80.0))).await;
+    assert!(matches!(result, Err(ProcessingError::InsufficientFunds)));
+    
+    let result = engine.process(transfer(1, 1, 3, dec!(10.0))).await;
+    assert!(matches!(result, Err(ProcessingError::InvalidTransfer)));
+    
+    let mut no_destination = transfer(1, 2, 4, dec!(10.0));
+    no_destination.to = None;
+    let result = engine.process(no_destination).await;
+    assert!(matches!(result, Err(ProcessingError::InvalidTransfer)));
+    
+    // Lock client 2, the credit fails after client 1 was debited and must be rolled back
+    engine.process(deposit(2, 5, dec!(5.0))).await.unwrap();
+    engine.process(dispute(2, 5)).await.unwrap();
+    engine.process(chargeback(2, 5)).await.unwrap();
+    
+    let result = engine.process(transfer(1, 2, 6, dec!(20.0))).await;
+    assert!(matches!(result, Err(ProcessingError::AccountLocked)));
+    
+    let sender = engine.get_account(1).await.unwrap();
+    assert_eq!(sender.available, dec!(50.0));
+    assert_eq!(engine.get_account(2).await.unwrap().available, dec!(0));
+    
+    // A rolled back transfer releases its tx id
+    engine.process(deposit(1, 6, dec!(1.0))).await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(51.0));
+}
+
+#[tokio::test]
+async fn test_logged_transfer_replays_both_legs_whatever_was_logged_before_it() {
+    use payments_engine::config::EngineConfig;
+    use payments_engine::event_store::InMemoryEventLog;
+    use payments_engine::test_support::{chargeback, deposit, dispute, transfer};
+    
+    // The receiver's chargeback was logged first but applied after the transfer
+    let log = Arc::new(InMemoryEventLog::with_events(vec![
+        deposit(1, 1, dec!(100.0)),
+        deposit(2, 2, dec!(10.0)),
+        dispute(2, 2),
+        chargeback(2, 2),
+        transfer(1, 2, 3, dec!(30.0)),
+    ]));
+    let config = EngineConfig { shards: 2, ..Default::default() };
+    let engine = ScalableEngine::from_event_log(log, &config, Arc::new(InMemoryStore::new())).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(70.0));
+    let receiver = engine.get_account(2).await.unwrap();
+    assert!(receiver.locked);
+    assert_eq!(receiver.available, dec!(30.0));
+}
+
+// ============================================================================
+// COMPAT MODE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_exact_retries_accepted_only_in_extended_mode() {
+    use payments_engine::compat::CompatConfig;
+    use payments_engine::test_support::{deposit, withdrawal};
+    use payments_engine::ProcessingError;
+    
+    for (compat, retries_accepted) in [(CompatConfig::strict(), false), (CompatConfig::extended(), true)] {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("events.csv");
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage)
+            .await
+            .unwrap()
+            .with_compat(compat)
+            .unwrap();
+        
+        engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+        engine.process(withdrawal(1, 2, dec!(3.0))).await.unwrap();
+        
+        let retry = engine.process(deposit(1, 1, dec!(10.0))).await;
+        assert_eq!(retry.is_ok(), retries_accepted);
+        
+        // Anything but an exact repeat is still a duplicate
+        let result = engine.process(deposit(1, 2, dec!(3.0))).await;
+        assert!(matches!(result, Err(ProcessingError::DuplicateTransaction)));
+        let result = engine.process(deposit(2, 1, dec!(10.0))).await;
+        assert!(matches!(result, Err(ProcessingError::DuplicateTransaction)));
+        
+        // A retry is acknowledged, never applied or logged twice
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(7.0));
+        let events = logged_events(&log_path).await;
+        assert_eq!(events.iter().filter(|e| e.tx == 1).count(), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_hold_policy_releases_locked_deposits_on_unlock() {
+    use payments_engine::compat::{CompatConfig, LockPolicy};
+    use payments_engine::test_support::{chargeback, deposit, dispute, withdrawal};
+    use payments_engine::ProcessingError;
+    
+    let compat = CompatConfig { lock_policy: LockPolicy::Hold, ..CompatConfig::strict() };
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.csv");
+    let open = || async {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap().with_compat(compat).unwrap()
+    };
+    
+    let engine = open().await;
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    engine.process(deposit(1, 2, dec!(5.0))).await.unwrap();
+    engine.process(dispute(1, 1)).await.unwrap();
+    engine.process(chargeback(1, 1)).await.unwrap();
+    
+    // Accepted while locked, but not spendable
+    engine.process(deposit(1, 3, dec!(20.0))).await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held, account.locked), (dec!(5.0), dec!(20.0), true));
+    assert!(matches!(engine.process(withdrawal(1, 4, dec!(1.0))).await, Err(ProcessingError::AccountLocked)));
+    assert!(matches!(engine.process(deposit(1, 3, dec!(20.0))).await, Err(ProcessingError::DuplicateTransaction)));
+    
+    engine.unlock_account(1).await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held, account.locked), (dec!(25.0), dec!(0), false));
+    engine.shutdown().await.unwrap();
+    
+    // Replayed under the same policy, the release is too
+    let engine = open().await;
+    engine.rebuild_from_events().await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held, account.locked), (dec!(25.0), dec!(0), false));
+    
+    // The default still rejects
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage);
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    engine.process(dispute(1, 1)).await.unwrap();
+    engine.process(chargeback(1, 1)).await.unwrap();
+    assert!(matches!(engine.process(deposit(1, 2, dec!(5.0))).await, Err(ProcessingError::AccountLocked)));
+}
+
+// ============================================================================
+// ACTOR SNAPSHOT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_stopped_actor_is_recreated_from_snapshot() {
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::test_support::{deposit, dispute, withdrawal};
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let manager = ShardManager::new(2, cold_storage.clone());
+    
+    manager.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+    manager.process(withdrawal(1, 2, dec!(30.0))).await.unwrap();
+    
+    // Same path as the idle timeout: persist, then stop
+    let first = manager.get_or_create_actor(1).await;
+    first.shutdown().await.unwrap();
+    while !first.is_closed() {
+        tokio::task::yield_now().await;
+    }
+    
+    // Readable while no actor runs, hot transactions were handed to cold storage
+    assert_eq!(manager.get_account(1).await.unwrap().available, dec!(70.0));
+    assert!(cold_storage.get(1).await.is_some());
+    
+    // The next transaction lands on a new actor that starts from the snapshot
+    manager.process(dispute(1, 1)).await.unwrap();
+    assert!(!manager.get_or_create_actor(1).await.same_actor(&first));
+    
+    let account = manager.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(-30.0));
+    assert_eq!(account.held, dec!(100.0));
+}
+
+#[tokio::test]
+async fn test_actor_stopped_by_idle_timeout_is_respawned_with_its_state() {
+    use payments_engine::account_actor::ActorConfig;
+    use payments_engine::config::EngineConfig;
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::test_support::{deposit, dispute, withdrawal};
+    use std::time::Duration;
+    
+    let config = EngineConfig {
+        shards: 2,
+        actor: ActorConfig {
+            idle_timeout_secs: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let manager = ShardManager::with_config(&config, cold_storage);
+    
+    manager.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+    let first = manager.get_or_create_actor(1).await;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !first.is_closed() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("actor never stopped idle");
+    
+    // The stale handle is replaced on the next message, which sees the saved balance
+    manager.process(withdrawal(1, 2, dec!(30.0))).await.unwrap();
+    manager.process(dispute(1, 1)).await.unwrap();
+    assert!(!manager.get_or_create_actor(1).await.same_actor(&first));
+    
+    let account = manager.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(-30.0));
+    assert_eq!(account.held, dec!(100.0));
+}
+
+#[tokio::test]
+async fn test_resident_actor_cap_evicts_least_recently_used_and_rehydrates() {
+    use payments_engine::account_actor::ActorConfig;
+    use payments_engine::config::EngineConfig;
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::test_support::{deposit, dispute, withdrawal};
+    use std::time::Duration;
+    
+    let config = EngineConfig {
+        shards: 4,
+        actor: ActorConfig {
+            max_resident: Some(10),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let manager = ShardManager::with_config(&config, cold_storage);
+    
+    // Client 2 is used first, then every other client up to the cap
+    let idle = manager.get_or_create_actor(2).await;
+    manager.process(deposit(2, 2, dec!(10.0))).await.unwrap();
+    for client in (1..=10u16).filter(|&client| client != 2) {
+        manager.process(deposit(client, client as u32, dec!(10.0))).await.unwrap();
+    }
+    let busy = manager.get_or_create_actor(1).await;
+    manager.process(withdrawal(1, 101, dec!(1.0))).await.unwrap();
+    
+    // The eleventh evicts one actor, a tenth of the cap
+    manager.process(deposit(11, 11, dec!(10.0))).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), idle.stopped()).await.expect("client 2 not evicted");
+    assert!(!busy.is_closed());
+    
+    for client in 12..=60u16 {
+        manager.process(deposit(client, client as u32, dec!(10.0))).await.unwrap();
+    }
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while manager.resident_actors() > 10 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("resident actors stayed over the cap");
+    
+    // Evicted clients keep their balances, and their deposits stay disputable
+    let accounts = manager.get_accounts_of(&(1..=60).collect::<Vec<u16>>()).await;
+    assert_eq!(accounts.len(), 60);
+    assert!(accounts.iter().all(|a| a.total() == if a.client == 1 { dec!(9.0) } else { dec!(10.0) }));
+    manager.process(dispute(2, 2)).await.unwrap();
+    let account = manager.get_account(2).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(0.0), dec!(10.0)));
+}
+
+// ============================================================================
+// SHARD AGGREGATE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_shard_totals_match_full_scan() {
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::test_support::{chargeback, deposit, dispute, transfer, withdrawal};
+    use payments_engine::treasury::TreasuryReport;
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let manager = ShardManager::new(3, cold_storage);
+    
+    let rows = vec![
+        deposit(1, 1, dec!(100.0)),
+        deposit(2, 2, dec!(40.0)),
+        withdrawal(1, 3, dec!(70.0)),
+        // Rejected, the account still exists with nothing in it
+        withdrawal(3, 4, dec!(5.0)),
+        transfer(2, 4, 5, dec!(15.0)),
+        // Rejected, client 2 has only 25 left
+        transfer(2, 1, 6, dec!(99.0)),
+        dispute(1, 1),
+        chargeback(1, 1),
+    ];
+    for row in rows {
+        let _ = manager.process(row).await;
+    }
+    
+    // A restarted actor resumes from its snapshot without being counted twice
+    let actor = manager.get_or_create_actor(2).await;
+    actor.shutdown().await.unwrap();
+    while !actor.is_closed() {
+        tokio::task::yield_now().await;
+    }
+    manager.process(deposit(2, 7, dec!(1.5))).await.unwrap();
+    
+    let scanned = TreasuryReport::from_accounts(&manager.get_all_accounts().await);
+    let totals = manager.totals();
+    assert_eq!(totals, scanned);
+    assert_eq!(totals.accounts, 4);
+    assert_eq!(totals.locked, 1);
+    assert_eq!(totals.negative_exposure, dec!(70.0));
+    
+    let mut combined = TreasuryReport::default();
+    for shard in 0..3 {
+        combined.merge(&manager.shard_totals(shard).unwrap());
+    }
+    assert_eq!(combined, totals);
+    assert!(manager.shard_totals(3).is_none());
+}
+
+// ============================================================================
+// COLD STORAGE FAILURE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_cold_storage_failures_leave_balances_untouched() {
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::test_support::{chargeback, deposit, dispute, FaultyStore};
+    use payments_engine::ProcessingError;
+    
+    let store = Arc::new(FaultyStore::new());
+    let manager = ShardManager::new(1, store.clone());
+    
+    manager.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+    
+    // Stopping the actor moves the deposit to cold storage
+    let actor = manager.get_or_create_actor(1).await;
+    actor.shutdown().await.unwrap();
+    while !actor.is_closed() {
+        tokio::task::yield_now().await;
+    }
+    
+    // Outlasting every retry: rejected, nothing applied
+    store.fail_next(10);
+    let result = manager.process(dispute(1, 1)).await;
+    assert!(matches!(result, Err(ProcessingError::StorageUnavailable)));
+    let account = manager.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(100.0), dec!(0)));
+    assert!(!store.get(1).await.unwrap().disputed);
+    
+    // A short outage is ridden out by the retries
+    store.fail_next(2);
+    manager.process(dispute(1, 1)).await.unwrap();
+    let account = manager.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(0.0), dec!(100.0)));
+    
+    store.fail_next(10);
+    let result = manager.process(chargeback(1, 1)).await;
+    assert!(matches!(result, Err(ProcessingError::StorageUnavailable)));
+    assert!(!manager.get_account(1).await.unwrap().locked);
+    
+    store.fail_next(0);
+    manager.process(chargeback(1, 1)).await.unwrap();
+    let account = manager.get_account(1).await.unwrap();
+    assert!(account.locked);
+    assert_eq!(account.total(), dec!(0.0));
+    assert!(store.get(1).await.is_none());
+}
+
+#[tokio::test]
+async fn test_failed_resolve_keeps_dispute_and_balances() {
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::test_support::{deposit, dispute, resolve, withdrawal, FaultyStore};
+    use payments_engine::ProcessingError;
+    
+    let store = Arc::new(FaultyStore::new());
+    let manager = ShardManager::new(1, store.clone());
+    
+    manager.process(deposit(1, 1, dec!(50.0))).await.unwrap();
+    manager.process(withdrawal(1, 2, dec!(20.0))).await.unwrap();
+    manager.process(dispute(1, 1)).await.unwrap();
+    
+    // Move the disputed deposit to cold storage so resolving it has to write there
+    let actor = manager.get_or_create_actor(1).await;
+    actor.shutdown().await.unwrap();
+    while !actor.is_closed() {
+        tokio::task::yield_now().await;
+    }
+    
+    store.fail_next(10);
+    let result = manager.process(resolve(1, 1)).await;
+    assert!(matches!(result, Err(ProcessingError::StorageUnavailable)));
+    let account = manager.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(-20.0), dec!(50.0)));
+    assert!(store.get(1).await.unwrap().disputed);
+    
+    store.fail_next(0);
+    manager.process(resolve(1, 1)).await.unwrap();
+    let account = manager.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(30.0), dec!(0.0)));
+    assert!(!store.get(1).await.unwrap().disputed);
+}
+
+// ============================================================================
+// ENGINE SNAPSHOT TESTS
+// ============================================================================
+
+async fn engine_with_snapshot(log_path: &std::path::Path, snapshot_path: &std::path::Path) -> ScalableEngine {
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path.to_path_buf(), 4, cold_storage)
+        .await
+        .unwrap()
+        .with_snapshot_path(snapshot_path.to_path_buf());
+    engine.rebuild_from_events().await.unwrap();
+    engine
+}
+
+#[tokio::test]
+async fn test_snapshot_restore_replays_only_the_tail() {
+    use payments_engine::engine_snapshot::EngineSnapshot;
+    use payments_engine::test_support::{deposit, dispute, resolve, withdrawal};
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let snapshot_path = temp_dir.path().join("engine.snapshot");
+    
+    {
+        let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+        engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+        engine.process(deposit(2, 2, dec!(50.0))).await.unwrap();
+        
+        let offset = engine.write_snapshot().await.unwrap();
+        assert_eq!(offset, std::fs::metadata(&log_path).unwrap().len());
+        
+        engine.process(withdrawal(2, 3, dec!(20.0))).await.unwrap();
+        engine.process(dispute(1, 1)).await.unwrap();
+    }
+    
+    // Only the tail is replayed: a balance changed in the snapshot shows through
+    let mut snapshot = EngineSnapshot::load(&snapshot_path).await.unwrap().unwrap();
+    assert_eq!(snapshot.events, 2);
+    for account in &mut snapshot.accounts {
+        if account.client == 2 {
+            account.available = dec!(1000.0);
+        }
+    }
+    snapshot.save(&snapshot_path).await.unwrap();
+    
+    let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+    let client2 = engine.get_account(2).await.unwrap();
+    assert_eq!(client2.available, dec!(980.0));
+    
+    // The tail's dispute found the deposit made before the snapshot
+    let client1 = engine.get_account(1).await.unwrap();
+    assert_eq!((client1.available, client1.held), (dec!(0.0), dec!(100.0)));
+    assert_eq!(engine.account_totals().accounts, 2);
+    
+    // Tx ids from before the snapshot are still taken
+    let result = engine.process(deposit(3, 2, dec!(5.0))).await;
+    assert!(matches!(result, Err(ProcessingError::DuplicateTransaction)));
+    
+    engine.process(resolve(1, 1)).await.unwrap();
+    let client1 = engine.get_account(1).await.unwrap();
+    assert_eq!((client1.available, client1.held), (dec!(100.0), dec!(0.0)));
+}
+
+#[tokio::test]
+async fn test_snapshot_ignored_when_log_is_shorter() {
+    use payments_engine::test_support::deposit;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let snapshot_path = temp_dir.path().join("engine.snapshot");
+    
+    {
+        let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+        engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+        engine.write_snapshot().await.unwrap();
+    }
+    
+    // A log replaced by another one must not be combined with the old snapshot
+    std::fs::remove_file(&log_path).unwrap();
+    {
+        let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+        assert!(engine.get_accounts().await.is_empty());
+        engine.process(deposit(1, 1, dec!(7.0))).await.unwrap();
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(7.0));
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot_ignored_when_another_generation_wrote_its_offset() {
+    use payments_engine::test_support::deposit;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let snapshot_path = temp_dir.path().join("engine.snapshot");
+    let unused_snapshot = temp_dir.path().join("unused.snapshot");
+    
+    let offset = {
+        let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+        engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+        engine.write_snapshot().await.unwrap()
+    };
+    
+    // A replacement log, longer than the snapshot's offset and restarted before it
+    std::fs::remove_file(&log_path).unwrap();
+    drop(engine_with_snapshot(&log_path, &unused_snapshot).await);
+    {
+        let engine = engine_with_snapshot(&log_path, &unused_snapshot).await;
+        engine.process(deposit(1, 1, dec!(7.0))).await.unwrap();
+        engine.process(deposit(2, 2, dec!(3.0))).await.unwrap();
+    }
+    assert!(std::fs::metadata(&log_path).unwrap().len() >= offset);
+    
+    let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(7.0));
+    assert_eq!(engine.get_account(2).await.unwrap().available, dec!(3.0));
+}
+
+#[tokio::test]
+async fn test_snapshot_verification_catches_corruption_and_broken_invariants() {
+    use payments_engine::engine_snapshot::EngineSnapshot;
+    use payments_engine::test_support::{deposit, dispute};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let snapshot_path = temp_dir.path().join("engine.snapshot");
+    
+    {
+        let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+        engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+        engine.process(dispute(1, 1)).await.unwrap();
+        engine.write_snapshot().await.unwrap();
+    }
+    
+    let snapshot = EngineSnapshot::load_verified(&snapshot_path).await.unwrap();
+    assert!(snapshot.violations().is_empty());
+    
+    // A flipped byte fails the checksum, for the server too
+    let mut bytes = std::fs::read(&snapshot_path).unwrap();
+    bytes[10] ^= 0xff;
+    let corrupt_path = temp_dir.path().join("corrupt.snapshot");
+    std::fs::write(&corrupt_path, bytes).unwrap();
+    assert!(EngineSnapshot::load_verified(&corrupt_path).await.is_err());
+    assert!(EngineSnapshot::load(&corrupt_path).await.is_err());
+    
+    // Well formed, but not a state the engine can be in
+    let mut broken = snapshot.clone();
+    broken.tx_ids.push(1);
+    broken.accounts.push(broken.accounts[0].clone());
+    broken.transactions[0].1.client = 9;
+    let violations = broken.violations();
+    assert_eq!(violations, vec![
+        "client 1 has more than one account".to_string(),
+        "tx 1 is registered twice".to_string(),
+        "stored tx 1 belongs to client 9 without an account".to_string(),
+    ]);
+    broken.save(&corrupt_path).await.unwrap();
+    assert!(EngineSnapshot::load_verified(&corrupt_path).await.is_err());
+}
+
+#[tokio::test]
+async fn test_snapshot_verify_and_restore_commands() {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    use payments_engine::test_support::{deposit, withdrawal};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let snapshot_path = temp_dir.path().join("engine.snapshot");
+    
+    {
+        let engine = engine_with_snapshot(&log_path, &snapshot_path).await;
+        engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+        engine.write_snapshot().await.unwrap();
+        engine.process(withdrawal(1, 2, dec!(30.0))).await.unwrap();
+    }
+    
+    let output = cargo_bin_cmd!("payments-engine")
+        .args(["snapshot", "verify"])
+        .arg(&snapshot_path)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(String::from_utf8(output).unwrap().contains("1 events"));
+    
+    // The snapshot's deposit plus the withdrawal logged after it
+    let logged = std::fs::read(&log_path).unwrap();
+    let output = cargo_bin_cmd!("payments-engine")
+        .args(["snapshot", "restore"])
+        .arg(&snapshot_path)
+        .arg("--log")
+        .arg(&log_path)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(String::from_utf8(output.stdout).unwrap().contains("1,70.0000,0.0000,70.0000,false"));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("replayed 1 logged after it"));
+    
+    // Restoring only reads the log, no generation marker is left behind
+    assert_eq!(std::fs::read(&log_path).unwrap(), logged);
+    
+    // Same length and generations, different bytes before the snapshot's offset
+    let rewritten_log = temp_dir.path().join("rewritten.log");
+    let mut rewritten = logged.clone();
+    rewritten[30] ^= 0x01;
+    std::fs::write(&rewritten_log, &rewritten).unwrap();
+    cargo_bin_cmd!("payments-engine")
+        .args(["snapshot", "restore"])
+        .arg(&snapshot_path)
+        .arg("--log")
+        .arg(&rewritten_log)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("checksum"));
+    
+    // Restoring against a log the snapshot doesn't belong to is refused, not papered over
+    let other_log = temp_dir.path().join("other.log");
+    std::fs::write(&other_log, b"").unwrap();
+    cargo_bin_cmd!("payments-engine")
+        .args(["snapshot", "restore"])
+        .arg(&snapshot_path)
+        .arg("--log")
+        .arg(&other_log)
+        .assert()
+        .failure();
+}
+
+// ============================================================================
+// REJECT REPLAY TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_corrected_resubmission_supersedes_rejection() {
+    use payments_engine::corrections::Corrections;
+    use payments_engine::models::CorrectionRow;
+    use payments_engine::test_support::{deposit, withdrawal};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("events.log"), 4, cold_storage).await.unwrap();
+    
+    engine.process(deposit(1, 1, dec!(100.0))).await.unwrap();
+    
+    // Sent under the wrong client, which has no funds
+    let original = withdrawal(9, 2, dec!(40.0));
+    assert!(engine.process(original.clone()).await.is_err());
+    
+    let mut corrections = Corrections::new();
+    corrections.add(CorrectionRow { tx: 2, field: "client".to_string(), value: "1".to_string() });
+    let corrected = corrections.apply(&original).unwrap();
+    assert_eq!((corrected.client, corrected.tx, corrected.amount), (1, 2, Some(dec!(40.0))));
+    
+    engine.resubmit(&original, corrected).await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(60.0));
+    
+    let timeline = engine.account_timeline(9).await.unwrap();
+    let rejection = timeline.entries.iter().find(|entry| entry.tx == 2).unwrap();
+    assert!(rejection.reason.is_some());
+    assert_eq!(rejection.superseded_by, Some(2));
+    
+    let unknown = Corrections::new();
+    let mut bad = Corrections::new();
+    bad.add(CorrectionRow { tx: 2, field: "colour".to_string(), value: "red".to_string() });
+    assert_eq!(unknown.apply(&original).unwrap().client, 9);
+    assert!(bad.apply(&original).is_err());
+}
+
+#[tokio::test]
+async fn test_replay_rejects_appends_corrected_rows_to_log() {
+    use payments_engine::compat::CompatConfig;
+    use payments_engine::test_support::deposit;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let rejects = temp_dir.path().join("rejects.csv");
+    let map = temp_dir.path().join("corrections.csv");
+    
+    {
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    }
+    
+    std::fs::write(
+        &rejects,
+        "type,client,tx,amount,reason\nwithdrawal,2,6,3.0,insufficient funds\nwithdrawal,4,7,1.0,insufficient funds\n",
+    )
+    .unwrap();
+    std::fs::write(&map, "tx,field,value\n6,client,1\n").unwrap();
+    
+    // A log written as CSV keeps its marks as CSV
+    let csv_log_path = temp_dir.path().join("events.csv.log");
+    std::fs::write(&csv_log_path, "deposit,1,1,10.0\n").unwrap();
+    
+    for log_path in [log_path, csv_log_path] {
+        payments_engine::cli::replay_rejects(rejects.clone(), Some(map.clone()), log_path.clone(), CompatConfig::default(), Default::default())
+            .await
+            .unwrap();
+        
+        // Only the corrected row applies, the other is still short of funds
+        let events = logged_events(&log_path).await;
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[1].client, events[1].tx, events[1].amount), (1, 6, Some(dec!(3.0))));
+        
+        // The supersede mark outlives the run that resubmitted
+        let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+        let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+        engine.rebuild_from_events().await.unwrap();
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(7.0));
+        let timeline = engine.account_timeline(2).await.unwrap();
+        let rejection = timeline.entries.iter().find(|entry| entry.tx == 6).unwrap();
+        assert!(rejection.reason.is_some());
+        assert_eq!(rejection.superseded_by, Some(6));
+    }
+}
+
+// ============================================================================
+// DURABILITY TESTS
+// ============================================================================
+
+#[test]
+fn test_durability_policy_parsing() {
+    use payments_engine::event_store::DurabilityPolicy;
+    use std::time::Duration;
+    
+    assert_eq!("buffered".parse::<DurabilityPolicy>().unwrap(), DurabilityPolicy::Buffered);
+    assert_eq!("per-write".parse::<DurabilityPolicy>().unwrap(), DurabilityPolicy::PerWrite);
+    assert_eq!(
+        "interval:50".parse::<DurabilityPolicy>().unwrap(),
+        DurabilityPolicy::Interval(Duration::from_millis(50))
+    );
+    assert_eq!(
+        "group:2".parse::<DurabilityPolicy>().unwrap(),
+        DurabilityPolicy::GroupCommit(Duration::from_millis(2))
+    );
+    assert!("group".parse::<DurabilityPolicy>().is_err());
+    assert!("per-write:3".parse::<DurabilityPolicy>().is_err());
+    assert!("eventually".parse::<DurabilityPolicy>().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_group_commit_shares_syncs_between_concurrent_writes() {
+    use payments_engine::event_store::DurabilityPolicy;
+    use payments_engine::test_support::deposit;
+    use std::time::{Duration, Instant};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(log_path.clone(), 4, cold_storage)
+            .await
+            .unwrap()
+            .with_durability(DurabilityPolicy::GroupCommit(Duration::from_millis(20)))
+            .unwrap(),
+    );
+    
+    // One sync per append would take 200 windows, shared ones take a handful
+    let started = Instant::now();
+    let mut writes = Vec::new();
+    for tx in 1..=200u32 {
+        let engine = engine.clone();
+        writes.push(tokio::spawn(async move {
+            engine.process(deposit((tx % 16) as u16, tx, dec!(1.0))).await
+        }));
+    }
+    for write in writes {
+        write.await.unwrap().unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+    
+    assert_eq!(logged_events(&log_path).await.len(), 200);
+}
+
+#[tokio::test]
+async fn test_every_durability_policy_logs_acknowledged_events() {
+    use payments_engine::event_store::DurabilityPolicy;
+    use payments_engine::test_support::deposit;
+    use std::time::Duration;
+    
+    for durability in [
+        DurabilityPolicy::Buffered,
+        DurabilityPolicy::PerWrite,
+        DurabilityPolicy::Interval(Duration::from_millis(5)),
+        DurabilityPolicy::GroupCommit(Duration::ZERO),
+    ] {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("events.log");
+        {
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let engine = ScalableEngine::new(log_path.clone(), 2, cold_storage)
+                .await
+                .unwrap()
+                .with_durability(durability)
+                .unwrap();
+            for tx in 1..=3 {
+                engine.process(deposit(1, tx, dec!(2.0))).await.unwrap();
+            }
+        }
+        
+        assert_eq!(logged_events(&log_path).await.len(), 3, "{:?}", durability);
+    }
+    
+    // Another handle may already be appending, the policy can't change under it
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("events.log"), 2, cold_storage).await.unwrap();
+    let shared = engine.clone();
+    assert!(engine.with_durability(DurabilityPolicy::PerWrite).is_err());
+    shared.process(deposit(1, 1, dec!(2.0))).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_batched_appends_keep_every_record_intact() {
+    use payments_engine::event_store::{DurabilityPolicy, EventStore};
+    use payments_engine::test_support::{deposit, transfer};
+    use std::collections::HashSet;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let store = Arc::new(EventStore::new(log_path.clone(), DurabilityPolicy::Buffered).await.unwrap());
+    
+    // Far more concurrent appends than fit in one batch
+    let mut appends = Vec::new();
+    for tx in 1..=3000u32 {
+        let store = store.clone();
+        appends.push(tokio::spawn(async move {
+            let row = if tx % 2 == 0 { deposit(1, tx, dec!(1.5)) } else { transfer(2, 3, tx, dec!(0.25)) };
+            store.append(&row).await
+        }));
+    }
+    for append in appends {
+        append.await.unwrap().unwrap();
+    }
+    
+    let events = store.replay().await.unwrap();
+    assert_eq!(events.len(), 3000);
+    let tx_ids: HashSet<u32> = events.iter().map(|event| event.tx).collect();
+    assert_eq!(tx_ids.len(), 3000);
+    assert!(events.iter().filter(|event| event.tx % 2 == 1).all(|event| event.to == Some(3)));
+}
+
+#[tokio::test]
+async fn test_engine_without_event_log() {
+    use payments_engine::test_support::{deposit, withdrawal};
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage);
+    
+    // Nothing to replay, writes are accepted right away
+    engine.rebuild_from_events().await.unwrap();
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    engine.process(withdrawal(1, 2, dec!(4.0))).await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(6.0));
+    
+    assert!(engine.trace_transaction(1).await.is_err());
+    assert_eq!(engine.account_timeline(1).await.unwrap().entries.len(), 2);
+}
+
+// ============================================================================
+// BATCH PROCESSING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_process_batch_keeps_client_order_and_logs_applied_rows() {
+    use payments_engine::test_support::{deposit, dispute, transfer, withdrawal};
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap();
+    engine.rebuild_from_events().await.unwrap();
+    
+    let mut rows = Vec::new();
+    for client in 1..=20u16 {
+        let base = client as u32 * 10;
+        rows.push(withdrawal(client, base, dec!(1.0)));
+        rows.push(deposit(client, base + 1, dec!(10.0)));
+        rows.push(withdrawal(client, base + 2, dec!(4.0)));
+    }
+    // Tx 11 is client 1's, the claim by client 2 comes second whatever runs first
+    rows.push(deposit(2, 11, dec!(99.0)));
+    rows.push(dispute(2, 11));
+    // Client 21 only has funds once the transfer lands
+    rows.push(transfer(3, 21, 500, dec!(5.0)));
+    rows.push(withdrawal(21, 501, dec!(5.0)));
+    
+    let results = engine.process_batch(rows.clone()).await;
+    assert_eq!(results.len(), rows.len());
+    for client in 0..20 {
+        assert!(matches!(results[client * 3], Err(ProcessingError::InsufficientFunds)));
+        assert!(results[client * 3 + 1].is_ok());
+        assert!(results[client * 3 + 2].is_ok());
+    }
+    assert!(matches!(results[60], Err(ProcessingError::DuplicateTransaction)));
+    assert!(matches!(results[61], Err(ProcessingError::TransactionNotFound)));
+    assert!(results[62].is_ok());
+    assert!(results[63].is_ok());
+    
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(6.0));
+    assert_eq!(engine.get_account(3).await.unwrap().available, dec!(1.0));
+    assert_eq!(engine.get_account(21).await.unwrap().available, dec!(0.0));
+    
+    // Only applied rows are logged, and replaying them gives the same balances
+    assert_eq!(logged_events(&log_path).await.len(), 42);
+    drop(engine);
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let replayed = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    replayed.rebuild_from_events().await.unwrap();
+    assert_eq!(replayed.get_account(3).await.unwrap().available, dec!(1.0));
+    assert_eq!(replayed.get_account(21).await.unwrap().available, dec!(0.0));
+}
+
+/// In-memory log whose appends fail while `failing` is set
+#[derive(Default)]
+struct FailingLog {
+    inner: payments_engine::event_store::InMemoryEventLog,
+    failing: std::sync::atomic::AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl payments_engine::event_store::EventLog for FailingLog {
+    async fn append_batch(&self, txs: &[TransactionRow]) -> anyhow::Result<()> {
+        if self.failing.load(std::sync::atomic::Ordering::SeqCst) {
+            anyhow::bail!("disk full");
+        }
+        self.inner.append_batch(txs).await
+    }
+    
+    async fn replay_from(&self, offset: u64) -> anyhow::Result<Vec<TransactionRow>> {
+        self.inner.replay_from(offset).await
+    }
+    
+    async fn end_offset(&self) -> anyhow::Result<u64> {
+        self.inner.end_offset().await
+    }
+    
+    async fn has_history(&self) -> anyhow::Result<bool> {
+        self.inner.has_history().await
+    }
+    
+    async fn open_generation(&self) -> anyhow::Result<u64> {
+        self.inner.open_generation().await
+    }
+    
+    async fn sync(&self) -> anyhow::Result<()> {
+        self.inner.sync().await
+    }
+    
+    fn path(&self) -> &std::path::Path {
+        self.inner.path()
+    }
+}
+
+#[tokio::test]
+async fn test_failed_append_reverts_what_it_would_have_logged() {
+    use payments_engine::test_support::{deposit, dispute, transfer, withdrawal};
+    use payments_engine::ProcessingError;
+    use std::sync::atomic::Ordering;
+    
+    let log = Arc::new(FailingLog::default());
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::from_event_log(log.clone(), &Default::default(), cold_storage).await.unwrap();
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    engine.process(deposit(2, 2, dec!(5.0))).await.unwrap();
+    
+    let rows = vec![
+        deposit(1, 3, dec!(4.0)),
+        withdrawal(1, 4, dec!(2.0)),
+        dispute(2, 2),
+        transfer(1, 2, 5, dec!(1.0)),
+        withdrawal(3, 6, dec!(1.0)),
+    ];
+    log.failing.store(true, Ordering::SeqCst);
+    let results = engine.process_batch(rows.clone()).await;
+    assert!(results[..4].iter().all(|result| matches!(result, Err(ProcessingError::EventLogUnavailable))), "{:?}", results);
+    assert!(matches!(results[4], Err(ProcessingError::InsufficientFunds)));
+    
+    // Balances, the disputed deposit and the claimed ids are as the log has them
+    let (one, two) = (engine.get_account(1).await.unwrap(), engine.get_account(2).await.unwrap());
+    assert_eq!((one.available, one.held), (dec!(10.0), dec!(0.0)));
+    assert_eq!((two.available, two.held), (dec!(5.0), dec!(0.0)));
+    assert!(matches!(engine.process(deposit(4, 9, dec!(1.0))).await, Err(ProcessingError::EventLogUnavailable)));
+    assert_eq!(engine.get_account(4).await.map(|account| account.available), Some(dec!(0.0)));
+    
+    // So the same rows apply once the log is back
+    log.failing.store(false, Ordering::SeqCst);
+    let results = engine.process_batch(rows).await;
+    assert!(results[..4].iter().all(Result::is_ok), "{:?}", results);
+    engine.process(deposit(4, 9, dec!(1.0))).await.unwrap();
+    let (one, two) = (engine.get_account(1).await.unwrap(), engine.get_account(2).await.unwrap());
+    assert_eq!((one.available, one.held), (dec!(11.0), dec!(0.0)));
+    assert_eq!((two.available, two.held), (dec!(1.0), dec!(5.0)));
+    assert_eq!(payments_engine::event_store::EventLog::replay(&log.inner).await.unwrap().len(), 7);
+}
+
+#[tokio::test]
+async fn test_failed_unlock_append_relocks_the_account() {
+    use payments_engine::compat::{CompatConfig, LockPolicy};
+    use payments_engine::test_support::{chargeback, deposit, dispute};
+    use payments_engine::ProcessingError;
+    use std::sync::atomic::Ordering;
+
+    let compat = CompatConfig { lock_policy: LockPolicy::Hold, ..CompatConfig::strict() };
+    let log = Arc::new(FailingLog::default());
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::from_event_log(log.clone(), &Default::default(), cold_storage)
+        .await
+        .unwrap()
+        .with_compat(compat)
+        .unwrap();
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    engine.process(deposit(1, 2, dec!(5.0))).await.unwrap();
+    engine.process(dispute(1, 1)).await.unwrap();
+    engine.process(chargeback(1, 1)).await.unwrap();
+    engine.process(deposit(1, 3, dec!(3.0))).await.unwrap();
+
+    // Still locked, with the deposit still held, as the log has it
+    log.failing.store(true, Ordering::SeqCst);
+    assert!(matches!(engine.unlock_account(1).await, Err(ProcessingError::EventLogUnavailable)));
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held, account.locked), (dec!(5.0), dec!(3.0), true));
+
+    log.failing.store(false, Ordering::SeqCst);
+    engine.unlock_account(1).await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held, account.locked), (dec!(8.0), dec!(0), false));
+}
+
+/// Cold store counting its point reads
+#[derive(Default)]
+struct CountingStore {
+    inner: InMemoryStore,
+    gets: std::sync::atomic::AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl TransactionStore for CountingStore {
+    async fn get(&self, tx_id: u32) -> Option<payments_engine::StoredTransaction> {
+        self.gets.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.inner.get(tx_id).await
+    }
+
+    async fn put(&self, tx_id: u32, tx: payments_engine::StoredTransaction) -> anyhow::Result<()> {
+        self.inner.put(tx_id, tx).await
+    }
+
+    async fn remove(&self, tx_id: u32) -> anyhow::Result<()> {
+        self.inner.remove(tx_id).await
+    }
+
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u32, payments_engine::StoredTransaction)>> {
+        self.inner.list_client(client, after, limit).await
+    }
+
+    async fn compact(&self, retain: payments_engine::storage::Retain) -> anyhow::Result<payments_engine::storage::CompactionReport> {
+        self.inner.compact(retain).await
+    }
+}
+
+#[tokio::test]
+async fn test_cold_dispute_reads_its_target_once_and_reverts() {
+    use payments_engine::test_support::{deposit, dispute};
+    use payments_engine::ProcessingError;
+    use std::sync::atomic::Ordering;
+
+    let log = Arc::new(FailingLog::default());
+    let store = Arc::new(CountingStore::default());
+    let engine = ScalableEngine::from_event_log(log.clone(), &Default::default(), store.clone()).await.unwrap();
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    assert!(engine.force_migrate_cold(1).await);
+    for _ in 0..50 {
+        if engine.hot_transactions(1).await.unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    // The undo record comes from the handler's own lookup
+    store.gets.store(0, Ordering::SeqCst);
+    log.failing.store(true, Ordering::SeqCst);
+    assert!(matches!(engine.process(dispute(1, 1)).await, Err(ProcessingError::EventLogUnavailable)));
+    assert_eq!(store.gets.load(Ordering::SeqCst), 1);
+    assert!(!store.inner.get(1).await.unwrap().disputed);
+
+    log.failing.store(false, Ordering::SeqCst);
+    engine.process(dispute(1, 1)).await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(0.0), dec!(10.0)));
+}
+
+// ============================================================================
+// PERSISTENT TX REGISTRY TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_tx_registry_refuses_ids_from_earlier_runs() {
+    use payments_engine::test_support::{deposit, withdrawal};
+    use payments_engine::ProcessingError;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let registry_dir = temp_dir.path().join("registry");
+    let engine_at = |log: &str| {
+        let log_path = temp_dir.path().join(log);
+        let registry_dir = registry_dir.clone();
+        async move {
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let engine = ScalableEngine::new(log_path, 4, cold_storage)
+                .await
+                .unwrap()
+                .with_tx_registry_dir(&registry_dir)
+                .await
+                .unwrap();
+            engine.rebuild_from_events().await.unwrap();
+            engine
+        }
+    };
+    
+    let first = engine_at("first.log").await;
+    first.process(deposit(1, 1, dec!(2.0))).await.unwrap();
+    first.process(deposit(1, 2, dec!(1.0))).await.unwrap();
+    // Rejected, so its id is released again
+    assert!(first.process(withdrawal(1, 3, dec!(9.0))).await.is_err());
+    drop(first);
+    
+    // A log without that history still knows the ids are taken
+    let fresh = engine_at("fresh.log").await;
+    assert!(matches!(fresh.process(deposit(2, 1, dec!(5.0))).await, Err(ProcessingError::DuplicateTransaction)));
+    fresh.process(deposit(2, 3, dec!(5.0))).await.unwrap();
+    drop(fresh);
+    
+    // Replaying the original log isn't mistaken for duplicates of the registry file
+    let replayed = engine_at("first.log").await;
+    assert_eq!(replayed.get_account(1).await.unwrap().available, dec!(3.0));
+    assert!(matches!(replayed.process(deposit(1, 3, dec!(1.0))).await, Err(ProcessingError::DuplicateTransaction)));
+    replayed.process(deposit(1, 4, dec!(1.0))).await.unwrap();
+    drop(replayed);
+    
+    // An engine of another shard count keeps the directory's, and its ids
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let other = ScalableEngine::new(temp_dir.path().join("other.log"), 8, cold_storage)
+        .await
+        .unwrap()
+        .with_tx_registry_dir(&registry_dir)
+        .await
+        .unwrap();
+    other.rebuild_from_events().await.unwrap();
+    for tx in 1..=4 {
+        assert!(matches!(other.process(deposit(3, tx, dec!(1.0))).await, Err(ProcessingError::DuplicateTransaction)));
+    }
+    other.process(deposit(3, 5, dec!(1.0))).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tx_registry_files_only_ids_whose_events_were_logged() {
+    use payments_engine::tx_registry_actor::ShardedTxRegistry;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let registry = ShardedTxRegistry::persistent(2, temp_dir.path()).await.unwrap();
+    assert!(registry.register(7).await.unwrap());
+    assert!(registry.register(8).await.unwrap());
+    registry.persist(&[8]).await.unwrap();
+    // Persisted then rolled back ids leave the file too
+    assert!(registry.register(9).await.unwrap());
+    registry.persist(&[9]).await.unwrap();
+    assert!(registry.unregister(9).await.unwrap());
+    registry.shutdown().await;
+    
+    // As after a crash before tx 7's event was logged
+    let reopened = ShardedTxRegistry::persistent(2, temp_dir.path()).await.unwrap();
+    reopened.finish_replay().await.unwrap();
+    assert!(!reopened.contains(7).await.unwrap());
+    assert!(reopened.contains(8).await.unwrap());
+    assert!(!reopened.contains(9).await.unwrap());
+    assert!(reopened.register(7).await.unwrap());
+}
+
+// ============================================================================
+// EVENT BUS TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_event_bus_publishes_domain_events() {
+    use payments_engine::compat::CompatConfig;
+    use payments_engine::events::DomainEvent;
+    use payments_engine::test_support::{chargeback, deposit, dispute, withdrawal};
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage).with_compat(CompatConfig::extended()).unwrap();
+    let mut events = engine.subscribe();
+    
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    // An acknowledged retry publishes nothing
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    assert!(engine.process(withdrawal(1, 2, dec!(50.0))).await.is_err());
+    let batch = engine.process_batch(vec![dispute(1, 1), chargeback(1, 1)]).await;
+    assert!(batch.iter().all(|result| result.is_ok()));
+    
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    assert_eq!(received, vec![
+        DomainEvent::TransactionApplied {
+            tx: 1,
+            client: 1,
+            tx_type: TransactionType::Deposit,
+            amount: Some(dec!(10.0)),
+            to: None,
+        },
+        DomainEvent::TransactionRejected {
+            tx: 2,
+            client: 1,
+            tx_type: TransactionType::Withdrawal,
+            code: "insufficient_funds",
+        },
+        DomainEvent::TransactionApplied {
+            tx: 1,
+            client: 1,
+            tx_type: TransactionType::Dispute,
+            amount: None,
+            to: None,
+        },
+        DomainEvent::DisputeOpened { client: 1, tx: 1 },
+        DomainEvent::TransactionApplied {
+            tx: 1,
+            client: 1,
+            tx_type: TransactionType::Chargeback,
+            amount: None,
+            to: None,
+        },
+        DomainEvent::AccountLocked { client: 1, tx: 1 },
+    ]);
+    
+    // Serialized tagged by event name, for subscribers forwarding them out of process
+    let json = serde_json::to_value(&received[5]).unwrap();
+    assert_eq!(json, serde_json::json!({"event": "account_locked", "client": 1, "tx": 1}));
+}
+
+// ============================================================================
+// DUPLICATE REPORT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_duplicate_report_groups_by_source() {
+    use payments_engine::duplicates::{DuplicateTracker, MAX_DUPLICATE_IDS_PER_SOURCE, MAX_DUPLICATE_SOURCES, OTHER_SOURCES};
+    use payments_engine::ProcessingError;
+    
+    let tracker = DuplicateTracker::new();
+    tracker.record("b.csv", 7);
+    tracker.record("a.csv", 3);
+    tracker.record("b.csv", 9);
+    // Only duplicate rejections count
+    tracker.record_outcome("a.csv", 4, &Ok(()));
+    tracker.record_outcome("a.csv", 5, &Err(ProcessingError::InsufficientFunds));
+    tracker.record_outcome("a.csv", 6, &Err(ProcessingError::DuplicateTransaction));
+    
+    let report = tracker.report();
+    assert_eq!(report.total, 4);
+    let sources: Vec<_> = report.sources.iter().map(|s| (s.source.as_str(), s.count, s.tx_ids.clone())).collect();
+    assert_eq!(sources, vec![("a.csv", 2, vec![3, 6]), ("b.csv", 2, vec![7, 9])]);
+    assert_eq!(tracker.to_csv().await.unwrap(), "source,count,tx_ids\na.csv,2,3 6\nb.csv,2,7 9\n");
+    assert!(report.summary().contains("  b.csv: 2 (tx 7, 9)"));
+    
+    // Past the bound ids are dropped, the count is not
+    let tracker = DuplicateTracker::new();
+    for tx in 0..MAX_DUPLICATE_IDS_PER_SOURCE as u32 + 5 {
+        tracker.record("feed", tx);
+    }
+    let report = tracker.report();
+    assert_eq!(report.total, MAX_DUPLICATE_IDS_PER_SOURCE as u64 + 5);
+    assert!(report.sources[0].truncated());
+    assert_eq!(report.sources[0].tx_ids.len(), MAX_DUPLICATE_IDS_PER_SOURCE);
+    
+    // Names holding commas or quotes stay one field
+    let tracker = DuplicateTracker::new();
+    tracker.record("feeds/a,b \"eu\".csv", 1);
+    assert_eq!(tracker.to_csv().await.unwrap(), "source,count,tx_ids\n\"feeds/a,b \"\"eu\"\".csv\",1,1\n");
+    
+    // Sources past the bound are counted together
+    let tracker = DuplicateTracker::new();
+    for source in 0..MAX_DUPLICATE_SOURCES as u32 + 3 {
+        tracker.record(&format!("10.0.0.1:{}", source), source);
+    }
+    tracker.record("10.0.0.1:0", 7);
+    let report = tracker.report();
+    assert_eq!(report.total, MAX_DUPLICATE_SOURCES as u64 + 4);
+    assert_eq!(report.sources.len(), MAX_DUPLICATE_SOURCES + 1);
+    let other = report.sources.iter().find(|s| s.source == OTHER_SOURCES).unwrap();
+    assert_eq!(other.count, 3);
+}
+
+#[test]
+fn test_cli_prints_duplicate_summary() {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,1,10.0\ndeposit,2,2,5.0\n").unwrap();
+    
+    let output = cargo_bin_cmd!("payments-engine")
+        .arg(&input)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(String::from_utf8(output.stdout).unwrap().contains("1,10.0000,0.0000,10.0000,false"));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("duplicates: 1 rejected"));
+    assert!(stderr.contains(&format!("{}: 1 (tx 1)", input.display())));
+    
+    // A clean run prints no section
+    std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,10.0\n").unwrap();
+    let output = cargo_bin_cmd!("payments-engine").arg(&input).assert().success().get_output().clone();
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("duplicates"));
+}
+
+// ============================================================================
+// SHARD ROUTING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_consistent_hash_routing_spreads_even_client_ids() {
+    use payments_engine::routing::{ConsistentHashRouting, ModuloRouting, RoutingStrategy};
+    
+    let storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, storage).with_routing(Arc::new(ConsistentHashRouting)).unwrap();
+    for client in (0..400u16).step_by(2) {
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client,
+            tx: client as u32,
+            amount: Some(dec!(1)),
+            to: None,
+        }).await.unwrap();
+    }
+    
+    // Modulo leaves the odd shards idle for even ids, the hash uses all of them
+    assert!((0..400u16).step_by(2).all(|client| ModuloRouting.shard(client, 4) % 2 == 0));
+    let per_shard: Vec<u64> = (0..4).map(|shard| engine.shard_totals(shard).unwrap().accounts).collect();
+    assert_eq!(per_shard.iter().sum::<u64>(), 200);
+    assert!(per_shard.iter().all(|&accounts| accounts >= 30), "{:?}", per_shard);
+    assert_eq!(engine.account_totals().total, dec!(200));
+    
+    // Growing the shard count moves only the clients landing on the new shard
+    let moved = (0..1000u16)
+        .filter(|&client| ConsistentHashRouting.shard(client, 8) != ConsistentHashRouting.shard(client, 9))
+        .count();
+    assert!(moved < 200, "{} of 1000 clients moved", moved);
+    
+    // Clones already route with the current strategy, it can't be swapped under them
+    let shared = engine.clone();
+    assert!(engine.with_routing(Arc::new(ModuloRouting)).is_err());
+    assert_eq!(shared.account_totals().total, dec!(200));
+}
+
+#[tokio::test]
+async fn test_table_routing_pins_clients_to_shards() {
+    use payments_engine::routing::TableRouting;
+    use std::collections::HashMap;
+    
+    let storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let routing = TableRouting::new(HashMap::from([(1, 3), (2, 3)]));
+    let engine = ScalableEngine::without_event_log(4, storage).with_routing(Arc::new(routing)).unwrap();
+    assert_eq!((engine.shard_of(1), engine.shard_of(2), engine.shard_of(5)), (3, 3, 1));
+    
+    for (client, tx) in [(1, 1), (2, 2), (5, 3)] {
+        engine.process(TransactionRow {
+            tx_type: TransactionType::Deposit,
+            client,
+            tx,
+            amount: Some(dec!(10)),
+            to: None,
+        }).await.unwrap();
+    }
+    assert_eq!(engine.shard_totals(3).unwrap().accounts, 2);
+    assert_eq!(engine.shard_totals(1).unwrap().accounts, 1);
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(10));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_reshard_while_processing_keeps_every_message_and_one_actor_per_client() {
+    use payments_engine::routing::{ConsistentHashRouting, RoutingStrategy};
+    use payments_engine::test_support::deposit;
+    
+    let storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::without_event_log(4, storage).with_routing(Arc::new(ConsistentHashRouting)).unwrap());
+    
+    // Each writer owns 25 clients and deposits 1 into each of them 50 times
+    let writers: Vec<_> = (0..8u16)
+        .map(|writer| {
+            let engine = engine.clone();
+            tokio::spawn(async move {
+                for round in 0..50u32 {
+                    for offset in 0..25u16 {
+                        let tx = writer as u32 * 100_000 + round * 100 + offset as u32 + 1;
+                        engine.process(deposit(writer * 25 + offset, tx, dec!(1))).await.unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for shards in [7, 3, 5] {
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        engine.reshard(shards).await.unwrap();
+    }
+    for writer in writers {
+        writer.await.unwrap();
+    }
+    
+    // A second actor for a client would have split its deposits
+    assert_eq!(engine.num_shards(), 5);
+    for client in 0..200u16 {
+        assert_eq!(engine.get_account(client).await.unwrap().available, dec!(50), "client {}", client);
+    }
+    let stats = engine.stats().await;
+    assert_eq!(stats.len(), 5);
+    assert_eq!(stats.iter().map(|shard| shard.actors).sum::<usize>(), 200);
+    for shard in &stats {
+        let clients = (0..200u16).filter(|&client| ConsistentHashRouting.shard(client, 5) == shard.shard).count();
+        assert_eq!(engine.shard_totals(shard.shard).unwrap().accounts, clients as u64);
+        assert_eq!(shard.actors, clients);
+    }
+    assert_eq!(engine.account_totals().total, dec!(10000));
+}
+
+#[tokio::test]
+async fn test_reshard_moves_only_the_clients_consistent_hashing_reassigns() {
+    use payments_engine::routing::{ConsistentHashRouting, RoutingStrategy};
+    use payments_engine::test_support::deposit;
+    
+    let storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(8, storage).with_routing(Arc::new(ConsistentHashRouting)).unwrap();
+    for client in 0..1000u16 {
+        engine.process(deposit(client, client as u32 + 1, dec!(1))).await.unwrap();
+    }
+    
+    let reassigned = (0..1000u16)
+        .filter(|&client| ConsistentHashRouting.shard(client, 8) != ConsistentHashRouting.shard(client, 9))
+        .count();
+    assert_eq!(engine.reshard(9).await.unwrap(), reassigned);
+    assert!(reassigned < 200, "{} of 1000 clients moved", reassigned);
+    assert_eq!(engine.shard_totals(8).unwrap().accounts, reassigned as u64);
+    
+    // Removing the shard sends the same clients back
+    assert_eq!(engine.reshard(8).await.unwrap(), reassigned);
+    assert!(engine.shard_totals(8).is_none());
+    assert!(engine.reshard(0).await.is_err());
+    assert_eq!(engine.num_shards(), 8);
+    
+    engine.process(deposit(7, 5000, dec!(2))).await.unwrap();
+    assert_eq!(engine.get_account(7).await.unwrap().available, dec!(3));
+    assert_eq!(engine.account_totals().accounts, 1000);
+}
+
+// ============================================================================
+// ESCALATION TESTS
+// ============================================================================
+
+#[derive(Default)]
+struct CollectingSink {
+    sent: std::sync::Mutex<Vec<payments_engine::escalation::Escalation>>,
+}
+
+#[async_trait::async_trait]
+impl payments_engine::escalation::EscalationSink for CollectingSink {
+    async fn send(&self, escalation: &payments_engine::escalation::Escalation) -> anyhow::Result<()> {
+        self.sent.lock().unwrap().push(escalation.clone());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_escalates_aged_disputes_and_high_value_chargebacks() {
+    use payments_engine::escalation::{EscalationMonitor, EscalationPolicy, EscalationReason};
+    use payments_engine::test_support::{chargeback, deposit, dispute, resolve, ManualClock};
+    use std::time::Duration;
+    
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage);
+    let mut events = engine.subscribe();
+    let sink = Arc::new(CollectingSink::default());
+    let clock = Arc::new(ManualClock::default());
+    let policy = EscalationPolicy {
+        max_open_age: Duration::from_secs(7 * 24 * 60 * 60),
+        high_value_chargeback: dec!(1000),
+    };
+    let monitor = EscalationMonitor::new(policy, sink.clone()).with_clock(clock.clone());
+    
+    // Events are observed as they happen, as the spawned monitor does
+    let mut rows = Vec::new();
+    for (client, tx, amount) in [(1, 1, dec!(50)), (2, 2, dec!(5000)), (3, 3, dec!(20))] {
+        rows.extend([deposit(client, tx, amount), dispute(client, tx)]);
+    }
+    rows.extend([resolve(3, 3), chargeback(2, 2)]);
+    for row in rows {
+        engine.process(row).await.unwrap();
+        while let Ok(event) = events.try_recv() {
+            monitor.observe(&engine, &event).await;
+        }
+    }
+    
+    // The chargeback went out at once, the resolved dispute is forgotten
+    assert_eq!(monitor.open_disputes(), 1);
+    let sent = sink.sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 1);
+    assert_eq!((sent[0].reason, sent[0].client, sent[0].tx, sent[0].amount), (EscalationReason::HighValueChargeback, 2, 2, dec!(5000)));
+    
+    monitor.sweep().await;
+    assert_eq!(sink.sent.lock().unwrap().len(), 1);
+    
+    // Past the age the remaining dispute is escalated, once
+    clock.advance(Duration::from_secs(8 * 24 * 60 * 60));
+    monitor.sweep().await;
+    monitor.sweep().await;
+    let sent = sink.sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 2);
+    assert_eq!((sent[1].reason, sent[1].client, sent[1].amount), (EscalationReason::DisputeAged, 1, dec!(50)));
+    assert_eq!(sent[1].escalated_at - sent[1].opened_at, 8 * 24 * 60 * 60);
+}
+
+#[tokio::test]
+async fn test_webhook_sink_posts_escalation_json() {
+    use axum::{routing::post, Json, Router};
+    use payments_engine::escalation::{Escalation, EscalationReason, EscalationSink, WebhookSink};
+    use tokio::sync::mpsc;
+    
+    let (received_tx, mut received) = mpsc::unbounded_channel();
+    let app = Router::new().route(
+        "/cases",
+        post(move |Json(body): Json<serde_json::Value>| async move {
+            received_tx.send(body).unwrap();
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    
+    let escalation = Escalation {
+        reason: EscalationReason::DisputeAged,
+        client: 4,
+        tx: 9,
+        amount: dec!(12.5),
+        opened_at: 100,
+        escalated_at: 200,
+    };
+    WebhookSink::new(format!("http://{}/cases", addr)).send(&escalation).await.unwrap();
+    assert_eq!(
+        received.recv().await.unwrap(),
+        serde_json::json!({"reason": "dispute_aged", "client": 4, "tx": 9, "amount": "12.5", "opened_at": 100, "escalated_at": 200})
+    );
+    
+    // A refused delivery is an error
+    assert!(WebhookSink::new(format!("http://{}/missing", addr)).send(&escalation).await.is_err());
+}
+
+// ============================================================================
+// KAFKA CONSUMER TESTS
+// ============================================================================
+
+#[test]
+fn test_consumer_decodes_records_and_settles_refused_rows() {
+    use payments_engine::amount::AmountFormat;
+    use payments_engine::consume::{decode_record, settled};
+    use payments_engine::ProcessingError;
+    
+    let row = decode_record(br#"{"type": "deposit", "client": 2, "tx": 7, "amount": 1.5}"#, AmountFormat::default()).unwrap();
+    assert_eq!((row.tx_type, row.client, row.tx, row.amount), (TransactionType::Deposit, 2, 7, Some(dec!(1.5))));
+    assert!(decode_record(b"deposit,2,7,1.5", AmountFormat::default()).is_err());
+    assert!(decode_record(&[0xff, 0xfe], AmountFormat::default()).is_err());
+    
+    // Refused rows are committed past, a failed append is delivered again
+    assert!(settled(&Ok(())));
+    assert!(settled(&Err(ProcessingError::DuplicateTransaction)));
+    assert!(settled(&Err(ProcessingError::InsufficientFunds)));
+    assert!(!settled(&Err(ProcessingError::EventLogUnavailable)));
+    assert!(!settled(&Err(ProcessingError::StorageUnavailable)));
+}
+
+#[cfg(not(feature = "kafka"))]
+#[test]
+fn test_consume_needs_kafka_feature() {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    
+    cargo_bin_cmd!("payments-engine")
+        .args(["consume", "--brokers", "localhost:9092", "--topic", "payments"])
+        .env("RUST_LOG", "off")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("consume needs a build with the kafka feature"));
+}
+
+// ============================================================================
+// ENGINE CONFIG TESTS
+// ============================================================================
+
+#[test]
+fn test_engine_config_layers_file_and_env_over_defaults() {
+    use payments_engine::config::EngineConfig;
+    use std::collections::HashMap;
+    
+    let config = EngineConfig::from_toml(
+        r#"
+        shards = 4
+        event_log = "events.log"
+        
+        [actor]
+        idle_timeout_secs = 60
+        
+        [migration]
+        max_puts_per_sec = 100
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.shards, 4);
+    assert_eq!(config.event_log, Some("events.log".into()));
+    assert_eq!(config.actor.idle_timeout_secs, 60);
+    // Values the file leaves out keep their defaults
+    assert_eq!(config.actor.hot_cutoff_days, 90);
+    assert_eq!(config.actor.mailbox_size, 1000);
+    assert_eq!((config.migration.batch_size, config.migration.max_puts_per_sec), (500, Some(100)));
+    
+    let env: HashMap<&str, &str> = [
+        ("PAYMENTS_ENGINE_SHARDS", "8"),
+        ("PAYMENTS_ENGINE_HOT_CUTOFF_DAYS", "7"),
+        ("PAYMENTS_ENGINE_EVENT_LOG", ""),
+        ("PAYMENTS_ENGINE_MIGRATION_MAX_PUTS_PER_SEC", ""),
+        ("PAYMENTS_ENGINE_MIGRATION_LOW_RISK_HOT_DAYS", "3"),
+    ]
+    .into();
+    let config = config.with_env(|name| env.get(name).map(|value| value.to_string())).unwrap();
+    assert_eq!((config.shards, config.actor.hot_cutoff_days), (8, 7));
+    assert_eq!(config.event_log, None);
+    assert_eq!(config.migration.max_puts_per_sec, None);
+    assert_eq!(config.migration.low_risk_hot_days, Some(3));
+    assert_eq!(config.actor.idle_timeout_secs, 60);
+    
+    // Typos and values the engine can't run with are refused, not ignored
+    assert!(EngineConfig::from_toml("shard = 4").is_err());
+    assert!(EngineConfig::from_toml("[actor]\nmailbox_size = 0").is_err());
+    assert!(EngineConfig::from_toml("[migration]\nlow_risk_max_dispute_rate = 1.5").is_err());
+    assert!(EngineConfig::default().with_env(|_| Some("many".to_string())).is_err());
+}
+
+#[tokio::test]
+async fn test_engine_from_config() {
+    use payments_engine::config::EngineConfig;
+    use payments_engine::test_support::deposit;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let config = EngineConfig {
+        shards: 3,
+        event_log: Some(temp_dir.path().join("events.log")),
+        ..Default::default()
+    };
+    let engine = ScalableEngine::from_config(&config, Arc::new(InMemoryStore::new())).await.unwrap();
+    engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+    assert!((0..100).all(|client| engine.shard_of(client) < 3));
+    assert_eq!(engine.trace_transaction(1).await.unwrap().events.len(), 1);
+    
+    // Without an event log nothing is logged
+    let config = EngineConfig::default();
+    let engine = ScalableEngine::from_config(&config, Arc::new(InMemoryStore::new())).await.unwrap();
+    engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+    assert!(engine.trace_transaction(1).await.is_err());
+}
+
+#[test]
+fn test_cli_reads_engine_config_file() {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,2.0\n").unwrap();
+    let log = temp_dir.path().join("events.log");
+    let config = temp_dir.path().join("engine.toml");
+    std::fs::write(&config, format!("shards = 2\nevent_log = {:?}\n", log.display().to_string())).unwrap();
+    
+    cargo_bin_cmd!("payments-engine")
+        .arg("cli")
+        .arg(&input)
+        .arg("--config")
+        .arg(&config)
+        .assert()
+        .success();
+    assert!(std::fs::metadata(&log).unwrap().len() > 0);
+    
+    // --no-event-log wins over the file
+    std::fs::remove_file(&log).unwrap();
+    cargo_bin_cmd!("payments-engine")
+        .arg("cli")
+        .arg(&input)
+        .arg("--config")
+        .arg(&config)
+        .arg("--no-event-log")
+        .assert()
+        .success();
+    assert!(!log.exists());
+    
+    std::fs::write(&config, "shards = 0\n").unwrap();
+    cargo_bin_cmd!("payments-engine")
+        .arg("cli")
+        .arg(&input)
+        .arg("--config")
+        .arg(&config)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("shards must be at least 1"));
+}
+
+#[test]
+fn test_cli_shard_count_does_not_change_output() {
+    use assert_cmd::cargo::cargo_bin_cmd;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    std::fs::write(
+        &input,
+        "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,2,2,3.0\nwithdrawal,1,3,1.5\ndispute,2,2,\n",
+    )
+    .unwrap();
+    
+    let run = |shards: &str| {
+        let output = cargo_bin_cmd!("payments-engine")
+            .arg("cli")
+            .arg(&input)
+            .args(["--shards", shards])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let mut lines: Vec<String> = String::from_utf8(output).unwrap().lines().map(String::from).collect();
+        lines.sort();
+        lines
+    };
+    assert_eq!(run("1"), run("64"));
+    
+    cargo_bin_cmd!("payments-engine")
+        .arg("cli")
+        .arg(&input)
+        .args(["--shards", "0"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_auto_shard_count_follows_cpus_and_numa_nodes() {
+    use payments_engine::config::EngineConfig;
+    use payments_engine::topology::{parse_node_list, CpuTopology};
+    
+    let shards = |cpus, numa_nodes| CpuTopology { cpus, numa_nodes }.shards();
+    assert_eq!(shards(1, 1), 4);
+    assert_eq!(shards(8, 1), 16);
+    assert_eq!(shards(6, 4), 12);
+    assert_eq!(shards(3, 4), 8);
+    assert_eq!(shards(4096, 1), 1024);
+    
+    assert_eq!(parse_node_list("0\n"), Some(1));
+    assert_eq!(parse_node_list("0-3"), Some(4));
+    assert_eq!(parse_node_list("0,2-3"), Some(3));
+    assert_eq!(parse_node_list("3-1"), None);
+    assert_eq!(parse_node_list(""), None);
+    
+    // A config leaving shards out gets the detected count
+    let config = EngineConfig::from_toml("").unwrap();
+    assert_eq!(config.shards, CpuTopology::detect().shards());
+}
+
+// ============================================================================
+// SPILL BUFFER TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_spill_buffer_overflows_to_disk_in_order() {
+    use payments_engine::spill::{SpillBuffer, SpillConfig};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let config = SpillConfig { dir: temp_dir.path().to_path_buf(), memory_rows: 3 };
+    let buffer = SpillBuffer::<u32>::new(&config);
+    for row in 0..10 {
+        buffer.push(row).unwrap();
+    }
+    assert_eq!(buffer.spilled_total(), 7);
+    assert!(buffer.path().exists());
+    
+    // Rows pushed while some are still on disk go to disk too, behind them
+    for expected in 0..5 {
+        assert_eq!(buffer.pop().await.unwrap(), Some(expected));
+    }
+    buffer.push(10).unwrap();
+    buffer.close();
+    let mut rest = Vec::new();
+    while let Some(row) = buffer.pop().await.unwrap() {
+        rest.push(row);
+    }
+    assert_eq!(rest, (5..=10).collect::<Vec<_>>());
+    // Drained files are emptied, and removed with the buffer
+    assert_eq!(std::fs::metadata(buffer.path()).unwrap().len(), 0);
+    let path = buffer.path().to_path_buf();
+    drop(buffer);
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_absorb_keeps_rows_and_errors_in_order() {
+    use futures::StreamExt;
+    use payments_engine::spill::{absorb, SpillConfig};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let config = SpillConfig { dir: temp_dir.path().to_path_buf(), memory_rows: 2 };
+    let rows = futures::stream::iter((0..50u32).map(|row| {
+        if row == 25 { Err(anyhow::anyhow!("bad row 25")) } else { Ok(row) }
+    }));
+    let out: Vec<_> = absorb(rows, &config).collect().await;
+    
+    assert_eq!(out.len(), 50);
+    for (row, item) in out.iter().enumerate() {
+        match item {
+            Ok(value) => assert_eq!(*value as usize, row),
+            Err(e) => assert_eq!((row, e.to_string()), (25, "bad row 25".to_string())),
+        }
+    }
+    // No spill files are left behind
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+// ============================================================================
+// EVENT LOG BACKEND TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_engine_over_in_memory_event_log() {
+    use payments_engine::config::EngineConfig;
+    use payments_engine::event_store::{EventLog, InMemoryEventLog};
+    use payments_engine::test_support::deposit;
+    
+    let log = Arc::new(InMemoryEventLog::new());
+    let config = EngineConfig { shards: 2, ..Default::default() };
+    let engine = ScalableEngine::from_event_log(log.clone(), &config, Arc::new(InMemoryStore::new())).await.unwrap();
+    assert_eq!(engine.generation(), 1);
+    engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+    engine.process(deposit(2, 2, dec!(3.0))).await.unwrap();
+    assert_eq!(log.end_offset().await.unwrap(), 2);
+    assert_eq!(log.replay_from(1).await.unwrap()[0].tx, 2);
+    assert!(log.replay_from(3).await.is_err());
+    
+    let trace = engine.trace_transaction(1).await.unwrap();
+    assert_eq!(trace.events.len(), 1);
+    assert_eq!(trace.source, std::path::Path::new(":memory:"));
+    
+    // A log with earlier events is replayed like a file
+    let log = Arc::new(InMemoryEventLog::with_events(log.replay().await.unwrap()));
+    let engine = ScalableEngine::from_event_log(log, &config, Arc::new(InMemoryStore::new())).await.unwrap();
+    assert_eq!(engine.generation(), 0);
+    engine.rebuild_from_events().await.unwrap();
+    assert_eq!(engine.replayed_events(), 2);
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(5.0));
+    assert!(engine.process(deposit(1, 2, dec!(1.0))).await.is_err());
+}
+
+// ============================================================================
+// INTAKE LOG TESTS
+// ============================================================================
+
+#[test]
+fn test_intake_log_recovers_unsettled_rows_in_order() {
+    use payments_engine::intake::IntakeLog;
+    use payments_engine::test_support::{deposit, withdrawal};
+    use std::io::Write;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("intake.log");
+    let (intake, pending) = IntakeLog::open(path.clone()).unwrap();
+    assert!(pending.is_empty());
+    let first = intake.receive(&deposit(1, 1, dec!(5.0))).unwrap();
+    let second = intake.receive(&withdrawal(1, 2, dec!(1.0))).unwrap();
+    let third = intake.receive(&deposit(2, 3, dec!(2.0))).unwrap();
+    intake.settle(second).unwrap();
+    assert_eq!(intake.pending(), 2);
+    drop(intake);
+    
+    // A receipt torn by the crash is skipped
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"received\":{\"id\":9").unwrap();
+    
+    let (intake, pending) = IntakeLog::open(path.clone()).unwrap();
+    let ids: Vec<u64> = pending.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![first, third]);
+    assert_eq!(pending[1].1.client, 2);
+    assert_eq!(intake.pending(), 2);
+    
+    // New receipts don't reuse pending ids, the file empties once nothing is pending
+    assert!(intake.receive(&deposit(3, 4, dec!(1.0))).unwrap() > third);
+    for id in [first, third, third + 1] {
+        intake.settle(id).unwrap();
+    }
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    drop(intake);
+    assert!(IntakeLog::open(path).unwrap().1.is_empty());
+}
+
+#[tokio::test]
+async fn test_engine_applies_recovered_intake_once() {
+    use payments_engine::intake::IntakeLog;
+    use payments_engine::test_support::{deposit, dispute};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("events.log");
+    let intake_path = temp_dir.path().join("intake.log");
+    
+    // Tx 1 was logged before the crash, tx 2 and the dispute were only received
+    {
+        let engine = ScalableEngine::new(log_path.clone(), 4, Arc::new(InMemoryStore::new())).await.unwrap();
+        engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+        let (intake, _) = IntakeLog::open(intake_path.clone()).unwrap();
+        intake.receive(&deposit(1, 1, dec!(5.0))).unwrap();
+        intake.receive(&deposit(1, 2, dec!(3.0))).unwrap();
+        intake.receive(&dispute(1, 2)).unwrap();
+    }
+    
+    let (intake, pending) = IntakeLog::open(intake_path).unwrap();
+    let engine = ScalableEngine::new(log_path, 4, Arc::new(InMemoryStore::new()))
+        .await
+        .unwrap()
+        .with_intake(intake);
+    engine.rebuild_from_events().await.unwrap();
+    assert_eq!(engine.recover_intake(pending).await.unwrap(), 2);
+    
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(5.0), dec!(3.0)));
+    assert_eq!(engine.intake().unwrap().pending(), 0);
+}
+
+// ============================================================================
+// BALANCE ALERT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_alert_rules_fire_when_an_account_crosses_them() {
+    use payments_engine::alerts::AlertRule;
+    use payments_engine::events::DomainEvent;
+    use payments_engine::test_support::{deposit, dispute, withdrawal};
+    
+    let temp_dir = TempDir::new().unwrap();
+    let engine = ScalableEngine::new(temp_dir.path().join("alerts.log"), 4, Arc::new(InMemoryStore::new())).await.unwrap();
+    let rules = vec![
+        AlertRule::AvailableBelow { threshold: dec!(5) },
+        AlertRule::HeldAbove { threshold: dec!(2) },
+        AlertRule::NegativeAvailable,
+    ];
+    engine.alerts().set_rules(1, rules.clone()).await.unwrap();
+    assert_eq!(engine.alerts().rules(1), rules);
+    assert!(engine.alerts().rules(2).is_empty());
+    let mut events = engine.subscribe();
+    
+    for row in [
+        deposit(1, 1, dec!(10)),
+        withdrawal(1, 2, dec!(6)),  // available 4: below 5
+        withdrawal(1, 3, dec!(1)),  // still below, no repeat
+        deposit(1, 4, dec!(5)),     // recovered
+        withdrawal(1, 5, dec!(5)),  // below again
+        dispute(1, 1),              // available -7, held 10
+        withdrawal(2, 6, dec!(1)),  // client 2 has no rules
+        deposit(2, 7, dec!(1)),
+    ] {
+        let _ = engine.process(row).await;
+    }
+    
+    let mut alerts = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let DomainEvent::BalanceAlert(alert) = event {
+            alerts.push((alert.client, alert.rule, alert.available));
+        }
+    }
+    assert_eq!(
+        alerts,
+        vec![
+            (1, AlertRule::AvailableBelow { threshold: dec!(5) }, dec!(4)),
+            (1, AlertRule::AvailableBelow { threshold: dec!(5) }, dec!(3)),
+            (1, AlertRule::HeldAbove { threshold: dec!(2) }, dec!(-7)),
+            (1, AlertRule::NegativeAvailable, dec!(-7)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_alert_rules_persist_and_reach_the_webhook() {
+    use axum::{routing::post, Json, Router};
+    use payments_engine::alerts::{self, AlertRule, AlertRules};
+    use payments_engine::escalation::WebhookSink;
+    use payments_engine::events::EventBus;
+    use payments_engine::test_support::{deposit, withdrawal};
+    use tokio::sync::mpsc;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("alerts.json");
+    let stored = AlertRules::new(EventBus::default());
+    stored.attach_file(path.clone()).await.unwrap();
+    stored.set_rules(3, vec![AlertRule::NegativeAvailable]).await.unwrap();
+    stored.set_rules(4, vec![AlertRule::HeldAbove { threshold: dec!(1) }]).await.unwrap();
+    stored.set_rules(4, Vec::new()).await.unwrap();
+    
+    let engine = ScalableEngine::new(temp_dir.path().join("alerts.log"), 4, Arc::new(InMemoryStore::new())).await.unwrap();
+    engine.alerts().attach_file(path).await.unwrap();
+    assert_eq!(engine.alerts().rules(3), vec![AlertRule::NegativeAvailable]);
+    assert!(engine.alerts().rules(4).is_empty());
+    engine.alerts().set_rules(3, vec![AlertRule::AvailableBelow { threshold: dec!(1) }]).await.unwrap();
+    
+    let (received_tx, mut received) = mpsc::unbounded_channel();
+    let app = Router::new().route(
+        "/alerts",
+        post(move |Json(body): Json<serde_json::Value>| async move {
+            received_tx.send(body).unwrap();
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    alerts::spawn_notifier(&engine, Arc::new(WebhookSink::new(format!("http://{}/alerts", addr))));
+    
+    engine.process(deposit(3, 1, dec!(2))).await.unwrap();
+    engine.process(withdrawal(3, 2, dec!(1.5))).await.unwrap();
+    assert_eq!(
+        received.recv().await.unwrap(),
+        serde_json::json!({
+            "client": 3,
+            "rule": {"kind": "available_below", "threshold": "1"},
+            "available": "0.5",
+            "held": "0",
+            "locked": false
+        })
+    );
+}
+
+// ============================================================================
+// ACCOUNT STATEMENT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_statement_running_balances_within_range() {
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::statement::build_statement;
+    use payments_engine::storage::StoredTransaction;
+    use payments_engine::test_support::{deposit, dispute, resolve, transfer, withdrawal};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let events = vec![
+        deposit(1, 1, dec!(100)),
+        deposit(2, 2, dec!(50)),
+        withdrawal(1, 3, dec!(30)),
+        transfer(2, 1, 4, dec!(20)),
+        withdrawal(1, 5, dec!(500)),
+        dispute(1, 1),
+        resolve(1, 1),
+    ];
+
+    // The deposit was migrated to cold storage, it knows when it was recorded
+    let cold_storage = InMemoryStore::new();
+    cold_storage
+        .put(1, StoredTransaction {
+            client: 1,
+            tx_type: TransactionType::Deposit,
+            amount: dec!(100),
+            disputed: false,
+            held_amount: None,
+            created_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        })
+        .await
+        .unwrap();
+
+    let log = std::path::Path::new("statement.log");
+    let full = build_statement(log, events.clone(), 1, None, None, &cold_storage, ShardManager::scratch(Default::default(), Default::default())).await;
+    let seqs: Vec<usize> = full.lines.iter().map(|line| line.seq).collect();
+    assert_eq!(seqs, vec![1, 3, 4, 5, 6, 7]);
+    assert_eq!(full.opening.total, dec!(0));
+    assert_eq!(full.closing.available, dec!(90));
+    assert_eq!(full.lines[0].recorded_at, Some(1_700_000_000));
+    assert_eq!(full.lines[1].recorded_at, None);
+    // The transfer credit names its sender, the oversized withdrawal stays on as rejected
+    assert_eq!(full.lines[2].counterparty, Some(2));
+    assert_eq!(full.lines[2].balance.available, dec!(90));
+    assert_eq!(full.lines[3].outcome, "insufficient funds");
+    assert_eq!(full.lines[3].balance.available, dec!(90));
+    assert_eq!(full.lines[4].balance.held, dec!(100));
+    assert_eq!(full.lines[4].balance.available, dec!(-10));
+
+    // Earlier events only make up the opening balance
+    let ranged = build_statement(log, events, 1, Some(4), Some(6), &cold_storage, ShardManager::scratch(Default::default(), Default::default())).await;
+    let seqs: Vec<usize> = ranged.lines.iter().map(|line| line.seq).collect();
+    assert_eq!(seqs, vec![4, 5, 6]);
+    assert_eq!(ranged.opening.available, dec!(70));
+    assert_eq!(ranged.closing.available, dec!(-10));
+    assert_eq!(ranged.closing.held, dec!(100));
+    assert!(ranged.to_csv().starts_with("seq,type,tx,amount,counterparty,outcome,available,held,total,locked,recorded_at\n4,transfer,4,20,2,applied,90.0000,0.0000,90.0000,false,\n"));
+}
+
+#[tokio::test]
+async fn test_statement_replays_under_the_log_compat() {
+    use payments_engine::compat::CompatConfig;
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::statement::build_statement;
+    use payments_engine::test_support::{chargeback, deposit, dispute, withdrawal};
+
+    let events = vec![
+        deposit(1, 1, dec!(10)),
+        withdrawal(1, 2, dec!(4)),
+        dispute(1, 2),
+        chargeback(1, 2),
+    ];
+
+    // Written under extended compat, the withdrawal dispute held its amount and the chargeback returned it
+    let scratch = ShardManager::scratch(CompatConfig::extended(), Default::default());
+    let statement = build_statement(std::path::Path::new("statement.log"), events, 1, None, None, &InMemoryStore::new(), scratch).await;
+    assert!(statement.lines.iter().all(|line| line.outcome == "applied"), "{:?}", statement.lines);
+    assert_eq!(statement.lines[2].balance.held, dec!(4));
+    assert_eq!(statement.closing.available, dec!(10));
+    assert_eq!(statement.closing.held, dec!(0));
+    assert!(statement.closing.locked);
+}
+
+// ============================================================================
+// LATENCY PROBE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_probes_are_timed_logged_and_kept_out_of_reports() {
+    use payments_engine::event_store::{DurabilityPolicy, EventStore};
+    use payments_engine::metrics::ProbeMetrics;
+    use payments_engine::prober::{LatencyProber, PROBE_CLIENT};
+    use payments_engine::shard_manager::ShardManager;
+    use payments_engine::statement::build_statement;
+    use payments_engine::test_support::deposit;
+
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("probe.log");
+    let cold = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path.clone(), 4, cold.clone())
+        .await
+        .unwrap()
+        .with_prober();
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+
+    let metrics: Arc<ProbeMetrics> = engine.probe_metrics().unwrap().clone();
+    let prober = LatencyProber::new(metrics.clone());
+    prober.probe(&engine).await.unwrap();
+    prober.probe(&engine).await.unwrap();
+
+    // A deposit and a withdrawal per probe, each timed through to the log
+    let snapshot = metrics.snapshot();
+    assert_eq!((snapshot.probes, snapshot.failures), (4, 0));
+    assert!(snapshot.max_micros >= snapshot.last_micros);
+    let probe = engine.get_account(PROBE_CLIENT).await.unwrap();
+    assert_eq!(probe.total(), dec!(0));
+
+    // Probe transactions are discarded, neither tier keeps them
+    let mut discarded = false;
+    for _ in 0..50 {
+        if engine.hot_transactions(PROBE_CLIENT).await.unwrap().is_empty() {
+            discarded = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(discarded);
+    assert!(cold.list_client(PROBE_CLIENT, None, 10).await.unwrap().is_empty());
+
+    // Listings, totals and counters only know the customer
+    let clients: Vec<u16> = engine.get_accounts().await.iter().map(|a| a.client).collect();
+    assert_eq!(clients, vec![1]);
+    // Totals leave out the probe account once the projection has seen it
+    let mut converged = false;
+    for _ in 0..50 {
+        if engine.account_totals().accounts == 1 && engine.treasury_report().accounts == 1 {
+            converged = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(converged);
+    assert_eq!(engine.account_totals().total, dec!(10.0));
+    let counted: Vec<u16> = engine.dispute_counters().report(None).iter().map(|c| c.client).collect();
+    assert_eq!(counted, vec![1]);
+
+    // The events are in the log, a statement replays them but lists none
+    engine.shutdown().await.unwrap();
+    let events = EventStore::new(log_path.clone(), DurabilityPolicy::Buffered).await.unwrap().replay().await.unwrap();
+    assert_eq!(events.iter().filter(|e| e.client == PROBE_CLIENT).count(), 4);
+    let statement = build_statement(&log_path, events, PROBE_CLIENT, None, None, &InMemoryStore::new(), ShardManager::scratch(Default::default(), Default::default())).await;
+    assert!(statement.lines.is_empty());
+}
+
+#[tokio::test]
+async fn test_producer_rows_for_probe_client_are_refused_with_prober() {
+    use payments_engine::errors::ProcessingError;
+    use payments_engine::prober::PROBE_CLIENT;
+    use payments_engine::test_support::deposit;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = ScalableEngine::new(temp_dir.path().join("reserved.log"), 4, Arc::new(InMemoryStore::new()))
+        .await
+        .unwrap()
+        .with_prober();
+    engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+
+    let row = deposit(PROBE_CLIENT, 2, dec!(1.0));
+    assert!(matches!(engine.validate(&row).await, Err(ProcessingError::ReservedClient)));
+    assert!(matches!(engine.process(row.clone()).await, Err(ProcessingError::ReservedClient)));
+    let outcomes = engine.process_batch(vec![row, deposit(1, 3, dec!(1.0))]).await;
+    assert!(matches!(outcomes[0], Err(ProcessingError::ReservedClient)));
+    assert!(outcomes[1].is_ok());
+
+    // Nor can a transfer credit the probe account
+    let transfer = TransactionRow {
+        tx_type: TransactionType::Transfer,
+        client: 1,
+        tx: 4,
+        amount: Some(dec!(1.0)),
+        to: Some(PROBE_CLIENT),
+    };
+    assert!(matches!(engine.process(transfer).await, Err(ProcessingError::ReservedClient)));
+
+    // The refused ids stay free
+    assert!(engine.get_account(PROBE_CLIENT).await.is_none());
+    engine.process(deposit(1, 2, dec!(1.0))).await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(7.0));
+}
+
+#[tokio::test]
+async fn test_probe_client_is_ordinary_without_prober() {
+    use payments_engine::prober::PROBE_CLIENT;
+    use payments_engine::test_support::deposit;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = ScalableEngine::new(temp_dir.path().join("noprobe.log"), 4, Arc::new(InMemoryStore::new()))
+        .await
+        .unwrap();
+    engine.process(deposit(PROBE_CLIENT, 1, dec!(1.0))).await.unwrap();
+
+    assert!(engine.probe_metrics().is_none());
+    assert_eq!(engine.get_accounts().await.len(), 1);
+    assert_eq!(engine.account_totals().accounts, 1);
+}
+
+// ============================================================================
+// ACCOUNT EXPORT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_server_export_writes_a_new_directory_each_time() {
+    use payments_engine::export::{ExportConfig, PartitionManifest, MANIFEST_FILE};
+    use payments_engine::server::export_now;
+    use payments_engine::test_support::deposit;
+    
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage);
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+    engine.process(deposit(40000, 2, dec!(5.0))).await.unwrap();
+    
+    let config = ExportConfig { dir: temp_dir.path().join("exports"), partitions: 2 };
+    let dir = export_now(&engine, &config).await.unwrap();
+    assert_eq!(dir.parent().unwrap(), config.dir);
+    
+    let manifest: PartitionManifest = serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+    let rows: Vec<u64> = manifest.partitions.iter().map(|p| p.rows).collect();
+    assert_eq!(rows, vec![1, 1]);
+    assert!(!dir.join(format!("{}.tmp", MANIFEST_FILE)).exists());
+}
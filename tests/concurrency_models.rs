@@ -0,0 +1,124 @@
+#![cfg(feature = "shuttle")]
+
+use payments_engine::config::EngineConfig;
+use payments_engine::event_store::InMemoryEventLog;
+use payments_engine::shard_manager::ShardManager;
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::test_support::{deposit, withdrawal};
+use payments_engine::ScalableEngine;
+use rust_decimal_macros::dec;
+use shuttle::future;
+use std::cell::Cell;
+use std::sync::{Arc, OnceLock};
+
+const ITERATIONS: usize = 1000;
+
+/// Enter a tokio context for actor timers, which never fire during a run
+///
+/// Kept for the rest of the thread and shared by every iteration: actors an
+/// iteration leaves behind still run and are dropped after it ends.
+fn enter_tokio() {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    thread_local!(static ENTERED: Cell<bool> = const { Cell::new(false) });
+    if !ENTERED.replace(true) {
+        let runtime = RUNTIME.get_or_init(|| tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap());
+        std::mem::forget(runtime.enter());
+    }
+}
+
+/// `shuttle::check_random`, with stacks deep enough for a whole engine's futures
+fn check_random<F>(f: F, iterations: usize)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let mut config = shuttle::Config::new();
+    config.stack_size = 1 << 20;
+    shuttle::Runner::new(shuttle::scheduler::RandomScheduler::new(iterations), config).run(f);
+}
+
+// ============================================================================
+// SHARD MANAGER ACTOR CREATION
+// ============================================================================
+
+#[test]
+fn test_racing_lookups_create_one_actor_per_client() {
+    check_random(
+        || {
+            enter_tokio();
+
+            let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+            let manager = Arc::new(ShardManager::new(1, cold_storage));
+
+            let lookups: Vec<_> = [7, 7, 7, 8]
+                .into_iter()
+                .map(|client| {
+                    let manager = manager.clone();
+                    future::spawn(async move { (client, manager.get_or_create_actor(client).await) })
+                })
+                .collect();
+
+            future::block_on(async move {
+                let mut handles = Vec::new();
+                for lookup in lookups {
+                    handles.push(lookup.await.unwrap());
+                }
+
+                for (client, handle) in &handles {
+                    let current = manager.get_or_create_actor(*client).await;
+                    assert!(current.same_actor(handle), "client {} got a second actor", client);
+                }
+                assert!(!handles[0].1.same_actor(&handles[3].1));
+            });
+        },
+        ITERATIONS,
+    );
+}
+
+// ============================================================================
+// TX ID REGISTRATION ROLLBACK
+// ============================================================================
+
+#[test]
+fn test_failed_apply_never_releases_another_writers_id() {
+    check_random(
+        || {
+            enter_tokio();
+
+            let engine = future::block_on(async {
+                let config = EngineConfig { shards: 2, ..Default::default() };
+                let engine = ScalableEngine::from_event_log(
+                    Arc::new(InMemoryEventLog::new()),
+                    &config,
+                    Arc::new(InMemoryStore::new()),
+                )
+                .await
+                .unwrap();
+                Arc::new(engine)
+            });
+
+            // The withdrawal claims tx 1 and fails on funds, releasing it again
+            let writers: Vec<_> = [withdrawal(1, 1, dec!(5.0)), deposit(1, 1, dec!(5.0)), deposit(2, 1, dec!(5.0))]
+                .into_iter()
+                .map(|row| {
+                    let engine = engine.clone();
+                    future::spawn(async move { engine.process(row).await.is_ok() })
+                })
+                .collect();
+
+            future::block_on(async move {
+                let mut applied = 0;
+                for writer in writers {
+                    if writer.await.unwrap() {
+                        applied += 1;
+                    }
+                }
+
+                // At most one writer wins, and its claim outlives every rollback
+                assert!(applied <= 1);
+                let still_claimed = engine.process(deposit(3, 1, dec!(1.0))).await.is_err();
+                assert_eq!(still_claimed, applied == 1);
+            });
+        },
+        ITERATIONS,
+    );
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "contention-profiling")]
+
+use payments_engine::contention;
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::{ScalableEngine, TransactionRow, TransactionType};
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+// ============================================================================
+// CONTENTION PROFILING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_profiler_records_waits_per_site() {
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("contention.log"), 4, cold_storage)
+        .await
+        .unwrap();
+
+    contention::reset();
+
+    let deposit = TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 1,
+        amount: Some(dec!(10.0)),
+        to: None,
+    };
+    engine.prefetch(&[TransactionRow { tx_type: TransactionType::Dispute, amount: None, ..deposit.clone() }]).await;
+    engine.process(deposit).await.unwrap();
+
+    let report = contention::report();
+    let waits = |site: &str| report.iter().find(|r| r.site == site).unwrap().waits;
+    assert!(waits("event_store") >= 1);
+    assert!(waits("shard_lock") >= 1);
+    assert!(waits("cold_storage") >= 1);
+
+    let mut out = Vec::new();
+    contention::write_report(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("site,waits,total_wait_us,max_wait_us\n"));
+    assert_eq!(out.lines().count(), 4);
+}
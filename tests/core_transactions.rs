@@ -1,4 +1,4 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 use std::fs;
 use tempfile::NamedTempFile;
@@ -9,9 +9,9 @@ use tempfile::NamedTempFile;
 
 #[test]
 fn test_basic_deposits_and_withdrawals() {
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
-        .arg("tests/fixtures/basic.csv")
+        .arg("tests/fixtures/golden/basic/input.csv")
         .assert()
         .success()
         .get_output()
@@ -39,7 +39,7 @@ fn test_withdrawal_insufficient_funds() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -66,7 +66,7 @@ fn test_multiple_clients() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -83,13 +83,86 @@ fn test_multiple_clients() {
     assert!(output_str.contains("3,300"));
 }
 
+// ============================================================================
+// AUTHORIZE & CAPTURE TESTS
+// ============================================================================
+
+#[test]
+fn test_authorize_holds_until_captured() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\ndeposit,1,1,10.0\nauthorize,1,2,4.0\ndeposit,2,3,5.0\nauthorize,2,4,5.0\ncapture,1,2,\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        // Captured: the hold became a withdrawal
+        .stdout(predicate::str::contains("1,6.0000,0.0000,6.0000,false"))
+        // Still authorized: held, not gone
+        .stdout(predicate::str::contains("2,0.0000,5.0000,5.0000,false"));
+}
+
+#[test]
+fn test_void_releases_hold() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\ndeposit,1,1,10.0\nauthorize,1,2,4.0\nvoid,1,2,\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,false"));
+}
+
+#[test]
+fn test_authorize_needs_available_funds() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\ndeposit,1,1,10.0\nauthorize,1,2,6.0\nauthorize,1,3,6.0\nwithdrawal,1,4,6.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        // Held funds can neither be authorized again nor withdrawn
+        .stdout(predicate::str::contains("1,4.0000,6.0000,10.0000,false"));
+}
+
+#[test]
+fn test_authorization_settles_once() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\ndeposit,1,1,10.0\nauthorize,1,2,4.0\nvoid,1,2,\ncapture,1,2,\nvoid,1,2,\ncapture,1,1,\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        // Nothing to capture after the void, and a deposit is no authorization
+        .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,false"));
+}
+
 // ============================================================================
 // INPUT VALIDATION TESTS
 // ============================================================================
 
 #[test]
 fn test_missing_input_file() {
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     cmd.arg("nonexistent.csv")
         .assert()
         .failure();
@@ -100,7 +173,7 @@ fn test_empty_file() {
     let temp_file = NamedTempFile::new().unwrap();
     fs::write(temp_file.path(), "type,client,tx,amount\n").unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     cmd.arg(temp_file.path())
         .assert()
         .success()
@@ -109,9 +182,9 @@ fn test_empty_file() {
 
 #[test]
 fn test_whitespace_handling() {
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
-        .arg("tests/fixtures/edge_cases.csv")
+        .arg("tests/fixtures/golden/edge_cases/input.csv")
         .assert()
         .success()
         .get_output()
@@ -135,7 +208,7 @@ fn test_decimal_precision() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -150,6 +223,188 @@ fn test_decimal_precision() {
     assert!(output_str.contains("1,3.5801"));
 }
 
+#[test]
+fn test_amount_notation_rules() {
+    use payments_engine::amount::{parse_amount, AmountError, AmountFormat};
+    use rust_decimal_macros::dec;
+
+    let strict = AmountFormat::default();
+    let lenient = AmountFormat {
+        allow_scientific: true,
+        allow_thousands_separators: true,
+    };
+
+    assert_eq!(parse_amount(" 2.5 ", strict), Ok(dec!(2.5)));
+    assert_eq!(parse_amount("1e3", strict), Err(AmountError::ScientificNotation("1e3".into())));
+    assert_eq!(parse_amount("1,000.00", strict), Err(AmountError::ThousandsSeparator("1,000.00".into())));
+    assert_eq!(parse_amount("abc", strict), Err(AmountError::Malformed("abc".into())));
+
+    assert_eq!(parse_amount("1e3", lenient), Ok(dec!(1000)));
+    assert_eq!(parse_amount("2.5E-2", lenient), Ok(dec!(0.025)));
+    assert_eq!(parse_amount("1,234,567.89", lenient), Ok(dec!(1234567.89)));
+    assert_eq!(parse_amount("-1,000", lenient), Ok(dec!(-1000)));
+
+    // Locale-ambiguous spellings are refused rather than guessed
+    for raw in ["1,00", "1,0000.5", ",100", "1.000,50", "1,,000"] {
+        assert_eq!(parse_amount(raw, lenient), Err(AmountError::MisplacedSeparator(raw.into())));
+    }
+}
+
+#[test]
+fn test_minor_unit_conversion() {
+    use payments_engine::amount::{AmountError, AmountUnits};
+    use rust_decimal_macros::dec;
+
+    let cents: AmountUnits = "minor:2".parse().unwrap();
+    assert_eq!(cents, AmountUnits::Minor(2));
+    assert_eq!(cents.to_decimal(dec!(1050)), Ok(dec!(10.50)));
+    assert_eq!(AmountUnits::Minor(0).to_decimal(dec!(1050)), Ok(dec!(1050)));
+    assert_eq!(cents.from_decimal(dec!(10.5)).to_string(), "1050");
+    // A fraction of a cent stays visible instead of being rounded away
+    assert_eq!(cents.from_decimal(dec!(0.125)).to_string(), "12.5");
+
+    assert_eq!(cents.to_decimal(dec!(10.5)), Err(AmountError::FractionalMinorUnits("10.5".into())));
+    assert!(matches!(cents.to_decimal(dec!(1e19)), Err(AmountError::MinorUnitsOutOfRange(_))));
+    assert_eq!(AmountUnits::Decimal.to_decimal(dec!(10.5)), Ok(dec!(10.5)));
+    assert!("minor:5".parse::<AmountUnits>().is_err());
+    assert!("cents".parse::<AmountUnits>().is_err());
+}
+
+#[test]
+fn test_minor_unit_feed() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\n\
+         deposit,1,1,1050\n\
+         withdrawal,1,2,250\n\
+         deposit,1,3,10.5\n",
+    )
+    .unwrap();
+
+    // Fractional minor units are rejected, the output is in cents too
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--amount-units", "minor:2"])
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,800,0,800,false"));
+}
+
+#[test]
+fn test_amount_notation_flags() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\n\
+         deposit,1,1,1e3\n\
+         deposit,1,2,\"1,000.00\"\n\
+         deposit,1,3,2.5\n",
+    )
+    .unwrap();
+
+    // Rejected rows are skipped by default
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,2.5000,0.0000,2.5000,false"));
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("cli")
+        .arg(temp_file.path())
+        .arg("--allow-scientific")
+        .arg("--allow-thousands-separators")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,2002.5000,0.0000,2002.5000,false"));
+}
+
+#[test]
+fn test_treasury_output() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         deposit,2,2,5.5\n\
+         dispute,2,2,\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("cli")
+        .arg(temp_file.path())
+        .arg("--treasury")
+        .assert()
+        .success()
+        .stdout("accounts,available,held,total,locked,negative_exposure\n2,10.0000,5.5000,15.5000,0,0.0000\n");
+}
+
+#[test]
+fn test_shard_stats_output() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         deposit,2,2,5.5\n\
+         deposit,3,3,1.0\n\
+         deposit,3,4,1.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.env("PAYMENTS_ENGINE_SHARDS", "2")
+        .arg("cli")
+        .arg(temp_file.path())
+        .arg("--shard-stats")
+        .assert()
+        .success()
+        .stdout("shard,actors,hot_transactions,mailbox_depth,in_flight\n0,1,1,0,0\n1,2,3,0,0\n");
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("cli").arg(temp_file.path()).args(["--shard-stats", "--treasury"]).assert().failure();
+}
+
+#[test]
+fn test_event_log_flags() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let input = temp_dir.path().join("input.csv");
+    let log = temp_dir.path().join("kept.log");
+    fs::write(&input, "type,client,tx,amount\ndeposit,1,1,10.0\n").unwrap();
+
+    // A kept log carries the first run into the second, where tx 1 is a duplicate
+    for _ in 0..2 {
+        cargo_bin_cmd!("payments-engine")
+            .arg("cli")
+            .arg(&input)
+            .arg("--event-log")
+            .arg(&log)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,false"));
+    }
+    assert!(fs::metadata(&log).unwrap().len() > 0);
+
+    cargo_bin_cmd!("payments-engine")
+        .arg("cli")
+        .arg(&input)
+        .arg("--no-event-log")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n");
+
+    cargo_bin_cmd!("payments-engine")
+        .arg("cli")
+        .arg(&input)
+        .arg("--no-event-log")
+        .arg("--event-log")
+        .arg(&log)
+        .assert()
+        .failure();
+}
+
 // ============================================================================
 // LOCKED ACCOUNT TESTS
 // ============================================================================
@@ -167,7 +422,7 @@ fn test_locked_account_rejects_deposits() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -201,7 +456,7 @@ fn test_locked_account_rejects_withdrawals() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -217,3 +472,490 @@ fn test_locked_account_rejects_withdrawals() {
     let client1_line = lines.iter().find(|l| l.starts_with("1,")).unwrap();
     assert!(client1_line.ends_with(",true"));  // locked
 }
+
+// ============================================================================
+// MULTI-FILE MERGE TESTS
+// ============================================================================
+
+#[test]
+fn test_merge_orders_rows_across_files_by_timestamp() {
+    let daily = NamedTempFile::new().unwrap();
+    fs::write(
+        daily.path(),
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,1,10.0,100\n\
+         withdrawal,1,3,12.0,300\n",
+    )
+    .unwrap();
+    let corrections = NamedTempFile::new().unwrap();
+    fs::write(
+        corrections.path(),
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,2,5.0,200\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd
+        .arg("merge")
+        .arg(daily.path())
+        .arg(corrections.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // The correction lands between the two daily rows, so the withdrawal is covered
+    let output_str = String::from_utf8(output).unwrap();
+    assert!(output_str.contains("1,3.0000,0.0000,3.0000,false"));
+}
+
+#[test]
+fn test_merge_rejects_unsorted_input() {
+    let daily = NamedTempFile::new().unwrap();
+    fs::write(
+        daily.path(),
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,1,10.0,200\n\
+         deposit,1,2,10.0,100\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("merge")
+        .arg(daily.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("row 2 (timestamp 100) is earlier"));
+}
+
+// ============================================================================
+// REJECTS REPORT TESTS
+// ============================================================================
+
+#[test]
+fn test_rejects_report_lists_every_row_not_applied() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount\n\
+         deposit, 1, 1, 10.0\n\
+         withdrawal, 1, 2, 50.0\n\
+         \n\
+         deposit, x, 3, 1.0\n\
+         deposit, 1, 1, 5.0\n\
+         dispute, 1, 9,\n",
+    )
+    .unwrap();
+
+    let rejects = dir.path().join("rejects.csv");
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--rejects"])
+        .arg(&rejects)
+        .arg(&input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,false"))
+        .stderr(predicate::str::contains("4 rejected rows written"));
+
+    // Line numbers count the header and blank lines, raw rows are quoted as read
+    let report = fs::read_to_string(&rejects).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "type,client,tx,amount,to,code,reason,line,raw");
+    assert!(lines[1].starts_with("withdrawal,1,2,50.0,,insufficient_funds,"));
+    assert!(lines[1].ends_with(",3,\"withdrawal, 1, 2, 50.0\""));
+    assert!(lines[2].starts_with(",,,,,malformed,"));
+    assert!(lines[3].starts_with("deposit,1,1,5.0,,duplicate_transaction,"));
+    assert!(lines[4].starts_with("dispute,1,9,,,transaction_not_found,"));
+    assert_eq!(lines.len(), 5);
+
+    // The report reads back as transactions, the malformed row aside
+    let log = dir.path().join("events.log");
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["replay-rejects"])
+        .arg(&rejects)
+        .arg("--log")
+        .arg(&log)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("invalid row").count(1));
+
+    // JSON Lines by extension
+    let rejects = dir.path().join("rejects.jsonl");
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--rejects"]).arg(&rejects).arg(&input).assert().success();
+    let report = fs::read_to_string(&rejects).unwrap();
+    let first: serde_json::Value = serde_json::from_str(report.lines().next().unwrap()).unwrap();
+    assert_eq!(first["line"], 3);
+    assert_eq!(first["code"], "insufficient_funds");
+    assert_eq!(first["raw"], "withdrawal, 1, 2, 50.0");
+    assert_eq!(first["type"], "withdrawal");
+    assert_eq!(first["tx"], 2);
+    assert_eq!(report.lines().count(), 4);
+}
+
+// ============================================================================
+// OUTPUT FORMAT TESTS
+// ============================================================================
+
+#[test]
+fn test_output_formats() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "type,client,tx,amount\ndeposit,1,1,10.5\ndeposit,12,2,3.0\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd.args(["cli", "--output-format", "json"]).arg(temp_file.path()).assert().success().get_output().clone();
+    let accounts: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(accounts[1]["client"], 12);
+    assert_eq!(accounts[1]["available"], "3.0");
+    assert_eq!(accounts[1]["locked"], false);
+
+    // Columns padded to the widest value, numbers right-aligned
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd.args(["cli", "--output-format", "table"]).arg(temp_file.path()).assert().success().get_output().clone();
+    let table = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[0], "client | available | held   | total   | locked");
+    assert_eq!(lines[1], "-------+-----------+--------+---------+-------");
+    assert_eq!(lines[3], "    12 |    3.0000 | 0.0000 |  3.0000 | false");
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--treasury", "--output-format", "json"])
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"accounts\":2"));
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--output-format", "xml"]).arg(temp_file.path()).assert().failure();
+}
+
+// ============================================================================
+// PARTITIONED EXPORT TESTS
+// ============================================================================
+
+#[test]
+fn test_client_ranges_cover_the_id_space() {
+    use payments_engine::export::client_ranges;
+
+    assert_eq!(client_ranges(1), vec![0..=u16::MAX]);
+    assert_eq!(client_ranges(4), vec![0..=16383, 16384..=32767, 32768..=49151, 49152..=65535]);
+
+    // Uneven splits stay contiguous and differ by one id at most
+    let ranges = client_ranges(7);
+    assert_eq!(*ranges[0].start(), 0);
+    assert_eq!(*ranges[6].end(), u16::MAX);
+    for pair in ranges.windows(2) {
+        assert_eq!(*pair[0].end() + 1, *pair[1].start());
+    }
+    let sizes: Vec<usize> = ranges.iter().map(|range| range.clone().count()).collect();
+    assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1);
+    assert_eq!(client_ranges(65536).len(), 65536);
+}
+
+#[test]
+fn test_export_dir_writes_partitions_and_manifest() {
+    use payments_engine::export::PartitionManifest;
+
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\ndeposit,2,1,1.0\ndeposit,1,2,2.5\ndeposit,20000,3,3.0\ndeposit,65535,4,4.0\n",
+    )
+    .unwrap();
+    let export_dir = tempfile::TempDir::new().unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--partitions", "4", "--export-dir"])
+        .arg(export_dir.path())
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout("");
+
+    let manifest: PartitionManifest =
+        serde_json::from_slice(&fs::read(export_dir.path().join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest.rows, 4);
+    let layout: Vec<_> = manifest
+        .partitions
+        .iter()
+        .map(|p| (p.file.as_str(), p.first_client, p.last_client, p.rows))
+        .collect();
+    assert_eq!(
+        layout,
+        vec![
+            ("part-00000.csv", 0, 16383, 2),
+            ("part-00001.csv", 16384, 32767, 1),
+            ("part-00002.csv", 32768, 49151, 0),
+            ("part-00003.csv", 49152, 65535, 1),
+        ]
+    );
+    for partition in &manifest.partitions {
+        let bytes = fs::read(export_dir.path().join(&partition.file)).unwrap();
+        assert_eq!(partition.bytes, bytes.len() as u64);
+        assert_eq!(partition.crc32, format!("{:08x}", crc32fast::hash(&bytes)));
+    }
+    assert_eq!(
+        fs::read_to_string(export_dir.path().join("part-00000.csv")).unwrap(),
+        "client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n2,1.0000,0.0000,1.0000,false\n"
+    );
+    // Empty ranges still get a file, header only
+    assert_eq!(
+        fs::read_to_string(export_dir.path().join("part-00002.csv")).unwrap(),
+        "client,available,held,total,locked\n"
+    );
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--partitions", "0", "--export-dir"])
+        .arg(export_dir.path())
+        .arg(temp_file.path())
+        .assert()
+        .failure();
+}
+
+// ============================================================================
+// SCHEMA DETECTION TESTS
+// ============================================================================
+
+#[test]
+fn test_schema_is_sniffed_with_diagnostics() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Semicolons and a missing header are both recognized and reported
+    for (name, input) in [
+        ("semicolon.csv", "type;client;tx;amount\ndeposit;1;1;10.0\nwithdrawal;1;2;4.0\n"),
+        ("headerless.csv", "deposit,1,1,10.0\nwithdrawal,1,2,4.0\n"),
+        ("both.csv", "deposit|1|1|10.0\nwithdrawal|1|2|4.0\n"),
+    ] {
+        let path = dir.path().join(name);
+        fs::write(&path, input).unwrap();
+        let mut cmd = cargo_bin_cmd!("payments-engine");
+        let output = cmd.arg(&path).assert().success().get_output().clone();
+        assert!(String::from_utf8(output.stdout).unwrap().contains("1,6.0000,0.0000,6.0000,false"), "{}", name);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(stderr.contains("-separated"), name != "headerless.csv", "{}", name);
+        assert_eq!(stderr.contains("No header line"), name != "semicolon.csv", "{}", name);
+    }
+
+    // A header without the required columns explains the empty report
+    let path = dir.path().join("renamed.csv");
+    fs::write(&path, "kind,customer,id,amount\ndeposit,1,1,10.0\n").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(&path)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("The header has no type, client, tx column"));
+
+    // A well-formed file gets no diagnostics
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("tests/fixtures/golden/basic/input.csv")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+}
+
+#[test]
+fn test_schema_flag_overrides_sniffing() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "deposit;1;1;10.0\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--schema", "sep=semicolon,header=no"])
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,10.0000,0.0000,10.0000,false"))
+        .stderr(predicate::str::is_empty());
+
+    // Forced to commas the row is one unreadable field
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--schema", "sep=comma,header=no"])
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,").not());
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--schema", "sep=space"]).arg(temp_file.path()).assert().failure();
+}
+
+// ============================================================================
+// COMPRESSED INPUT TESTS
+// ============================================================================
+
+fn compress(plain: &[u8], zstd: bool) -> Vec<u8> {
+    use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+    use tokio::io::AsyncReadExt;
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut out = Vec::new();
+        if zstd {
+            ZstdEncoder::new(plain).read_to_end(&mut out).await.unwrap();
+        } else {
+            GzipEncoder::new(plain).read_to_end(&mut out).await.unwrap();
+        }
+        out
+    })
+}
+
+#[test]
+fn test_compressed_input_is_detected() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv = b"type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,4.0\n";
+    let jsonl = b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"6.0\"}\n";
+
+    // Told apart by magic bytes, whatever the file is named
+    for (name, input, expected) in [
+        ("input.csv.gz", compress(csv, false), "1,6.0000,0.0000,6.0000,false"),
+        ("input.csv.zst", compress(csv, true), "1,6.0000,0.0000,6.0000,false"),
+        ("input.bin", compress(jsonl, true), "1,6.0000,0.0000,6.0000,false"),
+    ] {
+        let path = dir.path().join(name);
+        fs::write(&path, input).unwrap();
+        let mut cmd = cargo_bin_cmd!("payments-engine");
+        cmd.arg(&path).assert().success().stdout(predicate::str::contains(expected));
+    }
+
+    // Concatenated gzip members are read through
+    let mut members = compress(b"type,client,tx,amount\ndeposit,1,1,10.0\n", false);
+    members.extend(compress(b"deposit,1,2,5.0\n", false));
+    let path = dir.path().join("members.csv.gz");
+    fs::write(&path, members).unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,15.0000,0.0000,15.0000,false"));
+}
+
+// ============================================================================
+// JSON LINES INPUT TESTS
+// ============================================================================
+
+#[test]
+fn test_json_lines_input_is_detected() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"10.5\"}\n\
+         \n\
+         {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":2.5}\n\
+         not json\n\
+         {\"type\":\"dispute\",\"client\":1,\"tx\":1}\n",
+    )
+    .unwrap();
+
+    // Blank and malformed lines are skipped like bad CSV rows
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,-2.5000,10.5000,8.0000,false"));
+}
+
+#[test]
+fn test_json_number_amounts_keep_every_digit() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":12345678901234.5678}\n\
+         {\"type\":\"deposit\",\"client\":2,\"tx\":2,\"amount\":0.1}\n\
+         {\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":0.2}\n",
+    )
+    .unwrap();
+
+    // Through f64 the first would print as 12345678901234.5684
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,12345678901234.5678,0.0000,12345678901234.5678,false"))
+        .stdout(predicate::str::contains("2,0.3000,0.0000,0.3000,false"));
+}
+
+#[test]
+fn test_format_flag_overrides_detection() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"3.0\"}\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--format", "json"])
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,3.0000,0.0000,3.0000,false"));
+
+    // Read as CSV the line is no row at all
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--format", "csv"])
+        .arg(temp_file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1,").not());
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["cli", "--format", "xml"])
+        .arg(temp_file.path())
+        .assert()
+        .failure();
+}
+
+// ============================================================================
+// ACCOUNT STATEMENT TESTS
+// ============================================================================
+
+#[test]
+fn test_statement_from_event_log() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        // One client, so log positions don't depend on how shards interleave
+        "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,2,4.0\nwithdrawal,1,3,2.5\nwithdrawal,1,4,50.0\n",
+    )
+    .unwrap();
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let log = temp_dir.path().join("statement.log");
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("cli")
+        .arg(temp_file.path())
+        .arg("--event-log")
+        .arg(&log)
+        .assert()
+        .success();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["statement", "--client", "1", "--from", "2", "--log"])
+        .arg(&log)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("seq,type,tx,amount,counterparty,outcome,available,held,total,locked,recorded_at\n"))
+        .stdout(predicate::str::contains("1,deposit").not())
+        .stdout(predicate::str::contains("2,deposit,2,4.0,,applied,14.0000,0.0000,14.0000,false,"))
+        .stdout(predicate::str::contains("3,withdrawal,3,2.5,,applied,11.5000,0.0000,11.5000,false,"))
+        // Rejected rows never reach the event log
+        .stdout(predicate::str::contains("50.0").not());
+}
+
+#[test]
+fn test_statement_and_trace_leave_the_log_untouched() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let log = temp_dir.path().join("empty.log");
+    fs::write(&log, "").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["statement", "--client", "1", "--log"]).arg(&log).assert().success();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.args(["trace", "--tx", "1", "--log"]).arg(&log).assert().success();
+
+    // Opening it as a writer would have given it a binary header
+    assert!(fs::read(&log).unwrap().is_empty());
+}
@@ -1,4 +1,4 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use std::fs;
 use tempfile::NamedTempFile;
 
@@ -8,9 +8,9 @@ use tempfile::NamedTempFile;
 
 #[test]
 fn test_dispute_and_resolve() {
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
-        .arg("tests/fixtures/disputes.csv")
+        .arg("tests/fixtures/golden/disputes/input.csv")
         .assert()
         .success()
         .get_output()
@@ -39,7 +39,7 @@ fn test_dispute_nonexistent_transaction() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -65,7 +65,7 @@ fn test_dispute_withdrawal_rejected() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -90,7 +90,7 @@ fn test_dispute_wrong_client() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -116,7 +116,7 @@ fn test_double_dispute_same_transaction() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -142,7 +142,7 @@ fn test_dispute_with_negative_balance() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -169,7 +169,7 @@ fn test_multiple_disputes_different_transactions() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -198,7 +198,7 @@ fn test_resolve_non_disputed_transaction() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -225,7 +225,7 @@ fn test_resolve_then_dispute_again() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -252,7 +252,7 @@ fn test_resolve_after_chargeback() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -283,7 +283,7 @@ fn test_chargeback_non_disputed_transaction() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -310,7 +310,7 @@ fn test_chargeback_locks_account() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -339,7 +339,7 @@ fn test_chargeback_with_negative_balance() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -367,7 +367,7 @@ fn test_multiple_chargebacks_blocked() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -400,7 +400,7 @@ fn test_dispute_resolve_dispute_cycle() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -430,7 +430,7 @@ fn test_multiple_disputes_with_mixed_resolution() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -459,7 +459,7 @@ fn test_complex_dispute_flow() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -491,7 +491,7 @@ fn test_multiple_clients_with_disputes() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -523,7 +523,7 @@ fn test_multiple_clients_interleaved_disputes() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -554,7 +554,7 @@ fn test_dispute_tiny_amount() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -580,7 +580,7 @@ fn test_resolve_without_prior_dispute() {
     )
     .unwrap();
 
-    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    let mut cmd = cargo_bin_cmd!("payments-engine");
     let output = cmd
         .arg(temp_file.path())
         .assert()
@@ -593,3 +593,138 @@ fn test_resolve_without_prior_dispute() {
     // Resolve ignored, total = 150
     assert!(output_str.contains("1,150.0000,0.0000,150.0000,false"));
 }
+
+// ============================================================================
+// COMPAT MODES
+// ============================================================================
+
+fn run_with_compat(input: &str, compat: &str) -> String {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(temp_file.path(), input).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd
+        .arg("cli")
+        .arg(temp_file.path())
+        .arg("--compat")
+        .arg(compat)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_withdrawal_dispute_depends_on_compat_mode() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,100.0\n\
+                 withdrawal,1,2,40.0\n\
+                 dispute,1,2\n";
+
+    // Strict: withdrawals are final
+    let output = run_with_compat(input, "strict");
+    assert!(output.contains("1,60.0000,0.0000,60.0000,false"));
+
+    // Extended: the claimed amount is held
+    let output = run_with_compat(input, "extended");
+    assert!(output.contains("1,60.0000,40.0000,100.0000,false"));
+
+    // Resolving drops the claim, charging back returns the funds and locks
+    let resolved = run_with_compat(&format!("{}resolve,1,2\n", input), "extended");
+    assert!(resolved.contains("1,60.0000,0.0000,60.0000,false"));
+    let charged_back = run_with_compat(&format!("{}chargeback,1,2\n", input), "extended");
+    assert!(charged_back.contains("1,100.0000,0.0000,100.0000,true"));
+}
+
+#[test]
+fn test_locked_account_scope_depends_on_compat_mode() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 deposit,1,2,5.0\n\
+                 dispute,1,1\n\
+                 chargeback,1,1\n\
+                 deposit,1,3,20.0\n\
+                 withdrawal,1,4,1.0\n";
+
+    // Strict: the lock freezes everything
+    let output = run_with_compat(input, "strict");
+    assert!(output.contains("1,5.0000,0.0000,5.0000,true"));
+
+    // Extended: deposits still land, withdrawals don't
+    let output = run_with_compat(input, "extended");
+    assert!(output.contains("1,25.0000,0.0000,25.0000,true"));
+}
+
+#[test]
+fn test_lock_policy_flag_holds_deposits_to_locked_accounts() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\n\
+         deposit,1,1,10.0\n\
+         deposit,1,2,5.0\n\
+         dispute,1,1\n\
+         chargeback,1,1\n\
+         deposit,1,3,20.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd
+        .arg("cli")
+        .arg(temp_file.path())
+        .arg("--lock-policy")
+        .arg("hold")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // Accepted, held until an unlock
+    assert!(String::from_utf8(output).unwrap().contains("1,5.0000,20.0000,25.0000,true"));
+}
+
+#[test]
+fn test_dispute_withdrawals_flag_over_strict_mode() {
+    let temp_file = NamedTempFile::new().unwrap();
+    fs::write(
+        temp_file.path(),
+        "type,client,tx,amount\n\
+         deposit,1,1,100.0\n\
+         withdrawal,1,2,40.0\n\
+         dispute,1,2\n\
+         chargeback,1,2\n\
+         deposit,1,3,5.0\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd
+        .arg("cli")
+        .arg(temp_file.path())
+        .arg("--dispute-withdrawals")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // The charged back withdrawal is returned, the rest of strict mode still applies: the lock freezes deposits
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("1,100.0000,0.0000,100.0000,true"));
+}
+
+#[test]
+fn test_unknown_compat_mode_is_rejected() {
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    cmd.arg("cli")
+        .arg("tests/fixtures/golden/basic/input.csv")
+        .arg("--compat")
+        .arg("lenient")
+        .assert()
+        .failure();
+}
@@ -0,0 +1,148 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use futures::StreamExt;
+use payments_engine::csv_io::{stream_transactions, write_accounts};
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::{AccountOutput, ScalableEngine};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const GOLDEN_DIR: &str = "tests/fixtures/golden";
+
+/// Every directory holding an input.csv / expected_accounts.csv pair
+fn discover_cases() -> Vec<PathBuf> {
+    let mut cases: Vec<PathBuf> = std::fs::read_dir(GOLDEN_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("input.csv").is_file() && path.join("expected_accounts.csv").is_file())
+        .collect();
+    cases.sort();
+    cases
+}
+
+fn run_cli(case: &Path) -> String {
+    let mut cmd = cargo_bin_cmd!("payments-engine");
+    let output = cmd.arg(case.join("input.csv")).assert().success().get_output().stdout.clone();
+    String::from_utf8(output).unwrap()
+}
+
+async fn run_library(case: &Path) -> String {
+    let temp_dir = TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("golden.log"), 16, cold_storage)
+        .await
+        .unwrap();
+
+    let file = tokio::fs::File::open(case.join("input.csv")).await.unwrap();
+    let mut rows = stream_transactions(tokio::io::BufReader::new(file));
+    while let Some(row) = rows.next().await {
+        if let Ok(row) = row {
+            let _ = engine.process(row).await;
+        }
+    }
+
+    let mut accounts: Vec<AccountOutput> = engine.get_accounts().await.iter().map(AccountOutput::from).collect();
+    accounts.sort_by_key(|a| a.client);
+
+    let mut out = Vec::new();
+    write_accounts(&mut out, accounts).await.unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Line diff of expected vs actual, empty when they match
+fn diff(expected: &str, actual: &str) -> String {
+    if expected == actual {
+        return String::new();
+    }
+
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push_str(&format!("  {}\n", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push_str(&format!("- {}\n", e));
+                }
+                if let Some(a) = a {
+                    out.push_str(&format!("+ {}\n", a));
+                }
+            }
+        }
+    }
+    // Same lines but different bytes, e.g. a missing trailing newline
+    if out.lines().all(|line| line.starts_with("  ")) {
+        out.push_str("(outputs differ in line endings)\n");
+    }
+    out
+}
+
+// ============================================================================
+// GOLDEN FIXTURE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_golden_fixtures() {
+    let cases = discover_cases();
+    assert!(!cases.is_empty(), "no golden cases under {}", GOLDEN_DIR);
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let expected = std::fs::read_to_string(case.join("expected_accounts.csv")).unwrap();
+
+        for (path, actual) in [("cli", run_cli(case)), ("library", run_library(case).await)] {
+            let diff = diff(&expected, &actual);
+            if !diff.is_empty() {
+                failures.push(format!("{} ({}):\n{}", case.display(), path, diff));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "golden mismatches:\n\n{}", failures.join("\n"));
+}
+
+// ============================================================================
+// RECORDED FIXTURE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_recorded_bundle_replays_as_golden_case() {
+    use payments_engine::recorder::{FixtureRecorder, RecorderConfig};
+    use payments_engine::test_support::{chargeback, deposit, dispute, transfer, withdrawal};
+    use rust_decimal_macros::dec;
+
+    let temp_dir = TempDir::new().unwrap();
+    let case = temp_dir.path().join("recorded");
+    let recorder = FixtureRecorder::new(RecorderConfig {
+        dir: case.clone(),
+        sample_rate: 1.0,
+        max_rows: 100,
+    });
+    for row in [
+        deposit(300, 70, dec!(25)),
+        transfer(300, 12, 71, dec!(5)),
+        withdrawal(12, 72, dec!(1)),
+        deposit(12, 73, dec!(4)),
+        dispute(12, 73),
+        chargeback(12, 73),
+        // Refers to a deposit from before recording started
+        dispute(300, 1),
+    ] {
+        recorder.record(&row);
+    }
+    assert_eq!(recorder.finish().await.unwrap(), Some(case.clone()));
+
+    let input = std::fs::read_to_string(case.join("input.csv")).unwrap();
+    assert!(input.starts_with("type,client,tx,amount,to\ndeposit,1,1,25,\ntransfer,1,2,5,2\n"));
+    assert!(!input.contains("300") && !input.contains(",70,"));
+
+    let expected = std::fs::read_to_string(case.join("expected_accounts.csv")).unwrap();
+    assert_eq!(
+        expected,
+        "client,available,held,total,locked\n1,20.0000,0.0000,20.0000,false\n2,4.0000,0.0000,4.0000,true\n"
+    );
+    for (path, actual) in [("cli", run_cli(&case)), ("library", run_library(&case).await)] {
+        assert!(diff(&expected, &actual).is_empty(), "{}:\n{}", path, diff(&expected, &actual));
+    }
+}
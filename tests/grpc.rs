@@ -0,0 +1,234 @@
+#![cfg(feature = "grpc")]
+
+use payments_engine::grpc::proto::payments_engine_client::PaymentsEngineClient;
+use payments_engine::grpc::proto::{Consistency, ErrorDetail, GetAccountRequest, ListAccountsRequest, Transaction};
+use payments_engine::grpc::serve_on;
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::ScalableEngine;
+use prost::Message;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/// Engine served on an ephemeral port, with a client connected to it
+async fn start(temp_dir: &TempDir) -> (PaymentsEngineClient<Channel>, CancellationToken) {
+    let log_path = temp_dir.path().join("grpc.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    start_engine(engine).await
+}
+
+async fn start_engine(engine: ScalableEngine) -> (PaymentsEngineClient<Channel>, CancellationToken) {
+    let engine = Arc::new(engine);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = CancellationToken::new();
+    tokio::spawn(serve_on(listener, engine, shutdown.clone().cancelled_owned()));
+
+    let client = PaymentsEngineClient::connect(format!("http://{}", addr)).await.unwrap();
+    (client, shutdown)
+}
+
+fn transaction(tx_type: &str, client: u32, tx: u32, amount: Option<&str>) -> Transaction {
+    Transaction {
+        r#type: tx_type.to_string(),
+        client,
+        tx,
+        amount: amount.map(str::to_string),
+        to: None,
+    }
+}
+
+fn error_detail(status: &tonic::Status) -> ErrorDetail {
+    ErrorDetail::decode(status.details()).unwrap()
+}
+
+/// Rows streamed under the `acme` API key
+fn keyed_stream(transactions: Vec<Transaction>) -> tonic::Request<futures::stream::Iter<std::vec::IntoIter<Transaction>>> {
+    let mut request = tonic::Request::new(futures::stream::iter(transactions));
+    request.metadata_mut().insert("x-api-key", "acme".parse().unwrap());
+    request
+}
+
+// ============================================================================
+// SUBMIT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_submit_transaction_applies_and_reads_back() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut client, shutdown) = start(&temp_dir).await;
+
+    let reply = client.submit_transaction(transaction("deposit", 1, 1, Some("10.5"))).await.unwrap();
+    assert_eq!(reply.into_inner().tx, 1);
+    client.submit_transaction(transaction("withdrawal", 1, 2, Some("2.5"))).await.unwrap();
+
+    let account = client
+        .get_account(GetAccountRequest { client: 1, consistency: Consistency::Strong as i32 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(account.available, "8.0000");
+    assert_eq!(account.held, "0.0000");
+    assert_eq!(account.total, "8.0000");
+    assert!(!account.locked);
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn test_submit_transaction_error_carries_typed_detail() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut client, shutdown) = start(&temp_dir).await;
+
+    client.submit_transaction(transaction("deposit", 1, 1, Some("5"))).await.unwrap();
+
+    let status = client.submit_transaction(transaction("withdrawal", 1, 2, Some("50"))).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let detail = error_detail(&status);
+    assert_eq!(detail.code, "insufficient_funds");
+    assert!(!detail.transient);
+    assert_eq!(detail.tx, Some(2));
+    assert_eq!(detail.client, Some(1));
+
+    let status = client.submit_transaction(transaction("dispute", 1, 99, None)).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(error_detail(&status).code, "transaction_not_found");
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn test_submit_transaction_malformed_is_invalid_argument() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut client, shutdown) = start(&temp_dir).await;
+
+    let status = client.submit_transaction(transaction("deposit", 1, 1, Some("ten"))).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(error_detail(&status).code, "malformed");
+
+    let status = client.submit_transaction(transaction("deposit", 70_000, 2, Some("1"))).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let status = client.submit_transaction(transaction("refund", 1, 3, Some("1"))).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn test_submit_outside_key_scope_is_permission_denied() {
+    use payments_engine::auth::{ApiKeys, ClientScope};
+
+    let keys = ApiKeys::new().with_key("acme", ClientScope::Only([1].into()));
+    let engine = ScalableEngine::without_event_log(4, Arc::new(InMemoryStore::new())).with_api_keys(keys);
+    let (mut client, shutdown) = start_engine(engine).await;
+
+    let keyed = |transaction: Transaction| {
+        let mut request = tonic::Request::new(transaction);
+        request.metadata_mut().insert("x-api-key", "acme".parse().unwrap());
+        request
+    };
+    client.submit_transaction(keyed(transaction("deposit", 1, 1, Some("5")))).await.unwrap();
+
+    let status = client.submit_transaction(keyed(transaction("deposit", 2, 2, Some("5")))).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(error_detail(&status).code, "unauthorized");
+
+    // No key, no client
+    let status = client.submit_transaction(transaction("deposit", 1, 3, Some("5"))).await.unwrap_err();
+    assert_eq!(error_detail(&status).code, "unauthorized");
+
+    let summary = client
+        .submit_stream(keyed_stream(vec![transaction("deposit", 1, 4, Some("1")), transaction("deposit", 3, 5, Some("1"))]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(summary.applied, 1);
+    let rejected: Vec<_> = summary.rejections.iter().map(|r| (r.tx, r.code.as_str())).collect();
+    assert_eq!(rejected, vec![(5, "unauthorized")]);
+
+    shutdown.cancel();
+}
+
+// ============================================================================
+// STREAM TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_submit_stream_summarizes_applied_and_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut client, shutdown) = start(&temp_dir).await;
+
+    let rows = vec![
+        transaction("deposit", 1, 1, Some("100")),
+        transaction("deposit", 2, 2, Some("20")),
+        transaction("withdrawal", 2, 3, Some("30")),
+        transaction("deposit", 1, 4, Some("not-a-number")),
+        transaction("dispute", 1, 1, None),
+    ];
+    let summary = client
+        .submit_stream(futures::stream::iter(rows))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(summary.applied, 3);
+    let rejected: Vec<(u32, &str)> = summary.rejections.iter().map(|r| (r.tx, r.code.as_str())).collect();
+    assert_eq!(rejected, vec![(3, "insufficient_funds"), (4, "malformed")]);
+
+    let account = client
+        .get_account(GetAccountRequest { client: 1, consistency: Consistency::Strong as i32 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(account.available, "0.0000");
+    assert_eq!(account.held, "100.0000");
+
+    shutdown.cancel();
+}
+
+// ============================================================================
+// ACCOUNT QUERY TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_list_accounts_sorted_by_client() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut client, shutdown) = start(&temp_dir).await;
+
+    for (client_id, tx) in [(3, 1), (1, 2), (2, 3)] {
+        client.submit_transaction(transaction("deposit", client_id, tx, Some("1"))).await.unwrap();
+    }
+
+    let accounts = client
+        .list_accounts(ListAccountsRequest { consistency: Consistency::Strong as i32 })
+        .await
+        .unwrap()
+        .into_inner()
+        .accounts;
+    let clients: Vec<u32> = accounts.iter().map(|a| a.client).collect();
+    assert_eq!(clients, vec![1, 2, 3]);
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn test_get_unknown_account_is_not_found() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut client, shutdown) = start(&temp_dir).await;
+
+    let status = client
+        .get_account(GetAccountRequest { client: 42, consistency: Consistency::Eventual as i32 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    let detail = error_detail(&status);
+    assert_eq!(detail.code, "account_not_found");
+    assert_eq!(detail.client, Some(42));
+
+    shutdown.cancel();
+}
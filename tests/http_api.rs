@@ -0,0 +1,1012 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use payments_engine::http::router;
+use payments_engine::storage::{InMemoryStore, TransactionStore};
+use payments_engine::{ScalableEngine, TransactionRow, TransactionType};
+use rust_decimal_macros::dec;
+use serde_json::Value;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+async fn test_engine(temp_dir: &TempDir) -> Arc<ScalableEngine> {
+    let log_path = temp_dir.path().join("http.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    Arc::new(ScalableEngine::new(log_path, 4, cold_storage).await.unwrap())
+}
+
+async fn get_json(engine: Arc<ScalableEngine>, uri: &str) -> (StatusCode, Value) {
+    let response = router(engine)
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, json)
+}
+
+// ============================================================================
+// QUERY CONSISTENCY TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_strong_read_sees_acknowledged_write() {
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx: 1,
+        amount: Some(dec!(100.0)),
+        to: None,
+    }).await.unwrap();
+
+    let (status, body) = get_json(engine.clone(), "/accounts/1?consistency=strong").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["available"], "100.0");
+
+    // The projection converges on the same state
+    let mut converged = false;
+    for _ in 0..50 {
+        let (status, body) = get_json(engine.clone(), "/accounts/1").await;
+        if status == StatusCode::OK && body["available"] == "100.0" {
+            converged = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(converged);
+}
+
+#[tokio::test]
+async fn test_unknown_consistency_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    let (status, _) = get_json(engine, "/accounts?consistency=linearizable").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// DISPUTE REPORT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_dispute_report_json_and_csv() {
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 3,
+        tx: 1,
+        amount: Some(dec!(5.0)),
+        to: None,
+    }).await.unwrap();
+    engine.process(TransactionRow {
+        tx_type: TransactionType::Dispute,
+        client: 3,
+        tx: 1,
+        amount: None,
+        to: None,
+    }).await.unwrap();
+
+    let (status, body) = get_json(engine.clone(), "/reports/disputes").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["client"], 3);
+    assert_eq!(body[0]["transactions"], 1);
+    assert_eq!(body[0]["disputes"], 1);
+
+    let (_, body) = get_json(engine.clone(), "/reports/disputes?month=1970-01").await;
+    assert_eq!(body.as_array().unwrap().len(), 0);
+
+    let response = router(engine)
+        .oneshot(Request::builder().uri("/reports/disputes.csv").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "text/csv");
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let csv = String::from_utf8(body.to_vec()).unwrap();
+    assert!(csv.starts_with("client,month,transactions,disputes,chargebacks\n3,"));
+}
+
+// ============================================================================
+// TREASURY REPORT TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_treasury_report_follows_account_updates() {
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    let rows = [
+        (TransactionType::Deposit, 1, 1, Some(dec!(100.0))),
+        (TransactionType::Withdrawal, 1, 2, Some(dec!(80.0))),
+        (TransactionType::Deposit, 2, 3, Some(dec!(50.0))),
+        (TransactionType::Dispute, 1, 1, None),
+        (TransactionType::Chargeback, 1, 1, None),
+    ];
+    for (tx_type, client, tx, amount) in rows {
+        engine.process(TransactionRow { tx_type, client, tx, amount, to: None }).await.unwrap();
+    }
+
+    // Strong totals are the shard manager's running sums, current as soon as the writes are acknowledged
+    let (_, strong) = get_json(engine.clone(), "/reports/treasury?consistency=strong").await;
+    assert_eq!(strong["locked"], 1);
+    assert_eq!(strong["negative_exposure"], "80.0");
+
+    // Eventual totals come from the projection, wait for it to fold in the chargeback
+    let mut report = Value::Null;
+    for _ in 0..50 {
+        let (status, body) = get_json(engine.clone(), "/reports/treasury").await;
+        assert_eq!(status, StatusCode::OK);
+        if body["locked"] == 1 {
+            report = body;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    // Client 1 spent 80 of a deposit that was then charged back
+    assert_eq!(report["accounts"], 2);
+    assert_eq!(report["available"], "-30.0");
+    assert_eq!(report["held"], "0.0");
+    assert_eq!(report["total"], "-30.0");
+    assert_eq!(report["negative_exposure"], "80.0");
+
+    let response = router(engine)
+        .oneshot(Request::builder().uri("/reports/treasury.csv").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        "accounts,available,held,total,locked,negative_exposure\n2,-30.0000,0.0000,-30.0000,1,80.0000\n"
+    );
+}
+
+// ============================================================================
+// PERIOD CLOSE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_close_period_endpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    let close = |uri: &'static str| {
+        let engine = engine.clone();
+        async move {
+            router(engine)
+                .oneshot(Request::builder().method("POST").uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+
+    assert_eq!(close("/periods/2022-06/close").await, StatusCode::CREATED);
+    assert_eq!(close("/periods/2022-06/close").await, StatusCode::OK);
+    assert_eq!(close("/periods/june/close").await, StatusCode::BAD_REQUEST);
+
+    let (status, body) = get_json(engine, "/periods").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, serde_json::json!(["2022-06"]));
+}
+
+// ============================================================================
+// HOT STORAGE ADMIN TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_inspect_and_force_migrate_hot_storage() {
+    use payments_engine::test_support::{deposit, dispute, resolve};
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    for row in [
+        deposit(1, 1, dec!(1.0)),
+        deposit(1, 2, dec!(2.0)),
+        deposit(1, 3, dec!(3.0)),
+        deposit(2, 4, dec!(4.0)),
+        dispute(1, 2),
+    ] {
+        engine.process(row).await.unwrap();
+    }
+
+    let (status, body) = get_json(engine.clone(), "/admin/hot-storage").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, serde_json::json!([
+        {"client": 1, "transactions": 3},
+        {"client": 2, "transactions": 1},
+    ]));
+
+    let (status, body) = get_json(engine.clone(), "/admin/accounts/1/hot").await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = body.as_array().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1]["tx"], 2);
+    assert_eq!(entries[1]["tx_type"], "deposit");
+    assert_eq!(entries[1]["disputed"], true);
+    assert!(entries[1]["age_secs"].is_u64());
+
+    let migrate = |uri: &'static str| {
+        let engine = engine.clone();
+        async move {
+            router(engine)
+                .oneshot(Request::builder().method("POST").uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+
+    // Fresh transactions are moved too, unlike the hourly pass
+    assert_eq!(migrate("/admin/accounts/1/migrate").await, StatusCode::ACCEPTED);
+    let mut drained = false;
+    for _ in 0..50 {
+        let (_, body) = get_json(engine.clone(), "/admin/accounts/1/hot").await;
+        if body.as_array().unwrap().is_empty() {
+            drained = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(drained);
+
+    // Still disputable from cold storage
+    engine.process(resolve(1, 2)).await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(6.0));
+
+    // Admin calls never spawn an actor
+    assert_eq!(migrate("/admin/accounts/9/migrate").await, StatusCode::NOT_FOUND);
+    let (status, _) = get_json(engine.clone(), "/admin/accounts/9/hot").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_reshard_over_admin_api() {
+    use payments_engine::test_support::deposit;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    for client in 1..=20 {
+        engine.process(deposit(client, client as u32, dec!(1.0))).await.unwrap();
+    }
+
+    let put = |body: &'static str| {
+        let engine = engine.clone();
+        async move {
+            let response = router(engine)
+                .oneshot(
+                    Request::builder()
+                        .method("PUT")
+                        .uri("/admin/shards")
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+            (status, body)
+        }
+    };
+
+    let (status, body) = put(r#"{"shards": 6}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["shards"], 6);
+    assert!(body["moved"].as_u64().unwrap() <= 20);
+    let (_, stats) = get_json(engine.clone(), "/admin/shards").await;
+    assert_eq!(stats.as_array().unwrap().len(), 6);
+    assert_eq!(stats.as_array().unwrap().iter().map(|shard| shard["actors"].as_u64().unwrap()).sum::<u64>(), 20);
+
+    let (status, body) = put(r#"{"shards": 0}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_shards");
+    assert_eq!(engine.num_shards(), 6);
+    assert_eq!(engine.get_account(13).await.unwrap().available, dec!(1.0));
+}
+
+#[tokio::test]
+async fn test_migrate_all_moves_every_running_actor_to_cold_storage() {
+    use payments_engine::test_support::{deposit, dispute};
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    for row in [deposit(1, 1, dec!(1.0)), deposit(2, 2, dec!(2.0)), deposit(2, 3, dec!(3.0)), deposit(7, 4, dec!(4.0))] {
+        engine.process(row).await.unwrap();
+    }
+
+    let response = router(engine.clone())
+        .oneshot(Request::builder().method("POST").uri("/admin/migrate").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body, serde_json::json!({"actors": 3}));
+
+    let mut drained = false;
+    for _ in 0..50 {
+        let (_, body) = get_json(engine.clone(), "/admin/hot-storage").await;
+        if body.as_array().unwrap().iter().all(|size| size["transactions"] == 0) {
+            drained = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(drained);
+
+    // Still disputable from cold storage, and no actor was spawned for the broadcast
+    engine.process(dispute(2, 3)).await.unwrap();
+    assert_eq!(engine.get_account(2).await.unwrap().held, dec!(3.0));
+    assert_eq!(engine.migrate_all_cold().await, 3);
+}
+
+#[tokio::test]
+async fn test_shard_stats_report_each_shards_load() {
+    use payments_engine::test_support::deposit;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    let shards = engine.stats().await.len();
+
+    for row in [deposit(1, 1, dec!(1.0)), deposit(1, 2, dec!(2.0)), deposit(1, 3, dec!(3.0))] {
+        engine.process(row).await.unwrap();
+    }
+
+    let (status, body) = get_json(engine.clone(), "/admin/shards").await;
+    assert_eq!(status, StatusCode::OK);
+    let stats = body.as_array().unwrap();
+    assert_eq!(stats.len(), shards);
+    let busy: Vec<&Value> = stats.iter().filter(|shard| shard["actors"] != 0).collect();
+    assert_eq!(busy.len(), 1);
+    assert_eq!(busy[0]["shard"], engine.shard_of(1));
+    assert_eq!(busy[0]["hot_transactions"], 3);
+    assert_eq!(busy[0]["mailbox_depth"], 0);
+    assert_eq!(busy[0]["in_flight"], 0);
+}
+
+// ============================================================================
+// COLD STORAGE COMPACTION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_compaction_drops_records_no_dispute_can_target() {
+    use payments_engine::test_support::{deposit, dispute, resolve, withdrawal};
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    for row in [
+        deposit(1, 1, dec!(10.0)),
+        withdrawal(1, 2, dec!(4.0)),
+        deposit(1, 3, dec!(2.0)),
+        dispute(1, 3),
+    ] {
+        engine.process(row).await.unwrap();
+    }
+    assert!(engine.force_migrate_cold(1).await);
+    for _ in 0..50 {
+        if engine.hot_transactions(1).await.unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let response = router(engine.clone())
+        .oneshot(Request::builder().method("POST").uri("/admin/cold-storage/compact").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["scanned"], 3);
+    assert_eq!(body["removed"], 1);
+    assert!(body["bytes_reclaimed"].as_u64().unwrap() > 0);
+
+    // Only the strict-compat withdrawal went, both deposits stay disputable
+    let (_, page) = get_json(engine.clone(), "/accounts/1/transactions").await;
+    let txs: Vec<u64> = page["transactions"].as_array().unwrap().iter().map(|entry| entry["tx"].as_u64().unwrap()).collect();
+    assert_eq!(txs, vec![1, 3]);
+    engine.process(resolve(1, 3)).await.unwrap();
+    engine.process(dispute(1, 1)).await.unwrap();
+
+    // Nothing left to drop
+    let report = engine.compact_cold_storage().await.unwrap();
+    assert_eq!((report.scanned, report.removed, report.bytes_reclaimed), (2, 0, 0));
+}
+
+// ============================================================================
+// ACCOUNT UNLOCK TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_unlock_account_after_chargeback() {
+    use payments_engine::test_support::{chargeback, deposit, dispute};
+    use payments_engine::ProcessingError;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    for row in [deposit(1, 1, dec!(5.0)), deposit(1, 2, dec!(3.0)), dispute(1, 2), chargeback(1, 2)] {
+        engine.process(row).await.unwrap();
+    }
+    assert!(engine.get_account(1).await.unwrap().locked);
+
+    let unlock = |uri: &'static str| {
+        let engine = engine.clone();
+        async move {
+            router(engine)
+                .oneshot(Request::builder().method("POST").uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+
+    // Producers can't unlock through the row stream
+    let row = TransactionRow { tx_type: TransactionType::Unlock, client: 1, tx: 0, amount: None, to: None };
+    assert!(matches!(engine.process(row).await, Err(ProcessingError::UnsupportedTransactionType)));
+
+    assert_eq!(unlock("/admin/accounts/1/unlock").await, StatusCode::OK);
+    assert_eq!(unlock("/admin/accounts/1/unlock").await, StatusCode::CONFLICT);
+    assert_eq!(unlock("/admin/accounts/9/unlock").await, StatusCode::NOT_FOUND);
+
+    engine.process(deposit(1, 3, dec!(1.0))).await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(6.0));
+
+    // The unlock is in the log, a restarted engine comes back unlocked
+    drop(engine);
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let restarted = ScalableEngine::new(temp_dir.path().join("http.log"), 4, cold_storage).await.unwrap();
+    restarted.rebuild_from_events().await.unwrap();
+    let account = restarted.get_account(1).await.unwrap();
+    assert!(!account.locked);
+    assert_eq!(account.available, dec!(6.0));
+}
+
+// ============================================================================
+// BALANCE ALERT RULE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_alert_rules_admin_api() {
+    use serde_json::json;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    let put = |body: Value| {
+        let engine = engine.clone();
+        async move {
+            let response = router(engine)
+                .oneshot(
+                    Request::builder()
+                        .method("PUT")
+                        .uri("/admin/accounts/7/alerts")
+                        .header("content-type", "application/json")
+                        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null))
+        }
+    };
+
+    // Rules can be set before the client has an account
+    let rules = json!([{"kind": "available_below", "threshold": "100"}, {"kind": "negative_available"}]);
+    let (status, body) = put(rules.clone()).await;
+    assert_eq!((status, &body), (StatusCode::OK, &rules));
+    assert_eq!(get_json(engine.clone(), "/admin/accounts/7/alerts").await, (StatusCode::OK, rules));
+
+    let (status, body) = put(json!([{"kind": "held_above"}])).await;
+    assert_eq!(status.as_u16() / 100, 4);
+    assert_eq!(body["code"], "malformed");
+
+    assert_eq!(put(json!([])).await, (StatusCode::OK, json!([])));
+    assert_eq!(get_json(engine, "/admin/accounts/7/alerts").await, (StatusCode::OK, json!([])));
+}
+
+// ============================================================================
+// DRY-RUN VALIDATION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_validate_reports_outcome_without_applying() {
+    use payments_engine::test_support::{deposit, dispute, transfer, withdrawal};
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    engine.process(deposit(1, 1, dec!(10.0))).await.unwrap();
+
+    let validate = |row: TransactionRow| {
+        let engine = engine.clone();
+        async move {
+            let response = router(engine)
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/transactions:validate")
+                        .header("content-type", "application/json")
+                        .body(Body::from(serde_json::to_vec(&row).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()["code"].as_str().unwrap().to_string()
+        }
+    };
+
+    assert_eq!(validate(withdrawal(1, 2, dec!(20.0))).await, "insufficient_funds");
+    assert_eq!(validate(withdrawal(1, 2, dec!(5.0))).await, "ok");
+    assert_eq!(validate(deposit(1, 1, dec!(3.0))).await, "duplicate_transaction");
+    assert_eq!(validate(dispute(1, 99)).await, "transaction_not_found");
+    assert_eq!(validate(transfer(1, 1, 3, dec!(1.0))).await, "invalid_transfer");
+    assert_eq!(validate(transfer(1, 7, 3, dec!(1.0))).await, "ok");
+    assert_eq!(validate(deposit(7, 4, dec!(1.0))).await, "ok");
+
+    // Nothing was applied: no balance change, no new account, tx 2 still free
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(10.0));
+    assert!(engine.get_account(7).await.is_none());
+    engine.process(withdrawal(1, 2, dec!(5.0))).await.unwrap();
+}
+
+// ============================================================================
+// TRANSACTION HISTORY TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_transaction_history_pages_through_hot_and_cold() {
+    use payments_engine::history::Pagination;
+    use payments_engine::storage::StorageTier;
+    use payments_engine::test_support::{deposit, dispute};
+    use payments_engine::ProcessingError;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    for tx in 1..=5 {
+        engine.process(deposit(1, tx, dec!(1.0))).await.unwrap();
+    }
+    engine.process(deposit(2, 6, dec!(1.0))).await.unwrap();
+
+    assert!(engine.force_migrate_cold(1).await);
+    let mut drained = false;
+    for _ in 0..50 {
+        if engine.hot_transactions(1).await.unwrap().is_empty() {
+            drained = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(drained);
+
+    // New hot transactions interleave with cold ones, a cold one changed since is shown as changed
+    engine.process(deposit(1, 7, dec!(2.0))).await.unwrap();
+    engine.process(deposit(1, 8, dec!(2.0))).await.unwrap();
+    engine.process(dispute(1, 4)).await.unwrap();
+
+    let first = engine.get_transactions(1, Pagination::first(3)).await.unwrap();
+    let ids: Vec<u32> = first.transactions.iter().map(|entry| entry.tx).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert!(first.transactions.iter().all(|entry| entry.tier == StorageTier::Cold));
+    assert_eq!(first.next, Some(3));
+
+    let second = engine.get_transactions(1, Pagination::after(3, 3)).await.unwrap();
+    let ids: Vec<u32> = second.transactions.iter().map(|entry| entry.tx).collect();
+    assert_eq!(ids, vec![4, 5, 7]);
+    assert!(second.transactions[0].transaction.disputed);
+    assert_eq!(second.transactions[2].tier, StorageTier::Hot);
+    assert_eq!(second.next, Some(7));
+
+    let last = engine.get_transactions(1, Pagination::after(7, 3)).await.unwrap();
+    assert_eq!(last.transactions.len(), 1);
+    assert_eq!(last.transactions[0].tx, 8);
+    assert_eq!(last.next, None);
+
+    assert!(matches!(
+        engine.get_transactions(9, Pagination::default()).await,
+        Err(ProcessingError::AccountNotFound)
+    ));
+
+    let (status, body) = get_json(engine.clone(), "/accounts/1/transactions?after=5&limit=10").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["next"], Value::Null);
+    let entries = body["transactions"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["tx"], 7);
+    assert_eq!(entries[0]["tier"], "hot");
+    assert_eq!(entries[0]["tx_type"], "deposit");
+    assert_eq!(entries[0]["client"], 1);
+
+    let (status, _) = get_json(engine.clone(), "/accounts/9/transactions").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// PROBLEM DETAILS TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_errors_are_problem_details() {
+    use payments_engine::http::status_of;
+    use payments_engine::test_support::deposit;
+    use payments_engine::ProcessingError;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+
+    let send = |method: &'static str, uri: &'static str, body: &'static str| {
+        let engine = engine.clone();
+        async move {
+            let response = router(engine)
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, content_type, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    let (status, content_type, body) = send("POST", "/admin/accounts/9/unlock", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(content_type, "application/problem+json");
+    assert_eq!(body["type"], "urn:payments-engine:problem:account_not_found");
+    assert_eq!(body["title"], "account not found");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["code"], "account_not_found");
+    assert_eq!(body["client"], 9);
+
+    let (status, _, body) = send("POST", "/admin/accounts/1/unlock", "").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "account_not_locked");
+
+    let (status, _, body) = send("GET", "/transactions/77/trace", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "transaction_not_found");
+    assert_eq!(body["tx"], 77);
+
+    let (status, _, body) = send("POST", "/transactions:validate", "{\"type\":\"deposit\"").await;
+    assert!(status.is_client_error());
+    assert_eq!(body["code"], "malformed");
+    assert!(body["detail"].is_string());
+
+    // Path and query string parse failures too
+    let (status, content_type, body) = send("GET", "/accounts/abc", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type, "application/problem+json");
+    assert_eq!(body["code"], "malformed");
+    assert!(body["detail"].is_string());
+
+    let (status, content_type, body) = send("GET", "/accounts?consistency=linearizable", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type, "application/problem+json");
+    assert_eq!(body["code"], "malformed");
+
+        // Rejections of a dry run are its answer, not a failed request
+    let (status, content_type, body) =
+        send("POST", "/transactions:validate", "{\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"9.0\"}").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/json");
+    assert_eq!(body["code"], "insufficient_funds");
+
+    assert_eq!(status_of(&ProcessingError::InsufficientFunds), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(status_of(&ProcessingError::DuplicateTransaction), StatusCode::CONFLICT);
+    assert_eq!(status_of(&ProcessingError::AccountLocked), StatusCode::LOCKED);
+    assert_eq!(status_of(&ProcessingError::ReservedClient), StatusCode::FORBIDDEN);
+    assert_eq!(status_of(&ProcessingError::RebuildPending), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+// ============================================================================
+// BULK ACCOUNT QUERY TESTS
+// ============================================================================
+
+async fn post_json(engine: Arc<ScalableEngine>, uri: &str, body: Value) -> (StatusCode, Value) {
+    let response = router(engine)
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_bulk_account_query_with_field_mask() {
+    use payments_engine::test_support::deposit;
+    use serde_json::json;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    for client in 1..=10u16 {
+        engine.process(deposit(client, client as u32, dec!(1.5))).await.unwrap();
+    }
+
+    // Listed ids and a range are merged, unknown clients left out, results in client order
+    let (status, body) = post_json(
+        engine.clone(),
+        "/accounts:query",
+        json!({"clients": [9, 2, 42, 2], "range": {"from": 4, "to": 5}, "fields": ["client", "available", "locked"], "consistency": "strong"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!([
+            {"client": 2, "available": "1.5", "locked": false},
+            {"client": 4, "available": "1.5", "locked": false},
+            {"client": 5, "available": "1.5", "locked": false},
+            {"client": 9, "available": "1.5", "locked": false},
+        ])
+    );
+
+    // Without a mask every field comes back; eventual reads converge on the same accounts
+    let mut converged = false;
+    for _ in 0..50 {
+        let (status, body) = post_json(engine.clone(), "/accounts:query", json!({"range": {"from": 0, "to": 100}})).await;
+        assert_eq!(status, StatusCode::OK);
+        if body.as_array().unwrap().len() == 10 {
+            assert_eq!(body[0], json!({"client": 1, "available": "1.5", "held": "0", "total": "1.5", "locked": false}));
+            converged = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(converged);
+
+    for bad in [json!({"fields": ["balance"]}), json!({"range": {"from": 5, "to": 4}}), json!({"client": [1]})] {
+        let (status, body) = post_json(engine.clone(), "/accounts:query", bad).await;
+        assert_eq!(status.as_u16() / 100, 4);
+        assert_eq!(body["code"], "malformed");
+    }
+}
+
+// ============================================================================
+// CAPABILITIES TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_capabilities_list_run_features() {
+    use payments_engine::spill::SpillConfig;
+    use serde_json::json;
+
+    let temp_dir = TempDir::new().unwrap();
+    let (status, body) = get_json(test_engine(&temp_dir).await, "/capabilities").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["protocol_version"], 1);
+    assert_eq!(body["engine_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(body["formats"], json!(["csv", "json", "msgpack"]));
+    assert!(!body["features"].as_array().unwrap().contains(&json!("spill")));
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let spilling = ScalableEngine::new(temp_dir.path().join("spill.log"), 4, cold_storage)
+        .await
+        .unwrap()
+        .with_spill(SpillConfig { dir: temp_dir.path().to_path_buf(), memory_rows: 16 });
+    let (_, body) = get_json(Arc::new(spilling), "/capabilities").await;
+    assert!(body["features"].as_array().unwrap().contains(&json!("spill")));
+}
+
+// ============================================================================
+// LATENCY PROBE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_probe_metrics_count_probes() {
+    use payments_engine::prober::LatencyProber;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    let (status, body) = get_json(engine, "/metrics/probe").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["probes"], 0);
+
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("probe.log");
+    let engine = ScalableEngine::new(log_path, 4, Arc::new(InMemoryStore::new())).await.unwrap().with_prober();
+    let prober = LatencyProber::new(engine.probe_metrics().unwrap().clone());
+    prober.probe(&engine).await.unwrap();
+
+    let (status, body) = get_json(Arc::new(engine), "/metrics/probe").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["probes"], 2);
+    assert_eq!(body["failures"], 0);
+}
+
+// ============================================================================
+// WEBSOCKET TESTS
+// ============================================================================
+
+type WsClient = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// The API served on an ephemeral port, with a WebSocket session opened on it
+async fn open_ws(engine: Arc<ScalableEngine>) -> WsClient {
+    open_ws_with_key(engine, None).await
+}
+
+/// As `open_ws`, presenting `key` in the upgrade request's `x-api-key` header
+async fn open_ws_with_key(engine: Arc<ScalableEngine>, key: Option<&str>) -> WsClient {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(engine)).await });
+
+    let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+    if let Some(key) = key {
+        request.headers_mut().insert("x-api-key", key.parse().unwrap());
+    }
+    let (socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+    socket
+}
+
+async fn ws_send(socket: &mut WsClient, message: Value) {
+    use futures::SinkExt;
+    socket.send(tokio_tungstenite::tungstenite::Message::text(message.to_string())).await.unwrap();
+}
+
+/// Next message with the given `op`, skipping others
+async fn ws_next(socket: &mut WsClient, op: &str) -> Value {
+    use futures::StreamExt;
+    loop {
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .expect("no message within 5s")
+            .unwrap()
+            .unwrap();
+        let value: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        if value["op"] == op {
+            return value;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_submit_acks_each_row() {
+    use serde_json::json;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    let mut socket = open_ws(engine.clone()).await;
+
+    ws_send(&mut socket, json!({"op": "submit", "type": "deposit", "client": 1, "tx": 1, "amount": "10.0"})).await;
+    assert_eq!(ws_next(&mut socket, "ack").await, json!({"op": "ack", "tx": 1, "code": "ok"}));
+
+    ws_send(&mut socket, json!({"op": "submit", "type": "withdrawal", "client": 1, "tx": 2, "amount": "50.0"})).await;
+    assert_eq!(ws_next(&mut socket, "ack").await["code"], "insufficient_funds");
+
+    ws_send(&mut socket, json!({"op": "submit", "type": "deposit", "client": 1})).await;
+    assert_eq!(ws_next(&mut socket, "ack").await, json!({"op": "ack", "tx": null, "code": "malformed"}));
+
+    ws_send(&mut socket, json!({"op": "withdraw-all"})).await;
+    assert!(ws_next(&mut socket, "error").await["message"].as_str().unwrap().contains("withdraw-all"));
+
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(10.0));
+}
+
+#[tokio::test]
+async fn test_websocket_pushes_subscribed_account_changes() {
+    use payments_engine::test_support::deposit;
+    use serde_json::json;
+
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+    engine.process(deposit(1, 1, dec!(5.0))).await.unwrap();
+    let mut socket = open_ws(engine.clone()).await;
+
+    ws_send(&mut socket, json!({"op": "subscribe", "clients": [1]})).await;
+    assert_eq!(ws_next(&mut socket, "subscribed").await["clients"], json!([1]));
+    // Current state first
+    assert_eq!(ws_next(&mut socket, "account").await["available"], "5.0");
+
+    // Changes from anywhere are pushed, other clients' are not
+    engine.process(deposit(2, 2, dec!(7.0))).await.unwrap();
+    engine.process(deposit(1, 3, dec!(2.5))).await.unwrap();
+    let update = ws_next(&mut socket, "account").await;
+    assert_eq!((update["client"].clone(), update["available"].clone()), (json!(1), json!("7.5")));
+
+    ws_send(&mut socket, json!({"op": "unsubscribe", "clients": [1]})).await;
+    assert_eq!(ws_next(&mut socket, "subscribed").await["clients"], json!([]));
+}
+
+// ============================================================================
+// OPENAPI TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_openapi_document_covers_routes() {
+    let temp_dir = TempDir::new().unwrap();
+    let engine = test_engine(&temp_dir).await;
+
+    let (status, doc) = get_json(engine.clone(), "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+
+    let paths = doc["paths"].as_object().unwrap();
+    assert_eq!(paths.len(), 29);
+    let account = &paths["/accounts/{client}"]["get"];
+    let params: Vec<&str> = account["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|param| param["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(params, vec!["client", "consistency"]);
+    assert!(account["responses"]["404"]["content"]["application/problem+json"].is_object());
+    assert!(paths["/transactions:validate"]["post"]["requestBody"].is_object());
+    assert!(paths["/admin/accounts/{client}/unlock"]["post"].is_object());
+
+    // Bodies reference generated schemas, amounts are decimal strings
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+    for name in ["AccountOutput", "TransactionRow", "Problem", "TransactionPage", "TreasuryReport", "Ack"] {
+        assert!(schemas.contains_key(name), "missing schema {}", name);
+    }
+    assert_eq!(schemas["AccountOutput"]["properties"]["available"]["type"], "string");
+    assert_eq!(schemas["TransactionRow"]["properties"]["type"]["$ref"], "#/components/schemas/TransactionType");
+    assert_eq!(schemas["TransactionType"]["type"], "string");
+
+    // Every documented GET without path parameters is routed
+    for (path, item) in paths {
+        if item.get("get").is_some() && !path.contains('{') {
+            let response = router(engine.clone())
+                .oneshot(Request::builder().uri(path.as_str()).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_ne!(response.status(), StatusCode::NOT_FOUND, "{} is not routed", path);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_submissions_scoped_to_api_key() {
+    use payments_engine::auth::{ApiKeys, ClientScope};
+    use payments_engine::storage::InMemoryStore;
+    use serde_json::json;
+
+    let keys = ApiKeys::new().with_key("acme", ClientScope::Only([1].into()));
+    let engine = Arc::new(ScalableEngine::without_event_log(4, Arc::new(InMemoryStore::new())).with_api_keys(keys));
+
+    let mut socket = open_ws_with_key(engine.clone(), Some("acme")).await;
+    ws_send(&mut socket, json!({"op": "submit", "type": "deposit", "client": 1, "tx": 1, "amount": "10.0"})).await;
+    assert_eq!(ws_next(&mut socket, "ack").await["code"], "ok");
+    ws_send(&mut socket, json!({"op": "submit", "type": "deposit", "client": 2, "tx": 2, "amount": "10.0"})).await;
+    assert_eq!(ws_next(&mut socket, "ack").await, json!({"op": "ack", "tx": 2, "code": "unauthorized"}));
+
+    let mut anonymous = open_ws(engine.clone()).await;
+    ws_send(&mut anonymous, json!({"op": "submit", "type": "deposit", "client": 1, "tx": 3, "amount": "10.0"})).await;
+    assert_eq!(ws_next(&mut anonymous, "ack").await["code"], "unauthorized");
+
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(10.0));
+    assert!(engine.get_account(2).await.is_none());
+}
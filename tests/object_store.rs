@@ -0,0 +1,134 @@
+#![cfg(feature = "object-store")]
+
+use object_store::memory::InMemory;
+use object_store::ObjectStore;
+use payments_engine::storage::{ObjectStoreBackend, StoredTransaction, TransactionStore};
+use payments_engine::test_support::{deposit, dispute};
+use payments_engine::{ScalableEngine, TransactionType};
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn stored(client: u16, amount: rust_decimal::Decimal) -> StoredTransaction {
+    StoredTransaction {
+        client,
+        tx_type: TransactionType::Deposit,
+        amount,
+        disputed: false,
+        held_amount: None,
+        created_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+    }
+}
+
+// ============================================================================
+// OBJECT STORE BACKEND TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_object_store_get_put_remove() {
+    let objects = Arc::new(InMemory::new());
+    let store = ObjectStoreBackend::new(objects.clone(), "cold").unwrap();
+    
+    assert_eq!(store.get(1).await, None);
+    
+    store.put(1, stored(7, dec!(10.0))).await.unwrap();
+    store.put(2, stored(8, dec!(20.0))).await.unwrap();
+    assert_eq!(store.get(1).await, Some(stored(7, dec!(10.0))));
+    
+    // Overwrites keep a single object per tx id
+    let mut disputed = stored(7, dec!(10.0));
+    disputed.disputed = true;
+    disputed.held_amount = Some(dec!(10.0));
+    store.put(1, disputed.clone()).await.unwrap();
+    assert_eq!(store.get(1).await, Some(disputed));
+    
+    let found = store.get_many(&[1, 2, 3]).await;
+    assert_eq!(found.iter().map(|(tx_id, _)| *tx_id).collect::<Vec<_>>(), vec![1, 2]);
+    
+    store.remove(1).await.unwrap();
+    store.remove(1).await.unwrap();
+    assert_eq!(store.get(1).await, None);
+    assert!(store.get(2).await.is_some());
+    
+    // Everything lives under the prefix, one client per sub-prefix
+    let client = object_store::path::Path::from("cold/clients/00008");
+    assert_eq!(objects.list_with_delimiter(Some(&client)).await.unwrap().objects.len(), 1);
+}
+
+#[tokio::test]
+async fn test_object_store_lists_one_client_in_tx_order() {
+    let store = ObjectStoreBackend::new(Arc::new(InMemory::new()), "").unwrap();
+    assert_client_listing(store).await;
+}
+
+#[tokio::test]
+async fn test_object_store_sorted_listing_pages_from_the_cursor() {
+    // The in-memory store lists in key order, like S3
+    let store = ObjectStoreBackend::new(Arc::new(InMemory::new()), "cold").unwrap().with_sorted_listing();
+    assert_client_listing(store).await;
+}
+
+async fn assert_client_listing(store: ObjectStoreBackend) {
+    for (tx_id, client) in [(5, 7), (1, 7), (3, 8), (9, 7), (2, 6), (10, 7)] {
+        store.put(tx_id, stored(client, dec!(1.0))).await.unwrap();
+    }
+    
+    let ids = |listed: Vec<(u32, StoredTransaction)>| listed.into_iter().map(|(tx_id, _)| tx_id).collect::<Vec<_>>();
+    assert_eq!(ids(store.list_client(7, None, 10).await.unwrap()), vec![1, 5, 9, 10]);
+    assert_eq!(ids(store.list_client(7, None, 2).await.unwrap()), vec![1, 5]);
+    assert_eq!(ids(store.list_client(7, Some(5), 10).await.unwrap()), vec![9, 10]);
+    assert_eq!(ids(store.list_client(7, Some(u32::MAX), 10).await.unwrap()), Vec::<u32>::new());
+    
+    // Moving a tx id to another client moves it out of the old client's prefix
+    store.put(5, stored(8, dec!(1.0))).await.unwrap();
+    assert_eq!(ids(store.list_client(7, None, 10).await.unwrap()), vec![1, 9, 10]);
+    assert_eq!(ids(store.list_client(8, None, 10).await.unwrap()), vec![3, 5]);
+}
+
+#[tokio::test]
+async fn test_migrated_transactions_are_disputable_from_object_store() {
+    let store = Arc::new(ObjectStoreBackend::new(Arc::new(InMemory::new()), "cold").unwrap());
+    let engine = ScalableEngine::without_event_log(4, store.clone());
+    for tx in 1..=3 {
+        engine.process(deposit(1, tx, dec!(5.0))).await.unwrap();
+    }
+    
+    assert!(engine.force_migrate_cold(1).await);
+    for _ in 0..50 {
+        if engine.hot_transactions(1).await.unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(store.list_client(1, None, 10).await.unwrap().len(), 3);
+    
+    engine.process(dispute(1, 2)).await.unwrap();
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!((account.available, account.held), (dec!(10.0), dec!(5.0)));
+    assert!(store.get(2).await.unwrap().disputed);
+}
+
+#[tokio::test]
+async fn test_object_store_compaction_deletes_rejected_rows() {
+    let objects = Arc::new(InMemory::new());
+    let store = ObjectStoreBackend::new(objects.clone(), "cold").unwrap();
+    
+    let mut withdrawal = stored(7, dec!(3.0));
+    withdrawal.tx_type = TransactionType::Withdrawal;
+    store.put(1, stored(7, dec!(10.0))).await.unwrap();
+    store.put(2, withdrawal).await.unwrap();
+    store.put(3, stored(8, dec!(5.0))).await.unwrap();
+    
+    let report = store
+        .compact(Arc::new(|tx: &StoredTransaction| tx.tx_type == TransactionType::Deposit))
+        .await
+        .unwrap();
+    assert_eq!((report.scanned, report.removed), (3, 1));
+    assert!(report.bytes_reclaimed > 0);
+    
+    // Both the row and its owner object are gone
+    assert_eq!(store.get(2).await, None);
+    assert!(store.get(1).await.is_some());
+    let owners = object_store::path::Path::from("cold/tx");
+    assert_eq!(objects.list_with_delimiter(Some(&owners)).await.unwrap().objects.len(), 2);
+}
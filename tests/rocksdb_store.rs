@@ -0,0 +1,108 @@
+#![cfg(feature = "rocksdb")]
+
+use payments_engine::storage::{RocksDbStore, StoredTransaction, TransactionStore};
+use payments_engine::TransactionType;
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::TempDir;
+
+fn stored(client: u16, amount: rust_decimal::Decimal) -> StoredTransaction {
+    StoredTransaction {
+        client,
+        tx_type: TransactionType::Deposit,
+        amount,
+        disputed: false,
+        held_amount: None,
+        created_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+    }
+}
+
+// ============================================================================
+// ROCKSDB STORE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_rocksdb_store_get_put_remove() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = RocksDbStore::open(temp_dir.path()).unwrap();
+    
+    assert_eq!(store.get(1).await, None);
+    
+    store.put(1, stored(7, dec!(10.0))).await.unwrap();
+    store.put(2, stored(8, dec!(20.0))).await.unwrap();
+    assert_eq!(store.get(1).await, Some(stored(7, dec!(10.0))));
+    
+    // Overwrites keep a single row per tx id
+    let mut disputed = stored(7, dec!(10.0));
+    disputed.disputed = true;
+    disputed.held_amount = Some(dec!(10.0));
+    store.put(1, disputed.clone()).await.unwrap();
+    assert_eq!(store.get(1).await, Some(disputed));
+    
+    let found = store.get_many(&[1, 2, 3]).await;
+    assert_eq!(found.iter().map(|(tx_id, _)| *tx_id).collect::<Vec<_>>(), vec![1, 2]);
+    
+    store.remove(1).await.unwrap();
+    store.remove(1).await.unwrap();
+    assert_eq!(store.get(1).await, None);
+    assert!(store.get(2).await.is_some());
+}
+
+#[tokio::test]
+async fn test_rocksdb_store_survives_reopen() {
+    let temp_dir = TempDir::new().unwrap();
+    
+    {
+        let store = RocksDbStore::open(temp_dir.path()).unwrap();
+        store.put(42, stored(3, dec!(1.5))).await.unwrap();
+    }
+    
+    let store = RocksDbStore::open(temp_dir.path()).unwrap();
+    assert_eq!(store.get(42).await, Some(stored(3, dec!(1.5))));
+}
+
+#[tokio::test]
+async fn test_rocksdb_store_lists_one_client_in_tx_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = RocksDbStore::open(temp_dir.path()).unwrap();
+    
+    for (tx_id, client) in [(5, 7), (1, 7), (3, 8), (9, 7), (2, 6)] {
+        store.put(tx_id, stored(client, dec!(1.0))).await.unwrap();
+    }
+    
+    let ids = |listed: Vec<(u32, StoredTransaction)>| listed.into_iter().map(|(tx_id, _)| tx_id).collect::<Vec<_>>();
+    assert_eq!(ids(store.list_client(7, None, 10).await.unwrap()), vec![1, 5, 9]);
+    assert_eq!(ids(store.list_client(7, None, 2).await.unwrap()), vec![1, 5]);
+    assert_eq!(ids(store.list_client(7, Some(5), 10).await.unwrap()), vec![9]);
+    assert_eq!(ids(store.list_client(7, Some(u32::MAX), 10).await.unwrap()), Vec::<u32>::new());
+    
+    // Moving a tx id to another client moves it out of the old client's range
+    store.put(5, stored(8, dec!(1.0))).await.unwrap();
+    assert_eq!(ids(store.list_client(7, None, 10).await.unwrap()), vec![1, 9]);
+    assert_eq!(ids(store.list_client(8, None, 10).await.unwrap()), vec![3, 5]);
+}
+
+#[tokio::test]
+async fn test_rocksdb_store_compaction_drops_rejected_rows() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = RocksDbStore::open(temp_dir.path()).unwrap();
+    
+    let mut withdrawal = stored(7, dec!(3.0));
+    withdrawal.tx_type = TransactionType::Withdrawal;
+    store.put(1, stored(7, dec!(10.0))).await.unwrap();
+    store.put(2, withdrawal).await.unwrap();
+    store.put(3, stored(8, dec!(5.0))).await.unwrap();
+    
+    let report = store
+        .compact(Arc::new(|tx: &StoredTransaction| tx.tx_type == TransactionType::Deposit))
+        .await
+        .unwrap();
+    assert_eq!((report.scanned, report.removed), (3, 1));
+    assert!(report.bytes_reclaimed > 0);
+    
+    assert_eq!(store.get(2).await, None);
+    assert!(store.get(1).await.is_some());
+    let ids = |listed: Vec<(u32, StoredTransaction)>| listed.into_iter().map(|(tx_id, _)| tx_id).collect::<Vec<_>>();
+    assert_eq!(ids(store.list_client(7, None, 10).await.unwrap()), vec![1]);
+}
@@ -0,0 +1,1203 @@
+use futures::StreamExt;
+use payments_engine::wire::{encode_msgpack, WireFormat};
+use payments_engine::{AccountOutput, TransactionRow, TransactionType};
+use rust_decimal_macros::dec;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+
+fn deposit(tx: u32) -> TransactionRow {
+    TransactionRow {
+        tx_type: TransactionType::Deposit,
+        client: 1,
+        tx,
+        amount: Some(dec!(1.5)),
+        to: None,
+    }
+}
+
+async fn decode_all(format: WireFormat, input: Vec<u8>) -> Vec<TransactionRow> {
+    let codec = format.codec();
+    codec
+        .decode_rows(Box::new(std::io::Cursor::new(input)))
+        .filter_map(|row| async move { row.ok() })
+        .collect()
+        .await
+}
+
+// ============================================================================
+// FORMAT DETECTION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_format_sniffed_from_first_byte() {
+    let msgpack = encode_msgpack(&deposit(1)).unwrap();
+
+    for (input, expected) in [
+        (b"type,client,tx,amount\n".to_vec(), WireFormat::Csv),
+        (b"{\"type\":\"deposit\"}\n".to_vec(), WireFormat::Json),
+        (msgpack, WireFormat::MessagePack),
+        (Vec::new(), WireFormat::Csv),
+    ] {
+        let mut reader = BufReader::new(std::io::Cursor::new(input.clone()));
+        assert_eq!(WireFormat::sniff(&mut reader).await.unwrap(), expected);
+
+        // Sniffing consumes nothing
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, input);
+    }
+}
+
+// ============================================================================
+// CODEC TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_all_codecs_decode_the_same_rows() {
+    let csv = b"type,client,tx,amount\ndeposit,1,1,1.5\nwithdrawal,1,2,1.5\n".to_vec();
+    let json = b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.5\"}\n\n\
+        {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"1.5\"}\n"
+        .to_vec();
+    let mut withdrawal = deposit(2);
+    withdrawal.tx_type = TransactionType::Withdrawal;
+    let mut msgpack = encode_msgpack(&deposit(1)).unwrap();
+    msgpack.extend(encode_msgpack(&withdrawal).unwrap());
+
+    for (format, input) in [
+        (WireFormat::Csv, csv),
+        (WireFormat::Json, json),
+        (WireFormat::MessagePack, msgpack),
+    ] {
+        let rows = decode_all(format, input).await;
+        assert_eq!(rows.len(), 2, "{:?}", format);
+        assert_eq!(rows[0].tx_type, TransactionType::Deposit);
+        assert_eq!(rows[1].tx_type, TransactionType::Withdrawal);
+        assert_eq!(rows[1].amount, Some(dec!(1.5)));
+    }
+}
+
+#[tokio::test]
+async fn test_msgpack_frame_split_across_reads() {
+    let frame = encode_msgpack(&deposit(7)).unwrap();
+    let (mut client, server) = tokio::io::duplex(64);
+
+    let codec = WireFormat::MessagePack.codec();
+    let mut rows = codec.decode_rows(Box::new(server));
+
+    // The decoder sees the first half on its own before the rest arrives
+    tokio::spawn(async move {
+        let (head, tail) = frame.split_at(frame.len() / 2);
+        client.write_all(head).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        client.write_all(tail).await.unwrap();
+    });
+
+    let row = rows.next().await.unwrap().unwrap();
+    assert_eq!(row.tx, 7);
+    assert!(rows.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_json_skips_malformed_lines() {
+    let input = b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.0\"}\nnot json\n\
+        {\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"1.0\"}\n"
+        .to_vec();
+
+    let codec = WireFormat::Json.codec();
+    let results: Vec<_> = codec.decode_rows(Box::new(std::io::Cursor::new(input))).collect().await;
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap().tx, 2);
+}
+
+#[tokio::test]
+async fn test_json_amounts_as_numbers_or_strings() {
+    let input = b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1.5}\n\
+        {\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"1e3\"}\n\
+        {\"type\":\"dispute\",\"client\":1,\"tx\":1,\"amount\":null}\n"
+        .to_vec();
+
+    let codec = WireFormat::Json.codec();
+    let results: Vec<_> = codec.decode_rows(Box::new(std::io::Cursor::new(input))).collect().await;
+    assert_eq!(results[0].as_ref().unwrap().amount, Some(dec!(1.5)));
+    // Text amounts follow the same notation rules as CSV
+    assert!(results[1].as_ref().unwrap_err().to_string().contains("scientific notation"));
+    assert_eq!(results[2].as_ref().unwrap().amount, None);
+}
+
+#[tokio::test]
+async fn test_minor_unit_codecs() {
+    use payments_engine::amount::{AmountFormat, AmountUnits};
+
+    let input = b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":1050}\n\
+        {\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"10.5\"}\n"
+        .to_vec();
+    let codec = WireFormat::Json.codec_in(AmountUnits::Minor(2), AmountFormat::default());
+    let results: Vec<_> = codec.decode_rows(Box::new(std::io::Cursor::new(input))).collect().await;
+    assert_eq!(results[0].as_ref().unwrap().amount, Some(dec!(10.50)));
+    assert!(results[1].as_ref().unwrap_err().to_string().contains("whole number of minor units"));
+
+    let accounts = || futures::stream::iter(vec![AccountOutput {
+        client: 1,
+        available: dec!(10.5),
+        held: dec!(0.25),
+        total: dec!(10.75),
+        locked: false,
+    }]).boxed();
+    let (writer, mut reader) = tokio::io::duplex(1024);
+    WireFormat::Csv.codec_in(AmountUnits::Minor(2), AmountFormat::default()).write_accounts(Box::new(writer), accounts()).await.unwrap();
+    let mut out = String::new();
+    reader.read_to_string(&mut out).await.unwrap();
+    assert_eq!(out, "client,available,held,total,locked\n1,1050,25,1075,false\n");
+
+    let (writer, mut reader) = tokio::io::duplex(1024);
+    WireFormat::Json.codec_in(AmountUnits::Minor(2), AmountFormat::default()).write_accounts(Box::new(writer), accounts()).await.unwrap();
+    let mut out = String::new();
+    reader.read_to_string(&mut out).await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+    assert_eq!(json["available"], "1050");
+}
+
+#[tokio::test]
+async fn test_amount_format_is_per_codec() {
+    use payments_engine::amount::{AmountFormat, AmountUnits};
+
+    let lenient = AmountFormat {
+        allow_scientific: true,
+        allow_thousands_separators: true,
+    };
+    let msgpack = encode_msgpack(&serde_json::json!({"type": "deposit", "client": 1, "tx": 1, "amount": "1,000.5"})).unwrap();
+    let inputs = [
+        (WireFormat::Csv, b"type,client,tx,amount\ndeposit,1,1,\"1,000.5\"\n".to_vec()),
+        (WireFormat::Json, b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1,000.5\"}\n".to_vec()),
+        (WireFormat::MessagePack, msgpack),
+    ];
+    // Two connections in one process, each reading under its own format
+    for (format, input) in inputs {
+        let strict = format.codec_in(AmountUnits::Decimal, AmountFormat::default());
+        let lenient = format.codec_in(AmountUnits::Decimal, lenient);
+
+        let rows: Vec<_> = lenient.decode_rows(Box::new(std::io::Cursor::new(input.clone()))).collect().await;
+        assert_eq!(rows[0].as_ref().unwrap().amount, Some(dec!(1000.5)), "{:?}", format);
+        let rows: Vec<_> = strict.decode_rows(Box::new(std::io::Cursor::new(input))).collect().await;
+        assert!(rows[0].as_ref().unwrap_err().to_string().contains("thousands"), "{:?}", format);
+    }
+}
+
+#[tokio::test]
+async fn test_account_summary_encoding() {
+    let accounts = || vec![AccountOutput {
+        client: 1,
+        available: dec!(1.5),
+        held: dec!(0),
+        total: dec!(1.5),
+        locked: false,
+    }];
+
+    let (writer, mut reader) = tokio::io::duplex(1024);
+    let stream = futures::stream::iter(accounts()).boxed();
+    WireFormat::Json.codec().write_accounts(Box::new(writer), stream).await.unwrap();
+    let mut out = String::new();
+    reader.read_to_string(&mut out).await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(out.trim()).unwrap();
+    assert_eq!(json["client"], 1);
+    assert_eq!(json["available"], "1.5");
+
+    let (writer, mut reader) = tokio::io::duplex(1024);
+    let stream = futures::stream::iter(accounts()).boxed();
+    WireFormat::MessagePack.codec().write_accounts(Box::new(writer), stream).await.unwrap();
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).await.unwrap();
+    assert_eq!(out, encode_msgpack(&accounts()[0]).unwrap());
+}
+
+#[tokio::test]
+async fn test_batch_summary_streams_every_account_in_client_order() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::fmt::Write;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::without_event_log(8, cold_storage));
+    engine.rebuild_from_events().await.unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, engine).await.unwrap();
+        })
+    };
+
+    // Clients sent in reverse, the summary still comes back sorted
+    let mut rows = String::from("type,client,tx,amount\n");
+    for client in (1..=3000u32).rev() {
+        writeln!(rows, "deposit,{},{},1.0", client, client).unwrap();
+    }
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(rows.as_bytes()).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    // Read in small pieces, the server waits on the socket rather than buffering ahead
+    let mut summary = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let read = client.read(&mut buf).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        summary.extend_from_slice(&buf[..read]);
+    }
+    server.await.unwrap();
+
+    let summary = String::from_utf8(summary).unwrap();
+    let mut lines = summary.lines();
+    assert_eq!(lines.next(), Some("client,available,held,total,locked"));
+    let clients: Vec<u16> = lines.map(|line| line.split(',').next().unwrap().parse().unwrap()).collect();
+    assert_eq!(clients, (1..=3000).collect::<Vec<u16>>());
+
+    let streamed: Vec<u16> = engine.stream_accounts().map(|account| account.client).collect().await;
+    assert_eq!(streamed, clients);
+}
+
+// ============================================================================
+// ACK PROTOCOL TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_protocol_header_negotiation() {
+    use payments_engine::wire::Protocol;
+
+    let mut reader = BufReader::new(std::io::Cursor::new(b"#protocol ack\n{\"type\":1}".to_vec()));
+    assert_eq!(Protocol::negotiate(&mut reader).await.unwrap(), Protocol::Ack);
+    // The header is consumed, format sniffing sees the first row
+    assert_eq!(WireFormat::sniff(&mut reader).await.unwrap(), WireFormat::Json);
+
+    let mut reader = BufReader::new(std::io::Cursor::new(b"type,client,tx,amount\n".to_vec()));
+    assert_eq!(Protocol::negotiate(&mut reader).await.unwrap(), Protocol::Batch);
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).await.unwrap();
+    assert_eq!(rest, "type,client,tx,amount\n");
+
+    let mut reader = BufReader::new(std::io::Cursor::new(b"#protocol carrier-pigeon\n".to_vec()));
+    assert!(Protocol::negotiate(&mut reader).await.is_err());
+}
+
+#[tokio::test]
+async fn test_format_header_overrides_sniffing() {
+    use payments_engine::wire::{ConnectionHeader, Protocol};
+
+    let mut reader = BufReader::new(std::io::Cursor::new(b"#format json\n#protocol ack\n{}".to_vec()));
+    let header = ConnectionHeader::read(&mut reader).await.unwrap();
+    assert_eq!(header, ConnectionHeader { protocol: Protocol::Ack, format: Some(WireFormat::Json), ..Default::default() });
+
+    let mut reader = BufReader::new(std::io::Cursor::new(b"#format csv\n#amounts minor:2\n".to_vec()));
+    let header = ConnectionHeader::read(&mut reader).await.unwrap();
+    assert_eq!(header.format, Some(WireFormat::Csv));
+    assert_eq!(header.units, payments_engine::amount::AmountUnits::Minor(2));
+
+    let mut reader = BufReader::new(std::io::Cursor::new(b"type,client,tx,amount\n".to_vec()));
+    assert_eq!(ConnectionHeader::read(&mut reader).await.unwrap(), ConnectionHeader::default());
+
+    for unknown in [&b"#format yaml\n"[..], b"#compression zstd\n"] {
+        let mut reader = BufReader::new(std::io::Cursor::new(unknown.to_vec()));
+        assert!(ConnectionHeader::read(&mut reader).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_ack_per_row_over_tcp() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(temp_dir.path().join("ack.log"), 4, cold_storage)
+            .await
+            .unwrap(),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        handle_connection(socket, engine).await.unwrap();
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(
+            b"#protocol ack\n\
+              type,client,tx,amount\n\
+              deposit,1,1,10.0\n\
+              withdrawal,1,2,50.0\n\
+              not a row\n\
+              deposit,1,1,5.0\n",
+        )
+        .await
+        .unwrap();
+    client.shutdown().await.unwrap();
+
+    // Acks replace the account summary
+    let mut acks = String::new();
+    client.read_to_string(&mut acks).await.unwrap();
+    assert_eq!(acks, "1,ok\n2,insufficient_funds\n,malformed\n1,duplicate_transaction\n");
+}
+
+#[tokio::test]
+async fn test_duplicates_reported_per_connection_source() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::without_event_log(4, cold_storage));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (socket, _) = listener.accept().await.unwrap();
+                handle_connection(socket, engine.clone()).await.unwrap();
+            }
+        })
+    };
+
+    // Named feed, then an unnamed one reported by its address
+    for input in [
+        &b"#source bank-a\ntype,client,tx,amount\ndeposit,1,1,10.0\ndeposit,1,1,10.0\n"[..],
+        b"type,client,tx,amount\ndeposit,2,1,3.0\ndeposit,2,2,3.0\n",
+    ] {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(input).await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut summary = String::new();
+        client.read_to_string(&mut summary).await.unwrap();
+    }
+    server.await.unwrap();
+
+    let report = engine.duplicates().report();
+    assert_eq!(report.total, 2);
+    let sources: Vec<_> = report.sources.iter().map(|s| (s.source.as_str(), s.tx_ids.clone())).collect();
+    assert_eq!(sources, vec![("127.0.0.1", vec![1]), ("bank-a", vec![1])]);
+}
+
+// ============================================================================
+// GRACEFUL SHUTDOWN TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_shutdown_drains_open_connections_and_flushes_the_log() {
+    use payments_engine::server::serve_until;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("shutdown.log");
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::new(log_path.clone(), 4, cold_storage).await.unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(serve_until(listener, engine.clone(), 10, shutdown.clone(), Duration::from_secs(10)));
+
+    let client = TcpStream::connect(addr).await.unwrap();
+    let (reader, mut writer) = client.into_split();
+    let mut acks = BufReader::new(reader).lines();
+    writer.write_all(b"#protocol ack\ntype,client,tx,amount\ndeposit,1,1,10.0\n").await.unwrap();
+    assert_eq!(acks.next_line().await.unwrap().unwrap(), "1,ok");
+
+    // Requested mid-connection: no new connections, the open one still finishes
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(TcpStream::connect(addr).await.is_err());
+    writer.write_all(b"deposit,1,2,5.0\n").await.unwrap();
+    writer.shutdown().await.unwrap();
+    assert_eq!(acks.next_line().await.unwrap().unwrap(), "2,ok");
+    assert_eq!(acks.next_line().await.unwrap(), None);
+
+    server.await.unwrap().unwrap();
+    engine.shutdown().await.unwrap();
+
+    // Both rows are in the log a restarted engine replays
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let restarted = ScalableEngine::new(log_path, 4, cold_storage).await.unwrap();
+    restarted.rebuild_from_events().await.unwrap();
+    assert_eq!(restarted.get_account(1).await.unwrap().available, dec!(15.0));
+}
+
+#[tokio::test]
+async fn test_shutdown_aborts_connections_past_the_grace_period() {
+    use payments_engine::server::serve_until;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::without_event_log(4, cold_storage));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(serve_until(listener, engine, 10, shutdown.clone(), Duration::from_millis(100)));
+
+    // Never stops sending, so the connection outlives the grace period
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(b"type,client,tx,amount\ndeposit,1,1,10.0\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown.cancel();
+
+    tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    let mut rest = Vec::new();
+    let _ = client.read_to_end(&mut rest).await;
+    assert!(rest.is_empty());
+}
+
+// ============================================================================
+// CONCURRENT CONNECTION TESTS
+// ============================================================================
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_soak_many_connections_hold_invariants() {
+    use payments_engine::soak::{self, SoakConfig};
+    use std::time::Duration;
+
+    let report = soak::run(SoakConfig {
+        connections: 64,
+        duration: Duration::from_millis(800),
+        check_interval: Duration::from_millis(100),
+        batch_size: 16,
+        max_rss_bytes: None,
+    })
+    .await
+    .unwrap();
+
+    assert!(report.rows >= 64 * 16, "only {} rows", report.rows);
+    assert!(report.checks >= 2);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_soak_fails_past_memory_bound() {
+    use payments_engine::soak::{self, SoakConfig};
+    use std::time::Duration;
+
+    let result = soak::run(SoakConfig {
+        connections: 2,
+        duration: Duration::from_millis(200),
+        check_interval: Duration::from_millis(50),
+        batch_size: 4,
+        max_rss_bytes: Some(1),
+    })
+    .await;
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("Resident memory"), "{}", error);
+}
+
+// ============================================================================
+// BACKFILL CUTOVER TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_backfill_hands_over_to_live_stream() {
+    use payments_engine::ingestion::{self, Cutover, IngestionPhase};
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    assert_eq!("end".parse::<Cutover>().unwrap(), Cutover::EndOfFile);
+    assert_eq!("seq:3".parse::<Cutover>().unwrap(), Cutover::Sequence(3));
+    assert_eq!("at:1700000000".parse::<Cutover>().unwrap(), Cutover::Timestamp(1_700_000_000));
+    assert!("seq:".parse::<Cutover>().is_err());
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let history = temp_dir.path().join("history.csv");
+    std::fs::write(
+        &history,
+        "type,client,tx,amount\n\
+         deposit,1,1,1.0\n\
+         deposit,1,2,2.0\n\
+         deposit,1,3,3.0\n\
+         deposit,1,4,4.0\n",
+    )
+    .unwrap();
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(temp_dir.path().join("live.log"), 4, cold_storage)
+            .await
+            .unwrap(),
+    );
+    engine.ingestion().start_backfill();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, engine).await.unwrap();
+        })
+    };
+
+    // Live rows sent before the backfill ran: tx 3 overlaps the file, tx 4 was cut off from it
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(
+            b"#protocol ack\n\
+              type,client,tx,amount\n\
+              deposit,1,3,3.0\n\
+              deposit,1,4,4.0\n\
+              withdrawal,1,5,10.0\n\
+              deposit,1,2,9.0\n\
+              deposit,1,3,3.0\n",
+        )
+        .await
+        .unwrap();
+    client.shutdown().await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(engine.get_account(1).await.is_none());
+    assert_eq!(engine.ingestion().phase(), IngestionPhase::Backfilling);
+
+    let status = ingestion::backfill(&engine, &history, Cutover::Sequence(3)).await.unwrap();
+    assert_eq!(status.phase, IngestionPhase::Live);
+    assert_eq!(status.backfill_rows, 3);
+    assert_eq!(status.backfill_skipped, 1);
+
+    // The repeat is acknowledged once, a different row under a backfilled id is not
+    let mut acks = String::new();
+    client.read_to_string(&mut acks).await.unwrap();
+    assert_eq!(acks, "3,ok\n4,ok\n5,ok\n2,duplicate_transaction\n3,duplicate_transaction\n");
+    server.await.unwrap();
+
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(0.0));
+    let status = engine.ingestion().status();
+    assert_eq!(status.live_rows, 5);
+    assert_eq!(status.boundary_duplicates, 1);
+}
+
+#[tokio::test]
+async fn test_failed_backfill_refuses_live_rows() {
+    use payments_engine::ingestion::{self, Cutover, IngestionPhase};
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::new(temp_dir.path().join("live.log"), 4, cold_storage)
+        .await
+        .unwrap();
+    engine.ingestion().start_backfill();
+
+    let missing = temp_dir.path().join("missing.csv");
+    assert!(ingestion::backfill(&engine, &missing, Cutover::EndOfFile).await.is_err());
+    assert_eq!(engine.ingestion().phase(), IngestionPhase::Failed);
+    assert!(engine.ingestion().wait_live().await.is_err());
+}
+
+// ============================================================================
+// SEQUENCED ORDERING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_sequenced_rows_apply_in_order_across_connections() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::without_event_log(4, cold_storage));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(socket, engine.clone()));
+            }
+        });
+    }
+
+    // The withdrawal arrives first but is numbered after the deposit it needs
+    let header = "#protocol ack\n#ordering sequenced\ntype,client,tx,amount,seq\n";
+    let first = TcpStream::connect(addr).await.unwrap();
+    let (reader, mut first_writer) = first.into_split();
+    let mut first_acks = BufReader::new(reader).lines();
+    first_writer.write_all(format!("{}withdrawal,1,2,4.0,2\n", header).as_bytes()).await.unwrap();
+
+    let second = TcpStream::connect(addr).await.unwrap();
+    let (reader, mut second_writer) = second.into_split();
+    let mut second_acks = BufReader::new(reader).lines();
+    second_writer.write_all(format!("{}deposit,1,1,10.0,1\n", header).as_bytes()).await.unwrap();
+
+    assert_eq!(second_acks.next_line().await.unwrap().unwrap(), "1,ok");
+    assert_eq!(first_acks.next_line().await.unwrap().unwrap(), "2,ok");
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(6.0));
+
+    // A number already applied is refused, whichever connection repeats it
+    second_writer.write_all(b"deposit,1,3,1.0,2\n").await.unwrap();
+    assert_eq!(second_acks.next_line().await.unwrap().unwrap(), "3,out_of_sequence");
+
+    // Only CSV rows carry a seq column
+    let mut json = TcpStream::connect(addr).await.unwrap();
+    json.write_all(b"#ordering sequenced\n{\"type\":\"deposit\",\"client\":1,\"tx\":4,\"amount\":\"1.0\"}\n")
+        .await
+        .unwrap();
+    json.shutdown().await.unwrap();
+    let mut rest = String::new();
+    let _ = json.read_to_string(&mut rest).await;
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn test_sequence_gap_rejected_after_timeout() {
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::{ProcessingError, ScalableEngine};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage).with_sequence_timeout(Duration::from_millis(50));
+    let sequencer = engine.sequencer();
+
+    // Rows behind a gap never filled are rejected, the client still waits for the missing number
+    let third = sequencer.submit(&engine, 3, deposit(3)).await;
+    let second = sequencer.submit(&engine, 2, deposit(2)).await;
+    assert!(matches!(third.await.unwrap(), Err(ProcessingError::OutOfSequence)));
+    assert!(matches!(second.await.unwrap(), Err(ProcessingError::OutOfSequence)));
+    assert!(engine.get_account(1).await.is_none());
+
+    let first = sequencer.submit(&engine, 1, deposit(1)).await;
+    assert!(first.await.unwrap().is_ok());
+    let second = sequencer.submit(&engine, 2, deposit(2)).await;
+    assert!(second.await.unwrap().is_ok());
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(3.0));
+}
+
+#[tokio::test]
+async fn test_sequence_gap_filled_by_a_later_chunk_of_the_same_connection() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(ScalableEngine::without_event_log(4, cold_storage).with_sequence_timeout(Duration::from_secs(30)));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, engine).await
+        });
+    }
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut acks = BufReader::new(reader).lines();
+    writer
+        .write_all(b"#protocol ack\n#ordering sequenced\ntype,client,tx,amount,seq\ndeposit,1,2,2.0,2\ndeposit,2,10,1.0,1\n")
+        .await
+        .unwrap();
+
+    // The connection keeps reading while the first row waits, so the row filling its gap gets in
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    writer.write_all(b"deposit,1,1,1.0,1\n").await.unwrap();
+
+    let acks = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            lines.push(acks.next_line().await.unwrap().unwrap());
+        }
+        lines
+    })
+    .await
+    .expect("acks held back by the gap");
+    assert_eq!(acks, vec!["2,ok", "10,ok", "1,ok"]);
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(3.0));
+}
+
+#[tokio::test]
+async fn test_sequence_state_survives_restart() {
+    use payments_engine::sequencer::Sequencer;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::{ProcessingError, ScalableEngine};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let state = temp_dir.path().join("sequence.state");
+    let timeout = Duration::from_millis(50);
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = ScalableEngine::without_event_log(4, cold_storage.clone())
+        .with_sequencer(Sequencer::persistent(timeout, state.clone()).unwrap());
+    for seq in 1..=2 {
+        let outcome = engine.sequencer().submit(&engine, seq, deposit(seq as u32)).await;
+        assert!(outcome.await.unwrap().is_ok());
+    }
+    drop(engine);
+
+    // The client carries on from 3 rather than being expected to start over at 1
+    let engine = ScalableEngine::without_event_log(4, cold_storage)
+        .with_sequencer(Sequencer::persistent(timeout, state).unwrap());
+    let repeat = engine.sequencer().submit(&engine, 2, deposit(2)).await;
+    assert!(matches!(repeat.await.unwrap(), Err(ProcessingError::OutOfSequence)));
+    let next = engine.sequencer().submit(&engine, 3, deposit(3)).await;
+    assert!(next.await.unwrap().is_ok());
+}
+
+// ============================================================================
+// BURST SPILL TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_connection_spills_burst_and_acks_in_order() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::spill::SpillConfig;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let spill_dir = temp_dir.path().join("spill");
+    std::fs::create_dir(&spill_dir).unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(temp_dir.path().join("spill.log"), 4, cold_storage)
+            .await
+            .unwrap()
+            .with_spill(SpillConfig { dir: spill_dir.clone(), memory_rows: 2 }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        handle_connection(socket, engine.clone()).await.unwrap();
+        engine
+    });
+
+    let mut input = String::from("#protocol ack\ntype,client,tx,amount\n");
+    for tx in 1..=200 {
+        input.push_str(&format!("deposit,1,{},1.0\n", tx));
+    }
+    input.push_str("withdrawal,1,201,500.0\n");
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(input.as_bytes()).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    let mut acks = String::new();
+    client.read_to_string(&mut acks).await.unwrap();
+    let mut expected: String = (1..=200).map(|tx| format!("{},ok\n", tx)).collect();
+    expected.push_str("201,insufficient_funds\n");
+    assert_eq!(acks, expected);
+
+    let engine = server.await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(200.0));
+    // The connection's spill file goes with it
+    assert_eq!(std::fs::read_dir(&spill_dir).unwrap().count(), 0);
+}
+
+// ============================================================================
+// STAGED INTAKE TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_connection_settles_staged_rows_once_acked() {
+    use payments_engine::intake::IntakeLog;
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let intake_path = temp_dir.path().join("intake.log");
+    let (intake, _) = IntakeLog::open(intake_path.clone()).unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(temp_dir.path().join("intake_events.log"), 4, cold_storage)
+            .await
+            .unwrap()
+            .with_intake(intake),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, engine).await.unwrap();
+        })
+    };
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(b"#protocol ack\ntype,client,tx,amount\ndeposit,1,1,10.0\nnot a row\nwithdrawal,1,2,50.0\n")
+        .await
+        .unwrap();
+    client.shutdown().await.unwrap();
+    let mut acks = String::new();
+    client.read_to_string(&mut acks).await.unwrap();
+    assert_eq!(acks, "1,ok\n,malformed\n2,insufficient_funds\n");
+    server.await.unwrap();
+
+    // Applied and refused rows alike are settled, nothing is left to recover
+    assert_eq!(engine.intake().unwrap().pending(), 0);
+    assert!(IntakeLog::open(intake_path).unwrap().1.is_empty());
+}
+
+// ============================================================================
+// FIXTURE RECORDING TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_connection_rows_recorded_as_fixture() {
+    use payments_engine::recorder::{FixtureRecorder, RecorderConfig};
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let bundle = temp_dir.path().join("incident");
+    let recorder = FixtureRecorder::new(RecorderConfig {
+        dir: bundle.clone(),
+        sample_rate: 1.0,
+        max_rows: 3,
+    });
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(temp_dir.path().join("record_events.log"), 4, cold_storage)
+            .await
+            .unwrap()
+            .with_recorder(recorder),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, engine).await.unwrap();
+        })
+    };
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(b"#protocol ack\ntype,client,tx,amount\ndeposit,42,900,10.0\nwithdrawal,42,901,50.0\ndeposit,7,902,1.0\ndeposit,7,903,2.0\n")
+        .await
+        .unwrap();
+    client.shutdown().await.unwrap();
+    let mut acks = String::new();
+    client.read_to_string(&mut acks).await.unwrap();
+    server.await.unwrap();
+
+    // Written once the third row filled the recording, refused rows included
+    let input = std::fs::read_to_string(bundle.join("input.csv")).unwrap();
+    assert_eq!(input, "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,50.0\ndeposit,2,3,1.0\n");
+    let expected = std::fs::read_to_string(bundle.join("expected_accounts.csv")).unwrap();
+    assert_eq!(
+        expected,
+        "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n2,1.0000,0.0000,1.0000,false\n"
+    );
+    assert_eq!(engine.recorder().unwrap().finish().await.unwrap(), None);
+}
+
+// ============================================================================
+// VERSION NEGOTIATION TESTS
+// ============================================================================
+
+#[tokio::test]
+async fn test_hello_answered_with_capabilities() {
+    use payments_engine::wire::{Capabilities, ConnectionHeader, Protocol, PROTOCOL_VERSION};
+
+    let capabilities = Capabilities::builtin().with_feature("spill");
+
+    // A newer client is talked down to the server's version
+    let mut reader = BufReader::new(std::io::Cursor::new(b"#hello 7\n#protocol ack\ntype".to_vec()));
+    let mut reply = Vec::new();
+    let header = ConnectionHeader::read_answering(&mut reader, &mut reply, &capabilities).await.unwrap();
+    assert_eq!(header.version, Some(PROTOCOL_VERSION));
+    assert_eq!(header.protocol, Protocol::Ack);
+    let reply = String::from_utf8(reply).unwrap();
+    assert_eq!(
+        reply,
+        format!(
+            "#hello 1 engine={} formats=csv,json,msgpack protocols=batch,ack orderings=arrival,sequenced amounts=decimal,minor features={}\n",
+            env!("CARGO_PKG_VERSION"),
+            capabilities.features.join(",")
+        )
+    );
+
+    // Clients that skip the handshake get no reply and no version
+    let mut reader = BufReader::new(std::io::Cursor::new(b"#protocol ack\ntype".to_vec()));
+    let mut reply = Vec::new();
+    let header = ConnectionHeader::read_answering(&mut reader, &mut reply, &capabilities).await.unwrap();
+    assert_eq!(header.version, None);
+    assert!(reply.is_empty());
+
+    for invalid in [&b"#hello 0\n"[..], b"#hello one\n", b"#protocol ack\n#hello 1\n"] {
+        let mut reader = BufReader::new(std::io::Cursor::new(invalid.to_vec()));
+        assert!(ConnectionHeader::read_answering(&mut reader, &mut Vec::new(), &capabilities).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_hello_reply_precedes_rows_over_tcp() {
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let engine = Arc::new(
+        ScalableEngine::new(temp_dir.path().join("hello_events.log"), 4, cold_storage)
+            .await
+            .unwrap(),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            handle_connection(socket, engine).await.unwrap();
+        })
+    };
+
+    // The client waits for the answer before deciding what to send
+    let client = TcpStream::connect(addr).await.unwrap();
+    let (reader, mut writer) = client.into_split();
+    let mut reader = BufReader::new(reader);
+    writer.write_all(b"#hello 1\n").await.unwrap();
+    let mut hello = String::new();
+    tokio::io::AsyncBufReadExt::read_line(&mut reader, &mut hello).await.unwrap();
+    assert!(hello.starts_with("#hello 1 engine="));
+    assert!(hello.contains(" protocols=batch,ack "));
+
+    writer.write_all(b"#protocol ack\ntype,client,tx,amount\ndeposit,1,1,10.0\n").await.unwrap();
+    writer.shutdown().await.unwrap();
+    let mut acks = String::new();
+    reader.read_to_string(&mut acks).await.unwrap();
+    assert_eq!(acks, "1,ok\n");
+    server.await.unwrap();
+}
+
+// ============================================================================
+// API KEY TESTS
+// ============================================================================
+
+#[test]
+fn test_api_keys_scope_clients() {
+    use payments_engine::auth::{ApiKeys, ClientScope};
+
+    let keys = ApiKeys::from_toml("[keys]\nacme = [1, 2]\nops = \"*\"\n").unwrap();
+    assert_eq!(keys.len(), 2);
+    let acme = keys.scope(Some("acme"));
+    assert!(acme.permits(1) && acme.permits(2) && !acme.permits(3));
+    assert_eq!(keys.scope(Some("ops")), ClientScope::All);
+    // Unknown and missing keys may submit for nobody
+    assert!(!keys.scope(Some("guess")).permits(1));
+    assert_eq!(keys.scope(None), ClientScope::none());
+
+    assert!(ApiKeys::from_toml("[keys]\nacme = \"some\"\n").is_err());
+    assert!(ApiKeys::from_toml("[keys]\nacme = [70000]\n").is_err());
+}
+
+#[tokio::test]
+async fn test_connection_rows_refused_outside_key_scope() {
+    use payments_engine::auth::{ApiKeys, ClientScope};
+    use payments_engine::server::handle_connection;
+    use payments_engine::storage::{InMemoryStore, TransactionStore};
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let cold_storage: Arc<dyn TransactionStore> = Arc::new(InMemoryStore::new());
+    let keys = ApiKeys::new().with_key("acme", ClientScope::Only([1].into()));
+    let engine = Arc::new(ScalableEngine::without_event_log(4, cold_storage).with_api_keys(keys));
+    assert!(engine.capabilities().features.contains(&"api_keys".to_string()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                let (socket, _) = listener.accept().await.unwrap();
+                handle_connection(socket, engine.clone()).await.unwrap();
+            }
+        })
+    };
+
+    let mut replies = Vec::new();
+    for input in [
+        &b"#api-key acme\n#protocol ack\ntype,client,tx,amount\ndeposit,1,1,10.0\ndeposit,2,2,10.0\ndeposit,1,3,1.0\n"[..],
+        b"#api-key stolen\n#protocol ack\ntype,client,tx,amount\ndeposit,1,4,10.0\n",
+        b"#protocol ack\ntype,client,tx,amount\ndeposit,1,5,10.0\n",
+    ] {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(input).await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut acks = String::new();
+        client.read_to_string(&mut acks).await.unwrap();
+        replies.push(acks);
+    }
+    server.await.unwrap();
+
+    assert_eq!(replies, vec!["1,ok\n2,unauthorized\n3,ok\n", "4,unauthorized\n", "5,unauthorized\n"]);
+    assert_eq!(engine.get_account(1).await.unwrap().total(), dec!(11.0));
+    assert!(engine.get_account(2).await.is_none());
+}
+
+// ============================================================================
+// BACKPRESSURE TESTS
+// ============================================================================
+
+/// Cold store whose reads wait until the test opens the gate
+struct GatedStore {
+    inner: payments_engine::storage::InMemoryStore,
+    gate: tokio::sync::Semaphore,
+}
+
+#[async_trait::async_trait]
+impl payments_engine::storage::TransactionStore for GatedStore {
+    async fn get(&self, tx_id: u32) -> Option<payments_engine::StoredTransaction> {
+        let _permit = self.gate.acquire().await.ok()?;
+        self.inner.get(tx_id).await
+    }
+
+    async fn put(&self, tx_id: u32, tx: payments_engine::StoredTransaction) -> anyhow::Result<()> {
+        self.inner.put(tx_id, tx).await
+    }
+
+    async fn remove(&self, tx_id: u32) -> anyhow::Result<()> {
+        self.inner.remove(tx_id).await
+    }
+
+    async fn list_client(
+        &self,
+        client: u16,
+        after: Option<u32>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u32, payments_engine::StoredTransaction)>> {
+        self.inner.list_client(client, after, limit).await
+    }
+
+    async fn compact(&self, retain: payments_engine::storage::Retain) -> anyhow::Result<payments_engine::storage::CompactionReport> {
+        self.inner.compact(retain).await
+    }
+}
+
+#[tokio::test]
+async fn test_saturated_engine_refuses_rows_with_retry_hint() {
+    use payments_engine::backpressure::BackpressureConfig;
+    use payments_engine::server::handle_connection;
+    use payments_engine::ScalableEngine;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let store = Arc::new(GatedStore {
+        inner: payments_engine::storage::InMemoryStore::new(),
+        gate: tokio::sync::Semaphore::new(0),
+    });
+    let engine = Arc::new(ScalableEngine::without_event_log(1, store.clone()).with_backpressure(BackpressureConfig {
+        max_queue_depth: 1,
+        busy_after: Duration::from_millis(50),
+    }));
+    assert!(engine.capabilities().features.contains(&"backpressure".to_string()));
+    engine.wait_for_capacity().await.unwrap();
+
+    // A dispute looking its deposit up in cold storage holds the only shard
+    let blocked = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let dispute = TransactionRow {
+                tx_type: TransactionType::Dispute,
+                client: 9,
+                tx: 99,
+                amount: None,
+                to: None,
+            };
+            engine.process(dispute).await
+        })
+    };
+    while engine.queue_depths() != vec![1] {
+        tokio::task::yield_now().await;
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (socket, _) = listener.accept().await.unwrap();
+                handle_connection(socket, engine.clone()).await.unwrap();
+            }
+        })
+    };
+    let submit = |input: &'static [u8]| async move {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(input).await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut acks = String::new();
+        client.read_to_string(&mut acks).await.unwrap();
+        acks
+    };
+
+    let input = b"#protocol ack\ntype,client,tx,amount\ndeposit,1,1,10.0\ndeposit,2,2,5.0\n";
+    assert_eq!(submit(input).await, "1,engine_busy,50\n2,engine_busy,50\n");
+    assert!(engine.get_account(1).await.is_none());
+
+    // Once drained the same rows go through
+    store.gate.add_permits(usize::MAX >> 4);
+    assert!(blocked.await.unwrap().is_err());
+    assert_eq!(submit(input).await, "1,ok\n2,ok\n");
+    server.await.unwrap();
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(10.0));
+}